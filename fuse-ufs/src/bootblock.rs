@@ -0,0 +1,44 @@
+use std::fs::File;
+
+use anyhow::{ensure, Context, Result};
+use rufs::{BlockFile, Ufs, WindowedBackend, BOOTBLOCK_SIZE};
+
+use crate::cli::BootblockArgs;
+
+/// Write the boot area of a filesystem to a file, or replace it by the contents of a file.
+pub fn bootblock(args: &BootblockArgs) -> Result<()> {
+	let path = &args.image;
+	let file = File::options()
+		.read(true)
+		.write(args.replace.is_some())
+		.open(path)
+		.with_context(|| format!("failed to open {}", path.display()))?;
+	let file = BlockFile::from_file(file)?;
+	if !args.no_lock {
+		crate::lock(&file, path, args.replace.is_some(), "--no-lock")?;
+	}
+	let window = match rufs::find_ufs_partition(&file)? {
+		Some(part) => WindowedBackend::new(file, part.start, Some(part.size)),
+		None => WindowedBackend::whole(file),
+	};
+	let ufs = Ufs::new(window)
+		.with_context(|| format!("failed to open the filesystem in {}", path.display()))?;
+
+	if let Some(input) = &args.replace {
+		let data =
+			std::fs::read(input).with_context(|| format!("failed to read {}", input.display()))?;
+		ensure!(
+			data.len() <= BOOTBLOCK_SIZE,
+			"{} is larger than the boot area of {BOOTBLOCK_SIZE} bytes",
+			input.display()
+		);
+		ufs.write_bootblock(&data)
+			.with_context(|| format!("failed to write the boot area of {}", path.display()))?;
+		ufs.sync()?;
+	} else if let Some(output) = &args.output {
+		let data = ufs.read_bootblock()?;
+		std::fs::write(output, data)
+			.with_context(|| format!("failed to write {}", output.display()))?;
+	}
+	Ok(())
+}
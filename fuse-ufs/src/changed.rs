@@ -0,0 +1,119 @@
+use std::{
+	collections::HashSet,
+	ffi::OsString,
+	fs::File,
+	io::{BufWriter, ErrorKind, Result as IoResult, Write},
+	os::unix::ffi::OsStrExt,
+};
+
+use anyhow::{ensure, Context, Result};
+use rufs::{Backend, BlockFile, InodeNum, InodeType, Ufs, WindowedBackend};
+
+use crate::cli::ChangedSinceArgs;
+
+/// Print the paths or inode numbers of the files, which were created or modified
+/// after `args.time`.
+pub fn changed_since(args: &ChangedSinceArgs) -> Result<()> {
+	let path = &args.image;
+	let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+	let file = BlockFile::from_file(file)?;
+	if !args.no_lock {
+		crate::lock(&file, path, false, "--no-lock")?;
+	}
+	let window = match rufs::find_ufs_partition(&file)? {
+		Some(part) => WindowedBackend::new(file, part.start, Some(part.size)),
+		None => WindowedBackend::whole(file),
+	};
+	let ufs = Ufs::new(window)
+		.with_context(|| format!("failed to open the filesystem in {}", path.display()))?;
+
+	let mut errors = 0;
+	let mut changed = Vec::new();
+	for attr in ufs.changed_since(args.time) {
+		match attr {
+			Ok(attr) => changed.push(attr.inr),
+			Err(e) => {
+				log::error!("{e}");
+				errors += 1;
+			}
+		}
+	}
+
+	let mut out = BufWriter::new(std::io::stdout().lock());
+	let res = if args.inodes {
+		changed.iter().try_for_each(|inr| writeln!(out, "{inr}"))
+	} else {
+		print_paths(&ufs, changed.into_iter().collect(), &mut out, &mut errors)
+	};
+	match res.and_then(|()| out.flush()) {
+		Ok(()) => {}
+		// Stop quietly, eg. when piped into head(1).
+		Err(e) if e.kind() == ErrorKind::BrokenPipe => return Ok(()),
+		Err(e) => return Err(e).context("failed to write to stdout"),
+	}
+
+	ensure!(
+		errors == 0,
+		"{errors} inodes or directories could not be read"
+	);
+	Ok(())
+}
+
+/// Walk the directory tree, and print every path of the inodes in `changed`.
+///
+/// Only directories are read, the types of the other files are taken from their entries.
+/// Errors while reading directories are logged and counted.
+fn print_paths<B: Backend>(
+	ufs: &Ufs<B>,
+	mut changed: HashSet<InodeNum>,
+	out: &mut impl Write,
+	errors: &mut usize,
+) -> IoResult<()> {
+	if changed.contains(&InodeNum::ROOT) {
+		writeln!(out, "/")?;
+	}
+
+	let mut printed = HashSet::new();
+	let mut visited = HashSet::from([InodeNum::ROOT]);
+	let mut dirs = vec![(OsString::new(), InodeNum::ROOT)];
+	while let Some((path, inr)) = dirs.pop() {
+		let mut subdirs = Vec::new();
+		for entry in ufs.dir_entries(inr) {
+			let entry = match entry {
+				Ok(entry) => entry,
+				Err(e) => {
+					log::error!("{}/: {e}", path.to_string_lossy());
+					*errors += 1;
+					break;
+				}
+			};
+			if entry.name == "." || entry.name == ".." {
+				continue;
+			}
+
+			let mut child = path.clone();
+			child.push("/");
+			child.push(&entry.name);
+			if changed.contains(&entry.inr) {
+				out.write_all(child.as_bytes())?;
+				out.write_all(b"\n")?;
+				printed.insert(entry.inr);
+			}
+			// Corrupted directories may contain themselves.
+			if entry.kind == InodeType::Directory && visited.insert(entry.inr) {
+				subdirs.push((child, entry.inr));
+			}
+		}
+		// Descend into the subdirectories in the order of their entries.
+		dirs.extend(subdirs.into_iter().rev());
+	}
+
+	changed.retain(|inr| *inr != InodeNum::ROOT && !printed.contains(inr));
+	if !changed.is_empty() {
+		log::warn!(
+			"{} changed inodes aren't linked to any directory, use --inodes to print them",
+			changed.len()
+		);
+	}
+	Ok(())
+}
@@ -1,19 +1,62 @@
-use std::path::PathBuf;
+use std::{
+	ffi::OsString,
+	path::{Path, PathBuf},
+	time::{Duration, SystemTime},
+};
 
-use clap::Parser;
+use anyhow::{bail, ensure, Context};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use clap_verbosity_flag::{Verbosity, WarnLevel};
 
 #[derive(Parser)]
-#[command(version, about)]
+#[command(
+	version,
+	about,
+	args_conflicts_with_subcommands = true,
+	subcommand_negates_reqs = true
+)]
 pub struct Cli {
+	#[command(subcommand)]
+	pub command: Option<Command>,
+
 	/// Mount options to pass to the kernel
 	#[arg(short, long, value_delimiter(','))]
 	pub options: Vec<String>,
 
 	/// Path to the device
-	pub device:     PathBuf,
+	#[arg(required = true)]
+	pub device:     Option<PathBuf>,
 	/// Path to the mount point
-	pub mountpoint: PathBuf,
+	#[cfg_attr(feature = "trim", arg(required_unless_present_any = ["check", "trim"]))]
+	#[cfg_attr(not(feature = "trim"), arg(required_unless_present = "check"))]
+	pub mountpoint: Option<PathBuf>,
+
+	/// Check the filesystem for inconsistencies, instead of mounting it
+	#[arg(long)]
+	pub check: bool,
+
+	/// Discard the free space of the filesystem, like the trim command, instead of mounting it
+	#[cfg(feature = "trim")]
+	#[arg(long, conflicts_with = "check")]
+	pub trim: bool,
+
+	/// Reopen the filesystem, and invalidate the caches of the kernel, when the device is modified
+	/// by someone else, checking every SECONDS (1 by default)
+	#[cfg(feature = "fuse3")]
+	#[arg(
+		long,
+		value_name = "SECONDS",
+		num_args = 0..=1,
+		require_equals = true,
+		default_missing_value = "1",
+		value_parser = clap::value_parser!(u64).range(1..),
+		conflicts_with = "check"
+	)]
+	pub watch: Option<u64>,
+
+	/// Use the backup superblock at this sector (of 512 bytes), instead of the primary one
+	#[arg(long, value_name = "SECTOR")]
+	pub force_alternate_sb: Option<u64>,
 
 	#[command(flatten)]
 	pub verbose: Verbosity<WarnLevel>,
@@ -23,18 +66,636 @@ pub struct Cli {
 	pub foreground: bool,
 }
 
+#[derive(Subcommand)]
+pub enum Command {
+	/// Extract or replace the boot area, the 64 KiB before the superblock
+	Bootblock(BootblockArgs),
+
+	/// Print the files, which were created or modified after a point in time, without mounting
+	/// the filesystem, eg. for incremental backups
+	ChangedSince(ChangedSinceArgs),
+
+	/// Copy fragments of the filesystem to a file, or overwrite them, like dd(1)
+	Dd(DdArgs),
+
+	/// Search for files in the filesystem, without mounting it
+	Find(FindArgs),
+
+	/// Create a new, empty filesystem
+	#[cfg(feature = "mkfs")]
+	Mkfs(MkfsArgs),
+
+	/// Punch holes into an image file, or discard the blocks of a device, where the filesystem
+	/// has free space
+	#[cfg(feature = "trim")]
+	Trim(TrimArgs),
+}
+
+#[derive(Args)]
+pub struct BootblockArgs {
+	/// Write the boot area to this file
+	#[arg(short, long, value_name = "FILE", required_unless_present = "replace")]
+	pub output: Option<PathBuf>,
+
+	/// Replace the beginning of the boot area by the contents of this file
+	#[arg(short, long, value_name = "FILE", conflicts_with = "output")]
+	pub replace: Option<PathBuf>,
+
+	/// Log the fragments to this file, before overwriting them, so that a crash doesn't
+	/// leave them half-written; a log left behind by a crash is applied first
+	#[arg(long, value_name = "FILE", requires = "replace")]
+	pub intent_log: Option<PathBuf>,
+
+	/// Don't lock the image, for filesystems, which don't support locking
+	#[arg(long)]
+	pub no_lock: bool,
+
+	/// Path to the image file or device
+	pub image: PathBuf,
+}
+
+#[derive(Args)]
+pub struct ChangedSinceArgs {
+	/// Print inode numbers instead of paths, which is faster, because no directories are read
+	#[arg(short, long)]
+	pub inodes: bool,
+
+	/// Don't lock the image, for filesystems, which don't support locking
+	#[arg(long)]
+	pub no_lock: bool,
+
+	/// Path to the image file or device
+	pub image: PathBuf,
+
+	/// Print files, whose timestamps are newer than this time, eg. 2024-01-31T12:00 (UTC),
+	/// or seconds since the epoch
+	#[arg(value_parser = parse_time)]
+	pub time: SystemTime,
+}
+
+#[derive(Args)]
+pub struct DdArgs {
+	/// Number of fragments to copy
+	#[arg(short = 'n', long, default_value_t = 1, conflicts_with = "replace")]
+	pub count: u64,
+
+	/// Write the fragments to this file, instead of stdout
+	#[arg(short, long, value_name = "FILE")]
+	pub output: Option<PathBuf>,
+
+	/// Overwrite the fragments by the contents of this file, whose size must be a multiple
+	/// of the fragment size
+	#[arg(short, long, value_name = "FILE", conflicts_with = "output")]
+	pub replace: Option<PathBuf>,
+
+	/// Log the fragments to this file, before overwriting them, so that a crash doesn't
+	/// leave them half-written; a log left behind by a crash is applied first
+	#[arg(long, value_name = "FILE", requires = "replace")]
+	pub intent_log: Option<PathBuf>,
+
+	/// Don't lock the image, for filesystems, which don't support locking
+	#[arg(long)]
+	pub no_lock: bool,
+
+	/// Path to the image file or device
+	pub image: PathBuf,
+
+	/// Number of the first fragment
+	pub daddr: u64,
+}
+
+#[derive(Args)]
+pub struct FindArgs {
+	/// Only print files, which are newer than this time, eg. 2024-01-31T12:00 (UTC),
+	/// or seconds since the epoch
+	#[arg(long, value_name = "TIME", value_parser = parse_time)]
+	pub newer_than: Option<SystemTime>,
+
+	/// Timestamp, which is compared by --newer-than
+	#[arg(long, value_enum, default_value_t = TimeField::Birth)]
+	pub time: TimeField,
+
+	/// Only print files of this type: b, c, d, f, l, p or s
+	#[arg(long = "type", value_name = "TYPE", value_parser = parse_type)]
+	pub kind: Option<rufs::InodeType>,
+
+	/// Only print files, whose name matches this shell pattern
+	#[arg(long, value_name = "GLOB")]
+	pub name: Option<OsString>,
+
+	/// Only print files, which have all of these flags set, eg. schg,nodump
+	#[arg(long, value_name = "FLAGS", value_parser = parse_flags)]
+	pub flags: Option<u32>,
+
+	/// Don't lock the image, for filesystems, which don't support locking
+	#[arg(long)]
+	pub no_lock: bool,
+
+	/// Map the image into memory, which is faster for large directory trees
+	#[cfg(feature = "mmap")]
+	#[arg(long)]
+	pub mmap: bool,
+
+	/// Path to the image file or device
+	pub image: PathBuf,
+
+	/// Directory in the filesystem, where the search starts
+	#[arg(default_value = "/")]
+	pub path: PathBuf,
+}
+
+/// Timestamps of a file, like `find -newerBt`, `-newermt` etc.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TimeField {
+	/// Time of creation
+	Birth,
+	/// Time of the last modification of the contents
+	Modify,
+	/// Time of the last change of the inode
+	Change,
+	/// Time of the last access
+	Access,
+}
+
+#[cfg(feature = "mkfs")]
+#[derive(Args)]
+pub struct MkfsArgs {
+	/// Block size
+	#[arg(short, default_value_t = 32768)]
+	pub bsize: u32,
+
+	/// Fragment size
+	#[arg(short, default_value_t = 4096)]
+	pub fsize: u32,
+
+	/// Bytes of data per inode [default: twice the fragment size]
+	#[arg(short = 'i')]
+	pub density: Option<u32>,
+
+	/// Percentage of space, that is reserved for the superuser
+	#[arg(short, default_value_t = 8)]
+	pub minfree: u8,
+
+	/// Size of the filesystem, eg. 64M [default: size of the device]
+	#[arg(short, value_parser = parse_size)]
+	pub size: Option<u64>,
+
+	/// Volume name
+	#[arg(short = 'L')]
+	pub volname: Option<String>,
+
+	/// Use the big-endian byte order
+	#[arg(short = 'B', long)]
+	pub big_endian: bool,
+
+	/// Timestamp in seconds since the epoch, for reproducible images
+	#[arg(long)]
+	pub time: Option<i64>,
+
+	/// Seed of the filesystem id and inode generation numbers, for reproducible images
+	#[arg(long)]
+	pub seed: Option<u64>,
+
+	/// Don't lock the image, for filesystems, which don't support locking
+	#[arg(long)]
+	pub no_lock: bool,
+
+	/// Path to the image file or device, image files are created if `-s` is given
+	pub device: PathBuf,
+}
+
+#[cfg(feature = "trim")]
+#[derive(Args)]
+pub struct TrimArgs {
+	/// Trim the filesystem, even if it wasn't unmounted cleanly
+	#[arg(short, long)]
+	pub force: bool,
+
+	/// Don't lock the image, for filesystems, which don't support locking
+	#[arg(long)]
+	pub no_lock: bool,
+
+	/// Path to the image file or device
+	pub image: PathBuf,
+}
+
+#[cfg(feature = "mkfs")]
+impl MkfsArgs {
+	/// Parameters for creating a filesystem of `size` bytes.
+	pub fn mkfs_options(&self, size: u64) -> rufs::MkfsOptions {
+		let defaults = rufs::MkfsOptions::new(size);
+		rufs::MkfsOptions {
+			bsize: self.bsize,
+			fsize: self.fsize,
+			density: self.density,
+			minfree: self.minfree,
+			volname: self.volname.clone().unwrap_or_default(),
+			big_endian: self.big_endian,
+			time: self.time.unwrap_or(defaults.time),
+			seed: self.seed.unwrap_or(defaults.seed),
+			..defaults
+		}
+	}
+}
+
+/// Options, which are handled by fuse-ufs itself, instead of being passed to the kernel.
+const FS_OPTIONS: &[&str] = &[
+	"allow_create",
+	"allow_delete",
+	"allow_metadata",
+	"allow_overwrite",
+	"barrier",
+	"cache",
+	"check_permissions",
+	"dangling",
+	"dcache",
+	"direct",
+	"dirhash",
+	"force",
+	"gid",
+	"idmap",
+	"iosize",
+	"nolock",
+	"offset",
+	"part",
+	"prime",
+	"readahead",
+	"root_squash",
+	"size",
+	"statahead",
+	"summary",
+	"supervise",
+	"synthdots",
+	"threads",
+	"uid",
+	"uring",
+	"whiteouts",
+];
+
+/// Default size of the block cache.
+pub const DEFAULT_CACHE_SIZE: u64 = 16 << 20;
+
+/// Default number of cached directory lookups.
+const DEFAULT_DCACHE: usize = 4096;
+
+/// Default amount of memory for the hash indexes of large directories.
+const DEFAULT_DIRHASH: u64 = 2 << 20;
+
+/// Default amount of data to prefetch for sequential reads.
+const DEFAULT_READAHEAD: u64 = 128 << 10;
+
+/// Default number of inodes to prefetch, when many entries of a directory are looked up.
+const DEFAULT_STATAHEAD: usize = 1024;
+
+/// How fuse-ufs checks permissions, instead of the kernel (`-o check_permissions`).
+#[derive(Debug, Clone, Copy)]
+pub struct Permissions {
+	/// Treat requests by root like requests by "nobody" (`-o root_squash`).
+	pub root_squash: bool,
+}
+
+/// Parse a size with an optional suffix, like `512K`.
+fn parse_size(s: &str) -> anyhow::Result<u64> {
+	let (num, shift) = match s.char_indices().last() {
+		Some((i, 'k' | 'K')) => (&s[..i], 10),
+		Some((i, 'm' | 'M')) => (&s[..i], 20),
+		Some((i, 'g' | 'G')) => (&s[..i], 30),
+		_ => (s, 0),
+	};
+	let num: u64 = num.parse().with_context(|| format!("invalid size: {s}"))?;
+	num.checked_shl(shift)
+		.filter(|x| x >> shift == num)
+		.with_context(|| format!("size too large: {s}"))
+}
+
+/// Parse a point in time, either as seconds since the epoch,
+/// or as `YYYY-MM-DD`, optionally followed by `THH:MM[:SS]`, in UTC.
+fn parse_time(s: &str) -> anyhow::Result<SystemTime> {
+	let secs = match s.parse::<i64>() {
+		Ok(secs) => secs,
+		Err(_) => parse_date(s).with_context(|| format!("invalid time: {s}"))?,
+	};
+	let t = match u64::try_from(secs) {
+		Ok(secs) => SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(secs)),
+		Err(_) => SystemTime::UNIX_EPOCH.checked_sub(Duration::from_secs(secs.unsigned_abs())),
+	};
+	t.with_context(|| format!("time out of range: {s}"))
+}
+
+/// Seconds since the epoch of `YYYY-MM-DD[THH:MM[:SS]]` in UTC.
+fn parse_date(s: &str) -> Option<i64> {
+	let (date, time) = match s.split_once(['T', ' ']) {
+		Some((date, time)) => (date, Some(time)),
+		None => (s, None),
+	};
+
+	let mut date = date.splitn(3, '-');
+	let y: i64 = date.next()?.parse().ok()?;
+	let m: i64 = date.next()?.parse().ok()?;
+	let d: i64 = date.next()?.parse().ok()?;
+	if !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+		return None;
+	}
+
+	let (mut hh, mut mm, mut ss) = (0, 0, 0);
+	if let Some(time) = time {
+		let mut time = time.splitn(3, ':');
+		hh = time.next()?.parse().ok()?;
+		mm = time.next()?.parse().ok()?;
+		ss = time.next().map_or(Some(0), |x| x.parse().ok())?;
+		if hh > 23 || mm > 59 || ss > 60 {
+			return None;
+		}
+	}
+
+	// Days since the epoch, from Howard Hinnant's `days_from_civil()`.
+	let y = if m <= 2 { y - 1 } else { y };
+	let era = y.div_euclid(400);
+	let yoe = y - era * 400;
+	let doy = (153 * ((m + 9) % 12) + 2) / 5 + d - 1;
+	let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+	let days = era * 146097 + doe - 719468;
+
+	Some(days * 86400 + hh * 3600 + mm * 60 + ss)
+}
+
+/// Parse a file type, like `find -type`.
+fn parse_type(s: &str) -> anyhow::Result<rufs::InodeType> {
+	use rufs::InodeType;
+
+	Ok(match s {
+		"b" => InodeType::BlockDevice,
+		"c" => InodeType::CharDevice,
+		"d" => InodeType::Directory,
+		"f" => InodeType::RegularFile,
+		"l" => InodeType::Symlink,
+		"p" => InodeType::NamedPipe,
+		"s" => InodeType::Socket,
+		_ => bail!("invalid file type: {s}, expected one of b, c, d, f, l, p, s"),
+	})
+}
+
+/// Parse a comma-separated list of file flags, with the names used by `chflags(1)`.
+fn parse_flags(s: &str) -> anyhow::Result<u32> {
+	s.split(',').try_fold(0, |flags, name| {
+		let flag = match name {
+			"arch" | "archived" => rufs::SF_ARCHIVED,
+			"nodump" => rufs::UF_NODUMP,
+			"opaque" => rufs::UF_OPAQUE,
+			"sappnd" | "sappend" => rufs::SF_APPEND,
+			"schg" | "schange" | "simmutable" => rufs::SF_IMMUTABLE,
+			"snapshot" => rufs::SF_SNAPSHOT,
+			"sunlnk" | "sunlink" => rufs::SF_NOUNLINK,
+			"uappnd" | "uappend" => rufs::UF_APPEND,
+			"uchg" | "uchange" | "uimmutable" => rufs::UF_IMMUTABLE,
+			"uunlnk" | "uunlink" => rufs::UF_NOUNLINK,
+			_ => bail!("unknown file flag: {name}"),
+		};
+		Ok(flags | flag)
+	})
+}
+
+/// Parse a table of ids (`-o idmap=FILE`), which consists of lines like `u 1001 1000`,
+/// meaning that the user 1001 on disk is presented as 1000, and `g` lines for groups.
+/// Empty lines, and comments starting with `#` are ignored.
+fn parse_idmap(text: &str, idmap: &mut rufs::IdMap) -> anyhow::Result<()> {
+	for (i, line) in text.lines().enumerate() {
+		let line = line.split_once('#').map_or(line, |(line, _)| line);
+		let fields: Vec<_> = line.split_whitespace().collect();
+		let (map, from, to) = match fields[..] {
+			[] => continue,
+			["u", from, to] => (&mut idmap.uids, from, to),
+			["g", from, to] => (&mut idmap.gids, from, to),
+			_ => bail!("line {}: expected \"u|g FS_ID ID\": {line}", i + 1),
+		};
+		let parse = |id: &str| {
+			id.parse::<u32>()
+				.with_context(|| format!("line {}: invalid id: {id}", i + 1))
+		};
+		map.insert(parse(from)?, parse(to)?);
+	}
+	Ok(())
+}
+
+fn is_fs_option(opt: &str) -> bool {
+	let name = opt.split_once('=').map_or(opt, |(name, _)| name);
+	FS_OPTIONS.contains(&name)
+}
+
 impl Cli {
+	/// Get the value of an option, which is handled by fuse-ufs itself (eg. `-o threads=4`).
+	pub fn fs_option(&self, name: &str) -> Option<&str> {
+		self.options
+			.iter()
+			.rev()
+			.find_map(|opt| opt.strip_prefix(name)?.strip_prefix('='))
+	}
+
+	/// Check whether a flag, which is handled by fuse-ufs itself, was given (eg. `-o force`).
+	pub fn fs_flag(&self, name: &str) -> bool {
+		self.options.iter().any(|opt| opt == name)
+	}
+
+	/// Options for opening the filesystem.
+	pub fn ufs_options(&self) -> anyhow::Result<rufs::Options> {
+		let dangling_entries = match self.fs_option("dangling") {
+			None | Some("show") => rufs::DanglingEntries::Show,
+			Some("hide") => rufs::DanglingEntries::Hide,
+			Some(x) => bail!("invalid value for dangling: {x}"),
+		};
+		let dcache = match self.fs_option("dcache") {
+			None => DEFAULT_DCACHE,
+			Some(n) => {
+				n.parse()
+					.with_context(|| format!("invalid number of cached lookups: {n}"))?
+			}
+		};
+		let statahead = match self.fs_option("statahead") {
+			None => DEFAULT_STATAHEAD,
+			Some(n) => {
+				n.parse()
+					.with_context(|| format!("invalid number of inodes to prefetch: {n}"))?
+			}
+		};
+		let summary = match self.fs_option("summary") {
+			None => rufs::SummaryCheck::Off,
+			Some("check") => rufs::SummaryCheck::Check,
+			Some("fix") => rufs::SummaryCheck::Fix,
+			Some(x) => bail!("invalid value for summary: {x}"),
+		};
+		// "sync" is passed to the kernel as well.
+		let durability = if self.fs_flag("sync") {
+			rufs::DurabilityPolicy::Sync
+		} else if self.fs_flag("barrier") {
+			rufs::DurabilityPolicy::Barrier
+		} else {
+			rufs::DurabilityPolicy::Async
+		};
+		// These are passed to the kernel as well, and the last one wins.
+		let atime = self
+			.options
+			.iter()
+			.rev()
+			.find(|opt| matches!(opt.as_str(), "noatime" | "relatime" | "strictatime"));
+		let atime = match atime.map(String::as_str) {
+			Some("noatime") => rufs::AtimePolicy::NoAtime,
+			Some("strictatime") => rufs::AtimePolicy::Strict,
+			_ => rufs::AtimePolicy::Relatime,
+		};
+		Ok(rufs::Options {
+			dangling_entries,
+			dcache,
+			dirhash: self
+				.fs_option("dirhash")
+				.map_or(Ok(DEFAULT_DIRHASH), parse_size)?,
+			force: self.fs_flag("force"),
+			synthesize_dots: self.fs_flag("synthdots"),
+			write: rufs::WriteCaps {
+				create:    self.fs_flag("allow_create"),
+				delete:    self.fs_flag("allow_delete"),
+				overwrite: self.fs_flag("allow_overwrite"),
+				metadata:  self.fs_flag("allow_metadata"),
+				raw:       false,
+			},
+			io_size: self.fs_option("iosize").map(parse_size).transpose()?,
+			readahead: self
+				.fs_option("readahead")
+				.map_or(Ok(DEFAULT_READAHEAD), parse_size)?,
+			statahead,
+			durability,
+			atime,
+			alternate_superblock: self.force_alternate_sb,
+			summary,
+			idmap: self.idmap()?,
+			whiteouts: self.fs_flag("whiteouts"),
+			// TODO: -o intent_log, once the filesystem can be mounted read-write.
+			intent_log: None,
+		})
+	}
+
+	/// Whether fuse-ufs checks permissions itself, instead of letting the kernel check them
+	/// (`-o check_permissions`), which `-o root_squash` implies.
+	pub fn permissions(&self) -> Option<Permissions> {
+		let root_squash = self.fs_flag("root_squash");
+		(self.fs_flag("check_permissions") || root_squash).then_some(Permissions { root_squash })
+	}
+
+	/// How the owners and groups of files are presented
+	/// (`-o uid=N,gid=N` and `-o idmap=FILE`).
+	fn idmap(&self) -> anyhow::Result<rufs::IdMap> {
+		let mut idmap = rufs::IdMap::default();
+		if let Some(path) = self.fs_option("idmap") {
+			let text = std::fs::read_to_string(path)
+				.with_context(|| format!("failed to read the id map {path}"))?;
+			parse_idmap(&text, &mut idmap).with_context(|| format!("invalid id map {path}"))?;
+		}
+		let id = |name| {
+			self.fs_option(name)
+				.map(|n| n.parse().with_context(|| format!("invalid {name}: {n}")))
+				.transpose()
+		};
+		idmap.force_uid = id("uid")?;
+		idmap.force_gid = id("gid")?;
+		Ok(idmap)
+	}
+
+	/// Part of the device, which holds the filesystem (`-o offset=BYTES,size=BYTES`).
+	pub fn window(&self) -> anyhow::Result<Option<(u64, Option<u64>)>> {
+		let offset = self.fs_option("offset").map(parse_size).transpose()?;
+		let size = self.fs_option("size").map(parse_size).transpose()?;
+		if offset.is_none() && size.is_none() {
+			return Ok(None);
+		}
+		ensure!(
+			self.fs_option("part").is_none(),
+			"part can't be combined with offset or size"
+		);
+		Ok(Some((offset.unwrap_or(0), size)))
+	}
+
+	/// Size of the block cache in bytes (`-o cache=SIZE`).
+	pub fn cache_size(&self) -> anyhow::Result<u64> {
+		self.fs_option("cache")
+			.map_or(Ok(DEFAULT_CACHE_SIZE), parse_size)
+	}
+
+	/// The file, in which the cached blocks are saved at unmount (`-o prime` or `-o prime=FILE`).
+	/// By default, it is next to `device`.
+	pub fn prime(&self, device: &Path) -> Option<PathBuf> {
+		match self.fs_option("prime") {
+			Some(path) => Some(path.into()),
+			None => self.fs_flag("prime").then(|| crate::prime::sidecar(device)),
+		}
+	}
+
+	/// Number of threads handling requests (`-o threads=N`).
+	#[cfg(feature = "fuse3")]
+	pub fn threads(&self) -> anyhow::Result<usize> {
+		let Some(n) = self.fs_option("threads") else {
+			return Ok(std::thread::available_parallelism().map_or(1, |n| n.get()));
+		};
+		let n = n
+			.parse()
+			.with_context(|| format!("invalid number of threads: {n}"))?;
+		ensure!(n > 0, "number of threads must be at least 1");
+		Ok(n)
+	}
+
+	/// Whether the setuid and setgid bits, and device files are honored (`-o suid` and `-o dev`),
+	/// where the last of `suid` and `nosuid`, or `dev` and `nodev` wins.
+	///
+	/// Images may come from anywhere, so both are off, unless they are explicitly enabled.
+	/// The kernel reports the decision in the flags of statvfs(3) (`ST_NOSUID` and `ST_NODEV`).
+	fn suid_dev(&self) -> (bool, bool) {
+		let last = |yes: &str, no: &str| {
+			self.options
+				.iter()
+				.rev()
+				.find_map(|opt| (opt == yes || opt == no).then(|| opt == yes))
+				.unwrap_or(false)
+		};
+		let (suid, dev) = (last("suid", "nosuid"), last("dev", "nodev"));
+		let other = self.options.iter().any(|o| o == "allow_other");
+		if suid {
+			log::warn!(
+				"-o suid: setuid and setgid programs of the image run with elevated privileges"
+			);
+		}
+		if dev {
+			log::warn!(
+				"-o dev: device files of the image give access to the devices of this system"
+			);
+		}
+		if (suid || dev) && other {
+			log::warn!("-o allow_other: every user can use them, only do this for trusted images");
+		}
+		(suid, dev)
+	}
+
 	#[cfg(feature = "fuse3")]
 	pub fn options(&self) -> Vec<fuser::MountOption> {
 		use fuser::MountOption;
 		let mut opts = vec![
 			MountOption::FSName("fusefs".into()),
 			MountOption::Subtype("ufs".into()),
-			MountOption::DefaultPermissions,
 			MountOption::RO,
 		];
+		if self.permissions().is_none() {
+			opts.push(MountOption::DefaultPermissions);
+		}
+		let (suid, dev) = self.suid_dev();
+		opts.push(if suid {
+			MountOption::Suid
+		} else {
+			MountOption::NoSuid
+		});
+		opts.push(if dev {
+			MountOption::Dev
+		} else {
+			MountOption::NoDev
+		});
 
-		for opt in &self.options {
+		for opt in self.options.iter().filter(|opt| !is_fs_option(opt)) {
 			let opt = match opt.as_str() {
 				"allow_other" => MountOption::AllowOther,
 				"allow_root" => MountOption::AllowRoot,
@@ -42,16 +703,13 @@ impl Cli {
 				"atime" => MountOption::Atime,
 				"auto_unmount" => MountOption::AutoUnmount,
 				"default_permissions" => continue,
-				"dev" => MountOption::Dev,
+				"dev" | "nodev" | "suid" | "nosuid" => continue,
 				"dirsync" => MountOption::DirSync,
 				"exec" => MountOption::Exec,
 				"noatime" => MountOption::NoAtime,
-				"nodev" => MountOption::NoDev,
 				"noexec" => MountOption::NoExec,
-				"nosuid" => MountOption::NoSuid,
 				"ro" => continue,
 				"rw" => panic!("rw is not yet supported"),
-				"suid" => MountOption::Suid,
 				"sync" => MountOption::Sync,
 				custom => MountOption::CUSTOM(custom.into()),
 			};
@@ -67,7 +725,21 @@ impl Cli {
 
 		use fuse2rs::MountOption;
 
-		let mut opts = vec![MountOption::DefaultPermissions, MountOption::Ro];
+		let mut opts = vec![MountOption::Ro];
+		if self.permissions().is_none() {
+			opts.push(MountOption::DefaultPermissions);
+		}
+		let (suid, dev) = self.suid_dev();
+		opts.push(if suid {
+			MountOption::Suid
+		} else {
+			MountOption::NoSuid
+		});
+		opts.push(if dev {
+			MountOption::Dev
+		} else {
+			MountOption::NoDev
+		});
 
 		if self.foreground {
 			opts.push(MountOption::Foreground);
@@ -81,22 +753,21 @@ impl Cli {
 			opts.push(MountOption::Debug);
 		}
 
-		for opt in &self.options {
+		for opt in self.options.iter().filter(|opt| !is_fs_option(opt)) {
 			let opt = match opt.as_str() {
 				"debug" => MountOption::Debug,
 				"allow_other" => MountOption::AllowOther,
 				"async" => MountOption::Async,
 				"atime" => MountOption::Atime,
+				// libfuse2 doesn't support it, see unmount.rs.
+				"auto_unmount" => continue,
 				"default_permissions" => continue,
-				"dev" => MountOption::Dev,
+				"dev" | "nodev" | "suid" | "nosuid" => continue,
 				"exec" => MountOption::Exec,
 				"noatime" => MountOption::NoAtime,
-				"nodev" => MountOption::NoDev,
 				"noexec" => MountOption::NoExec,
-				"nosuid" => MountOption::NoSuid,
 				"ro" => continue,
 				"rw" => panic!("rw is not yet supported"),
-				"suid" => MountOption::Suid,
 				"sync" => MountOption::Sync,
 				custom => MountOption::Custom(CString::new(custom)?),
 			};
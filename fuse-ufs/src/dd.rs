@@ -0,0 +1,69 @@
+use std::{fs::File, io::Write};
+
+use anyhow::{ensure, Context, Result};
+use rufs::{BlockFile, Options, Ufs, WindowedBackend, WriteCaps};
+
+use crate::cli::DdArgs;
+
+/// Copy fragments of a filesystem to a file, or overwrite them by the contents of a file.
+pub fn dd(args: &DdArgs) -> Result<()> {
+	let path = &args.image;
+	let file = File::options()
+		.read(true)
+		.write(args.replace.is_some())
+		.open(path)
+		.with_context(|| format!("failed to open {}", path.display()))?;
+	let file = BlockFile::from_file(file)?;
+	if !args.no_lock {
+		crate::lock(&file, path, args.replace.is_some(), "--no-lock")?;
+	}
+	let window = match rufs::find_ufs_partition(&file)? {
+		Some(part) => WindowedBackend::new(file, part.start, Some(part.size)),
+		None => WindowedBackend::whole(file),
+	};
+	let opts = Options {
+		write: WriteCaps {
+			raw: args.replace.is_some(),
+			..WriteCaps::NONE
+		},
+		intent_log: args.intent_log.clone(),
+		..Options::default()
+	};
+	let ufs = Ufs::with_options(window, opts)
+		.with_context(|| format!("failed to open the filesystem in {}", path.display()))?;
+	let replayed = ufs.stats().replayed_bytes;
+	if replayed > 0 {
+		eprintln!("replayed {replayed} bytes of the intent log");
+	}
+	let fsize = ufs.info().fsize as u64;
+
+	if let Some(input) = &args.replace {
+		let data =
+			std::fs::read(input).with_context(|| format!("failed to read {}", input.display()))?;
+		ensure!(
+			data.len() as u64 % fsize == 0,
+			"the size of {} is not a multiple of the fragment size of {fsize} bytes",
+			input.display()
+		);
+		ufs.with_txn(|txn| txn.write_raw_block(args.daddr, &data))
+			.with_context(|| format!("failed to write fragment {}", args.daddr))?;
+		return Ok(());
+	}
+
+	let len = args
+		.count
+		.checked_mul(fsize)
+		.and_then(|len| usize::try_from(len).ok())
+		.context("too many fragments")?;
+	let mut data = vec![0u8; len];
+	ufs.read_raw_block(args.daddr, &mut data)
+		.with_context(|| format!("failed to read fragment {}", args.daddr))?;
+	match &args.output {
+		Some(output) => {
+			std::fs::write(output, data)
+				.with_context(|| format!("failed to write {}", output.display()))?
+		}
+		None => std::io::stdout().lock().write_all(&data)?,
+	}
+	Ok(())
+}
@@ -0,0 +1,84 @@
+//! Exit codes, which allow scripts to react to failures, without parsing messages.
+use std::{
+	fmt::{self, Display, Formatter},
+	io::{self, ErrorKind},
+	process::ExitCode,
+};
+
+/// The reason, why fuse-ufs failed.
+///
+/// The values are stable, and documented in fuse-ufs(8).
+/// 2 is used by clap for invalid command lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+	/// Any other failure.
+	Failure = 1,
+	/// The device doesn't contain a UFS filesystem.
+	BadMagic = 3,
+	/// The superblock, or another structure of the filesystem, is corrupted.
+	Corrupted = 4,
+	/// The filesystem, or the requested mode, like read-write, is not supported.
+	Unsupported = 5,
+	/// The device or the mount point can't be accessed.
+	PermissionDenied = 6,
+	/// The device is in use by another process, or the mount point is busy.
+	Busy = 7,
+	/// `--check` found inconsistencies.
+	Inconsistent = 8,
+}
+
+impl Status {
+	/// Determine the exit code of a failure.
+	///
+	/// A `Status` in the chain of causes takes precedence, otherwise the first [`io::Error`]
+	/// is classified by the [`rufs::Error`] it wraps, its errno, or kind.
+	pub fn of(e: &anyhow::Error) -> Self {
+		if let Some(status) = e.chain().find_map(|c| c.downcast_ref::<Status>()) {
+			return *status;
+		}
+		e.chain()
+			.find_map(|c| c.downcast_ref::<io::Error>())
+			.map_or(Self::Failure, Self::of_io)
+	}
+
+	fn of_io(e: &io::Error) -> Self {
+		match rufs::Error::of(e) {
+			Some(rufs::Error::Corrupt { .. }) => return Self::Corrupted,
+			Some(rufs::Error::Unsupported { .. }) => return Self::Unsupported,
+			_ => {}
+		}
+		match (e.raw_os_error(), e.kind()) {
+			(Some(libc::EACCES | libc::EPERM), _) => Self::PermissionDenied,
+			(Some(libc::EBUSY), _) | (_, ErrorKind::WouldBlock) => Self::Busy,
+			(Some(libc::EIO), _) => Self::Corrupted,
+			(Some(libc::EROFS | libc::EOPNOTSUPP), _) => Self::Unsupported,
+			(Some(errno), _) if errno == libc::ENOTSUP => Self::Unsupported,
+			(None, ErrorKind::Unsupported) => Self::Unsupported,
+			(None, ErrorKind::InvalidData) => Self::BadMagic,
+			_ => Self::Failure,
+		}
+	}
+}
+
+impl Display for Status {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		let msg = match self {
+			Self::Failure => "failure",
+			Self::BadMagic => "not a UFS filesystem",
+			Self::Corrupted => "the filesystem is corrupted",
+			Self::Unsupported => "not supported",
+			Self::PermissionDenied => "permission denied",
+			Self::Busy => "busy",
+			Self::Inconsistent => "the filesystem is inconsistent",
+		};
+		f.write_str(msg)
+	}
+}
+
+impl std::error::Error for Status {}
+
+impl From<Status> for ExitCode {
+	fn from(status: Status) -> Self {
+		Self::from(status as u8)
+	}
+}
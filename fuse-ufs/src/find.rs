@@ -0,0 +1,248 @@
+use std::{
+	ffi::{OsStr, OsString},
+	fs::File,
+	io::{BufWriter, ErrorKind, Write},
+	os::unix::ffi::OsStrExt,
+	path::Component,
+};
+
+use anyhow::{ensure, Context, Result};
+use rufs::{Backend, InodeAttr, InodeNum, InodeType, TreeGuard, Ufs, WindowedBackend};
+
+use crate::cli::{FindArgs, TimeField};
+
+struct Finder<'a, B: Backend, W: Write> {
+	ufs:    &'a Ufs<B>,
+	args:   &'a FindArgs,
+	out:    W,
+	guard:  TreeGuard,
+	errors: usize,
+}
+
+/// Walk the directory tree of a filesystem, and print the paths of all files,
+/// which match the filters, like find(1).
+pub fn find(args: &FindArgs) -> Result<()> {
+	let path = &args.image;
+	let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+	let file = rufs::BlockFile::from_file(file)?;
+	if !args.no_lock {
+		crate::lock(&file, path, false, "--no-lock")?;
+	}
+
+	#[cfg(feature = "mmap")]
+	if args.mmap {
+		// The duplicated descriptor shares the lock.
+		let file = rufs::MmapBackend::new(file.get_ref().try_clone()?)
+			.with_context(|| format!("failed to map {}", path.display()))?;
+		return search(file, args);
+	}
+	search(file, args)
+}
+
+/// Search the filesystem, which is in `file`, or in a partition of it.
+fn search<B: Backend>(file: B, args: &FindArgs) -> Result<()> {
+	let path = &args.image;
+	let window = match rufs::find_ufs_partition(&file)? {
+		Some(part) => WindowedBackend::new(file, part.start, Some(part.size)),
+		None => WindowedBackend::whole(file),
+	};
+	let ufs = Ufs::new(window)
+		.with_context(|| format!("failed to open the filesystem in {}", path.display()))?;
+
+	let start = resolve(&ufs, args)?;
+	let name = args
+		.path
+		.file_name()
+		.unwrap_or_else(|| args.path.as_os_str());
+
+	let mut finder = Finder {
+		ufs: &ufs,
+		args,
+		out: BufWriter::new(std::io::stdout().lock()),
+		guard: TreeGuard::new(),
+		errors: 0,
+	};
+	let res = finder
+		.visit(args.path.as_os_str().to_owned(), name, start)
+		.and_then(|()| finder.out.flush());
+	match res {
+		Ok(()) => {}
+		// Stop quietly, eg. when piped into head(1).
+		Err(e) if e.kind() == ErrorKind::BrokenPipe => return Ok(()),
+		Err(e) => return Err(e).context("failed to write to stdout"),
+	}
+
+	ensure!(
+		finder.errors == 0,
+		"{} files or directories could not be read",
+		finder.errors
+	);
+	Ok(())
+}
+
+/// Look up the directory, where the search starts.
+fn resolve<B: Backend>(ufs: &Ufs<B>, args: &FindArgs) -> Result<InodeNum> {
+	let mut inr = InodeNum::ROOT;
+	for c in args.path.components() {
+		let name: &OsStr = match c {
+			Component::RootDir | Component::CurDir => continue,
+			Component::ParentDir => "..".as_ref(),
+			Component::Normal(name) => name,
+			Component::Prefix(_) => unreachable!(),
+		};
+		inr = ufs
+			.dir_lookup(inr, name)
+			.with_context(|| format!("failed to look up {}", args.path.display()))?;
+	}
+	Ok(inr)
+}
+
+impl<B: Backend, W: Write> Finder<'_, B, W> {
+	/// Print `path`, if it matches, and search it, if it is a directory.
+	/// Only errors while printing are returned, others are logged and counted.
+	fn visit(&mut self, path: OsString, name: &OsStr, inr: InodeNum) -> std::io::Result<()> {
+		let attr = match self.ufs.inode_attr(inr) {
+			Ok(attr) => attr,
+			Err(e) => {
+				log::error!("{}: {e}", path.to_string_lossy());
+				self.errors += 1;
+				return Ok(());
+			}
+		};
+
+		if self.args.matches(name, &attr) {
+			self.out.write_all(path.as_bytes())?;
+			self.out.write_all(b"\n")?;
+		}
+		if attr.kind != InodeType::Directory {
+			return Ok(());
+		}
+
+		let mut entries = Vec::new();
+		let res = self.guard.enter(inr).and_then(|()| {
+			let res = self.ufs.dir_iter(inr, |name, inr, _kind| {
+				if name != "." && name != ".." {
+					entries.push((name.to_owned(), inr));
+				}
+				None::<()>
+			});
+			if res.is_err() {
+				self.guard.leave();
+			}
+			res
+		});
+		if let Err(e) = res {
+			log::error!("{}: {e}", path.to_string_lossy());
+			self.errors += 1;
+			return Ok(());
+		}
+
+		for (name, inr) in entries {
+			let mut child = path.clone();
+			if !path.as_bytes().ends_with(b"/") {
+				child.push("/");
+			}
+			child.push(&name);
+			self.visit(child, &name, inr)?;
+		}
+		self.guard.leave();
+		Ok(())
+	}
+}
+
+impl FindArgs {
+	/// Check, whether a file named `name` matches all filters.
+	fn matches(&self, name: &OsStr, attr: &InodeAttr) -> bool {
+		if let Some(t) = self.newer_than {
+			let time = match self.time {
+				TimeField::Birth => attr.btime,
+				TimeField::Modify => attr.mtime,
+				TimeField::Change => attr.ctime,
+				TimeField::Access => attr.atime,
+			};
+			if time <= t {
+				return false;
+			}
+		}
+		if self.kind.is_some_and(|kind| kind != attr.kind) {
+			return false;
+		}
+		if let Some(pat) = &self.name {
+			if !fnmatch(pat.as_bytes(), name.as_bytes()) {
+				return false;
+			}
+		}
+		self.flags.map_or(true, |f| attr.flags & f == f)
+	}
+}
+
+/// Match `name` against a shell pattern, which may contain `*`, `?`, `[...]` and `\`,
+/// like fnmatch(3) without any flags.
+fn fnmatch(pat: &[u8], name: &[u8]) -> bool {
+	// Position in the pattern after the last `*`, and in the name, where it was tried.
+	let mut star = None;
+	let (mut p, mut n) = (0, 0);
+	while n < name.len() {
+		let step = match pat.get(p) {
+			Some(b'*') => {
+				star = Some((p + 1, n));
+				p += 1;
+				continue;
+			}
+			Some(b'?') => Some(1),
+			Some(b'[') => bracket(&pat[(p + 1)..], name[n]).map(|len| len + 1),
+			Some(b'\\') if p + 1 < pat.len() => (pat[p + 1] == name[n]).then_some(2),
+			Some(&c) => (c == name[n]).then_some(1),
+			None => None,
+		};
+		match (step, star) {
+			(Some(len), _) => {
+				p += len;
+				n += 1;
+			}
+			(None, Some((sp, sn))) => {
+				// Let the last `*` match one more byte.
+				star = Some((sp, sn + 1));
+				p = sp;
+				n = sn + 1;
+			}
+			(None, None) => return false,
+		}
+	}
+	pat[p..].iter().all(|&c| c == b'*')
+}
+
+/// Match `c` against a bracket expression, of which `pat` is the part after the `[`.
+/// Returns the length of the rest of the expression, including the `]`, if `c` matches.
+fn bracket(pat: &[u8], c: u8) -> Option<usize> {
+	let (negate, mut i) = match pat.first() {
+		Some(b'!' | b'^') => (true, 1),
+		_ => (false, 0),
+	};
+	let mut found = false;
+	let mut first = true;
+	loop {
+		let lo = match pat.get(i) {
+			// An unterminated `[` matches itself.
+			None => return (c == b'[').then_some(0),
+			Some(b']') if !first => break,
+			Some(b'\\') if i + 1 < pat.len() => {
+				i += 1;
+				pat[i]
+			}
+			Some(&lo) => lo,
+		};
+		first = false;
+		i += 1;
+
+		let hi = match (pat.get(i), pat.get(i + 1)) {
+			(Some(b'-'), Some(&hi)) if hi != b']' => {
+				i += 2;
+				hi
+			}
+			_ => lo,
+		};
+		found |= (lo..=hi).contains(&c);
+	}
+	(found != negate).then_some(i + 1)
+}
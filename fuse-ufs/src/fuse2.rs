@@ -3,77 +3,125 @@ use std::{
 	io::{Error, Result},
 	os::unix::ffi::OsStrExt,
 	path::Path,
+	sync::Arc,
 };
 
 use fuse2rs::*;
-use rufs::InodeNum;
+use rufs::{Credentials, InodeNum};
 
-use crate::Fs;
+use crate::{open_mask, open_read_only, prime, Fs};
 
 impl Fs {
-	fn lookup(&mut self, path: &Path) -> Result<InodeNum> {
+	/// Look up `path`, after checking the search permission of each directory,
+	/// if fuse-ufs checks permissions.
+	fn lookup(&mut self, req: &Request, path: &Path) -> Result<InodeNum> {
 		if !path.is_absolute() {
 			return Err(Error::from_raw_os_error(libc::EINVAL));
 		}
 
+		let cred = self.cred(req);
 		let mut inr = InodeNum::ROOT;
 		for comp in path.components().skip(1) {
-			inr = self.ufs.dir_lookup(inr, comp.as_os_str())?;
+			if let Some(cred) = &cred {
+				self.ufs.get().access(inr, cred, libc::X_OK)?;
+			}
+			inr = self.ufs.get().dir_lookup(inr, comp.as_os_str())?;
 		}
 		Ok(inr)
 	}
+
+	/// Credentials of the process, which made `req`, if fuse-ufs checks permissions.
+	fn cred(&self, req: &Request) -> Option<Credentials> {
+		self.credentials(req.uid, req.gid, None)
+	}
+
+	/// Check the permissions for `open()` and `opendir()`.
+	fn open_path(&mut self, req: &Request, path: &Path, flags: i32) -> Result<()> {
+		let inr = self.lookup(req, path)?;
+		if let Some(cred) = self.cred(req) {
+			self.ufs.get().access(inr, &cred, open_mask(flags))?;
+		}
+		open_read_only(flags)
+	}
 }
 
 impl Filesystem for Fs {
-	fn getattr(&mut self, _req: &Request, path: &Path) -> Result<FileAttr> {
-		let inr = self.lookup(path)?;
-		let ino = self.ufs.inode_attr(inr)?;
+	fn init(&mut self, _req: &Request) {
+		// fuse2rs daemonizes before calling this, so threads can be spawned here.
+		if let Some(path) = &self.prime {
+			prime::spawn(Arc::clone(&self.ufs), path.clone());
+		}
+	}
+
+	fn destroy(&mut self) {
+		if let Some(path) = &self.prime {
+			if let Err(e) = prime::save(&self.ufs.get(), path) {
+				log::warn!("failed to save the cached blocks: {e:#}");
+			}
+		}
+	}
+
+	fn getattr(&mut self, req: &Request, path: &Path) -> Result<FileAttr> {
+		let inr = self.lookup(req, path)?;
+		let ino = self.ufs.get().inode_attr(inr)?;
 		Ok(ino.into())
 	}
 
 	fn readdir(
 		&mut self,
-		_req: &Request,
+		req: &Request,
 		path: &Path,
 		off: u64,
 		filler: &mut DirFiller,
 		_info: &FileInfo,
 	) -> Result<()> {
-		let pinr = self.lookup(path)?;
+		let pinr = self.lookup(req, path)?;
 
 		// TODO
 		if off != 0 {
 			return Ok(());
 		}
 
-		self.ufs.dir_iter(pinr, |name, _inr, _kind| {
-			let name = CString::new(name.as_bytes().to_vec()).unwrap();
+		let res = self.ufs.get().dir_iter(pinr, |name, _inr, _kind| {
+			// rufs rejects names with a NUL byte, but don't rely on it.
+			let Ok(name) = CString::new(name.as_bytes()) else {
+				log::error!("readdir({path:?}): name with a NUL byte: {name:?}");
+				return Some(Err(Error::from_raw_os_error(libc::EIO)));
+			};
 			if filler.push(&name) {
 				None
 			} else {
-				Some(())
+				Some(Ok(()))
 			}
 		})?;
 
-		Ok(())
+		res.unwrap_or(Ok(()))
 	}
 
 	fn read(
 		&mut self,
-		_req: &Request,
+		req: &Request,
 		path: &Path,
 		off: u64,
 		buf: &mut [u8],
 		_info: &FileInfo,
 	) -> Result<usize> {
-		let inr = self.lookup(path)?;
-		let num = self.ufs.inode_read(inr, off, buf)?;
+		let inr = self.lookup(req, path)?;
+		let num = self.ufs.get().inode_read(inr, off, buf)?;
 		Ok(num)
 	}
 
-	fn readlink(&mut self, _req: &Request, path: &Path, buf: &mut [u8]) -> Result<()> {
-		let inr = self.lookup(path)?;
-		let link = self.ufs.symlink_read(inr)?;
+	fn open(&mut self, req: &Request, path: &Path, info: &mut FileInfo) -> Result<()> {
+		self.open_path(req, path, info.flags)
+	}
+
+	fn opendir(&mut self, req: &Request, path: &Path, info: &mut FileInfo) -> Result<()> {
+		self.open_path(req, path, info.flags)
+	}
+
+	fn readlink(&mut self, req: &Request, path: &Path, buf: &mut [u8]) -> Result<()> {
+		let inr = self.lookup(req, path)?;
+		let link = self.ufs.get().symlink_read(inr)?;
 
 		let len = link.len();
 
@@ -88,14 +136,14 @@ impl Filesystem for Fs {
 	}
 
 	fn statfs(&mut self, _req: &Request, _path: &Path) -> Result<Statfs> {
-		let info = self.ufs.info();
+		let info = self.ufs.get().info();
 
 		Ok(Statfs {
-			bsize:  info.bsize,
-			frsize: info.fsize,
+			bsize:  info.iosize,
+			frsize: info.frsize,
 			blocks: info.blocks,
 			bfree:  info.bfree,
-			bavail: info.bfree,
+			bavail: info.bavail,
 			files:  info.files,
 			ffree:  info.ffree,
 			favail: info.ffree,
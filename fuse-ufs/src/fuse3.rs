@@ -1,26 +1,105 @@
 use std::{
 	ffi::{c_int, OsStr},
 	io::{Error as IoError, ErrorKind, Result as IoResult},
+	sync::Arc,
 	time::Duration,
 };
 
 use fuser::{FileAttr, Filesystem, KernelConfig, Request};
-use rufs::InodeNum;
+#[cfg(target_os = "linux")]
+use rufs::{Acl, AclTag, IoctlRequest};
+use rufs::{Credentials, InodeNum, Ufs, Whence};
 
-use crate::Fs;
+use crate::{
+	handles::{Dirent, OpenFile},
+	open_mask,
+	open_read_only,
+	pool::Pool,
+	prime,
+	Device,
+	Fs,
+};
 
 const MAX_CACHE: Duration = Duration::MAX;
 
+/// Virtual extended attribute of the root directory, which lists the [`rufs::Capabilities`].
+const CAPS_XATTR: &str = "user.fuse-ufs.capabilities";
+
+/// Extended attributes, in which Linux expects POSIX ACLs.
+#[cfg(target_os = "linux")]
+const POSIX_ACL_ACCESS: &str = "system.posix_acl_access";
+#[cfg(target_os = "linux")]
+const POSIX_ACL_DEFAULT: &str = "system.posix_acl_default";
+
+/// The kernel enforces POSIX ACLs, which it reads from `POSIX_ACL_ACCESS`.
+/// `fuser::consts::FUSE_POSIX_ACL` requires the `abi-7-26` feature.
+#[cfg(target_os = "linux")]
+const FUSE_POSIX_ACL: u32 = 1 << 20;
+
 fn run<T>(f: impl FnOnce() -> IoResult<T>) -> Result<T, c_int> {
 	f().map_err(|e| {
-		log::error!("Error: {e}");
-		e.raw_os_error().unwrap_or(libc::EIO)
+		// Denied accesses are expected, with `-o check_permissions`.
+		if e.kind() != ErrorKind::PermissionDenied {
+			log::error!("Error: {e}");
+		}
+		rufs::errno(&e)
 	})
 }
 
+/// Encode an ACL like Linux' POSIX ACL extended attributes (`struct posix_acl_xattr_header`).
+#[cfg(target_os = "linux")]
+fn linux_acl(acl: &Acl) -> Vec<u8> {
+	const VERSION: u32 = 2;
+	const UNDEFINED_ID: u32 = u32::MAX;
+
+	let mut data = VERSION.to_le_bytes().to_vec();
+	for e in &acl.entries {
+		let (tag, id): (u16, u32) = match e.tag {
+			AclTag::UserObj => (0x01, UNDEFINED_ID),
+			AclTag::User(uid) => (0x02, uid),
+			AclTag::GroupObj => (0x04, UNDEFINED_ID),
+			AclTag::Group(gid) => (0x08, gid),
+			AclTag::Mask => (0x10, UNDEFINED_ID),
+			AclTag::Other => (0x20, UNDEFINED_ID),
+		};
+		data.extend_from_slice(&tag.to_le_bytes());
+		data.extend_from_slice(&e.perm.to_le_bytes());
+		data.extend_from_slice(&id.to_le_bytes());
+	}
+	data
+}
+
+/// Get the value of an extended attribute, which isn't stored as-is on disk.
+fn virtual_xattr(ufs: &Ufs<Device>, inr: InodeNum, name: &OsStr) -> IoResult<Option<Vec<u8>>> {
+	if inr == InodeNum::ROOT && name == CAPS_XATTR {
+		return Ok(Some(ufs.capabilities().to_string().into_bytes()));
+	}
+
+	#[cfg(target_os = "linux")]
+	{
+		let acl = if name == POSIX_ACL_ACCESS {
+			Some(ufs.acl(inr)?)
+		} else if name == POSIX_ACL_DEFAULT {
+			Some(ufs.default_acl(inr)?)
+		} else {
+			None
+		};
+		if let Some(acl) = acl {
+			let acl = acl.ok_or(IoError::from_raw_os_error(libc::ENODATA))?;
+			return Ok(Some(linux_acl(&acl)));
+		}
+	}
+	Ok(None)
+}
+
+/// Node ID of whiteouts, because their inode number (`WINO`) is the same as `FUSE_ROOT_ID`.
+const WHITEOUT_ID: u64 = 1 << 32;
+
 fn transino(inr: u64) -> IoResult<InodeNum> {
 	if inr == fuser::FUSE_ROOT_ID {
 		Ok(InodeNum::ROOT)
+	} else if inr == WHITEOUT_ID {
+		Ok(InodeNum::WHITEOUT)
 	} else {
 		let inr = inr
 			.try_into()
@@ -29,85 +108,234 @@ fn transino(inr: u64) -> IoResult<InodeNum> {
 	}
 }
 
+impl Fs {
+	/// Handle a request on one of the worker threads,
+	/// or on the current thread, if there are no workers.
+	fn spawn(&self, f: impl FnOnce(&Ufs<Device>) + Send + 'static) {
+		let ufs = self.ufs.get();
+		match &self.pool {
+			Some(pool) => pool.spawn(move || f(&ufs)),
+			None => f(&ufs),
+		}
+	}
+
+	/// Credentials of the process, which made `req`, if fuse-ufs checks permissions.
+	fn cred(&self, req: &Request<'_>) -> Option<Credentials> {
+		self.credentials(req.uid(), req.gid(), Some(req.pid()))
+	}
+
+	/// Reply to `open()`, after checking the permissions, and remember the flags.
+	fn open_inode(&self, req: &Request<'_>, ino: u64, flags: i32, reply: fuser::ReplyOpen) {
+		let cred = self.cred(req);
+		let files = Arc::clone(&self.files);
+		self.spawn(move |ufs| {
+			let f = || {
+				let inr = transino(ino)?;
+				if let Some(cred) = &cred {
+					ufs.access(inr, cred, open_mask(flags))?;
+				}
+				open_read_only(flags)?;
+				// TODO: write() must write to the end of the file for O_APPEND, whatever the offset.
+				Ok(files.insert(OpenFile { inr, flags }))
+			};
+			match run(f) {
+				Ok(fh) => reply.opened(fh, 0),
+				Err(e) => reply.error(e),
+			}
+		});
+	}
+
+	/// Reply to `fsync()` and `fsyncdir()`. The inode is written back even for `fdatasync(2)`,
+	/// because its size may have changed.
+	fn sync_inode(&self, ino: u64, reply: fuser::ReplyEmpty) {
+		self.spawn(move |ufs| {
+			let f = || {
+				let inr = transino(ino)?;
+				ufs.sync_inode(inr)
+			};
+			match run(f) {
+				Ok(()) => reply.ok(),
+				Err(e) => reply.error(e),
+			}
+		});
+	}
+}
+
 impl Filesystem for Fs {
-	fn init(&mut self, _req: &Request<'_>, _config: &mut KernelConfig) -> Result<(), c_int> {
+	fn init(&mut self, _req: &Request<'_>, config: &mut KernelConfig) -> Result<(), c_int> {
+		#[cfg(target_os = "linux")]
+		if config.add_capabilities(FUSE_POSIX_ACL).is_err() {
+			log::warn!("the kernel doesn't support POSIX ACLs, ignoring them");
+		}
+		#[cfg(not(target_os = "linux"))]
+		let _ = config;
+		// TODO: advertise `-o max_write` with config.set_max_write(), once there is write support.
+
+		// The worker threads must be spawned after daemonizing.
+		if self.threads > 1 {
+			log::debug!("spawning {} worker threads", self.threads);
+			self.pool = Some(Pool::new(self.threads));
+		}
+		if let Some(path) = &self.prime {
+			prime::spawn(Arc::clone(&self.ufs), path.clone());
+		}
 		Ok(())
 	}
 
-	fn destroy(&mut self) {}
+	fn destroy(&mut self) {
+		self.pool = None;
+		let ufs = self.ufs.get();
+		log::debug!("cache: {:?}", ufs.cache_stats());
+		log::debug!("dirhash: {:?}", ufs.dirhash_stats());
+		if let Some(path) = &self.prime {
+			if let Err(e) = prime::save(&ufs, path) {
+				log::warn!("failed to save the cached blocks: {e:#}");
+			}
+		}
+	}
 
 	fn getattr(&mut self, _req: &Request<'_>, ino: u64, reply: fuser::ReplyAttr) {
-		// TODO: don't use read_inode()
-		let f = || {
-			let inr = transino(ino)?;
-			let st: FileAttr = self.ufs.inode_attr(inr)?.into();
-			Ok(st)
-		};
-		match run(f) {
-			Ok(x) => reply.attr(&MAX_CACHE, &x),
-			Err(e) => reply.error(e),
-		}
+		self.spawn(move |ufs| {
+			// TODO: don't use read_inode()
+			let f = || {
+				let inr = transino(ino)?;
+				let st: FileAttr = ufs.inode_attr(inr)?.into();
+				Ok(st)
+			};
+			match run(f) {
+				Ok(x) => reply.attr(&MAX_CACHE, &x),
+				Err(e) => reply.error(e),
+			}
+		});
 	}
 
-	fn open(&mut self, _req: &Request<'_>, ino: u64, _flags: i32, reply: fuser::ReplyOpen) {
-		let _ino = transino(ino);
-		reply.opened(0, 0);
+	fn open(&mut self, req: &Request<'_>, ino: u64, flags: i32, reply: fuser::ReplyOpen) {
+		self.open_inode(req, ino, flags, reply);
+	}
+
+	fn opendir(&mut self, req: &Request<'_>, ino: u64, flags: i32, reply: fuser::ReplyOpen) {
+		let cred = self.cred(req);
+		let dirs = Arc::clone(&self.dirs);
+		self.spawn(move |ufs| {
+			let f = || {
+				let inr = transino(ino)?;
+				if let Some(cred) = &cred {
+					ufs.access(inr, cred, open_mask(flags))?;
+				}
+				let mut entries = Vec::new();
+				ufs.dir_iter(inr, |name, inr, kind| {
+					entries.push(Dirent {
+						name: name.to_owned(),
+						inr,
+						kind,
+					});
+					None::<()>
+				})?;
+				Ok(dirs.insert(entries.into()))
+			};
+			match run(f) {
+				Ok(fh) => reply.opened(fh, 0),
+				Err(e) => reply.error(e),
+			}
+		});
+	}
+
+	fn releasedir(
+		&mut self,
+		_req: &Request<'_>,
+		_ino: u64,
+		fh: u64,
+		_flags: i32,
+		reply: fuser::ReplyEmpty,
+	) {
+		self.dirs.remove(fh);
+		reply.ok();
 	}
 
-	fn opendir(&mut self, _req: &Request<'_>, ino: u64, _flags: i32, reply: fuser::ReplyOpen) {
-		let _ino = transino(ino);
-		reply.opened(0, 0);
+	/// Only called, if the kernel doesn't check permissions itself.
+	fn access(&mut self, req: &Request<'_>, ino: u64, mask: i32, reply: fuser::ReplyEmpty) {
+		let Some(cred) = self.cred(req) else {
+			reply.ok();
+			return;
+		};
+		self.spawn(move |ufs| {
+			let f = || {
+				let inr = transino(ino)?;
+				ufs.access(inr, &cred, mask)
+			};
+			match run(f) {
+				Ok(()) => reply.ok(),
+				Err(e) => reply.error(e),
+			}
+		});
 	}
 
-	// TODO: use offset in a less stupid way
 	fn readdir(
 		&mut self,
 		_req: &Request<'_>,
-		inr: u64,
-		_fh: u64,
+		_inr: u64,
+		fh: u64,
 		offset: i64,
 		mut reply: fuser::ReplyDirectory,
 	) {
-		let f = || {
-			let inr = transino(inr)?;
-			if offset != 0 {
-				return Ok(());
-			}
-
-			let mut i = 0;
-
-			self.ufs.dir_iter(inr, |name, inr, kind| {
-				i += 1;
-				if i > offset && reply.add(inr.get64(), i, kind.into(), name) {
-					return Some(());
-				}
-				None
-			})?;
-
-			Ok(())
+		let Some(entries) = self.dirs.get(fh) else {
+			log::error!("readdir(): unknown directory handle {fh}");
+			reply.error(libc::EBADF);
+			return;
 		};
-		match run(f) {
-			Ok(_) => reply.ok(),
-			Err(e) => reply.error(e),
+		// The offset of an entry is the offset of the next one.
+		let skip = usize::try_from(offset).unwrap_or(usize::MAX);
+		for (i, e) in entries.iter().enumerate().skip(skip) {
+			if reply.add(e.inr.get64(), i as i64 + 1, e.kind.into(), &e.name) {
+				break;
+			}
 		}
+		reply.ok();
 	}
 
-	fn lookup(&mut self, _req: &Request<'_>, pinr: u64, name: &OsStr, reply: fuser::ReplyEntry) {
-		let mut f = || {
-			let pinr = transino(pinr)?;
-			let inr = self.ufs.dir_lookup(pinr, name)?;
-			let st = self.ufs.inode_attr(inr)?;
-			Ok::<_, IoError>((st.gen, st.into()))
-		};
+	fn lookup(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: fuser::ReplyEntry) {
+		let name = name.to_owned();
+		let cred = self.cred(req);
+		let known = self.known.clone();
+		self.spawn(move |ufs| {
+			let f = || {
+				let pinr = transino(parent)?;
+				if let Some(cred) = &cred {
+					ufs.access(pinr, cred, libc::X_OK)?;
+				}
+				let inr = ufs.dir_lookup(pinr, &name)?;
+				let st = ufs.inode_attr(inr)?;
+				let gen = st.gen;
+				let mut st: FileAttr = st.into();
+				// The kernel knows the root directory, which ".." may refer to, by FUSE_ROOT_ID.
+				if inr == InodeNum::ROOT {
+					st.ino = fuser::FUSE_ROOT_ID;
+				} else if inr == InodeNum::WHITEOUT {
+					st.ino = WHITEOUT_ID;
+				}
+				Ok::<_, IoError>((gen, st))
+			};
 
-		match f() {
-			Ok((gen, st)) => reply.entry(&Duration::ZERO, &st, gen.into()),
-			Err(e) => {
-				if e.kind() != ErrorKind::NotFound {
-					log::error!("Error: {e}");
+			match f() {
+				Ok((gen, st)) => {
+					if let Some(known) = &known {
+						known.lookup(parent, &name, st.ino);
+					}
+					reply.entry(&Duration::ZERO, &st, gen.into())
+				}
+				Err(e) => {
+					if !matches!(e.kind(), ErrorKind::NotFound | ErrorKind::PermissionDenied) {
+						log::error!("Error: {e}");
+					}
+					reply.error(rufs::errno(&e))
 				}
-				reply.error(e.raw_os_error().unwrap_or(libc::EIO))
 			}
+		});
+	}
+
+	fn forget(&mut self, _req: &Request<'_>, ino: u64, nlookup: u64) {
+		if let Some(known) = &self.known {
+			known.forget(ino, nlookup);
 		}
 	}
 
@@ -115,50 +343,183 @@ impl Filesystem for Fs {
 		&mut self,
 		_req: &Request<'_>,
 		inr: u64,
-		_fh: u64,
+		fh: u64,
 		offset: i64,
 		size: u32,
 		_flags: i32,
 		_lock_owner: Option<u64>,
 		reply: fuser::ReplyData,
 	) {
-		let f = || {
-			let inr = transino(inr)?;
-			let mut buffer = vec![0u8; size as usize];
-			let n = self.ufs.inode_read(inr, offset as u64, &mut buffer)?;
-			buffer.shrink_to(n);
-			Ok(buffer)
+		let Some(file) = self.files.get(fh) else {
+			reply.error(libc::EBADF);
+			return;
 		};
+		self.spawn(move |ufs| {
+			let f = || {
+				let inr = transino(inr)?;
+				if !file.readable() || file.inr != inr {
+					return Err(IoError::from_raw_os_error(libc::EBADF));
+				}
+				let mut buffer = vec![0u8; size as usize];
+				let n = ufs.inode_read(inr, offset as u64, &mut buffer)?;
+				buffer.truncate(n);
+				Ok(buffer)
+			};
+
+			match run(f) {
+				Ok(buf) => reply.data(&buf),
+				Err(e) => reply.error(e),
+			}
+		});
+	}
+
+	fn release(
+		&mut self,
+		_req: &Request<'_>,
+		_ino: u64,
+		fh: u64,
+		_flags: i32,
+		_lock_owner: Option<u64>,
+		_flush: bool,
+		reply: fuser::ReplyEmpty,
+	) {
+		self.files.remove(fh);
+		reply.ok();
+	}
+
+	/// Nothing is buffered per file handle, and `close(2)` doesn't promise durability,
+	/// so only `fsync(2)` writes modifications back.
+	fn flush(
+		&mut self,
+		_req: &Request<'_>,
+		_ino: u64,
+		_fh: u64,
+		_lock_owner: u64,
+		reply: fuser::ReplyEmpty,
+	) {
+		reply.ok();
+	}
+
+	fn fsync(
+		&mut self,
+		_req: &Request<'_>,
+		ino: u64,
+		_fh: u64,
+		_datasync: bool,
+		reply: fuser::ReplyEmpty,
+	) {
+		self.sync_inode(ino, reply);
+	}
+
+	fn fsyncdir(
+		&mut self,
+		_req: &Request<'_>,
+		ino: u64,
+		_fh: u64,
+		_datasync: bool,
+		reply: fuser::ReplyEmpty,
+	) {
+		self.sync_inode(ino, reply);
+	}
 
-		match run(f) {
-			Ok(buf) => reply.data(&buf),
-			Err(e) => reply.error(e),
+	fn lseek(
+		&mut self,
+		_req: &Request<'_>,
+		inr: u64,
+		_fh: u64,
+		offset: i64,
+		whence: i32,
+		reply: fuser::ReplyLseek,
+	) {
+		self.spawn(move |ufs| {
+			let f = || {
+				let inr = transino(inr)?;
+				let whence = match whence {
+					libc::SEEK_DATA => Whence::Data,
+					libc::SEEK_HOLE => Whence::Hole,
+					_ => return Err(IoError::from_raw_os_error(libc::EINVAL)),
+				};
+				let offset = offset
+					.try_into()
+					.map_err(|_| IoError::from_raw_os_error(libc::EINVAL))?;
+				let pos = ufs.inode_seek(inr, offset, whence)?;
+				Ok(pos as i64)
+			};
+
+			match run(f) {
+				Ok(pos) => reply.offset(pos),
+				Err(e) => reply.error(e),
+			}
+		});
+	}
+
+	#[cfg(target_os = "linux")]
+	fn ioctl(
+		&mut self,
+		req: &Request<'_>,
+		inr: u64,
+		_fh: u64,
+		_flags: u32,
+		cmd: u32,
+		in_data: &[u8],
+		out_size: u32,
+		reply: fuser::ReplyIoctl,
+	) {
+		if !self.ioctls.contains(cmd) {
+			reply.error(libc::ENOTTY);
+			return;
 		}
+
+		// Note: Linux currently answers FS_IOC_FIEMAP on FUSE filesystems itself,
+		// with EOPNOTSUPP, so it is only handled, if the kernel forwards it.
+		let cred = self
+			.credentials(req.uid(), req.gid(), Some(req.pid()))
+			.unwrap_or_else(|| Credentials::new(req.uid(), req.gid()));
+		let ioctls = Arc::clone(&self.ioctls);
+		let in_data = in_data.to_vec();
+		self.spawn(move |ufs| {
+			let f = || {
+				let req = IoctlRequest {
+					inr: transino(inr)?,
+					cmd,
+					cred: &cred,
+					input: &in_data,
+					out_size,
+				};
+				ioctls.call(ufs, &req)
+			};
+			match run(f) {
+				Ok(data) => reply.ioctl(0, &data),
+				Err(e) => reply.error(e),
+			}
+		});
 	}
 
 	fn statfs(&mut self, _req: &Request<'_>, _ino: u64, reply: fuser::ReplyStatfs) {
-		let info = self.ufs.info();
+		let info = self.ufs.get().info();
 		reply.statfs(
 			info.blocks,
 			info.bfree,
-			info.bfree,
+			info.bavail,
 			info.files,
 			info.ffree,
-			info.bsize,
-			255,
-			info.fsize,
+			info.iosize,
+			info.namemax,
+			info.frsize,
 		)
 	}
 
 	fn readlink(&mut self, _req: &Request<'_>, inr: u64, reply: fuser::ReplyData) {
-		let f = || {
-			let inr = transino(inr)?;
-			self.ufs.symlink_read(inr)
-		};
-		match run(f) {
-			Ok(x) => reply.data(&x),
-			Err(e) => reply.error(e),
-		}
+		self.spawn(move |ufs| {
+			let f = || {
+				let inr = transino(inr)?;
+				ufs.symlink_read(inr)
+			};
+			match run(f) {
+				Ok(x) => reply.data(&x),
+				Err(e) => reply.error(e),
+			}
+		});
 	}
 
 	fn listxattr(&mut self, _req: &Request<'_>, inr: u64, size: u32, reply: fuser::ReplyXattr) {
@@ -167,22 +528,32 @@ impl Filesystem for Fs {
 			Data(Vec<u8>),
 		}
 
-		let f = || {
-			let inr = transino(inr)?;
-			if size == 0 {
-				let len = self.ufs.xattr_list_len(inr)?;
-				Ok(R::Len(len))
-			} else {
-				let data = self.ufs.xattr_list(inr)?;
-				Ok(R::Data(data))
-			}
-		};
+		self.spawn(move |ufs| {
+			let f = || {
+				let inr = transino(inr)?;
+				let root = inr == InodeNum::ROOT;
+				if size == 0 {
+					let mut len = ufs.xattr_list_len(inr)?;
+					if root {
+						len += CAPS_XATTR.len() as u32 + 1;
+					}
+					Ok(R::Len(len))
+				} else {
+					let mut data = ufs.xattr_list(inr)?;
+					if root {
+						data.extend_from_slice(CAPS_XATTR.as_bytes());
+						data.push(b'\0');
+					}
+					Ok(R::Data(data))
+				}
+			};
 
-		match run(f) {
-			Ok(R::Data(data)) => reply.data(&data),
-			Ok(R::Len(len)) => reply.size(len),
-			Err(e) => reply.error(e),
-		}
+			match run(f) {
+				Ok(R::Data(data)) => reply.data(&data),
+				Ok(R::Len(len)) => reply.size(len),
+				Err(e) => reply.error(e),
+			}
+		});
 	}
 
 	fn getxattr(
@@ -199,26 +570,36 @@ impl Filesystem for Fs {
 			Len(u32),
 		}
 
-		let f = || {
-			let inr = transino(inr)?;
-			if size == 0 {
-				let len = self.ufs.xattr_len(inr, name)?;
-				Ok(R::Len(len))
-			} else {
-				let data = self.ufs.xattr_read(inr, name)?;
-				if (size as usize) >= data.len() {
-					Ok(R::Data(data))
+		let name = name.to_owned();
+		self.spawn(move |ufs| {
+			let f = || {
+				let inr = transino(inr)?;
+				let virt = virtual_xattr(ufs, inr, &name)?;
+				if size == 0 {
+					let len = match &virt {
+						Some(data) => data.len() as u32,
+						None => ufs.xattr_len(inr, &name)?,
+					};
+					Ok(R::Len(len))
 				} else {
-					Ok(R::TooShort)
+					let data = match virt {
+						Some(data) => data,
+						None => ufs.xattr_read(inr, &name)?,
+					};
+					if (size as usize) >= data.len() {
+						Ok(R::Data(data))
+					} else {
+						Ok(R::TooShort)
+					}
 				}
-			}
-		};
+			};
 
-		match run(f) {
-			Ok(R::Data(x)) => reply.data(&x),
-			Ok(R::TooShort) => reply.error(libc::ERANGE),
-			Ok(R::Len(l)) => reply.size(l),
-			Err(e) => reply.error(e),
-		}
+			match run(f) {
+				Ok(R::Data(x)) => reply.data(&x),
+				Ok(R::TooShort) => reply.error(libc::ERANGE),
+				Ok(R::Len(l)) => reply.size(l),
+				Err(e) => reply.error(e),
+			}
+		});
 	}
 }
@@ -0,0 +1,81 @@
+use std::{
+	collections::HashMap,
+	ffi::OsString,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+		Mutex,
+		MutexGuard,
+		PoisonError,
+	},
+};
+
+use rufs::{InodeNum, InodeType};
+
+/// An entry of a directory, as it was, when the directory was opened.
+pub struct Dirent {
+	pub name: OsString,
+	pub inr:  InodeNum,
+	pub kind: InodeType,
+}
+
+/// A file, as it was opened by `open()`.
+#[derive(Clone, Copy)]
+pub struct OpenFile {
+	pub inr:   InodeNum,
+	/// The flags of `open(2)`.
+	pub flags: i32,
+}
+
+impl OpenFile {
+	/// Whether the file was opened for reading.
+	pub fn readable(&self) -> bool {
+		self.flags & libc::O_ACCMODE != libc::O_WRONLY
+	}
+}
+
+/// The state of open files or directories, by file handle.
+pub struct Handles<T> {
+	next: AtomicU64,
+	open: Mutex<HashMap<u64, T>>,
+}
+
+/// Snapshots of the entries of open directories.
+///
+/// `readdir()` is served from the snapshot, which is taken by `opendir()`,
+/// so that entries are neither skipped nor repeated, if the directory changes in between,
+/// and the offset of an entry is simply its index.
+pub type DirHandles = Handles<Arc<[Dirent]>>;
+
+/// The flags of open files, which `read()` checks against.
+pub type FileHandles = Handles<OpenFile>;
+
+impl<T: Clone> Handles<T> {
+	pub fn new() -> Self {
+		Self {
+			// 0 is never handed out, so that it can't be confused with "no handle".
+			next: AtomicU64::new(1),
+			open: Mutex::new(HashMap::new()),
+		}
+	}
+
+	/// Remember `state`, and return the new file handle.
+	pub fn insert(&self, state: T) -> u64 {
+		let fh = self.next.fetch_add(1, Ordering::Relaxed);
+		self.lock().insert(fh, state);
+		fh
+	}
+
+	pub fn get(&self, fh: u64) -> Option<T> {
+		self.lock().get(&fh).cloned()
+	}
+
+	pub fn remove(&self, fh: u64) {
+		self.lock().remove(&fh);
+	}
+
+	fn lock(&self) -> MutexGuard<'_, HashMap<u64, T>> {
+		// Handles are only inserted and removed as a whole, so a poisoned lock is harmless.
+		self.open.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+}
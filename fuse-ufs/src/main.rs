@@ -1,13 +1,37 @@
-use std::fs::File;
+use std::{
+	io::ErrorKind,
+	num::NonZeroUsize,
+	path::{Path, PathBuf},
+	process::ExitCode,
+	sync::{Arc, PoisonError, RwLock},
+	time::Duration,
+};
 
-use anyhow::Result;
+use anyhow::{ensure, Context, Result};
 use cfg_if::cfg_if;
 use clap::Parser;
-use rufs::Ufs;
+#[cfg(all(feature = "fuse3", target_os = "linux"))]
+use rufs::Ioctls;
+use rufs::{Backend, BlockCache, BlockFile, Credentials, Ufs, WindowedBackend};
 
-use crate::cli::Cli;
+use crate::{
+	cli::{Cli, Command, Permissions},
+	exit::Status,
+};
 
+mod bootblock;
+mod changed;
 mod cli;
+mod dd;
+mod exit;
+mod find;
+mod prime;
+
+#[cfg(feature = "mkfs")]
+mod mkfs;
+
+#[cfg(feature = "trim")]
+mod trim;
 
 #[cfg(feature = "fuse3")]
 mod fuse3;
@@ -15,34 +39,338 @@ mod fuse3;
 #[cfg(feature = "fuse2")]
 mod fuse2;
 
+#[cfg(feature = "fuse3")]
+mod handles;
+
+#[cfg(feature = "fuse3")]
+mod pool;
+
+#[cfg(feature = "fuse3")]
+mod supervise;
+
+#[cfg(feature = "fuse2")]
+mod unmount;
+
+#[cfg(feature = "fuse3")]
+mod watch;
+
+/// The device, or the partition of it, which holds the filesystem.
+type Device = BlockCache<WindowedBackend<Box<dyn Backend + Send + Sync>>>;
+
+/// uid and gid of "nobody", which root is treated like with `-o root_squash`.
+const NOBODY: u32 = 65534;
+
+/// The filesystem, which requests are served from.
+///
+/// With `--watch`, it is replaced, once the device was modified by someone else.
+struct Current(RwLock<Arc<Ufs<Device>>>);
+
+impl Current {
+	fn new(ufs: Ufs<Device>) -> Self {
+		Self(RwLock::new(Arc::new(ufs)))
+	}
+
+	fn get(&self) -> Arc<Ufs<Device>> {
+		Arc::clone(&self.0.read().unwrap_or_else(PoisonError::into_inner))
+	}
+
+	#[cfg(feature = "fuse3")]
+	fn replace(&self, ufs: Ufs<Device>) {
+		*self.0.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(ufs);
+	}
+}
+
 struct Fs {
-	ufs: Ufs<File>,
+	ufs:     Arc<Current>,
+	/// `None`, if the kernel checks permissions.
+	perms:   Option<Permissions>,
+	#[cfg(feature = "fuse3")]
+	threads: usize,
+	#[cfg(feature = "fuse3")]
+	pool:    Option<pool::Pool>,
+	/// Snapshots of the open directories.
+	#[cfg(feature = "fuse3")]
+	dirs:    Arc<handles::DirHandles>,
+	/// Flags of the open files.
+	#[cfg(feature = "fuse3")]
+	files:   Arc<handles::FileHandles>,
+	/// What the kernel may have cached, with `--watch`.
+	#[cfg(feature = "fuse3")]
+	known:   Option<Arc<watch::Known>>,
+	/// The `ioctl(2)` commands, which are passed through.
+	#[cfg(all(feature = "fuse3", target_os = "linux"))]
+	ioctls:  Arc<Ioctls<Device>>,
+	/// Where the cached blocks are saved at unmount, and loaded from at mount (`-o prime`).
+	prime:   Option<PathBuf>,
+}
+
+impl Fs {
+	/// Credentials to check the permissions of a request with,
+	/// or `None`, if the kernel checks permissions.
+	fn credentials(&self, uid: u32, gid: u32, pid: Option<u32>) -> Option<Credentials> {
+		let perms = self.perms?;
+		if uid == 0 && perms.root_squash {
+			return Some(Credentials::new(NOBODY, NOBODY));
+		}
+		let mut cred = Credentials::new(uid, gid);
+		cred.groups = pid.map(groups).unwrap_or_default();
+		Some(cred)
+	}
+}
+
+/// Permissions, which `open(2)` with `flags` requires, as `access(2)` mask.
+fn open_mask(flags: i32) -> i32 {
+	match flags & libc::O_ACCMODE {
+		libc::O_WRONLY => libc::W_OK,
+		libc::O_RDWR => libc::R_OK | libc::W_OK,
+		_ => libc::R_OK,
+	}
+}
+
+/// Check that `open(2)` with `flags` doesn't modify the file.
+fn open_read_only(flags: i32) -> std::io::Result<()> {
+	// TODO: O_TRUNC using inode_truncate(), once there is write support.
+	if flags & libc::O_ACCMODE != libc::O_RDONLY || flags & libc::O_TRUNC != 0 {
+		return Err(std::io::Error::from_raw_os_error(libc::EROFS));
+	}
+	Ok(())
+}
+
+/// Supplementary groups of process `pid`, which FUSE doesn't pass along with requests.
+#[cfg(target_os = "linux")]
+fn groups(pid: u32) -> Vec<u32> {
+	let Ok(status) = std::fs::read_to_string(format!("/proc/{pid}/status")) else {
+		return Vec::new();
+	};
+	status
+		.lines()
+		.find_map(|l| l.strip_prefix("Groups:"))
+		.map(|g| {
+			g.split_whitespace()
+				.filter_map(|g| g.parse().ok())
+				.collect()
+		})
+		.unwrap_or_default()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn groups(_pid: u32) -> Vec<u32> {
+	Vec::new()
 }
 
-fn main() -> Result<()> {
+/// Decompress the device, if it is a compressed image.
+fn decompress<B: Backend + Send + Sync + 'static>(
+	file: B,
+) -> Result<Box<dyn Backend + Send + Sync>> {
+	#[cfg(feature = "zstd")]
+	if rufs::ZstdBackend::detect(&file)? {
+		log::info!("decompressing a zstd-compressed image");
+		return Ok(Box::new(rufs::ZstdBackend::new(file)?));
+	}
+	Ok(Box::new(file))
+}
+
+/// Take an advisory lock on the device, so that another instance of fuse-ufs doesn't modify it,
+/// while it is in use. `nolock` is the option, which skips this.
+fn lock(file: &BlockFile, path: &Path, exclusive: bool, nolock: &str) -> Result<()> {
+	match file.lock(exclusive) {
+		Ok(()) => Ok(()),
+		Err(e) if e.kind() == ErrorKind::WouldBlock => {
+			Err(e).with_context(|| format!("{} is in use by another process", path.display()))
+		}
+		Err(e) => {
+			Err(e).with_context(|| {
+				format!(
+					"failed to lock {}, use {nolock}, if its filesystem doesn't support locking",
+					path.display()
+				)
+			})
+		}
+	}
+}
+
+/// Open the device, and select the part of it, which holds the filesystem
+/// (`-o part=NAME` or `-o offset=BYTES,size=BYTES`).
+fn open(cli: &Cli, device: &Path) -> Result<Device> {
+	let file = if cli.fs_flag("direct") {
+		BlockFile::open_direct(device)
+	} else {
+		BlockFile::open(device)
+	}
+	.with_context(|| format!("failed to open {}", device.display()))?;
+	// TODO: take an exclusive lock, once the filesystem can be mounted read-write.
+	if !cli.fs_flag("nolock") {
+		lock(&file, device, false, "-o nolock")?;
+	}
+	#[cfg(all(feature = "io-uring", target_os = "linux"))]
+	let file = if cli.fs_flag("uring") {
+		ensure!(!file.is_direct(), "-o uring can't be used with -o direct");
+		// The duplicated descriptor shares the lock.
+		let uring = rufs::UringBackend::new(file.get_ref().try_clone()?)
+			.context("failed to set up io_uring")?;
+		decompress(uring)
+	} else {
+		decompress(file)
+	};
+	#[cfg(not(all(feature = "io-uring", target_os = "linux")))]
+	let file = {
+		ensure!(
+			!cli.fs_flag("uring"),
+			"-o uring requires Linux, and the io-uring feature"
+		);
+		decompress(file)
+	};
+	let file = file.with_context(|| format!("failed to decompress {}", device.display()))?;
+	let window = if let Some((offset, size)) = cli.window()? {
+		WindowedBackend::new(file, offset, size)
+	} else if let Some(spec) = cli.fs_option("part") {
+		let part = rufs::partitions(&file)?
+			.into_iter()
+			.find(|p| p.matches(spec))
+			.with_context(|| format!("no such partition: {spec}"))?;
+		WindowedBackend::new(file, part.start, Some(part.size))
+	} else {
+		match rufs::find_ufs_partition(&file)? {
+			Some(part) => WindowedBackend::new(file, part.start, Some(part.size)),
+			None => WindowedBackend::whole(file),
+		}
+	};
+	Ok(BlockCache::new(window, cli.cache_size()?))
+}
+
+/// Check the filesystem, and print all inconsistencies.
+fn check<B: Backend + Sync>(ufs: &Ufs<B>) -> Result<()> {
+	let threads = std::thread::available_parallelism().unwrap_or(NonZeroUsize::MIN);
+	let findings = ufs.check_deep_parallel(threads)?;
+	for f in &findings {
+		println!("{f}");
+	}
+	if !findings.is_empty() {
+		return Err(Status::Inconsistent)
+			.with_context(|| format!("{} inconsistencies found", findings.len()));
+	}
+	Ok(())
+}
+
+fn main() -> ExitCode {
+	match run() {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(e) => {
+			eprintln!("Error: {e:?}");
+			Status::of(&e).into()
+		}
+	}
+}
+
+fn run() -> Result<()> {
 	let cli = Cli::parse();
 
 	env_logger::builder()
 		.filter_level(cli.verbose.log_level_filter())
 		.init();
 
+	if let Some(cmd) = &cli.command {
+		return match cmd {
+			Command::Bootblock(args) => bootblock::bootblock(args),
+			Command::ChangedSince(args) => changed::changed_since(args),
+			Command::Dd(args) => dd::dd(args),
+			Command::Find(args) => find::find(args),
+			#[cfg(feature = "mkfs")]
+			Command::Mkfs(args) => mkfs::mkfs(args),
+			#[cfg(feature = "trim")]
+			Command::Trim(args) => trim::trim(args),
+		};
+	}
+	let Some(device) = &cli.device else {
+		unreachable!("clap requires a device");
+	};
+	#[cfg(feature = "trim")]
+	if cli.trim {
+		let args = cli::TrimArgs {
+			force:   cli.fs_flag("force"),
+			no_lock: false,
+			image:   device.clone(),
+		};
+		return trim::trim(&args);
+	}
+	// The watchdog is forked, before the device is opened, so that it doesn't keep it open.
+	#[cfg(feature = "fuse2")]
+	if let (Some(mp), false) = (&cli.mountpoint, cli.check) {
+		if cli.options.iter().any(|opt| opt == "auto_unmount") {
+			unmount::spawn(mp)?;
+		}
+	}
+
+	let ufs = Ufs::with_options(open(&cli, device)?, cli.ufs_options()?)?;
+	if let Some(sector) = ufs.stats().alternate_superblock {
+		log::warn!("using the backup superblock at sector {sector}, the filesystem is read-only");
+	}
+	let vol = ufs.volume_info();
+	log::info!(
+		"Volume {:?}, last mounted on {:?}",
+		vol.volname,
+		vol.last_mountpoint
+	);
+	if cli.check {
+		return check(&ufs);
+	}
+	let Some(mp) = &cli.mountpoint else {
+		unreachable!("clap requires a mount point");
+	};
+	log::info!("Capabilities: {}", ufs.capabilities());
+	// TODO: a new session would have to be watched after each crash.
+	#[cfg(feature = "fuse3")]
+	ensure!(
+		cli.watch.is_none() || !cli.fs_flag("supervise"),
+		"--watch can't be used with -o supervise"
+	);
+
 	let fs = Fs {
-		ufs: Ufs::open(&cli.device)?,
+		ufs: Arc::new(Current::new(ufs)),
+		perms: cli.permissions(),
+		#[cfg(feature = "fuse3")]
+		threads: cli.threads()?,
+		#[cfg(feature = "fuse3")]
+		pool: None,
+		#[cfg(feature = "fuse3")]
+		dirs: Arc::new(handles::DirHandles::new()),
+		#[cfg(feature = "fuse3")]
+		files: Arc::new(handles::FileHandles::new()),
+		#[cfg(feature = "fuse3")]
+		known: cli.watch.map(|_| Arc::default()),
+		// The device is opened read-only, so FITRIM can't be offered.
+		#[cfg(all(feature = "fuse3", target_os = "linux"))]
+		ioctls: Arc::new(Ioctls::linux()),
+		prime: cli.prime(device),
 	};
 
-	let mp = &cli.mountpoint;
 	cfg_if! {
 		if #[cfg(all(feature = "fuse3", feature = "fuse2"))] {
 			compile_error!("more than one FUSE backend selected")
 		} else if #[cfg(feature = "fuse3")] {
 			let opts = cli.options();
-			if cli.foreground {
-				fuser::mount2(fs, mp, &opts)?;
-			} else {
+			if !cli.foreground {
 				daemonize::Daemonize::new()
 					.working_directory(std::env::current_dir()?)
 					.start()?;
-				fuser::mount2(fs, mp, &opts)?;
+			}
+			if cli.fs_flag("supervise") {
+				supervise::run(fs, mp, &opts)?;
+			} else {
+				let current = Arc::clone(&fs.ufs);
+				let known = fs.known.clone();
+				let mut session = fuser::Session::new(fs, mp, &opts)?;
+				if let (Some(secs), Some(known)) = (cli.watch, known) {
+					watch::spawn(
+						device.clone(),
+						Duration::from_secs(secs),
+						move |path| Ok(Ufs::with_options(open(&cli, path)?, cli.ufs_options()?)?),
+						current,
+						known,
+						session.notifier(),
+					)?;
+				}
+				session.run()?;
 			}
 		} else if #[cfg(feature = "fuse2")] {
 			fuse2rs::mount(mp, fs, cli.options()?)?;
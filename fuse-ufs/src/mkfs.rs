@@ -0,0 +1,36 @@
+use std::{
+	fs::File,
+	io::{Seek, SeekFrom},
+};
+
+use anyhow::{Context, Result};
+use rufs::{BlockCache, BlockFile};
+
+use crate::cli::{self, MkfsArgs};
+
+/// Create a new filesystem on a device, or an image file.
+pub fn mkfs(args: &MkfsArgs) -> Result<()> {
+	let path = &args.device;
+	let file = File::options()
+		.read(true)
+		.write(true)
+		.create(args.size.is_some())
+		.truncate(false)
+		.open(path)
+		.with_context(|| format!("failed to open {}", path.display()))?;
+	let file = BlockFile::from_file(file)?;
+	if !args.no_lock {
+		crate::lock(&file, path, true, "--no-lock")?;
+	}
+
+	// The size of block devices isn't reported by stat(2).
+	let len = file.get_ref().seek(SeekFrom::End(0))?;
+	let size = args.size.unwrap_or(len);
+	if size > len && file.get_ref().metadata()?.is_file() {
+		file.get_ref().set_len(size)?;
+	}
+
+	let backend = BlockCache::new(file, cli::DEFAULT_CACHE_SIZE);
+	rufs::mkfs(&backend, &args.mkfs_options(size))
+		.with_context(|| format!("failed to create a filesystem on {}", path.display()))
+}
@@ -0,0 +1,71 @@
+use std::{
+	panic::{self, AssertUnwindSafe},
+	sync::{
+		mpsc::{self, Sender},
+		Arc,
+		Mutex,
+	},
+	thread::{self, JoinHandle},
+};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A minimal thread pool, used for handling FUSE requests concurrently.
+pub struct Pool {
+	tx:      Option<Sender<Job>>,
+	workers: Vec<JoinHandle<()>>,
+}
+
+impl Pool {
+	/// Spawn `n` worker threads.
+	pub fn new(n: usize) -> Self {
+		let (tx, rx) = mpsc::channel::<Job>();
+		let rx = Arc::new(Mutex::new(rx));
+
+		let workers = (0..n)
+			.map(|i| {
+				let rx = Arc::clone(&rx);
+				thread::Builder::new()
+					.name(format!("fuse-ufs-worker-{i}"))
+					.spawn(move || {
+						loop {
+							let job = rx.lock().unwrap().recv();
+							let Ok(job) = job else {
+								break;
+							};
+
+							// The reply is dropped while unwinding, which answers the request with EIO.
+							if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+								log::error!("worker {i}: request handler panicked");
+							}
+						}
+					})
+					.expect("failed to spawn worker thread")
+			})
+			.collect();
+
+		Self {
+			tx: Some(tx),
+			workers,
+		}
+	}
+
+	/// Execute `f` on one of the worker threads.
+	pub fn spawn(&self, f: impl FnOnce() + Send + 'static) {
+		self.tx
+			.as_ref()
+			.unwrap()
+			.send(Box::new(f))
+			.expect("all worker threads died");
+	}
+}
+
+impl Drop for Pool {
+	fn drop(&mut self) {
+		// closing the channel stops the workers
+		drop(self.tx.take());
+		for w in self.workers.drain(..) {
+			let _ = w.join();
+		}
+	}
+}
@@ -0,0 +1,94 @@
+//! Warming up the block cache with the blocks, that were cached, when the filesystem
+//! was unmounted the last time (`-o prime`).
+use std::{
+	fmt::Write as _,
+	fs,
+	io::ErrorKind,
+	path::{Path, PathBuf},
+	sync::Arc,
+	thread,
+};
+
+use anyhow::{bail, Context, Result};
+use rufs::Ufs;
+
+use crate::{Current, Device};
+
+/// First line of priming files, which changes, if the format changes.
+const MAGIC: &str = "fuse-ufs prime 1";
+
+/// The priming file of `device`, if none was given with `-o prime=FILE`.
+pub fn sidecar(device: &Path) -> PathBuf {
+	let mut path = device.as_os_str().to_owned();
+	path.push(".prime");
+	path.into()
+}
+
+/// The line identifying the filesystem, so that other filesystems aren't primed with its blocks.
+fn id_line(ufs: &Ufs<Device>) -> String {
+	let [a, b] = ufs.volume_info().id;
+	format!("id {a:08x} {b:08x}")
+}
+
+/// Save the offsets of the cached blocks to `path`, the least recently used one first.
+pub fn save(ufs: &Ufs<Device>, path: &Path) -> Result<()> {
+	let blocks = ufs.cache().cached_blocks(usize::MAX);
+	let mut data = format!("{MAGIC}\n{}\n", id_line(ufs));
+	for pos in &blocks {
+		writeln!(data, "{pos}").unwrap();
+	}
+	// Don't leave a truncated file behind, if fuse-ufs is killed.
+	let mut tmp = path.as_os_str().to_owned();
+	tmp.push(".tmp");
+	let tmp = PathBuf::from(tmp);
+	fs::write(&tmp, data).with_context(|| format!("failed to write {}", tmp.display()))?;
+	fs::rename(&tmp, path).with_context(|| format!("failed to rename {}", tmp.display()))?;
+	log::info!("saved {} cached blocks to {}", blocks.len(), path.display());
+	Ok(())
+}
+
+/// Load the offsets of the blocks, which were saved for this filesystem.
+///
+/// Returns nothing, if there is no priming file yet, or it belongs to another filesystem.
+fn load(ufs: &Ufs<Device>, path: &Path) -> Result<Vec<u64>> {
+	let data = match fs::read_to_string(path) {
+		Ok(data) => data,
+		Err(e) if e.kind() == ErrorKind::NotFound => {
+			log::debug!("{} doesn't exist yet", path.display());
+			return Ok(Vec::new());
+		}
+		Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+	};
+	let mut lines = data.lines();
+	if lines.next() != Some(MAGIC) {
+		bail!("{} is not a priming file", path.display());
+	}
+	if lines.next() != Some(&id_line(ufs)) {
+		log::info!(
+			"{} belongs to another filesystem, ignoring it",
+			path.display()
+		);
+		return Ok(Vec::new());
+	}
+	lines
+		.map(|line| {
+			line.parse()
+				.with_context(|| format!("{}: invalid offset: {line}", path.display()))
+		})
+		.collect()
+}
+
+/// Prime the cache of the current filesystem with the blocks saved in `path`, in the background.
+pub fn spawn(current: Arc<Current>, path: PathBuf) {
+	let res = thread::Builder::new().name("prime".into()).spawn(move || {
+		let ufs = current.get();
+		let res = load(&ufs, &path).and_then(|blocks| Ok(ufs.cache().prime(&blocks)?));
+		match res {
+			Ok(n) => log::info!("primed the cache with {n} blocks from {}", path.display()),
+			Err(e) => log::warn!("failed to prime the cache: {e:#}"),
+		}
+	});
+	if let Err(e) = res {
+		log::warn!("failed to prime the cache: {e}");
+	}
+}
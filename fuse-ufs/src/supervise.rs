@@ -0,0 +1,73 @@
+use std::{
+	collections::VecDeque,
+	panic::{self, AssertUnwindSafe},
+	path::Path,
+	sync::Arc,
+	time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use fuser::{MountOption, Session};
+
+use crate::{
+	handles::{DirHandles, FileHandles},
+	Fs,
+};
+
+/// Give up, if the session crashed this many times within `WINDOW`.
+const MAX_RESTARTS: usize = 5;
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Run the FUSE session, and restart it, if it crashes (`-o supervise`).
+///
+/// A panic in the session loop would leave a dead mount point behind, which has to be
+/// unmounted manually. Instead, the filesystem is unmounted, and mounted again,
+/// using the same, already opened filesystem.
+pub fn run(fs: Fs, mp: &Path, opts: &[MountOption]) -> Result<()> {
+	let ufs = Arc::clone(&fs.ufs);
+	let threads = fs.threads;
+	let perms = fs.perms;
+	#[cfg(target_os = "linux")]
+	let ioctls = Arc::clone(&fs.ioctls);
+	let prime = fs.prime.clone();
+	let mut fs = Some(fs);
+	let mut crashes = VecDeque::new();
+
+	loop {
+		let fs = fs.take().unwrap_or_else(|| {
+			Fs {
+				ufs: Arc::clone(&ufs),
+				perms,
+				threads,
+				pool: None,
+				// The kernel forgets the open directories, when it unmounts.
+				dirs: Arc::new(DirHandles::new()),
+				files: Arc::new(FileHandles::new()),
+				known: None,
+				#[cfg(target_os = "linux")]
+				ioctls: Arc::clone(&ioctls),
+				prime: prime.clone(),
+			}
+		});
+		let mut session = Session::new(fs, mp, opts)?;
+		match panic::catch_unwind(AssertUnwindSafe(|| session.run())) {
+			Ok(res) => return Ok(res?),
+			Err(_) => log::error!("the FUSE session crashed, unmounting {}", mp.display()),
+		}
+		session.unmount();
+		drop(session);
+
+		let now = Instant::now();
+		crashes.push_back(now);
+		crashes.retain(|&t| now.duration_since(t) < WINDOW);
+		if crashes.len() > MAX_RESTARTS {
+			bail!(
+				"the FUSE session crashed {} times within {}s, giving up",
+				crashes.len(),
+				WINDOW.as_secs()
+			);
+		}
+		log::warn!("mounting {} again", mp.display());
+	}
+}
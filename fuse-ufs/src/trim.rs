@@ -0,0 +1,54 @@
+use std::{fs::File, os::unix::fs::MetadataExt};
+
+use anyhow::{Context, Result};
+use rufs::{BlockFile, Options, Ufs, WindowedBackend};
+
+use crate::cli::TrimArgs;
+
+/// Punch holes into an image file, or discard the blocks of a device, where the filesystem
+/// has free fragments, and print how much storage was reclaimed.
+pub fn trim(args: &TrimArgs) -> Result<()> {
+	let path = &args.image;
+	let file = File::options()
+		.read(true)
+		.write(true)
+		.open(path)
+		.with_context(|| format!("failed to open {}", path.display()))?;
+	let meta = file.metadata()?;
+	// st_blocks is always in units of 512 bytes.
+	let before = meta.blocks() * 512;
+
+	let file = BlockFile::from_file(file)?;
+	if !args.no_lock {
+		crate::lock(&file, path, true, "--no-lock")?;
+	}
+	let window = match rufs::find_ufs_partition(&file)? {
+		Some(part) => WindowedBackend::new(file, part.start, Some(part.size)),
+		None => WindowedBackend::whole(file),
+	};
+	let opts = Options {
+		force: args.force,
+		..Options::default()
+	};
+	let ufs = Ufs::with_options(window, opts)
+		.with_context(|| format!("failed to open the filesystem in {}", path.display()))?;
+	let discarded = ufs
+		.trim()
+		.with_context(|| format!("failed to trim {}", path.display()))?;
+	ufs.sync()?;
+
+	if !meta.is_file() {
+		println!(
+			"{}: discarded {discarded} bytes of free space",
+			path.display()
+		);
+		return Ok(());
+	}
+	let after = std::fs::metadata(path)?.blocks() * 512;
+	println!(
+		"{}: discarded {discarded} bytes of free space, reclaimed {} bytes",
+		path.display(),
+		before.saturating_sub(after)
+	);
+	Ok(())
+}
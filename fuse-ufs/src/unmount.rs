@@ -0,0 +1,79 @@
+//! Unmounting the filesystem, if fuse-ufs exits without doing so, eg. because it was killed
+//! (`-o auto_unmount` with FUSE2).
+//!
+//! With FUSE3, fusermount3 takes care of this, but libfuse2 doesn't support `auto_unmount`.
+//! Instead, a watchdog process waits until fuse-ufs exited, and unmounts the stale mount.
+use std::{
+	fs::{self, File},
+	io::{Error as IoError, ErrorKind, Read},
+	os::fd::{FromRawFd, OwnedFd},
+	path::Path,
+	process::Command,
+};
+
+use anyhow::{Context, Result};
+
+/// The command, which unmounts a FUSE filesystem, that isn't served anymore.
+/// It is lazy or forced, because files may still be open.
+#[cfg(target_os = "linux")]
+const UMOUNT: &[&str] = &["fusermount", "-u", "-z"];
+#[cfg(not(target_os = "linux"))]
+const UMOUNT: &[&str] = &["umount", "-f"];
+
+/// Fork a watchdog, which unmounts `mp`, once this process and its children exited,
+/// if the filesystem wasn't unmounted.
+///
+/// This must be called, before any threads are started, and before the device is opened,
+/// so that the watchdog doesn't keep it open.
+pub fn spawn(mp: &Path) -> Result<()> {
+	let mp = fs::canonicalize(mp).with_context(|| format!("failed to resolve {}", mp.display()))?;
+	let mut fds = [0; 2];
+	if unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 {
+		return Err(IoError::last_os_error()).context("failed to create a pipe");
+	}
+	// SAFETY: the descriptors were just created, and aren't owned by anything else.
+	let rx = unsafe { OwnedFd::from_raw_fd(fds[0]) };
+
+	// SAFETY: there is only one thread yet.
+	match unsafe { libc::fork() } {
+		-1 => Err(IoError::last_os_error()).context("failed to fork the watchdog"),
+		0 => {
+			unsafe { libc::close(fds[1]) };
+			watch(rx, &mp);
+			// Neither run the destructors, nor flush the buffers of the parent.
+			unsafe { libc::_exit(0) }
+		}
+		// The write end is left open, and inherited by the daemon, which fuse_main() forks.
+		// The kernel closes it, when the last process holding it exited.
+		_ => Ok(()),
+	}
+}
+
+/// Wait until the write end of `rx` is closed, and unmount `mp`, if the mount is stale.
+fn watch(rx: OwnedFd, mp: &Path) {
+	// Don't get the signals of the terminal, like ^C, and don't keep the working directory busy.
+	unsafe { libc::setsid() };
+	let _ = std::env::set_current_dir("/");
+
+	// Nothing is ever written, so this returns at EOF.
+	let mut rx = File::from(rx);
+	let mut buf = [0u8; 1];
+	while let Err(e) = rx.read(&mut buf) {
+		if e.kind() != ErrorKind::Interrupted {
+			break;
+		}
+	}
+
+	// Stale mounts fail with ENOTCONN or ENXIO, depending on the OS.
+	// Otherwise, it was unmounted, or something else was mounted there since.
+	match fs::metadata(mp) {
+		Ok(_) => return,
+		Err(e) if e.kind() == ErrorKind::NotFound => return,
+		Err(e) => log::warn!("{}: {e}, unmounting the stale mount", mp.display()),
+	}
+	match Command::new(UMOUNT[0]).args(&UMOUNT[1..]).arg(mp).status() {
+		Ok(status) if status.success() => {}
+		Ok(status) => log::error!("{} {}: {status}", UMOUNT.join(" "), mp.display()),
+		Err(e) => log::error!("failed to run {}: {e}", UMOUNT[0]),
+	}
+}
@@ -0,0 +1,121 @@
+use std::{
+	collections::{HashMap, HashSet},
+	ffi::{OsStr, OsString},
+	fs,
+	io,
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex, MutexGuard, PoisonError},
+	thread,
+	time::{Duration, SystemTime},
+};
+
+use rufs::Ufs;
+
+use crate::{Current, Device};
+
+/// An inode, which the kernel knows about, and the names, it was looked up by.
+#[derive(Default)]
+struct Node {
+	nlookup: u64,
+	names:   HashSet<(u64, OsString)>,
+}
+
+/// Inodes and directory entries, which the kernel may have cached,
+/// because they were looked up, and not forgotten yet.
+#[derive(Default)]
+pub struct Known(Mutex<HashMap<u64, Node>>);
+
+impl Known {
+	/// Remember, that `name` in directory `parent` was looked up as inode `ino`.
+	pub fn lookup(&self, parent: u64, name: &OsStr, ino: u64) {
+		let mut nodes = self.lock();
+		let node = nodes.entry(ino).or_default();
+		node.nlookup += 1;
+		node.names.insert((parent, name.to_owned()));
+	}
+
+	/// The kernel dropped `nlookup` references to inode `ino`.
+	pub fn forget(&self, ino: u64, nlookup: u64) {
+		let mut nodes = self.lock();
+		if let Some(node) = nodes.get_mut(&ino) {
+			node.nlookup = node.nlookup.saturating_sub(nlookup);
+			if node.nlookup == 0 {
+				nodes.remove(&ino);
+			}
+		}
+	}
+
+	fn lock(&self) -> MutexGuard<'_, HashMap<u64, Node>> {
+		// Nodes are updated as a whole, so a poisoned lock is harmless.
+		self.0.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+}
+
+/// What is compared, to detect that the device was modified.
+fn stamp(path: &Path) -> io::Result<(SystemTime, u64)> {
+	let md = fs::metadata(path)?;
+	Ok((md.modified()?, md.len()))
+}
+
+/// Check `device` every `interval`, and once it was modified, serve requests from the
+/// filesystem, that `reopen(device)` returns, and invalidate everything, which the kernel may have cached.
+pub fn spawn(
+	device: PathBuf,
+	interval: Duration,
+	reopen: impl Fn(&Path) -> anyhow::Result<Ufs<Device>> + Send + 'static,
+	current: Arc<Current>,
+	known: Arc<Known>,
+	notifier: fuser::Notifier,
+) -> io::Result<()> {
+	let mut last = stamp(&device)?;
+	thread::Builder::new().name("watch".into()).spawn(move || {
+		loop {
+			thread::sleep(interval);
+			let now = match stamp(&device) {
+				Ok(now) if now != last => now,
+				Ok(_) => continue,
+				Err(e) => {
+					log::warn!("failed to watch {}: {e}", device.display());
+					continue;
+				}
+			};
+			// The device may still be in the middle of being written, so try again later.
+			match reopen(&device) {
+				Ok(ufs) => current.replace(ufs),
+				Err(e) => {
+					log::warn!(
+						"{} was modified, but can't be opened: {e:#}",
+						device.display()
+					);
+					continue;
+				}
+			}
+			last = now;
+			log::info!("{} was modified, invalidating caches", device.display());
+			invalidate(&known, &notifier);
+		}
+	})?;
+	Ok(())
+}
+
+/// Tell the kernel to forget the attributes, data and directory entries of all known inodes.
+fn invalidate(known: &Known, notifier: &fuser::Notifier) {
+	let nodes = known
+		.lock()
+		.iter()
+		.map(|(&ino, node)| (ino, node.names.iter().cloned().collect::<Vec<_>>()))
+		.collect::<Vec<_>>();
+	// The root directory is never looked up.
+	let root = (fuser::FUSE_ROOT_ID, Vec::new());
+	for (ino, names) in nodes.into_iter().chain([root]) {
+		for (parent, name) in names {
+			// Entries, which the kernel already dropped, fail with ENOENT.
+			if let Err(e) = notifier.inval_entry(parent, &name) {
+				log::debug!("inval_entry({parent}, {name:?}): {e}");
+			}
+		}
+		if let Err(e) = notifier.inval_inode(ino, 0, 0) {
+			log::debug!("inval_inode({ino}): {e}");
+		}
+	}
+}
@@ -0,0 +1,37 @@
+//! Finding changed files with `fuse-ufs changed-since`.
+mod common;
+
+use std::process::Command;
+
+use assert_cmd::cargo::CommandCargoExt;
+use common::GOLDEN_LE;
+
+fn changed_since(args: &[&str]) -> String {
+	let out = Command::cargo_bin("fuse-ufs")
+		.unwrap()
+		.arg("changed-since")
+		.args(&args[..(args.len() - 1)])
+		.arg(&*GOLDEN_LE)
+		.arg(args[args.len() - 1])
+		.output()
+		.unwrap();
+	assert!(out.status.success(), "{out:?}");
+	String::from_utf8(out.stdout).unwrap()
+}
+
+#[test]
+fn paths() {
+	let out = changed_since(&["0"]);
+	let paths = out.lines().collect::<Vec<_>>();
+	assert_eq!(paths[..3], ["/", "/.snap", "/file1"]);
+	assert!(paths.contains(&"/dir1/dir2/dir3/file2"));
+
+	assert_eq!(changed_since(&["2100-01-01"]), "");
+}
+
+#[test]
+fn inodes() {
+	let out = changed_since(&["--inodes", "1970-01-01T00:00"]);
+	assert_eq!(out.lines().next(), Some("2"));
+	assert_eq!(out.lines().count(), changed_since(&["0"]).lines().count());
+}
@@ -0,0 +1,513 @@
+//! Scenarios, which are shared by the FUSE-mounted tests in integration.rs,
+//! and the unprivileged tests in direct.rs, which use the rufs API instead.
+#![allow(dead_code)]
+
+use std::{
+	ffi::{OsStr, OsString},
+	fs,
+	io::{self, ErrorKind, Write},
+	os::unix::ffi::OsStrExt,
+	path::{Path, PathBuf},
+	process::Command,
+	time::{Duration, SystemTime},
+};
+
+use lazy_static::lazy_static;
+use tempfile::{tempdir, TempDir};
+
+pub fn prepare_image(filename: &str) -> PathBuf {
+	let mut zimg = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+	zimg.push("../resources");
+	zimg.push(filename);
+	zimg.set_extension("img.zst");
+	let mut img = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+	img.push(filename);
+
+	// If the golden image doesn't exist, or is out of date, rebuild it
+	// Note: we can't accurately compare the two timestamps with less than 1
+	// second granularity due to a zstd bug.
+	// https://github.com/facebook/zstd/issues/3748
+	let zmtime = fs::metadata(&zimg).unwrap().modified().unwrap();
+	let mtime = fs::metadata(&img);
+	if mtime.is_err() || (mtime.unwrap().modified().unwrap() + Duration::from_secs(1)) < zmtime {
+		Command::new("unzstd")
+			.arg("-f")
+			.arg("-o")
+			.arg(&img)
+			.arg(&zimg)
+			.output()
+			.expect("Uncompressing golden image failed");
+	}
+	img
+}
+
+lazy_static! {
+	// TODO: GOLDEN_BIG and other configs, like 64K/8K, 4K/4k, etc.
+	pub static ref GOLDEN_LE: PathBuf = prepare_image("ufs-little.img");
+	pub static ref GOLDEN_BE: PathBuf = prepare_image("ufs-big.img");
+}
+
+/// Metadata of a file, as far as the scenarios care about it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stat {
+	pub ino:    u64,
+	pub size:   u64,
+	/// Number of 512-byte blocks.
+	pub blocks: u64,
+	pub mtime:  SystemTime,
+	pub ctime:  SystemTime,
+}
+
+/// Usage of the filesystem, like `statvfs(3)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatFs {
+	pub iosize: u64,
+	pub frsize: u64,
+	pub blocks: u64,
+	pub bfree:  u64,
+	pub bavail: u64,
+	pub files:  u64,
+	pub ffree:  u64,
+}
+
+/// The operations, that the scenarios perform on a filesystem.
+/// Paths are relative to the root of the filesystem, and symbolic links aren't followed.
+pub trait Fs {
+	/// Names and inode numbers of the entries of a directory, including "." and "..".
+	fn read_dir(&self, path: &Path) -> io::Result<Vec<(OsString, u64)>>;
+
+	fn stat(&self, path: &Path) -> io::Result<Stat>;
+
+	/// Fill `buf` from offset `off` of a file.
+	fn read_exact_at(&self, path: &Path, buf: &mut [u8], off: u64) -> io::Result<()>;
+
+	fn read_link(&self, path: &Path) -> io::Result<PathBuf>;
+
+	/// Find the next data (`SEEK_DATA`), or the next hole (`SEEK_HOLE`), at or after `off`.
+	fn seek(&self, path: &Path, off: u64, data: bool) -> io::Result<u64>;
+
+	fn list_xattr(&self, path: &Path) -> io::Result<Vec<OsString>>;
+
+	fn get_xattr(&self, path: &Path, name: &str) -> io::Result<Option<Vec<u8>>>;
+
+	fn statfs(&self) -> io::Result<StatFs>;
+
+	/// Write back the modifications of a file or directory, like `fsync(2)`.
+	fn sync(&self, path: &Path) -> io::Result<()>;
+
+	/// Whether the filesystem is accessed through a FUSE mount.
+	fn is_fuse(&self) -> bool;
+
+	/// Read the whole file.
+	fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+		let mut buf = vec![0u8; self.stat(path)?.size as usize];
+		self.read_exact_at(path, &mut buf, 0)?;
+		Ok(buf)
+	}
+}
+
+/// Define a test for each scenario, which runs it on both golden images,
+/// after opening them with `$open`.
+#[allow(unused_macros)]
+macro_rules! scenario_tests {
+	($open:path) => {
+		scenario_tests! {
+			$open;
+			contents,
+			root_dotdot,
+			read_direct,
+			read_indir1,
+			readlink_short,
+			readlink_long,
+			statfs,
+			non_existent,
+			sparse,
+			sparse_hole,
+			sparse2,
+			sparse3,
+			sparse3_issue54,
+			#[cfg(any(target_os = "freebsd", target_os = "linux"))]
+			seek_data,
+			#[cfg(any(target_os = "freebsd", target_os = "linux"))]
+			seek_data_indir3,
+			listxattr,
+			getxattr,
+			noxattrs,
+			many_xattrs,
+			big_xattr,
+			fsync,
+		}
+
+		/// Names are arbitrary bytes, which are passed through unchanged.
+		#[rstest]
+		#[case::le(GOLDEN_LE.as_path())]
+		#[case::be(GOLDEN_BE.as_path())]
+		fn non_utf8_name(#[case] golden: &Path) {
+			let (_tmp, img) = common::non_utf8_image(golden);
+			common::non_utf8_name(&$open(&img));
+		}
+
+		/// Timestamps after 2038 are passed through unchanged.
+		#[rstest]
+		#[case::le(GOLDEN_LE.as_path())]
+		#[case::be(GOLDEN_BE.as_path())]
+		fn timestamps(#[case] golden: &Path) {
+			let (_tmp, img) = common::timestamps_image(golden);
+			common::timestamps(&$open(&img));
+		}
+
+		/// Timestamps before 1970 are passed through unchanged, except by fuse2,
+		/// which clamps them to the epoch.
+		#[rstest]
+		#[case::le(GOLDEN_LE.as_path())]
+		#[case::be(GOLDEN_BE.as_path())]
+		fn timestamps_pre_epoch(#[case] golden: &Path) {
+			let (_tmp, img) = common::timestamps_image(golden);
+			common::timestamps_pre_epoch(&$open(&img));
+		}
+	};
+	($open:path; $($(#[$attr:meta])* $name:ident),* $(,)?) => {$(
+		$(#[$attr])*
+		#[rstest]
+		#[case::le(GOLDEN_LE.as_path())]
+		#[case::be(GOLDEN_BE.as_path())]
+		fn $name(#[case] img: &Path) {
+			common::$name(&$open(img));
+		}
+	)*};
+}
+#[allow(unused_imports)]
+pub(crate) use scenario_tests;
+
+// TODO: find all files recursively
+pub fn contents(fs: &impl Fs) {
+	let mut entries = fs
+		.read_dir(Path::new(""))
+		.unwrap()
+		.into_iter()
+		.map(|(name, _)| name.into_string().unwrap())
+		.collect::<Vec<_>>();
+
+	entries.sort();
+
+	let mut expected = [
+		".",
+		"..",
+		".snap",
+		"dir1",
+		"file1",
+		"file3",
+		"link1",
+		"xattrs",
+		"sparse",
+		"sparse2",
+		"sparse3",
+		"xattrs2",
+		"xattrs3",
+		"long-link",
+	];
+
+	expected.sort();
+
+	assert_eq!(entries, expected);
+}
+
+/// ".." of the root directory refers to the root directory, in lookups and in readdir.
+pub fn root_dotdot(fs: &impl Fs) {
+	let root = fs.stat(Path::new("")).unwrap();
+	let up = fs.stat(Path::new("dir1/..")).unwrap();
+	assert_eq!(up.ino, root.ino);
+	let up = fs.stat(Path::new("dir1/dir2/../..")).unwrap();
+	assert_eq!(up.ino, root.ino);
+
+	let entries = fs.read_dir(Path::new("")).unwrap();
+	let (_, dotdot) = entries.iter().find(|(name, _)| name == "..").unwrap();
+	assert_eq!(*dotdot, root.ino);
+}
+
+pub fn read_direct(fs: &impl Fs) {
+	let file = fs.read(Path::new("file1")).unwrap();
+	assert_eq!(file, b"This is a simple file.\n");
+}
+
+pub fn read_indir1(fs: &impl Fs) {
+	let file = String::from_utf8(fs.read(Path::new("file3")).unwrap()).unwrap();
+	file.lines().enumerate().for_each(|(i, l)| {
+		let l = &l[0..15];
+		assert_eq!(l, format!("{i:015x}"));
+	});
+}
+
+// TODO: read_indir{2,3} pending #29
+
+/// Files and directories can be synced, even though there is nothing to write back.
+pub fn fsync(fs: &impl Fs) {
+	for path in ["", "dir1", "file1", "file3", "sparse3"] {
+		fs.sync(Path::new(path)).unwrap();
+	}
+}
+
+pub fn readlink_short(fs: &impl Fs) {
+	let link = fs.read_link(Path::new("link1")).unwrap();
+	assert_eq!(&link, Path::new("dir1/dir2/dir3/file2"));
+}
+
+pub fn readlink_long(fs: &impl Fs) {
+	let link = fs.read_link(Path::new("long-link")).unwrap();
+	let expected = (0..508).map(|_| "./").fold(String::new(), |a, x| a + x) + "//file1";
+
+	assert_eq!(link, Path::new(&expected));
+}
+
+pub fn statfs(fs: &impl Fs) {
+	let sfs = fs.statfs().unwrap();
+
+	// Like `ffs_statfs()`, blocks are counted in fragments.
+	assert_eq!(sfs.iosize, 32768);
+	assert_eq!(sfs.frsize, 4096);
+	assert_eq!(sfs.blocks, 871);
+	assert_eq!(sfs.bfree, 430);
+	// minfree is 8%
+	assert_eq!(sfs.bavail, 361);
+	// Inodes 0 and 1 aren't counted.
+	assert_eq!(sfs.files, 1022);
+	assert_eq!(sfs.ffree, 1006);
+}
+
+pub fn non_existent(fs: &impl Fs) {
+	assert_eq!(
+		fs.stat(Path::new("non-existent")).unwrap_err().kind(),
+		ErrorKind::NotFound
+	);
+}
+
+// This tests both sparse files and 2nd level indirect block addressing
+pub fn sparse(fs: &impl Fs) {
+	let path = Path::new("sparse");
+	let st = fs.stat(path).unwrap();
+
+	assert_eq!(st.blocks, 320);
+	assert_eq!(st.size, 134643712);
+
+	let mut buf = [0u8; 32768];
+	fs.read_exact_at(path, &mut buf, (12 + 4096) * 32768)
+		.unwrap();
+	let expected = [b'x'; 32768];
+	assert_eq!(buf, expected);
+}
+
+pub fn sparse_hole(fs: &impl Fs) {
+	let mut buf = [0u8; 32768];
+	fs.read_exact_at(Path::new("sparse"), &mut buf, (12 + 5) * 32768)
+		.unwrap();
+	let expected = [0; 32768];
+	assert_eq!(buf, expected);
+}
+
+// A sparse file with only a single fragment of data at the end
+pub fn sparse2(fs: &impl Fs) {
+	let path = Path::new("sparse2");
+	let st = fs.stat(path).unwrap();
+
+	assert_eq!(st.blocks, 320);
+	assert_eq!(st.size, 134615040);
+
+	let mut buf = [0u8; 4096];
+	fs.read_exact_at(path, &mut buf, (12 + 4096) * 32768)
+		.unwrap();
+	let expected = [b'x'; 4096];
+	assert_eq!(buf, expected);
+}
+
+// A sparse so large, it needs third level indirect block addressing.
+pub fn sparse3(fs: &impl Fs) {
+	let path = Path::new("sparse3");
+	let st = fs.stat(path).unwrap();
+
+	assert_eq!(st.blocks, 448);
+	assert_eq!(st.size, 549890457600);
+
+	let mut buf = [0u8; 32768];
+	fs.read_exact_at(path, &mut buf, (12 + 4096 + 4096 * 4096) * 32768)
+		.unwrap();
+	let expected = [b'x'; 32768];
+	assert_eq!(buf, expected);
+}
+
+// This checks, that issue #54 doesn't happen.
+pub fn sparse3_issue54(fs: &impl Fs) {
+	let mut buf = [0u8; 128 * 1024];
+	fs.read_exact_at(Path::new("sparse3"), &mut buf, 549883084800)
+		.unwrap();
+	let expected = [0; 128 * 1024];
+	assert_eq!(buf, expected);
+}
+
+#[cfg(any(target_os = "freebsd", target_os = "linux"))]
+pub fn seek_data(fs: &impl Fs) {
+	let path = Path::new("sparse");
+	let size = fs.stat(path).unwrap().size;
+
+	assert_eq!(fs.seek(path, 0, false).unwrap(), 0);
+	let data = fs.seek(path, 0, true).unwrap();
+	assert_eq!(data, (12 + 4095) * 32768);
+	assert_eq!(fs.seek(path, data, false).unwrap(), size);
+	assert_eq!(
+		fs.seek(path, size, true).unwrap_err().raw_os_error(),
+		Some(libc::ENXIO)
+	);
+}
+
+// Seeking in a file so large, that it needs third level indirect blocks
+#[cfg(any(target_os = "freebsd", target_os = "linux"))]
+pub fn seek_data_indir3(fs: &impl Fs) {
+	let path = Path::new("sparse3");
+	let size = fs.stat(path).unwrap().size;
+
+	let data = fs.seek(path, 0, true).unwrap();
+	assert_eq!(data, size - 2 * 32768);
+	assert_eq!(fs.seek(path, data, false).unwrap(), size);
+}
+
+pub fn listxattr(fs: &impl Fs) {
+	let xattrs = fs.list_xattr(Path::new("xattrs")).unwrap();
+	let expected = [OsStr::new("user.test")];
+	assert_eq!(xattrs, expected);
+}
+
+pub fn getxattr(fs: &impl Fs) {
+	let data = fs
+		.get_xattr(Path::new("xattrs"), "user.test")
+		.unwrap()
+		.unwrap();
+	let expected = b"testvalue";
+	assert_eq!(data, expected);
+	assert_eq!(
+		fs.get_xattr(Path::new("xattrs"), "user.missing").unwrap(),
+		None
+	);
+}
+
+pub fn noxattrs(fs: &impl Fs) {
+	let xattrs = fs.list_xattr(Path::new("file1")).unwrap();
+	assert_eq!(xattrs.len(), 0);
+}
+
+pub fn many_xattrs(fs: &impl Fs) {
+	let max = 2297;
+	let path = Path::new("xattrs2");
+
+	let xattrs = fs.list_xattr(path).unwrap();
+	let expected = (1..=max)
+		.map(|i| OsString::from(format!("user.attr{i}")))
+		.collect::<Vec<_>>();
+	assert_eq!(xattrs, expected);
+
+	for i in 1..=max {
+		let name = format!("user.attr{i}");
+		let data = fs.get_xattr(path, &name).unwrap().unwrap();
+		let expected = format!("value{i}");
+		assert_eq!(data, expected.as_bytes());
+	}
+}
+
+pub fn big_xattr(fs: &impl Fs) {
+	let data = fs
+		.get_xattr(Path::new("xattrs3"), "user.big")
+		.unwrap()
+		.unwrap();
+	let mut expected = (0..4000).fold(Vec::new(), |mut s, i| {
+		writeln!(&mut s, "{i:015x}").unwrap();
+		s
+	});
+	expected.pop(); // remove the trailing '\n'
+
+	// first check for the size, to avoid spamming the output
+	assert_eq!(data.len(), expected.len());
+	assert_eq!(data, expected);
+}
+
+/// Not valid UTF-8, and of the same length as "file1", so that the entry can be renamed in place.
+pub const NON_UTF8_NAME: &[u8] = b"f\xe4 l\xff";
+
+/// A private copy of a golden image, in which "file1" was renamed to [`NON_UTF8_NAME`].
+pub fn non_utf8_image(golden: &Path) -> (TempDir, PathBuf) {
+	let tmp = tempdir().unwrap();
+	let img = tmp.path().join("ufs.img");
+	let mut data = fs::read(golden).unwrap();
+	// type (DT_REG) and length of the name, followed by the name
+	let pattern = b"\x08\x05file1";
+	let pos = data
+		.windows(pattern.len())
+		.position(|w| w == pattern)
+		.unwrap();
+	data[(pos + 2)..(pos + 7)].copy_from_slice(NON_UTF8_NAME);
+	fs::write(&img, data).unwrap();
+	(tmp, img)
+}
+
+/// Names are arbitrary bytes, which are passed through unchanged.
+pub fn non_utf8_name(fs: &impl Fs) {
+	let name = OsStr::from_bytes(NON_UTF8_NAME);
+	let names = fs
+		.read_dir(Path::new(""))
+		.unwrap()
+		.into_iter()
+		.map(|(name, _)| name)
+		.collect::<Vec<_>>();
+	assert!(names.iter().any(|n| n == name), "{names:?}");
+	assert!(fs.stat(Path::new("file1")).is_err());
+	let contents = fs.read(Path::new(name)).unwrap();
+	assert_eq!(contents, b"This is a simple file.\n");
+}
+
+/// Build a `SystemTime` from a `struct timespec`, whose nanoseconds count forward.
+pub fn timespec(sec: i64, nsec: u32) -> SystemTime {
+	let secs = Duration::from_secs(sec.unsigned_abs());
+	let t = match sec < 0 {
+		true => SystemTime::UNIX_EPOCH - secs,
+		false => SystemTime::UNIX_EPOCH + secs,
+	};
+	t + Duration::from_nanos(nsec.into())
+}
+
+/// 2100-01-01 00:00:00.123456789 UTC, beyond the range of a 32-bit `time_t`.
+pub const Y2100: (i64, u32) = (4_102_444_800, 123_456_789);
+
+/// 1969-12-31 23:59:58.5 UTC, before the epoch.
+pub const PRE_EPOCH: (i64, u32) = (-2, 500_000_000);
+
+/// A private copy of a golden image, in which "file1" was modified at [`Y2100`],
+/// and changed at [`PRE_EPOCH`].
+pub fn timestamps_image(golden: &Path) -> (TempDir, PathBuf) {
+	let tmp = tempdir().unwrap();
+	let img = tmp.path().join("ufs.img");
+	let mut data = fs::read(golden).unwrap();
+	// fs_magic (0x19540119) is at offset 1372 of the superblock.
+	let le = data[65536 + 1373] == 0x01;
+	let b64 = |x: i64| if le { x.to_le_bytes() } else { x.to_be_bytes() };
+	let b32 = |x: u32| if le { x.to_le_bytes() } else { x.to_be_bytes() };
+
+	// Inode 4 ("file1") in the inode block at fragment 40 of the first cylinder group.
+	let ino = 40 * 4096 + 4 * 256;
+	data[(ino + 40)..(ino + 48)].copy_from_slice(&b64(Y2100.0));
+	data[(ino + 48)..(ino + 56)].copy_from_slice(&b64(PRE_EPOCH.0));
+	data[(ino + 64)..(ino + 68)].copy_from_slice(&b32(Y2100.1));
+	data[(ino + 72)..(ino + 76)].copy_from_slice(&b32(PRE_EPOCH.1));
+	fs::write(&img, data).unwrap();
+	(tmp, img)
+}
+
+pub fn timestamps(fs: &impl Fs) {
+	let st = fs.stat(Path::new("file1")).unwrap();
+	assert_eq!(st.mtime, timespec(Y2100.0, Y2100.1));
+}
+
+pub fn timestamps_pre_epoch(fs: &impl Fs) {
+	let st = fs.stat(Path::new("file1")).unwrap();
+	if cfg!(all(feature = "fuse2", not(feature = "fuse3"))) && fs.is_fuse() {
+		assert_eq!(st.ctime, SystemTime::UNIX_EPOCH);
+	} else {
+		assert_eq!(st.ctime, timespec(PRE_EPOCH.0, PRE_EPOCH.1));
+	}
+}
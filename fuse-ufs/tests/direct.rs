@@ -0,0 +1,125 @@
+//! The scenarios of the integration tests, run directly against the rufs API,
+//! which needs neither FUSE, nor privileges.
+mod common;
+
+use std::{
+	ffi::{OsStr, OsString},
+	io::{self, ErrorKind},
+	os::unix::ffi::OsStrExt,
+	path::{Component, Path, PathBuf},
+};
+
+use common::*;
+use rstest::rstest;
+use rufs::{BlockFile, InodeNum, Ufs, Whence};
+
+struct Direct(Ufs<BlockFile>);
+
+fn direct(img: &Path) -> Direct {
+	Direct(Ufs::open(img).unwrap())
+}
+
+impl Direct {
+	fn lookup(&self, path: &Path) -> io::Result<InodeNum> {
+		path.components().try_fold(InodeNum::ROOT, |inr, c| {
+			match c {
+				Component::Normal(name) => self.0.dir_lookup(inr, name),
+				Component::ParentDir => self.0.dir_lookup(inr, "..".as_ref()),
+				Component::CurDir => Ok(inr),
+				_ => Err(io::Error::from(ErrorKind::InvalidInput)),
+			}
+		})
+	}
+}
+
+impl Fs for Direct {
+	fn read_dir(&self, path: &Path) -> io::Result<Vec<(OsString, u64)>> {
+		let inr = self.lookup(path)?;
+		let mut entries = Vec::new();
+		self.0.dir_iter(inr, |name, inr, _kind| {
+			entries.push((name.to_owned(), inr.get64()));
+			None::<()>
+		})?;
+		Ok(entries)
+	}
+
+	fn stat(&self, path: &Path) -> io::Result<Stat> {
+		let attr = self.0.inode_attr(self.lookup(path)?)?;
+		Ok(Stat {
+			ino:    attr.inr.get64(),
+			size:   attr.size,
+			blocks: attr.blocks,
+			mtime:  attr.mtime,
+			ctime:  attr.ctime,
+		})
+	}
+
+	fn read_exact_at(&self, path: &Path, mut buf: &mut [u8], mut off: u64) -> io::Result<()> {
+		let inr = self.lookup(path)?;
+		while !buf.is_empty() {
+			match self.0.inode_read(inr, off, buf)? {
+				0 => return Err(ErrorKind::UnexpectedEof.into()),
+				n => {
+					buf = &mut buf[n..];
+					off += n as u64;
+				}
+			}
+		}
+		Ok(())
+	}
+
+	fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+		let target = self.0.symlink_read(self.lookup(path)?)?;
+		Ok(OsStr::from_bytes(&target).into())
+	}
+
+	fn seek(&self, path: &Path, off: u64, data: bool) -> io::Result<u64> {
+		let whence = if data { Whence::Data } else { Whence::Hole };
+		self.0.inode_seek(self.lookup(path)?, off, whence)
+	}
+
+	fn list_xattr(&self, path: &Path) -> io::Result<Vec<OsString>> {
+		let names = self.0.xattr_list(self.lookup(path)?)?;
+		Ok(names
+			.split(|&b| b == 0)
+			.filter(|name| !name.is_empty())
+			.map(|name| OsStr::from_bytes(name).to_owned())
+			.collect())
+	}
+
+	fn get_xattr(&self, path: &Path, name: &str) -> io::Result<Option<Vec<u8>>> {
+		match self.0.xattr_read(self.lookup(path)?, name.as_ref()) {
+			Ok(data) => Ok(Some(data)),
+			Err(e) if e.raw_os_error() == Some(ENOATTR) => Ok(None),
+			Err(e) => Err(e),
+		}
+	}
+
+	fn statfs(&self) -> io::Result<StatFs> {
+		let info = self.0.info();
+		Ok(StatFs {
+			iosize: info.iosize.into(),
+			frsize: info.frsize.into(),
+			blocks: info.blocks,
+			bfree:  info.bfree,
+			bavail: info.bavail,
+			files:  info.files,
+			ffree:  info.ffree,
+		})
+	}
+
+	fn sync(&self, path: &Path) -> io::Result<()> {
+		self.0.sync_inode(self.lookup(path)?)
+	}
+
+	fn is_fuse(&self) -> bool {
+		false
+	}
+}
+
+#[cfg(target_os = "linux")]
+const ENOATTR: i32 = libc::ENODATA;
+#[cfg(not(target_os = "linux"))]
+const ENOATTR: i32 = libc::ENOATTR;
+
+scenario_tests!(direct);
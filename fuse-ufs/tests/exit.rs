@@ -0,0 +1,60 @@
+//! The exit codes of fuse-ufs, which are documented in fuse-ufs(8).
+mod common;
+
+use std::{fs, os::unix::fs::FileExt, path::Path, process::Command};
+
+use assert_cmd::cargo::CommandCargoExt;
+use common::GOLDEN_LE;
+use rufs::BlockFile;
+use tempfile::NamedTempFile;
+
+fn check(img: &Path) -> Option<i32> {
+	Command::cargo_bin("fuse-ufs")
+		.unwrap()
+		.arg("--check")
+		.arg(img)
+		.output()
+		.unwrap()
+		.status
+		.code()
+}
+
+#[test]
+fn success() {
+	assert_eq!(check(&GOLDEN_LE), Some(0));
+}
+
+#[test]
+fn usage() {
+	let status = Command::cargo_bin("fuse-ufs")
+		.unwrap()
+		.arg("--no-such-option")
+		.status()
+		.unwrap();
+	assert_eq!(status.code(), Some(2));
+}
+
+#[test]
+fn bad_magic() {
+	let img = NamedTempFile::new().unwrap();
+	img.as_file().set_len(1 << 20).unwrap();
+	assert_eq!(check(img.path()), Some(3));
+}
+
+#[test]
+fn ufs1() {
+	let img = NamedTempFile::new().unwrap();
+	img.as_file().set_len(1 << 20).unwrap();
+	let magic = 0x011954i32.to_le_bytes();
+	img.as_file().write_all_at(&magic, 8192 + 1372).unwrap();
+	assert_eq!(check(img.path()), Some(5));
+}
+
+#[test]
+fn busy() {
+	let img = NamedTempFile::new().unwrap();
+	fs::copy(&*GOLDEN_LE, img.path()).unwrap();
+	let file = BlockFile::open(img.path()).unwrap();
+	file.lock(true).unwrap();
+	assert_eq!(check(img.path()), Some(7));
+}
@@ -0,0 +1,84 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use rufs::*;
+
+type Image<'a> = SeekBackend<Cursor<&'a [u8]>>;
+
+/// Maximum number of bytes, that are read from each file.
+const MAX_READ: u64 = 1 << 16;
+
+/// Maximum number of inodes, that are looked at.
+const MAX_INODES: usize = 256;
+
+// No function of rufs may panic, whatever the image contains, because a panic takes down
+// the whole FUSE daemon. libFuzzer reports every panic as a crash.
+// The image is opened in degraded mode, which gets past most checks of the superblock,
+// and as many public functions as possible are called. Their results don't matter.
+fuzz_target!(|img: &[u8]| {
+	let opts = Options {
+		force: true,
+		..Options::default()
+	};
+	let Ok(ufs) = Ufs::with_options(SeekBackend::new(Cursor::new(img)), opts) else {
+		return;
+	};
+
+	let _ = ufs.info();
+	let _ = ufs.volume_info();
+	let _ = ufs.lint();
+	let _ = ufs.capabilities();
+	let _ = ufs.check_deep();
+	let _ = ufs.free_extents();
+	let _ = ufs.quota_for_uid(0);
+	let _ = ufs.quota_for_gid(0);
+
+	for entry in ufs.walk(InodeNum::ROOT).take(MAX_INODES) {
+		let _ = entry;
+	}
+	for attr in ufs.inodes().take(MAX_INODES).flatten() {
+		inode(&ufs, &attr);
+	}
+});
+
+/// Call everything, that takes an inode.
+fn inode(ufs: &Ufs<Image>, attr: &InodeAttr) {
+	let inr = attr.inr;
+	let _ = ufs.inode_attr(inr);
+	let _ = ufs.inode_extents(inr);
+	let _ = ufs.xattr_list(inr);
+	let _ = ufs.xattr_read(inr, "user.test".as_ref());
+	let _ = ufs.acl(inr);
+	let _ = ufs.default_acl(inr);
+	for whence in [Whence::Data, Whence::Hole] {
+		let _ = ufs.inode_seek(inr, 0, whence);
+		let _ = ufs.inode_seek(inr, attr.size / 2, whence);
+	}
+
+	match attr.kind {
+		InodeType::Directory => {
+			let _ = ufs.dir_lookup(inr, "..".as_ref());
+			let _ = ufs.dir_slack(inr);
+			for entry in ufs.dir_entries(inr) {
+				let _ = entry;
+			}
+		}
+		InodeType::Symlink => {
+			let _ = ufs.symlink_read(inr);
+		}
+		_ => {
+			let mut buf = [0u8; 4096];
+			let mut pos = 0;
+			while pos < attr.size.min(MAX_READ) {
+				match ufs.inode_read(inr, pos, &mut buf) {
+					Ok(0) | Err(_) => break,
+					Ok(n) => pos += n as u64,
+				}
+			}
+			// Reads beyond the end of the file.
+			let _ = ufs.inode_read(inr, u64::MAX - 1, &mut buf);
+		}
+	}
+}
@@ -1,28 +1,153 @@
 #![no_main]
 
-use std::io::{Cursor, Read, Seek};
+use std::{
+	ffi::OsString,
+	io::{Cursor, ErrorKind, Result as IoResult},
+};
 
 use libfuzzer_sys::fuzz_target;
 use rufs::*;
 
+type Image<'a> = SeekBackend<Cursor<&'a [u8]>>;
+
+/// Maximum number of bytes, that are read from each file.
+const MAX_READ: u64 = 1 << 16;
+
+/// Sizes of the caches, which are taken from the first bytes of the input,
+/// so that the fuzzer can explore tiny caches, which evict entries all the time.
+struct CacheConfig {
+	/// Number of blocks of the [`BlockCache`], 0 disables it.
+	blocks:    u64,
+	dcache:    usize,
+	readahead: u64,
+	statahead: usize,
+}
+
+impl CacheConfig {
+	const LEN: usize = 4;
+
+	fn new(b: &[u8]) -> Self {
+		Self {
+			blocks:    (b[0] % 16).into(),
+			dcache:    (b[1] % 8).into(),
+			readahead: u64::from(b[2] % 4) * 32768,
+			statahead: usize::from(b[3] % 4) * 8,
+		}
+	}
+
+	fn options(&self) -> Options {
+		Options {
+			dcache: self.dcache,
+			readahead: self.readahead,
+			statahead: self.statahead,
+			..Options::default()
+		}
+	}
+}
+
+/// Errors are compared by their kind and `errno`.
+fn res<T>(r: IoResult<T>) -> Result<T, (ErrorKind, i32)> {
+	r.map_err(|e| (e.kind(), rufs::errno(&e)))
+}
+
+// The same filesystem is opened with and without caches, and every operation must return
+// the same result on both, so that stale or mixed up cache entries are detected.
 fuzz_target!(|data: &[u8]| {
-	let rdr = BlockReader::new(Cursor::new(data), 4096);
-	let mut fs = match Ufs::new(rdr) {
-		Ok(fs) => fs,
+	if data.len() < CacheConfig::LEN {
+		return;
+	}
+	let (cfg, img) = data.split_at(CacheConfig::LEN);
+	let cfg = CacheConfig::new(cfg);
+
+	let plain = Ufs::new(SeekBackend::new(Cursor::new(img)));
+	let cache = BlockCache::new(
+		SeekBackend::new(Cursor::new(img)),
+		cfg.blocks * BlockCache::<Image>::BLOCK_SIZE,
+	);
+	let cached = Ufs::with_options(cache, cfg.options());
+	let (plain, cached) = match (plain, cached) {
+		(Ok(plain), Ok(cached)) => (plain, cached),
 		// Malformed FS already detected and handled properly by rufs
-		Err(_) => return,
+		(Err(a), Err(b)) => {
+			assert_eq!(res::<()>(Err(a)), res(Err(b)));
+			return;
+		}
+		(a, b) => panic!("opened with caches: {}, without: {}", b.is_ok(), a.is_ok()),
 	};
-	traverse(&mut fs, InodeNum::ROOT);
+
+	let mut guard = TreeGuard::new();
+	traverse(&plain, &cached, &mut guard, InodeNum::ROOT);
 });
 
-fn traverse<R: Read + Seek>(fs: &mut Ufs<R>, inr: InodeNum) {
+fn children<B: Backend>(
+	fs: &Ufs<B>,
+	inr: InodeNum,
+) -> IoResult<Vec<(OsString, InodeNum, InodeType)>> {
 	let mut children = Vec::new();
-	let _ = fs.dir_iter(inr, |name, inr, kind| {
-		children.push((name.to_owned(), inr, kind));
+	fs.dir_iter(inr, |name, inr, kind| {
+		if name != "." && name != ".." {
+			children.push((name.to_owned(), inr, kind));
+		}
 		None::<()>
-	});
-	for (_name, cinr, _kind) in children {
-		// TODO: Also excercise other fs methods
-		traverse(fs, cinr);
+	})?;
+	Ok(children)
+}
+
+/// Read a file sequentially, in small chunks, so that readahead kicks in.
+fn read<B: Backend>(fs: &Ufs<B>, inr: InodeNum, size: u64) -> IoResult<Vec<u8>> {
+	let mut data = Vec::new();
+	let mut buf = [0u8; 4096];
+	let mut pos = 0;
+	while pos < size.min(MAX_READ) {
+		let n = fs.inode_read(inr, pos, &mut buf)?;
+		if n == 0 {
+			break;
+		}
+		data.extend_from_slice(&buf[..n]);
+		pos += n as u64;
+	}
+	Ok(data)
+}
+
+fn traverse(
+	plain: &Ufs<Image>,
+	cached: &Ufs<BlockCache<Image>>,
+	guard: &mut TreeGuard,
+	inr: InodeNum,
+) {
+	if guard.enter(inr).is_err() {
+		return;
+	}
+
+	let children = res(children(plain, inr));
+	assert_eq!(children, res(self::children(cached, inr)));
+	for (name, cinr, kind) in children.unwrap_or_default() {
+		// The second lookup is served from the dentry cache.
+		for _ in 0..2 {
+			let expected = res(plain.dir_lookup(inr, &name));
+			assert_eq!(expected, res(cached.dir_lookup(inr, &name)));
+		}
+
+		let attr = res(plain.inode_attr(cinr));
+		assert_eq!(attr, res(cached.inode_attr(cinr)));
+		let Ok(attr) = attr else {
+			continue;
+		};
+		assert_eq!(res(plain.xattr_list(cinr)), res(cached.xattr_list(cinr)));
+
+		match kind {
+			InodeType::RegularFile => {
+				let expected = res(read(plain, cinr, attr.size));
+				assert_eq!(expected, res(read(cached, cinr, attr.size)));
+			}
+			InodeType::Symlink => {
+				let expected = res(plain.symlink_read(cinr));
+				assert_eq!(expected, res(cached.symlink_read(cinr)));
+			}
+			InodeType::Directory => traverse(plain, cached, guard, cinr),
+			_ => {}
+		}
 	}
+
+	guard.leave();
 }
@@ -0,0 +1,829 @@
+use std::{
+	fs::File,
+	io::{Error as IoError, ErrorKind, Read, Result as IoResult, Seek, SeekFrom},
+	ops::{Deref, DerefMut, Range},
+	os::unix::fs::{FileExt, FileTypeExt, MetadataExt},
+	path::Path,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Mutex,
+		PoisonError,
+		RwLock,
+	},
+};
+
+use crate::err;
+
+/// Storage, that holds a filesystem.
+///
+/// All accesses are done using positioned I/O, so no seeking is required,
+/// and implementations can serve multiple threads at once.
+pub trait Backend {
+	/// Read exactly `buf.len()` bytes, starting at `pos`.
+	fn read_at(&self, pos: u64, buf: &mut [u8]) -> IoResult<()>;
+
+	/// Write all of `buf`, starting at `pos`.
+	///
+	/// Read-only backends fail with `EROFS`.
+	fn write_at(&self, _pos: u64, _buf: &[u8]) -> IoResult<()> {
+		Err(err!(EROFS))
+	}
+
+	/// Hint, that `len` bytes starting at `pos` are going to be read soon.
+	///
+	/// Backends without a cache ignore this.
+	fn prefetch(&self, _pos: u64, _len: usize) -> IoResult<()> {
+		Ok(())
+	}
+
+	/// Make sure, that all writes reached stable storage.
+	fn sync(&self) -> IoResult<()> {
+		Ok(())
+	}
+
+	/// Make sure, that all writes to `ranges` reached stable storage.
+	///
+	/// Backends, which don't know, where their unsynced writes are, sync everything.
+	fn sync_ranges(&self, _ranges: &[Range<u64>]) -> IoResult<()> {
+		self.sync()
+	}
+
+	/// Release the storage of `len` bytes starting at `pos`, whose contents are no longer needed.
+	///
+	/// Reading them afterwards returns either zeros, or the previous contents.
+	/// Backends, which can't release storage, fail with `EOPNOTSUPP`.
+	#[doc(alias("trim", "punch_hole"))]
+	fn discard(&self, _pos: u64, _len: u64) -> IoResult<()> {
+		Err(err!(EOPNOTSUPP))
+	}
+
+	/// Get `len` bytes starting at `pos` without copying them,
+	/// if the storage is mapped into memory, like by `MmapBackend` (with the `mmap` feature).
+	///
+	/// Other backends return `None`, and are read using [`Backend::read_at()`],
+	/// which is used as well, if the range isn't mapped.
+	fn mapped(&self, _pos: u64, _len: usize) -> Option<&[u8]> {
+		None
+	}
+
+	/// Size of the storage in bytes, if it is known.
+	fn size(&self) -> IoResult<Option<u64>> {
+		Ok(None)
+	}
+
+	/// Hint, that the filesystem is accessed in units of `size` bytes, like its fragment size.
+	///
+	/// This is called, once the superblock was read, see [`Options::io_size`](crate::Options::io_size).
+	/// Backends, which read whole blocks, like [`BlockFile`], may read blocks of this size,
+	/// instead of guessing one. Others ignore this.
+	fn set_io_size(&self, _size: u64) {}
+}
+
+impl<B: Backend + ?Sized> Backend for Box<B> {
+	fn read_at(&self, pos: u64, buf: &mut [u8]) -> IoResult<()> {
+		(**self).read_at(pos, buf)
+	}
+
+	fn write_at(&self, pos: u64, buf: &[u8]) -> IoResult<()> {
+		(**self).write_at(pos, buf)
+	}
+
+	fn prefetch(&self, pos: u64, len: usize) -> IoResult<()> {
+		(**self).prefetch(pos, len)
+	}
+
+	fn sync(&self) -> IoResult<()> {
+		(**self).sync()
+	}
+
+	fn sync_ranges(&self, ranges: &[Range<u64>]) -> IoResult<()> {
+		(**self).sync_ranges(ranges)
+	}
+
+	fn discard(&self, pos: u64, len: u64) -> IoResult<()> {
+		(**self).discard(pos, len)
+	}
+
+	fn mapped(&self, pos: u64, len: usize) -> Option<&[u8]> {
+		(**self).mapped(pos, len)
+	}
+
+	fn size(&self) -> IoResult<Option<u64>> {
+		(**self).size()
+	}
+
+	fn set_io_size(&self, size: u64) {
+		(**self).set_io_size(size)
+	}
+}
+
+/// `DIOCGSECTORSIZE` from `<sys/disk.h>`: `_IOR('d', 128, u_int)`
+#[cfg(target_os = "freebsd")]
+const DIOCGSECTORSIZE: libc::c_ulong = 0x4004_6480;
+
+/// Query the sector size of a disk device.
+///
+/// Returns `None` for regular files, and on platforms, where this isn't supported.
+pub(crate) fn sector_size(file: &File) -> IoResult<Option<u64>> {
+	let kind = file.metadata()?.file_type();
+	if !kind.is_block_device() && !kind.is_char_device() {
+		return Ok(None);
+	}
+
+	#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+	{
+		use std::os::fd::AsRawFd;
+
+		#[cfg(target_os = "linux")]
+		let req = libc::BLKSSZGET;
+		#[cfg(target_os = "freebsd")]
+		let req = DIOCGSECTORSIZE;
+
+		let mut ss: libc::c_uint = 0;
+		// SAFETY: both ioctls store an integer into `ss`.
+		if unsafe { libc::ioctl(file.as_raw_fd(), req as _, &mut ss) } < 0 {
+			let e = IoError::last_os_error();
+			log::debug!("failed to query the sector size: {e}");
+			return Ok(None);
+		}
+		Ok((ss > 0).then_some(ss as u64))
+	}
+
+	#[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+	Ok(None)
+}
+
+/// Take an advisory lock on `file` using `flock(2)`, without waiting for it.
+pub(crate) fn lock_file(file: &File, exclusive: bool) -> IoResult<()> {
+	use std::os::fd::AsRawFd;
+
+	let op = if exclusive {
+		libc::LOCK_EX
+	} else {
+		libc::LOCK_SH
+	};
+	// SAFETY: flock(2) doesn't access memory.
+	if unsafe { libc::flock(file.as_raw_fd(), op | libc::LOCK_NB) } < 0 {
+		return Err(IoError::last_os_error());
+	}
+	Ok(())
+}
+
+/// Deallocate `len` bytes of `file` at `pos`, without changing its size.
+#[cfg(target_os = "linux")]
+fn punch_hole(file: &File, pos: u64, len: u64) -> IoResult<()> {
+	use std::os::fd::AsRawFd;
+
+	let (Ok(off), Ok(len)) = (libc::off_t::try_from(pos), libc::off_t::try_from(len)) else {
+		return Err(err!(EINVAL));
+	};
+	let mode = libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE;
+	// SAFETY: fallocate(2) doesn't access memory.
+	if unsafe { libc::fallocate(file.as_raw_fd(), mode, off, len) } < 0 {
+		return Err(IoError::last_os_error());
+	}
+	Ok(())
+}
+
+/// Deallocate `len` bytes of `file` at `pos`, without changing its size.
+#[cfg(target_os = "freebsd")]
+fn punch_hole(file: &File, pos: u64, len: u64) -> IoResult<()> {
+	use std::os::fd::AsRawFd;
+
+	let (Ok(off), Ok(len)) = (libc::off_t::try_from(pos), libc::off_t::try_from(len)) else {
+		return Err(err!(EINVAL));
+	};
+	let mut range = libc::spacectl_range {
+		r_offset: off,
+		r_len:    len,
+	};
+	// fspacectl(2) may return early, and updates the range to what is left.
+	while range.r_len > 0 {
+		let rqsr = range;
+		// SAFETY: both ranges are valid for the duration of the call.
+		let res = unsafe {
+			libc::fspacectl(
+				file.as_raw_fd(),
+				libc::SPACECTL_DEALLOC,
+				&rqsr,
+				0,
+				&mut range,
+			)
+		};
+		if res < 0 {
+			return Err(IoError::last_os_error());
+		}
+	}
+	Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+fn punch_hole(_file: &File, _pos: u64, _len: u64) -> IoResult<()> {
+	Err(err!(EOPNOTSUPP))
+}
+
+/// `BLKDISCARD` from `<linux/fs.h>`: `_IO(0x12, 119)`
+#[cfg(target_os = "linux")]
+const BLKDISCARD: libc::c_ulong = 0x1277;
+
+/// `DIOCGDELETE` from `<sys/disk.h>`: `_IOW('d', 136, off_t[2])`
+#[cfg(target_os = "freebsd")]
+const DIOCGDELETE: libc::c_ulong = 0x8010_6488;
+
+/// Tell a disk device, that `len` bytes at `pos` are unused, like `blkdiscard(8)`.
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+fn discard_device(file: &File, pos: u64, len: u64) -> IoResult<()> {
+	use std::os::fd::AsRawFd;
+
+	#[cfg(target_os = "linux")]
+	let (req, range) = (BLKDISCARD, [pos, len]);
+	#[cfg(target_os = "freebsd")]
+	let (req, range) = {
+		let (Ok(off), Ok(len)) = (libc::off_t::try_from(pos), libc::off_t::try_from(len)) else {
+			return Err(err!(EINVAL));
+		};
+		(DIOCGDELETE, [off, len])
+	};
+	// SAFETY: both ioctls read two integers from `range`.
+	if unsafe { libc::ioctl(file.as_raw_fd(), req as _, range.as_ptr()) } < 0 {
+		return Err(IoError::last_os_error());
+	}
+	Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+fn discard_device(_file: &File, _pos: u64, _len: u64) -> IoResult<()> {
+	Err(err!(EOPNOTSUPP))
+}
+
+/// A zero-filled buffer, whose start is aligned, as required by `O_DIRECT`.
+struct AlignedBuf {
+	data: Vec<u8>,
+	off:  usize,
+	len:  usize,
+}
+
+impl AlignedBuf {
+	fn new(len: usize, align: usize) -> Self {
+		let data = vec![0u8; len + align];
+		let off = data.as_ptr().align_offset(align);
+		Self { data, off, len }
+	}
+}
+
+impl Deref for AlignedBuf {
+	type Target = [u8];
+
+	fn deref(&self) -> &[u8] {
+		&self.data[self.off..(self.off + self.len)]
+	}
+}
+
+impl DerefMut for AlignedBuf {
+	fn deref_mut(&mut self) -> &mut [u8] {
+		&mut self.data[self.off..(self.off + self.len)]
+	}
+}
+
+/// A file or device, which is accessed using `pread(2)` and `pwrite(2)`.
+///
+/// Accesses are aligned to the sector size of the device,
+/// because raw disk devices on FreeBSD don't support unaligned I/O.
+/// Reads are done in whole blocks, whose size is a multiple of it,
+/// see [`Backend::set_io_size()`], and unaligned writes, like through a [`WindowedBackend`]
+/// with an unaligned offset, read and rewrite the sectors at their start and end.
+pub struct BlockFile {
+	file:   File,
+	/// Alignment, which the file or device requires.
+	align:  u64,
+	/// Size of the blocks, which are read.
+	bs:     AtomicU64,
+	direct: bool,
+	/// Held exclusively during unaligned writes, which read and rewrite whole sectors,
+	/// and shared by all other writes, so that none of them is undone by a stale sector.
+	rmw:    RwLock<()>,
+}
+
+impl BlockFile {
+	/// Open the file or device at `path`, read-only.
+	///
+	/// Accesses to devices are aligned to their sector size,
+	/// and accesses to files aren't aligned, see [`BlockFile::from_file()`].
+	pub fn open(path: &Path) -> IoResult<Self> {
+		Self::open_impl(path, false)
+	}
+
+	/// Open the file or device at `path`, read-only, bypassing the buffer cache (`O_DIRECT`).
+	///
+	/// This makes the performance more predictable, when a [`BlockCache`](crate::BlockCache)
+	/// is used on top of it, but it isn't supported by all filesystems, like tmpfs.
+	/// Fails with `ENOTSUP` on platforms other than FreeBSD and Linux.
+	pub fn open_direct(path: &Path) -> IoResult<Self> {
+		Self::open_impl(path, true)
+	}
+
+	fn open_impl(path: &Path, direct: bool) -> IoResult<Self> {
+		let mut opts = File::options();
+		opts.read(true).write(false);
+		if direct {
+			#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+			{
+				use std::os::unix::fs::OpenOptionsExt;
+				opts.custom_flags(libc::O_DIRECT);
+			}
+			#[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+			return Err(err!(ENOTSUP));
+		}
+
+		let file = opts.open(path)?;
+		let mut bf = if direct {
+			// O_DIRECT requires aligned accesses to files as well.
+			let bs = match sector_size(&file)? {
+				Some(ss) => ss,
+				None => file.metadata()?.blksize(),
+			};
+			Self::new(file, bs)
+		} else {
+			Self::from_file(file)?
+		};
+		bf.direct = direct;
+		log::debug!(
+			"BlockFile::open({path:?}): alignment {}, direct: {direct}",
+			bf.align
+		);
+		Ok(bf)
+	}
+
+	/// Use `file`, which was already opened, with accesses to devices aligned to their sector size.
+	///
+	/// Accesses to regular files aren't aligned, because their `st_blksize` is merely
+	/// a preferred I/O size, which may be huge, like the 128K records of ZFS.
+	/// Until [`Backend::set_io_size()`] is called, reads of files aren't extended at all.
+	pub fn from_file(file: File) -> IoResult<Self> {
+		let bs = sector_size(&file)?.unwrap_or(1);
+		Ok(Self::new(file, bs))
+	}
+
+	/// Use `file`, with accesses aligned to `bs`.
+	pub fn new(file: File, bs: u64) -> Self {
+		// Some filesystems report an `st_blksize` of 0.
+		let bs = bs.max(1);
+		Self {
+			file,
+			align: bs,
+			bs: AtomicU64::new(bs),
+			direct: false,
+			rmw: RwLock::new(()),
+		}
+	}
+
+	/// Take an advisory lock on the file, which is exclusive, if `exclusive` is set,
+	/// and shared otherwise, so that a filesystem isn't modified, while another process uses it.
+	///
+	/// Fails with `EWOULDBLOCK`, if another process holds a conflicting lock,
+	/// and with `ENOLCK` or `EOPNOTSUPP`, if locking isn't supported, like on some network filesystems.
+	/// The lock is released, when the file is closed.
+	#[doc(alias = "flock")]
+	pub fn lock(&self, exclusive: bool) -> IoResult<()> {
+		lock_file(&self.file, exclusive)
+	}
+
+	/// Get the underlying file.
+	pub fn get_ref(&self) -> &File {
+		&self.file
+	}
+
+	/// Check whether the buffer cache is bypassed (`O_DIRECT`).
+	pub fn is_direct(&self) -> bool {
+		self.direct
+	}
+
+	/// Check whether `buf` can be used for I/O without copying it.
+	fn is_aligned(&self, buf: &[u8]) -> bool {
+		!self.direct || buf.as_ptr() as u64 % self.align == 0
+	}
+
+	/// Get the size of the blocks, which are read.
+	pub fn blksize(&self) -> u64 {
+		self.bs.load(Ordering::Relaxed)
+	}
+
+	fn read_some_at(&self, pos: u64, buf: &mut [u8]) -> IoResult<usize> {
+		let mut num = 0;
+		while num < buf.len() {
+			match self.file.read_at(&mut buf[num..], pos + num as u64) {
+				Ok(0) => break,
+				Ok(n) => num += n,
+				Err(e) if e.kind() == ErrorKind::Interrupted => {}
+				Err(e) => return Err(e),
+			}
+		}
+		Ok(num)
+	}
+}
+
+impl Backend for BlockFile {
+	fn read_at(&self, pos: u64, buf: &mut [u8]) -> IoResult<()> {
+		let bs = self.blksize();
+		let end = pos
+			.checked_add(buf.len() as u64)
+			.and_then(|end| end.div_ceil(bs).checked_mul(bs).map(|aend| (end, aend)));
+		let Some((end, aend)) = end else {
+			return Err(err!(EINVAL));
+		};
+		let astart = pos / bs * bs;
+		if astart == pos && aend == end && self.is_aligned(buf) {
+			return self.file.read_exact_at(buf, pos);
+		}
+
+		// The last block of an image file may be incomplete, so only fail,
+		// if the requested range can't be read.
+		let mut block = AlignedBuf::new((aend - astart) as usize, self.align as usize);
+		let num = self.read_some_at(astart, &mut block)?;
+		let off = (pos - astart) as usize;
+		if num < off + buf.len() {
+			return Err(IoError::from(ErrorKind::UnexpectedEof));
+		}
+		buf.copy_from_slice(&block[off..(off + buf.len())]);
+		Ok(())
+	}
+
+	fn write_at(&self, pos: u64, buf: &[u8]) -> IoResult<()> {
+		let align = self.align;
+		if pos % align != 0 || buf.len() as u64 % align != 0 {
+			let end = pos
+				.checked_add(buf.len() as u64)
+				.and_then(|end| end.div_ceil(align).checked_mul(align));
+			let Some(aend) = end else {
+				return Err(err!(EINVAL));
+			};
+			let astart = pos / align * align;
+			let a = align as usize;
+
+			let _guard = self.rmw.write().unwrap_or_else(PoisonError::into_inner);
+			// Sectors beyond the end of an image file read as zeroes.
+			let mut block = AlignedBuf::new((aend - astart) as usize, a);
+			let len = block.len();
+			self.read_some_at(astart, &mut block[..a])?;
+			if len > a {
+				self.read_some_at(aend - align, &mut block[(len - a)..])?;
+			}
+			let off = (pos - astart) as usize;
+			block[off..(off + buf.len())].copy_from_slice(buf);
+			return self.file.write_all_at(&block, astart);
+		}
+
+		let _guard = self.rmw.read().unwrap_or_else(PoisonError::into_inner);
+		if !self.is_aligned(buf) {
+			let mut copy = AlignedBuf::new(buf.len(), self.align as usize);
+			copy.copy_from_slice(buf);
+			return self.file.write_all_at(&copy, pos);
+		}
+		self.file.write_all_at(buf, pos)
+	}
+
+	fn sync(&self) -> IoResult<()> {
+		self.file.sync_data()
+	}
+
+	/// Punch a hole into an image file, which keeps its size,
+	/// or send `BLKDISCARD` (`DIOCGDELETE` on FreeBSD) to a disk device.
+	///
+	/// Fails with `EOPNOTSUPP` on platforms other than FreeBSD and Linux.
+	fn discard(&self, pos: u64, len: u64) -> IoResult<()> {
+		let _guard = self.rmw.read().unwrap_or_else(PoisonError::into_inner);
+		let kind = self.file.metadata()?.file_type();
+		if kind.is_file() {
+			punch_hole(&self.file, pos, len)
+		} else if kind.is_block_device() || kind.is_char_device() {
+			discard_device(&self.file, pos, len)
+		} else {
+			Err(err!(EOPNOTSUPP))
+		}
+	}
+
+	fn size(&self) -> IoResult<Option<u64>> {
+		// Seeking works for devices as well, and doesn't affect positioned I/O.
+		// Some devices report a size of zero, which isn't useful.
+		let size = (&self.file).seek(SeekFrom::End(0))?;
+		Ok((size > 0).then_some(size))
+	}
+
+	/// Read blocks of `size` bytes, if it is a multiple of the required alignment.
+	fn set_io_size(&self, size: u64) {
+		if size == 0 || size % self.align != 0 {
+			log::debug!(
+				"BlockFile: ignoring I/O size {size}, which isn't a multiple of {}",
+				self.align
+			);
+			return;
+		}
+		log::debug!("BlockFile: I/O size {size}");
+		self.bs.store(size, Ordering::Relaxed);
+	}
+}
+
+/// Adapter for readers, which only support `Read + Seek`, like [`std::io::Cursor`].
+///
+/// Accesses are serialized using a lock, and every read is preceded by a seek.
+/// This backend is read-only.
+pub struct SeekBackend<R: Read + Seek> {
+	inner: Mutex<R>,
+}
+
+impl<R: Read + Seek> SeekBackend<R> {
+	pub fn new(inner: R) -> Self {
+		Self {
+			inner: Mutex::new(inner),
+		}
+	}
+}
+
+impl<R: Read + Seek> Backend for SeekBackend<R> {
+	fn read_at(&self, pos: u64, buf: &mut [u8]) -> IoResult<()> {
+		// Every access seeks first, so a poisoned lock is harmless.
+		let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+		inner.seek(SeekFrom::Start(pos))?;
+		inner.read_exact(buf)
+	}
+
+	fn size(&self) -> IoResult<Option<u64>> {
+		let mut inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+		inner.seek(SeekFrom::End(0)).map(Some)
+	}
+}
+
+/// A part of another backend, like a partition.
+///
+/// All accesses are shifted by `offset`, and accesses beyond `size` fail.
+pub struct WindowedBackend<B: Backend> {
+	inner:  B,
+	offset: u64,
+	size:   Option<u64>,
+}
+
+impl<B: Backend> WindowedBackend<B> {
+	/// Access `size` bytes of `inner`, starting at `offset`.
+	/// If `size` is `None`, the window extends to the end of `inner`.
+	pub fn new(inner: B, offset: u64, size: Option<u64>) -> Self {
+		Self {
+			inner,
+			offset,
+			size,
+		}
+	}
+
+	/// Access all of `inner`.
+	pub fn whole(inner: B) -> Self {
+		Self::new(inner, 0, None)
+	}
+
+	/// Get the offset of the window within the underlying backend.
+	pub fn offset(&self) -> u64 {
+		self.offset
+	}
+
+	/// Get a reference to the underlying backend.
+	pub fn inner(&self) -> &B {
+		&self.inner
+	}
+
+	/// Translate a range of the window into the underlying backend.
+	fn translate(&self, pos: u64, len: usize) -> IoResult<u64> {
+		let end = pos
+			.checked_add(len as u64)
+			.ok_or_else(|| IoError::from(ErrorKind::UnexpectedEof))?;
+		if self.size.is_some_and(|size| end > size) {
+			return Err(IoError::from(ErrorKind::UnexpectedEof));
+		}
+		self.offset.checked_add(pos).ok_or_else(|| err!(EINVAL))
+	}
+}
+
+impl<B: Backend> Backend for WindowedBackend<B> {
+	fn read_at(&self, pos: u64, buf: &mut [u8]) -> IoResult<()> {
+		self.inner.read_at(self.translate(pos, buf.len())?, buf)
+	}
+
+	fn write_at(&self, pos: u64, buf: &[u8]) -> IoResult<()> {
+		self.inner.write_at(self.translate(pos, buf.len())?, buf)
+	}
+
+	fn prefetch(&self, pos: u64, len: usize) -> IoResult<()> {
+		// Readahead may go beyond the end of the window.
+		let len = match self.size {
+			Some(size) => len.min(size.saturating_sub(pos) as usize),
+			None => len,
+		};
+		self.inner.prefetch(self.translate(pos, len)?, len)
+	}
+
+	fn sync(&self) -> IoResult<()> {
+		self.inner.sync()
+	}
+
+	fn sync_ranges(&self, ranges: &[Range<u64>]) -> IoResult<()> {
+		let ranges = ranges
+			.iter()
+			.map(|r| {
+				let len = r.end.saturating_sub(r.start);
+				let len = usize::try_from(len).map_err(|_| err!(EINVAL))?;
+				let start = self.translate(r.start, len)?;
+				Ok(start..(start + len as u64))
+			})
+			.collect::<IoResult<Vec<_>>>()?;
+		self.inner.sync_ranges(&ranges)
+	}
+
+	fn discard(&self, pos: u64, len: u64) -> IoResult<()> {
+		let len = usize::try_from(len).map_err(|_| err!(EINVAL))?;
+		self.inner.discard(self.translate(pos, len)?, len as u64)
+	}
+
+	fn mapped(&self, pos: u64, len: usize) -> Option<&[u8]> {
+		self.inner.mapped(self.translate(pos, len).ok()?, len)
+	}
+
+	fn set_io_size(&self, size: u64) {
+		self.inner.set_io_size(size)
+	}
+
+	fn size(&self) -> IoResult<Option<u64>> {
+		let avail = self
+			.inner
+			.size()?
+			.map(|size| size.saturating_sub(self.offset));
+		Ok(match (self.size, avail) {
+			(Some(size), Some(avail)) => Some(size.min(avail)),
+			(size, avail) => size.or(avail),
+		})
+	}
+}
+
+#[cfg(test)]
+mod t {
+	use std::io::{Cursor, Write};
+
+	use super::*;
+
+	fn harness(bs: u64) -> BlockFile {
+		let mut f = tempfile::tempfile().unwrap();
+		let data = (0..10000u32).map(|i| i as u8).collect::<Vec<_>>();
+		f.write_all(&data).unwrap();
+		BlockFile::new(f, bs)
+	}
+
+	#[test]
+	fn unaligned() {
+		let bf = harness(4096);
+		let mut buf = [0u8; 3];
+		bf.read_at(4095, &mut buf).unwrap();
+		assert_eq!(buf, [4095u32 as u8, 4096u32 as u8, 4097u32 as u8]);
+	}
+
+	/// The last block of the file is incomplete.
+	#[test]
+	fn tail() {
+		let bf = harness(4096);
+		let mut buf = [0u8; 2];
+		bf.read_at(9998, &mut buf).unwrap();
+		assert_eq!(buf, [9998u32 as u8, 9999u32 as u8]);
+
+		let e = bf.read_at(9999, &mut buf).unwrap_err();
+		assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+	}
+
+	/// Unaligned writes keep the rest of the sectors, which they touch.
+	#[test]
+	fn unaligned_write() {
+		let bf = harness(512);
+		bf.write_at(1, &[0xffu8; 512]).unwrap();
+		let mut buf = [0u8; 1024];
+		bf.read_at(0, &mut buf).unwrap();
+		assert_eq!(buf[0], 0);
+		assert!(buf[1..513].iter().all(|&b| b == 0xff));
+		assert!((513..1024).all(|i| buf[i] == i as u8));
+
+		bf.write_at(600, &[0xee; 10]).unwrap();
+		bf.read_at(512, &mut buf[..512]).unwrap();
+		assert_eq!(buf[0], 0xff);
+		assert!(buf[88..98].iter().all(|&b| b == 0xee));
+		assert_eq!(buf[98], 610u32 as u8);
+
+		// Beyond the end of the file.
+		bf.write_at(9999, &[1, 2]).unwrap();
+		assert_eq!(bf.size().unwrap(), Some(10240));
+		bf.read_at(9998, &mut buf[..4]).unwrap();
+		assert_eq!(buf[..4], [9998u32 as u8, 1, 2, 0]);
+	}
+
+	/// Aligned writes wait for unaligned ones, so that they aren't undone by a stale sector.
+	#[test]
+	fn concurrent_write() {
+		let bf = harness(512);
+		let rmw = bf.rmw.write().unwrap();
+		std::thread::scope(|s| {
+			let t = s.spawn(|| bf.write_at(512, &[0xaa; 512]).unwrap());
+			std::thread::sleep(std::time::Duration::from_millis(50));
+			let mut buf = [0u8; 512];
+			bf.file.read_exact_at(&mut buf, 512).unwrap();
+			assert_eq!(buf[0], 512u32 as u8);
+			drop(rmw);
+			t.join().unwrap();
+		});
+		let mut buf = [0u8; 512];
+		bf.read_at(512, &mut buf).unwrap();
+		assert_eq!(buf, [0xaa; 512]);
+	}
+
+	#[test]
+	fn overflow() {
+		let bf = harness(512);
+		let mut buf = [0u8; 4];
+		let e = bf.read_at(u64::MAX - 1, &mut buf).unwrap_err();
+		assert_eq!(e.raw_os_error(), Some(libc::EINVAL));
+		let e = bf.write_at(u64::MAX - 1, &buf).unwrap_err();
+		assert_eq!(e.raw_os_error(), Some(libc::EINVAL));
+	}
+
+	/// Writes through a window with an unaligned offset.
+	#[test]
+	fn windowed_write() {
+		let wb = WindowedBackend::new(harness(512), 100, None);
+		wb.write_at(512, &[0xaa; 512]).unwrap();
+		let mut buf = [0u8; 514];
+		wb.read_at(511, &mut buf).unwrap();
+		assert_eq!(buf[0], 611u32 as u8);
+		assert!(buf[1..513].iter().all(|&b| b == 0xaa));
+		assert_eq!(buf[513], 1124u32 as u8);
+	}
+
+	/// Reads are extended to the I/O size, but writes only to the sector size.
+	#[test]
+	fn io_size() {
+		let bf = harness(512);
+		bf.set_io_size(1000);
+		assert_eq!(bf.blksize(), 512);
+		bf.set_io_size(4096);
+		assert_eq!(bf.blksize(), 4096);
+
+		let mut buf = [0u8; 3];
+		bf.read_at(8191, &mut buf).unwrap();
+		assert_eq!(buf, [8191u32 as u8, 8192u32 as u8, 8193u32 as u8]);
+		bf.write_at(512, &[0u8; 512]).unwrap();
+
+		// Files don't need to be aligned at all.
+		let bf = BlockFile::from_file(tempfile::tempfile().unwrap()).unwrap();
+		assert_eq!(bf.blksize(), 1);
+		bf.write_at(1, &[1u8; 3]).unwrap();
+		bf.set_io_size(4096);
+		bf.read_at(2, &mut buf[..2]).unwrap();
+		assert_eq!(buf[..2], [1, 1]);
+	}
+
+	#[test]
+	fn seek_backend() {
+		let sb = SeekBackend::new(Cursor::new(vec![1u8, 2, 3, 4]));
+		let mut buf = [0u8; 2];
+		sb.read_at(2, &mut buf).unwrap();
+		assert_eq!(buf, [3, 4]);
+		assert!(sb.read_at(3, &mut buf).is_err());
+		let e = sb.write_at(0, &buf).unwrap_err();
+		assert_eq!(e.raw_os_error(), Some(libc::EROFS));
+		assert_eq!(sb.size().unwrap(), Some(4));
+	}
+
+	#[test]
+	fn windowed() {
+		let wb = WindowedBackend::new(harness(512), 1000, Some(100));
+		let mut buf = [0u8; 2];
+		wb.read_at(98, &mut buf).unwrap();
+		assert_eq!(buf, [1098u32 as u8, 1099u32 as u8]);
+		let e = wb.read_at(99, &mut buf).unwrap_err();
+		assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+		assert_eq!(wb.size().unwrap(), Some(100));
+
+		let wb = WindowedBackend::new(harness(512), 9000, None);
+		assert_eq!(wb.size().unwrap(), Some(1000));
+	}
+
+	#[test]
+	fn discard() {
+		let bf = harness(512);
+		match bf.discard(4096, 4096) {
+			Err(e) if e.raw_os_error() == Some(libc::EOPNOTSUPP) => return,
+			res => res.unwrap(),
+		}
+		let mut buf = [0xffu8; 4098];
+		bf.read_at(4095, &mut buf).unwrap();
+		assert_eq!(buf[0], 4095u32 as u8);
+		assert!(buf[1..4097].iter().all(|&b| b == 0));
+		assert_eq!(buf[4097], 8192u32 as u8);
+		assert_eq!(bf.size().unwrap(), Some(10000));
+	}
+
+	#[test]
+	fn size() {
+		assert_eq!(harness(4096).size().unwrap(), Some(10000));
+	}
+}
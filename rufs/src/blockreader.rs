@@ -1,10 +1,14 @@
 use std::{
 	fs::File,
 	io::{self, BufRead, Read, Result as IoResult, Seek, SeekFrom},
-	os::unix::fs::MetadataExt,
 	path::Path,
 };
 
+use crate::backend::{lock_file, sector_size};
+
+/// Size of the blocks, which are read from files, the default fragment size of newfs(8).
+const DEFAULT_BLKSIZE: usize = 4096;
+
 /// Block-level Abstraction Layer.
 ///
 /// `BlockReader` maps random access reads onto block operations.
@@ -15,15 +19,31 @@ pub struct BlockReader<T: Read + Seek> {
 }
 
 impl BlockReader<File> {
+	/// Open the file or device at `path`, read-only.
+	///
+	/// Devices are read in blocks of their sector size, and files in blocks of 4K,
+	/// use [`BlockReader::new()`] for other sizes.
+	///
+	/// A shared lock is taken, which fails with `EWOULDBLOCK`, if another process
+	/// is modifying the file, see [`BlockFile::lock()`](crate::BlockFile::lock).
+	/// Files, which can't be locked at all, are opened anyway.
 	pub fn open(path: &Path) -> IoResult<Self> {
 		let file = File::options().read(true).write(false).open(path)?;
-		let bs = file.metadata()?.blksize() as usize;
+		match lock_file(&file, false) {
+			Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Err(e),
+			Err(e) => log::debug!("BlockReader::open({path:?}): failed to lock: {e}"),
+			Ok(()) => {}
+		}
+		// The `st_blksize` of files may be huge, like the 128K records of ZFS.
+		let bs = sector_size(&file)?.map_or(DEFAULT_BLKSIZE, |ss| ss as usize);
 		Ok(BlockReader::new(file, bs))
 	}
 }
 
 impl<T: Read + Seek> BlockReader<T> {
 	pub fn new(inner: T, bs: usize) -> Self {
+		// Some filesystems report an `st_blksize` of 0.
+		let bs = bs.max(1);
 		let block = vec![0u8; bs];
 		Self {
 			inner,
@@ -79,8 +99,7 @@ impl<T: Read + Seek> BufRead for BlockReader<T> {
 	}
 
 	fn consume(&mut self, amt: usize) {
-		assert!(amt <= self.buffered());
-		self.idx += amt;
+		self.idx += amt.min(self.buffered());
 	}
 }
 
@@ -89,9 +108,14 @@ impl<T: Read + Seek> Seek for BlockReader<T> {
 		let bs = self.blksize() as u64;
 		match pos {
 			SeekFrom::Start(pos) => {
-				let real = self.inner.seek(SeekFrom::Start(pos / bs * bs))?;
+				let start = pos / bs * bs;
+				let real = self.inner.seek(SeekFrom::Start(start))?;
+				if real != start {
+					return Err(io::Error::other(format!(
+						"seeking to {start} ended up at {real}"
+					)));
+				}
 				let rem = pos - real;
-				assert!(rem < bs);
 
 				self.refill()?;
 				self.idx = rem as usize;
@@ -112,7 +136,13 @@ impl<T: Read + Seek> Seek for BlockReader<T> {
 					self.seek(SeekFrom::Start((cur as i64 + offset) as u64))
 				}
 			}
-			SeekFrom::End(_) => todo!("SeekFrom::End()"),
+			SeekFrom::End(offset) => {
+				let end = self.inner.seek(SeekFrom::End(0))?;
+				match end.checked_add_signed(offset) {
+					Some(pos) => self.seek(SeekFrom::Start(pos)),
+					None => Err(io::Error::from_raw_os_error(libc::EINVAL)),
+				}
+			}
 		}
 	}
 }
@@ -214,5 +244,18 @@ mod t {
 				br.inner.stream_position().unwrap() + br.idx as u64
 			);
 		}
+
+		/// Seek relative to the end of the file
+		#[test]
+		fn end() {
+			let mut br = harness();
+			assert_eq!(br.seek(SeekFrom::End(-1)).unwrap(), FSIZE - 1);
+			let mut buf = [1u8; 4];
+			assert_eq!(br.read(&mut buf).unwrap(), 1);
+			assert_eq!(buf[0], 0);
+
+			let e = br.seek(SeekFrom::End(-2 * FSIZE as i64)).unwrap_err();
+			assert_eq!(libc::EINVAL, e.raw_os_error().unwrap());
+		}
 	}
 }
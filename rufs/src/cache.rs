@@ -0,0 +1,865 @@
+use std::{
+	collections::{BTreeMap, HashMap},
+	io::{ErrorKind, Result as IoResult},
+	ops::Range,
+	sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+};
+
+use crate::Backend;
+
+/// Statistics of a [`BlockCache`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CacheStats {
+	/// Maximum number of bytes, that can be cached.
+	pub capacity: u64,
+
+	/// Number of bytes, that are currently cached.
+	pub cached: u64,
+
+	/// Number of bytes, that were modified, but not yet written back.
+	pub dirty: u64,
+
+	/// Number of block lookups, that were served from the cache.
+	pub hits: u64,
+
+	/// Number of block lookups, that had to go to the backend.
+	pub misses: u64,
+
+	/// Number of blocks, that were evicted to make room for others.
+	pub evictions: u64,
+
+	/// Number of dirty blocks, that were written back.
+	pub writebacks: u64,
+
+	/// Number of blocks, that were read ahead using [`Backend::prefetch()`].
+	pub prefetched: u64,
+}
+
+struct Entry {
+	data:  Box<[u8]>,
+	dirty: bool,
+	tick:  u64,
+}
+
+/// A dirty block, which is being written back.
+#[derive(Clone)]
+struct Pending {
+	data: Arc<[u8]>,
+	/// Newer versions of the block have a higher generation.
+	gen:  u64,
+}
+
+struct Inner {
+	blocks:  HashMap<u64, Entry>,
+	/// Least recently used blocks first.
+	lru:     BTreeMap<u64, u64>,
+	tick:    u64,
+	stats:   CacheStats,
+	/// Blocks, which are being written back, without holding the lock.
+	writing: HashMap<u64, Pending>,
+	/// Number of completed writes to the backend, so that reads, which overlapped
+	/// with one, can tell that their data may be stale.
+	written: u64,
+}
+
+/// A write-back block cache, in front of another [`Backend`].
+///
+/// Reads are served from the cache, if possible, and least recently used blocks are
+/// evicted, once the capacity is exceeded.
+/// Writes only modify the cache, and are written back to the backend,
+/// when a block is evicted, or when [`Backend::sync()`] is called.
+/// The cache isn't locked, while the backend is accessed, so threads don't wait for each other's I/O.
+///
+/// Which blocks are evicted only depends on the sequence of accesses, so the cache behaves
+/// deterministically, even with tiny capacities, like the fuzzer uses.
+///
+/// # Example
+/// ```
+/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+/// # use support::*;
+/// use std::io::Cursor;
+///
+/// use rufs::{BlockCache, InodeNum, SeekBackend, Ufs};
+///
+/// let backend = SeekBackend::new(Cursor::new(golden_image("ufs-little")));
+/// let ufs = Ufs::new(BlockCache::new(backend, 1 << 20))?;
+/// for _ in 0..2 {
+///     ufs.inode_attr(InodeNum::ROOT)?;
+/// }
+/// assert!(ufs.cache_stats().hits > 0);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct BlockCache<B: Backend> {
+	inner: B,
+	bs:    u64,
+	cache: Mutex<Inner>,
+	/// Signalled, whenever a block was written back.
+	done:  Condvar,
+}
+
+impl<B: Backend> BlockCache<B> {
+	/// Default granularity of the cache, which is the smallest fragment size of UFS2.
+	pub const BLOCK_SIZE: u64 = 4096;
+
+	/// Cache up to `capacity` bytes of `inner`.
+	pub fn new(inner: B, capacity: u64) -> Self {
+		Self::with_block_size(inner, capacity, Self::BLOCK_SIZE)
+	}
+
+	/// Cache up to `capacity` bytes of `inner`, in blocks of `bs` bytes, at least 1.
+	pub fn with_block_size(inner: B, capacity: u64, bs: u64) -> Self {
+		let bs = bs.max(1);
+		let inner_cache = Inner {
+			blocks:  HashMap::new(),
+			lru:     BTreeMap::new(),
+			tick:    0,
+			stats:   CacheStats {
+				capacity: capacity / bs * bs,
+				..CacheStats::default()
+			},
+			writing: HashMap::new(),
+			written: 0,
+		};
+		Self {
+			inner,
+			bs,
+			cache: Mutex::new(inner_cache),
+			done: Condvar::new(),
+		}
+	}
+
+	/// Get statistics about the cache.
+	pub fn stats(&self) -> CacheStats {
+		self.lock().stats.clone()
+	}
+
+	/// Get the byte offsets of the cached blocks, the least recently used one first,
+	/// and at most `max` of the most recently used ones.
+	///
+	/// These can be passed to [`BlockCache::prime()`] of a later instance,
+	/// to warm it up with the same blocks.
+	pub fn cached_blocks(&self, max: usize) -> Vec<u64> {
+		let c = self.lock();
+		let skip = c.lru.len().saturating_sub(max);
+		c.lru.values().skip(skip).map(|blk| blk * self.bs).collect()
+	}
+
+	/// Read the blocks at the byte offsets `blocks` into the cache,
+	/// like [`BlockCache::cached_blocks()`] returned them, in that order.
+	///
+	/// Blocks are only read into free space, so that nothing is evicted,
+	/// and the cache isn't locked, while other blocks are read, so this can run in the background.
+	/// Returns the number of blocks, that were read.
+	#[doc(alias("warm", "preload"))]
+	pub fn prime(&self, blocks: &[u64]) -> IoResult<usize> {
+		let mut num = 0;
+		for &pos in blocks {
+			let c = self.lock();
+			if c.blocks.len() >= self.capacity(&c) {
+				break;
+			}
+			let blk = pos / self.bs;
+			if c.blocks.contains_key(&blk) || c.writing.contains_key(&blk) {
+				continue;
+			}
+			let written = c.written;
+			drop(c);
+
+			let mut data = vec![0u8; self.bs as usize].into_boxed_slice();
+			match self.inner.read_at(blk * self.bs, &mut data) {
+				Ok(()) => {}
+				// The filesystem may have shrunk.
+				Err(e) if e.kind() == ErrorKind::UnexpectedEof => continue,
+				Err(e) => return Err(e),
+			}
+			let mut c = self.lock();
+			// Another thread may have read or written the block in the meantime.
+			if c.blocks.contains_key(&blk) ||
+				c.writing.contains_key(&blk) ||
+				c.written != written ||
+				c.blocks.len() >= self.capacity(&c)
+			{
+				continue;
+			}
+			self.insert(&mut c, blk, data, &mut Vec::new());
+			c.stats.prefetched += 1;
+			num += 1;
+		}
+		Ok(num)
+	}
+
+	/// Get the underlying backend.
+	pub fn get_ref(&self) -> &B {
+		&self.inner
+	}
+
+	fn lock(&self) -> MutexGuard<'_, Inner> {
+		// The cache is only modified after I/O was successful, so a poisoned lock is harmless.
+		self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	/// Wait until another thread has written back a block.
+	fn wait<'a>(&self, c: MutexGuard<'a, Inner>) -> MutexGuard<'a, Inner> {
+		self.done.wait(c).unwrap_or_else(PoisonError::into_inner)
+	}
+
+	fn capacity(&self, c: &Inner) -> usize {
+		(c.stats.capacity / self.bs) as usize
+	}
+
+	/// Get block `blk` into the cache, and mark it as the most recently used one.
+	/// If `fill` is false, the caller is going to overwrite the whole block.
+	///
+	/// The backend is read without holding the lock `c`, which is returned with the block cached.
+	/// Dirty blocks, which had to be evicted, are added to `owned`.
+	fn load<'a>(
+		&'a self,
+		mut c: MutexGuard<'a, Inner>,
+		blk: u64,
+		fill: bool,
+		owned: &mut Vec<u64>,
+	) -> IoResult<MutexGuard<'a, Inner>> {
+		if c.blocks.contains_key(&blk) {
+			c.stats.hits += 1;
+			Self::touch(&mut c, blk);
+			return Ok(c);
+		}
+
+		c.stats.misses += 1;
+		let mut data = vec![0u8; self.bs as usize].into_boxed_slice();
+		if let Some(p) = c.writing.get(&blk) {
+			data.copy_from_slice(&p.data);
+		} else if fill {
+			let written = c.written;
+			drop(c);
+			self.inner.read_at(blk * self.bs, &mut data)?;
+			c = self.lock();
+
+			if c.blocks.contains_key(&blk) {
+				// Another thread was faster, and may even have modified the block.
+				Self::touch(&mut c, blk);
+				return Ok(c);
+			} else if let Some(p) = c.writing.get(&blk) {
+				data.copy_from_slice(&p.data);
+			} else if c.written != written {
+				// The block may have been written back during the read, so read it again.
+				// This is rare, so the lock can be held this time.
+				self.inner.read_at(blk * self.bs, &mut data)?;
+			}
+		}
+		self.insert(&mut c, blk, data, owned);
+		Ok(c)
+	}
+
+	/// Mark the cached block `blk` as the most recently used one.
+	fn touch(c: &mut Inner, blk: u64) {
+		c.tick += 1;
+		let tick = c.tick;
+		let e = c.blocks.get_mut(&blk).unwrap();
+		c.lru.remove(&e.tick);
+		c.lru.insert(tick, blk);
+		e.tick = tick;
+	}
+
+	/// Insert block `blk`, which is not yet cached, as the most recently used one.
+	fn insert(&self, c: &mut Inner, blk: u64, data: Box<[u8]>, owned: &mut Vec<u64>) {
+		while c.blocks.len() >= self.capacity(c) {
+			self.evict(c, owned);
+		}
+
+		c.tick += 1;
+		let tick = c.tick;
+		c.lru.insert(tick, blk);
+		c.stats.cached += self.bs;
+		c.blocks.insert(
+			blk,
+			Entry {
+				data,
+				dirty: false,
+				tick,
+			},
+		);
+	}
+
+	/// Evict the least recently used block.
+	/// Dirty blocks are handed over for being written back, and added to `owned`, if necessary.
+	fn evict(&self, c: &mut Inner, owned: &mut Vec<u64>) {
+		let Some((&tick, &blk)) = c.lru.iter().next() else {
+			return;
+		};
+		c.lru.remove(&tick);
+		let e = c.blocks.remove(&blk).unwrap();
+		c.stats.cached -= self.bs;
+		c.stats.evictions += 1;
+		if e.dirty && self.hand_over(c, blk, e.data.into()) {
+			owned.push(blk);
+		}
+	}
+
+	/// Hand the data of the dirty block `blk` over for being written back, and return,
+	/// whether the caller has to write it back, using [`BlockCache::flush()`].
+	/// Otherwise, another thread is writing back an older version, and writes this one afterwards.
+	fn hand_over(&self, c: &mut Inner, blk: u64, data: Arc<[u8]>) -> bool {
+		c.tick += 1;
+		c.stats.dirty -= self.bs;
+		let p = Pending { data, gen: c.tick };
+		c.writing.insert(blk, p).is_none()
+	}
+
+	/// Write back the blocks `blks`, which were handed over to this thread, without holding the lock.
+	/// Blocks, which fail to be written back, become dirty again, so that their data isn't lost.
+	fn flush(&self, blks: Vec<u64>) -> IoResult<()> {
+		let mut res = Ok(());
+		for blk in blks {
+			let r = self.flush_block(blk);
+			res = res.and(r);
+		}
+		res
+	}
+
+	fn flush_block(&self, blk: u64) -> IoResult<()> {
+		let mut c = self.lock();
+		let res = loop {
+			let p = c.writing[&blk].clone();
+			drop(c);
+			let res = self.inner.write_at(blk * self.bs, &p.data);
+			c = self.lock();
+
+			if let Err(e) = res {
+				let p = c.writing.remove(&blk).unwrap();
+				self.redirty(&mut c, blk, p.data);
+				break Err(e);
+			}
+			c.stats.writebacks += 1;
+			c.written += 1;
+			// Newer versions, which were handed over in the meantime, must be written as well.
+			if c.writing[&blk].gen == p.gen {
+				c.writing.remove(&blk);
+				break Ok(());
+			}
+		};
+		drop(c);
+		self.done.notify_all();
+		res
+	}
+
+	/// Mark block `blk` as dirty again, after writing back `data` failed.
+	fn redirty(&self, c: &mut Inner, blk: u64, data: Arc<[u8]>) {
+		if let Some(e) = c.blocks.get_mut(&blk) {
+			// The cached block was copied from `data`, or is even newer.
+			if !e.dirty {
+				e.dirty = true;
+				c.stats.dirty += self.bs;
+			}
+			return;
+		}
+
+		// Rather exceed the capacity for a while, than lose the data.
+		c.stats.dirty += self.bs;
+		c.tick += 1;
+		let tick = c.tick;
+		c.lru.insert(tick, blk);
+		c.stats.cached += self.bs;
+		c.blocks.insert(
+			blk,
+			Entry {
+				data: data[..].into(),
+				dirty: true,
+				tick,
+			},
+		);
+	}
+
+	/// Write back the dirty blocks, for which `wanted` returns true, in ascending order.
+	/// Blocks, which another thread is writing back already, are waited for.
+	fn write_back(&self, wanted: impl Fn(u64) -> bool) -> IoResult<()> {
+		let mut c = self.lock();
+		loop {
+			let mut dirty = c
+				.blocks
+				.iter()
+				.filter(|&(&blk, e)| e.dirty && wanted(blk) && !c.writing.contains_key(&blk))
+				.map(|(&blk, _)| blk)
+				.collect::<Vec<_>>();
+			if dirty.is_empty() {
+				if !c.writing.keys().any(|&blk| wanted(blk)) {
+					return Ok(());
+				}
+				c = self.wait(c);
+				continue;
+			}
+			dirty.sort_unstable();
+
+			for &blk in &dirty {
+				let e = c.blocks.get_mut(&blk).unwrap();
+				e.dirty = false;
+				let data = e.data[..].into();
+				self.hand_over(&mut c, blk, data);
+			}
+			drop(c);
+			self.flush(dirty)?;
+			c = self.lock();
+		}
+	}
+
+	/// Call `f` on each part of the range `pos..(pos + len)`,
+	/// with the block number, the offset within the block, and the offset within the range.
+	fn split(
+		&self,
+		pos: u64,
+		len: usize,
+		mut f: impl FnMut(u64, usize, usize, usize) -> IoResult<()>,
+	) -> IoResult<()> {
+		let bs = self.bs as usize;
+		let mut done = 0;
+		while done < len {
+			let p = pos + done as u64;
+			let blk = p / self.bs;
+			let off = (p % self.bs) as usize;
+			let num = (bs - off).min(len - done);
+			f(blk, off, done, num)?;
+			done += num;
+		}
+		Ok(())
+	}
+}
+
+impl<B: Backend> Backend for BlockCache<B> {
+	fn read_at(&self, pos: u64, buf: &mut [u8]) -> IoResult<()> {
+		let c = self.lock();
+		if self.capacity(&c) == 0 {
+			drop(c);
+			return self.inner.read_at(pos, buf);
+		}
+		drop(c);
+
+		let mut owned = Vec::new();
+		let res = self.split(pos, buf.len(), |blk, off, boff, num| {
+			let dst = &mut buf[boff..(boff + num)];
+			match self.load(self.lock(), blk, true, &mut owned) {
+				Ok(c) => dst.copy_from_slice(&c.blocks[&blk].data[off..(off + num)]),
+				// The last block of an image may be incomplete, so read only this part of it.
+				Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+					self.inner.read_at(blk * self.bs + off as u64, dst)?
+				}
+				Err(e) => return Err(e),
+			}
+			Ok(())
+		});
+		let flushed = self.flush(owned);
+		res.and(flushed)
+	}
+
+	fn write_at(&self, pos: u64, buf: &[u8]) -> IoResult<()> {
+		let c = self.lock();
+		if self.capacity(&c) == 0 {
+			drop(c);
+			return self.inner.write_at(pos, buf);
+		}
+		drop(c);
+
+		let bs = self.bs as usize;
+		let mut owned = Vec::new();
+		let res = self.split(pos, buf.len(), |blk, off, boff, num| {
+			let mut guard = self.load(self.lock(), blk, num != bs, &mut owned)?;
+			let c = &mut *guard;
+			let e = c.blocks.get_mut(&blk).unwrap();
+			e.data[off..(off + num)].copy_from_slice(&buf[boff..(boff + num)]);
+			if !e.dirty {
+				e.dirty = true;
+				c.stats.dirty += self.bs;
+			}
+			Ok(())
+		});
+		let flushed = self.flush(owned);
+		res.and(flushed)
+	}
+
+	fn prefetch(&self, pos: u64, len: usize) -> IoResult<()> {
+		let mut c = self.lock();
+		if self.capacity(&c) == 0 {
+			drop(c);
+			return self.inner.prefetch(pos, len);
+		}
+		let bs = self.bs as usize;
+
+		// Don't let readahead evict more than half of the cache.
+		let max = self.capacity(&c) as u64 / 2;
+		let first = pos / self.bs;
+		let last = (pos + len as u64).div_ceil(self.bs).min(first + max);
+
+		let cached =
+			|c: &Inner, blk: &u64| c.blocks.contains_key(blk) || c.writing.contains_key(blk);
+		let mut owned = Vec::new();
+		let mut blk = first;
+		while blk < last {
+			if cached(&c, &blk) {
+				blk += 1;
+				continue;
+			}
+
+			// Read each run of missing blocks at once, without holding the lock.
+			let end = (blk..last).find(|b| cached(&c, b)).unwrap_or(last);
+			let written = c.written;
+			drop(c);
+			let mut data = vec![0u8; (end - blk) as usize * bs];
+			let res = self.inner.read_at(blk * self.bs, &mut data);
+			if let Err(e) = res {
+				return self.flush(owned).and(Err(e));
+			}
+
+			c = self.lock();
+			// Blocks, which were written back in the meantime, may be stale, and this is only a hint.
+			if c.written == written {
+				for (i, chunk) in data.chunks_exact(bs).enumerate() {
+					let b = blk + i as u64;
+					if !cached(&c, &b) {
+						self.insert(&mut c, b, chunk.into(), &mut owned);
+						c.stats.prefetched += 1;
+					}
+				}
+			}
+			blk = end;
+		}
+		drop(c);
+		self.flush(owned)
+	}
+
+	fn sync(&self) -> IoResult<()> {
+		self.write_back(|_| true)?;
+		self.inner.sync()
+	}
+
+	/// Write back only the dirty blocks, which overlap with `ranges`.
+	fn sync_ranges(&self, ranges: &[Range<u64>]) -> IoResult<()> {
+		let bs = self.bs;
+		self.write_back(|blk| {
+			ranges
+				.iter()
+				.any(|r| r.start < (blk + 1) * bs && blk * bs < r.end)
+		})?;
+		self.inner.sync_ranges(ranges)
+	}
+
+	fn discard(&self, pos: u64, len: u64) -> IoResult<()> {
+		let end = pos.saturating_add(len);
+		let (first, last) = (pos / self.bs, end.div_ceil(self.bs));
+		let mut c = self.lock();
+		// Blocks, which are being written back, must not land after the discard.
+		while c.writing.keys().any(|blk| (first..last).contains(blk)) {
+			c = self.wait(c);
+		}
+
+		let mut cached = c
+			.blocks
+			.keys()
+			.copied()
+			.filter(|blk| (first..last).contains(blk))
+			.collect::<Vec<_>>();
+		cached.sort_unstable();
+
+		let mut owned = Vec::new();
+		for blk in cached {
+			let e = c.blocks.remove(&blk).unwrap();
+			c.lru.remove(&e.tick);
+			c.stats.cached -= self.bs;
+			if !e.dirty {
+				continue;
+			}
+			// Blocks, which are only partially discarded, keep the rest of their data.
+			let whole = blk * self.bs >= pos && (blk + 1) * self.bs <= end;
+			if whole {
+				c.stats.dirty -= self.bs;
+			} else if self.hand_over(&mut c, blk, e.data.into()) {
+				owned.push(blk);
+			}
+		}
+		drop(c);
+
+		self.flush(owned)?;
+		self.inner.discard(pos, len)
+	}
+
+	fn size(&self) -> IoResult<Option<u64>> {
+		self.inner.size()
+	}
+
+	/// The hint is passed on, but the cache keeps its own block size.
+	fn set_io_size(&self, size: u64) {
+		self.inner.set_io_size(size)
+	}
+}
+
+impl<B: Backend> Drop for BlockCache<B> {
+	fn drop(&mut self) {
+		if self.lock().stats.dirty == 0 {
+			return;
+		}
+		if let Err(e) = self.sync() {
+			log::error!("failed to write back the block cache: {e}");
+		}
+	}
+}
+
+#[cfg(test)]
+mod t {
+	use std::{
+		io::{Cursor, Error as IoError},
+		sync::{mpsc, Arc},
+		thread,
+		time::Duration,
+	};
+
+	use super::*;
+	use crate::SeekBackend;
+
+	/// A backend, that records all writes.
+	#[derive(Default)]
+	struct Mem {
+		data:   Mutex<Vec<u8>>,
+		writes: Mutex<Vec<(u64, usize)>>,
+	}
+
+	impl Backend for Arc<Mem> {
+		fn read_at(&self, pos: u64, buf: &mut [u8]) -> IoResult<()> {
+			let data = self.data.lock().unwrap();
+			let pos = pos as usize;
+			let src = data
+				.get(pos..(pos + buf.len()))
+				.ok_or(IoError::from(ErrorKind::UnexpectedEof))?;
+			buf.copy_from_slice(src);
+			Ok(())
+		}
+
+		fn write_at(&self, pos: u64, buf: &[u8]) -> IoResult<()> {
+			let pos = pos as usize;
+			self.data.lock().unwrap()[pos..(pos + buf.len())].copy_from_slice(buf);
+			self.writes.lock().unwrap().push((pos as u64, buf.len()));
+			Ok(())
+		}
+	}
+
+	fn mem(len: usize) -> Arc<Mem> {
+		let m = Mem::default();
+		*m.data.lock().unwrap() = (0..len).map(|i| i as u8).collect();
+		Arc::new(m)
+	}
+
+	#[test]
+	fn hits() {
+		let bc = BlockCache::with_block_size(SeekBackend::new(Cursor::new(vec![7u8; 64])), 32, 16);
+		let mut buf = [0u8; 20];
+		bc.read_at(10, &mut buf).unwrap();
+		bc.read_at(12, &mut buf).unwrap();
+		let st = bc.stats();
+		assert_eq!((st.hits, st.misses, st.cached), (2, 2, 32));
+		assert_eq!(buf, [7u8; 20]);
+	}
+
+	#[test]
+	fn evict() {
+		let bc = BlockCache::with_block_size(mem(64), 32, 16);
+		let mut buf = [0u8; 1];
+		for pos in [0, 16, 32, 0] {
+			bc.read_at(pos, &mut buf).unwrap();
+			assert_eq!(buf[0], pos as u8);
+		}
+		let st = bc.stats();
+		assert_eq!((st.hits, st.misses, st.evictions), (0, 4, 2));
+	}
+
+	#[test]
+	fn write_back() {
+		let m = mem(64);
+		let bc = BlockCache::with_block_size(Arc::clone(&m), 32, 16);
+		bc.write_at(14, &[0xff; 4]).unwrap();
+		assert!(m.writes.lock().unwrap().is_empty());
+		assert_eq!(bc.stats().dirty, 32);
+
+		let mut buf = [0u8; 6];
+		bc.read_at(13, &mut buf).unwrap();
+		assert_eq!(buf, [13, 0xff, 0xff, 0xff, 0xff, 18]);
+
+		bc.sync().unwrap();
+		assert_eq!(*m.writes.lock().unwrap(), [(0, 16), (16, 16)]);
+		assert_eq!(bc.stats().dirty, 0);
+		assert_eq!(m.data.lock().unwrap()[13..19], buf);
+	}
+
+	/// Dirty blocks are written back, when they are evicted.
+	#[test]
+	fn evict_dirty() {
+		let m = mem(64);
+		let bc = BlockCache::with_block_size(Arc::clone(&m), 16, 16);
+		bc.write_at(0, &[0xff; 16]).unwrap();
+		bc.read_at(16, &mut [0u8; 1]).unwrap();
+		assert_eq!(*m.writes.lock().unwrap(), [(0, 16)]);
+		assert_eq!(bc.stats().writebacks, 1);
+	}
+
+	/// Discarded blocks are dropped from the cache, and the rest of partial blocks is kept.
+	#[test]
+	fn discard() {
+		let m = mem(64);
+		let bc = BlockCache::with_block_size(Arc::clone(&m), 64, 16);
+		bc.write_at(8, &[0xff; 24]).unwrap();
+		bc.read_at(48, &mut [0u8; 1]).unwrap();
+		// Mem doesn't support discarding.
+		let e = bc.discard(12, 32).unwrap_err();
+		assert_eq!(e.raw_os_error(), Some(libc::EOPNOTSUPP));
+		assert_eq!(*m.writes.lock().unwrap(), [(0, 16)]);
+		let st = bc.stats();
+		assert_eq!((st.cached, st.dirty), (16, 0));
+	}
+
+	#[test]
+	fn prefetch() {
+		let bc = BlockCache::with_block_size(mem(128), 64, 16);
+		bc.read_at(16, &mut [0u8; 1]).unwrap();
+
+		// Only half of the cache may be used for readahead.
+		bc.prefetch(0, 128).unwrap();
+		let st = bc.stats();
+		assert_eq!((st.misses, st.prefetched, st.cached), (1, 1, 32));
+
+		let mut buf = [0u8; 2];
+		bc.read_at(15, &mut buf).unwrap();
+		assert_eq!(buf, [15, 16]);
+		let st = bc.stats();
+		assert_eq!((st.hits, st.misses), (2, 1));
+	}
+
+	/// A new cache is warmed up with the blocks of another one, in the same order.
+	#[test]
+	fn prime() {
+		let old = BlockCache::with_block_size(mem(128), 64, 16);
+		for pos in [32, 0, 112, 16] {
+			old.read_at(pos, &mut [0u8; 1]).unwrap();
+		}
+		assert_eq!(old.cached_blocks(usize::MAX), [32, 0, 112, 16]);
+		assert_eq!(old.cached_blocks(2), [112, 16]);
+
+		let new = BlockCache::with_block_size(mem(128), 48, 16);
+		new.read_at(0, &mut [0u8; 1]).unwrap();
+		// Cached blocks are skipped, and nothing is evicted, once the cache is full.
+		assert_eq!(new.prime(&old.cached_blocks(usize::MAX)).unwrap(), 2);
+		assert_eq!(new.cached_blocks(usize::MAX), [0, 32, 112]);
+		assert_eq!(new.stats().prefetched, 2);
+
+		let mut buf = [0u8; 1];
+		new.read_at(113, &mut buf).unwrap();
+		assert_eq!(buf, [113]);
+		assert_eq!(new.stats().misses, 1);
+		// Blocks beyond the end are ignored.
+		let new = BlockCache::with_block_size(mem(128), 64, 16);
+		assert_eq!(new.prime(&[1024, 16]).unwrap(), 1);
+	}
+
+	/// Only the incomplete last block is read from the backend, the others come from the cache.
+	#[test]
+	fn eof() {
+		let m = mem(40);
+		let bc = BlockCache::with_block_size(Arc::clone(&m), 64, 16);
+		bc.write_at(0, &[0xff; 16]).unwrap();
+		let mut buf = [0u8; 28];
+		bc.read_at(8, &mut buf).unwrap();
+		assert_eq!(buf[..8], [0xff; 8]);
+		assert_eq!(buf[8..], (16..36).map(|i| i as u8).collect::<Vec<_>>());
+		assert!(m.writes.lock().unwrap().is_empty());
+	}
+
+	/// A backend, whose reads of the second block wait, until they are allowed to continue.
+	struct Slow {
+		mem:     Arc<Mem>,
+		entered: Mutex<mpsc::Sender<()>>,
+		go:      Mutex<mpsc::Receiver<()>>,
+	}
+
+	impl Backend for Slow {
+		fn read_at(&self, pos: u64, buf: &mut [u8]) -> IoResult<()> {
+			if pos == 16 {
+				self.entered.lock().unwrap().send(()).unwrap();
+				self.go
+					.lock()
+					.unwrap()
+					.recv_timeout(Duration::from_secs(10))
+					.map_err(|_| IoError::from(ErrorKind::TimedOut))?;
+			}
+			self.mem.read_at(pos, buf)
+		}
+
+		fn write_at(&self, pos: u64, buf: &[u8]) -> IoResult<()> {
+			self.mem.write_at(pos, buf)
+		}
+	}
+
+	/// Other threads can use the cache, while a block is read from the backend.
+	#[test]
+	fn unlocked() {
+		let (entered, rx_entered) = mpsc::channel();
+		let (go, rx_go) = mpsc::channel();
+		let slow = Slow {
+			mem:     mem(64),
+			entered: Mutex::new(entered),
+			go:      Mutex::new(rx_go),
+		};
+		let bc = BlockCache::with_block_size(slow, 64, 16);
+		bc.read_at(0, &mut [0u8; 1]).unwrap();
+
+		thread::scope(|s| {
+			let t = s.spawn(|| {
+				let mut buf = [0u8; 1];
+				bc.read_at(16, &mut buf).map(|()| buf[0])
+			});
+			rx_entered.recv().unwrap();
+			let mut buf = [0u8; 1];
+			bc.read_at(1, &mut buf).unwrap();
+			bc.write_at(32, &[0xff]).unwrap();
+			assert_eq!(buf, [1]);
+			go.send(()).unwrap();
+			assert_eq!(t.join().unwrap().unwrap(), 16);
+		});
+	}
+
+	/// Threads, which modify the same blocks, and evict each other's blocks, don't lose data.
+	#[test]
+	fn threads() {
+		let m = mem(128);
+		let bc = BlockCache::with_block_size(Arc::clone(&m), 32, 16);
+		thread::scope(|s| {
+			for t in 0..4 {
+				let bc = &bc;
+				s.spawn(move || {
+					for i in 0..100u8 {
+						for blk in 0..8 {
+							let pos = blk * 16 + t;
+							bc.write_at(pos, &[i]).unwrap();
+							let mut buf = [0u8; 1];
+							bc.read_at(pos, &mut buf).unwrap();
+							assert_eq!(buf, [i]);
+						}
+					}
+				});
+			}
+		});
+		bc.sync().unwrap();
+		assert_eq!(bc.stats().dirty, 0);
+		let data = m.data.lock().unwrap();
+		for (blk, chunk) in data.chunks(16).enumerate() {
+			assert_eq!(chunk[..4], [99; 4]);
+			let rest = (blk * 16 + 4..(blk + 1) * 16).map(|i| i as u8);
+			assert_eq!(chunk[4..], rest.collect::<Vec<_>>());
+		}
+	}
+
+	#[test]
+	fn disabled() {
+		let m = mem(64);
+		let bc = BlockCache::new(Arc::clone(&m), 0);
+		bc.write_at(1, &[0xff]).unwrap();
+		assert_eq!(*m.writes.lock().unwrap(), [(1, 1)]);
+		bc.read_at(0, &mut [0u8; 2]).unwrap();
+		assert_eq!(bc.stats(), CacheStats::default());
+	}
+}
@@ -0,0 +1,128 @@
+//! Errors, which tell why an operation failed, not only the `errno`.
+use std::{
+	error::Error as StdError,
+	fmt::{self, Display, Formatter},
+	io::{Error as IoError, ErrorKind},
+};
+
+/// Why an operation failed.
+///
+/// For compatibility with [`std::io`], all functions of rufs return an [`std::io::Error`].
+/// Failures, which carry more information than an `errno`, like corrupted metadata,
+/// are returned as an `std::io::Error` wrapping an `Error`, which can be recovered
+/// with [`Error::of()`], or by converting the `std::io::Error` into an `Error`.
+///
+/// The FUSE layers reply with [`Error::errno()`], see [`errno()`].
+///
+/// # Example
+/// ```
+/// use std::io::Error as IoError;
+///
+/// use rufs::Error;
+///
+/// let e = IoError::from(Error::Corrupt { what: "bad".into(), offset: Some(8192) });
+/// assert_eq!(rufs::errno(&e), libc::EIO);
+/// assert_eq!(e.to_string(), "filesystem corrupted at offset 8192: bad");
+/// assert!(matches!(Error::of(&e), Some(Error::Corrupt { offset: Some(8192), .. })));
+/// ```
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+	/// The metadata of the filesystem is corrupted.
+	Corrupt {
+		/// What is wrong.
+		what:   String,
+		/// Byte offset of the corrupted structure on the device, if it is known.
+		offset: Option<u64>,
+	},
+	/// The filesystem uses a feature, which isn't supported.
+	Unsupported {
+		/// Name of the feature.
+		feature: &'static str,
+	},
+	/// The filesystem, or the device, is read-only (`EROFS`).
+	ReadOnly,
+	/// The file doesn't exist (`ENOENT`).
+	NotFound,
+	/// Any other failure, like one of the device.
+	Io(IoError),
+}
+
+impl Error {
+	/// Get the `Error` wrapped by `e`, if any.
+	///
+	/// Unlike converting it into an `Error`, this doesn't recognize plain `errno`s.
+	pub fn of(e: &IoError) -> Option<&Self> {
+		e.get_ref()?.downcast_ref()
+	}
+
+	/// The `errno`, which describes this error best, `EIO` if there is none.
+	pub fn errno(&self) -> i32 {
+		match self {
+			Self::Corrupt { .. } => libc::EIO,
+			Self::Unsupported { .. } => libc::EOPNOTSUPP,
+			Self::ReadOnly => libc::EROFS,
+			Self::NotFound => libc::ENOENT,
+			Self::Io(e) => errno(e),
+		}
+	}
+}
+
+/// The `errno` of `e`, which may wrap an [`Error`], `EIO` if there is none.
+pub fn errno(e: &IoError) -> i32 {
+	e.raw_os_error()
+		.or_else(|| Error::of(e).map(Error::errno))
+		.unwrap_or(libc::EIO)
+}
+
+impl Display for Error {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		match self {
+			Self::Corrupt {
+				what,
+				offset: Some(offset),
+			} => write!(f, "filesystem corrupted at offset {offset}: {what}"),
+			Self::Corrupt { what, offset: None } => write!(f, "filesystem corrupted: {what}"),
+			Self::Unsupported { feature } => write!(f, "{feature} is not supported"),
+			Self::ReadOnly => f.write_str("read-only filesystem"),
+			Self::NotFound => f.write_str("no such file or directory"),
+			Self::Io(e) => e.fmt(f),
+		}
+	}
+}
+
+impl StdError for Error {
+	fn source(&self) -> Option<&(dyn StdError + 'static)> {
+		match self {
+			Self::Io(e) => Some(e),
+			_ => None,
+		}
+	}
+}
+
+impl From<IoError> for Error {
+	fn from(e: IoError) -> Self {
+		if Self::of(&e).is_some() {
+			let inner = e.into_inner().unwrap();
+			return *inner.downcast().unwrap();
+		}
+		match e.raw_os_error() {
+			Some(libc::EROFS) => Self::ReadOnly,
+			Some(libc::ENOENT) => Self::NotFound,
+			_ => Self::Io(e),
+		}
+	}
+}
+
+impl From<Error> for IoError {
+	fn from(e: Error) -> Self {
+		match e {
+			Error::Io(e) => e,
+			// These carry nothing, but their errno.
+			Error::ReadOnly | Error::NotFound => Self::from_raw_os_error(e.errno()),
+			// Not `InvalidData`, which tells, that the device doesn't contain a filesystem.
+			Error::Corrupt { .. } => Self::other(e),
+			Error::Unsupported { .. } => Self::new(ErrorKind::Unsupported, e),
+		}
+	}
+}
@@ -1,44 +1,29 @@
-use std::time::{Duration, SystemTime};
+use std::io::{Error as IoError, Result as IoResult};
 
-use bincode::{de::Decoder, error::DecodeError, Decode};
+use crate::{data::*, err};
 
-use crate::data::*;
+/// Driver-level extensions of the on-disk inode.
+pub(crate) trait InodeExt {
+	/// Type of the inode.
+	///
+	/// Fails with `EINVAL`, if the inode has a type, that can't be represented, like a whiteout.
+	fn kind(&self) -> IoResult<InodeType>;
 
-fn timetosys(mut s: UfsTime, ns: u32) -> SystemTime {
-	let neg = s < 0;
-	if neg {
-		s = -s;
-	}
-	let dur = Duration::new(s as u64, ns);
-	let mut time = SystemTime::UNIX_EPOCH;
-	if neg {
-		time -= dur;
-	} else {
-		time += dur;
-	}
-	time
-}
-
-impl Inode {
-	pub fn atime(&self) -> SystemTime {
-		timetosys(self.atime, self.atimensec)
-	}
-
-	pub fn mtime(&self) -> SystemTime {
-		timetosys(self.mtime, self.mtimensec)
-	}
+	/// Whether this is a snapshot of the filesystem.
+	fn is_snapshot(&self) -> bool;
 
-	pub fn ctime(&self) -> SystemTime {
-		timetosys(self.ctime, self.ctimensec)
-	}
+	/// Whether the inode can have data; devices, FIFOs and sockets don't,
+	/// and devices store their number in place of the first block pointer.
+	fn has_data(&self) -> bool;
 
-	pub fn btime(&self) -> SystemTime {
-		timetosys(self.birthtime, self.birthnsec)
-	}
+	/// Convert into the public metadata representation.
+	fn as_attr(&self, inr: InodeNum) -> IoResult<InodeAttr>;
+}
 
-	pub fn kind(&self) -> InodeType {
+impl InodeExt for Inode {
+	fn kind(&self) -> IoResult<InodeType> {
 		let mode = self.mode & S_IFMT;
-		match mode {
+		let kind = match mode {
 			S_IFIFO => InodeType::NamedPipe,
 			S_IFCHR => InodeType::CharDevice,
 			S_IFDIR => InodeType::Directory,
@@ -46,15 +31,36 @@ impl Inode {
 			S_IFREG => InodeType::RegularFile,
 			S_IFLNK => InodeType::Symlink,
 			S_IFSOCK => InodeType::Socket,
-			_ => unreachable!("invalid file mode: {mode:o}"),
-		}
+			S_IFWHT => {
+				log::warn!("whiteout inode: {:o}", self.mode);
+				return Err(err!(EINVAL));
+			}
+			_ => {
+				log::warn!("invalid file mode: {:o}", self.mode);
+				return Err(err!(EINVAL));
+			}
+		};
+		Ok(kind)
+	}
+
+	fn is_snapshot(&self) -> bool {
+		self.flags & SF_SNAPSHOT != 0
+	}
+
+	fn has_data(&self) -> bool {
+		matches!(self.mode & S_IFMT, S_IFREG | S_IFDIR | S_IFLNK)
 	}
 
-	pub fn as_attr(&self, inr: InodeNum) -> InodeAttr {
-		InodeAttr {
+	fn as_attr(&self, inr: InodeNum) -> IoResult<InodeAttr> {
+		// Snapshots can never be written to.
+		let perm = match self.is_snapshot() {
+			true => self.mode & 0o7555,
+			false => self.mode & 0o7777,
+		};
+		Ok(InodeAttr {
 			inr,
-			perm: self.mode & 0o7777,
-			kind: self.kind(),
+			perm,
+			kind: self.kind()?,
 			size: self.size,
 			blocks: self.blocks,
 			atime: self.atime(),
@@ -69,110 +75,55 @@ impl Inode {
 			flags: self.flags,
 			kernflags: self.kernflags,
 			extsize: self.extsize,
-		}
-	}
-
-	pub fn size(&self, bs: u64, fs: u64) -> (u64, u64) {
-		let size = match self.kind() {
-			InodeType::Directory => self.blocks * fs,
-			InodeType::RegularFile | InodeType::Symlink => self.size,
-			kind => todo!("Inode::size() is undefined for {kind:?}"),
-		};
-		Self::inode_size(bs, fs, size)
-	}
-
-	/// The number of blocks and fragments this inode needs.
-	fn inode_size(bs: u64, fs: u64, size: u64) -> (u64, u64) {
-		let blocks = size / bs;
-		let frags = (size % bs).div_ceil(fs);
-
-		(blocks, frags)
+			rdev: self.rdev(),
+		})
 	}
 }
 
-impl Decode for Inode {
-	fn decode<D: Decoder>(d: &mut D) -> Result<Self, DecodeError> {
-		let mode = u16::decode(d)?;
-		let nlink = u16::decode(d)?;
-		let uid = u32::decode(d)?;
-		let gid = u32::decode(d)?;
-		let blksize = u32::decode(d)?;
-		let size = u64::decode(d)?;
-		let blocks = u64::decode(d)?;
-		let atime = UfsTime::decode(d)?;
-		let mtime = UfsTime::decode(d)?;
-		let ctime = UfsTime::decode(d)?;
-		let birthtime = UfsTime::decode(d)?;
-		let mtimensec = u32::decode(d)?;
-		let atimensec = u32::decode(d)?;
-		let ctimensec = u32::decode(d)?;
-		let birthnsec = u32::decode(d)?;
-		let gen = u32::decode(d)?;
-		let kernflags = u32::decode(d)?;
-		let flags = u32::decode(d)?;
-		let extsize = u32::decode(d)?;
-		let extb = <[UfsDaddr; UFS_NXADDR]>::decode(d)?;
-		let data = if (mode & S_IFMT) == S_IFLNK && blocks == 0 {
-			InodeData::Shortlink(Decode::decode(d)?)
-		} else {
-			InodeData::Blocks(InodeBlocks::decode(d)?)
-		};
-
-		let ino = Self {
-			mode,
-			nlink,
-			uid,
-			gid,
-			blksize,
-			size,
-			blocks,
-			atime,
-			mtime,
-			ctime,
-			birthtime,
-			mtimensec,
-			atimensec,
-			ctimensec,
-			birthnsec,
-			gen,
-			kernflags,
-			flags,
-			extsize,
-			extb,
-			data,
-			modrev: u64::decode(d)?,
-			ignored: u32::decode(d)?,
-			ckhash: u32::decode(d)?,
-			spare: <[u32; 2]>::decode(d)?,
-		};
-
-		Ok(ino)
+/// The device number, as FUSE passes it to the kernel.
+#[cfg(any(feature = "fuser", feature = "fuse2rs"))]
+fn fuse_rdev(a: &InodeAttr) -> u32 {
+	// Linux decodes it using new_decode_dev().
+	#[cfg(target_os = "linux")]
+	{
+		let (major, minor) = (a.major(), a.minor());
+		(minor & 0xff) | (major << 8) | ((minor & !0xff) << 12)
 	}
-}
-
-mod test {
-	#[test]
-	fn inode_size() {
-		let bs = 32768;
-		let fs = 4096;
-
-		let isz = |sz| super::Inode::inode_size(bs, fs, sz);
-
-		assert_eq!(isz(0), (0, 0));
-		assert_eq!(isz(1), (0, 1));
-		assert_eq!(isz(fs), (0, 1));
-		assert_eq!(isz(bs), (1, 0));
-		assert_eq!(isz(bs + 2 * fs), (1, 2));
-		assert_eq!(isz(100 * bs + 7 * fs), (100, 7));
+	// FreeBSD uses the lower 32 bits as dev_t, which is enough for small major numbers.
+	#[cfg(not(target_os = "linux"))]
+	{
+		a.rdev as u32
 	}
 }
 
 #[cfg(feature = "fuser")]
 mod f {
+	use std::time::{Duration, SystemTime};
+
 	use fuser::{FileAttr, FileType};
 
 	use super::*;
 
+	/// fuser turns times before the epoch into the seconds and nanoseconds before it,
+	/// instead of a normalized `struct timespec`, whose nanoseconds count forward.
+	/// Move those times, so that the kernel gets the right timestamp.
+	fn time(t: SystemTime) -> SystemTime {
+		let Err(e) = t.duration_since(SystemTime::UNIX_EPOCH) else {
+			return t;
+		};
+		let d = e.duration();
+		match d.subsec_nanos() {
+			0 => t,
+			ns => {
+				let secs = d.as_secs() + 1;
+				let ns = 1_000_000_000 - ns;
+				SystemTime::UNIX_EPOCH
+					.checked_sub(Duration::new(secs, ns))
+					.unwrap_or(t)
+			}
+		}
+	}
+
 	impl From<InodeType> for FileType {
 		fn from(t: InodeType) -> Self {
 			match t {
@@ -183,6 +134,8 @@ mod f {
 				InodeType::CharDevice => Self::CharDevice,
 				InodeType::BlockDevice => Self::BlockDevice,
 				InodeType::NamedPipe => Self::NamedPipe,
+				// Like overlayfs on Linux, whiteouts are character devices with the number 0/0.
+				InodeType::Whiteout => Self::CharDevice,
 			}
 		}
 	}
@@ -193,29 +146,56 @@ mod f {
 				ino:     a.inr.get64(),
 				size:    a.size,
 				blocks:  a.blocks,
-				atime:   a.atime,
-				mtime:   a.mtime,
-				ctime:   a.ctime,
-				crtime:  a.btime,
+				atime:   time(a.atime),
+				mtime:   time(a.mtime),
+				ctime:   time(a.ctime),
+				crtime:  time(a.btime),
 				kind:    a.kind.into(),
 				perm:    a.perm,
 				nlink:   a.nlink.into(),
 				uid:     a.uid,
 				gid:     a.gid,
-				rdev:    0,
+				rdev:    fuse_rdev(&a),
 				blksize: a.blksize,
 				flags:   a.flags,
 			}
 		}
 	}
+
+	#[cfg(test)]
+	#[test]
+	fn pre_epoch() {
+		let epoch = SystemTime::UNIX_EPOCH;
+		assert_eq!(time(epoch), epoch);
+		assert_eq!(
+			time(epoch - Duration::from_secs(3)),
+			epoch - Duration::from_secs(3)
+		);
+		// fuser sends -2.5s as -2s + 500000000ns, which is -1.5s.
+		assert_eq!(
+			time(epoch - Duration::from_millis(1500)),
+			epoch - Duration::from_millis(2500)
+		);
+	}
 }
 
 #[cfg(feature = "fuse2rs")]
 mod f2 {
+	use std::time::SystemTime;
+
 	use fuse2rs::{FileAttr, FileType};
 
 	use super::*;
 
+	/// fuse2rs can't represent times before the epoch, and would panic.
+	fn time(t: SystemTime) -> SystemTime {
+		if t < SystemTime::UNIX_EPOCH {
+			log::debug!("clamping {t:?} to the epoch");
+			return SystemTime::UNIX_EPOCH;
+		}
+		t
+	}
+
 	impl From<InodeType> for FileType {
 		fn from(t: InodeType) -> Self {
 			match t {
@@ -226,6 +206,7 @@ mod f2 {
 				InodeType::CharDevice => Self::CharDevice,
 				InodeType::BlockDevice => Self::BlockDevice,
 				InodeType::NamedPipe => Self::NamedPipe,
+				InodeType::Whiteout => Self::CharDevice,
 			}
 		}
 	}
@@ -236,16 +217,16 @@ mod f2 {
 				ino:     a.inr.get64(),
 				size:    a.size,
 				blocks:  a.blocks,
-				atime:   a.atime,
-				mtime:   a.mtime,
-				ctime:   a.ctime,
-				btime:   a.btime,
+				atime:   time(a.atime),
+				mtime:   time(a.mtime),
+				ctime:   time(a.ctime),
+				btime:   time(a.btime),
 				kind:    a.kind.into(),
 				perm:    a.perm,
 				nlink:   a.nlink.into(),
 				uid:     a.uid,
 				gid:     a.gid,
-				rdev:    0,
+				rdev:    fuse_rdev(&a),
 				blksize: a.blksize,
 				flags:   a.flags,
 			}
@@ -1,13 +1,106 @@
 #![cfg_attr(fuzzing, allow(dead_code, unused_imports, unused_mut))]
 
+mod backend;
 mod blockreader;
+mod cache;
 mod data;
-mod decoder;
+mod error;
 mod inode;
+#[cfg(feature = "mkfs")]
+mod mkfs;
+#[cfg(feature = "mmap")]
+mod mmap;
+mod part;
 mod ufs;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod uring;
+#[cfg(feature = "zstd")]
+mod zstd;
 
+#[cfg(feature = "mkfs")]
+pub use crate::mkfs::{mkfs, MkfsOptions};
+#[cfg(feature = "mmap")]
+pub use crate::mmap::MmapBackend;
+#[cfg(feature = "tokio")]
+pub use crate::ufs::{AsyncBackend, AsyncUfs};
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+pub use crate::uring::UringBackend;
+#[cfg(feature = "zstd")]
+pub use crate::zstd::ZstdBackend;
 pub use crate::{
+	backend::{Backend, BlockFile, SeekBackend, WindowedBackend},
 	blockreader::BlockReader,
-	data::{InodeAttr, InodeNum},
-	ufs::{Info, Ufs},
+	cache::{BlockCache, CacheStats},
+	data::{
+		InodeAttr,
+		InodeNum,
+		InodeType,
+		CK_CYLGRP,
+		CK_DIR,
+		CK_INDIR,
+		CK_INODE,
+		CK_SUPERBLOCK,
+		SF_APPEND,
+		SF_ARCHIVED,
+		SF_IMMUTABLE,
+		SF_NOUNLINK,
+		SF_SNAPSHOT,
+		UF_APPEND,
+		UF_IMMUTABLE,
+		UF_NODUMP,
+		UF_NOUNLINK,
+		UF_OPAQUE,
+	},
+	error::{errno, Error},
+	part::{find_ufs_partition, partitions, Partition},
+	ufs::{
+		Acl,
+		AclEntry,
+		AclTag,
+		AtimePolicy,
+		Capabilities,
+		Creator,
+		Credentials,
+		DanglingEntries,
+		DirEntries,
+		DirEntry,
+		DirHashStats,
+		DirRemnant,
+		DirSlack,
+		DurabilityPolicy,
+		Extent,
+		FsckCounts,
+		FsckFinding,
+		IdMap,
+		Info,
+		Inodes,
+		Ioctl,
+		IoctlRequest,
+		Ioctls,
+		Journal,
+		Lint,
+		LintFinding,
+		Options,
+		Quota,
+		Stats,
+		SummaryCheck,
+		SymlinkPolicy,
+		TreeGuard,
+		Txn,
+		Ufs,
+		VolumeInfo,
+		Walk,
+		WalkOptions,
+		WalkOrder,
+		Whence,
+		WriteCaps,
+		BOOTBLOCK_SIZE,
+		FITRIM,
+		FS_IOC32_GETFLAGS,
+		FS_IOC32_SETFLAGS,
+		FS_IOC_FIEMAP,
+		FS_IOC_GETFLAGS,
+		FS_IOC_SETFLAGS,
+		SYMLINK_MAX,
+	},
 };
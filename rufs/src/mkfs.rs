@@ -0,0 +1,698 @@
+use std::{
+	collections::hash_map::RandomState,
+	hash::{BuildHasher, Hasher},
+	io::{Error as IoError, ErrorKind, Result as IoResult},
+	mem::size_of,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{backend::Backend, data::*};
+
+/// Largest size of a single I/O (`MAXPHYS`), which limits the size of clusters.
+const MAXPHYS: u64 = 1 << 20;
+
+/// Largest size of a cluster, that is tracked in the cluster summary (`FS_MAXCONTIG`).
+const FS_MAXCONTIG: u64 = 16;
+
+/// Create at least this many cylinder groups, if the filesystem is large enough.
+const MINCYLGRPS: u64 = 4;
+
+/// Size of `struct cg`, which is followed by the maps.
+const CGHDRSIZE: u64 = 168;
+
+/// Percentage of free blocks, below which time optimization is useless (`MINFREE`).
+const MINFREE: u8 = 8;
+
+/// Optimize block allocation for time or space (`FS_OPTTIME`, `FS_OPTSPACE`).
+const FS_OPTTIME: i32 = 0;
+const FS_OPTSPACE: i32 = 1;
+
+/// Expected average file size, and number of files per directory (`AVFILESIZ`, `AFPDIR`).
+const AVFILESIZ: u32 = 16384;
+const AFPDIR: u32 = 64;
+
+/// Inode number of the root directory, and the ".snap" directory.
+const ROOTINO: u64 = 2;
+const SNAPINO: u64 = 3;
+
+/// Group of the ".snap" directory (`operator`).
+const GID_OPERATOR: u32 = 5;
+
+/// Parameters for creating a new filesystem using [`mkfs()`].
+///
+/// Filesystems created with the same parameters are identical,
+/// so `time` and `seed` can be fixed for reproducible builds.
+#[derive(Debug, Clone)]
+pub struct MkfsOptions {
+	/// Size of the filesystem in bytes.
+	pub size: u64,
+
+	/// Block size.
+	pub bsize: u32,
+
+	/// Fragment size.
+	pub fsize: u32,
+
+	/// Number of bytes of data per inode, defaults to twice the fragment size.
+	pub density: Option<u32>,
+
+	/// Percentage of blocks, that are reserved for the superuser.
+	pub minfree: u8,
+
+	/// Volume name, at most 31 bytes.
+	pub volname: String,
+
+	/// Use the big-endian byte order, instead of little-endian.
+	pub big_endian: bool,
+
+	/// Timestamp of the filesystem and the created directories, in seconds since the epoch.
+	pub time: i64,
+
+	/// Seed of the filesystem id, and the inode generation numbers.
+	pub seed: u64,
+}
+
+impl MkfsOptions {
+	/// Default parameters for a filesystem of `size` bytes,
+	/// using the current time and a random seed.
+	pub fn new(size: u64) -> Self {
+		let time = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map_or(0, |d| d.as_secs() as i64);
+		Self {
+			size,
+			bsize: 32768,
+			fsize: 4096,
+			density: None,
+			minfree: MINFREE,
+			volname: String::new(),
+			big_endian: false,
+			time,
+			seed: RandomState::new().build_hasher().finish(),
+		}
+	}
+}
+
+/// Pseudo-random numbers for the filesystem id and inode generation numbers (splitmix64).
+struct Rng(u64);
+
+impl Rng {
+	fn next(&mut self) -> u32 {
+		self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+		let mut z = self.0;
+		z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+		(z ^ (z >> 31)) as u32
+	}
+}
+
+fn invalid(msg: String) -> IoError {
+	IoError::new(ErrorKind::InvalidInput, msg)
+}
+
+fn roundup(x: u64, y: u64) -> u64 {
+	x.div_ceil(y) * y
+}
+
+fn setbit(map: &mut [u8], i: u64) {
+	map[(i / 8) as usize] |= 1 << (i % 8);
+}
+
+fn clrbit(map: &mut [u8], i: u64) {
+	map[(i / 8) as usize] &= !(1 << (i % 8));
+}
+
+fn isset(map: &[u8], i: u64) -> bool {
+	map[(i / 8) as usize] & (1 << (i % 8)) != 0
+}
+
+/// Offsets of the maps in a cylinder group block, in bytes.
+struct CgLayout {
+	iusedoff:      u64,
+	freeoff:       u64,
+	clustersumoff: u64,
+	clusteroff:    u64,
+	nextfreeoff:   u64,
+}
+
+impl CgLayout {
+	fn new(fpg: u64, ipg: u64, frag: u64, contigsumsize: u64) -> Self {
+		let iusedoff = CGHDRSIZE;
+		let freeoff = iusedoff + ipg.div_ceil(8);
+		// The unused first entry of the cluster summary overlaps the free map.
+		let clustersumoff = roundup(freeoff + fpg.div_ceil(8) - 4, 4);
+		let clusteroff = clustersumoff + (contigsumsize + 1) * 4;
+		let nextfreeoff = clusteroff + (fpg / frag).div_ceil(8);
+		Self {
+			iusedoff,
+			freeoff,
+			clustersumoff,
+			clusteroff,
+			nextfreeoff,
+		}
+	}
+}
+
+/// Layout of a new filesystem. Sizes and addresses are in fragments, unless noted otherwise.
+struct Geometry {
+	bsize:         u64,
+	fsize:         u64,
+	frag:          u64,
+	size:          u64,
+	ncg:           u64,
+	fpg:           u64,
+	ipg:           u64,
+	inopb:         u64,
+	sblkno:        u64,
+	cblkno:        u64,
+	iblkno:        u64,
+	dblkno:        u64,
+	contigsumsize: u64,
+	/// Size of the cylinder group summary area in bytes.
+	cssize:        u64,
+	/// Size of the cylinder group block in bytes.
+	cgsize:        u64,
+	cg:            CgLayout,
+}
+
+impl Geometry {
+	fn new(opts: &MkfsOptions) -> IoResult<Self> {
+		let bsize = opts.bsize as u64;
+		let fsize = opts.fsize as u64;
+		if !bsize.is_power_of_two() ||
+			!fsize.is_power_of_two() ||
+			!(MINBSIZE as u64..=MAXBSIZE as u64).contains(&bsize) ||
+			!(DEV_BSIZE as u64..=bsize).contains(&fsize) ||
+			bsize / fsize > MAXFRAG as u64
+		{
+			return Err(invalid(format!(
+				"unsupported block size {bsize} and fragment size {fsize}"
+			)));
+		}
+		let density = opts.density.unwrap_or(opts.fsize * 2) as u64;
+		if density == 0 || opts.minfree > 99 {
+			return Err(invalid(format!(
+				"invalid density {density} or minfree {}",
+				opts.minfree
+			)));
+		}
+
+		let frag = bsize / fsize;
+		let size = opts.size / fsize;
+		let sblkno = roundup((SBLOCK_UFS2 + SBLOCKSIZE) as u64, bsize) / fsize;
+		let cblkno = sblkno + roundup(SBLOCKSIZE as u64, bsize) / fsize;
+		let iblkno = cblkno + frag;
+		let inopb = bsize / UFS_INOSZ as u64;
+		let inopf = inopb / frag;
+		let contigsumsize = (MAXPHYS / bsize).clamp(1, FS_MAXCONTIG);
+		let too_small = || invalid(format!("filesystem is too small: {} bytes", opts.size));
+
+		// The number of inodes is limited to 2^32.
+		let fragsperinode = (density / fsize).max(1).max(1 + size / ((1 << 32) - inopb));
+		let ipg_for = |fpg: u64| roundup(fpg.div_ceil(fragsperinode), inopb);
+		// Same as `CGSIZE()`, which slightly overestimates the size of the cylinder group.
+		let cgsize_for = |fpg: u64| {
+			CGHDRSIZE +
+				4 + ipg_for(fpg).div_ceil(8) +
+				fpg.div_ceil(8) +
+				4 + contigsumsize * 4 +
+				(fpg / frag).div_ceil(8)
+		};
+
+		// Start with the smallest cylinder group, that satisfies the density.
+		let minfpg = (fragsperinode * inopb).min(size);
+		let mut fpg = roundup(iblkno + inopb / inopf, frag).max(minfpg);
+		fpg = roundup(iblkno + ipg_for(fpg) / inopf, frag).max(minfpg);
+		if cgsize_for(fpg) >= bsize {
+			return Err(invalid(format!("density {density} is too high")));
+		}
+
+		// Grow the cylinder groups, until there would be too few of them,
+		// or they no longer fit into a block.
+		while fpg < size.saturating_sub(1) {
+			if size / fpg < MINCYLGRPS {
+				break;
+			}
+			if cgsize_for(fpg) < bsize {
+				fpg += frag;
+				continue;
+			}
+			if cgsize_for(fpg) > bsize {
+				fpg -= frag;
+			}
+			break;
+		}
+
+		// Move blocks into the last cylinder group, until it is large enough to be useful.
+		let lastminfpg = |fpg: u64| roundup(iblkno + ipg_for(fpg) / inopf, frag);
+		loop {
+			if size < lastminfpg(fpg) || fpg < lastminfpg(fpg) {
+				return Err(too_small());
+			}
+			if size % fpg == 0 || size % fpg >= lastminfpg(fpg) {
+				break;
+			}
+			fpg -= frag;
+		}
+
+		let ipg = ipg_for(fpg);
+		let ncg = size.div_ceil(fpg);
+		let dblkno = iblkno + ipg / inopf;
+		if ncg * ipg > u32::MAX as u64 {
+			return Err(invalid(format!("too many inodes: {}", ncg * ipg)));
+		}
+
+		// The first cylinder group also holds the summary area, and the root directory.
+		let cssize = roundup(ncg * 16, fsize);
+		if dblkno + cssize / fsize + 2 * frag > fpg.min(size) {
+			return Err(too_small());
+		}
+
+		Ok(Self {
+			bsize,
+			fsize,
+			frag,
+			size,
+			ncg,
+			fpg,
+			ipg,
+			inopb,
+			sblkno,
+			cblkno,
+			iblkno,
+			dblkno,
+			contigsumsize,
+			cssize,
+			cg: CgLayout::new(fpg, ipg, frag, contigsumsize),
+			cgsize: roundup(cgsize_for(fpg), fsize),
+		})
+	}
+
+	/// Number of data fragments, that are neither metadata nor boot area.
+	fn dsize(&self) -> u64 {
+		self.size - self.sblkno - self.ncg * (self.dblkno - self.sblkno) - self.cssize / self.fsize
+	}
+
+	/// Number of inodes, that are initialized per cylinder group.
+	fn initediblk(&self) -> u64 {
+		self.ipg.min(2 * self.inopb)
+	}
+}
+
+/// Free counts of a cylinder group, as derived from its maps.
+#[derive(Default)]
+struct Summary {
+	nbfree:     u64,
+	nffree:     u64,
+	nifree:     u64,
+	frsum:      [u32; MAXFRAG],
+	clustermap: Vec<u8>,
+	clustersum: Vec<u32>,
+}
+
+/// A cylinder group, that is being built.
+struct Cg {
+	cgx:   u64,
+	/// Number of fragments in this group.
+	ndblk: u64,
+	iused: Vec<u8>,
+	free:  Vec<u8>,
+	ndir:  u64,
+}
+
+impl Cg {
+	fn new(g: &Geometry, cgx: u64) -> Self {
+		let ndblk = g.fpg.min(g.size - cgx * g.fpg);
+		let mut cg = Self {
+			cgx,
+			ndblk,
+			iused: vec![0u8; g.ipg.div_ceil(8) as usize],
+			free: vec![0u8; g.fpg.div_ceil(8) as usize],
+			ndir: 0,
+		};
+
+		// Only the first group has a boot area in front of the superblock.
+		let mut dupper = g.dblkno;
+		if cgx == 0 {
+			dupper += g.cssize / g.fsize;
+			for inr in 0..ROOTINO {
+				setbit(&mut cg.iused, inr);
+			}
+		} else {
+			for f in 0..g.sblkno {
+				setbit(&mut cg.free, f);
+			}
+		}
+		for f in dupper..ndblk {
+			setbit(&mut cg.free, f);
+		}
+		cg
+	}
+
+	fn is_block_free(&self, g: &Geometry, blk: u64) -> bool {
+		(blk * g.frag..(blk + 1) * g.frag).all(|f| isset(&self.free, f))
+	}
+
+	/// Allocate a fragment from the first free block, and return its address.
+	fn alloc_frag(&mut self, g: &Geometry) -> IoResult<u64> {
+		let blk = (0..(self.ndblk / g.frag))
+			.find(|&b| self.is_block_free(g, b))
+			.ok_or_else(|| invalid(format!("no free block in cylinder group {}", self.cgx)))?;
+		clrbit(&mut self.free, blk * g.frag);
+		Ok(self.cgx * g.fpg + blk * g.frag)
+	}
+
+	fn alloc_inode(&mut self, g: &Geometry, inr: u64) {
+		setbit(&mut self.iused, inr % g.ipg);
+	}
+
+	/// Count runs of free fragments in `start..end`.
+	fn frag_runs(&self, start: u64, end: u64, s: &mut Summary) {
+		let mut run = 0;
+		for f in start..=end {
+			if f < end && isset(&self.free, f) {
+				run += 1;
+			} else if run > 0 {
+				s.frsum[run as usize] += 1;
+				s.nffree += run;
+				run = 0;
+			}
+		}
+	}
+
+	fn summary(&self, g: &Geometry) -> Summary {
+		let nblk = self.ndblk / g.frag;
+		let mut s = Summary {
+			clustermap: vec![0u8; (g.fpg / g.frag).div_ceil(8) as usize],
+			clustersum: vec![0u32; g.contigsumsize as usize + 1],
+			..Summary::default()
+		};
+
+		let mut run = 0;
+		for blk in 0..=nblk {
+			if blk < nblk && self.is_block_free(g, blk) {
+				s.nbfree += 1;
+				setbit(&mut s.clustermap, blk);
+				run += 1;
+				continue;
+			}
+			if run > 0 {
+				s.clustersum[run.min(g.contigsumsize) as usize] += 1;
+				run = 0;
+			}
+			let end = ((blk + 1) * g.frag).min(self.ndblk);
+			self.frag_runs(blk * g.frag, end, &mut s);
+		}
+
+		let used = self
+			.iused
+			.iter()
+			.map(|b| b.count_ones() as u64)
+			.sum::<u64>();
+		s.nifree = g.ipg - used;
+		s
+	}
+
+	/// Serialize the cylinder group block.
+	fn encode(&self, g: &Geometry, config: Config, time: i64) -> IoResult<Vec<u8>> {
+		let s = self.summary(g);
+		let mut cg: CylGroup = config.decode_slice(&[0u8; CGHDRSIZE as usize])?;
+		cg.magic = CG_MAGIC;
+		cg.old_time = time as i32;
+		cg.cgx = self.cgx as u32;
+		cg.ndblk = self.ndblk as u32;
+		cg.cs = Csum {
+			ndir:   self.ndir as i32,
+			nbfree: s.nbfree as i32,
+			nifree: s.nifree as i32,
+			nffree: s.nffree as i32,
+		};
+		cg.frsum = s.frsum;
+		cg.iusedoff = g.cg.iusedoff as u32;
+		cg.freeoff = g.cg.freeoff as u32;
+		cg.nextfreeoff = g.cg.nextfreeoff as u32;
+		cg.clustersumoff = g.cg.clustersumoff as u32;
+		cg.clusteroff = g.cg.clusteroff as u32;
+		cg.nclusterblks = (self.ndblk / g.frag) as u32;
+		cg.niblk = g.ipg as u32;
+		cg.initediblk = g.initediblk() as u32;
+		cg.time = time;
+
+		let mut buf = vec![0u8; g.cgsize as usize];
+		let hdr = config.encode_to_vec(&cg)?;
+		buf[0..hdr.len()].copy_from_slice(&hdr);
+
+		let mut put = |off: u64, data: &[u8]| {
+			let off = off as usize;
+			buf[off..(off + data.len())].copy_from_slice(data);
+		};
+		put(g.cg.iusedoff, &self.iused);
+		put(g.cg.freeoff, &self.free);
+		// The first entry of the cluster summary is unused, and overlaps the free map.
+		for (i, &n) in s.clustersum.iter().enumerate().skip(1) {
+			put(
+				g.cg.clustersumoff + 4 * i as u64,
+				&config.encode_to_vec(&n)?,
+			);
+		}
+		put(g.cg.clusteroff, &s.clustermap);
+		Ok(buf)
+	}
+
+	fn csum(&self, g: &Geometry) -> Csum {
+		let s = self.summary(g);
+		Csum {
+			ndir:   self.ndir as i32,
+			nbfree: s.nbfree as i32,
+			nifree: s.nifree as i32,
+			nffree: s.nffree as i32,
+		}
+	}
+}
+
+/// Build a directory block of `fsize` bytes, the last entry fills the first chunk.
+fn dir_block(config: Config, fsize: u64, entries: &[(u64, &str)]) -> IoResult<Vec<u8>> {
+	let mut buf = vec![0u8; fsize as usize];
+	let mut off = 0;
+	for (i, &(inr, name)) in entries.iter().enumerate() {
+		let reclen = if i + 1 == entries.len() {
+			DIRBLKSIZ - off
+		} else {
+			(8 + name.len() + 1).next_multiple_of(4)
+		};
+		buf[off..(off + 4)].copy_from_slice(&config.encode_to_vec(&(inr as u32))?);
+		buf[(off + 4)..(off + 6)].copy_from_slice(&config.encode_to_vec(&(reclen as u16))?);
+		buf[off + 6] = DT_DIR;
+		buf[off + 7] = name.len() as u8;
+		buf[(off + 8)..(off + 8 + name.len())].copy_from_slice(name.as_bytes());
+		off += reclen;
+	}
+	Ok(buf)
+}
+
+/// Create a new, empty filesystem on `backend`.
+///
+/// This writes the superblocks, the cylinder groups, the root directory and the ".snap" directory,
+/// like `newfs(8)` does, but without soft updates or check hashes.
+/// All writes are aligned to, and multiples of the fragment size.
+///
+/// # Example
+/// ```
+/// use rufs::{mkfs, InodeNum, MkfsOptions, Ufs};
+///
+/// let opts = MkfsOptions::new(16 << 20);
+/// let file = tempfile::tempfile()?;
+/// file.set_len(opts.size)?;
+/// let backend = rufs::BlockFile::new(file, opts.fsize as u64);
+/// mkfs(&backend, &opts)?;
+///
+/// let ufs = Ufs::new(backend)?;
+/// let snap = ufs.dir_lookup(InodeNum::ROOT, ".snap".as_ref())?;
+/// assert_eq!(ufs.inode_attr(snap)?.gid, 5);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[doc(alias("newfs", "makefs"))]
+pub fn mkfs<B: Backend>(backend: &B, opts: &MkfsOptions) -> IoResult<()> {
+	let g = Geometry::new(opts)?;
+	let config = if opts.big_endian {
+		Config::big()
+	} else {
+		Config::little()
+	};
+	let mut rng = Rng(opts.seed);
+	let time = opts.time;
+	let fs = g.fsize;
+
+	let mut volname = [0u8; MAXVOLLEN];
+	if opts.volname.len() >= MAXVOLLEN {
+		return Err(invalid(format!(
+			"volume name is too long: {}",
+			opts.volname
+		)));
+	}
+	volname[0..opts.volname.len()].copy_from_slice(opts.volname.as_bytes());
+
+	let mut cgs = (0..g.ncg).map(|cgx| Cg::new(&g, cgx)).collect::<Vec<_>>();
+	let rootblk = cgs[0].alloc_frag(&g)?;
+	let snapblk = cgs[0].alloc_frag(&g)?;
+	cgs[0].alloc_inode(&g, ROOTINO);
+	cgs[0].alloc_inode(&g, SNAPINO);
+	cgs[0].ndir = 2;
+
+	let mut cstotal = CsumTotal {
+		ndir:        0,
+		nbfree:      0,
+		nifree:      0,
+		nffree:      0,
+		numclusters: 0,
+		spare:       [0; 3],
+	};
+	let mut csums = Vec::new();
+	for cg in &cgs {
+		let cs = cg.csum(&g);
+		cstotal.ndir += cs.ndir as i64;
+		cstotal.nbfree += cs.nbfree as i64;
+		cstotal.nifree += cs.nifree as i64;
+		cstotal.nffree += cs.nffree as i64;
+		csums.extend(config.encode_to_vec(&cs)?);
+	}
+	csums.resize(g.cssize as usize, 0u8);
+
+	let mut sb: Superblock = config.decode_slice(&[0u8; SBLOCKSIZE])?;
+	sb.sblkno = g.sblkno as i32;
+	sb.cblkno = g.cblkno as i32;
+	sb.iblkno = g.iblkno as i32;
+	sb.dblkno = g.dblkno as i32;
+	sb.ncg = g.ncg as u32;
+	sb.bsize = g.bsize as i32;
+	sb.fsize = g.fsize as i32;
+	sb.frag = g.frag as i32;
+	sb.minfree = opts.minfree as i32;
+	sb.bmask = !(g.bsize as i32 - 1);
+	sb.fmask = !(g.fsize as i32 - 1);
+	sb.bshift = g.bsize.trailing_zeros() as i32;
+	sb.fshift = g.fsize.trailing_zeros() as i32;
+	sb.fs_maxcontig = (MAXPHYS / g.bsize).max(1) as i32;
+	sb.fs_maxbpg = (g.bsize / size_of::<UfsDaddr>() as u64) as i32;
+	sb.fragshift = g.frag.trailing_zeros() as i32;
+	sb.fsbtodb = (g.fsize / DEV_BSIZE as u64).trailing_zeros() as i32;
+	sb.sbsize = roundup(SBSTRUCTSIZE as u64, g.fsize) as i32;
+	sb.nindir = (g.bsize / size_of::<UfsDaddr>() as u64) as i32;
+	sb.inopb = g.inopb as u32;
+	sb.optim = if opts.minfree < MINFREE {
+		FS_OPTSPACE
+	} else {
+		FS_OPTTIME
+	};
+	sb.id = [time as i32, rng.next() as i32];
+	sb.cssize = g.cssize as i32;
+	sb.cgsize = g.cgsize as i32;
+	sb.ipg = g.ipg as u32;
+	sb.fpg = g.fpg as i32;
+	sb.clean = 1;
+	sb.old_flags = FS_FLAGS_UPDATED as i8;
+	sb.volname = volname;
+	sb.maxbsize = g.bsize as i32;
+	sb.providersize = (opts.size / g.fsize) as i64;
+	sb.metaspace = ((g.fpg * opts.minfree as u64 / 200) / g.frag * g.frag) as i64;
+	sb.sblockloc = SBLOCK_UFS2 as i64;
+	sb.cstotal = cstotal;
+	sb.time = time;
+	sb.size = g.size as i64;
+	sb.dsize = g.dsize() as i64;
+	sb.csaddr = g.dblkno as i64;
+	sb.avgfilesize = AVFILESIZ;
+	sb.avgfpdir = AFPDIR;
+	sb.contigsumsize = g.contigsumsize as i32;
+	sb.maxsymlinklen = UFS_SLLEN as i32;
+	let mut sizepb = g.bsize;
+	sb.maxfilesize = g.bsize * UFS_NDADDR as u64 - 1;
+	for _ in 0..UFS_NIADDR {
+		sizepb *= g.bsize / size_of::<UfsDaddr>() as u64;
+		sb.maxfilesize += sizepb;
+	}
+	sb.qbmask = g.bsize as i64 - 1;
+	sb.qfmask = g.fsize as i64 - 1;
+	sb.magic = FS_UFS2_MAGIC;
+
+	// Inodes are initialized with random generation numbers.
+	let mut ino: Inode = config.decode_slice(&[0u8; UFS_INOSZ])?;
+	let mut dir_inode = |mode: u16, nlink: u16, gid: u32, blk: u64, depth: u32| {
+		ino.mode = S_IFDIR | mode;
+		ino.nlink = nlink;
+		ino.gid = gid;
+		ino.size = DIRBLKSIZ as u64;
+		ino.blocks = fs / DEV_BSIZE as u64;
+		ino.atime = time;
+		ino.mtime = time;
+		ino.ctime = time;
+		ino.birthtime = time;
+		ino.gen = rng.next();
+		let mut direct = [0; UFS_NDADDR];
+		direct[0] = blk as UfsDaddr;
+		ino.data = InodeData::Blocks(InodeBlocks {
+			direct,
+			indirect: [0; UFS_NIADDR],
+		});
+		ino.ignored = depth;
+		config.encode_to_vec(&ino)
+	};
+	let root = dir_inode(0o755, 3, 0, rootblk, 0)?;
+	let snap = dir_inode(0o775, 2, GID_OPERATOR, snapblk, 1)?;
+	let mut empty: Inode = config.decode_slice(&[0u8; UFS_INOSZ])?;
+
+	for cg in &cgs {
+		let base = cg.cgx * g.fpg;
+
+		// Backup superblocks record their own location.
+		let pos = (base + g.sblkno) * fs;
+		sb.sblockactualloc = pos as i64;
+		let mut backup = config.encode_to_vec(&sb)?;
+		backup.resize(SBLOCKSIZE.next_multiple_of(fs as usize), 0u8);
+		backend.write_at(pos, &backup)?;
+		backend.write_at((base + g.cblkno) * fs, &cg.encode(&g, config, time)?)?;
+
+		let mut inodes = Vec::with_capacity(g.initediblk() as usize * UFS_INOSZ);
+		for i in 0..g.initediblk() {
+			empty.gen = rng.next();
+			match cg.cgx * g.ipg + i {
+				ROOTINO => inodes.extend_from_slice(&root),
+				SNAPINO => inodes.extend_from_slice(&snap),
+				_ => inodes.extend(config.encode_to_vec(&empty)?),
+			}
+		}
+		backend.write_at((base + g.iblkno) * fs, &inodes)?;
+	}
+
+	let root = dir_block(
+		config,
+		fs,
+		&[(ROOTINO, "."), (ROOTINO, ".."), (SNAPINO, ".snap")],
+	)?;
+	backend.write_at(rootblk * fs, &root)?;
+	let snap = dir_block(config, fs, &[(SNAPINO, "."), (ROOTINO, "..")])?;
+	backend.write_at(snapblk * fs, &snap)?;
+	backend.write_at(g.dblkno * fs, &csums)?;
+
+	// newfs(8) stores where to find the backups in front of the primary superblock,
+	// without overwriting the rest of the boot area.
+	let fsr = config.encode_to_vec(&FsRecovery {
+		magic:   FS_UFS2_MAGIC,
+		fsbtodb: sb.fsbtodb,
+		sblkno:  sb.sblkno,
+		fpg:     sb.fpg,
+		ncg:     sb.ncg,
+	})?;
+	let mut boot = vec![0u8; fs as usize];
+	backend.read_at(SBLOCK_UFS2 as u64 - fs, &mut boot)?;
+	let off = boot.len() - fsr.len();
+	boot[off..].copy_from_slice(&fsr);
+	backend.write_at(SBLOCK_UFS2 as u64 - fs, &boot)?;
+
+	sb.sblockactualloc = SBLOCK_UFS2 as i64;
+	let mut primary = config.encode_to_vec(&sb)?;
+	// Devices, whose sectors are larger than the superblock, need whole fragments.
+	primary.resize(SBLOCKSIZE.next_multiple_of(fs as usize), 0u8);
+	backend.write_at(SBLOCK_UFS2 as u64, &primary)?;
+	backend.sync()
+}
@@ -0,0 +1,102 @@
+//! Reading images, which are mapped into memory.
+use std::{
+	fs::File,
+	io::{Error as IoError, ErrorKind, Result as IoResult, Seek, SeekFrom},
+	os::unix::fs::FileExt,
+	path::Path,
+};
+
+use memmap2::{Mmap, MmapOptions};
+
+use crate::{backend::lock_file, Backend};
+
+/// A file or device, which is mapped into memory, read-only and shared (`MAP_SHARED`).
+///
+/// Reads are served from the mapping, without a system call, and structures, like inodes,
+/// are decoded directly from it, see [`Backend::mapped()`], so metadata-heavy workloads,
+/// like walking whole directory trees, are faster than using a [`BlockFile`](crate::BlockFile).
+/// The mapping can be shared between threads without locking.
+///
+/// Writes go through the file, using `pwrite(2)`, if it was opened for writing,
+/// and are visible through the mapping, because it is shared.
+///
+/// If the file is truncated by another process, while it is mapped,
+/// accessing the missing part raises `SIGBUS`, so use [`MmapBackend::lock()`].
+#[doc(alias("MemoryMappedBackend", "mmap"))]
+pub struct MmapBackend {
+	file: File,
+	map:  Mmap,
+}
+
+impl MmapBackend {
+	/// Open the file or device at `path`, read-only, and map it.
+	pub fn open(path: &Path) -> IoResult<Self> {
+		Self::new(File::open(path)?)
+	}
+
+	/// Map all of `file`, which may be a device.
+	///
+	/// Writes fail with `EBADF`, unless `file` was opened for writing.
+	pub fn new(mut file: File) -> IoResult<Self> {
+		// The size of devices is only known by seeking.
+		let len = file.seek(SeekFrom::End(0))?;
+		let len = usize::try_from(len).map_err(|_| IoError::from(ErrorKind::OutOfMemory))?;
+		// SAFETY: the mapping is read-only, and the file is only modified through write_at(),
+		// unless another process modifies it, which `lock()` protects against.
+		let map = unsafe { MmapOptions::new().len(len).map(&file)? };
+		log::debug!("MmapBackend::new(): mapped {len} bytes");
+		Ok(Self { file, map })
+	}
+
+	/// Take an advisory lock on the file, like [`BlockFile::lock()`](crate::BlockFile::lock).
+	#[doc(alias = "flock")]
+	pub fn lock(&self, exclusive: bool) -> IoResult<()> {
+		lock_file(&self.file, exclusive)
+	}
+
+	/// Get the underlying file.
+	pub fn get_ref(&self) -> &File {
+		&self.file
+	}
+}
+
+impl Backend for MmapBackend {
+	fn read_at(&self, pos: u64, buf: &mut [u8]) -> IoResult<()> {
+		let data = self
+			.mapped(pos, buf.len())
+			.ok_or_else(|| IoError::from(ErrorKind::UnexpectedEof))?;
+		buf.copy_from_slice(data);
+		Ok(())
+	}
+
+	fn write_at(&self, pos: u64, buf: &[u8]) -> IoResult<()> {
+		let end = pos.checked_add(buf.len() as u64);
+		if end.map_or(true, |end| end > self.map.len() as u64) {
+			// The mapping can't grow.
+			return Err(IoError::from(ErrorKind::UnexpectedEof));
+		}
+		self.file.write_all_at(buf, pos)
+	}
+
+	fn prefetch(&self, pos: u64, len: usize) -> IoResult<()> {
+		let Some(pos) = usize::try_from(pos).ok().filter(|&p| p < self.map.len()) else {
+			return Ok(());
+		};
+		let len = len.min(self.map.len() - pos);
+		self.map.advise_range(memmap2::Advice::WillNeed, pos, len)
+	}
+
+	fn sync(&self) -> IoResult<()> {
+		// The mapping is read-only, so everything was written through the file.
+		self.file.sync_data()
+	}
+
+	fn mapped(&self, pos: u64, len: usize) -> Option<&[u8]> {
+		let pos = usize::try_from(pos).ok()?;
+		self.map.get(pos..pos.checked_add(len)?)
+	}
+
+	fn size(&self) -> IoResult<Option<u64>> {
+		Ok(Some(self.map.len() as u64))
+	}
+}
@@ -0,0 +1,333 @@
+//! Partition tables, which are used by FreeBSD: GPT, MBR and BSD disklabels.
+use std::io::{ErrorKind, Result as IoResult};
+
+use crate::{data::*, ufs::config_from_magic, Backend};
+
+/// Type GUID of a `freebsd-ufs` GPT partition (`516e7cb6-6ecf-11d6-8ff8-00022d09712b`),
+/// in the mixed-endian encoding used on disk.
+const GPT_FREEBSD_UFS: [u8; 16] = [
+	0xb6, 0x7c, 0x6e, 0x51, 0xcf, 0x6e, 0xd6, 0x11, 0x8f, 0xf8, 0x00, 0x02, 0x2d, 0x09, 0x71, 0x2b,
+];
+
+/// Sector sizes, at which a GPT header is searched.
+const GPT_SECTOR_SIZES: [u64; 2] = [512, 4096];
+
+/// Upper limit for the size of the GPT partition entry array.
+const GPT_MAX_ENTRIES_SIZE: usize = 1 << 20;
+
+/// MBR partition type of a protective MBR, which is followed by a GPT.
+const MBR_PROTECTIVE: u8 = 0xee;
+
+/// MBR partition type of a FreeBSD slice, which holds a BSD disklabel.
+const MBR_FREEBSD: u8 = 0xa5;
+
+/// Magic number of a BSD disklabel (`d_magic` and `d_magic2`).
+const DISKMAGIC: u32 = 0x8256_4557;
+
+/// Offset of the BSD disklabel within a slice or disk.
+const LABELOFFSET: u64 = 512;
+
+/// Index of the partition, which covers the whole slice (`c`).
+const RAW_PART: usize = 2;
+
+/// `p_fstype` of a UFS partition in a BSD disklabel.
+const FS_BSDFFS: u8 = 7;
+
+/// An entry of a partition table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Partition {
+	/// Name of the partition, as a suffix to the name of the disk, like FreeBSD does (eg. `p2`, `s1a`).
+	pub name:  String,
+	/// Number of the partition, within its partition table.
+	/// GPT and MBR partitions are numbered from 1, BSD partitions from 0 (`a`).
+	pub index: usize,
+	/// Offset of the partition in bytes.
+	pub start: u64,
+	/// Size of the partition in bytes.
+	pub size:  u64,
+	/// Whether the partition is marked as holding a UFS filesystem.
+	pub ufs:   bool,
+}
+
+impl Partition {
+	/// Check whether `spec` refers to this partition.
+	///
+	/// `spec` is either a name (eg. `p2`, `s1a`), or the number of a GPT partition or MBR slice.
+	pub fn matches(&self, spec: &str) -> bool {
+		match spec.parse::<usize>() {
+			Ok(idx) => self.name[1..].parse() == Ok(idx),
+			Err(_) => self.name == spec,
+		}
+	}
+}
+
+fn le16(buf: &[u8], off: usize) -> u16 {
+	u16::from_le_bytes(buf[off..(off + 2)].try_into().unwrap())
+}
+
+fn le32(buf: &[u8], off: usize) -> u32 {
+	u32::from_le_bytes(buf[off..(off + 4)].try_into().unwrap())
+}
+
+fn le64(buf: &[u8], off: usize) -> u64 {
+	u64::from_le_bytes(buf[off..(off + 8)].try_into().unwrap())
+}
+
+/// Read `len` bytes at `pos`, or `None`, if the device is too small.
+fn read<B: Backend>(backend: &B, pos: u64, len: usize) -> IoResult<Option<Vec<u8>>> {
+	let mut buf = vec![0u8; len];
+	match backend.read_at(pos, &mut buf) {
+		Ok(()) => Ok(Some(buf)),
+		Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(None),
+		Err(e) => Err(e),
+	}
+}
+
+/// Parse a GUID Partition Table.
+fn gpt<B: Backend>(backend: &B) -> IoResult<Option<Vec<Partition>>> {
+	for ss in GPT_SECTOR_SIZES {
+		let Some(hdr) = read(backend, ss, 92)? else {
+			continue;
+		};
+		if &hdr[0..8] != b"EFI PART" {
+			continue;
+		}
+
+		let lba = le64(&hdr, 72);
+		let num = le32(&hdr, 80) as usize;
+		let esize = le32(&hdr, 84) as usize;
+		if esize < 128 || num.saturating_mul(esize) > GPT_MAX_ENTRIES_SIZE {
+			log::warn!("GPT: invalid partition entry array: {num} * {esize}");
+			return Ok(Some(Vec::new()));
+		}
+		let Some(entries) = read(backend, lba.saturating_mul(ss), num * esize)? else {
+			log::warn!("GPT: partition entry array beyond the end of the device");
+			return Ok(Some(Vec::new()));
+		};
+
+		let parts = entries
+			.chunks_exact(esize)
+			.enumerate()
+			.filter(|(_, e)| e[0..16].iter().any(|&b| b != 0))
+			.filter_map(|(i, e)| {
+				let (first, last) = (le64(e, 32), le64(e, 40));
+				let size = last.checked_sub(first)?.checked_add(1)?.checked_mul(ss)?;
+				Some(Partition {
+					name: format!("p{}", i + 1),
+					index: i + 1,
+					start: first.checked_mul(ss)?,
+					size,
+					ufs: e[0..16] == GPT_FREEBSD_UFS,
+				})
+			})
+			.collect();
+		return Ok(Some(parts));
+	}
+	Ok(None)
+}
+
+/// Parse a BSD disklabel at the start of a slice, or the whole disk.
+fn disklabel<B: Backend>(backend: &B, base: u64, prefix: &str) -> IoResult<Vec<Partition>> {
+	let Some(label) = read(backend, base + LABELOFFSET, 148 + 20 * 16)? else {
+		return Ok(Vec::new());
+	};
+	if le32(&label, 0) != DISKMAGIC || le32(&label, 132) != DISKMAGIC {
+		return Ok(Vec::new());
+	}
+
+	let ss = match le32(&label, 40) {
+		0 => 512,
+		ss => ss as u64,
+	};
+	let num = (le16(&label, 138) as usize).min(20);
+	let entry = |i: usize| &label[(148 + i * 16)..(148 + (i + 1) * 16)];
+
+	// Depending on the version of FreeBSD, offsets are either relative to the disk or the slice.
+	// The raw partition always covers the whole slice, so offsets are taken relative to it.
+	let raw = if num > RAW_PART {
+		le32(entry(RAW_PART), 4) as u64
+	} else {
+		0
+	};
+
+	let parts = (0..num)
+		.filter(|&i| i != RAW_PART)
+		.filter_map(|i| {
+			let e = entry(i);
+			let (size, offset, fstype) = (le32(e, 0) as u64, le32(e, 4) as u64, e[12]);
+			if size == 0 || fstype == 0 {
+				return None;
+			}
+			Some(Partition {
+				name:  format!("{prefix}{}", (b'a' + i as u8) as char),
+				index: i,
+				start: base + offset.checked_sub(raw)? * ss,
+				size:  size * ss,
+				ufs:   fstype == FS_BSDFFS,
+			})
+		})
+		.collect();
+	Ok(parts)
+}
+
+/// Parse a Master Boot Record, and the disklabels of FreeBSD slices.
+fn mbr<B: Backend>(backend: &B) -> IoResult<Option<Vec<Partition>>> {
+	let Some(mbr) = read(backend, 0, 512)? else {
+		return Ok(None);
+	};
+	if mbr[510..512] != [0x55, 0xaa] {
+		return Ok(None);
+	}
+
+	let entries = mbr[446..510].chunks_exact(16);
+	if entries.clone().any(|e| e[4] == MBR_PROTECTIVE) {
+		log::warn!("MBR: protective MBR, but no GPT found");
+		return Ok(Some(Vec::new()));
+	}
+
+	let mut parts = Vec::new();
+	for (i, e) in entries.enumerate() {
+		// The status byte is either 0x00 or 0x80, otherwise it isn't an MBR (eg. a boot block).
+		if e[0] & 0x7f != 0 {
+			return Ok(None);
+		}
+		let (kind, start, size) = (e[4], le32(e, 8) as u64 * 512, le32(e, 12) as u64 * 512);
+		if kind == 0 || size == 0 {
+			continue;
+		}
+
+		let name = format!("s{}", i + 1);
+		let labels = match kind {
+			MBR_FREEBSD => disklabel(backend, start, &name)?,
+			_ => Vec::new(),
+		};
+		parts.push(Partition {
+			name,
+			index: i + 1,
+			start,
+			size,
+			ufs: false,
+		});
+		parts.extend(labels);
+	}
+	Ok(Some(parts))
+}
+
+/// List the partitions of a disk.
+///
+/// GPT is preferred over MBR, and BSD disklabels are parsed within FreeBSD slices,
+/// or at the start of a disk without an MBR (a "dangerously dedicated" disk).
+/// If the disk doesn't contain a partition table, the list is empty.
+///
+/// # Example
+/// ```
+/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+/// # use support::*;
+/// # use std::io::Cursor;
+/// # use rufs::SeekBackend;
+/// let backend = SeekBackend::new(Cursor::new(golden_image("ufs-little")));
+/// assert!(rufs::partitions(&backend).unwrap().is_empty());
+/// ```
+pub fn partitions<B: Backend>(backend: &B) -> IoResult<Vec<Partition>> {
+	if let Some(parts) = gpt(backend)? {
+		return Ok(parts);
+	}
+	if let Some(parts) = mbr(backend)? {
+		return Ok(parts);
+	}
+	disklabel(backend, 0, "")
+}
+
+/// Find the partition, which holds the filesystem.
+///
+/// Returns `None`, if the disk itself holds a filesystem,
+/// or no partition is marked as holding a UFS filesystem.
+pub fn find_ufs_partition<B: Backend>(backend: &B) -> IoResult<Option<Partition>> {
+	if let Some(magic) = read(backend, SBLOCK_UFS2 as u64 + MAGIC_OFFSET, 4)? {
+		if config_from_magic(magic.try_into().unwrap()).is_ok() {
+			return Ok(None);
+		}
+	}
+
+	let part = partitions(backend)?.into_iter().find(|p| p.ufs);
+	if let Some(part) = &part {
+		log::info!(
+			"using partition {} at offset {}, with a size of {} bytes",
+			part.name,
+			part.start,
+			part.size
+		);
+	}
+	Ok(part)
+}
+
+#[cfg(test)]
+mod t {
+	use std::io::Cursor;
+
+	use super::*;
+	use crate::SeekBackend;
+
+	/// A disk with an MBR, whose 2nd slice is a FreeBSD slice with a disklabel.
+	fn mbr_disk() -> SeekBackend<Cursor<Vec<u8>>> {
+		let mut disk = vec![0u8; 1 << 20];
+		let mut entry = |i: usize, kind: u8, start: u32, size: u32| {
+			let e = &mut disk[(446 + i * 16)..(446 + (i + 1) * 16)];
+			e[4] = kind;
+			e[8..12].copy_from_slice(&start.to_le_bytes());
+			e[12..16].copy_from_slice(&size.to_le_bytes());
+		};
+		entry(0, 0x0b, 63, 100);
+		entry(1, MBR_FREEBSD, 200, 1800);
+		disk[510..512].copy_from_slice(&[0x55, 0xaa]);
+
+		// Offsets are relative to the disk, like older versions of FreeBSD did.
+		let label = &mut disk[(200 * 512 + 512)..];
+		label[0..4].copy_from_slice(&DISKMAGIC.to_le_bytes());
+		label[132..136].copy_from_slice(&DISKMAGIC.to_le_bytes());
+		label[40..44].copy_from_slice(&512u32.to_le_bytes());
+		label[138..140].copy_from_slice(&8u16.to_le_bytes());
+		let mut part = |i: usize, size: u32, offset: u32, fstype: u8| {
+			let p = &mut label[(148 + i * 16)..(148 + (i + 1) * 16)];
+			p[0..4].copy_from_slice(&size.to_le_bytes());
+			p[4..8].copy_from_slice(&offset.to_le_bytes());
+			p[12] = fstype;
+		};
+		part(0, 1000, 216, FS_BSDFFS);
+		part(1, 584, 1216, 1);
+		part(RAW_PART, 1800, 200, 0);
+		SeekBackend::new(Cursor::new(disk))
+	}
+
+	#[test]
+	fn mbr_disklabel() {
+		let parts = partitions(&mbr_disk()).unwrap();
+		let names = parts.iter().map(|p| p.name.as_str()).collect::<Vec<_>>();
+		assert_eq!(names, ["s1", "s2", "s2a", "s2b"]);
+		assert_eq!(
+			parts[2],
+			Partition {
+				name:  "s2a".into(),
+				index: 0,
+				start: 216 * 512,
+				size:  1000 * 512,
+				ufs:   true,
+			}
+		);
+		assert!(parts[1].matches("2"));
+		assert!(!parts[2].matches("0"));
+		assert!(parts[2].matches("s2a"));
+		assert_eq!(
+			find_ufs_partition(&mbr_disk()).unwrap(),
+			Some(parts[2].clone())
+		);
+	}
+
+	#[test]
+	fn empty() {
+		let backend = SeekBackend::new(Cursor::new(vec![0u8; 4096]));
+		assert_eq!(partitions(&backend).unwrap(), []);
+		assert_eq!(find_ufs_partition(&backend).unwrap(), None);
+	}
+}
@@ -0,0 +1,138 @@
+use super::*;
+use crate::{err, InodeNum};
+
+/// Identity of a process, which accesses a file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[doc(alias = "ucred")]
+pub struct Credentials {
+	/// Effective user ID.
+	pub uid: u32,
+
+	/// Effective group ID.
+	pub gid: u32,
+
+	/// Supplementary groups.
+	pub groups: Vec<u32>,
+}
+
+impl Credentials {
+	/// Credentials of a process, which is not in any supplementary groups.
+	pub fn new(uid: u32, gid: u32) -> Self {
+		Self {
+			uid,
+			gid,
+			groups: Vec::new(),
+		}
+	}
+
+	/// Check whether this is the superuser.
+	pub fn is_root(&self) -> bool {
+		self.uid == 0
+	}
+
+	/// Check whether the process is a member of group `gid`.
+	pub fn in_group(&self, gid: u32) -> bool {
+		self.gid == gid || self.groups.contains(&gid)
+	}
+}
+
+/// Check whether the permission bits `perm` grant all of the requested bits `want`.
+fn granted(perm: u16, want: u16) -> IoResult<()> {
+	if perm & want == want {
+		Ok(())
+	} else {
+		Err(err!(EACCES))
+	}
+}
+
+/// Check the permissions like `vaccess_acl_posix1e()` in FreeBSD.
+fn check_acl(acl: &Acl, attr: &InodeAttr, cred: &Credentials, want: u16) -> IoResult<()> {
+	let perm = |tag| acl.entries.iter().find(|e| e.tag == tag).map(|e| e.perm);
+	let mask = perm(AclTag::Mask).unwrap_or(0o7);
+
+	if cred.uid == attr.uid {
+		return granted(perm(AclTag::UserObj).unwrap_or(0), want);
+	}
+	if let Some(p) = perm(AclTag::User(cred.uid)) {
+		return granted(p & mask, want);
+	}
+
+	// Any of the matching groups may grant the access.
+	let mut member = false;
+	for e in &acl.entries {
+		let matches = match e.tag {
+			AclTag::GroupObj => cred.in_group(attr.gid),
+			AclTag::Group(gid) => cred.in_group(gid),
+			_ => false,
+		};
+		if matches && granted(e.perm & mask, want).is_ok() {
+			return Ok(());
+		}
+		member |= matches;
+	}
+	if member {
+		return Err(err!(EACCES));
+	}
+
+	granted(perm(AclTag::Other).unwrap_or(0), want)
+}
+
+impl<B: Backend> Ufs<B> {
+	/// Check whether a process with the credentials `cred` may access inode `inr`, like `access(2)`.
+	///
+	/// `mask` is either `F_OK`, or a combination of `R_OK`, `W_OK` and `X_OK`.
+	/// The permission bits, the POSIX.1e ACL (see [`Ufs::acl()`]) and the immutable flag are checked.
+	/// The superuser may read and write everything, but may only execute files,
+	/// which have an execute bit set.
+	///
+	/// Fails with `EACCES`, if the access is denied, or with `EPERM`, if an immutable file
+	/// is going to be written.
+	/// Whether the filesystem can be written to at all, isn't checked.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::{Credentials, InodeNum};
+	///
+	/// # let ufs = example_image();
+	/// let nobody = Credentials::new(65534, 65534);
+	/// ufs.access(InodeNum::ROOT, &nobody, libc::R_OK | libc::X_OK)?;
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	pub fn access(&self, inr: InodeNum, cred: &Credentials, mask: i32) -> IoResult<()> {
+		if mask & !(libc::R_OK | libc::W_OK | libc::X_OK) != 0 {
+			return Err(err!(EINVAL));
+		}
+
+		let attr = self.inode_attr(inr)?;
+		// The bits of R_OK, W_OK and X_OK are the same as the ones of the mode.
+		let want = mask as u16;
+		if want & 0o2 != 0 && attr.is_immutable() {
+			return Err(err!(EPERM));
+		}
+
+		if cred.is_root() {
+			let exec = attr.kind == InodeType::Directory || attr.perm & 0o111 != 0;
+			return if want & 0o1 == 0 || exec {
+				Ok(())
+			} else {
+				Err(err!(EACCES))
+			};
+		}
+
+		if let Some(acl) = self.acl(inr)? {
+			return check_acl(&acl, &attr, cred, want);
+		}
+
+		let shift = if cred.uid == attr.uid {
+			6
+		} else if cred.in_group(attr.gid) {
+			3
+		} else {
+			0
+		};
+		granted((attr.perm >> shift) & 0o7, want)
+	}
+}
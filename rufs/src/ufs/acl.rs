@@ -0,0 +1,249 @@
+use super::{xattr::ENOATTR, *};
+use crate::InodeNum;
+
+/// Extended attributes, which hold the POSIX.1e ACLs of an inode.
+const ACL_ACCESS: &str = "system.posix1e.acl_access";
+const ACL_DEFAULT: &str = "system.posix1e.acl_default";
+
+/// Maximum number of entries of an ACL on disk (`OLDACL_MAX_ENTRIES`).
+const ACL_MAX_ENTRIES: usize = 32;
+
+/// Size of an entry on disk: tag (4), id (4), permissions (2), padding (2).
+const ACL_ENTRY_SIZE: usize = 12;
+
+/// Size of an ACL on disk (`struct oldacl`): the number of entries, followed by all entries.
+const ACL_SIZE: usize = 4 + ACL_MAX_ENTRIES * ACL_ENTRY_SIZE;
+
+// Tags of the entries on disk.
+const ACL_USER_OBJ: u32 = 0x01;
+const ACL_USER: u32 = 0x02;
+const ACL_GROUP_OBJ: u32 = 0x04;
+const ACL_GROUP: u32 = 0x08;
+const ACL_MASK: u32 = 0x10;
+const ACL_OTHER: u32 = 0x20;
+
+/// Whom an entry of an [`Acl`] applies to.
+///
+/// The order of the variants is the canonical order of the entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AclTag {
+	/// The owner of the file.
+	UserObj,
+
+	/// The user with this uid.
+	User(u32),
+
+	/// The group of the file.
+	GroupObj,
+
+	/// The group with this gid.
+	Group(u32),
+
+	/// Upper bound of the permissions granted by `User`, `GroupObj` and `Group` entries.
+	Mask,
+
+	/// Everyone else.
+	Other,
+}
+
+/// An entry of an [`Acl`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AclEntry {
+	/// Whom this entry applies to.
+	pub tag: AclTag,
+
+	/// Permissions: 4 (read), 2 (write) and 1 (execute), like the bits of a mode.
+	pub perm: u16,
+}
+
+/// POSIX.1e access control list, see `acl(3)`.
+///
+/// The entries are sorted by their tags, see [`AclTag`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Acl {
+	pub entries: Vec<AclEntry>,
+}
+
+impl Acl {
+	/// Decode an ACL stored in an extended attribute (`struct oldacl`).
+	fn decode(data: &[u8], config: Config) -> IoResult<Self> {
+		if data.len() != ACL_SIZE {
+			return Err(corrupt!("invalid ACL length: {}", data.len()));
+		}
+
+		let cnt: i32 = config.decode_slice(&data[0..4])?;
+		if cnt < 0 || cnt as usize > ACL_MAX_ENTRIES {
+			return Err(corrupt!("invalid number of ACL entries: {cnt}"));
+		}
+
+		let mut entries = data[4..]
+			.chunks_exact(ACL_ENTRY_SIZE)
+			.take(cnt as usize)
+			.map(|e| {
+				let tag: u32 = config.decode_slice(&e[0..4])?;
+				let id: u32 = config.decode_slice(&e[4..8])?;
+				let perm: u16 = config.decode_slice(&e[8..10])?;
+				let tag = match tag {
+					ACL_USER_OBJ => AclTag::UserObj,
+					ACL_USER => AclTag::User(id),
+					ACL_GROUP_OBJ => AclTag::GroupObj,
+					ACL_GROUP => AclTag::Group(id),
+					ACL_MASK => AclTag::Mask,
+					ACL_OTHER => AclTag::Other,
+					_ => {
+						return Err(corrupt!("invalid ACL tag: {tag:#x}"));
+					}
+				};
+				Ok(AclEntry {
+					tag,
+					perm: perm & 0o7,
+				})
+			})
+			.collect::<IoResult<Vec<_>>>()?;
+		entries.sort_by_key(|e| e.tag);
+		Ok(Self { entries })
+	}
+
+	/// Update the entries, which correspond to the permission bits `perm` of the file,
+	/// like `ufs_sync_acl_from_inode()` in FreeBSD.
+	///
+	/// The group bits are the permissions of the mask entry, if there is one.
+	fn sync_from_perm(&mut self, perm: u16) {
+		let has_mask = self.entries.iter().any(|e| e.tag == AclTag::Mask);
+		for e in &mut self.entries {
+			match e.tag {
+				AclTag::UserObj => e.perm = (perm >> 6) & 0o7,
+				AclTag::GroupObj if !has_mask => e.perm = (perm >> 3) & 0o7,
+				AclTag::Mask => e.perm = (perm >> 3) & 0o7,
+				AclTag::Other => e.perm = perm & 0o7,
+				_ => {}
+			}
+		}
+	}
+}
+
+impl<B: Backend> Ufs<B> {
+	/// Get the POSIX.1e access ACL of inode `inr`.
+	///
+	/// Returns `None`, if the inode has no ACL, or if ACLs aren't enabled on the filesystem,
+	/// so that only its permission bits apply.
+	/// The entries of the owner, group and others reflect the permission bits,
+	/// which take precedence, like in FreeBSD.
+	/// The users and groups of the other entries are mapped using [`Options::idmap`].
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::InodeNum;
+	///
+	/// # let ufs = example_image();
+	/// // The example image doesn't have ACLs enabled.
+	/// assert_eq!(ufs.acl(InodeNum::ROOT)?, None);
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	#[doc(alias = "acl_get_file")]
+	pub fn acl(&self, inr: InodeNum) -> IoResult<Option<Acl>> {
+		let Some(mut acl) = self.read_acl(inr, ACL_ACCESS)? else {
+			return Ok(None);
+		};
+		let attr = self.inode_attr(inr)?;
+		acl.sync_from_perm(attr.perm);
+		Ok(Some(acl))
+	}
+
+	/// Get the POSIX.1e default ACL of directory `inr`, which new files inherit.
+	///
+	/// Returns `None`, if the inode has no default ACL, or if ACLs aren't enabled on the filesystem.
+	pub fn default_acl(&self, inr: InodeNum) -> IoResult<Option<Acl>> {
+		self.read_acl(inr, ACL_DEFAULT)
+	}
+
+	/// Read and decode the ACL stored in the extended attribute `name`.
+	fn read_acl(&self, inr: InodeNum, name: &str) -> IoResult<Option<Acl>> {
+		// FreeBSD ignores the extended attributes, unless the filesystem is mounted with ACLs.
+		if self.superblock.flags & FS_ACLS == 0 {
+			return Ok(None);
+		}
+		let data = match self.xattr_read(inr, name.as_ref()) {
+			Ok(data) => data,
+			Err(e) if e.raw_os_error() == Some(ENOATTR) => return Ok(None),
+			Err(e) => return Err(e),
+		};
+		let mut acl = Acl::decode(&data, self.config)?;
+		self.options.idmap.apply_acl(&mut acl);
+		Ok(Some(acl))
+	}
+}
+
+#[cfg(test)]
+mod t {
+	use super::*;
+
+	fn encode(entries: &[(u32, u32, u16)]) -> Vec<u8> {
+		let mut data = vec![0u8; ACL_SIZE];
+		data[0..4].copy_from_slice(&(entries.len() as i32).to_le_bytes());
+		for (i, &(tag, id, perm)) in entries.iter().enumerate() {
+			let pos = 4 + i * ACL_ENTRY_SIZE;
+			data[pos..(pos + 4)].copy_from_slice(&tag.to_le_bytes());
+			data[(pos + 4)..(pos + 8)].copy_from_slice(&id.to_le_bytes());
+			data[(pos + 8)..(pos + 10)].copy_from_slice(&perm.to_le_bytes());
+		}
+		data
+	}
+
+	fn entry(tag: AclTag, perm: u16) -> AclEntry {
+		AclEntry { tag, perm }
+	}
+
+	#[test]
+	fn decode() {
+		let config = Config::little();
+		let data = encode(&[
+			(ACL_OTHER, u32::MAX, 0),
+			(ACL_GROUP, 20, 5),
+			(ACL_USER, 1002, 6),
+			(ACL_USER_OBJ, u32::MAX, 7),
+			(ACL_MASK, u32::MAX, 7),
+			(ACL_USER, 1001, 4),
+			(ACL_GROUP_OBJ, u32::MAX, 5),
+		]);
+		let mut acl = Acl::decode(&data, config).unwrap();
+		assert_eq!(
+			acl.entries,
+			[
+				entry(AclTag::UserObj, 7),
+				entry(AclTag::User(1001), 4),
+				entry(AclTag::User(1002), 6),
+				entry(AclTag::GroupObj, 5),
+				entry(AclTag::Group(20), 5),
+				entry(AclTag::Mask, 7),
+				entry(AclTag::Other, 0),
+			]
+		);
+
+		// The group bits are the mask.
+		acl.sync_from_perm(0o640);
+		assert_eq!(acl.entries[0], entry(AclTag::UserObj, 6));
+		assert_eq!(acl.entries[3], entry(AclTag::GroupObj, 5));
+		assert_eq!(acl.entries[5], entry(AclTag::Mask, 4));
+		assert_eq!(acl.entries[6], entry(AclTag::Other, 0));
+	}
+
+	#[test]
+	fn invalid() {
+		let config = Config::little();
+		let data = encode(&[(ACL_USER_OBJ, u32::MAX, 7)]);
+		assert!(Acl::decode(&data[..100], config).is_err());
+
+		let mut bad = data.clone();
+		bad[0..4].copy_from_slice(&33i32.to_le_bytes());
+		assert!(Acl::decode(&bad, config).is_err());
+
+		let bad = encode(&[(0x40, 0, 7)]);
+		assert!(Acl::decode(&bad, config).is_err());
+	}
+}
@@ -0,0 +1,341 @@
+use std::io::SeekFrom;
+
+use bincode::Decode;
+use tokio::{
+	io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite},
+	sync::Mutex as AsyncMutex,
+};
+
+use super::{
+	dir::{dir_block_len, dir_blocks, readdir_block},
+	inode::{block_path, block_size, find_block, map_block, BlockPath},
+	symlink::{long_target_len, short_target},
+	*,
+};
+use crate::{err, InodeNum};
+
+/// Storage, that can hold an [`AsyncUfs`].
+///
+/// This is implemented for everything, that implements tokio's I/O traits,
+/// eg. `tokio::fs::File` or [`std::io::Cursor`].
+pub trait AsyncBackend: AsyncRead + AsyncWrite + AsyncSeek + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + AsyncSeek + Unpin + Send> AsyncBackend for T {}
+
+/// Asynchronous variant of [`Ufs`], for embedding rufs in async servers.
+///
+/// All I/O is done on an [`AsyncBackend`], so no executor thread is blocked while
+/// waiting for the device. Accesses to the backend are serialized internally.
+///
+/// Unlike [`BlockFile`], the backend doesn't align accesses to the block size of the device,
+/// so it must support reads at arbitrary offsets.
+/// This is the case for image files, but not for raw disk devices on FreeBSD.
+///
+/// # Example
+/// ```
+/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+/// # use support::*;
+/// use std::io::Cursor;
+///
+/// use rufs::{AsyncUfs, InodeNum};
+///
+/// # tokio::runtime::Builder::new_current_thread().build()?.block_on(async {
+/// let ufs = AsyncUfs::new(Cursor::new(golden_image("ufs-little"))).await?;
+/// let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).await?;
+/// let mut buf = [0u8; 23];
+/// let n = ufs.inode_read(inr, 0, &mut buf).await?;
+/// assert_eq!(&buf[0..n], b"This is a simple file.\n");
+/// # Ok::<(), std::io::Error>(())
+/// # })?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct AsyncUfs<B: AsyncBackend> {
+	dev:        AsyncMutex<B>,
+	config:     Config,
+	superblock: Superblock,
+	geo:        FsGeometry,
+	journal:    Option<Journal>,
+}
+
+async fn read_at<B: AsyncBackend>(dev: &mut B, pos: u64, buf: &mut [u8]) -> IoResult<()> {
+	dev.seek(SeekFrom::Start(pos)).await?;
+	dev.read_exact(buf).await?;
+	Ok(())
+}
+
+impl<B: AsyncBackend> AsyncUfs<B> {
+	/// Open a filesystem from an asynchronous backend.
+	///
+	/// The superblock is read and checked, before the filesystem is returned.
+	pub async fn new(mut dev: B) -> IoResult<Self> {
+		let mut magic = [0u8; 4];
+		read_at(&mut dev, SBLOCK_UFS2 as u64 + MAGIC_OFFSET, &mut magic).await?;
+		let config = config_from_magic(magic)?;
+
+		let mut buf = vec![0u8; SBLOCKSIZE];
+		read_at(&mut dev, SBLOCK_UFS2 as u64, &mut buf).await?;
+		let superblock: Superblock = config.decode_slice(&buf)?;
+		check_superblock(&superblock, false)?;
+		Lint::new(&superblock).log();
+		let geo = geometry(&superblock)?;
+
+		let mut s = Self {
+			dev: AsyncMutex::new(dev),
+			config,
+			superblock,
+			geo,
+			journal: None,
+		};
+		s.check().await?;
+		s.journal = Journal::new(&s.superblock, s.journal_file().await?);
+		Ok(s)
+	}
+
+	/// Get filesystem metadata.
+	#[doc(alias("statfs", "statvfs"))]
+	pub fn info(&self) -> Info {
+		Info::new(&self.superblock, self.journal.clone())
+	}
+
+	/// Get the name, and other descriptive metadata of the filesystem.
+	pub fn volume_info(&self) -> VolumeInfo {
+		VolumeInfo::new(&self.superblock)
+	}
+
+	/// Get metadata about an inode.
+	#[doc(alias("stat", "getattr"))]
+	pub async fn inode_attr(&self, inr: InodeNum) -> IoResult<InodeAttr> {
+		let ino = self.read_inode(inr).await?;
+		ino.as_attr(inr)
+	}
+
+	/// Read data from an inode.
+	///
+	/// Reads up to `buffer.len()` bytes starting at `offset`, and returns the number of bytes read,
+	/// which is less, if the end of the file is reached, and 0 at or beyond the end.
+	pub async fn inode_read(
+		&self,
+		inr: InodeNum,
+		mut offset: u64,
+		buffer: &mut [u8],
+	) -> IoResult<usize> {
+		let mut blockbuf = vec![0u8; self.superblock.bsize as usize];
+		let ino = self.read_inode(inr).await?;
+
+		let mut boff = 0;
+		let end = offset.saturating_add(buffer.len() as u64).min(ino.size);
+
+		while offset < end {
+			let block = find_block(&self.superblock, &ino, offset)?;
+			let num = (block.size - block.off).min(end - offset);
+
+			self.inode_read_block(
+				inr,
+				&ino,
+				block.blkidx,
+				&mut blockbuf[0..(block.size as usize)],
+			)
+			.await?;
+			let off = block.off as usize;
+			buffer[boff..(boff + num as usize)]
+				.copy_from_slice(&blockbuf[off..(off + num as usize)]);
+
+			offset += num;
+			boff += num as usize;
+		}
+
+		Ok(boff)
+	}
+
+	/// Find a file named `name` in the directory referenced by `pinr`.
+	///
+	/// ".." of the root directory always refers to the root directory itself.
+	/// Fails with `ENOENT`, if there is no such file,
+	/// and with [`Error::Corrupt`](crate::Error::Corrupt), if the directory is corrupted.
+	pub async fn dir_lookup(&self, pinr: InodeNum, name: &OsStr) -> IoResult<InodeNum> {
+		self.dir_iter(pinr, |name2, inr, _kind| (name == name2).then_some(inr))
+			.await?
+			.ok_or(err!(ENOENT))
+	}
+
+	/// Iterate through a directory referenced by `inr`, and call `f` for each entry.
+	///
+	/// The iteration stops as soon as `f` returns `Some(_)`, which is then returned.
+	pub async fn dir_iter<T>(
+		&self,
+		inr: InodeNum,
+		mut f: impl FnMut(&OsStr, InodeNum, InodeType) -> Option<T>,
+	) -> IoResult<Option<T>> {
+		let ino = self.read_inode(inr).await?;
+		let nblocks = dir_blocks(&self.superblock, inr, &ino)?;
+		let mut block = vec![0u8; self.superblock.bsize as usize];
+
+		for blkidx in 0..nblocks {
+			self.inode_read_block(inr, &ino, blkidx, &mut block).await?;
+			let len = dir_block_len(&self.superblock, &ino, blkidx);
+
+			// The entries are collected first, because the inodes of entries
+			// of unknown type have to be read asynchronously.
+			let mut entries = Vec::new();
+			readdir_block(inr, &block[0..len], self.config, |name, cinr, kind| {
+				entries.push((name.to_owned(), cinr, kind));
+				None::<()>
+			})?;
+
+			// The root directory is its own parent, and whiteouts are skipped,
+			// like in `Ufs::dir_iter()`.
+			for (name, cinr, kind) in entries {
+				let x = match kind {
+					_ if inr == InodeNum::ROOT && name == ".." => {
+						f(&name, InodeNum::ROOT, InodeType::Directory)
+					}
+					Some(InodeType::Whiteout) => None,
+					Some(kind) => f(&name, cinr, kind),
+					None => {
+						let ino = self.read_inode(cinr).await;
+						let kind = ino.and_then(|ino| ino.kind()).map_err(|e| {
+							corrupt!("dir_iter({inr}): can't get the type of entry {name:?} -> {cinr}: {e}")
+						})?;
+						f(&name, cinr, kind)
+					}
+				};
+				if x.is_some() {
+					return Ok(x);
+				}
+			}
+		}
+		Ok(None)
+	}
+
+	/// Read the contents of a symbolic link.
+	#[doc(alias = "readlink")]
+	pub async fn symlink_read(&self, inr: InodeNum) -> IoResult<Vec<u8>> {
+		let ino = self.read_inode(inr).await?;
+
+		if ino.mode & S_IFMT != S_IFLNK {
+			return Err(err!(EINVAL));
+		}
+
+		match &ino.data {
+			InodeData::Shortlink(link) => short_target(inr, &ino, link),
+			InodeData::Blocks { .. } => {
+				let len = long_target_len(inr, &ino)?;
+				let mut buf = vec![0u8; len];
+				let n = self.inode_read(inr, 0, &mut buf).await?;
+				buf.truncate(n);
+				Ok(buf)
+			}
+		}
+	}
+
+	async fn read_at(&self, pos: u64, buf: &mut [u8]) -> IoResult<()> {
+		let mut dev = self.dev.lock().await;
+		read_at(&mut *dev, pos, buf).await
+	}
+
+	async fn decode_at<T: Decode>(&self, pos: u64, len: usize) -> IoResult<T> {
+		let mut buf = vec![0u8; len];
+		self.read_at(pos, &mut buf).await?;
+		self.config.decode_slice(&buf)
+	}
+
+	/// Find the journal file, if journaling is enabled.
+	async fn journal_file(&self) -> IoResult<Option<(InodeNum, u64)>> {
+		if self.superblock.flags & FS_SUJ == 0 {
+			return Ok(None);
+		}
+		match self.dir_lookup(InodeNum::ROOT, SUJ_FILE.as_ref()).await {
+			Ok(inr) => Ok(Some((inr, self.read_inode(inr).await?.size))),
+			Err(e) if e.raw_os_error() == Some(libc::ENOENT) => Ok(None),
+			Err(e) => Err(e),
+		}
+	}
+
+	async fn check(&self) -> IoResult<()> {
+		for i in 0..self.geo.ncg() {
+			let addr = cg_addr(&self.geo, i, self.geo.sblkno())?;
+			let csb: Superblock = self.decode_at(addr, SBLOCKSIZE).await?;
+			if csb.magic != FS_UFS2_MAGIC {
+				return Err(
+					corrupt!(@ Some(addr), "CG{i} has invalid superblock magic: {:x}", csb.magic),
+				);
+			}
+		}
+
+		for i in 0..self.geo.ncg() {
+			let addr = cg_addr(&self.geo, i, self.geo.cblkno())?;
+			let cg: CylGroup = self.decode_at(addr, size_of::<CylGroup>()).await?;
+			if cg.magic != CG_MAGIC {
+				return Err(corrupt!(@ Some(addr), "CG{i} has invalid cg magic: {:x}", cg.magic));
+			}
+		}
+		Ok(())
+	}
+
+	async fn read_inode(&self, inr: InodeNum) -> IoResult<Inode> {
+		let off = ino_addr(&self.geo, inr)?;
+		let ino: Inode = self.decode_at(off, UFS_INOSZ).await?;
+
+		if (ino.mode & S_IFMT) == 0 {
+			log::warn!("invalid inode {inr}");
+			return Err(err!(EINVAL));
+		}
+
+		Ok(ino)
+	}
+
+	async fn inode_read_block(
+		&self,
+		inr: InodeNum,
+		ino: &Inode,
+		blkidx: u64,
+		buf: &mut [u8],
+	) -> IoResult<usize> {
+		let size = block_size(&self.superblock, ino, blkidx)?;
+		match self.inode_resolve_block(inr, ino, blkidx).await? {
+			Some(blkno) => {
+				self.read_at(frag_addr(&self.geo, blkno.get())?, &mut buf[0..size])
+					.await?
+			}
+			None => buf.fill(0u8),
+		}
+
+		Ok(size)
+	}
+
+	async fn inode_resolve_block(
+		&self,
+		inr: InodeNum,
+		ino: &Inode,
+		blkno: u64,
+	) -> IoResult<Option<NonZeroU64>> {
+		let sb = &self.superblock;
+		let su64 = size_of::<UfsDaddr>() as u64;
+
+		let InodeData::Blocks(InodeBlocks { direct, indirect }) = &ino.data else {
+			return Err(corrupt!(
+				"resolve_file_block({inr}, {blkno}): inode doesn't have blocks"
+			));
+		};
+
+		match block_path(sb, blkno) {
+			BlockPath::Direct(i) => Ok(map_block(sb, ino, blkno, direct[i] as u64)),
+			BlockPath::Indirect(level, idx) => {
+				let mut ptr = indirect[level] as u64;
+				for &i in &idx[0..=level] {
+					if ptr == 0 || (ino.is_snapshot() && ptr < sb.frag as u64) {
+						return Ok(map_block(sb, ino, blkno, ptr));
+					}
+					ptr = self
+						.decode_at(frag_addr(&self.geo, ptr)? + i * su64, size_of::<u64>())
+						.await?;
+				}
+				Ok(map_block(sb, ino, blkno, ptr))
+			}
+			BlockPath::OutOfRange => {
+				log::warn!("resolve_file_block({inr}, {blkno}): block number too large");
+				Ok(None)
+			}
+		}
+	}
+}
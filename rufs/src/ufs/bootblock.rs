@@ -0,0 +1,49 @@
+use super::*;
+use crate::err;
+
+/// Size of the boot area, which precedes the superblock.
+pub const BOOTBLOCK_SIZE: usize = SBLOCK_UFS2;
+
+impl<B: Backend> Ufs<B> {
+	/// Read the boot area, the first [`BOOTBLOCK_SIZE`] bytes before the superblock,
+	/// which may hold boot code, like FreeBSD's boot2.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::BOOTBLOCK_SIZE;
+	///
+	/// # let ufs = example_image();
+	/// let boot = ufs.read_bootblock()?;
+	/// assert_eq!(boot.len(), BOOTBLOCK_SIZE);
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	#[doc(alias = "bootcode")]
+	pub fn read_bootblock(&self) -> IoResult<Vec<u8>> {
+		let mut buf = vec![0u8; BOOTBLOCK_SIZE];
+		self.read_at(0, &mut buf)?;
+		Ok(buf)
+	}
+
+	/// Replace the beginning of the boot area by `data`, and keep the rest of it.
+	///
+	/// `data` can't be larger than [`BOOTBLOCK_SIZE`], so the superblock is never touched,
+	/// otherwise this fails with `EINVAL`.
+	/// This doesn't modify the filesystem itself, so it works without write support,
+	/// but the backend must be writable. Call [`Ufs::sync()`] afterwards.
+	pub fn write_bootblock(&self, data: &[u8]) -> IoResult<()> {
+		if data.len() > BOOTBLOCK_SIZE {
+			log::error!(
+				"write_bootblock(): {} bytes don't fit into the boot area of {BOOTBLOCK_SIZE} bytes",
+				data.len()
+			);
+			return Err(err!(EINVAL));
+		}
+
+		// Backends may only support writing whole blocks.
+		let mut buf = self.read_bootblock()?;
+		buf[..data.len()].copy_from_slice(data);
+		self.backend.write_at(0, &buf)
+	}
+}
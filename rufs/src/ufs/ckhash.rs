@@ -0,0 +1,79 @@
+//! Verifying the check-hashes of the metadata, which [`Lint::check_hashes`] expects.
+use super::*;
+
+/// CRC-32C (Castagnoli) lookup table, for the reflected polynomial.
+const CRC32C_TABLE: [u32; 256] = {
+	let mut table = [0u32; 256];
+	let mut i = 0;
+	while i < 256 {
+		let mut crc = i as u32;
+		let mut bit = 0;
+		while bit < 8 {
+			crc = if crc & 1 != 0 {
+				(crc >> 1) ^ 0x82f6_3b78
+			} else {
+				crc >> 1
+			};
+			bit += 1;
+		}
+		table[i] = crc;
+		i += 1;
+	}
+	table
+};
+
+/// The check-hash of `buf`, computed like `calculate_crc32c(~0L, buf, len)` of FreeBSD,
+/// with the check-hash itself at `field` treated as 0.
+///
+/// Unlike the standard CRC-32C, the result isn't inverted.
+pub(super) fn ckhash(buf: &[u8], field: usize) -> u32 {
+	buf.iter().enumerate().fold(!0u32, |crc, (i, &b)| {
+		let b = if (field..(field + 4)).contains(&i) {
+			0
+		} else {
+			b
+		};
+		CRC32C_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8)
+	})
+}
+
+/// Offset of `fs_ckhash` in the superblock.
+const SB_CKHASH: usize = 1304;
+
+/// Offset of `cg_ckhash` in the cylinder group.
+pub(super) const CG_CKHASH: usize = 132;
+
+/// Offset of `di_ckhash` in the inode.
+pub(super) const DI_CKHASH: usize = 244;
+
+impl<B: Backend> Ufs<B> {
+	/// Whether the filesystem is expected to protect `what` (`CK_*`) with check-hashes.
+	pub(super) fn has_ckhash(&self, what: u32) -> bool {
+		self.lint.check_hashes & what != 0
+	}
+
+	/// Whether the check-hash, which is stored at `field` of `buf`, is valid.
+	pub(super) fn verify_ckhash(&self, buf: &[u8], field: usize) -> IoResult<bool> {
+		let Some(stored) = buf.get(field..(field + 4)) else {
+			return Ok(false);
+		};
+		let stored: u32 = self.config.decode_slice(stored)?;
+		Ok(ckhash(buf, field) == stored)
+	}
+
+	/// Verify the check-hash of the superblock, which is in use.
+	///
+	/// Returns `true`, if the filesystem doesn't protect the superblock by a check-hash.
+	pub(super) fn verify_superblock_ckhash(&self) -> IoResult<bool> {
+		if !self.has_ckhash(CK_SUPERBLOCK) {
+			return Ok(true);
+		}
+		let pos = match self.alternate {
+			Some(sector) => sector.saturating_mul(DEV_BSIZE as u64),
+			None => SBLOCK_UFS2 as u64,
+		};
+		let mut buf = vec![0u8; self.superblock.sbsize.max(0) as usize];
+		self.read_at(pos, &mut buf)?;
+		self.verify_ckhash(&buf, SB_CKHASH)
+	}
+}
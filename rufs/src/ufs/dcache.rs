@@ -0,0 +1,125 @@
+use std::{
+	collections::HashMap,
+	sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use super::*;
+use crate::InodeNum;
+
+struct Inner {
+	/// Cached lookups per directory, `None` means that the name doesn't exist.
+	dirs: HashMap<InodeNum, HashMap<OsString, Option<InodeNum>>>,
+	len:  usize,
+}
+
+/// Cache of directory lookups, including lookups of names, which don't exist.
+///
+/// Filesystems can't be modified through rufs yet, so entries never become stale.
+/// TODO: invalidate the entries of a directory, when it is modified.
+pub(super) struct DentryCache {
+	capacity: usize,
+	inner:    Mutex<Inner>,
+}
+
+impl DentryCache {
+	pub(super) fn new(capacity: usize) -> Self {
+		Self {
+			capacity,
+			inner: Mutex::new(Inner {
+				dirs: HashMap::new(),
+				len:  0,
+			}),
+		}
+	}
+
+	fn lock(&self) -> MutexGuard<'_, Inner> {
+		// Entries are only inserted as a whole, so a poisoned lock is harmless.
+		self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	/// Look up `name` in `dir`. Returns `Some(None)`, if `name` is known not to exist.
+	pub(super) fn get(&self, dir: InodeNum, name: &OsStr) -> Option<Option<InodeNum>> {
+		if self.capacity == 0 {
+			return None;
+		}
+		self.lock().dirs.get(&dir)?.get(name).copied()
+	}
+
+	/// Remember the result of looking up `name` in `dir`.
+	pub(super) fn insert(&self, dir: InodeNum, name: &OsStr, inr: Option<InodeNum>) {
+		if self.capacity == 0 {
+			return;
+		}
+
+		let mut c = self.lock();
+		if c.len >= self.capacity {
+			c.dirs.clear();
+			c.len = 0;
+		}
+		if c.dirs
+			.entry(dir)
+			.or_default()
+			.insert(name.into(), inr)
+			.is_none()
+		{
+			c.len += 1;
+		}
+	}
+}
+
+/// Maximum number of directories, whose parent is remembered.
+const MAX_PARENTS: usize = 1 << 16;
+
+/// Parents of the directories, that were seen while listing other directories.
+#[derive(Default)]
+pub(super) struct Parents {
+	map: Mutex<HashMap<InodeNum, InodeNum>>,
+}
+
+impl Parents {
+	pub(super) fn insert(&self, dir: InodeNum, parent: InodeNum) {
+		// The map is only used as a fallback, so a poisoned lock is harmless.
+		let mut map = self.map.lock().unwrap_or_else(PoisonError::into_inner);
+		if map.len() >= MAX_PARENTS {
+			map.clear();
+		}
+		map.insert(dir, parent);
+	}
+
+	pub(super) fn get(&self, dir: InodeNum) -> Option<InodeNum> {
+		if dir == InodeNum::ROOT {
+			return Some(dir);
+		}
+		let map = self.map.lock().unwrap_or_else(PoisonError::into_inner);
+		map.get(&dir).copied()
+	}
+}
+
+#[cfg(test)]
+mod t {
+	use super::*;
+
+	#[test]
+	fn negative() {
+		let dc = DentryCache::new(2);
+		let dir = InodeNum::ROOT;
+		assert_eq!(dc.get(dir, "a".as_ref()), None);
+
+		dc.insert(dir, "a".as_ref(), None);
+		dc.insert(dir, "b".as_ref(), Some(dir));
+		assert_eq!(dc.get(dir, "a".as_ref()), Some(None));
+		assert_eq!(dc.get(dir, "b".as_ref()), Some(Some(dir)));
+
+		// The cache is full, so it starts over.
+		dc.insert(dir, "c".as_ref(), None);
+		assert_eq!(dc.get(dir, "a".as_ref()), None);
+		assert_eq!(dc.get(dir, "c".as_ref()), Some(None));
+	}
+
+	#[test]
+	fn disabled() {
+		let dc = DentryCache::new(0);
+		dc.insert(InodeNum::ROOT, "a".as_ref(), None);
+		assert_eq!(dc.get(InodeNum::ROOT, "a".as_ref()), None);
+	}
+}
@@ -1,49 +1,137 @@
-use super::*;
+use std::{
+	collections::{HashMap, VecDeque},
+	mem,
+};
+
+use super::{
+	dirhash::{Stamp, DIRHASH_MINSIZE},
+	inode::MAX_READ,
+	*,
+};
 use crate::{err, InodeNum};
 
-fn readdir_block<T>(
+/// Directories larger than this are considered to be corrupted.
+const MAX_DIR_SIZE: u64 = 1 << 30;
+
+/// Number of blocks of the directory `ino`, after checking that it is sane.
+pub(super) fn dir_blocks(sb: &Superblock, inr: InodeNum, ino: &Inode) -> IoResult<u64> {
+	let bs = sb.bsize as u64;
+	let fs = sb.fsize as u64;
+	let frag = sb.frag as u64;
+
+	if ino.kind()? != InodeType::Directory {
+		return Err(err!(ENOTDIR));
+	}
+
+	if ino.size > MAX_DIR_SIZE || ino.blocks / frag > MAX_DIR_SIZE / fs {
+		log::error!(
+			"dir_iter({inr}): directory is too large: size={}, blocks={}",
+			ino.size,
+			ino.blocks
+		);
+		return Err(err!(EFBIG));
+	}
+
+	Ok(ino.size.div_ceil(bs))
+}
+
+/// Number of bytes of block `blkidx` of the directory `ino`, that contain entries.
+pub(super) fn dir_block_len(sb: &Superblock, ino: &Inode, blkidx: u64) -> usize {
+	let bs = sb.bsize as u64;
+	(ino.size - blkidx * bs).min(bs) as usize
+}
+
+/// Check whether `name` may be the name of a new directory entry.
+///
+/// Fails with `EINVAL`, if `name` is not a valid name, and with `ENAMETOOLONG`, if it is too long.
+pub(super) fn check_name(name: &OsStr) -> IoResult<()> {
+	let bytes = name.as_bytes();
+	if bytes.is_empty() || name == "." || name == ".." || bytes.contains(&b'/') {
+		return Err(err!(EINVAL));
+	}
+	if bytes.len() > UFS_MAXNAMELEN {
+		return Err(err!(ENAMETOOLONG));
+	}
+	Ok(())
+}
+
+/// Convert the file type of a directory entry, unless it is a whiteout or unknown.
+pub(super) fn dt_kind(dt: u8) -> Option<InodeType> {
+	match dt {
+		DT_FIFO => Some(InodeType::NamedPipe),
+		DT_CHR => Some(InodeType::CharDevice),
+		DT_DIR => Some(InodeType::Directory),
+		DT_BLK => Some(InodeType::BlockDevice),
+		DT_REG => Some(InodeType::RegularFile),
+		DT_LNK => Some(InodeType::Symlink),
+		DT_SOCK => Some(InodeType::Socket),
+		_ => None,
+	}
+}
+
+/// Parse the directory entries in `block`, and call `f` for each of them.
+///
+/// Whiteouts are reported with [`InodeNum::WHITEOUT`] and [`InodeType::Whiteout`].
+/// The type of entries is `None`, if it is `DT_UNKNOWN`, like on older filesystems,
+/// so that callers, which need it, can read it from the inode.
+/// Fails with [`crate::Error::Corrupt`], if an entry is malformed.
+pub(super) fn readdir_block<T>(
 	inr: InodeNum,
 	block: &[u8],
 	config: Config,
-	mut f: impl FnMut(&OsStr, InodeNum, InodeType) -> Option<T>,
+	mut f: impl FnMut(&OsStr, InodeNum, Option<InodeType>) -> Option<T>,
 ) -> IoResult<Option<T>> {
-	let mut name = [0u8; UFS_MAXNAMELEN + 1];
-	let file = Cursor::new(block);
-	let mut file = Decoder::new(file, config);
-
-	loop {
-		let Ok(ino) = file.decode::<InodeNum>() else {
-			break;
+	let mut off = 0;
+	while off < block.len() {
+		let Some(hdr) = block.get(off..(off + 8)) else {
+			return Err(corrupt!(
+				"readdir_block({inr}): truncated entry at offset {off}"
+			));
 		};
-		if ino.get() == 0 {
-			break;
+		// struct direct: ino (4), reclen (2), type (1), namelen (1), name
+		let ino: InodeNum = config.decode_slice(&hdr[0..4])?;
+		let reclen = config.decode_slice::<u16>(&hdr[4..6])? as usize;
+		let kind = hdr[6];
+		let namelen = hdr[7] as usize;
+
+		if reclen < namelen + 8 ||
+			off % DIRBLKSIZ + reclen > DIRBLKSIZ ||
+			off + reclen > block.len()
+		{
+			return Err(corrupt!(
+				"readdir_block({inr}): invalid record length {reclen} for entry {ino} at offset {off}"
+			));
 		}
 
-		let reclen: u16 = file.decode()?;
-		let kind: u8 = file.decode()?;
-		let namelen: u8 = file.decode()?;
-		let name = &mut name[0..namelen.into()];
-		file.read(name)?;
+		let name = &block[(off + 8)..(off + 8 + namelen)];
+		off += reclen;
 
-		// skip remaining bytes of record, if any
-		let off = reclen - (namelen as u16) - 8;
-		file.seek_relative(off as i64)?;
+		// deleted entry
+		if ino.get() == 0 {
+			continue;
+		}
 
-		let name = unsafe { OsStr::from_encoded_bytes_unchecked(name) };
-		let kind = match kind {
-			DT_FIFO => InodeType::NamedPipe,
-			DT_CHR => InodeType::CharDevice,
-			DT_DIR => InodeType::Directory,
-			DT_BLK => InodeType::BlockDevice,
-			DT_REG => InodeType::RegularFile,
-			DT_LNK => InodeType::Symlink,
-			DT_SOCK => InodeType::Socket,
-			DT_WHT => {
-				log::warn!("readdir_block({inr}): encountered a whiteout entry: {name:?}");
-				continue;
+		// Names may contain any bytes, except for these, like fsck_ffs(8) checks.
+		let name = OsStr::from_bytes(name);
+		if name.as_bytes().iter().any(|&b| b == b'\0' || b == b'/') {
+			return Err(corrupt!(
+				"readdir_block({inr}): invalid name {name:?} of entry {ino}"
+			));
+		}
+
+		let (ino, kind) = match kind {
+			DT_WHT => (InodeNum::WHITEOUT, Some(InodeType::Whiteout)),
+			DT_UNKNOWN => (ino, None),
+			_ => {
+				match dt_kind(kind) {
+					Some(kind) => (ino, Some(kind)),
+					None => {
+						return Err(corrupt!(
+							"readdir_block({inr}): invalid file type {kind} of entry {name:?}"
+						));
+					}
+				}
 			}
-			DT_UNKNOWN => todo!("DT_UNKNOWN: {ino}"),
-			_ => panic!("invalid filetype: {kind}"),
 		};
 		let res = f(name, ino, kind);
 		if res.is_some() {
@@ -54,40 +142,567 @@ fn readdir_block<T>(
 	Ok(None)
 }
 
-impl<R: Read + Seek> Ufs<R> {
+impl<B: Backend> Ufs<B> {
 	/// Find a file named `name` in the directory referenced by `pinr`.
-	pub fn dir_lookup(&mut self, pinr: InodeNum, name: &OsStr) -> IoResult<InodeNum> {
-		self.dir_iter(
-			pinr,
-			|name2, inr, _kind| {
-				if name == name2 {
-					Some(inr)
-				} else {
-					None
-				}
-			},
-		)?
-		.ok_or(err!(ENOENT))
+	///
+	/// ".." of the root directory always refers to the root directory itself.
+	/// Whiteouts are found as [`InodeNum::WHITEOUT`], if [`Options::whiteouts`] is set.
+	/// Fails with `ENOENT`, if there is no such file,
+	/// and with [`Error::Corrupt`](crate::Error::Corrupt), if the directory is corrupted.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::InodeNum;
+	///
+	/// # let ufs = example_image();
+	/// let dir1 = ufs.dir_lookup(InodeNum::ROOT, "dir1".as_ref())?;
+	/// let dir2 = ufs.dir_lookup(dir1, "dir2".as_ref())?;
+	/// assert_eq!(ufs.dir_lookup(dir2, "..".as_ref())?, dir1);
+	///
+	/// let err = ufs.dir_lookup(InodeNum::ROOT, "nonexistent".as_ref()).unwrap_err();
+	/// assert_eq!(err.raw_os_error(), Some(libc::ENOENT));
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	pub fn dir_lookup(&self, pinr: InodeNum, name: &OsStr) -> IoResult<InodeNum> {
+		self.statahead(pinr);
+		let res = match self.dcache.get(pinr, name) {
+			Some(res) => res,
+			None => {
+				let res = self.dir_find(pinr, name)?;
+				self.dcache.insert(pinr, name, res);
+				res
+			}
+		};
+		res.ok_or(err!(ENOENT))
+	}
+
+	/// Find `name` in the directory `pinr`, using a hash index, if the directory is large.
+	fn dir_find(&self, pinr: InodeNum, name: &OsStr) -> IoResult<Option<InodeNum>> {
+		let ino = self.read_inode(pinr)?;
+		if ino.size < DIRHASH_MINSIZE || !self.dirhash.enabled() {
+			return self.dir_iter(pinr, |name2, inr, _kind| (name == name2).then_some(inr));
+		}
+
+		let stamp = Stamp::new(&ino);
+		if let Some(res) = self.dirhash.get(pinr, stamp, name) {
+			return Ok(res);
+		}
+
+		// Like a linear search, the first entry of a name wins.
+		let mut names = HashMap::new();
+		self.dir_iter(pinr, |name, inr, _kind| {
+			names.entry(name.to_owned()).or_insert(inr);
+			None::<()>
+		})?;
+		let res = names.get(name).copied();
+		log::debug!("dir_find({pinr}): indexed {} entries", names.len());
+		self.dirhash.insert(pinr, stamp, names);
+		Ok(res)
 	}
 
 	/// Iterate through a directory referenced by `inr`, and call `f` for each entry.
+	///
+	/// Like [`Ufs::dir_lookup()`], ".." of the root directory refers to the root directory.
+	/// Whiteouts are skipped, unless [`Options::whiteouts`] is set.
+	///
+	/// The iteration stops as soon as `f` returns `Some(_)`, which is then returned.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::{InodeNum, InodeType};
+	///
+	/// # let ufs = example_image();
+	/// let mut dirs = Vec::new();
+	/// ufs.dir_iter(InodeNum::ROOT, |name, _inr, kind| {
+	///     if kind == InodeType::Directory {
+	///         dirs.push(name.to_owned());
+	///     }
+	///     None::<()>
+	/// })?;
+	/// assert_eq!(dirs, [".", "..", ".snap", "dir1"]);
+	///
+	/// // Stop at the first symbolic link
+	/// let link = ufs.dir_iter(InodeNum::ROOT, |name, _inr, kind| {
+	///     (kind == InodeType::Symlink).then(|| name.to_owned())
+	/// })?;
+	/// assert_eq!(link.unwrap(), "link1");
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
 	pub fn dir_iter<T>(
-		&mut self,
+		&self,
 		inr: InodeNum,
 		mut f: impl FnMut(&OsStr, InodeNum, InodeType) -> Option<T>,
 	) -> IoResult<Option<T>> {
-		let ino = self.read_inode(inr)?;
-		let mut block = vec![0u8; self.superblock.bsize as usize];
-		let frag = self.superblock.frag as u64;
+		for entry in self.dir_entries(inr) {
+			let entry = entry?;
+			if let Some(x) = f(&entry.name, entry.inr, entry.kind) {
+				return Ok(Some(x));
+			}
+		}
+		Ok(None)
+	}
+
+	/// Iterate through a directory referenced by `inr`.
+	///
+	/// Unlike [`Ufs::dir_iter()`], this allows using `?` and iterator adapters.
+	/// The entries are the same, and the iteration stops after the first error,
+	/// which is also returned, if `inr` is not a directory.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::{InodeNum, InodeType};
+	///
+	/// # let ufs = example_image();
+	/// let mut dirs = Vec::new();
+	/// for entry in ufs.dir_entries(InodeNum::ROOT) {
+	///     let entry = entry?;
+	///     if entry.kind == InodeType::Directory {
+	///         dirs.push(entry.name);
+	///     }
+	/// }
+	/// assert_eq!(dirs, [".", "..", ".snap", "dir1"]);
+	///
+	/// let link = ufs
+	///     .dir_entries(InodeNum::ROOT)
+	///     .find(|e| e.as_ref().map_or(true, |e| e.kind == InodeType::Symlink))
+	///     .transpose()?;
+	/// assert_eq!(link.unwrap().name, "link1");
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	pub fn dir_entries(&self, inr: InodeNum) -> DirEntries<'_, B> {
+		let mut iter = DirEntries {
+			ufs: self,
+			inr,
+			ino: None,
+			nblocks: 0,
+			blkidx: 0,
+			buf: Vec::new(),
+			raw: VecDeque::new(),
+			error: None,
+		};
+		let res = self
+			.read_inode(inr)
+			.and_then(|ino| Ok((dir_blocks(&self.superblock, inr, &ino)?, ino)));
+		match res {
+			Ok((nblocks, ino)) => {
+				iter.nblocks = nblocks;
+				iter.ino = Some(ino);
+			}
+			Err(e) => iter.error = Some(e),
+		}
+		if self.options.synthesize_dots && iter.nblocks == 0 && iter.ino.is_some() {
+			let res = self.synthesize_dots(inr, &[], &mut DirEntries::<B>::queue(&mut iter.raw));
+			iter.error = res.err();
+		}
+		iter
+	}
+
+	/// Handle the raw entry `name` of the directory `inr`, see [`Ufs::dir_iter()`].
+	///
+	/// Returns `None`, if the entry is skipped.
+	fn dir_entry(
+		&self,
+		inr: InodeNum,
+		name: OsString,
+		cinr: InodeNum,
+		kind: Option<InodeType>,
+	) -> Option<IoResult<DirEntry>> {
+		let entry = |name, inr, kind| Some(Ok(DirEntry { name, inr, kind }));
 
-		for blkidx in 0..(ino.blocks / frag) {
-			let size = self.inode_read_block(inr, &ino, blkidx, &mut block)?;
+		// The root directory is its own parent, even if its ".." says otherwise.
+		if inr == InodeNum::ROOT && name == ".." {
+			return entry(name, InodeNum::ROOT, InodeType::Directory);
+		}
+		if kind == Some(InodeType::Whiteout) {
+			if !self.options.whiteouts {
+				log::debug!("dir_iter({inr}): skipping whiteout {name:?}");
+				return None;
+			}
+			return entry(name, cinr, InodeType::Whiteout);
+		}
+		if self.options.dangling_entries == DanglingEntries::Hide && self.is_dangling(cinr) {
+			log::warn!("dir_iter({inr}): hiding dangling entry {name:?} -> {cinr}");
+			self.dangling.fetch_add(1, Ordering::Relaxed);
+			return None;
+		}
+		let kind = match kind {
+			Some(kind) => kind,
+			None => {
+				match self.dirent_kind(inr, &name, cinr) {
+					Ok(kind) => kind,
+					Err(e) => return Some(Err(e)),
+				}
+			}
+		};
+		if self.options.synthesize_dots &&
+			kind == InodeType::Directory &&
+			name != "." &&
+			name != ".."
+		{
+			self.parents.insert(cinr, inr);
+		}
+		entry(name, cinr, kind)
+	}
+
+	/// Create a whiteout named `name` in the directory `dinr`, which hides a file of the same
+	/// name in a lower layer of a union filesystem, like `unionfs` on FreeBSD does.
+	///
+	/// This requires [`WriteCaps::create`].
+	/// Fails with `EINVAL`, if `name` is not a valid name, with `ENAMETOOLONG`, if it is too long,
+	/// with `EEXIST`, if it already exists, and with `EROFS`, if the filesystem is read-only.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::InodeNum;
+	///
+	/// # let ufs = example_image();
+	/// // There is no write support yet.
+	/// let err = ufs.dir_whiteout(InodeNum::ROOT, "file1".as_ref()).unwrap_err();
+	/// assert_eq!(err.raw_os_error(), Some(libc::EROFS));
+	/// ```
+	#[doc(alias = "whiteout")]
+	pub fn dir_whiteout(&self, dinr: InodeNum, name: &OsStr) -> IoResult<()> {
+		self.check_create(dinr, name)?;
+
+		// TODO: insert a DT_WHT entry with WINO, once directory insertion is implemented.
+		log::error!("dir_whiteout({dinr}, {name:?}): write support is not implemented");
+		Err(err!(EROFS))
+	}
+
+	/// Check whether an entry named `name` may be created in the directory `dinr`.
+	///
+	/// Fails with `EINVAL`, if `name` is not a valid name, with `ENAMETOOLONG`, if it is too long,
+	/// with `EEXIST`, if it already exists, and with `EROFS`, if [`WriteCaps::create`] isn't set.
+	pub(super) fn check_create(&self, dinr: InodeNum, name: &OsStr) -> IoResult<()> {
+		check_name(name)?;
+		if !self.options.write.create {
+			return Err(err!(EROFS));
+		}
+		match self.dir_lookup(dinr, name) {
+			Ok(_) => Err(err!(EEXIST)),
+			Err(e) if e.raw_os_error() == Some(libc::ENOENT) => Ok(()),
+			Err(e) => Err(e),
+		}
+	}
+
+	/// Type of the entry `name` of the directory `inr`, which is `DT_UNKNOWN`,
+	/// taken from the mode of the inode `cinr`.
+	///
+	/// Like an invalid type, an inode, that can't be read, makes the whole directory unreadable.
+	fn dirent_kind(&self, inr: InodeNum, name: &OsStr, cinr: InodeNum) -> IoResult<InodeType> {
+		self.read_inode(cinr)
+			.and_then(|ino| ino.kind())
+			.map_err(|e| {
+				corrupt!("dir_iter({inr}): can't get the type of entry {name:?} -> {cinr}: {e}")
+			})
+	}
+
+	/// Call `f` for "." and "..", if they are missing in `block`, the first block of `inr`.
+	fn synthesize_dots<T>(
+		&self,
+		inr: InodeNum,
+		block: &[u8],
+		f: &mut impl FnMut(&OsStr, InodeNum, Option<InodeType>) -> Option<T>,
+	) -> IoResult<Option<T>> {
+		let (mut dot, mut dotdot) = (false, false);
+		readdir_block(inr, block, self.config, |name, _, _| {
+			dot |= name == ".";
+			dotdot |= name == "..";
+			None::<()>
+		})?;
 
-			let x = readdir_block(inr, &block[0..size], self.file.config(), &mut f)?;
+		if !dot {
+			log::warn!("dir_iter({inr}): synthesizing missing \".\"");
+			let x = f(".".as_ref(), inr, Some(InodeType::Directory));
 			if x.is_some() {
 				return Ok(x);
 			}
 		}
+
+		if !dotdot {
+			let parent = match inr {
+				InodeNum::ROOT => Some(InodeNum::ROOT),
+				_ => self.parents.get(inr),
+			};
+			let Some(parent) = parent else {
+				log::warn!("dir_iter({inr}): \"..\" is missing, and the parent is unknown");
+				return Ok(None);
+			};
+			log::warn!("dir_iter({inr}): synthesizing missing \"..\"");
+			return Ok(f("..".as_ref(), parent, Some(InodeType::Directory)));
+		}
+
 		Ok(None)
 	}
+
+	/// Check whether `inr` refers to an unallocated inode.
+	/// Inodes, which can't be read, are not considered to be dangling,
+	/// so that the error shows up when they are accessed.
+	fn is_dangling(&self, inr: InodeNum) -> bool {
+		if !self.inode_inited(inr).unwrap_or(true) {
+			return true;
+		}
+		let Ok(off) = ino_addr(&self.geo, inr) else {
+			return false;
+		};
+		match self.decode_at::<Inode>(off, UFS_INOSZ) {
+			Ok(ino) => ino.mode & S_IFMT == 0,
+			Err(_) => false,
+		}
+	}
+}
+
+/// An entry of a directory, see [`Ufs::dir_entries()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DirEntry {
+	/// Name of the entry.
+	pub name: OsString,
+
+	/// Inode number of the entry, which is [`InodeNum::WHITEOUT`] for whiteouts.
+	pub inr: InodeNum,
+
+	/// File type of the entry.
+	pub kind: InodeType,
+}
+
+/// Iterator over the entries of a directory, returned by [`Ufs::dir_entries()`].
+///
+/// Large directories are read using as few reads as possible, like by [`Ufs::dir_iter()`],
+/// so only the entries of up to 128K are kept in memory at a time.
+pub struct DirEntries<'a, B: Backend> {
+	ufs:     &'a Ufs<B>,
+	inr:     InodeNum,
+	/// The directory, or `None`, once the iteration stopped.
+	ino:     Option<Inode>,
+	nblocks: u64,
+	blkidx:  u64,
+	buf:     Vec<u8>,
+	/// Entries, which were read, but not handled yet.
+	raw:     VecDeque<(OsString, InodeNum, Option<InodeType>)>,
+	/// Error, which is returned after the entries, that were read before it occurred.
+	error:   Option<IoError>,
+}
+
+impl<B: Backend> DirEntries<'_, B> {
+	/// Queue the entry `name`, which will be handled, when it is returned.
+	fn queue(
+		raw: &mut VecDeque<(OsString, InodeNum, Option<InodeType>)>,
+	) -> impl FnMut(&OsStr, InodeNum, Option<InodeType>) -> Option<()> + '_ {
+		|name, inr, kind| {
+			raw.push_back((name.to_owned(), inr, kind));
+			None
+		}
+	}
+
+	/// Stop the iteration, after an error was returned.
+	fn stop(&mut self) {
+		self.ino = None;
+		self.raw.clear();
+		self.error = None;
+	}
+
+	/// Read the next blocks of the directory, and queue their entries.
+	fn read_blocks(&mut self) {
+		let Some(ino) = &self.ino else {
+			return;
+		};
+		let ufs = self.ufs;
+		let bs = ufs.superblock.bsize as usize;
+		if self.buf.is_empty() {
+			self.buf = vec![0u8; MAX_READ.max(bs) / bs * bs];
+		}
+
+		let start = self.blkidx;
+		let n = match ufs.inode_read_blocks(self.inr, ino, start, self.nblocks, &mut self.buf) {
+			Ok(n) => n,
+			Err(e) => {
+				self.error = Some(e);
+				return;
+			}
+		};
+		self.blkidx += n;
+
+		let buf = mem::take(&mut self.buf);
+		for (blkidx, block) in (start..(start + n)).zip(buf.chunks(bs)) {
+			let len = dir_block_len(&ufs.superblock, ino, blkidx);
+			let block = &block[0..len];
+
+			if ufs.options.synthesize_dots && blkidx == 0 {
+				let res = ufs.synthesize_dots(self.inr, block, &mut Self::queue(&mut self.raw));
+				if let Err(e) = res {
+					self.error = Some(e);
+					break;
+				}
+			}
+
+			let res = readdir_block(self.inr, block, ufs.config, Self::queue(&mut self.raw));
+			if let Err(e) = res {
+				self.error = Some(e);
+				break;
+			}
+		}
+		self.buf = buf;
+	}
+}
+
+impl<B: Backend> Iterator for DirEntries<'_, B> {
+	type Item = IoResult<DirEntry>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		loop {
+			if let Some((name, cinr, kind)) = self.raw.pop_front() {
+				match self.ufs.dir_entry(self.inr, name, cinr, kind) {
+					Some(Ok(entry)) => return Some(Ok(entry)),
+					Some(Err(e)) => {
+						// Errors, which occur while handling an entry, stop the iteration as well.
+						self.stop();
+						return Some(Err(e));
+					}
+					None => continue,
+				}
+			}
+			if let Some(e) = self.error.take() {
+				self.stop();
+				return Some(Err(e));
+			}
+			if self.ino.is_none() || self.blkidx >= self.nblocks {
+				self.ino = None;
+				return None;
+			}
+			self.read_blocks();
+		}
+	}
+}
+
+#[cfg(test)]
+mod t {
+	use super::*;
+
+	/// Build a directory chunk from `(ino, type, name)`, the last entry fills the chunk.
+	fn chunk(entries: &[(u32, u8, &str)]) -> Vec<u8> {
+		let mut buf = Vec::new();
+		for (i, (ino, kind, name)) in entries.iter().enumerate() {
+			let reclen = if i + 1 == entries.len() {
+				DIRBLKSIZ - buf.len()
+			} else {
+				(8 + name.len() + 1).next_multiple_of(4)
+			};
+			let start = buf.len();
+			buf.extend_from_slice(&ino.to_le_bytes());
+			buf.extend_from_slice(&(reclen as u16).to_le_bytes());
+			buf.extend_from_slice(&[*kind, name.len() as u8]);
+			buf.extend_from_slice(name.as_bytes());
+			buf.resize(start + reclen, 0);
+		}
+		buf
+	}
+
+	fn names(block: &[u8]) -> IoResult<Vec<OsString>> {
+		let mut v = Vec::new();
+		let inr = InodeNum::ROOT;
+		readdir_block(inr, block, Config::little(), |name, _, _| {
+			v.push(name.to_owned());
+			None::<()>
+		})?;
+		Ok(v)
+	}
+
+	#[test]
+	fn deleted() {
+		let block = chunk(&[(0, DT_REG, "gone"), (5, DT_REG, "file")]);
+		assert_eq!(names(&block).unwrap(), ["file"]);
+	}
+
+	#[test]
+	fn multiple_chunks() {
+		let mut block = chunk(&[(2, DT_DIR, "."), (2, DT_DIR, "..")]);
+		block.extend(chunk(&[(7, DT_LNK, "link")]));
+		assert_eq!(names(&block).unwrap(), [".", "..", "link"]);
+	}
+
+	#[test]
+	fn bad_reclen() {
+		let mut block = chunk(&[(3, DT_REG, "a"), (4, DT_REG, "b")]);
+		block[4] = 0;
+		let e = names(&block).unwrap_err();
+		assert_eq!(crate::errno(&e), libc::EIO);
+	}
+
+	/// Entries must not cross the boundary of a chunk.
+	#[test]
+	fn crosses_chunk() {
+		let mut block = chunk(&[(3, DT_REG, "a")]);
+		block.extend(chunk(&[(4, DT_REG, "b")]));
+		block[4..6].copy_from_slice(&1024u16.to_le_bytes());
+		let e = names(&block).unwrap_err();
+		assert_eq!(crate::errno(&e), libc::EIO);
+	}
+
+	/// Names are arbitrary bytes, which don't need to be valid UTF-8.
+	#[test]
+	fn arbitrary_bytes() {
+		let long = "x".repeat(255);
+		let mut block = chunk(&[(3, DT_REG, "a b"), (4, DT_REG, &long)]);
+		block[8] = 0xe4;
+		block[20 + 254] = 0xff;
+		let names = names(&block).unwrap();
+		assert_eq!(names[0].as_bytes(), b"\xe4 b");
+		assert_eq!(names[1].len(), 255);
+		assert_eq!(names[1].as_bytes()[254], 0xff);
+	}
+
+	/// Names must not contain NUL or '/'.
+	#[test]
+	fn bad_name() {
+		for b in [b'\0', b'/'] {
+			let mut block = chunk(&[(3, DT_REG, "a b")]);
+			block[9] = b;
+			let e = names(&block).unwrap_err();
+			assert_eq!(crate::errno(&e), libc::EIO);
+		}
+	}
+
+	#[test]
+	fn whiteout() {
+		let block = chunk(&[(1, DT_WHT, "gone"), (5, DT_REG, "file")]);
+		let mut v = Vec::new();
+		readdir_block(
+			InodeNum::ROOT,
+			&block,
+			Config::little(),
+			|name, inr, kind| {
+				v.push((name.to_owned(), inr, kind));
+				None::<()>
+			},
+		)
+		.unwrap();
+		assert_eq!(
+			v[0],
+			("gone".into(), InodeNum::WHITEOUT, Some(InodeType::Whiteout))
+		);
+		assert_eq!(v.len(), 2);
+	}
+
+	#[test]
+	fn bad_type() {
+		let block = chunk(&[(3, 42, "a")]);
+		let e = names(&block).unwrap_err();
+		assert_eq!(crate::errno(&e), libc::EIO);
+	}
+
+	#[test]
+	fn truncated() {
+		let block = chunk(&[(3, DT_REG, "a")]);
+		let e = names(&block[0..100]).unwrap_err();
+		assert_eq!(crate::errno(&e), libc::EIO);
+	}
 }
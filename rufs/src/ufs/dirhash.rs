@@ -0,0 +1,253 @@
+use std::{
+	collections::HashMap,
+	mem::size_of,
+	sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use super::*;
+use crate::InodeNum;
+
+/// Directories smaller than this are searched linearly, like `vfs.ufs.dirhash_minsize` on FreeBSD.
+pub(super) const DIRHASH_MINSIZE: u64 = 2560;
+
+/// Estimated memory usage of an entry, in addition to its name.
+const ENTRY_OVERHEAD: usize = size_of::<(OsString, InodeNum)>() + size_of::<u64>();
+
+/// Statistics of the in-memory indexes of large directories, see [`Options::dirhash`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DirHashStats {
+	/// Maximum number of bytes, that can be used by the indexes.
+	pub capacity: u64,
+
+	/// Estimated number of bytes, that are currently used by the indexes.
+	pub used: u64,
+
+	/// Number of directories, that are currently indexed.
+	pub dirs: u64,
+
+	/// Number of lookups, that were answered using an index.
+	pub hits: u64,
+
+	/// Number of lookups in large directories, for which an index had to be built.
+	pub misses: u64,
+
+	/// Number of indexes, that were dropped to make room for others.
+	pub evictions: u64,
+
+	/// Number of indexes, that were dropped, because their directory was modified.
+	pub stale: u64,
+}
+
+/// The state of a directory, from which an index was built.
+/// If it changes, the directory was modified, and the index is rebuilt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Stamp {
+	size:  u64,
+	mtime: (UfsTime, u32),
+	ctime: (UfsTime, u32),
+	gen:   u32,
+}
+
+impl Stamp {
+	pub(super) fn new(ino: &Inode) -> Self {
+		Self {
+			size:  ino.size,
+			mtime: (ino.mtime, ino.mtimensec),
+			ctime: (ino.ctime, ino.ctimensec),
+			gen:   ino.gen,
+		}
+	}
+}
+
+struct Index {
+	stamp: Stamp,
+	names: HashMap<OsString, InodeNum>,
+	size:  u64,
+	/// When the index was last used.
+	tick:  u64,
+}
+
+#[derive(Default)]
+struct Inner {
+	dirs:  HashMap<InodeNum, Index>,
+	tick:  u64,
+	stats: DirHashStats,
+}
+
+impl Inner {
+	fn remove(&mut self, dir: InodeNum) {
+		if let Some(index) = self.dirs.remove(&dir) {
+			self.stats.used -= index.size;
+			self.stats.dirs -= 1;
+		}
+	}
+}
+
+/// In-memory hash indexes of the entries of large directories, like `UFS_DIRHASH` on FreeBSD.
+///
+/// The index of a directory is built on the first lookup in it, and kept until the memory
+/// budget is exhausted, like `vfs.ufs.dirhash_maxmem`. Then the least recently used indexes
+/// are dropped.
+pub(super) struct DirHash {
+	capacity: u64,
+	inner:    Mutex<Inner>,
+}
+
+impl DirHash {
+	pub(super) fn new(capacity: u64) -> Self {
+		let stats = DirHashStats {
+			capacity,
+			..DirHashStats::default()
+		};
+		Self {
+			capacity,
+			inner: Mutex::new(Inner {
+				stats,
+				..Inner::default()
+			}),
+		}
+	}
+
+	fn lock(&self) -> MutexGuard<'_, Inner> {
+		// Indexes are only inserted as a whole, so a poisoned lock is harmless.
+		self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	/// Whether directories are indexed at all.
+	pub(super) fn enabled(&self) -> bool {
+		self.capacity > 0
+	}
+
+	/// Look up `name` in the index of `dir`, if there is one, which is up to date with `stamp`.
+	/// Returns `Some(None)`, if `name` doesn't exist.
+	pub(super) fn get(
+		&self,
+		dir: InodeNum,
+		stamp: Stamp,
+		name: &OsStr,
+	) -> Option<Option<InodeNum>> {
+		let mut h = self.lock();
+		h.tick += 1;
+		let tick = h.tick;
+		match h.dirs.get_mut(&dir) {
+			Some(index) if index.stamp == stamp => {
+				index.tick = tick;
+				let res = index.names.get(name).copied();
+				h.stats.hits += 1;
+				return Some(res);
+			}
+			Some(_) => {
+				log::debug!("dirhash({dir}): the directory was modified, dropping the index");
+				h.remove(dir);
+				h.stats.stale += 1;
+			}
+			None => {}
+		}
+		h.stats.misses += 1;
+		None
+	}
+
+	/// Remember the entries `names` of `dir`.
+	pub(super) fn insert(&self, dir: InodeNum, stamp: Stamp, names: HashMap<OsString, InodeNum>) {
+		let size = names
+			.keys()
+			.map(|name| (name.len() + ENTRY_OVERHEAD) as u64)
+			.sum();
+
+		let mut h = self.lock();
+		h.remove(dir);
+		if size > self.capacity {
+			log::debug!("dirhash({dir}): the index of {size} bytes exceeds the budget");
+			return;
+		}
+		while h.stats.used + size > self.capacity {
+			let Some((&lru, _)) = h.dirs.iter().min_by_key(|(_, index)| index.tick) else {
+				break;
+			};
+			log::debug!("dirhash({lru}): dropping the index to make room for {dir}");
+			h.remove(lru);
+			h.stats.evictions += 1;
+		}
+
+		h.tick += 1;
+		let tick = h.tick;
+		h.dirs.insert(
+			dir,
+			Index {
+				stamp,
+				names,
+				size,
+				tick,
+			},
+		);
+		h.stats.used += size;
+		h.stats.dirs += 1;
+	}
+
+	pub(super) fn stats(&self) -> DirHashStats {
+		self.lock().stats.clone()
+	}
+}
+
+#[cfg(test)]
+mod t {
+	use super::*;
+
+	fn stamp(size: u64) -> Stamp {
+		Stamp {
+			size,
+			mtime: (0, 0),
+			ctime: (0, 0),
+			gen: 0,
+		}
+	}
+
+	fn inr(n: u32) -> InodeNum {
+		unsafe { InodeNum::new(n) }
+	}
+
+	#[test]
+	fn modified() {
+		let dh = DirHash::new(1 << 20);
+		let dir = InodeNum::ROOT;
+		let names = HashMap::from([("a".into(), dir)]);
+		dh.insert(dir, stamp(512), names);
+		assert_eq!(dh.get(dir, stamp(512), "a".as_ref()), Some(Some(dir)));
+		assert_eq!(dh.get(dir, stamp(512), "b".as_ref()), Some(None));
+
+		assert_eq!(dh.get(dir, stamp(1024), "a".as_ref()), None);
+		assert_eq!(dh.get(dir, stamp(512), "a".as_ref()), None);
+		let stats = dh.stats();
+		assert_eq!((stats.used, stats.dirs, stats.stale), (0, 0, 1));
+		assert_eq!((stats.hits, stats.misses), (2, 2));
+	}
+
+	/// The least recently used index is dropped, when the budget is exhausted.
+	#[test]
+	fn budget() {
+		let size = (1 + ENTRY_OVERHEAD) as u64;
+		let dh = DirHash::new(2 * size);
+		let names = || HashMap::from([("a".into(), InodeNum::ROOT)]);
+		dh.insert(inr(3), stamp(512), names());
+		dh.insert(inr(4), stamp(512), names());
+		assert!(dh.get(inr(3), stamp(512), "a".as_ref()).is_some());
+
+		dh.insert(inr(5), stamp(512), names());
+		assert!(dh.get(inr(3), stamp(512), "a".as_ref()).is_some());
+		assert!(dh.get(inr(4), stamp(512), "a".as_ref()).is_none());
+		assert!(dh.get(inr(5), stamp(512), "a".as_ref()).is_some());
+		let stats = dh.stats();
+		assert_eq!((stats.used, stats.dirs, stats.evictions), (2 * size, 2, 1));
+
+		// Indexes, which are larger than the whole budget, are never kept.
+		let big = HashMap::from([
+			("a".into(), inr(3)),
+			("b".into(), inr(3)),
+			("c".into(), inr(3)),
+		]);
+		dh.insert(inr(6), stamp(512), big);
+		assert!(dh.get(inr(6), stamp(512), "a".as_ref()).is_none());
+		assert_eq!(dh.stats().dirs, 2);
+	}
+}
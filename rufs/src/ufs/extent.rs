@@ -0,0 +1,177 @@
+use super::{
+	inode::{block_size, map_block},
+	*,
+};
+use crate::InodeNum;
+
+/// Offset of the block pointers (`di_db`) in an inode, where short symbolic links are stored.
+const DI_DB: u64 = 112;
+
+/// A contiguous range of a file, which is stored contiguously on the device,
+/// like a `struct fiemap_extent` of `FS_IOC_FIEMAP` on Linux.
+///
+/// Offsets and lengths are in bytes, physical offsets are relative to the start of the filesystem.
+/// The length of the last extent is rounded up to the fragment size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Extent {
+	/// Offset in the file.
+	pub logical: u64,
+
+	/// Offset on the device.
+	pub physical: u64,
+
+	/// Length of the extent.
+	pub length: u64,
+
+	/// A combination of [`Extent::LAST`], [`Extent::NOT_ALIGNED`], [`Extent::INLINE`]
+	/// and [`Extent::SHARED`], which have the same values as the `FIEMAP_EXTENT_*` flags.
+	pub flags: u32,
+}
+
+impl Extent {
+	/// The data is stored in the inode (`FIEMAP_EXTENT_DATA_INLINE`), like short symbolic links.
+	pub const INLINE: u32 = 0x0200;
+	/// The last extent of the file (`FIEMAP_EXTENT_LAST`).
+	pub const LAST: u32 = 0x0001;
+	/// The extent isn't aligned to the block size (`FIEMAP_EXTENT_NOT_ALIGNED`).
+	pub const NOT_ALIGNED: u32 = 0x0100;
+	/// The blocks are shared with the filesystem (`FIEMAP_EXTENT_SHARED`),
+	/// like the blocks of snapshots, which weren't copied.
+	pub const SHARED: u32 = 0x2000;
+}
+
+impl<B: Backend> Ufs<B> {
+	/// Get the physical layout of a file, by walking its direct and indirect block pointers,
+	/// like `FS_IOC_FIEMAP` or `FIBMAP` on Linux.
+	///
+	/// Adjacent blocks are merged into a single extent. Holes aren't reported.
+	/// Files without data, like devices, have no extents.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::{Extent, InodeNum};
+	///
+	/// # let ufs = example_image();
+	/// let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref())?;
+	/// let extents = ufs.inode_extents(inr)?;
+	/// assert_eq!(extents.len(), 1);
+	/// assert_eq!(extents[0].logical, 0);
+	/// assert_eq!(extents[0].length, 4096);
+	/// assert_eq!(extents[0].flags, Extent::LAST);
+	///
+	/// // Short symbolic links are stored in the inode.
+	/// let inr = ufs.dir_lookup(InodeNum::ROOT, "link1".as_ref())?;
+	/// let extents = ufs.inode_extents(inr)?;
+	/// assert_eq!(extents[0].flags, Extent::LAST | Extent::NOT_ALIGNED | Extent::INLINE);
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	#[doc(alias("fiemap", "fibmap", "bmap"))]
+	pub fn inode_extents(&self, inr: InodeNum) -> IoResult<Vec<Extent>> {
+		let ino = self.read_inode(inr)?;
+		if !ino.has_data() || ino.size == 0 {
+			return Ok(Vec::new());
+		}
+
+		let mut extents = Vec::new();
+		match &ino.data {
+			InodeData::Shortlink(_) => {
+				extents.push(Extent {
+					logical:  0,
+					physical: ino_addr(&self.geo, inr)? + DI_DB,
+					length:   ino.size,
+					flags:    Extent::NOT_ALIGNED | Extent::INLINE,
+				});
+			}
+			InodeData::Blocks(InodeBlocks { direct, indirect }) => {
+				let sb = &self.superblock;
+				let (blocks, frags) = ino.size(sb.bsize as u64, sb.fsize as u64);
+				let nblocks = blocks + u64::from(frags > 0);
+				let pbp = sb.bsize as u64 / size_of::<UfsDaddr>() as u64;
+
+				for (i, &ptr) in direct.iter().enumerate() {
+					self.extent_tree(&ino, ptr as u64, 0, i as u64, nblocks, &mut extents)?;
+				}
+				let mut base = UFS_NDADDR as u64;
+				let mut span = pbp;
+				for (level, &ptr) in indirect.iter().enumerate() {
+					let level = level as u32 + 1;
+					self.extent_tree(&ino, ptr as u64, level, base, nblocks, &mut extents)?;
+					base = base.saturating_add(span);
+					span = span.saturating_mul(pbp);
+				}
+			}
+		}
+
+		if let Some(last) = extents.last_mut() {
+			last.flags |= Extent::LAST;
+		}
+		Ok(extents)
+	}
+
+	/// Append the extents of the block tree of depth `level`, referenced by `ptr`
+	/// and beginning at block `base`, to `extents`.
+	fn extent_tree(
+		&self,
+		ino: &Inode,
+		ptr: u64,
+		level: u32,
+		base: u64,
+		nblocks: u64,
+		extents: &mut Vec<Extent>,
+	) -> IoResult<()> {
+		if base >= nblocks {
+			return Ok(());
+		}
+
+		let sb = &self.superblock;
+		let snap = ino.is_snapshot();
+		if level == 0 {
+			if let Some(addr) = map_block(sb, ino, base, ptr) {
+				let bs = sb.bsize as u64;
+				let ext = Extent {
+					logical:  base * bs,
+					physical: frag_addr(&self.geo, addr.get())?,
+					length:   block_size(sb, ino, base)? as u64,
+					flags:    if ptr == 0 { Extent::SHARED } else { 0 },
+				};
+				match extents.last_mut() {
+					Some(last)
+						if last.flags == ext.flags &&
+							last.logical + last.length == ext.logical &&
+							last.physical + last.length == ext.physical =>
+					{
+						last.length += ext.length;
+					}
+					_ => extents.push(ext),
+				}
+			}
+			return Ok(());
+		}
+
+		// See seek_tree() for the meaning of the block pointers of snapshots.
+		let frag = sb.frag as u64;
+		if (ptr == 0 && !snap) || (snap && ptr != 0 && ptr < frag) {
+			return Ok(());
+		}
+
+		let bs = sb.bsize as u64;
+		let pbp = bs / size_of::<UfsDaddr>() as u64;
+		let span = pbp.pow(level - 1);
+		let mut children = vec![0u8; bs as usize];
+		if ptr != 0 {
+			self.read_at(frag_addr(&self.geo, ptr)?, &mut children)?;
+		}
+		for (i, child) in children.chunks_exact(size_of::<UfsDaddr>()).enumerate() {
+			let child: u64 = self.config.decode_slice(child)?;
+			let start = base + i as u64 * span;
+			if start >= nblocks {
+				break;
+			}
+			self.extent_tree(ino, child, level - 1, start, nblocks, extents)?;
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,948 @@
+use std::{
+	collections::{hash_map::Entry, HashMap},
+	fmt,
+	num::NonZeroUsize,
+	ops::Range,
+	panic,
+	sync::{Mutex, PoisonError},
+	thread,
+};
+
+use super::{
+	dir::{dir_block_len, dir_blocks, readdir_block},
+	*,
+};
+use crate::InodeNum;
+
+/// Summary counts of a cylinder group, or of the whole filesystem.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FsckCounts {
+	/// Number of directories.
+	pub ndir: u64,
+
+	/// Number of free blocks.
+	pub nbfree: u64,
+
+	/// Number of free inodes.
+	pub nifree: u64,
+
+	/// Number of free fragments, which are not part of a free block.
+	pub nffree: u64,
+}
+
+impl FsckCounts {
+	fn from_csum(cs: &Csum) -> Self {
+		Self {
+			ndir:   cs.ndir as u64,
+			nbfree: cs.nbfree as u64,
+			nifree: cs.nifree as u64,
+			nffree: cs.nffree as u64,
+		}
+	}
+
+	pub(super) fn from_total(cst: &CsumTotal) -> Self {
+		Self {
+			ndir:   cst.ndir as u64,
+			nbfree: cst.nbfree as u64,
+			nifree: cst.nifree as u64,
+			nffree: cst.nffree as u64,
+		}
+	}
+
+	fn add(&mut self, other: &Self) {
+		self.ndir += other.ndir;
+		self.nbfree += other.nbfree;
+		self.nifree += other.nifree;
+		self.nffree += other.nffree;
+	}
+}
+
+/// An inconsistency, that was found by [`Ufs::check_deep()`].
+///
+/// Block addresses are in units of fragments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum FsckFinding {
+	/// The backup superblock of cylinder group `cg` has an invalid magic number.
+	BadSuperblock { cg: u32 },
+
+	/// Cylinder group `cg` has an invalid magic number or map offsets, so its maps weren't checked.
+	BadCg { cg: u32 },
+
+	/// The inode map of its cylinder group marks `inr` as used, but it isn't, or vice versa.
+	InodeMap {
+		inr:       InodeNum,
+		allocated: bool,
+	},
+
+	/// `inr` refers to a block at `addr`, which is outside of the filesystem.
+	BadBlock { inr: InodeNum, addr: u64 },
+
+	/// The extended attribute area of `inr` is larger than its `UFS_NXADDR` blocks.
+	ExtattrSize { inr: InodeNum, extsize: u32 },
+
+	/// `inr` refers to a block at `addr`, which is marked free.
+	FreeBlockInUse { inr: InodeNum, addr: u64 },
+
+	/// `inr` refers to a block at `addr`, which is also used by another inode, or by metadata.
+	DuplicateBlock { inr: InodeNum, addr: u64 },
+
+	/// `count` fragments of cylinder group `cg` are marked used, but nothing refers to them.
+	LostFragments { cg: u32, count: u64 },
+
+	/// The block count of `inr` doesn't match the blocks it refers to, in units of 512 bytes.
+	BlockCount {
+		inr:      InodeNum,
+		recorded: u64,
+		actual:   u64,
+	},
+
+	/// The link count of `inr` doesn't match the number of directory entries, which refer to it.
+	LinkCount {
+		inr:   InodeNum,
+		nlink: u16,
+		refs:  u64,
+	},
+
+	/// The entry `name` of the directory `dir` refers to `inr`, which isn't allocated.
+	DanglingEntry {
+		dir:  InodeNum,
+		name: OsString,
+		inr:  InodeNum,
+	},
+
+	/// The directory `dir` couldn't be read.
+	BadDirectory { dir: InodeNum, error: String },
+
+	/// The summary of cylinder group `cg` doesn't match its maps.
+	CgSummary {
+		cg:       u32,
+		recorded: FsckCounts,
+		actual:   FsckCounts,
+	},
+
+	/// The summary in the superblock doesn't match the cylinder groups.
+	TotalSummary {
+		recorded: FsckCounts,
+		actual:   FsckCounts,
+	},
+
+	/// Soft updates were still freeing `blocks` blocks and `inodes` inodes,
+	/// when the superblock was last written, see [`Info::pending_blocks`].
+	PendingFrees { blocks: u64, inodes: u64 },
+
+	/// The check-hash of the superblock is wrong, see [`Lint::check_hashes`].
+	SuperblockHash,
+
+	/// The check-hash of cylinder group `cg` is wrong, see [`Lint::check_hashes`].
+	CgHash { cg: u32 },
+
+	/// The check-hash of `inr` is wrong, see [`Lint::check_hashes`].
+	InodeHash { inr: InodeNum },
+}
+
+impl fmt::Display for FsckFinding {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::BadSuperblock { cg } => write!(f, "CG{cg}: invalid backup superblock"),
+			Self::BadCg { cg } => write!(f, "CG{cg}: invalid cylinder group"),
+			Self::InodeMap { inr, allocated } => {
+				let state = if *allocated { "free" } else { "used" };
+				write!(f, "inode {inr}: marked {state} in the inode map")
+			}
+			Self::BadBlock { inr, addr } => write!(f, "inode {inr}: bad block {addr}"),
+			Self::ExtattrSize { inr, extsize } => {
+				write!(f, "inode {inr}: extattr size {extsize} is too large")
+			}
+			Self::FreeBlockInUse { inr, addr } => {
+				write!(f, "inode {inr}: block {addr} is marked free")
+			}
+			Self::DuplicateBlock { inr, addr } => {
+				write!(f, "inode {inr}: block {addr} is used more than once")
+			}
+			Self::LostFragments { cg, count } => {
+				write!(f, "CG{cg}: {count} fragments are used, but unreferenced")
+			}
+			Self::BlockCount {
+				inr,
+				recorded,
+				actual,
+			} => {
+				write!(
+					f,
+					"inode {inr}: block count is {recorded}, should be {actual}"
+				)
+			}
+			Self::LinkCount { inr, nlink, refs } => {
+				write!(f, "inode {inr}: link count is {nlink}, should be {refs}")
+			}
+			Self::DanglingEntry { dir, name, inr } => {
+				write!(
+					f,
+					"inode {dir}: entry {name:?} refers to unallocated inode {inr}"
+				)
+			}
+			Self::BadDirectory { dir, error } => write!(f, "inode {dir}: {error}"),
+			Self::CgSummary {
+				cg,
+				recorded,
+				actual,
+			} => write!(f, "CG{cg}: summary is {recorded:?}, should be {actual:?}"),
+			Self::TotalSummary { recorded, actual } => {
+				write!(
+					f,
+					"superblock summary is {recorded:?}, should be {actual:?}"
+				)
+			}
+			Self::PendingFrees { blocks, inodes } => {
+				write!(
+					f,
+					"superblock: {blocks} blocks and {inodes} inodes are still being freed"
+				)
+			}
+			Self::SuperblockHash => write!(f, "superblock: bad check-hash"),
+			Self::CgHash { cg } => write!(f, "CG{cg}: bad check-hash"),
+			Self::InodeHash { inr } => write!(f, "inode {inr}: bad check-hash"),
+		}
+	}
+}
+
+/// Ranges of fragments, which hold metadata, like superblocks, cylinder groups and inodes.
+/// The first cylinder group also contains the boot area and the summary area.
+pub(super) fn metadata_extents(sb: &Superblock) -> Vec<Range<u64>> {
+	let fpg = sb.fpg as u64;
+	let mut extents = (0..(sb.ncg as u64))
+		.map(|cgx| {
+			let base = cgx * fpg;
+			let start = if cgx == 0 { 0 } else { sb.sblkno as u64 };
+			(base + start)..base.saturating_add(sb.dblkno as u64)
+		})
+		.collect::<Vec<_>>();
+	// A corrupted superblock may point the summary area anywhere.
+	let cssize = (sb.cssize.max(0) as u64).div_ceil(sb.fsize as u64);
+	let csaddr = sb.csaddr as u64;
+	extents.push(csaddr..csaddr.saturating_add(cssize));
+	extents
+}
+
+pub(super) fn isset(map: &[u8], i: u64) -> bool {
+	map[(i / 8) as usize] & (1 << (i % 8)) != 0
+}
+
+/// Maps of a cylinder group.
+pub(super) struct CgMaps {
+	cs:               FsckCounts,
+	/// Whether the check-hash is valid, or isn't expected.
+	ckhash:           bool,
+	/// Number of data fragments in the cylinder group.
+	pub(super) ndblk: u64,
+	iused:            Vec<u8>,
+	/// Bitmap of free fragments.
+	pub(super) free:  Vec<u8>,
+}
+
+impl CgMaps {
+	/// Count the free blocks, fragments and inodes, which the maps say.
+	fn counts(&self, sb: &Superblock, ndir: u64) -> FsckCounts {
+		let frag = sb.frag as u64;
+		let iused = self
+			.iused
+			.iter()
+			.map(|b| b.count_ones() as u64)
+			.sum::<u64>();
+		let mut counts = FsckCounts {
+			ndir,
+			// A corrupted map may have bits set beyond the last inode.
+			nifree: (sb.ipg as u64).saturating_sub(iused),
+			..FsckCounts::default()
+		};
+		for blk in 0..self.ndblk.div_ceil(frag) {
+			let frags = (blk * frag)..((blk + 1) * frag).min(self.ndblk);
+			let nfree = frags.filter(|&f| isset(&self.free, f)).count() as u64;
+			if nfree == frag {
+				counts.nbfree += 1;
+			} else {
+				counts.nffree += nfree;
+			}
+		}
+		counts
+	}
+}
+
+/// Check whether the fragments `addr..(addr + n)` are inside of the filesystem.
+fn in_fs(sb: &Superblock, addr: u64, n: u64) -> bool {
+	addr.checked_add(n).is_some_and(|end| end <= sb.size as u64)
+}
+
+/// Something, that was found while checking the inodes of a cylinder group.
+enum Event {
+	Finding(FsckFinding),
+	/// `inr` refers to the fragments `addr..(addr + n)`.
+	Claim {
+		inr:  InodeNum,
+		addr: u64,
+		n:    u64,
+	},
+}
+
+/// What checking a single cylinder group found, which doesn't depend on the other ones.
+///
+/// Whether blocks are used twice, or marked free, depends on all inodes and on the maps
+/// of other cylinder groups, so the blocks are only recorded, in the order, in which the
+/// inodes refer to them, to be claimed later, in the same order as a serial check would.
+struct CgCheck {
+	/// Findings of the backup superblock and the cylinder group itself.
+	header: Vec<FsckFinding>,
+	maps:   Option<CgMaps>,
+	/// Findings and blocks of the inodes.
+	events: Vec<Event>,
+	/// Allocated inodes, and their link counts.
+	inodes: Vec<(InodeNum, u16)>,
+	dirs:   Vec<InodeNum>,
+}
+
+impl CgCheck {
+	fn push(&mut self, finding: FsckFinding) {
+		self.events.push(Event::Finding(finding));
+	}
+}
+
+/// An allocated inode.
+struct Links {
+	nlink: u16,
+	refs:  u64,
+}
+
+/// State of a running consistency check.
+struct Fsck {
+	findings: Vec<FsckFinding>,
+	/// Fragments, which are referenced by inodes or by metadata.
+	used:     Vec<u8>,
+	cgs:      Vec<Option<CgMaps>>,
+	inodes:   HashMap<InodeNum, Links>,
+	dirs:     Vec<InodeNum>,
+}
+
+impl Fsck {
+	/// Mark the fragments `addr..(addr + n)`, which are inside of the filesystem,
+	/// as used, by `inr` or by metadata.
+	fn claim(&mut self, sb: &Superblock, inr: Option<InodeNum>, addr: u64, n: u64) {
+		if !in_fs(sb, addr, n) {
+			return;
+		}
+
+		let fpg = sb.fpg as u64;
+		let (mut dup, mut free) = (false, false);
+		for f in addr..(addr + n) {
+			let bit = 1 << (f % 8);
+			let byte = &mut self.used[(f / 8) as usize];
+			dup |= *byte & bit != 0;
+			*byte |= bit;
+
+			if let Some(Some(cg)) = self.cgs.get((f / fpg) as usize) {
+				free |= f % fpg < cg.ndblk && isset(&cg.free, f % fpg);
+			}
+		}
+
+		let Some(inr) = inr else {
+			return;
+		};
+		if dup {
+			self.findings
+				.push(FsckFinding::DuplicateBlock { inr, addr });
+		}
+		if free {
+			self.findings
+				.push(FsckFinding::FreeBlockInUse { inr, addr });
+		}
+	}
+}
+
+impl<B: Backend + Sync> Ufs<B> {
+	/// Like [`Ufs::check_deep()`], but the cylinder groups are checked by `threads` threads at once.
+	///
+	/// The findings are the same, and in the same order.
+	/// Only the cylinder groups and their inodes are checked in parallel,
+	/// the directories and the summaries are checked afterwards.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// # let ufs = example_image();
+	/// let threads = std::thread::available_parallelism()?;
+	/// let findings = ufs.check_deep_parallel(threads)?;
+	/// assert_eq!(findings, []);
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	pub fn check_deep_parallel(&self, threads: NonZeroUsize) -> IoResult<Vec<FsckFinding>> {
+		let ncg = self.superblock.ncg as u64;
+		let next = AtomicU64::new(0);
+		let checks = Mutex::new((0..ncg).map(|_| None).collect::<Vec<_>>());
+		let threads = threads.get().min(ncg as usize);
+		log::debug!("check_deep_parallel(): {threads} threads");
+
+		thread::scope(|scope| {
+			let workers = (0..threads)
+				.map(|_| {
+					scope.spawn(|| -> IoResult<()> {
+						loop {
+							let cgx = next.fetch_add(1, Ordering::Relaxed);
+							if cgx >= ncg {
+								return Ok(());
+							}
+							let check = self.check_cg(cgx).map_err(|e| {
+								// Let the other threads stop early.
+								next.store(ncg, Ordering::Relaxed);
+								e
+							})?;
+							let mut checks = checks.lock().unwrap_or_else(PoisonError::into_inner);
+							checks[cgx as usize] = Some(check);
+						}
+					})
+				})
+				.collect::<Vec<_>>();
+			workers
+				.into_iter()
+				.try_for_each(|w| w.join().unwrap_or_else(|p| panic::resume_unwind(p)))
+		})?;
+
+		let checks = checks
+			.into_inner()
+			.unwrap_or_else(PoisonError::into_inner)
+			.into_iter()
+			.map(|c| c.expect("all cylinder groups were checked"))
+			.collect();
+		Ok(self.check_all(checks))
+	}
+}
+
+impl<B: Backend> Ufs<B> {
+	/// Check the consistency of the whole filesystem, without modifying it.
+	///
+	/// This compares the maps of all cylinder groups against the blocks and inodes in use,
+	/// the link counts of all inodes against the directory entries referring to them,
+	/// and the summaries of the cylinder groups and the superblock against the maps.
+	/// Inconsistencies are returned, instead of being logged.
+	/// Errors are only returned, if the device can't be read.
+	///
+	/// This reads every allocated inode and directory, so it can take a while,
+	/// see [`Ufs::check_deep_parallel()`] and [`Ufs::check_touched()`] for faster checks.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// # let ufs = example_image();
+	/// let findings = ufs.check_deep()?;
+	/// assert_eq!(findings, []);
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	#[doc(alias("fsck", "fsck_ffs"))]
+	pub fn check_deep(&self) -> IoResult<Vec<FsckFinding>> {
+		let checks = (0..self.superblock.ncg as u64)
+			.map(|cgx| self.check_cg(cgx))
+			.collect::<IoResult<Vec<_>>>()?;
+		Ok(self.check_all(checks))
+	}
+
+	/// Check the cylinder groups, which were written to since they were checked the last time,
+	/// without modifying them, like [`Ufs::check_deep()`] would.
+	///
+	/// This only reads the touched cylinder groups, and the inodes and indirect blocks in them,
+	/// so it is fast enough to be run after every session, which modified the filesystem.
+	/// Only the inconsistencies, which can be found within a cylinder group, are found:
+	/// the maps, block counts, blocks outside of the filesystem or marked free,
+	/// and the summaries of the cylinder groups. Duplicate blocks, link counts,
+	/// directory entries and lost fragments need [`Ufs::check_deep()`].
+	///
+	/// Cylinder groups are touched by [`Ufs::write_raw_block()`] and [`Ufs::with_txn()`].
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// # let ufs = example_image();
+	/// // Nothing was written yet.
+	/// let findings = ufs.check_touched()?;
+	/// assert_eq!(findings, []);
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	pub fn check_touched(&self) -> IoResult<Vec<FsckFinding>> {
+		let sb = &self.superblock;
+		let fpg = sb.fpg as u64;
+		let touched = (0..sb.ncg as u64)
+			.filter(|&cgx| self.touched[cgx as usize].swap(false, Ordering::Relaxed))
+			.collect::<Vec<_>>();
+		log::debug!("check_touched(): cylinder groups {touched:?}");
+
+		let mut findings = Vec::new();
+		// Maps of the cylinder groups, which the blocks of the checked inodes are in.
+		let mut maps = HashMap::new();
+		for cgx in touched {
+			let check = self.check_cg(cgx).map_err(|e| {
+				// Check it again next time.
+				self.touched[cgx as usize].store(true, Ordering::Relaxed);
+				e
+			})?;
+			findings.extend(check.header);
+			for event in check.events {
+				let (inr, addr, n) = match event {
+					Event::Finding(f) => {
+						findings.push(f);
+						continue;
+					}
+					Event::Claim { inr, addr, n } => (inr, addr, n),
+				};
+				let mut free = false;
+				for f in addr..(addr + n) {
+					let cg = match maps.entry(f / fpg) {
+						Entry::Occupied(e) => e.into_mut(),
+						Entry::Vacant(e) => e.insert(self.cg_maps(f / fpg)?),
+					};
+					if let Some(cg) = cg {
+						free |= f % fpg < cg.ndblk && isset(&cg.free, f % fpg);
+					}
+				}
+				if free {
+					findings.push(FsckFinding::FreeBlockInUse { inr, addr });
+				}
+			}
+			if let Some(cgmaps) = &check.maps {
+				let actual = cgmaps.counts(sb, check.dirs.len() as u64);
+				if cgmaps.cs != actual {
+					findings.push(FsckFinding::CgSummary {
+						cg: cgx as u32,
+						recorded: cgmaps.cs,
+						actual,
+					});
+				}
+			}
+		}
+		Ok(findings)
+	}
+
+	/// Remember, that the bytes `pos` were written, for [`Ufs::check_touched()`].
+	pub(super) fn touch(&self, pos: Range<u64>) {
+		let cgsize = self.geo.cgsize();
+		if pos.is_empty() {
+			return;
+		}
+		let last = (pos.end - 1) / cgsize;
+		for cgx in (pos.start / cgsize)..=last {
+			if let Some(t) = self.touched.get(cgx as usize) {
+				t.store(true, Ordering::Relaxed);
+			}
+		}
+	}
+
+	/// Finish a check of the whole filesystem, after all cylinder groups were checked.
+	fn check_all(&self, checks: Vec<CgCheck>) -> Vec<FsckFinding> {
+		let sb = &self.superblock;
+		let ncg = sb.ncg as u64;
+		let fpg = sb.fpg as u64;
+		for touched in &self.touched {
+			touched.store(false, Ordering::Relaxed);
+		}
+
+		let mut fsck = Fsck {
+			findings: Vec::new(),
+			used:     vec![0u8; (sb.size as u64).max(ncg * fpg).div_ceil(8) as usize],
+			cgs:      Vec::new(),
+			inodes:   HashMap::new(),
+			dirs:     Vec::new(),
+		};
+
+		let mut rest = Vec::new();
+		for check in checks {
+			fsck.findings.extend(check.header);
+			fsck.cgs.push(check.maps);
+			rest.push((check.events, check.inodes, check.dirs));
+		}
+
+		for r in metadata_extents(sb) {
+			fsck.claim(sb, None, r.start, r.end.saturating_sub(r.start));
+		}
+
+		let mut ndir = Vec::new();
+		for (events, inodes, dirs) in rest {
+			for event in events {
+				match event {
+					Event::Finding(f) => fsck.findings.push(f),
+					Event::Claim { inr, addr, n } => fsck.claim(sb, Some(inr), addr, n),
+				}
+			}
+			for (inr, nlink) in inodes {
+				fsck.inodes.insert(inr, Links { nlink, refs: 0 });
+			}
+			ndir.push(dirs.len() as u64);
+			fsck.dirs.extend(dirs);
+		}
+
+		for dir in std::mem::take(&mut fsck.dirs) {
+			self.fsck_dir(&mut fsck, dir);
+		}
+
+		let mut inodes = fsck.inodes.iter().collect::<Vec<_>>();
+		inodes.sort_unstable_by_key(|(inr, _)| **inr);
+		for (&inr, l) in inodes {
+			if l.refs != l.nlink as u64 {
+				fsck.findings.push(FsckFinding::LinkCount {
+					inr,
+					nlink: l.nlink,
+					refs: l.refs,
+				});
+			}
+		}
+
+		let mut total = FsckCounts::default();
+		for (cgx, maps) in fsck.cgs.iter().enumerate() {
+			let Some(maps) = maps else {
+				continue;
+			};
+			let base = cgx as u64 * fpg;
+			let actual = maps.counts(sb, ndir[cgx]);
+
+			let mut lost = 0;
+			for f in 0..maps.ndblk {
+				if !isset(&maps.free, f) && !isset(&fsck.used, base + f) {
+					lost += 1;
+				}
+			}
+
+			let cg = cgx as u32;
+			if lost > 0 {
+				fsck.findings
+					.push(FsckFinding::LostFragments { cg, count: lost });
+			}
+			if maps.cs != actual {
+				fsck.findings.push(FsckFinding::CgSummary {
+					cg,
+					recorded: maps.cs,
+					actual,
+				});
+			}
+			total.add(&actual);
+		}
+
+		let recorded = FsckCounts::from_total(&sb.cstotal);
+		if fsck.cgs.iter().all(Option::is_some) && recorded != total {
+			fsck.findings.push(FsckFinding::TotalSummary {
+				recorded,
+				actual: total,
+			});
+		}
+
+		let info = self.info();
+		if info.pending_blocks != 0 || info.pending_inodes != 0 {
+			fsck.findings.push(FsckFinding::PendingFrees {
+				blocks: info.pending_blocks,
+				inodes: info.pending_inodes,
+			});
+		}
+
+		fsck.findings
+	}
+
+	/// Sum up the summaries in the headers of the cylinder groups,
+	/// if they are protected by check-hashes, see [`Lint::check_hashes`].
+	///
+	/// They are more trustworthy than the summary area, which is merely a copy of them.
+	/// Returns `None`, if there are no check-hashes, or if a cylinder group is damaged.
+	pub(super) fn cg_summary_total(&self) -> IoResult<Option<FsckCounts>> {
+		if !self.has_ckhash(CK_CYLGRP) {
+			return Ok(None);
+		}
+		let mut total = FsckCounts::default();
+		for cgx in 0..(self.superblock.ncg as u64) {
+			match self.cg_maps(cgx)? {
+				Some(maps) if maps.ckhash => total.add(&maps.cs),
+				_ => {
+					log::warn!("CG{cgx} is damaged, using the summary area instead");
+					return Ok(None);
+				}
+			}
+		}
+		Ok(Some(total))
+	}
+
+	/// Sum up the summary area, which holds the summaries of all cylinder groups.
+	///
+	/// Returns `None`, if the summary area is too small.
+	pub(super) fn summary_total(&self) -> IoResult<Option<FsckCounts>> {
+		let sb = &self.superblock;
+		let len = sb.ncg as u64 * size_of::<Csum>() as u64;
+		if len > sb.cssize.max(0) as u64 {
+			log::warn!("the summary area is too small: {} bytes", sb.cssize);
+			return Ok(None);
+		}
+
+		let mut buf = vec![0u8; len as usize];
+		self.read_at(frag_addr(&self.geo, sb.csaddr as u64)?, &mut buf)?;
+		let mut total = FsckCounts::default();
+		for cs in buf.chunks_exact(size_of::<Csum>()) {
+			let cs: Csum = self.config.decode_slice(cs)?;
+			total.add(&FsckCounts::from_csum(&cs));
+		}
+		Ok(Some(total))
+	}
+
+	/// Check cylinder group `cgx`, its backup superblock, and its inodes.
+	fn check_cg(&self, cgx: u64) -> IoResult<CgCheck> {
+		let cg = cgx as u32;
+		let mut check = CgCheck {
+			header: Vec::new(),
+			maps:   None,
+			events: Vec::new(),
+			inodes: Vec::new(),
+			dirs:   Vec::new(),
+		};
+
+		let pos = cg_addr(&self.geo, cgx, self.geo.sblkno())? + MAGIC_OFFSET;
+		let magic: i32 = self.decode_at(pos, 4)?;
+		if magic != FS_UFS2_MAGIC {
+			check.header.push(FsckFinding::BadSuperblock { cg });
+		}
+		if cgx == 0 && !self.verify_superblock_ckhash()? {
+			check.header.push(FsckFinding::SuperblockHash);
+		}
+
+		check.maps = self.cg_maps(cgx)?;
+		match &check.maps {
+			None => check.header.push(FsckFinding::BadCg { cg }),
+			Some(maps) if !maps.ckhash => check.header.push(FsckFinding::CgHash { cg }),
+			Some(_) => (),
+		}
+
+		self.fsck_inodes(&mut check, cgx)?;
+		Ok(check)
+	}
+
+	/// Load the maps of cylinder group `cgx`.
+	///
+	/// Returns `None`, if the cylinder group is damaged.
+	pub(super) fn cg_maps(&self, cgx: u64) -> IoResult<Option<CgMaps>> {
+		let sb = &self.superblock;
+		let mut buf = vec![0u8; sb.cgsize.max(0) as usize];
+		self.read_at(cg_addr(&self.geo, cgx, self.geo.cblkno())?, &mut buf)?;
+		let hdr: CylGroup = self.config.decode_slice(&buf)?;
+
+		let ipg = sb.ipg as usize;
+		let fpg = sb.fpg as usize;
+		let map = |off: u32, len: usize| buf.get((off as usize)..(off as usize + len.div_ceil(8)));
+		let (Some(iused), Some(free)) = (map(hdr.iusedoff, ipg), map(hdr.freeoff, fpg)) else {
+			return Ok(None);
+		};
+		if hdr.magic != CG_MAGIC || hdr.cgx != cgx as u32 || hdr.ndblk as usize > fpg {
+			return Ok(None);
+		}
+
+		let ckhash = !self.has_ckhash(CK_CYLGRP) || self.verify_ckhash(&buf, ckhash::CG_CKHASH)?;
+		Ok(Some(CgMaps {
+			cs: FsckCounts::from_csum(&hdr.cs),
+			ckhash,
+			ndblk: hdr.ndblk as u64,
+			iused: iused.to_vec(),
+			free: free.to_vec(),
+		}))
+	}
+
+	/// Check the inodes of cylinder group `cgx`.
+	fn fsck_inodes(&self, check: &mut CgCheck, cgx: u64) -> IoResult<()> {
+		let sb = &self.superblock;
+		let bs = sb.bsize as u64;
+		let ipg = sb.ipg as u64;
+		let inopb = sb.inopb as u64;
+
+		// Inodes after the initialized ones may contain garbage.
+		let inited = match &check.maps {
+			Some(_) => {
+				let pos = cg_addr(&self.geo, cgx, self.geo.cblkno())?;
+				let hdr: CylGroup = self.decode_at(pos, size_of::<CylGroup>())?;
+				(hdr.initediblk as u64).min(ipg)
+			}
+			None => ipg,
+		};
+
+		let mut block = vec![0u8; bs as usize];
+		for idx in 0..ipg {
+			// Inodes are contiguous, so read them a block at a time.
+			if idx % inopb == 0 && idx < inited {
+				let pos = cg_addr(&self.geo, cgx, self.geo.iblkno())?;
+				self.read_at(pos + idx * UFS_INOSZ as u64, &mut block)?;
+			}
+
+			// SAFETY: this is an inode number of this filesystem.
+			let inr = unsafe { InodeNum::new((cgx * ipg + idx) as u32) };
+			if inr < InodeNum::ROOT {
+				continue;
+			}
+
+			let off = (idx % inopb) as usize * UFS_INOSZ;
+			let ino = if idx < inited {
+				Some(self.config.decode_slice::<Inode>(&block[off..])?)
+			} else {
+				None
+			};
+			let ino = ino.filter(|ino| ino.mode & S_IFMT != 0);
+
+			if let Some(maps) = &check.maps {
+				let used = isset(&maps.iused, idx);
+				if used != ino.is_some() {
+					check.push(FsckFinding::InodeMap {
+						inr,
+						allocated: ino.is_some(),
+					});
+				}
+			}
+
+			let Some(ino) = ino else {
+				continue;
+			};
+			let raw = &block[off..(off + UFS_INOSZ)];
+			if self.has_ckhash(CK_INODE) && !self.verify_ckhash(raw, ckhash::DI_CKHASH)? {
+				check.push(FsckFinding::InodeHash { inr });
+			}
+			check.inodes.push((inr, ino.nlink));
+			if ino.mode & S_IFMT == S_IFDIR {
+				check.dirs.push(inr);
+			}
+
+			let frags = self.fsck_blocks(check, inr, &ino)?;
+			let actual = frags * sb.fsize as u64 / DEV_BSIZE as u64;
+			if ino.blocks != actual {
+				check.push(FsckFinding::BlockCount {
+					inr,
+					recorded: ino.blocks,
+					actual,
+				});
+			}
+		}
+		Ok(())
+	}
+
+	/// Record all blocks of `inr`, and return the number of fragments.
+	fn fsck_blocks(&self, check: &mut CgCheck, inr: InodeNum, ino: &Inode) -> IoResult<u64> {
+		let sb = &self.superblock;
+		let bs = sb.bsize as u64;
+		let fs = sb.fsize as u64;
+		let mut total = 0;
+
+		let mut claim = |check: &mut CgCheck, addr: UfsDaddr, len: u64| {
+			let (addr, n) = (addr as u64, len.div_ceil(fs));
+			if !in_fs(sb, addr, n) {
+				check.push(FsckFinding::BadBlock { inr, addr });
+				return false;
+			}
+			check.events.push(Event::Claim { inr, addr, n });
+			total += n;
+			true
+		};
+
+		// Extended attributes are stored in up to two blocks, which may end in fragments.
+		let ext = ino.extsize as u64;
+		if ext > UFS_NXADDR as u64 * bs {
+			check.push(FsckFinding::ExtattrSize {
+				inr,
+				extsize: ino.extsize,
+			});
+		}
+		for (i, &addr) in ino.extb.iter().enumerate() {
+			let off = i as u64 * bs;
+			if addr != 0 && off < ext {
+				claim(check, addr, (ext - off).min(bs));
+			}
+		}
+
+		let InodeData::Blocks(blocks) = &ino.data else {
+			return Ok(total);
+		};
+		if !ino.has_data() {
+			return Ok(total);
+		}
+
+		// BLK_NOCOPY and BLK_SNAP in snapshots don't refer to actual blocks.
+		let snap = ino.is_snapshot();
+		let frag = sb.frag as UfsDaddr;
+		let real = |addr: UfsDaddr| addr != 0 && !(snap && addr < frag);
+
+		// Only the last direct block may be a fragment.
+		for (i, &addr) in blocks.direct.iter().enumerate() {
+			let off = i as u64 * bs;
+			if real(addr) {
+				let len = if off < ino.size {
+					(ino.size - off).min(bs)
+				} else {
+					bs
+				};
+				claim(check, addr, len);
+			}
+		}
+
+		let nindir = bs / size_of::<UfsDaddr>() as u64;
+		let mut ptrs = vec![0u8; bs as usize];
+		let mut stack = blocks
+			.indirect
+			.iter()
+			.enumerate()
+			.map(|(level, &addr)| (level, addr))
+			.collect::<Vec<_>>();
+		while let Some((level, addr)) = stack.pop() {
+			if !real(addr) || !claim(check, addr, bs) {
+				continue;
+			}
+			self.read_at(frag_addr(&self.geo, addr as u64)?, &mut ptrs)?;
+			for i in 0..nindir as usize {
+				let child: UfsDaddr = self.config.decode_slice(&ptrs[(i * 8)..])?;
+				if level == 0 {
+					if real(child) {
+						claim(check, child, bs);
+					}
+				} else {
+					stack.push((level - 1, child));
+				}
+			}
+		}
+
+		Ok(total)
+	}
+
+	/// Count the references of the entries of `dir`, and look for dangling ones.
+	fn fsck_dir(&self, fsck: &mut Fsck, dir: InodeNum) {
+		let mut entries = Vec::new();
+		let res = self.read_inode(dir).and_then(|ino| {
+			let nblocks = dir_blocks(&self.superblock, dir, &ino)?;
+			let mut block = vec![0u8; self.superblock.bsize as usize];
+			for blkidx in 0..nblocks {
+				self.inode_read_block(dir, &ino, blkidx, &mut block)?;
+				let len = dir_block_len(&self.superblock, &ino, blkidx);
+				readdir_block(dir, &block[0..len], self.config, |name, inr, kind| {
+					// Whiteouts don't refer to an inode.
+					if kind != Some(InodeType::Whiteout) {
+						entries.push((name.to_owned(), inr));
+					}
+					None::<()>
+				})?;
+			}
+			Ok(())
+		});
+		if let Err(e) = res {
+			fsck.findings.push(FsckFinding::BadDirectory {
+				dir,
+				error: e.to_string(),
+			});
+		}
+
+		for (name, inr) in entries {
+			match fsck.inodes.get_mut(&inr) {
+				Some(l) => l.refs += 1,
+				None => {
+					fsck.findings
+						.push(FsckFinding::DanglingEntry { dir, name, inr })
+				}
+			}
+		}
+	}
+}
@@ -0,0 +1,75 @@
+use std::ops::Range;
+
+use super::*;
+use crate::InodeNum;
+
+impl<B: Backend> Ufs<B> {
+	/// Write the modified data and metadata of a single inode back to the device, like `fsync(2)`.
+	///
+	/// This covers the inode itself, its data and its indirect blocks,
+	/// but not the cylinder groups and the superblock, which [`Ufs::sync()`] writes back as well.
+	/// Backends, which don't know where their unsynced writes are, like [`BlockFile`],
+	/// sync everything anyway, and so do snapshots.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::InodeNum;
+	///
+	/// # let ufs = example_image();
+	/// let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref())?;
+	/// ufs.sync_inode(inr)?;
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	#[doc(alias("fsync", "fdatasync", "fsyncdir"))]
+	pub fn sync_inode(&self, inr: InodeNum) -> IoResult<()> {
+		let ino = self.read_inode(inr)?;
+		if ino.is_snapshot() {
+			// See seek_tree() for the meaning of the block pointers of snapshots.
+			return self.sync();
+		}
+
+		let pos = ino_addr(&self.geo, inr)?;
+		let inode = pos..(pos + UFS_INOSZ as u64);
+		let mut ranges = vec![inode];
+		ranges.extend(
+			self.inode_extents(inr)?
+				.into_iter()
+				.filter(|e| e.flags & Extent::INLINE == 0)
+				.map(|e| e.physical..(e.physical + e.length)),
+		);
+		if let InodeData::Blocks(InodeBlocks { indirect, .. }) = &ino.data {
+			for (level, &ptr) in indirect.iter().enumerate() {
+				self.indirect_ranges(ptr as u64, level as u32, &mut ranges)?;
+			}
+		}
+		log::trace!("sync_inode({inr}): {} ranges", ranges.len());
+		self.backend.sync_ranges(&ranges)
+	}
+
+	/// Append the blocks of the tree of indirect blocks, which is referenced by `ptr`,
+	/// and whose children are `level` levels above the data, to `ranges`.
+	fn indirect_ranges(&self, ptr: u64, level: u32, ranges: &mut Vec<Range<u64>>) -> IoResult<()> {
+		if ptr == 0 {
+			return Ok(());
+		}
+
+		let bs = self.superblock.bsize as u64;
+		let pos = ptr
+			.checked_mul(self.superblock.fsize as u64)
+			.ok_or_else(|| corrupt!("the address of fragment {ptr} overflows"))?;
+		ranges.push(pos..pos.saturating_add(bs));
+		if level == 0 {
+			return Ok(());
+		}
+
+		let mut children = vec![0u8; bs as usize];
+		self.read_at(pos, &mut children)?;
+		for child in children.chunks_exact(size_of::<UfsDaddr>()) {
+			let child: u64 = self.config.decode_slice(child)?;
+			self.indirect_ranges(child, level - 1, ranges)?;
+		}
+		Ok(())
+	}
+}
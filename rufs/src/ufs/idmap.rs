@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+
+use super::*;
+
+/// Mapping between the uids and gids stored in the filesystem, and the ones presented
+/// by [`Ufs::inode_attr()`] and [`Ufs::acl()`], eg. to match the users of another system.
+///
+/// Ids, which aren't mapped, are presented as they are.
+/// The mapping doesn't have to be a bijection, so mapping an id back to the filesystem
+/// may not result in the original id.
+///
+/// # Example
+/// ```
+/// use rufs::IdMap;
+///
+/// let mut idmap = IdMap::default();
+/// idmap.uids.insert(1001, 1000);
+/// assert_eq!(idmap.uid(1001), 1000);
+/// assert_eq!(idmap.uid(0), 0);
+/// assert_eq!(idmap.fs_uid(1000), 1001);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IdMap {
+	/// Present all files as owned by this user, regardless of the owner on disk.
+	pub force_uid: Option<u32>,
+
+	/// Present all files as owned by this group, regardless of the group on disk.
+	pub force_gid: Option<u32>,
+
+	/// uids on disk, and the uids they are presented as.
+	pub uids: BTreeMap<u32, u32>,
+
+	/// gids on disk, and the gids they are presented as.
+	pub gids: BTreeMap<u32, u32>,
+}
+
+/// Find the smallest id, that is mapped to `id`, or `id` itself, if there is none.
+fn reverse(map: &BTreeMap<u32, u32>, id: u32) -> u32 {
+	map.iter()
+		.find(|(_, &to)| to == id)
+		.map_or(id, |(&from, _)| from)
+}
+
+impl IdMap {
+	/// Check whether all ids are presented as they are.
+	pub fn is_identity(&self) -> bool {
+		*self == Self::default()
+	}
+
+	/// Map uid `uid` on disk to the presented one.
+	///
+	/// This doesn't apply `force_uid`, which only affects the owner of files.
+	pub fn uid(&self, uid: u32) -> u32 {
+		self.uids.get(&uid).copied().unwrap_or(uid)
+	}
+
+	/// Map gid `gid` on disk to the presented one.
+	///
+	/// This doesn't apply `force_gid`, which only affects the group of files.
+	pub fn gid(&self, gid: u32) -> u32 {
+		self.gids.get(&gid).copied().unwrap_or(gid)
+	}
+
+	/// Map a presented uid back to the one, which is stored on disk.
+	///
+	/// TODO: use this for the owner of new files, and for chown(2), once they are supported.
+	pub fn fs_uid(&self, uid: u32) -> u32 {
+		reverse(&self.uids, uid)
+	}
+
+	/// Map a presented gid back to the one, which is stored on disk.
+	pub fn fs_gid(&self, gid: u32) -> u32 {
+		reverse(&self.gids, gid)
+	}
+
+	/// Present the owner and the group of `attr`.
+	pub(super) fn apply(&self, attr: &mut InodeAttr) {
+		attr.uid = self.force_uid.unwrap_or_else(|| self.uid(attr.uid));
+		attr.gid = self.force_gid.unwrap_or_else(|| self.gid(attr.gid));
+	}
+
+	/// Present the users and groups of the entries of `acl`.
+	pub(super) fn apply_acl(&self, acl: &mut Acl) {
+		for e in &mut acl.entries {
+			e.tag = match e.tag {
+				AclTag::User(uid) => AclTag::User(self.uid(uid)),
+				AclTag::Group(gid) => AclTag::Group(self.gid(gid)),
+				tag => tag,
+			};
+		}
+		acl.entries.sort_by_key(|e| e.tag);
+	}
+}
@@ -1,30 +1,108 @@
+use std::time::UNIX_EPOCH;
+
 use super::*;
 use crate::{err, InodeNum};
 
-impl<R: Read + Seek> Ufs<R> {
+/// Maximum number of bytes, that are read at once by [`Ufs::inode_read_blocks()`].
+pub(super) const MAX_READ: usize = 128 << 10;
+
+/// What [`Ufs::inode_seek()`] should look for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Whence {
+	/// Find the next region, that contains data (`SEEK_DATA`).
+	Data,
+
+	/// Find the next hole (`SEEK_HOLE`).
+	/// The end of the file is considered to be a hole.
+	Hole,
+}
+
+impl<B: Backend> Ufs<B> {
 	/// Get metadata about an inode.
+	///
+	/// The owner and the group are mapped using [`Options::idmap`].
+	/// If [`Options::whiteouts`] is set, the metadata of [`InodeNum::WHITEOUT`] is synthesized,
+	/// like for an empty character device 0/0, owned by root.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::{InodeNum, InodeType};
+	///
+	/// # let ufs = example_image();
+	/// let attr = ufs.inode_attr(InodeNum::ROOT)?;
+	/// assert_eq!(attr.kind, InodeType::Directory);
+	/// assert_eq!(attr.inr, InodeNum::ROOT);
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
 	#[doc(alias("stat", "getattr"))]
-	pub fn inode_attr(&mut self, inr: InodeNum) -> IoResult<InodeAttr> {
+	pub fn inode_attr(&self, inr: InodeNum) -> IoResult<InodeAttr> {
+		if inr == InodeNum::WHITEOUT && self.options.whiteouts {
+			return Ok(self.whiteout_attr());
+		}
 		let ino = self.read_inode(inr)?;
-		Ok(ino.as_attr(inr))
+		let mut attr = ino.as_attr(inr)?;
+		self.options.idmap.apply(&mut attr);
+		Ok(attr)
+	}
+
+	/// Metadata of whiteouts, which don't have an inode.
+	fn whiteout_attr(&self) -> InodeAttr {
+		InodeAttr {
+			inr:       InodeNum::WHITEOUT,
+			perm:      0,
+			kind:      InodeType::Whiteout,
+			size:      0,
+			blocks:    0,
+			atime:     UNIX_EPOCH,
+			mtime:     UNIX_EPOCH,
+			ctime:     UNIX_EPOCH,
+			btime:     UNIX_EPOCH,
+			nlink:     1,
+			uid:       0,
+			gid:       0,
+			gen:       0,
+			blksize:   self.superblock.bsize as u32,
+			flags:     0,
+			kernflags: 0,
+			extsize:   0,
+			rdev:      0,
+		}
 	}
 
 	/// Read data from an inode.
-	pub fn inode_read(
-		&mut self,
-		inr: InodeNum,
-		mut offset: u64,
-		buffer: &mut [u8],
-	) -> IoResult<usize> {
+	///
+	/// Reads up to `buffer.len()` bytes starting at `offset`, and returns the number of bytes read,
+	/// which is less, if the end of the file is reached, and 0 at or beyond the end.
+	/// Devices, FIFOs and sockets don't have any data, so nothing is read from them.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::InodeNum;
+	///
+	/// # let ufs = example_image();
+	/// let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref())?;
+	/// let mut buf = [0u8; 6];
+	/// let n = ufs.inode_read(inr, 10, &mut buf)?;
+	/// assert_eq!(&buf[0..n], b"simple");
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	pub fn inode_read(&self, inr: InodeNum, mut offset: u64, buffer: &mut [u8]) -> IoResult<usize> {
 		let mut blockbuf = vec![0u8; self.superblock.bsize as usize];
 		let ino = self.read_inode(inr)?;
+		if !ino.has_data() {
+			return Ok(0);
+		}
 
 		let mut boff = 0;
-		let len = buffer.len() as u64;
-		let end = offset + len;
+		let end = offset.saturating_add(buffer.len() as u64).min(ino.size);
+		let start = offset;
 
 		while offset < end {
-			let block = self.inode_find_block(inr, &ino, offset);
+			let block = find_block(&self.superblock, &ino, offset)?;
 			let num = (block.size - block.off).min(end - offset);
 
 			self.inode_read_block(
@@ -33,18 +111,153 @@ impl<R: Read + Seek> Ufs<R> {
 				block.blkidx,
 				&mut blockbuf[0..(block.size as usize)],
 			)?;
-			buffer[boff..(boff + num as usize)].copy_from_slice(&blockbuf[0..(num as usize)]);
+			let off = block.off as usize;
+			buffer[boff..(boff + num as usize)]
+				.copy_from_slice(&blockbuf[off..(off + num as usize)]);
 
 			offset += num;
 			boff += num as usize;
 		}
 
+		if self.options.readahead > 0 {
+			self.readahead(inr, &ino, start, end);
+		}
 		Ok(boff)
 	}
 
-	pub(super) fn read_inode(&mut self, inr: InodeNum) -> IoResult<Inode> {
-		let off = self.superblock.ino_to_fso(inr);
-		let ino: Inode = self.file.decode_at(off)?;
+	/// Find the next data region or hole of a file, starting at `offset`.
+	///
+	/// Fails with `ENXIO`, if `offset` is beyond the end of the file,
+	/// or if there is no more data after `offset`.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::{InodeNum, Whence};
+	///
+	/// # let ufs = example_image();
+	/// let inr = ufs.dir_lookup(InodeNum::ROOT, "sparse".as_ref())?;
+	/// let size = ufs.inode_attr(inr)?.size;
+	///
+	/// // The file starts with a large hole, and ends with a few blocks of data.
+	/// assert_eq!(ufs.inode_seek(inr, 0, Whence::Hole)?, 0);
+	/// let data = ufs.inode_seek(inr, 0, Whence::Data)?;
+	/// assert_eq!(data, (12 + 4095) * 32768);
+	/// assert_eq!(ufs.inode_seek(inr, data, Whence::Hole)?, size);
+	///
+	/// // Seeking beyond the end of the file fails
+	/// assert!(ufs.inode_seek(inr, size, Whence::Data).is_err());
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	#[doc(alias("lseek", "SEEK_DATA", "SEEK_HOLE"))]
+	pub fn inode_seek(&self, inr: InodeNum, offset: u64, whence: Whence) -> IoResult<u64> {
+		let ino = self.read_inode(inr)?;
+		if offset >= ino.size || !ino.has_data() {
+			return Err(err!(ENXIO));
+		}
+
+		let InodeData::Blocks(InodeBlocks { direct, indirect }) = &ino.data else {
+			// short symlinks are entirely made of data
+			return match whence {
+				Whence::Data => Ok(offset),
+				Whence::Hole => Ok(ino.size),
+			};
+		};
+
+		let bs = self.superblock.bsize as u64;
+		let pbp = bs / size_of::<UfsDaddr>() as u64;
+		let want_data = whence == Whence::Data;
+		let start = offset / bs;
+
+		let snap = ino.is_snapshot();
+		let mut found = None;
+		for (i, &ptr) in direct.iter().enumerate() {
+			let i = i as u64;
+			if i >= start {
+				found = self.seek_tree(ptr as u64, 0, i, start, want_data, snap)?;
+				if found.is_some() {
+					break;
+				}
+			}
+		}
+
+		let mut base = UFS_NDADDR as u64;
+		let mut span = pbp;
+		for (level, &ptr) in indirect.iter().enumerate() {
+			if found.is_some() {
+				break;
+			}
+			if start < base + span {
+				found =
+					self.seek_tree(ptr as u64, level as u32 + 1, base, start, want_data, snap)?;
+			}
+			base += span;
+			span *= pbp;
+		}
+
+		match (whence, found.map(|blk| (blk * bs).max(offset))) {
+			(Whence::Data, Some(pos)) if pos < ino.size => Ok(pos),
+			(Whence::Data, _) => Err(err!(ENXIO)),
+			(Whence::Hole, Some(pos)) => Ok(pos.min(ino.size)),
+			(Whence::Hole, None) => Ok(ino.size),
+		}
+	}
+
+	/// Find the first block `>= start`, which is (not) allocated,
+	/// in the block tree of depth `level`, referenced by `ptr` and beginning at block `base`.
+	///
+	/// In snapshots, unallocated blocks contain data, and only [`BLK_NOCOPY`]
+	/// and [`BLK_SNAP`] are holes (see [`map_block()`]).
+	fn seek_tree(
+		&self,
+		ptr: u64,
+		level: u32,
+		base: u64,
+		start: u64,
+		want_data: bool,
+		snap: bool,
+	) -> IoResult<Option<u64>> {
+		let first = base.max(start);
+		let frag = self.superblock.frag as u64;
+		if ptr == 0 {
+			return Ok((want_data == snap).then_some(first));
+		} else if snap && ptr < frag {
+			return Ok((!want_data).then_some(first));
+		} else if level == 0 {
+			return Ok(want_data.then_some(first));
+		}
+
+		let bs = self.superblock.bsize as u64;
+		let pbp = bs / size_of::<UfsDaddr>() as u64;
+		let span = pbp.pow(level - 1);
+
+		let mut block = vec![0u8; bs as usize];
+		self.read_at(frag_addr(&self.geo, ptr)?, &mut block)?;
+		let mut rdr = Decoder::new(Cursor::new(block), self.config);
+
+		let skip = (first - base) / span;
+		rdr.seek(skip * size_of::<UfsDaddr>() as u64)?;
+		for i in skip..pbp {
+			let child: u64 = rdr.decode()?;
+			let found =
+				self.seek_tree(child, level - 1, base + i * span, start, want_data, snap)?;
+			if found.is_some() {
+				return Ok(found);
+			}
+		}
+
+		Ok(None)
+	}
+
+	pub(super) fn read_inode(&self, inr: InodeNum) -> IoResult<Inode> {
+		if !self.inode_inited(inr)? {
+			log::warn!("uninitialized inode {inr}");
+			return Err(err!(EINVAL));
+		}
+
+		let off = ino_addr(&self.geo, inr)?;
+		let ino: Inode = self.decode_at(off, UFS_INOSZ)?;
 
 		if (ino.mode & S_IFMT) == 0 {
 			log::warn!("invalid inode {inr}");
@@ -54,19 +267,47 @@ impl<R: Read + Seek> Ufs<R> {
 		Ok(ino)
 	}
 
+	/// Check whether the inode `inr` was initialized.
+	///
+	/// FreeBSD initializes the inode blocks of a cylinder group lazily,
+	/// so inodes after `initediblk` of their cylinder group may contain garbage.
+	pub(super) fn inode_inited(&self, inr: InodeNum) -> IoResult<bool> {
+		let geo = &self.geo;
+		let cgx = geo.ino_to_cg(inr);
+		let Some(cell) = self.inited.get(cgx as usize) else {
+			return Ok(false);
+		};
+
+		let inited = match cell.get() {
+			Some(&inited) => inited,
+			None => {
+				let pos = cg_addr(geo, cgx, geo.cblkno())?;
+				let cg: CylGroup = self.decode_at(pos, size_of::<CylGroup>())?;
+				// Damaged cylinder groups are only accepted in force mode,
+				// in which case all inodes are assumed to be initialized.
+				let ipg = self.superblock.ipg;
+				let inited = match cg.magic {
+					CG_MAGIC => cg.initediblk.min(ipg),
+					_ => ipg,
+				};
+				*cell.get_or_init(|| inited)
+			}
+		};
+		Ok(inr.get64() % geo.ipg() < inited as u64)
+	}
+
 	pub(super) fn inode_read_block(
-		&mut self,
+		&self,
 		inr: InodeNum,
 		ino: &Inode,
 		blkidx: u64,
 		buf: &mut [u8],
 	) -> IoResult<usize> {
 		log::trace!("read_file_block({inr}, {blkidx});");
-		let fs = self.superblock.fsize as u64;
-		let size = self.inode_get_block_size(ino, blkidx);
+		let size = block_size(&self.superblock, ino, blkidx)?;
 		match self.inode_resolve_block(inr, ino, blkidx)? {
 			Some(blkno) => {
-				self.file.read_at(blkno.get() * fs, &mut buf[0..size])?;
+				self.read_at(frag_addr(&self.geo, blkno.get())?, &mut buf[0..size])?;
 			}
 			None => buf.fill(0u8),
 		}
@@ -74,152 +315,232 @@ impl<R: Read + Seek> Ufs<R> {
 		Ok(size)
 	}
 
-	pub(super) fn inode_find_block(
-		&mut self,
+	/// Read consecutive blocks of a file into `buf`, starting at `blkidx`,
+	/// using a single read, as long as they are contiguous on the device.
+	///
+	/// At most `buf.len() / bsize` blocks are read, and at least one.
+	/// Blocks after `nblocks` aren't read. Returns the number of blocks read.
+	pub(super) fn inode_read_blocks(
+		&self,
 		inr: InodeNum,
 		ino: &Inode,
-		offset: u64,
-	) -> BlockInfo {
-		let bs = self.superblock.bsize as u64;
-		let fs = self.superblock.fsize as u64;
-		let (blocks, frags) = ino.size(bs, fs);
-		log::trace!(
-			"find_file_block({inr}, {offset}): size={}, blocks={blocks}, frags={frags}",
-			ino.size
-		);
+		blkidx: u64,
+		nblocks: u64,
+		buf: &mut [u8],
+	) -> IoResult<u64> {
+		let sb = &self.superblock;
+		let bs = sb.bsize as usize;
+		let frag = sb.frag as u64;
+		let max = (nblocks - blkidx).min((buf.len() / bs) as u64);
+		log::trace!("inode_read_blocks({inr}, {blkidx}, {max});");
 
-		let x = if offset < (bs * blocks) {
-			BlockInfo {
-				blkidx: offset / bs,
-				off:    offset % bs,
-				size:   bs,
-			}
-		} else if offset < (bs * blocks + fs * frags) {
-			BlockInfo {
-				blkidx: blocks,
-				off:    offset % bs,
-				size:   frags * fs,
-			}
-		} else {
-			panic!("out of bounds");
+		let Some(first) = self.inode_resolve_block(inr, ino, blkidx)? else {
+			let size = block_size(sb, ino, blkidx)?;
+			buf[0..size].fill(0u8);
+			return Ok(1);
 		};
-		log::trace!("find_file_block({inr}, {offset}) = {x:?}");
-		x
+
+		// Only full blocks can be followed by another block.
+		let mut n = 1;
+		let mut size = block_size(sb, ino, blkidx)?;
+		while n < max && size == n as usize * bs {
+			match self.inode_resolve_block(inr, ino, blkidx + n)? {
+				Some(blkno) if blkno.get() == first.get() + n * frag => {
+					size += block_size(sb, ino, blkidx + n)?;
+					n += 1;
+				}
+				_ => break,
+			}
+		}
+
+		self.read_at(frag_addr(&self.geo, first.get())?, &mut buf[0..size])?;
+		Ok(n)
 	}
 
-	fn inode_resolve_block(
-		&mut self,
+	pub(super) fn inode_resolve_block(
+		&self,
 		inr: InodeNum,
 		ino: &Inode,
 		blkno: u64,
 	) -> IoResult<Option<NonZeroU64>> {
-		let sb = &self.superblock;
-		let fs = sb.fsize as u64;
-		let bs = sb.bsize as u64;
-		let nd = UFS_NDADDR as u64;
+		let frag = self.superblock.frag as u64;
+		let snap = ino.is_snapshot();
 		let su64 = size_of::<UfsDaddr>() as u64;
-		let pbp = bs / su64;
 
 		let InodeData::Blocks(InodeBlocks { direct, indirect }) = &ino.data else {
-			log::warn!("resolve_file_block({inr}, {blkno}): inode doesn't have blocks");
-			return Err(err!(EIO));
+			return Err(corrupt!(
+				"resolve_file_block({inr}, {blkno}): inode doesn't have blocks"
+			));
 		};
 
-		let begin_indir1 = nd;
-		let begin_indir2 = nd + pbp;
-		let begin_indir3 = nd + pbp + pbp * pbp;
-		let begin_indir4 = nd + pbp + pbp * pbp + pbp * pbp * pbp;
+		match block_path(&self.superblock, blkno) {
+			BlockPath::Direct(i) => Ok(map_block(&self.superblock, ino, blkno, direct[i] as u64)),
+			BlockPath::Indirect(level, idx) => {
+				let idx = &idx[0..=level];
+				log::trace!(
+					"resolve_file_block({inr}, {blkno}): {}-indirect: {idx:?}",
+					level + 1
+				);
 
-		if blkno < begin_indir1 {
-			Ok(NonZeroU64::new(direct[blkno as usize] as u64))
-		} else if blkno < begin_indir2 {
-			let low = blkno - begin_indir1;
-			assert!(low < pbp);
+				let mut ptr = indirect[level] as u64;
+				for &i in idx {
+					if ptr == 0 || (snap && ptr < frag) {
+						return Ok(map_block(&self.superblock, ino, blkno, ptr));
+					}
+					ptr =
+						self.decode_at(frag_addr(&self.geo, ptr)? + i * su64, size_of::<u64>())?;
+				}
+				Ok(map_block(&self.superblock, ino, blkno, ptr))
+			}
+			BlockPath::OutOfRange => {
+				log::warn!("resolve_file_block({inr}, {blkno}): block number too large");
+				Ok(None)
+			}
+		}
+	}
+}
 
-			log::trace!("resolve_file_block({inr}, {blkno}): 1-indirect: low={low}");
+/// Position of a logical block within the block tree of an inode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum BlockPath {
+	/// Index into the direct block pointers.
+	Direct(usize),
 
-			let first = indirect[0] as u64;
-			if first == 0 {
-				return Ok(None);
-			}
+	/// Index into the indirect block pointers (the level of indirection - 1),
+	/// followed by the index into each indirect block, from the top down.
+	Indirect(usize, [u64; UFS_NIADDR]),
 
-			let pos = first * fs + low * su64;
-			let block: u64 = self.file.decode_at(pos)?;
-			log::trace!("first={first:#x} *{pos:#x} = {block:#x}");
-			Ok(NonZeroU64::new(block))
-		} else if blkno < begin_indir3 {
-			let x = blkno - begin_indir2;
-			let low = x % pbp;
-			let high = x / pbp;
-			assert!(high < pbp);
-
-			log::trace!("resolve_file_block({inr}, {blkno}): 2-indirect: high={high}, low={low}");
-
-			let first = indirect[1] as u64;
-			if first == 0 {
-				return Ok(None);
-			}
-			let pos = first * fs + high * su64;
-			let snd: u64 = self.file.decode_at(pos)?;
-			log::trace!("first={first:x} pos={pos:x} snd={snd:x}");
-			if snd == 0 {
-				return Ok(None);
-			}
+	/// The block number is too large to be addressed.
+	OutOfRange,
+}
 
-			let pos = snd * fs + low * su64;
-			let block: u64 = self.file.decode_at(pos)?;
-			log::trace!("*{pos:x} = {block:x}");
-			Ok(NonZeroU64::new(block))
-		} else if blkno < begin_indir4 {
-			let x = blkno - begin_indir3;
-			let low = x % pbp;
-			let mid = x / pbp % pbp;
-			let high = x / pbp / pbp;
-			assert!(high < pbp);
-
-			log::trace!(
-				"resolve_file_block({inr}, {blkno}): 3-indirect: x={x:#x} high={high:#x}, mid={mid:#x}, low={low:#x}"
-			);
+/// Find where the block pointer of logical block `blkno` is stored.
+pub(super) fn block_path(sb: &Superblock, blkno: u64) -> BlockPath {
+	let pbp = sb.bsize as u64 / size_of::<UfsDaddr>() as u64;
+	let nd = UFS_NDADDR as u64;
 
-			let first = indirect[2] as u64;
-			log::trace!("first = {first:#x}");
-			if first == 0 {
-				return Ok(None);
-			}
+	if blkno < nd {
+		return BlockPath::Direct(blkno as usize);
+	}
 
-			let pos = first * fs + high * su64;
-			let second: u64 = self.file.decode_at(pos)?;
-			log::trace!("second = {second:#x}");
-			if second == 0 {
-				return Ok(None);
+	let mut x = blkno - nd;
+	let mut span = pbp;
+	for level in 0..UFS_NIADDR {
+		if x < span {
+			let mut idx = [0; UFS_NIADDR];
+			for i in idx[0..=level].iter_mut().rev() {
+				*i = x % pbp;
+				x /= pbp;
 			}
+			return BlockPath::Indirect(level, idx);
+		}
+		x -= span;
+		span *= pbp;
+	}
 
-			let pos = second * fs + mid * su64;
-			let third: u64 = self.file.decode_at(pos)?;
-			log::trace!("third = {third:#x}");
-			if third == 0 {
-				return Ok(None);
-			}
-			let pos = third * fs + low * su64;
-			let block: u64 = self.file.decode_at(pos)?;
-			Ok(NonZeroU64::new(block))
-		} else {
-			log::warn!("block number too large: {blkno} >= {begin_indir4}");
-			Ok(None)
+	BlockPath::OutOfRange
+}
+
+/// Address of logical block `blkno` of `ino`, whose block pointer is `ptr`.
+///
+/// Snapshots are images of the whole filesystem, so their unallocated blocks refer
+/// to the unchanged block at the same address. Block pointers below `frag`
+/// ([`BLK_NOCOPY`], [`BLK_SNAP`]) refer to blocks, which were free, and read as zeros.
+pub(super) fn map_block(sb: &Superblock, ino: &Inode, blkno: u64, ptr: u64) -> Option<NonZeroU64> {
+	let frag = sb.frag as u64;
+	match (ptr, ino.is_snapshot()) {
+		(0, true) => NonZeroU64::new(blkno * frag),
+		(p, true) if p < frag => None,
+		(p, _) => NonZeroU64::new(p),
+	}
+}
+
+/// Find the block, which contains byte `offset` of a file.
+///
+/// Fails, if `offset` is beyond the blocks of the file, eg. because the size of a device
+/// or a FIFO is not 0.
+pub(super) fn find_block(sb: &Superblock, ino: &Inode, offset: u64) -> IoResult<BlockInfo> {
+	let bs = sb.bsize as u64;
+	let fs = sb.fsize as u64;
+	let (blocks, frags) = ino.size(bs, fs);
+	log::trace!(
+		"find_file_block({offset}): size={}, blocks={blocks}, frags={frags}",
+		ino.size
+	);
+
+	let x = if offset < (bs * blocks) {
+		BlockInfo {
+			blkidx: offset / bs,
+			off:    offset % bs,
+			size:   bs,
+		}
+	} else if offset - bs * blocks < fs * frags {
+		BlockInfo {
+			blkidx: blocks,
+			off:    offset % bs,
+			size:   frags * fs,
 		}
+	} else {
+		return Err(corrupt!(
+			"offset {offset} is beyond the blocks of a file of size {}",
+			ino.size
+		));
+	};
+	log::trace!("find_file_block({offset}) = {x:?}");
+	Ok(x)
+}
+
+/// Size of the logical block `blkidx` of a file,
+/// which is smaller than the block size for the fragments at the end.
+pub(super) fn block_size(sb: &Superblock, ino: &Inode, blkidx: u64) -> IoResult<usize> {
+	let bs = sb.bsize as u64;
+	let fs = sb.fsize as u64;
+	let (blocks, frags) = ino.size(bs, fs);
+
+	if blkidx < blocks {
+		Ok(bs as usize)
+	} else if blkidx < blocks + frags {
+		Ok((fs * frags) as usize)
+	} else {
+		Err(corrupt!(
+			"block {blkidx} is beyond the end of a file of size {}",
+			ino.size
+		))
 	}
+}
 
-	fn inode_get_block_size(&mut self, ino: &Inode, blkidx: u64) -> usize {
-		let bs = self.superblock.bsize as u64;
-		let fs = self.superblock.fsize as u64;
-		let (blocks, frags) = ino.size(bs, fs);
-
-		if blkidx < blocks {
-			bs as usize
-		} else if blkidx < blocks + frags {
-			(fs * frags) as usize
-		} else {
-			panic!("out of bounds: {blkidx}, blocks: {blocks}, frags: {frags}");
+#[cfg(test)]
+mod t {
+	use super::*;
+
+	#[test]
+	fn block_paths() {
+		let mut sb: Superblock = Config::little().decode_slice(&[0u8; SBLOCKSIZE]).unwrap();
+		let nd = UFS_NDADDR as u64;
+
+		for bsize in [4096, 16384, 32768, 65536] {
+			sb.bsize = bsize;
+			let pbp = bsize as u64 / 8;
+
+			assert_eq!(block_path(&sb, 0), BlockPath::Direct(0));
+			assert_eq!(block_path(&sb, nd - 1), BlockPath::Direct(11));
+			assert_eq!(block_path(&sb, nd), BlockPath::Indirect(0, [0, 0, 0]));
+			assert_eq!(
+				block_path(&sb, nd + pbp - 1),
+				BlockPath::Indirect(0, [pbp - 1, 0, 0])
+			);
+			assert_eq!(
+				block_path(&sb, nd + pbp + 2 * pbp + 3),
+				BlockPath::Indirect(1, [2, 3, 0])
+			);
+			assert_eq!(
+				block_path(&sb, nd + pbp + pbp * pbp + 5),
+				BlockPath::Indirect(2, [0, 0, 5])
+			);
+			assert_eq!(
+				block_path(&sb, nd + pbp + pbp * pbp + pbp * pbp * pbp),
+				BlockPath::OutOfRange
+			);
 		}
 	}
 }
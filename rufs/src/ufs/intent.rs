@@ -0,0 +1,235 @@
+use std::{
+	fs::{self, File},
+	io::Write,
+};
+
+use super::*;
+use crate::err;
+
+const MAGIC: &[u8; 8] = b"RUFSILOG";
+const VERSION: u32 = 2;
+/// Size of the magic, the version, the number of records, and the id and size of the filesystem.
+const HEADER_SIZE: usize = 32;
+/// Size of the position, and the length of a record.
+const RECORD_SIZE: usize = 12;
+
+/// A write-ahead log of the fragments, that a [`Txn`] is about to write,
+/// in a file next to the filesystem, see [`Options::intent_log`].
+///
+/// Before a batch is applied, the new contents of all modified fragments are written to the
+/// log, and synced. Once the batch was applied and synced, the log is truncated.
+/// If the system crashes in between, the complete log is applied again,
+/// the next time the filesystem is opened, and an incomplete log is discarded,
+/// because the filesystem wasn't modified yet.
+/// The log is only applied to the filesystem, which wrote it: its id and size
+/// are stored in the log, and compared to the ones in the superblock.
+///
+/// The log consists of a header (`"RUFSILOG"`, version, number of records,
+/// and the id and size of the filesystem), followed by the records (position, length and data),
+/// and a FNV-1a checksum of all of it, all in little endian.
+pub(super) struct IntentLog {
+	path: PathBuf,
+	/// `fs_id` of the filesystem.
+	id:   u64,
+	/// Size of the filesystem, in fragments.
+	size: u64,
+}
+
+/// The contents of a complete log.
+struct Parsed {
+	id:     u64,
+	size:   u64,
+	writes: Vec<(u64, Vec<u8>)>,
+}
+
+impl IntentLog {
+	pub(super) fn new(path: &Path, sb: &Superblock) -> Self {
+		let id = (sb.id[0] as u32 as u64) | ((sb.id[1] as u32 as u64) << 32);
+		Self {
+			path: path.to_owned(),
+			id,
+			size: sb.size as u64,
+		}
+	}
+
+	/// Write the log of `writes`, and make sure, that it reached stable storage.
+	pub(super) fn record(&self, writes: &[(u64, Vec<u8>)]) -> IoResult<()> {
+		let mut buf = Vec::new();
+		buf.extend_from_slice(MAGIC);
+		buf.extend_from_slice(&VERSION.to_le_bytes());
+		buf.extend_from_slice(&(writes.len() as u32).to_le_bytes());
+		buf.extend_from_slice(&self.id.to_le_bytes());
+		buf.extend_from_slice(&self.size.to_le_bytes());
+		for (pos, data) in writes {
+			buf.extend_from_slice(&pos.to_le_bytes());
+			buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+			buf.extend_from_slice(data);
+		}
+		buf.extend_from_slice(&fnv1a(&buf).to_le_bytes());
+
+		let mut file = File::create(&self.path)?;
+		file.write_all(&buf)?;
+		file.sync_all()?;
+		// The log may have just been created, so its directory entry has to be synced as well.
+		let dir = match self.path.parent() {
+			Some(dir) if !dir.as_os_str().is_empty() => dir,
+			_ => Path::new("."),
+		};
+		File::open(dir)?.sync_all()
+	}
+
+	/// Truncate the log, after its writes were applied and synced.
+	pub(super) fn clear(&self) -> IoResult<()> {
+		let file = File::create(&self.path)?;
+		file.sync_all()
+	}
+
+	/// Get the writes of a complete log, which still have to be applied.
+	///
+	/// A missing or empty log has none, and an incomplete or corrupted one is discarded.
+	/// Fails with `EINVAL`, if the log was written for another filesystem.
+	pub(super) fn pending(&self) -> IoResult<Vec<(u64, Vec<u8>)>> {
+		let buf = match fs::read(&self.path) {
+			Ok(buf) => buf,
+			Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+			Err(e) => return Err(e),
+		};
+		if buf.is_empty() {
+			return Ok(Vec::new());
+		}
+		match parse(&buf) {
+			Some(log) if log.id != self.id || log.size != self.size => {
+				log::error!(
+					"the intent log {} belongs to another filesystem (id {:#x}, {} fragments), \
+					 than this one (id {:#x}, {} fragments)",
+					self.path.display(),
+					log.id,
+					log.size,
+					self.id,
+					self.size
+				);
+				Err(err!(EINVAL))
+			}
+			Some(log) => Ok(log.writes),
+			None => {
+				log::warn!(
+					"discarding the incomplete intent log {}",
+					self.path.display()
+				);
+				Ok(Vec::new())
+			}
+		}
+	}
+
+	/// Apply the writes of a complete log to `backend`, and truncate it.
+	///
+	/// Returns the number of bytes, that were written.
+	pub(super) fn replay<B: Backend>(&self, backend: &B) -> IoResult<u64> {
+		let writes = self.pending()?;
+		if writes.is_empty() {
+			return Ok(0);
+		}
+
+		log::warn!(
+			"replaying {} writes of the intent log {}",
+			writes.len(),
+			self.path.display()
+		);
+		let mut len = 0;
+		for (pos, data) in &writes {
+			backend.write_at(*pos, data).map_err(|e| {
+				log::error!("failed to replay the intent log: {e}");
+				e
+			})?;
+			len += data.len() as u64;
+		}
+		backend.sync()?;
+		self.clear()?;
+		Ok(len)
+	}
+}
+
+fn parse(buf: &[u8]) -> Option<Parsed> {
+	let (body, sum) = buf.split_at(buf.len().checked_sub(8)?);
+	if fnv1a(body).to_le_bytes() != sum || body.len() < HEADER_SIZE || &body[0..8] != MAGIC {
+		return None;
+	}
+	let u32_at = |pos: usize| {
+		Some(u32::from_le_bytes(
+			body.get(pos..(pos + 4))?.try_into().ok()?,
+		))
+	};
+	let u64_at = |pos: usize| {
+		Some(u64::from_le_bytes(
+			body.get(pos..(pos + 8))?.try_into().ok()?,
+		))
+	};
+	if u32_at(8)? != VERSION {
+		return None;
+	}
+
+	let mut writes = Vec::new();
+	let mut pos = HEADER_SIZE;
+	for _ in 0..u32_at(12)? {
+		let start = u64_at(pos)?;
+		let len = u32_at(pos + 8)? as usize;
+		pos += RECORD_SIZE;
+		writes.push((start, body.get(pos..(pos + len))?.to_vec()));
+		pos += len;
+	}
+	(pos == body.len()).then_some(Parsed {
+		id: u64_at(16)?,
+		size: u64_at(24)?,
+		writes,
+	})
+}
+
+/// 64-bit FNV-1a, which detects torn writes of the log.
+fn fnv1a(data: &[u8]) -> u64 {
+	data.iter().fold(0xcbf2_9ce4_8422_2325, |h, &b| {
+		(h ^ u64::from(b)).wrapping_mul(0x100_0000_01b3)
+	})
+}
+
+impl<B: Backend> Ufs<B> {
+	/// The intent log, which is used for batches, see [`Options::intent_log`].
+	pub(super) fn intent_log(&self) -> Option<IntentLog> {
+		let path = self.options.intent_log.as_deref()?;
+		Some(IntentLog::new(path, &self.superblock))
+	}
+}
+
+/// Replay the intent log of `options`, once the superblock `sb` was validated,
+/// but before anything else is read.
+///
+/// A log, which still has to be applied, needs [`WriteCaps::raw`].
+pub(super) fn replay_intent_log<B: Backend>(
+	backend: &B,
+	sb: &Superblock,
+	options: &Options,
+) -> IoResult<u64> {
+	let Some(path) = &options.intent_log else {
+		return Ok(0);
+	};
+	let log = IntentLog::new(path, sb);
+	if !options.write.raw {
+		if log.pending()?.is_empty() {
+			return Ok(0);
+		}
+		log::error!(
+			"the intent log {} must be replayed, but raw writes are not enabled",
+			path.display()
+		);
+		return Err(err!(EROFS));
+	}
+	log.replay(backend).map_err(|e| {
+		if e.raw_os_error() == Some(libc::EROFS) {
+			log::error!(
+				"the intent log {} must be replayed, but the device is read-only",
+				path.display()
+			);
+			return err!(EROFS);
+		}
+		e
+	})
+}
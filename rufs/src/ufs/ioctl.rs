@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+
+use super::*;
+use crate::{err, InodeNum};
+
+/// `_IOWR('X', 121, struct fstrim_range)`, see [`Ufs::trim()`].
+pub const FITRIM: u32 = 0xc018_5879;
+/// `_IOR('f', 1, int)`, which FUSE uses for the flags of `lsattr(1)`.
+pub const FS_IOC32_GETFLAGS: u32 = 0x8004_6601;
+/// `_IOW('f', 2, int)`, which FUSE uses for the flags of `chattr(1)`.
+pub const FS_IOC32_SETFLAGS: u32 = 0x4004_6602;
+/// `_IOWR('f', 11, struct fiemap)`, see [`Ufs::inode_extents()`].
+pub const FS_IOC_FIEMAP: u32 = 0xc020_660b;
+/// `_IOR('f', 1, long)`, the flags of `lsattr(1)`.
+pub const FS_IOC_GETFLAGS: u32 = 0x8008_6601;
+/// `_IOW('f', 2, long)`, the flags of `chattr(1)`.
+pub const FS_IOC_SETFLAGS: u32 = 0x4008_6602;
+
+/// An `ioctl(2)`, that was made on a file of the filesystem.
+#[derive(Debug, Clone, Copy)]
+pub struct IoctlRequest<'a> {
+	/// The file, on which the `ioctl(2)` was made.
+	pub inr: InodeNum,
+
+	/// The command, like [`FS_IOC_FIEMAP`].
+	pub cmd: u32,
+
+	/// The process, which made the `ioctl(2)`.
+	pub cred: &'a Credentials,
+
+	/// The argument, which was copied from the process.
+	pub input: &'a [u8],
+
+	/// Maximum number of bytes, that can be copied back to the process.
+	pub out_size: u32,
+}
+
+/// A handler of an `ioctl(2)` command, which can be registered in [`Ioctls`].
+///
+/// This is implemented for closures, which take the same arguments as [`Ioctl::ioctl()`].
+pub trait Ioctl<B: Backend>: Send + Sync {
+	/// Handle `req`, and return the data, which is copied back to the process.
+	fn ioctl(&self, ufs: &Ufs<B>, req: &IoctlRequest<'_>) -> IoResult<Vec<u8>>;
+}
+
+impl<B, F> Ioctl<B> for F
+where
+	B: Backend,
+	F: Fn(&Ufs<B>, &IoctlRequest<'_>) -> IoResult<Vec<u8>> + Send + Sync,
+{
+	fn ioctl(&self, ufs: &Ufs<B>, req: &IoctlRequest<'_>) -> IoResult<Vec<u8>> {
+		self(ufs, req)
+	}
+}
+
+/// A table of `ioctl(2)` commands, and their handlers, for passing them through from FUSE.
+///
+/// # Example
+/// ```
+/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+/// # use support::*;
+/// use rufs::{Credentials, InodeNum, IoctlRequest, Ioctls};
+///
+/// # let ufs = example_image();
+/// const GET_SIZE: u32 = 0x8008_7801;
+///
+/// let mut ioctls = Ioctls::new();
+/// ioctls.register(GET_SIZE, |ufs: &MemUfs, req: &IoctlRequest<'_>| {
+///     Ok(ufs.inode_attr(req.inr)?.size.to_ne_bytes().to_vec())
+/// });
+///
+/// let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref())?;
+/// let req = IoctlRequest {
+///     inr,
+///     cmd: GET_SIZE,
+///     cred: &Credentials::new(1000, 1000),
+///     input: &[],
+///     out_size: 8,
+/// };
+/// assert_eq!(ioctls.call(&ufs, &req)?, 23u64.to_ne_bytes());
+///
+/// // Unknown commands fail with ENOTTY.
+/// let req = IoctlRequest { cmd: 0x1234, ..req };
+/// assert_eq!(ioctls.call(&ufs, &req).unwrap_err().raw_os_error(), Some(libc::ENOTTY));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct Ioctls<B: Backend> {
+	handlers: HashMap<u32, Box<dyn Ioctl<B>>>,
+}
+
+impl<B: Backend> Ioctls<B> {
+	/// A table without any commands.
+	pub fn new() -> Self {
+		Self {
+			handlers: HashMap::new(),
+		}
+	}
+
+	/// The commands of Linux filesystems, which have a meaning for UFS:
+	/// [`FS_IOC_FIEMAP`], [`FS_IOC_GETFLAGS`] and [`FS_IOC_SETFLAGS`],
+	/// which are mapped to the flags of `chflags(1)`.
+	///
+	/// [`FITRIM`] is left out, because it needs a writable backend,
+	/// see [`Ioctls::linux_writable()`].
+	#[cfg(target_os = "linux")]
+	pub fn linux() -> Self
+	where
+		B: 'static,
+	{
+		let mut ioctls = Self::new();
+		ioctls.register(FS_IOC_FIEMAP, linux::fiemap);
+		ioctls.register(FS_IOC_GETFLAGS, linux::getflags);
+		ioctls.register(FS_IOC32_GETFLAGS, linux::getflags);
+		ioctls.register(FS_IOC_SETFLAGS, linux::setflags);
+		ioctls.register(FS_IOC32_SETFLAGS, linux::setflags);
+		ioctls
+	}
+
+	/// Like [`Ioctls::linux()`], and [`FITRIM`], which discards free fragments,
+	/// for backends, which were opened for writing.
+	#[cfg(target_os = "linux")]
+	pub fn linux_writable() -> Self
+	where
+		B: 'static,
+	{
+		let mut ioctls = Self::linux();
+		ioctls.register(FITRIM, linux::fitrim);
+		ioctls
+	}
+
+	/// Handle `cmd` with `handler`, instead of any previously registered handler.
+	pub fn register(&mut self, cmd: u32, handler: impl Ioctl<B> + 'static) {
+		self.handlers.insert(cmd, Box::new(handler));
+	}
+
+	/// Check whether there is a handler for `cmd`.
+	pub fn contains(&self, cmd: u32) -> bool {
+		self.handlers.contains_key(&cmd)
+	}
+
+	/// Handle `req`, or fail with `ENOTTY`, if there is no handler for the command.
+	pub fn call(&self, ufs: &Ufs<B>, req: &IoctlRequest<'_>) -> IoResult<Vec<u8>> {
+		let Some(handler) = self.handlers.get(&req.cmd) else {
+			log::debug!("ioctl({}, {:#x}): unknown command", req.inr, req.cmd);
+			return Err(err!(ENOTTY));
+		};
+		handler.ioctl(ufs, req)
+	}
+}
+
+impl<B: Backend> Default for Ioctls<B> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// The handlers of [`Ioctls::linux()`].
+#[cfg(target_os = "linux")]
+mod linux {
+	use super::*;
+	use crate::{SF_APPEND, SF_IMMUTABLE, UF_APPEND, UF_IMMUTABLE, UF_NODUMP};
+
+	/// Sizes of `struct fiemap` and `struct fiemap_extent`.
+	const FIEMAP_SIZE: usize = 32;
+	const FIEMAP_EXTENT_SIZE: usize = 56;
+	/// Flush dirty data before mapping, which is a no-op on a read-only filesystem.
+	const FIEMAP_FLAG_SYNC: u32 = 0x1;
+
+	/// Flags of `chattr(1)`, which have an equivalent `chflags(1)` flag.
+	const FS_IMMUTABLE_FL: u32 = 0x10;
+	const FS_APPEND_FL: u32 = 0x20;
+	const FS_NODUMP_FL: u32 = 0x40;
+
+	/// Read the native-endian integer at `pos` of the argument of an `ioctl(2)`.
+	fn arg<const N: usize>(input: &[u8], pos: usize) -> IoResult<[u8; N]> {
+		input
+			.get(pos..(pos + N))
+			.map(|x| x.try_into().unwrap())
+			.ok_or(err!(EINVAL))
+	}
+
+	/// Handle `FS_IOC_FIEMAP`: the input is a `struct fiemap`, which is returned with as many
+	/// extents, as were requested and fit into the output.
+	pub(super) fn fiemap<B: Backend>(ufs: &Ufs<B>, req: &IoctlRequest<'_>) -> IoResult<Vec<u8>> {
+		let input = req.input;
+		let start = u64::from_ne_bytes(arg(input, 0)?);
+		let end = start.saturating_add(u64::from_ne_bytes(arg(input, 8)?));
+		let flags = u32::from_ne_bytes(arg(input, 16)?);
+		let count = u32::from_ne_bytes(arg(input, 24)?) as usize;
+		if flags & !FIEMAP_FLAG_SYNC != 0 {
+			return Err(err!(EBADR));
+		}
+
+		let extents = ufs
+			.inode_extents(req.inr)?
+			.into_iter()
+			.filter(|e| e.logical < end && e.logical + e.length > start)
+			.collect::<Vec<_>>();
+		let room = (req.out_size as usize).saturating_sub(FIEMAP_SIZE) / FIEMAP_EXTENT_SIZE;
+		// With fm_extent_count == 0, only the number of extents is returned.
+		let n = if count == 0 {
+			0
+		} else {
+			extents.len().min(count).min(room)
+		};
+		let mapped = if count == 0 { extents.len() } else { n };
+
+		let mut out = input.get(0..FIEMAP_SIZE).ok_or(err!(EINVAL))?.to_vec();
+		out[20..24].copy_from_slice(&(mapped as u32).to_ne_bytes());
+		for e in &extents[0..n] {
+			let mut fe = [0u8; FIEMAP_EXTENT_SIZE];
+			fe[0..8].copy_from_slice(&e.logical.to_ne_bytes());
+			fe[8..16].copy_from_slice(&e.physical.to_ne_bytes());
+			fe[16..24].copy_from_slice(&e.length.to_ne_bytes());
+			fe[40..44].copy_from_slice(&e.flags.to_ne_bytes());
+			out.extend_from_slice(&fe);
+		}
+		Ok(out)
+	}
+
+	/// Translate the flags of `chflags(1)` to the flags of `chattr(1)`.
+	fn linux_flags(flags: u32) -> u32 {
+		let mut fl = 0;
+		if flags & (UF_IMMUTABLE | SF_IMMUTABLE) != 0 {
+			fl |= FS_IMMUTABLE_FL;
+		}
+		if flags & (UF_APPEND | SF_APPEND) != 0 {
+			fl |= FS_APPEND_FL;
+		}
+		if flags & UF_NODUMP != 0 {
+			fl |= FS_NODUMP_FL;
+		}
+		fl
+	}
+
+	/// Handle `FS_IOC_GETFLAGS`, which returns an `int`, even though its size is that of a `long`.
+	pub(super) fn getflags<B: Backend>(ufs: &Ufs<B>, req: &IoctlRequest<'_>) -> IoResult<Vec<u8>> {
+		let attr = ufs.inode_attr(req.inr)?;
+		Ok(linux_flags(attr.flags).to_ne_bytes().to_vec())
+	}
+
+	/// Handle `FS_IOC_SETFLAGS`, which succeeds, if the flags wouldn't change.
+	pub(super) fn setflags<B: Backend>(ufs: &Ufs<B>, req: &IoctlRequest<'_>) -> IoResult<Vec<u8>> {
+		let fl = u32::from_ne_bytes(arg(req.input, 0)?);
+		let attr = ufs.inode_attr(req.inr)?;
+		if fl == linux_flags(attr.flags) {
+			return Ok(Vec::new());
+		}
+		if fl & !(FS_IMMUTABLE_FL | FS_APPEND_FL | FS_NODUMP_FL) != 0 {
+			log::debug!("ioctl({}): unsupported flags {fl:#x}", req.inr);
+			return Err(err!(EOPNOTSUPP));
+		}
+
+		// TODO: map FS_IMMUTABLE_FL and FS_APPEND_FL to the SF_* flags for root,
+		// and to the UF_* flags otherwise, like chflags(2).
+		log::error!(
+			"ioctl({}, FS_IOC_SETFLAGS): write support is not implemented",
+			req.inr
+		);
+		Err(err!(EROFS))
+	}
+
+	/// Handle `FITRIM`: the input is a `struct fstrim_range`, whose length is set to the number
+	/// of bytes, that were discarded, see [`Ufs::trim_free_space()`].
+	pub(super) fn fitrim<B: Backend>(ufs: &Ufs<B>, req: &IoctlRequest<'_>) -> IoResult<Vec<u8>> {
+		if !req.cred.is_root() {
+			return Err(err!(EPERM));
+		}
+		let start = u64::from_ne_bytes(arg(req.input, 0)?);
+		let len = u64::from_ne_bytes(arg(req.input, 8)?);
+		let minlen = u64::from_ne_bytes(arg(req.input, 16)?);
+		let len = ufs.trim_free_space(start..start.saturating_add(len), minlen)?;
+		let mut out = req.input[0..24].to_vec();
+		out[8..16].copy_from_slice(&len.to_ne_bytes());
+		Ok(out)
+	}
+}
@@ -0,0 +1,157 @@
+//! Recognizing the tool, which created a filesystem, and its quirks.
+//!
+//! Filesystems created by other tools than newfs(8) of FreeBSD aren't corrupted,
+//! but differ in ways, which must not be mistaken for corruption.
+//! makefs(8) of FreeBSD and NetBSD doesn't compute check-hashes, nor record the size
+//! of the device, and NetBSD uses some of the flags of FreeBSD for other features.
+use std::fmt;
+
+use super::*;
+
+/// Flags, which rufs knows about.
+const KNOWN_FLAGS: i32 = 0x07ff;
+
+/// Flags, which NetBSD uses for other features, than FreeBSD.
+const NETBSD_FLAGS: i32 = FS_NFS4ACLS | FS_METACKHASH;
+
+/// The tool, which most likely created a filesystem, see [`Lint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Creator {
+	/// newfs(8) of FreeBSD, or [`mkfs()`](crate::mkfs), which creates the same layout.
+	Newfs,
+	/// makefs(8) of FreeBSD or NetBSD, which creates images from a directory tree.
+	Makefs,
+	/// NetBSD, which sets flags, that mean something else on FreeBSD.
+	NetBsd,
+}
+
+impl fmt::Display for Creator {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(match self {
+			Self::Newfs => "newfs",
+			Self::Makefs => "makefs",
+			Self::NetBsd => "NetBSD",
+		})
+	}
+}
+
+/// A quirk of a filesystem, found by [`Ufs::lint()`].
+///
+/// Unlike an [`FsckFinding`], this isn't an inconsistency.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum LintFinding {
+	/// The metadata isn't protected by check-hashes, so they can't be verified.
+	NoCheckHashes,
+	/// The size of the device wasn't recorded, so a truncated image can't be detected.
+	NoProviderSize,
+	/// `flags` mean something else on NetBSD (WAPBL and quotas), and are ignored.
+	NetBsdFlags {
+		/// The flags.
+		flags: u32,
+	},
+	/// `flags` are unknown, and are ignored.
+	UnknownFlags {
+		/// The flags.
+		flags: u32,
+	},
+}
+
+impl fmt::Display for LintFinding {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::NoCheckHashes => f.write_str("metadata is not protected by check-hashes"),
+			Self::NoProviderSize => f.write_str("the size of the device is not recorded"),
+			Self::NetBsdFlags { flags } => write!(f, "NetBSD flags {flags:#x} are ignored"),
+			Self::UnknownFlags { flags } => write!(f, "unknown flags {flags:#x} are ignored"),
+		}
+	}
+}
+
+/// Which tool created a filesystem, and what can be expected of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Lint {
+	/// The tool, which most likely created the filesystem.
+	pub creator: Creator,
+
+	/// The quirks of the filesystem.
+	pub findings: Vec<LintFinding>,
+
+	/// The metadata, whose check-hashes are expected to be valid (`CK_*`),
+	/// 0 if there are none.
+	pub check_hashes: u32,
+}
+
+impl Lint {
+	pub(super) fn new(sb: &Superblock) -> Self {
+		let mut findings = Vec::new();
+		let hashed = sb.flags & FS_METACKHASH != 0 && sb.metackhash != 0;
+		let creator = if sb.flags & FS_METACKHASH != 0 && sb.metackhash == 0 {
+			// FS_DOQUOTA2 of NetBSD, which doesn't support check-hashes.
+			Creator::NetBsd
+		} else if hashed || sb.providersize != 0 {
+			Creator::Newfs
+		} else {
+			Creator::Makefs
+		};
+
+		if !hashed {
+			findings.push(LintFinding::NoCheckHashes);
+		}
+		if sb.providersize == 0 {
+			findings.push(LintFinding::NoProviderSize);
+		}
+		if creator == Creator::NetBsd {
+			findings.push(LintFinding::NetBsdFlags {
+				flags: (sb.flags & NETBSD_FLAGS) as u32,
+			});
+		}
+		if sb.flags & !KNOWN_FLAGS != 0 {
+			findings.push(LintFinding::UnknownFlags {
+				flags: (sb.flags & !KNOWN_FLAGS) as u32,
+			});
+		}
+
+		Self {
+			creator,
+			findings,
+			check_hashes: if hashed { sb.metackhash } else { 0 },
+		}
+	}
+
+	/// Log the creator and the findings, when opening the filesystem.
+	pub(super) fn log(&self) {
+		log::info!("Created by: {}", self.creator);
+		for finding in &self.findings {
+			log::info!("Quirk: {finding}");
+		}
+	}
+}
+
+impl<B: Backend> Ufs<B> {
+	/// Find out, which tool created the filesystem, and how it differs from one,
+	/// which was created by newfs(8) of FreeBSD.
+	///
+	/// This only looks at the superblock, when the filesystem is opened.
+	/// The creator is a guess, because nothing records it.
+	/// [`Ufs::check_deep()`] verifies the check-hashes, which are expected.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::{Creator, CK_CYLGRP};
+	///
+	/// # let ufs = example_image();
+	/// let lint = ufs.lint();
+	/// assert_eq!(lint.creator, Creator::Newfs);
+	/// assert_eq!(lint.findings, []);
+	/// assert_ne!(lint.check_hashes & CK_CYLGRP, 0);
+	/// ```
+	pub fn lint(&self) -> Lint {
+		self.lint.clone()
+	}
+}
@@ -1,22 +1,107 @@
 use std::{
 	ffi::{OsStr, OsString},
-	fs::File,
-	io::{Cursor, Error as IoError, ErrorKind, Read, Result as IoResult, Seek, SeekFrom},
+	fmt,
+	io::{Cursor, Error as IoError, ErrorKind, Result as IoResult},
 	mem::size_of,
 	num::NonZeroU64,
 	os::unix::ffi::{OsStrExt, OsStringExt},
-	path::Path,
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicBool, AtomicU64, Ordering},
+		OnceLock,
+	},
+	time::{Duration, SystemTime},
 };
 
+use bincode::Decode;
+
+/// Log and construct an [`crate::Error::Corrupt`], optionally at byte offset `@ pos`.
+macro_rules! corrupt {
+	(@ $offset:expr, $($tk:tt)+) => {{
+		let what = format!($($tk)+);
+		log::error!("{what}");
+		IoError::from($crate::Error::Corrupt {
+			what,
+			offset: $offset,
+		})
+	}};
+	($($tk:tt)+) => {
+		corrupt!(@ None, $($tk)+)
+	};
+}
+
+mod access;
+mod acl;
+#[cfg(feature = "tokio")]
+mod asyncufs;
+mod bootblock;
+mod ckhash;
+mod dcache;
 mod dir;
+mod dirhash;
+mod extent;
+mod fsck;
+mod fsync;
+mod idmap;
 mod inode;
+mod intent;
+mod ioctl;
+mod lint;
+mod quota;
+mod raw;
+mod readahead;
+mod scan;
+mod slack;
+mod statahead;
 mod symlink;
+mod trim;
+mod txn;
+mod validate;
+mod walk;
 mod xattr;
 
+#[cfg(feature = "tokio")]
+pub use self::asyncufs::{AsyncBackend, AsyncUfs};
+pub use self::{
+	access::Credentials,
+	acl::{Acl, AclEntry, AclTag},
+	bootblock::BOOTBLOCK_SIZE,
+	dir::{DirEntries, DirEntry},
+	dirhash::DirHashStats,
+	extent::Extent,
+	fsck::{FsckCounts, FsckFinding},
+	idmap::IdMap,
+	inode::Whence,
+	ioctl::{
+		Ioctl,
+		IoctlRequest,
+		Ioctls,
+		FITRIM,
+		FS_IOC32_GETFLAGS,
+		FS_IOC32_SETFLAGS,
+		FS_IOC_FIEMAP,
+		FS_IOC_GETFLAGS,
+		FS_IOC_SETFLAGS,
+	},
+	lint::{Creator, Lint, LintFinding},
+	quota::Quota,
+	scan::Inodes,
+	slack::{DirRemnant, DirSlack},
+	symlink::SYMLINK_MAX,
+	txn::Txn,
+	walk::{SymlinkPolicy, TreeGuard, Walk, WalkOptions, WalkOrder},
+};
+use self::{
+	dcache::{DentryCache, Parents},
+	dirhash::DirHash,
+	readahead::Readahead,
+	statahead::Statahead,
+};
 use crate::{
-	blockreader::BlockReader,
+	backend::{Backend, BlockFile},
+	cache::{BlockCache, CacheStats},
 	data::*,
-	decoder::{Config, Decoder},
+	inode::InodeExt,
 };
 
 /// (INTERNAL) Constructs an [`std::io::Error`] from an `errno`.
@@ -35,155 +120,1253 @@ macro_rules! iobail {
 
 /// Summary of filesystem statistics.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[doc(alias = "Statfs")]
 pub struct Info {
-	/// Number of blocks.
+	/// Number of blocks, which can hold data, in units of `frsize`.
 	pub blocks: u64,
 
-	/// Number of free blocks.
+	/// Number of free blocks, in units of `frsize`.
+	/// Blocks, which were still being freed, are not included, see `pending_blocks`.
 	pub bfree: u64,
 
-	/// Number of inodes (files).
+	/// Number of free blocks, which can be used by unprivileged users, in units of `frsize`.
+	/// This is `bfree` minus the `reserved` blocks.
+	pub bavail: u64,
+
+	/// Number of blocks, which are reserved for the superuser
+	/// (the `minfree` percentage of all blocks), in units of `frsize`.
+	pub reserved: u64,
+
+	/// Number of inodes (files), without the reserved inodes 0 and 1.
 	pub files: u64,
 
 	/// Number of free inodes (files).
+	/// Inodes, which were still being freed, are not included, see `pending_inodes`.
 	pub ffree: u64,
 
+	/// Number of blocks, which soft updates were still freeing, when the superblock was
+	/// last written (`fs_pendingblocks`).
+	///
+	/// This is only non-zero, if the filesystem wasn't unmounted cleanly.
+	/// The blocks remain allocated, until fsck_ffs(8) reclaims them.
+	pub pending_blocks: u64,
+
+	/// Number of inodes, which soft updates were still freeing, when the superblock was
+	/// last written (`fs_pendinginodes`), like `pending_blocks`.
+	pub pending_inodes: u64,
+
 	/// Block size.
 	pub bsize: u32,
 
 	/// Fragment size.
 	pub fsize: u32,
+
+	/// Optimal size of I/O requests, the block size
+	/// (`f_iosize` of FreeBSD, `f_bsize` of statvfs(3)).
+	pub iosize: u32,
+
+	/// Unit of the block counts, the fragment size
+	/// (`f_bsize` of FreeBSD, `f_frsize` of statvfs(3)).
+	pub frsize: u32,
+
+	/// Maximum length of a file name.
+	pub namemax: u32,
+
+	/// Whether soft updates are enabled.
+	pub softdep: bool,
+
+	/// The soft updates journal (SU+J), if journaling is enabled.
+	pub journal: Option<Journal>,
+}
+
+impl Info {
+	/// Like `ffs_statfs()` in FreeBSD.
+	fn new(sb: &Superblock, journal: Option<Journal>) -> Self {
+		let cst = &sb.cstotal;
+		// `ffs_statfs()` counts blocks and inodes, which are being freed by soft updates,
+		// as free. But nobody finishes freeing the ones recorded on disk, FreeBSD discards
+		// them on mount, so they stay allocated, until fsck_ffs(8) reclaims them.
+		// TODO: count our own deferred frees here, once there is write support.
+		let pending_blocks = (sb.pendingblocks.max(0) as u64)
+			.checked_shr(sb.fsbtodb as u32)
+			.unwrap_or(0);
+		let bfree = (cst.nbfree.max(0) as u64)
+			.saturating_mul(sb.frag as u64)
+			.saturating_add(cst.nffree.max(0) as u64);
+		let reserved = (sb.dsize as u64).saturating_mul(sb.minfree.clamp(0, 100) as u64) / 100;
+		Self {
+			blocks: sb.dsize as u64,
+			bfree,
+			bavail: bfree.saturating_sub(reserved),
+			reserved,
+			// The inodes before the root directory are never used.
+			files: (sb.ipg as u64 * sb.ncg as u64).saturating_sub(InodeNum::ROOT.get64()),
+			ffree: cst.nifree as u64,
+			pending_blocks,
+			pending_inodes: sb.pendinginodes as u64,
+			bsize: sb.bsize as u32,
+			fsize: sb.fsize as u32,
+			iosize: sb.bsize as u32,
+			frsize: sb.fsize as u32,
+			namemax: UFS_MAXNAMELEN as u32,
+			softdep: sb.flags & FS_DOSOFTDEP != 0,
+			journal,
+		}
+	}
+}
+
+/// Descriptive metadata of a filesystem, which is stored in the superblock.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VolumeInfo {
+	/// Name of the volume (`fs_volname`), which may be empty.
+	pub volname: OsString,
+
+	/// Where the filesystem was mounted the last time (`fs_fsmnt`), which may be empty.
+	pub last_mountpoint: OsString,
+
+	/// Unique filesystem id (`fs_id`).
+	pub id: [u32; 2],
+
+	/// System-wide unique id (`fs_swuid`).
+	pub swuid: u64,
+
+	/// When the superblock was written the last time.
+	pub written: SystemTime,
+
+	/// When the filesystem was mounted, or checked by fsck the last time.
+	pub mounted: SystemTime,
+
+	/// Flags of the filesystem (`fs_flags`), see [`FS_SUJ`](crate::FS_SUJ) and friends.
+	pub flags: u32,
+
+	/// Whether the filesystem was unmounted cleanly.
+	pub clean: bool,
+}
+
+impl VolumeInfo {
+	fn new(sb: &Superblock) -> Self {
+		Self {
+			volname:         cstr(&sb.volname),
+			last_mountpoint: cstr(&sb.fsmnt),
+			id:              sb.id.map(|x| x as u32),
+			swuid:           sb.swuid,
+			written:         sb.time(),
+			mounted:         sb.mtime(),
+			flags:           sb.flags as u32,
+			clean:           sb.clean != 0,
+		}
+	}
+}
+
+/// The NUL-terminated string in `buf`.
+fn cstr(buf: &[u8]) -> OsString {
+	let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+	OsStr::from_bytes(&buf[0..len]).into()
+}
+
+/// State of the soft updates journal, which is replayed by fsck_ffs(8).
+///
+/// Modifying a filesystem without updating the journal invalidates it,
+/// so filesystems with a journal are never written to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Journal {
+	/// Inode of the journal file (".sujournal" in the root directory), if it exists.
+	pub inr: Option<InodeNum>,
+
+	/// Size of the journal file in bytes.
+	pub size: u64,
+
+	/// The filesystem wasn't unmounted cleanly, so the journal has to be replayed.
+	pub needs_replay: bool,
+
+	/// First inode of the list of unlinked inodes, which were still in use (`fs_sujfree`).
+	pub unlinked: Option<InodeNum>,
+}
+
+impl Journal {
+	/// The journal described by `sb`, whose file is `file` (inode number and size).
+	fn new(sb: &Superblock, file: Option<(InodeNum, u64)>) -> Option<Self> {
+		if sb.flags & FS_SUJ == 0 {
+			return None;
+		}
+
+		let journal = Self {
+			inr:          file.map(|(inr, _)| inr),
+			size:         file.map_or(0, |(_, size)| size),
+			needs_replay: sb.clean == 0 || sb.flags & (FS_UNCLEAN | FS_NEEDSFSCK) != 0,
+			unlinked:     (sb.sujfree > 0).then(|| unsafe { InodeNum::new(sb.sujfree as u32) }),
+		};
+		if journal.inr.is_none() {
+			log::warn!("soft updates journal is enabled, but {SUJ_FILE:?} is missing");
+		}
+		log::info!("Journal: {journal:?}");
+		Some(journal)
+	}
+}
+
+/// What to do with directory entries, which point to unallocated inodes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DanglingEntries {
+	/// Return them like any other entry (default).
+	/// Looking them up works, but getting their attributes fails.
+	#[default]
+	Show,
+
+	/// Hide them, and count them in [`Stats::dangling_entries`].
+	/// This requires reading the inode of every directory entry.
+	Hide,
+}
+
+/// Whether the summary in the superblock is compared with the summaries of the cylinder groups,
+/// when the filesystem is opened.
+///
+/// Filesystems, which weren't unmounted cleanly, often have a stale summary in the superblock,
+/// so that the free space reported by [`Ufs::info()`] is wrong.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SummaryCheck {
+	/// Trust the summary in the superblock (default).
+	#[default]
+	Off,
+
+	/// Log a warning, and set [`Stats::stale_summary`], if the summaries don't match.
+	Check,
+
+	/// Like `Check`, but also replace the summary in the superblock with the sum of the
+	/// cylinder groups. This is only done in memory, the filesystem is never modified.
+	Fix,
+}
+
+/// Kinds of modifications, which are allowed on a filesystem.
+///
+/// Everything is forbidden by default.
+/// Renaming an entry requires both `create` and `delete`.
+///
+/// # Example
+/// ```
+/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+/// # use support::*;
+/// use std::io::Cursor;
+///
+/// use rufs::{Options, SeekBackend, Ufs, WriteCaps};
+///
+/// let caps = WriteCaps {
+///     create: true,
+///     ..WriteCaps::NONE
+/// };
+/// assert!(!caps.is_read_only());
+///
+/// // There is no write support yet.
+/// let opts = Options {
+///     write: caps,
+///     ..Options::default()
+/// };
+/// let backend = SeekBackend::new(Cursor::new(golden_image("ufs-little")));
+/// let err = Ufs::with_options(backend, opts).err().unwrap();
+/// assert_eq!(err.raw_os_error(), Some(libc::EROFS));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WriteCaps {
+	/// Creating new files, directories, links and symbolic links.
+	pub create: bool,
+
+	/// Removing directory entries.
+	pub delete: bool,
+
+	/// Modifying the contents of existing files.
+	pub overwrite: bool,
+
+	/// Changing attributes of existing inodes, like permissions, owners and timestamps.
+	pub metadata: bool,
+
+	/// Overwriting fragments directly, using [`Ufs::write_raw_block()`] and [`Ufs::with_txn()`],
+	/// which bypasses the filesystem, so it is allowed without write support.
+	pub raw: bool,
+}
+
+impl WriteCaps {
+	/// Everything may be modified.
+	pub const ALL: Self = Self {
+		create:    true,
+		delete:    true,
+		overwrite: true,
+		metadata:  true,
+		raw:       true,
+	};
+	/// Nothing may be modified.
+	pub const NONE: Self = Self {
+		create:    false,
+		delete:    false,
+		overwrite: false,
+		metadata:  false,
+		raw:       false,
+	};
+
+	/// Check whether no modifications are allowed at all.
+	pub fn is_read_only(&self) -> bool {
+		*self == Self::NONE
+	}
+}
+
+/// When modifications reach stable storage, relative to when they are acknowledged.
+///
+/// Every operation, which modifies the filesystem, writes its metadata (inodes, directories,
+/// cylinder groups and the superblock) and its data through the backend.
+/// Depending on the policy, the backend is then synced using [`Backend::sync()`],
+/// before the operation returns.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DurabilityPolicy {
+	/// Modifications are only written back, when [`Ufs::sync()`] is called,
+	/// or when the backend evicts them from its cache (default).
+	#[default]
+	Async,
+
+	/// Modifications of metadata are synced, before the operation returns,
+	/// so that a crash never leaves acknowledged metadata behind the data referring to it.
+	/// Data written to files is still cached.
+	Barrier,
+
+	/// Every modification is synced, before the operation returns (`-o sync`).
+	Sync,
+}
+
+impl DurabilityPolicy {
+	/// Check whether modifications of metadata must be synced before returning.
+	pub fn sync_metadata(self) -> bool {
+		self != Self::Async
+	}
+
+	/// Check whether modifications of file data must be synced before returning.
+	pub fn sync_data(self) -> bool {
+		self == Self::Sync
+	}
+}
+
+/// When the access time of an inode is updated, as it is read.
+///
+/// The modification and change times are always updated, when an inode is modified.
+/// Timestamps are only maintained on filesystems, which are opened for writing.
+///
+/// # Example
+/// ```
+/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+/// # use support::*;
+/// use std::time::Duration;
+///
+/// use rufs::{AtimePolicy, InodeNum};
+///
+/// # let ufs = example_image();
+/// let mut attr = ufs.inode_attr(InodeNum::ROOT)?;
+/// attr.atime = attr.mtime.max(attr.ctime) + Duration::from_secs(1);
+/// let soon = attr.atime + Duration::from_secs(60);
+/// let later = attr.atime + Duration::from_secs(2 * 24 * 60 * 60);
+/// assert!(!AtimePolicy::Relatime.needs_update(&attr, soon));
+/// assert!(AtimePolicy::Relatime.needs_update(&attr, later));
+/// assert!(AtimePolicy::Strict.needs_update(&attr, soon));
+/// assert!(!AtimePolicy::NoAtime.needs_update(&attr, later));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AtimePolicy {
+	/// Never update the access time (`-o noatime`).
+	NoAtime,
+
+	/// Only update the access time, if it isn't newer than the modification or change time,
+	/// or if it is older than a day, like Linux does (`-o relatime`, default).
+	#[default]
+	Relatime,
+
+	/// Update the access time on every access (`-o strictatime`).
+	Strict,
+}
+
+impl AtimePolicy {
+	/// How old the access time may get with [`AtimePolicy::Relatime`].
+	const RELATIME_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+	/// Check whether reading the inode with the attributes `attr` at `now`
+	/// updates its access time.
+	pub fn needs_update(self, attr: &InodeAttr, now: SystemTime) -> bool {
+		match self {
+			Self::NoAtime => false,
+			Self::Relatime => {
+				attr.atime <= attr.mtime ||
+					attr.atime <= attr.ctime ||
+					now.duration_since(attr.atime)
+						.is_ok_and(|age| age >= Self::RELATIME_MAX_AGE)
+			}
+			Self::Strict => true,
+		}
+	}
+}
+
+/// Options for opening a filesystem.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+	/// How to handle directory entries pointing to unallocated inodes.
+	pub dangling_entries: DanglingEntries,
+
+	/// Open the filesystem in degraded mode, if non-critical consistency checks fail.
+	///
+	/// Failed checks are logged as warnings, and counted in [`Stats::ignored_checks`].
+	/// Checks, that protect against crashes or unbounded memory usage, are always fatal.
+	pub force: bool,
+
+	/// Size of the blocks, in which the backend is read, if it reads whole blocks,
+	/// like [`BlockFile`], see [`Backend::set_io_size()`].
+	///
+	/// Defaults to the fragment size of the filesystem, instead of the preferred I/O size
+	/// of the file, which holds the image. Sizes, which the backend can't use,
+	/// like ones, which aren't a multiple of the sector size, are ignored.
+	pub io_size: Option<u64>,
+
+	/// Number of bytes to prefetch, when a file is read sequentially (0 disables readahead).
+	///
+	/// This only has an effect, if the backend caches data, like [`BlockCache`].
+	pub readahead: u64,
+
+	/// Maximum number of inodes to prefetch, when many entries of a directory are looked up,
+	/// like `ls -l` does (0 disables it).
+	///
+	/// This only has an effect, if the backend caches data, like [`BlockCache`].
+	pub statahead: usize,
+
+	/// Number of directory lookups to cache, including lookups of names,
+	/// which don't exist (0 disables the cache).
+	pub dcache: usize,
+
+	/// Maximum number of bytes of memory for the hash indexes of large directories,
+	/// like `vfs.ufs.dirhash_maxmem` on FreeBSD (0 disables them).
+	///
+	/// Lookups in indexed directories don't need to read the directory again,
+	/// which speeds up directories with many entries, like mail spools.
+	/// See [`Ufs::dirhash_stats()`].
+	pub dirhash: u64,
+
+	/// Synthesize "." and "..", if they are missing in damaged directories.
+	///
+	/// ".." can only be synthesized, if the parent directory was listed before.
+	pub synthesize_dots: bool,
+
+	/// Which modifications are allowed.
+	pub write: WriteCaps,
+
+	/// When modifications are synced to the backend.
+	pub durability: DurabilityPolicy,
+
+	/// When the access times of inodes are updated.
+	pub atime: AtimePolicy,
+
+	/// Read the superblock from this sector (of 512 bytes), instead of the primary one,
+	/// like `fsck_ffs -b`.
+	///
+	/// By default, a backup superblock is only searched for, if the primary one is damaged.
+	/// Filesystems opened using a backup superblock are always read-only.
+	pub alternate_superblock: Option<u64>,
+
+	/// Whether the summary in the superblock is checked.
+	pub summary: SummaryCheck,
+
+	/// How the owners and groups of files are presented.
+	pub idmap: IdMap,
+
+	/// Report whiteout entries as [`InodeType::Whiteout`], instead of skipping them.
+	///
+	/// Their inode number is [`InodeNum::WHITEOUT`](crate::InodeNum::WHITEOUT),
+	/// for which [`Ufs::inode_attr()`] returns synthesized metadata.
+	pub whiteouts: bool,
+
+	/// Log the fragments of each batch ([`Ufs::with_txn()`]) to this file, before they are
+	/// written, so that a batch is either applied completely, or not at all,
+	/// even if the system crashes.
+	///
+	/// If the log holds a batch, which wasn't applied completely, it is applied again,
+	/// when the filesystem is opened, which fails with `EROFS`, if the backend is read-only,
+	/// or [`WriteCaps::raw`] isn't set, and with `EINVAL`, if the log belongs to another filesystem.
+	/// The log must be on another device than the filesystem.
+	pub intent_log: Option<PathBuf>,
+}
+
+/// Features, which are supported for an opened filesystem.
+///
+/// This depends on how rufs was built, and on the properties of the filesystem.
+/// The [`Display`](fmt::Display) implementation lists the names of the supported features,
+/// separated by spaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Capabilities {
+	/// Modifying the filesystem.
+	pub write: bool,
+
+	/// Modifying extended attributes.
+	pub xattr_write: bool,
+
+	/// Enforcing POSIX.1e and NFSv4 ACLs.
+	pub acl: bool,
+
+	/// Reading snapshots, see [`InodeAttr::flags`](crate::InodeAttr::flags).
+	pub snapshots: bool,
+
+	/// Replaying the soft updates journal, see [`Journal`].
+	pub journal_replay: bool,
+
+	/// Reading UFS1 filesystems.
+	pub ufs1: bool,
+
+	/// [`AsyncUfs`], which requires the `tokio` feature.
+	pub tokio: bool,
+
+	/// Serializing the public data types, which requires the `serde` feature.
+	pub serde: bool,
+
+	/// Reading compressed images using [`ZstdBackend`](crate::ZstdBackend),
+	/// which requires the `zstd` feature.
+	pub zstd: bool,
+}
+
+impl fmt::Display for Capabilities {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let caps = [
+			("write", self.write),
+			("xattr_write", self.xattr_write),
+			("acl", self.acl),
+			("snapshots", self.snapshots),
+			("journal_replay", self.journal_replay),
+			("ufs1", self.ufs1),
+			("tokio", self.tokio),
+			("serde", self.serde),
+			("zstd", self.zstd),
+		];
+		let mut sep = "";
+		for (name, _) in caps.iter().filter(|(_, supported)| *supported) {
+			write!(f, "{sep}{name}")?;
+			sep = " ";
+		}
+		Ok(())
+	}
+}
+
+/// Counters of problems, that were encountered since the filesystem was opened.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Stats {
+	/// Number of times a dangling directory entry was hidden.
+	pub dangling_entries: u64,
+
+	/// Number of failed consistency checks, that were ignored because of [`Options::force`].
+	pub ignored_checks: u64,
+
+	/// Number of bytes, which are missing at the end of the device,
+	/// because it is smaller than the filesystem. Reading them fails with `EIO`.
+	pub missing_bytes: u64,
+
+	/// Sector (of 512 bytes) of the backup superblock, which is used instead of the primary one.
+	pub alternate_superblock: Option<u64>,
+
+	/// Whether the summary in the superblock doesn't match the cylinder groups,
+	/// see [`Options::summary`].
+	pub stale_summary: bool,
+
+	/// Number of bytes, that were written by replaying the intent log,
+	/// when the filesystem was opened, see [`Options::intent_log`].
+	pub replayed_bytes: u64,
 }
 
 /// Berkley Unix (Fast) Filesystem v2
-pub struct Ufs<R: Read + Seek> {
-	file:       Decoder<BlockReader<R>>,
+///
+/// # Example
+/// ```no_run
+/// use std::path::Path;
+///
+/// use rufs::{InodeNum, Ufs};
+///
+/// let ufs = Ufs::open(Path::new("/dev/ada0p2"))?;
+/// let inr = ufs.dir_lookup(InodeNum::ROOT, "etc".as_ref())?;
+/// let attr = ufs.inode_attr(inr)?;
+/// println!("/etc is owned by {}", attr.uid);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// # Thread Safety
+/// All methods take `&self`, and `Ufs` is `Sync` if `B` is `Sync`,
+/// so a single instance can be shared between threads, eg. using an [`std::sync::Arc`].
+/// Whether accesses to the underlying device can run in parallel, depends on the [`Backend`].
+///
+/// # Panics
+/// No method panics, whatever the filesystem contains. Corrupted metadata makes
+/// the operation fail with [`Error::Corrupt`](crate::Error::Corrupt), instead.
+/// This is checked by the `nopanic` fuzz target.
+pub struct Ufs<B: Backend> {
+	backend:    B,
+	config:     Config,
 	superblock: Superblock,
+	/// The checked geometry of `superblock`.
+	geo:        FsGeometry,
+	/// The tool, which created the filesystem, and the check-hashes it maintains.
+	lint:       Lint,
+	options:    Options,
+	dangling:   AtomicU64,
+	ignored:    u64,
+	readahead:  Readahead,
+	statahead:  Statahead,
+	dcache:     DentryCache,
+	dirhash:    DirHash,
+	parents:    Parents,
+	journal:    Option<Journal>,
+	dev_size:   Option<u64>,
+	missing:    u64,
+	alternate:  Option<u64>,
+	stale:      bool,
+	replayed:   u64,
+	/// Number of initialized inodes per cylinder group, read on first use.
+	inited:     Vec<OnceLock<u32>>,
+	/// Cylinder groups, which were written to since the last check, see `check_touched()`.
+	touched:    Vec<AtomicBool>,
 }
 
-impl Ufs<File> {
+impl Ufs<BlockFile> {
+	/// Open the filesystem stored in the file or device at `path`.
+	///
+	/// # Example
+	/// ```no_run
+	/// use std::path::Path;
+	///
+	/// use rufs::Ufs;
+	///
+	/// let ufs = Ufs::open(Path::new("disk.img"))?;
+	/// println!("block size: {}", ufs.info().bsize);
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
 	pub fn open(path: &Path) -> IoResult<Self> {
-		let file = BlockReader::open(path)?;
+		let file = BlockFile::open(path)?;
 		Self::new(file)
 	}
 }
 
-impl<R: Read + Seek> Ufs<R> {
-	pub fn new(mut file: BlockReader<R>) -> IoResult<Self> {
-		let pos = SBLOCK_UFS2 as u64 + MAGIC_OFFSET;
-		file.seek(SeekFrom::Start(pos))?;
-		let mut magic = [0u8; 4];
-		file.read_exact(&mut magic)?;
+impl<B: Backend> Ufs<B> {
+	/// Open a filesystem from an arbitrary backend.
+	///
+	/// The superblock is read and checked, before the filesystem is returned.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use std::io::Cursor;
+	///
+	/// use rufs::{SeekBackend, Ufs};
+	///
+	/// let image: Vec<u8> = golden_image("ufs-little");
+	/// let ufs = Ufs::new(SeekBackend::new(Cursor::new(image)))?;
+	/// assert_eq!(ufs.info().bsize, 32768);
+	///
+	/// // Garbage is rejected
+	/// let garbage = vec![0u8; 1 << 20];
+	/// assert!(Ufs::new(SeekBackend::new(Cursor::new(garbage))).is_err());
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	pub fn new(backend: B) -> IoResult<Self> {
+		Self::with_options(backend, Options::default())
+	}
 
-		// magic: 0x19 54 01 19
-		let config = match magic {
-			[0x19, 0x01, 0x54, 0x19] => Config::little(),
-			[0x19, 0x54, 0x01, 0x19] => Config::big(),
-			_ => {
-				iobail!(
-					ErrorKind::InvalidInput,
-					"invalid superblock magic number: {magic:?}"
-				)
+	/// Open a filesystem from an arbitrary backend, using custom options.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use std::io::Cursor;
+	///
+	/// use rufs::{DanglingEntries, Options, SeekBackend, Ufs};
+	///
+	/// let opts = Options {
+	///     dangling_entries: DanglingEntries::Hide,
+	///     ..Options::default()
+	/// };
+	/// let backend = SeekBackend::new(Cursor::new(golden_image("ufs-big")));
+	/// let ufs = Ufs::with_options(backend, opts)?;
+	/// assert_eq!(ufs.stats().dangling_entries, 0);
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	pub fn with_options(backend: B, options: Options) -> IoResult<Self> {
+		// FIXME: Choose based on hash of input or so, to excercise BE as well with introducing non-determinism
+		let (config, superblock, mut ignored, alternate) = match options.alternate_superblock {
+			Some(sector) => {
+				log::warn!("using the backup superblock at sector {sector}");
+				let pos = sector.saturating_mul(DEV_BSIZE as u64);
+				let (config, sb, ignored) = load_superblock(&backend, pos, options.force)?;
+				(config, sb, ignored, Some(sector))
+			}
+			None => {
+				match load_superblock(&backend, SBLOCK_UFS2 as u64, options.force) {
+					Ok((config, sb, ignored)) => (config, sb, ignored, None),
+					Err(e) => {
+						log::error!("the primary superblock is damaged: {e}");
+						let Some((sector, config, sb)) = find_alternate_superblock(&backend) else {
+							if is_ufs1(&backend) {
+								return Err(crate::Error::Unsupported { feature: "UFS1" }.into());
+							}
+							return Err(e);
+						};
+						(config, sb, 0, Some(sector))
+					}
+				}
 			}
 		};
-		// FIXME: Choose based on hash of input or so, to excercise BE as well with introducing non-determinism
+		let geo = geometry(&superblock)?;
+		let lint = Lint::new(&superblock);
+		lint.log();
+		backend.set_io_size(options.io_size.unwrap_or(geo.fsize()));
+		let dev_size = backend.size()?;
+		let missing = check_size(&superblock, dev_size, options.force, &mut ignored)?;
 
-		let mut file = Decoder::new(file, config);
+		if alternate.is_some() && !options.write.is_read_only() {
+			log::error!("refusing to modify a filesystem using a backup superblock");
+			return Err(err!(EROFS));
+		}
 
-		let superblock: Superblock = file.decode_at(SBLOCK_UFS2 as u64)?;
-		if superblock.magic != FS_UFS2_MAGIC {
-			iobail!(
-				ErrorKind::InvalidInput,
-				"invalid superblock magic number: {}",
-				superblock.magic
+		// Raw writes bypass the filesystem, so they don't need write support.
+		let fs_write = WriteCaps {
+			raw: false,
+			..options.write
+		};
+		if !fs_write.is_read_only() {
+			if superblock.flags & FS_SUJ != 0 {
+				log::error!("refusing to modify a filesystem with a soft updates journal");
+				return Err(err!(EROFS));
+			}
+
+			// TODO: write support
+			log::error!("write support is not implemented: {:?}", options.write);
+			return Err(err!(EROFS));
+		}
+		let replayed = intent::replay_intent_log(&backend, &superblock, &options)?;
+
+		let dcache = DentryCache::new(options.dcache);
+		let dirhash = DirHash::new(options.dirhash);
+		let inited = (0..superblock.ncg).map(|_| OnceLock::new()).collect();
+		let touched = (0..superblock.ncg)
+			.map(|_| AtomicBool::new(false))
+			.collect();
+		let mut s = Self {
+			backend,
+			config,
+			superblock,
+			geo,
+			lint,
+			options,
+			dangling: AtomicU64::new(0),
+			ignored,
+			readahead: Readahead::default(),
+			statahead: Statahead::default(),
+			dcache,
+			dirhash,
+			parents: Parents::default(),
+			journal: None,
+			dev_size,
+			missing,
+			alternate,
+			stale: false,
+			replayed,
+			inited,
+			touched,
+		};
+		s.ignored += s.check()?;
+		s.check_summary()?;
+		let info = s.info();
+		if info.pending_blocks != 0 || info.pending_inodes != 0 {
+			log::warn!(
+				"{} blocks and {} inodes were still being freed, run fsck_ffs(8) to reclaim them",
+				info.pending_blocks,
+				info.pending_inodes
 			);
 		}
-		let mut s = Self { file, superblock };
-		s.check()?;
+		s.journal = Journal::new(&s.superblock, s.journal_file()?);
 		Ok(s)
 	}
 
+	fn read_at(&self, pos: u64, buf: &mut [u8]) -> IoResult<()> {
+		if let Some(size) = self.dev_size {
+			let end = pos.saturating_add(buf.len() as u64);
+			if end > size {
+				return Err(corrupt!(
+					@ Some(pos),
+					"read_at({pos}..{end}): beyond the end of the device ({size})"
+				));
+			}
+		}
+		self.backend.read_at(pos, buf)
+	}
+
+	/// Decode a structure, which is at most `len` bytes large, at `pos`.
+	fn decode_at<T: Decode>(&self, pos: u64, len: usize) -> IoResult<T> {
+		if let Some(buf) = self.backend.mapped(pos, len) {
+			return self.config.decode_slice(buf);
+		}
+		let mut buf = vec![0u8; len];
+		self.read_at(pos, &mut buf)?;
+		self.config.decode_slice(&buf)
+	}
+
 	/// Get filesystem metadata.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// # let ufs = example_image();
+	/// let info = ufs.info();
+	/// assert_eq!(info.fsize, 4096);
+	/// assert_eq!((info.iosize, info.frsize), (32768, 4096));
+	/// assert_eq!(info.blocks, 871);
+	/// assert!(info.bfree <= info.blocks);
+	/// assert!(info.ffree <= info.files);
+	///
+	/// // 8% of the blocks are reserved for the superuser.
+	/// assert_eq!(info.reserved, 69);
+	/// assert_eq!(info.bavail, info.bfree - 69);
+	/// ```
 	#[doc(alias("statfs", "statvfs"))]
 	pub fn info(&self) -> Info {
-		let sb = &self.superblock;
-		let cst = &sb.cstotal;
-		Info {
-			blocks: sb.dsize as u64,
-			bfree:  (cst.nbfree * sb.frag as i64 + cst.nffree) as u64,
-			files:  (sb.ipg * sb.ncg) as u64,
-			ffree:  cst.nifree as u64,
-			bsize:  sb.bsize as u32,
-			fsize:  sb.fsize as u32,
-		}
-	}
-
-	fn check(&mut self) -> IoResult<()> {
-		let sb = &self.superblock;
-		log::debug!("Superblock: {sb:#?}");
-
-		log::info!("Summary:");
-		log::info!("Block Size: {}", sb.bsize);
-		log::info!("# Blocks: {}", sb.size);
-		log::info!("# Data Blocks: {}", sb.dsize);
-		log::info!("Fragment Size: {}", sb.fsize);
-		log::info!("Fragments per Block: {}", sb.frag);
-		log::info!("# Cylinder Groups: {}", sb.ncg);
-		log::info!("CG Size: {}MiB", sb.cgsize() / 1024 / 1024);
-
-		macro_rules! sbassert {
-			($e:expr) => {
-				if !($e) {
-					log::error!("superblock corrupted: {}", stringify!($e));
-					return Err(IoError::from_raw_os_error(libc::EIO));
-				}
-			};
-		}
-
-		sbassert!(sb.sblkno == 24);
-		sbassert!(sb.cblkno == 32);
-		sbassert!(sb.iblkno == 40);
-		sbassert!(sb.ncg > 0);
-		sbassert!(sb.ipg > 0);
-		sbassert!(sb.fpg > 0);
-		sbassert!(sb.frag > 0 && sb.frag <= 8);
-		sbassert!(sb.fsize == (sb.bsize / sb.frag));
-		// TODO: this looks ugly:
-		sbassert!(Some(sb.bsize) == 1i32.checked_shl(sb.bshift as u32));
-		sbassert!(Some(sb.fsize) == 1i32.checked_shl(sb.fshift as u32));
-		sbassert!(Some(sb.frag) == 1i32.checked_shl(sb.fragshift as u32));
-		sbassert!(sb.bsize == (!sb.bmask + 1));
-		sbassert!(sb.fsize == (!sb.fmask + 1));
-		sbassert!(sb.sbsize == 4096);
-		sbassert!(sb.cgsize_struct() < sb.bsize as usize);
-
-		// TODO: support other block/frag sizes
-		sbassert!(sb.bsize == 32768);
-		sbassert!(sb.fsize == 4096);
+		Info::new(&self.superblock, self.journal.clone())
+	}
+
+	/// Get the name, and other descriptive metadata of the filesystem.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// # let ufs = example_image();
+	/// let vol = ufs.volume_info();
+	/// assert!(vol.clean);
+	/// assert!(vol.mounted <= vol.written);
+	/// println!("{:?} was last mounted on {:?}", vol.volname, vol.last_mountpoint);
+	/// ```
+	pub fn volume_info(&self) -> VolumeInfo {
+		VolumeInfo::new(&self.superblock)
+	}
+
+	/// Get the features, which are supported for this filesystem.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// # let ufs = example_image();
+	/// let caps = ufs.capabilities();
+	/// assert!(caps.snapshots);
+	/// assert!(!caps.write);
+	/// assert!(caps.to_string().split(' ').any(|c| c == "snapshots"));
+	/// ```
+	pub fn capabilities(&self) -> Capabilities {
+		Capabilities {
+			// TODO: write support, which must stay disabled for filesystems with a journal.
+			write:          false,
+			xattr_write:    false,
+			acl:            false,
+			snapshots:      true,
+			journal_replay: false,
+			ufs1:           false,
+			tokio:          cfg!(feature = "tokio"),
+			serde:          cfg!(feature = "serde"),
+			zstd:           cfg!(feature = "zstd"),
+		}
+	}
+
+	/// Get counters of problems, that were encountered so far.
+	pub fn stats(&self) -> Stats {
+		Stats {
+			dangling_entries:     self.dangling.load(Ordering::Relaxed),
+			ignored_checks:       self.ignored,
+			missing_bytes:        self.missing,
+			alternate_superblock: self.alternate,
+			stale_summary:        self.stale,
+			replayed_bytes:       self.replayed,
+		}
+	}
+
+	/// Write all modified data back to the device, see [`Ufs::sync_inode()`] for a single inode.
+	#[doc(alias("sync_all", "syncfs", "flush"))]
+	pub fn sync(&self) -> IoResult<()> {
+		self.backend.sync()
+	}
+
+	/// Find the journal file, if journaling is enabled.
+	fn journal_file(&self) -> IoResult<Option<(InodeNum, u64)>> {
+		if self.superblock.flags & FS_SUJ == 0 {
+			return Ok(None);
+		}
+		match self.dir_lookup(InodeNum::ROOT, SUJ_FILE.as_ref()) {
+			Ok(inr) => Ok(Some((inr, self.read_inode(inr)?.size))),
+			Err(e) if e.raw_os_error() == Some(libc::ENOENT) => Ok(None),
+			Err(e) => Err(e),
+		}
+	}
+
+	/// Compare the summary in the superblock with the summaries of the cylinder groups,
+	/// see [`Options::summary`].
+	///
+	/// Those are read from the cylinder groups, if they are protected by check-hashes,
+	/// and from the summary area otherwise.
+	fn check_summary(&mut self) -> IoResult<()> {
+		if self.options.summary == SummaryCheck::Off {
+			return Ok(());
+		}
+		let actual = match self.cg_summary_total()? {
+			Some(total) => Some(total),
+			None => self.summary_total()?,
+		};
+		let Some(actual) = actual else {
+			return Ok(());
+		};
+
+		let cst = &mut self.superblock.cstotal;
+		let recorded = FsckCounts::from_total(cst);
+		if recorded == actual {
+			return Ok(());
+		}
+		log::warn!("the superblock summary is stale: {recorded:?}, should be {actual:?}");
+		self.stale = true;
+
+		if self.options.summary == SummaryCheck::Fix {
+			cst.ndir = actual.ndir as i64;
+			cst.nbfree = actual.nbfree as i64;
+			cst.nifree = actual.nifree as i64;
+			cst.nffree = actual.nffree as i64;
+		}
+		Ok(())
+	}
+
+	/// Check the superblock copies and cylinder groups.
+	///
+	/// Returns the number of failed checks, that were ignored because of [`Options::force`].
+	fn check(&self) -> IoResult<u64> {
+		let force = self.options.force;
+		let mut ignored = 0;
 
 		// check that all superblocks are ok.
-		for i in 0..sb.ncg {
-			let sb = &self.superblock;
-			let addr = ((sb.fpg + sb.sblkno) * sb.fsize) as u64;
-			let csb: Superblock = self.file.decode_at(addr).unwrap();
+		for i in 0..self.geo.ncg() {
+			let addr = cg_addr(&self.geo, i, self.geo.sblkno())?;
+			let csb: Superblock = self.decode_at(addr, SBLOCKSIZE)?;
 			if csb.magic != FS_UFS2_MAGIC {
-				log::error!("CG{i} has invalid superblock magic: {:x}", csb.magic);
-				return Err(err!(EIO));
+				let e =
+					corrupt!(@ Some(addr), "CG{i} has invalid superblock magic: {:x}", csb.magic);
+				soft_fail(force, &mut ignored, e)?;
 			}
 		}
 
 		// check that all cylgroups are ok.
-		for i in 0..self.superblock.ncg {
-			let sb = &self.superblock;
-			let addr = ((sb.fpg + sb.cblkno) * sb.fsize) as u64;
-			let cg: CylGroup = self.file.decode_at(addr).unwrap();
+		for i in 0..self.geo.ncg() {
+			let addr = cg_addr(&self.geo, i, self.geo.cblkno())?;
+			let cg: CylGroup = self.decode_at(addr, size_of::<CylGroup>())?;
 			if cg.magic != CG_MAGIC {
-				log::error!("CG{i} has invalid cg magic: {:x}", cg.magic);
-				return Err(err!(EIO));
+				let e = corrupt!(@ Some(addr), "CG{i} has invalid cg magic: {:x}", cg.magic);
+				soft_fail(force, &mut ignored, e)?;
 			}
 		}
 		log::info!("OK");
-		Ok(())
+		Ok(ignored)
+	}
+}
+
+impl<B: Backend> Ufs<B> {
+	/// Get statistics about the hash indexes of large directories, see [`Options::dirhash`].
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use std::io::Cursor;
+	///
+	/// use rufs::{Options, SeekBackend, Ufs};
+	///
+	/// let opts = Options {
+	///     dirhash: 2 << 20,
+	///     ..Options::default()
+	/// };
+	/// let backend = SeekBackend::new(Cursor::new(golden_image("ufs-little")));
+	/// let ufs = Ufs::with_options(backend, opts)?;
+	/// let stats = ufs.dirhash_stats();
+	/// assert_eq!(stats.capacity, 2 << 20);
+	/// assert_eq!(stats.hits, 0);
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	pub fn dirhash_stats(&self) -> DirHashStats {
+		self.dirhash.stats()
+	}
+}
+
+impl<B: Backend> Ufs<BlockCache<B>> {
+	/// Get statistics about the block cache.
+	pub fn cache_stats(&self) -> CacheStats {
+		self.backend.stats()
+	}
+
+	/// Get the block cache, eg. to save its contents with [`BlockCache::cached_blocks()`],
+	/// and warm it up after the next mount with [`BlockCache::prime()`].
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use std::io::Cursor;
+	///
+	/// use rufs::{BlockCache, InodeNum, SeekBackend, Ufs};
+	///
+	/// let open = || Ufs::new(BlockCache::new(SeekBackend::new(Cursor::new(golden_image("ufs-little"))), 1 << 20));
+	/// let ufs = open()?;
+	/// ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref())?;
+	/// let blocks = ufs.cache().cached_blocks(1024);
+	///
+	/// let ufs = open()?;
+	/// ufs.cache().prime(&blocks)?;
+	/// let misses = ufs.cache_stats().misses;
+	/// ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref())?;
+	/// assert_eq!(ufs.cache_stats().misses, misses);
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	pub fn cache(&self) -> &BlockCache<B> {
+		&self.backend
+	}
+}
+
+/// Determine the byte order of a filesystem from the superblock magic number.
+pub(crate) fn config_from_magic(magic: [u8; 4]) -> IoResult<Config> {
+	// magic: 0x19 54 01 19
+	match magic {
+		[0x19, 0x01, 0x54, 0x19] => Ok(Config::little()),
+		[0x19, 0x54, 0x01, 0x19] => Ok(Config::big()),
+		_ => {
+			iobail!(
+				ErrorKind::InvalidData,
+				"invalid superblock magic number: {magic:?}"
+			)
+		}
+	}
+}
+
+/// Check the geometry of `sb`, see [`FsGeometry::new()`].
+fn geometry(sb: &Superblock) -> IoResult<FsGeometry> {
+	FsGeometry::new(sb).map_err(|what| corrupt!("superblock corrupted: {what}"))
+}
+
+/// Byte offset of fragment `frag` of cylinder group `cgx`, see [`FsGeometry::cg_addr()`].
+///
+/// Fails with [`crate::Error::Corrupt`] on overflow, which only corrupt addresses cause.
+fn cg_addr(geo: &FsGeometry, cgx: u64, frag: u64) -> IoResult<u64> {
+	geo.cg_addr(cgx, frag)
+		.ok_or_else(|| corrupt!("the address of fragment {frag} of CG{cgx} overflows"))
+}
+
+/// Byte offset of inode `inr`, see [`FsGeometry::ino_to_fso()`].
+fn ino_addr(geo: &FsGeometry, inr: InodeNum) -> IoResult<u64> {
+	geo.ino_to_fso(inr)
+		.ok_or_else(|| corrupt!("the address of inode {inr} overflows"))
+}
+
+/// Byte offset of fragment `frag`, like a block pointer, see [`FsGeometry::frag_addr()`].
+///
+/// Fails with [`crate::Error::Corrupt`], if the fragment is outside of the filesystem.
+fn frag_addr(geo: &FsGeometry, frag: u64) -> IoResult<u64> {
+	geo.frag_addr(frag)
+		.ok_or_else(|| corrupt!("fragment {frag} is outside of the filesystem"))
+}
+
+/// Handle a failed non-critical consistency check.
+///
+/// In `force` mode, the failure is counted in `ignored`, otherwise `err` is returned.
+fn soft_fail(force: bool, ignored: &mut u64, err: IoError) -> IoResult<()> {
+	if !force {
+		return Err(err);
+	}
+	log::warn!("continuing in degraded mode");
+	*ignored += 1;
+	Ok(())
+}
+
+/// Check that the device of size `dev_size` is large enough for the filesystem.
+///
+/// Returns the number of missing bytes, if the failed check is ignored because of `force`.
+fn check_size(
+	sb: &Superblock,
+	dev_size: Option<u64>,
+	force: bool,
+	ignored: &mut u64,
+) -> IoResult<u64> {
+	let Some(dev_size) = dev_size else {
+		return Ok(0);
+	};
+	let fs = sb.fsize as u64;
+	let needed = (sb.size as u64).saturating_mul(fs);
+	let provider = (sb.providersize as u64).saturating_mul(fs);
+
+	if dev_size < needed {
+		let missing = needed - dev_size;
+		let e = corrupt!("the device is truncated: {missing} of {needed} bytes are missing");
+		soft_fail(force, ignored, e)?;
+		return Ok(missing);
+	} else if dev_size < provider {
+		log::warn!(
+			"the device is {} bytes smaller than when the filesystem was created",
+			provider - dev_size
+		);
+	}
+	Ok(0)
+}
+
+/// Read the superblock at `pos`, and check it for consistency.
+///
+/// Returns the number of failed checks, that were ignored because of `force`.
+fn load_superblock<B: Backend>(
+	backend: &B,
+	pos: u64,
+	force: bool,
+) -> IoResult<(Config, Superblock, u64)> {
+	let mut buf = vec![0u8; SBLOCKSIZE];
+	backend.read_at(pos, &mut buf)?;
+	let magic = buf[(MAGIC_OFFSET as usize)..(MAGIC_OFFSET as usize + 4)]
+		.try_into()
+		.unwrap();
+	let config = config_from_magic(magic)?;
+	let sb: Superblock = config.decode_slice(&buf)?;
+	let ignored = check_superblock(&sb, force)?;
+	Ok((config, sb, ignored))
+}
+
+/// Check whether the device contains a UFS1 filesystem, in either byte order.
+fn is_ufs1<B: Backend>(backend: &B) -> bool {
+	let mut magic = [0u8; 4];
+	let pos = SBLOCK_UFS1 as u64 + MAGIC_OFFSET;
+	backend.read_at(pos, &mut magic).is_ok() &&
+		[i32::from_le_bytes(magic), i32::from_be_bytes(magic)].contains(&FS_UFS1_MAGIC)
+}
+
+/// Find a consistent backup superblock, using the recovery information,
+/// which newfs(8) stores in front of the primary superblock, like `ffs_sbsearch()` does.
+///
+/// Returns the sector of the backup superblock.
+fn find_alternate_superblock<B: Backend>(backend: &B) -> Option<(u64, Config, Superblock)> {
+	// Upper limit for the number of backups, that are tried.
+	const MAX_BACKUPS: u32 = 1024;
+
+	let mut buf = [0u8; size_of::<FsRecovery>()];
+	backend
+		.read_at(SBLOCK_UFS2 as u64 - buf.len() as u64, &mut buf)
+		.ok()?;
+	let fsr: FsRecovery = match config_from_magic(buf[0..4].try_into().unwrap()) {
+		Ok(config) => config.decode_slice(&buf).ok()?,
+		Err(_) => {
+			log::error!("no recovery information found in front of the primary superblock");
+			return None;
+		}
+	};
+	log::debug!("recovery information: {fsr:?}");
+	if !(0..=16).contains(&fsr.fsbtodb) || fsr.sblkno <= 0 || fsr.fpg <= 0 {
+		log::error!("invalid recovery information: {fsr:?}");
+		return None;
+	}
+
+	let fs = (DEV_BSIZE as u64) << fsr.fsbtodb;
+	for cgx in 0..fsr.ncg.min(MAX_BACKUPS) {
+		let Some(pos) = (cgx as u64 * fsr.fpg as u64 + fsr.sblkno as u64).checked_mul(fs) else {
+			break;
+		};
+		match load_superblock(backend, pos, false) {
+			Ok((config, sb, _))
+				if sb.fsbtodb == fsr.fsbtodb &&
+					sb.sblkno == fsr.sblkno &&
+					sb.fpg == fsr.fpg &&
+					sb.ncg == fsr.ncg =>
+			{
+				let sector = pos / DEV_BSIZE as u64;
+				log::warn!("using the backup superblock of CG{cgx} at sector {sector}");
+				return Some((sector, config, sb));
+			}
+			Ok(_) => {
+				log::warn!(
+					"the backup superblock of CG{cgx} doesn't match the recovery information"
+				)
+			}
+			Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+			Err(e) => log::warn!("the backup superblock of CG{cgx} is damaged: {e}"),
+		}
+	}
+	log::error!("no usable backup superblock found");
+	None
+}
+
+/// Check a superblock for consistency.
+///
+/// Returns the number of failed checks, that were ignored because of `force`.
+fn check_superblock(sb: &Superblock, force: bool) -> IoResult<u64> {
+	if sb.magic != FS_UFS2_MAGIC {
+		iobail!(
+			ErrorKind::InvalidData,
+			"invalid superblock magic number: {}",
+			sb.magic
+		);
+	}
+
+	log::debug!("Superblock: {sb:#?}");
+
+	log::info!("Summary:");
+	log::info!("Block Size: {}", sb.bsize);
+	log::info!("# Blocks: {}", sb.size);
+	log::info!("# Data Blocks: {}", sb.dsize);
+	log::info!("Fragment Size: {}", sb.fsize);
+	log::info!("Fragments per Block: {}", sb.frag);
+	log::info!("# Cylinder Groups: {}", sb.ncg);
+	log::info!("CG Size: {}MiB", sb.cgsize().unwrap_or(0) / 1024 / 1024);
+	log::info!("# Pending Blocks: {}", sb.pendingblocks);
+	log::info!("# Pending Inodes: {}", sb.pendinginodes);
+
+	// Violating these would cause overflows, divisions by zero,
+	// out-of-bounds accesses or huge allocations later on, so they are always fatal.
+	let geo = geometry(sb)?;
+
+	// These are merely unexpected, and can be ignored in degraded mode.
+	let mut ignored = 0;
+	macro_rules! sbcheck {
+		($e:expr) => {
+			if !($e) {
+				let e = corrupt!("superblock corrupted: {}", stringify!($e));
+				soft_fail(force, &mut ignored, e)?;
+			}
+		};
+	}
+
+	// The layout, which newfs(8) and makefs(8) create for any block and fragment size:
+	// the superblock, the cylinder group and the inodes each start on a block.
+	let frags = |bytes: usize| {
+		(bytes as u64)
+			.div_ceil(geo.fsize())
+			.next_multiple_of(geo.frag())
+	};
+	sbcheck!(geo.sblkno() == frags(SBLOCK_UFS2 + SBLOCKSIZE));
+	sbcheck!(geo.cblkno() == geo.sblkno() + frags(SBLOCKSIZE));
+	sbcheck!(geo.iblkno() == geo.cblkno() + geo.frag());
+	sbcheck!(sb.bsize == (!sb.bmask + 1));
+	sbcheck!(sb.fsize == (!sb.fmask + 1));
+	sbcheck!(sb.sbsize as u64 == (SBSTRUCTSIZE as u64).next_multiple_of(geo.fsize()));
+	sbcheck!(sb.cgsize_struct() < sb.bsize as usize);
+	sbcheck!(sb.providersize == 0 || sb.size <= sb.providersize);
+
+	Ok(ignored)
+}
+
+#[cfg(test)]
+mod t {
+	use super::*;
+
+	#[test]
+	fn ufs_is_sync() {
+		fn assert_sync<T: Send + Sync>() {}
+		assert_sync::<Ufs<BlockFile>>();
 	}
 }
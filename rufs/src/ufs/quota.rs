@@ -0,0 +1,157 @@
+use super::*;
+use crate::{err, InodeNum};
+
+/// Names of the quota files of users and groups in the root directory, like FreeBSD's defaults.
+const USER_QUOTA_FILE: &str = "quota.user";
+const GROUP_QUOTA_FILE: &str = "quota.group";
+
+/// Magic number of the header of 64-bit quota files (`struct dqhdr64`).
+const QUOTA64_MAGIC: &[u8; 8] = b"QUOTA64\0";
+
+/// Size of the header and the records of 64-bit quota files.
+const QUOTA64_HDRLEN: u64 = 64;
+const QUOTA64_RECLEN: u64 = 64;
+
+/// Size of the records of old, 32-bit quota files, which have no header.
+const QUOTA32_RECLEN: u64 = 32;
+
+/// Disk quota of a user or group: its limits, and its current usage.
+///
+/// Blocks are counted in units of 512 bytes, and a limit of 0 means, that there is no limit.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[doc(alias = "dqblk")]
+pub struct Quota {
+	/// Number of blocks, which can't be exceeded.
+	pub block_hard_limit: u64,
+
+	/// Number of blocks, which may only be exceeded during a grace period.
+	pub block_soft_limit: u64,
+
+	/// Number of blocks in use.
+	pub blocks: u64,
+
+	/// Number of inodes, which can't be exceeded.
+	pub inode_hard_limit: u64,
+
+	/// Number of inodes, which may only be exceeded during a grace period.
+	pub inode_soft_limit: u64,
+
+	/// Number of inodes in use.
+	pub inodes: u64,
+
+	/// When the grace period for exceeding the soft limit of blocks ends,
+	/// in seconds since the epoch, or 0, if the limit isn't exceeded.
+	/// The record of id 0 holds the length of the grace period instead.
+	pub block_time: i64,
+
+	/// Like `block_time`, but for the soft limit of inodes.
+	pub inode_time: i64,
+}
+
+impl Quota {
+	/// Decode a record of a 64-bit quota file, which is always big-endian (`struct dqblk64`).
+	fn from_dqblk64(rec: &[u8]) -> Self {
+		let field = |i: usize| u64::from_be_bytes(rec[(i * 8)..(i * 8 + 8)].try_into().unwrap());
+		Self {
+			block_hard_limit: field(0),
+			block_soft_limit: field(1),
+			blocks:           field(2),
+			inode_hard_limit: field(3),
+			inode_soft_limit: field(4),
+			inodes:           field(5),
+			block_time:       field(6) as i64,
+			inode_time:       field(7) as i64,
+		}
+	}
+
+	/// Decode a record of a 32-bit quota file, which has the byte order of the filesystem
+	/// (`struct dqblk32`).
+	fn from_dqblk32(rec: &[u8], config: Config) -> IoResult<Self> {
+		let field = |i: usize| config.decode_slice::<u32>(&rec[(i * 4)..(i * 4 + 4)]);
+		Ok(Self {
+			block_hard_limit: field(0)?.into(),
+			block_soft_limit: field(1)?.into(),
+			blocks:           field(2)?.into(),
+			inode_hard_limit: field(3)?.into(),
+			inode_soft_limit: field(4)?.into(),
+			inodes:           field(5)?.into(),
+			block_time:       (field(6)? as i32).into(),
+			inode_time:       (field(7)? as i32).into(),
+		})
+	}
+}
+
+impl<B: Backend> Ufs<B> {
+	/// Get the disk quota of user `uid` from "quota.user" in the root directory.
+	///
+	/// Returns `None`, if there is no such file, or if it has no record for `uid`.
+	/// Quotas aren't enforced, as rufs doesn't modify filesystems.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// # let ufs = example_image();
+	/// // The example image has no quotas.
+	/// assert_eq!(ufs.quota_for_uid(1001)?, None);
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	#[doc(alias = "quotactl")]
+	pub fn quota_for_uid(&self, uid: u32) -> IoResult<Option<Quota>> {
+		self.quota(USER_QUOTA_FILE, uid)
+	}
+
+	/// Get the disk quota of group `gid` from "quota.group" in the root directory.
+	///
+	/// See [`Ufs::quota_for_uid()`].
+	pub fn quota_for_gid(&self, gid: u32) -> IoResult<Option<Quota>> {
+		self.quota(GROUP_QUOTA_FILE, gid)
+	}
+
+	/// Read the record of `id` from the quota file `name`, like `dqget()` in FreeBSD.
+	fn quota(&self, name: &str, id: u32) -> IoResult<Option<Quota>> {
+		let inr = match self.dir_lookup(InodeNum::ROOT, name.as_ref()) {
+			Ok(inr) => inr,
+			Err(e) if e.raw_os_error() == Some(libc::ENOENT) => return Ok(None),
+			Err(e) => return Err(e),
+		};
+		let attr = self.inode_attr(inr)?;
+		if attr.kind != InodeType::RegularFile {
+			log::error!("quota({name:?}): not a regular file");
+			return Err(err!(EINVAL));
+		}
+		let size = attr.size;
+
+		// struct dqhdr64: magic (8), version (4), hdrlen (4), reclen (4), all big-endian
+		let mut hdr = [0u8; 20];
+		let is64 = size >= QUOTA64_HDRLEN && {
+			self.inode_read(inr, 0, &mut hdr)?;
+			&hdr[0..8] == QUOTA64_MAGIC
+		};
+		let (base, reclen) = if is64 {
+			let field = |i: usize| u32::from_be_bytes(hdr[i..(i + 4)].try_into().unwrap());
+			let (version, hdrlen, reclen) = (field(8), field(12), field(16));
+			if version != 1 || hdrlen as u64 != QUOTA64_HDRLEN || reclen as u64 != QUOTA64_RECLEN {
+				log::error!("quota({name:?}): unsupported header: version {version}, header length {hdrlen}, record length {reclen}");
+				return Err(err!(EINVAL));
+			}
+			(QUOTA64_HDRLEN, QUOTA64_RECLEN)
+		} else {
+			(0, QUOTA32_RECLEN)
+		};
+
+		// Records beyond the end of the file are all zero.
+		let pos = base + id as u64 * reclen;
+		if pos + reclen > size {
+			return Ok(None);
+		}
+		let mut rec = vec![0u8; reclen as usize];
+		self.inode_read(inr, pos, &mut rec)?;
+		if is64 {
+			Ok(Some(Quota::from_dqblk64(&rec)))
+		} else {
+			Quota::from_dqblk32(&rec, self.config).map(Some)
+		}
+	}
+}
@@ -0,0 +1,203 @@
+use std::ops::Range;
+
+use super::{fsck::metadata_extents, *};
+use crate::{err, InodeNum};
+
+/// Fragments, which are used as indirect blocks, or by directories, see [`Ufs::structure_map()`].
+pub(super) struct StructureMap {
+	/// Fragments and their inode, sorted by the first fragment.
+	ranges:  Vec<(Range<u64>, InodeNum)>,
+	/// Largest end of the ranges up to each index, so that overlapping ranges are found as well.
+	max_end: Vec<u64>,
+}
+
+impl StructureMap {
+	/// Find an inode, which uses one of the fragments `frags`.
+	fn owner(&self, frags: &Range<u64>) -> Option<InodeNum> {
+		let idx = self.ranges.partition_point(|(r, _)| r.start < frags.end);
+		self.ranges[..idx]
+			.iter()
+			.zip(&self.max_end)
+			.rev()
+			.take_while(|(_, &max_end)| max_end > frags.start)
+			.find(|((r, _), _)| r.end > frags.start)
+			.map(|((_, inr), _)| *inr)
+	}
+}
+
+impl<B: Backend> Ufs<B> {
+	/// Read `buf.len()` bytes of the filesystem, starting at fragment `daddr`,
+	/// like `dd(1)` would, but with the offset checked against the size of the filesystem.
+	///
+	/// Fails with `EINVAL`, if the range isn't inside of the filesystem.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::{Extent, InodeNum};
+	///
+	/// # let ufs = example_image();
+	/// // Read the data of "file1" directly from the device.
+	/// let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref())?;
+	/// let ext = ufs.inode_extents(inr)?[0];
+	/// let daddr = ext.physical / ufs.info().fsize as u64;
+	/// let mut buf = vec![0u8; ext.length as usize];
+	/// ufs.read_raw_block(daddr, &mut buf)?;
+	/// assert!(buf.starts_with(b"This is a simple file."));
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	pub fn read_raw_block(&self, daddr: u64, buf: &mut [u8]) -> IoResult<()> {
+		let pos = self.raw_range("read_raw_block", daddr, buf.len())?;
+		self.read_at(pos.start, buf)
+	}
+
+	/// Overwrite whole fragments of the filesystem with `data`, starting at fragment `daddr`.
+	///
+	/// The length of `data` must be a multiple of the fragment size, otherwise this fails with
+	/// `EINVAL`, like if the range isn't inside of the filesystem.
+	/// Fragments, which hold metadata, like superblocks, cylinder groups, inodes,
+	/// and the boot area, can't be overwritten, and fail with `EPERM`, and so do the indirect
+	/// blocks of files and the blocks of directories, which are found by reading all inodes.
+	///
+	/// This bypasses the filesystem, so it works without write support,
+	/// but [`WriteCaps::raw`] must be set, otherwise this fails with `EROFS`,
+	/// and the backend must be writable. Caches of rufs, like the indexes of directories,
+	/// don't notice the change, so the filesystem should be reopened afterwards.
+	/// Call [`Ufs::sync()`] afterwards, or use [`Txn::write_raw_block()`] to batch writes.
+	pub fn write_raw_block(&self, daddr: u64, data: &[u8]) -> IoResult<()> {
+		let pos = self.raw_write_range(daddr, data.len())?;
+		self.check_structure(&self.structure_map()?, &pos)?;
+		self.touch(pos.clone());
+		self.backend.write_at(pos.start, data)
+	}
+
+	/// Byte range of `len` bytes, starting at fragment `daddr`,
+	/// if they can be overwritten by [`Ufs::write_raw_block()`], and aren't metadata.
+	///
+	/// Indirect blocks and directories are checked by [`Ufs::check_structure()`].
+	pub(super) fn raw_write_range(&self, daddr: u64, len: usize) -> IoResult<Range<u64>> {
+		if !self.options.write.raw {
+			log::error!(
+				"write_raw_block({daddr}): the filesystem was opened without WriteCaps::raw"
+			);
+			return Err(err!(EROFS));
+		}
+		let fs = self.superblock.fsize as u64;
+		if len as u64 % fs != 0 {
+			log::error!(
+				"write_raw_block({daddr}): {len} bytes are not a multiple of the fragment size"
+			);
+			return Err(err!(EINVAL));
+		}
+
+		let pos = self.raw_range("write_raw_block", daddr, len)?;
+		let frags = daddr..(daddr + len as u64 / fs);
+		if let Some(meta) = metadata_extents(&self.superblock)
+			.into_iter()
+			.find(|m| m.start < frags.end && frags.start < m.end)
+		{
+			log::error!(
+				"write_raw_block({daddr}): {frags:?} overlaps with the metadata at {meta:?}"
+			);
+			return Err(err!(EPERM));
+		}
+		Ok(pos)
+	}
+
+	/// Fail with `EPERM`, if the byte range `pos` holds indirect blocks or blocks of directories.
+	pub(super) fn check_structure(&self, map: &StructureMap, pos: &Range<u64>) -> IoResult<()> {
+		let fs = self.superblock.fsize as u64;
+		let frags = (pos.start / fs)..(pos.end / fs);
+		if let Some(inr) = map.owner(&frags) {
+			log::error!(
+				"write_raw_block({}): {frags:?} holds indirect blocks or a directory of inode {inr}",
+				frags.start
+			);
+			return Err(err!(EPERM));
+		}
+		Ok(())
+	}
+
+	/// Find all fragments, which are used as indirect blocks, or as blocks of directories,
+	/// by reading all inodes.
+	pub(super) fn structure_map(&self) -> IoResult<StructureMap> {
+		let sb = &self.superblock;
+		let bs = sb.bsize as u64;
+		let fs = sb.fsize as u64;
+		let frag = sb.frag as u64;
+
+		let mut ranges = Vec::new();
+		let mut ptrs = vec![0u8; bs as usize];
+		for attr in self.inodes() {
+			let inr = attr?.inr;
+			let ino = self.read_inode(inr)?;
+			let InodeData::Blocks(blocks) = &ino.data else {
+				continue;
+			};
+			if !ino.has_data() {
+				continue;
+			}
+
+			if ino.mode & S_IFMT == S_IFDIR {
+				for ext in self.inode_extents(inr)? {
+					let start = ext.physical / fs;
+					ranges.push((start..(start + ext.length.div_ceil(fs)), inr));
+				}
+			}
+
+			// BLK_NOCOPY and BLK_SNAP in snapshots don't refer to actual blocks.
+			let snap = ino.is_snapshot();
+			let real = |addr: u64| addr != 0 && !(snap && addr < frag);
+
+			// Only the indirect blocks above the first level have to be read.
+			let mut stack = blocks
+				.indirect
+				.iter()
+				.enumerate()
+				.map(|(level, &addr)| (level, addr as u64))
+				.collect::<Vec<_>>();
+			while let Some((level, addr)) = stack.pop() {
+				if !real(addr) {
+					continue;
+				}
+				ranges.push((addr..addr.saturating_add(frag), inr));
+				if level == 0 {
+					continue;
+				}
+				self.read_at(frag_addr(&self.geo, addr)?, &mut ptrs)?;
+				for child in ptrs.chunks_exact(size_of::<UfsDaddr>()) {
+					let child: UfsDaddr = self.config.decode_slice(child)?;
+					stack.push((level - 1, child as u64));
+				}
+			}
+		}
+
+		ranges.sort_unstable_by_key(|(r, _)| r.start);
+		let max_end = ranges
+			.iter()
+			.scan(0, |max, (r, _)| {
+				*max = r.end.max(*max);
+				Some(*max)
+			})
+			.collect();
+		Ok(StructureMap { ranges, max_end })
+	}
+
+	/// Byte range of `len` bytes, starting at fragment `daddr`, if it is inside of the filesystem.
+	pub(super) fn raw_range(&self, op: &str, daddr: u64, len: usize) -> IoResult<Range<u64>> {
+		let sb = &self.superblock;
+		let fs = sb.fsize as u64;
+		let size = (sb.size as u64).saturating_mul(fs);
+		match daddr
+			.checked_mul(fs)
+			.and_then(|start| Some(start..start.checked_add(len as u64)?))
+		{
+			Some(r) if r.end <= size => Ok(r),
+			_ => {
+				log::error!("{op}({daddr}): {len} bytes are beyond the end of the filesystem");
+				Err(err!(EINVAL))
+			}
+		}
+	}
+}
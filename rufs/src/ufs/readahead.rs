@@ -0,0 +1,126 @@
+use std::{
+	collections::HashMap,
+	sync::{Mutex, PoisonError},
+};
+
+use super::{inode::block_size, *};
+use crate::InodeNum;
+
+/// Maximum number of files, whose access pattern is tracked at once.
+const MAX_STREAMS: usize = 256;
+
+/// A file, which is being read sequentially.
+struct Stream {
+	/// Where the next read is expected to start.
+	next:   u64,
+	/// End of the data, that was already prefetched.
+	ra_end: u64,
+}
+
+/// Detection of sequential reads, per inode.
+#[derive(Default)]
+pub(super) struct Readahead {
+	streams: Mutex<HashMap<InodeNum, Stream>>,
+}
+
+impl Readahead {
+	/// Record a read of `offset..end`, and return the range of the file,
+	/// that should be prefetched, if the file is read sequentially.
+	fn access(&self, inr: InodeNum, offset: u64, end: u64, window: u64) -> Option<(u64, u64)> {
+		// The map is only used as a hint, so a poisoned lock is harmless.
+		let mut streams = self.streams.lock().unwrap_or_else(PoisonError::into_inner);
+
+		if streams.len() >= MAX_STREAMS && !streams.contains_key(&inr) {
+			streams.clear();
+		}
+		let s = streams.entry(inr).or_insert(Stream {
+			next:   u64::MAX,
+			ra_end: 0,
+		});
+
+		let sequential = offset == 0 || s.next == offset;
+		s.next = end;
+		if !sequential {
+			s.ra_end = end;
+			return None;
+		} else if offset == 0 {
+			s.ra_end = 0;
+		}
+
+		// Start the next window, once half of the current one was consumed.
+		if s.ra_end.saturating_sub(end) > window / 2 {
+			return None;
+		}
+		let start = s.ra_end.max(end);
+		s.ra_end = end + window;
+		Some((start, s.ra_end))
+	}
+}
+
+impl<B: Backend> Ufs<B> {
+	/// Prefetch the data following a read of `offset..end`, if `inr` is read sequentially.
+	///
+	/// Failures are ignored, because the data isn't needed yet.
+	pub(super) fn readahead(&self, inr: InodeNum, ino: &Inode, offset: u64, end: u64) {
+		let window = self.options.readahead;
+		let Some((start, stop)) = self.readahead.access(inr, offset, end, window) else {
+			return;
+		};
+
+		let stop = stop.min(ino.size);
+		if let Err(e) = self.prefetch(inr, ino, start, stop) {
+			log::debug!("readahead({inr}, {start}..{stop}): {e}");
+		}
+	}
+
+	/// Hint the backend about all blocks of the range `start..end` of a file.
+	fn prefetch(&self, inr: InodeNum, ino: &Inode, start: u64, end: u64) -> IoResult<()> {
+		let sb = &self.superblock;
+		let bs = sb.bsize as u64;
+
+		// Physically contiguous blocks are merged into a single hint.
+		let mut run: Option<(u64, usize)> = None;
+		for blkidx in (start / bs)..end.div_ceil(bs) {
+			let Some(blkno) = self.inode_resolve_block(inr, ino, blkidx)? else {
+				continue;
+			};
+			let pos = frag_addr(&self.geo, blkno.get())?;
+			let len = block_size(sb, ino, blkidx)?;
+
+			match &mut run {
+				Some((p, l)) if *p + *l as u64 == pos => *l += len,
+				_ => {
+					if let Some((p, l)) = run.replace((pos, len)) {
+						self.backend.prefetch(p, l)?;
+					}
+				}
+			}
+		}
+
+		match run {
+			Some((p, l)) => self.backend.prefetch(p, l),
+			None => Ok(()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod t {
+	use super::*;
+
+	#[test]
+	fn sequential() {
+		let ra = Readahead::default();
+		let inr = InodeNum::ROOT;
+		assert_eq!(ra.access(inr, 0, 512, 4096), Some((512, 4608)));
+		assert_eq!(ra.access(inr, 512, 1024, 4096), None);
+		assert_eq!(ra.access(inr, 1024, 2560, 4096), Some((4608, 6656)));
+
+		// Random access stops the readahead, until the file is read sequentially again.
+		assert_eq!(ra.access(inr, 8192, 8704, 4096), None);
+		assert_eq!(ra.access(inr, 8704, 9216, 4096), Some((9216, 13312)));
+
+		// Reading from the start begins a new stream.
+		assert_eq!(ra.access(inr, 0, 512, 4096), Some((512, 4608)));
+	}
+}
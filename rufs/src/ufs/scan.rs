@@ -0,0 +1,154 @@
+use super::*;
+use crate::InodeNum;
+
+/// Iterator over the allocated inodes of a filesystem, returned by [`Ufs::inodes()`].
+pub struct Inodes<'a, B: Backend> {
+	ufs:   &'a Ufs<B>,
+	/// The inode, which is looked at next.
+	next:  u64,
+	/// The inodes of the block, which was read last.
+	block: Vec<u8>,
+	/// The first inode of `block`, or `None`, if it must be read first.
+	start: Option<u64>,
+}
+
+impl<B: Backend> Inodes<'_, B> {
+	/// Read the block of inodes, which contains `self.next`.
+	///
+	/// On failure, the whole block is skipped.
+	fn read_block(&mut self) -> IoResult<()> {
+		let inopb = self.ufs.geo.inopb();
+		let start = self.next - self.next % inopb;
+		self.start = None;
+		// SAFETY: this is an inode number of this filesystem.
+		let pos = ino_addr(&self.ufs.geo, unsafe { InodeNum::new(start as u32) });
+		let res = pos.and_then(|pos| self.ufs.read_at(pos, &mut self.block));
+		if let Err(e) = res {
+			log::error!(
+				"inodes(): failed to read inodes {start}..{}: {e}",
+				start + inopb
+			);
+			self.next = start + inopb;
+			return Err(e);
+		}
+		self.start = Some(start);
+		Ok(())
+	}
+}
+
+impl<B: Backend> Iterator for Inodes<'_, B> {
+	type Item = IoResult<InodeAttr>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let geo = &self.ufs.geo;
+		let ipg = geo.ipg();
+		let inopb = geo.inopb();
+		loop {
+			if self.next >= geo.ninodes() {
+				return None;
+			}
+			// SAFETY: this is an inode number of this filesystem.
+			let inr = unsafe { InodeNum::new(self.next as u32) };
+			if inr < InodeNum::ROOT {
+				self.next += 1;
+				continue;
+			}
+			// Inodes after the initialized ones may contain garbage.
+			match self.ufs.inode_inited(inr) {
+				Ok(true) => {}
+				Ok(false) => {
+					self.next = (self.next / ipg + 1) * ipg;
+					continue;
+				}
+				Err(e) => {
+					self.next = (self.next / ipg + 1) * ipg;
+					return Some(Err(e));
+				}
+			}
+			if self.start != Some(self.next - self.next % inopb) {
+				if let Err(e) = self.read_block() {
+					return Some(Err(e));
+				}
+			}
+
+			let off = (self.next % inopb) as usize * UFS_INOSZ;
+			self.next += 1;
+			let ino = match self.ufs.config.decode_slice::<Inode>(&self.block[off..]) {
+				Ok(ino) => ino,
+				Err(e) => return Some(Err(e)),
+			};
+			if ino.mode & S_IFMT == 0 {
+				continue;
+			}
+			let res = ino.as_attr(inr).map(|mut attr| {
+				self.ufs.options.idmap.apply(&mut attr);
+				attr
+			});
+			return Some(res);
+		}
+	}
+}
+
+impl<B: Backend> Ufs<B> {
+	/// Iterate through all allocated inodes, in the order of their inode numbers.
+	///
+	/// This reads the inode tables a block at a time, which is much faster than walking
+	/// the directory tree, but doesn't tell the names of the files.
+	/// Like [`Ufs::inode_attr()`], the owner and the group are mapped using [`Options::idmap`].
+	/// Errors are returned in place of the inodes, that couldn't be read, and the iteration
+	/// continues with the next ones.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::InodeType;
+	///
+	/// # let ufs = example_image();
+	/// let dirs = ufs
+	///     .inodes()
+	///     .filter(|attr| attr.as_ref().is_ok_and(|a| a.kind == InodeType::Directory))
+	///     .count();
+	/// assert_eq!(dirs, 5);
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	#[doc(alias = "inode_scan")]
+	pub fn inodes(&self) -> Inodes<'_, B> {
+		Inodes {
+			ufs:   self,
+			next:  0,
+			block: vec![0u8; self.geo.inopb() as usize * UFS_INOSZ],
+			start: None,
+		}
+	}
+
+	/// Find the inodes, which were created, or whose contents or metadata were modified,
+	/// after `since`, using [`Ufs::inodes()`].
+	///
+	/// This is meant for incremental backups, where `since` is the time of the last one.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use std::time::{Duration, SystemTime};
+	///
+	/// # let ufs = example_image();
+	/// let all = ufs.changed_since(SystemTime::UNIX_EPOCH).count();
+	/// assert_eq!(all, ufs.inodes().count());
+	///
+	/// let future = SystemTime::now() + Duration::from_secs(3600);
+	/// assert_eq!(ufs.changed_since(future).count(), 0);
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	pub fn changed_since(
+		&self,
+		since: SystemTime,
+	) -> impl Iterator<Item = IoResult<InodeAttr>> + '_ {
+		self.inodes().filter(move |attr| {
+			attr.as_ref().map_or(true, |a| {
+				a.mtime > since || a.ctime > since || a.btime > since
+			})
+		})
+	}
+}
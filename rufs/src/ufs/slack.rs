@@ -0,0 +1,188 @@
+use super::{
+	dir::{dir_block_len, dir_blocks, dt_kind},
+	inode::MAX_READ,
+	*,
+};
+use crate::InodeNum;
+
+/// Unused space in a directory, which may contain the remnants of deleted entries.
+///
+/// When an entry is deleted, the record length of the entry in front of it is extended,
+/// so that it covers the deleted entry. If the deleted entry is the first one in a chunk
+/// of `DIRBLKSIZ` (512) bytes, its inode number is cleared instead.
+/// The names of deleted entries remain, until the space is reused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DirSlack {
+	/// Byte offset of the unused space within the directory.
+	pub offset: u64,
+
+	/// The unused space.
+	pub data: Vec<u8>,
+
+	/// Plausible directory entries, which were found in the unused space.
+	pub remnants: Vec<DirRemnant>,
+}
+
+/// A plausible remnant of a deleted directory entry, see [`DirSlack`].
+///
+/// Remnants are only guesses: the inode may have been freed, or reused for another file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DirRemnant {
+	/// Byte offset of the entry within the directory.
+	pub offset: u64,
+
+	/// Inode number of the entry, or `None`, if it was cleared.
+	pub inr: Option<InodeNum>,
+
+	/// File type of the entry, or `None`, if it is unknown.
+	pub kind: Option<InodeType>,
+
+	/// Name of the entry.
+	pub name: OsString,
+}
+
+/// Size of a directory entry with a name of `namelen` bytes, including the NUL and padding.
+fn dirsiz(namelen: usize) -> usize {
+	(8 + namelen + 1).next_multiple_of(4)
+}
+
+/// Parse the header of a directory entry at `off`, if it looks plausible.
+///
+/// Returns the inode number, the record length, the file type and the name.
+fn parse_entry(block: &[u8], off: usize, config: Config) -> Option<(InodeNum, usize, u8, &[u8])> {
+	let hdr = block.get(off..(off + 8))?;
+	let ino: InodeNum = config.decode_slice(&hdr[0..4]).ok()?;
+	let reclen = config.decode_slice::<u16>(&hdr[4..6]).ok()? as usize;
+	let namelen = hdr[7] as usize;
+	let name = block.get((off + 8)..(off + 8 + namelen))?;
+	Some((ino, reclen, hdr[6], name))
+}
+
+/// Find plausible entries in the unused space `block[start..end]`.
+fn remnants(
+	block: &[u8],
+	start: usize,
+	end: usize,
+	base: u64,
+	max_inr: u64,
+	config: Config,
+) -> Vec<DirRemnant> {
+	let mut remnants = Vec::new();
+	let mut off = start.next_multiple_of(4);
+	while off + 8 < end {
+		let plausible =
+			parse_entry(&block[..end], off, config).filter(|(ino, reclen, dt, name)| {
+				!name.is_empty() &&
+					!name.contains(&b'/') &&
+					!name.contains(&0) &&
+					block.get(off + 8 + name.len()) == Some(&0) &&
+					ino.get64() < max_inr &&
+					reclen % 4 == 0 && *reclen >= dirsiz(name.len()) &&
+					off % DIRBLKSIZ + reclen <= DIRBLKSIZ &&
+					(*dt == DT_UNKNOWN || dt_kind(*dt).is_some())
+			});
+		let Some((ino, _, dt, name)) = plausible else {
+			off += 4;
+			continue;
+		};
+
+		remnants.push(DirRemnant {
+			offset: base + off as u64,
+			inr:    Some(ino).filter(|ino| ino.get() != 0),
+			kind:   dt_kind(dt),
+			name:   OsStr::from_bytes(name).to_owned(),
+		});
+		off += dirsiz(name.len());
+	}
+	remnants
+}
+
+/// Find the unused space in `block`, which starts at byte `base` of the directory `inr`.
+fn slack_block(
+	inr: InodeNum,
+	block: &[u8],
+	base: u64,
+	max_inr: u64,
+	config: Config,
+	slack: &mut Vec<DirSlack>,
+) -> IoResult<()> {
+	let mut push = |start: usize, end: usize| {
+		if start < end {
+			slack.push(DirSlack {
+				offset:   base + start as u64,
+				data:     block[start..end].to_vec(),
+				remnants: remnants(block, start, end, base, max_inr, config),
+			});
+		}
+	};
+
+	let mut off = 0;
+	while off < block.len() {
+		let Some((ino, reclen, _, name)) = parse_entry(block, off, config) else {
+			return Err(corrupt!(
+				"dir_slack({inr}): truncated entry at offset {off}"
+			));
+		};
+		if reclen < name.len() + 8 ||
+			off % DIRBLKSIZ + reclen > DIRBLKSIZ ||
+			off + reclen > block.len()
+		{
+			return Err(corrupt!(
+				"dir_slack({inr}): invalid record length {reclen} at offset {off}"
+			));
+		}
+
+		if ino.get() == 0 {
+			push(off, off + reclen);
+		} else {
+			push((off + dirsiz(name.len())).min(off + reclen), off + reclen);
+		}
+		off += reclen;
+	}
+	Ok(())
+}
+
+impl<B: Backend> Ufs<B> {
+	/// Find the unused space in the directory `inr`, and the remnants of deleted entries in it.
+	///
+	/// This is meant for recovering deleted files, and for forensic analysis.
+	/// Fails with [`Error::Corrupt`](crate::Error::Corrupt), if the directory is corrupted.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::InodeNum;
+	///
+	/// # let ufs = example_image();
+	/// for slack in ufs.dir_slack(InodeNum::ROOT)? {
+	///     for r in &slack.remnants {
+	///         println!("{:?} was deleted, it referred to inode {:?}", r.name, r.inr);
+	///     }
+	/// }
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	#[doc(alias("undelete"))]
+	pub fn dir_slack(&self, inr: InodeNum) -> IoResult<Vec<DirSlack>> {
+		let ino = self.read_inode(inr)?;
+		let nblocks = dir_blocks(&self.superblock, inr, &ino)?;
+		let bs = self.superblock.bsize as usize;
+		let max_inr = self.superblock.ipg as u64 * self.superblock.ncg as u64;
+		let mut buf = vec![0u8; MAX_READ.max(bs) / bs * bs];
+
+		let mut slack = Vec::new();
+		let mut blkidx = 0;
+		while blkidx < nblocks {
+			let n = self.inode_read_blocks(inr, &ino, blkidx, nblocks, &mut buf)?;
+			for (blkidx, block) in (blkidx..(blkidx + n)).zip(buf.chunks(bs)) {
+				let len = dir_block_len(&self.superblock, &ino, blkidx);
+				let base = blkidx * bs as u64;
+				slack_block(inr, &block[0..len], base, max_inr, self.config, &mut slack)?;
+			}
+			blkidx += n;
+		}
+		Ok(slack)
+	}
+}
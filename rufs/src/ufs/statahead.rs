@@ -0,0 +1,131 @@
+use std::{
+	collections::{BTreeSet, HashMap},
+	sync::{Mutex, PoisonError},
+};
+
+use super::{
+	dir::{dir_block_len, dir_blocks, readdir_block},
+	inode::MAX_READ,
+	*,
+};
+use crate::InodeNum;
+
+/// Maximum number of directories, whose lookups are counted at once.
+const MAX_DIRS: usize = 256;
+
+/// Number of lookups in a directory, after which the inodes of all its entries are prefetched.
+const TRIGGER: u32 = 8;
+
+/// Detection of lookups of many entries of the same directory, like `ls -l` does.
+#[derive(Default)]
+pub(super) struct Statahead {
+	lookups: Mutex<HashMap<InodeNum, u32>>,
+}
+
+impl Statahead {
+	/// Record a lookup in `dir`, and return whether the inodes of its entries should be prefetched.
+	fn access(&self, dir: InodeNum) -> bool {
+		// The map is only used as a hint, so a poisoned lock is harmless.
+		let mut lookups = self.lookups.lock().unwrap_or_else(PoisonError::into_inner);
+
+		if lookups.len() >= MAX_DIRS && !lookups.contains_key(&dir) {
+			lookups.clear();
+		}
+		let n = lookups.entry(dir).or_insert(0);
+		*n = n.saturating_add(1);
+		*n == TRIGGER
+	}
+}
+
+impl<B: Backend> Ufs<B> {
+	/// Prefetch the inodes of the entries of `dir`, if many of them are looked up.
+	///
+	/// Failures are ignored, because the inodes aren't needed yet.
+	pub(super) fn statahead(&self, dir: InodeNum) {
+		let max = self.options.statahead;
+		if max == 0 || !self.statahead.access(dir) {
+			return;
+		}
+
+		if let Err(e) = self.prefetch_inodes(dir, max) {
+			log::debug!("statahead({dir}): {e}");
+		}
+	}
+
+	/// Hint the backend about the inode blocks of up to `max` entries of `dir`.
+	fn prefetch_inodes(&self, dir: InodeNum, max: usize) -> IoResult<()> {
+		let sb = &self.superblock;
+		let bs = sb.bsize as u64;
+		let fs = sb.fsize as u64;
+		let ninodes = sb.ipg as u64 * sb.ncg as u64;
+
+		// The directory is parsed directly, instead of using dir_iter(),
+		// to not count or hide dangling entries twice.
+		let ino = self.read_inode(dir)?;
+		let nblocks = dir_blocks(sb, dir, &ino)?;
+		let mut buf = vec![0u8; MAX_READ.max(bs as usize) / bs as usize * bs as usize];
+		let mut blocks = BTreeSet::new();
+		let mut n = 0;
+		let mut blkidx = 0;
+		'outer: while blkidx < nblocks {
+			let num = self.inode_read_blocks(dir, &ino, blkidx, nblocks, &mut buf)?;
+			for (blkidx, block) in (blkidx..(blkidx + num)).zip(buf.chunks(bs as usize)) {
+				let len = dir_block_len(sb, &ino, blkidx);
+				let full = readdir_block(dir, &block[0..len], self.config, |_, inr, kind| {
+					if kind == Some(InodeType::Whiteout) {
+						return None;
+					}
+					let pos = sb.ino_to_fsba(inr).and_then(|b| b.checked_mul(fs));
+					if let Some(pos) = pos.filter(|_| inr.get64() < ninodes) {
+						blocks.insert(pos);
+					}
+					n += 1;
+					(n >= max).then_some(())
+				})?;
+				if full.is_some() {
+					break 'outer;
+				}
+			}
+			blkidx += num;
+		}
+		log::trace!("statahead({dir}): {n} entries in {} blocks", blocks.len());
+
+		// Inodes are contiguous within a cylinder group, so neighbouring blocks are merged.
+		let mut run: Option<(u64, usize)> = None;
+		for pos in blocks {
+			match &mut run {
+				Some((p, l)) if *p + *l as u64 == pos => *l += bs as usize,
+				_ => {
+					if let Some((p, l)) = run.replace((pos, bs as usize)) {
+						self.backend.prefetch(p, l)?;
+					}
+				}
+			}
+		}
+
+		match run {
+			Some((p, l)) => self.backend.prefetch(p, l),
+			None => Ok(()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod t {
+	use super::*;
+
+	#[test]
+	fn trigger() {
+		let sa = Statahead::default();
+		let (a, b) = (InodeNum::ROOT, unsafe { InodeNum::new(3) });
+		for _ in 1..TRIGGER {
+			assert!(!sa.access(a));
+			assert!(!sa.access(b));
+		}
+
+		// Lookups in other directories don't interfere, and each directory is prefetched once.
+		assert!(sa.access(a));
+		assert!(sa.access(b));
+		assert!(!sa.access(a));
+	}
+}
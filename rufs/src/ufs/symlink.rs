@@ -1,10 +1,28 @@
-use super::*;
-use crate::InodeNum;
+use super::{inode::MAX_READ, *};
+use crate::{err, InodeNum};
 
-impl<R: Read + Seek> Ufs<R> {
+/// Maximum length of the target of a symbolic link, which is `MAXPATHLEN - 1` on FreeBSD.
+pub const SYMLINK_MAX: usize = 1023;
+
+impl<B: Backend> Ufs<B> {
 	/// Read the contents of a symbolic link.
+	///
+	/// Targets, which are longer than one block, like some other implementations create them,
+	/// are supported.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::InodeNum;
+	///
+	/// # let ufs = example_image();
+	/// let inr = ufs.dir_lookup(InodeNum::ROOT, "link1".as_ref())?;
+	/// assert_eq!(ufs.symlink_read(inr)?, b"dir1/dir2/dir3/file2");
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
 	#[doc(alias = "readlink")]
-	pub fn symlink_read(&mut self, inr: InodeNum) -> IoResult<Vec<u8>> {
+	pub fn symlink_read(&self, inr: InodeNum) -> IoResult<Vec<u8>> {
 		let ino = self.read_inode(inr)?;
 
 		if ino.mode & S_IFMT != S_IFLNK {
@@ -12,21 +30,86 @@ impl<R: Read + Seek> Ufs<R> {
 		}
 
 		match &ino.data {
-			InodeData::Shortlink(link) => {
-				assert_eq!(ino.blocks, 0);
-				let len = ino.size as usize;
-				Ok(link[0..len].to_vec())
-			}
+			InodeData::Shortlink(link) => short_target(inr, &ino, link),
 			InodeData::Blocks { .. } => {
-				// TODO: this has to be tested for other configurations, such as 4K/4K
-				assert!(ino.blocks <= 8);
-
-				let len = ino.size as usize;
-				let mut buf = vec![0u8; self.superblock.bsize as usize];
-				self.inode_read_block(inr, &ino, 0, &mut buf)?;
-				buf.resize(len, 0u8);
+				let len = long_target_len(inr, &ino)?;
+				let mut buf = vec![0u8; len];
+				let n = self.inode_read(inr, 0, &mut buf)?;
+				buf.truncate(n);
 				Ok(buf)
 			}
 		}
 	}
+
+	/// Create a symbolic link named `name` in the directory `dinr`, which points to `target`.
+	///
+	/// The target may contain any bytes, except for NUL, and may be at most [`SYMLINK_MAX`]
+	/// bytes long, or [`Superblock::maxfilesize`], if that is smaller.
+	/// Targets, which are longer than [`Superblock::maxsymlinklen`], are stored in blocks.
+	/// This requires [`WriteCaps::create`].
+	///
+	/// Fails with `ENOENT`, if `target` is empty, with `EINVAL`, if it contains NUL,
+	/// with `ENAMETOOLONG`, if it is too long, and otherwise like [`Ufs::dir_whiteout()`].
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::{InodeNum, SYMLINK_MAX};
+	///
+	/// # let ufs = example_image();
+	/// let long = vec![b'x'; SYMLINK_MAX + 1];
+	/// let err = ufs.symlink(InodeNum::ROOT, "link".as_ref(), &long).unwrap_err();
+	/// assert_eq!(err.raw_os_error(), Some(libc::ENAMETOOLONG));
+	///
+	/// // There is no write support yet.
+	/// let err = ufs.symlink(InodeNum::ROOT, "link".as_ref(), b"file1").unwrap_err();
+	/// assert_eq!(err.raw_os_error(), Some(libc::EROFS));
+	/// ```
+	pub fn symlink(&self, dinr: InodeNum, name: &OsStr, target: &[u8]) -> IoResult<InodeAttr> {
+		if target.is_empty() {
+			return Err(err!(ENOENT));
+		}
+		if target.contains(&0) {
+			return Err(err!(EINVAL));
+		}
+		if target.len() > SYMLINK_MAX || target.len() as u64 > self.superblock.maxfilesize {
+			return Err(err!(ENAMETOOLONG));
+		}
+		self.check_create(dinr, name)?;
+
+		// TODO: allocate an inode, store `target` in it, if it is shorter than maxsymlinklen,
+		// or in as many blocks as needed otherwise, and insert the entry.
+		log::error!("symlink({dinr}, {name:?}): write support is not implemented");
+		Err(err!(EROFS))
+	}
+}
+
+/// Target of the symbolic link `inr`, which is stored in the inode.
+pub(super) fn short_target(inr: InodeNum, ino: &Inode, link: &[u8]) -> IoResult<Vec<u8>> {
+	match usize::try_from(ino.size)
+		.ok()
+		.and_then(|len| link.get(0..len))
+	{
+		Some(target) => Ok(target.to_vec()),
+		None => {
+			Err(corrupt!(
+				"symlink_read({inr}): invalid length of a short link: {}",
+				ino.size
+			))
+		}
+	}
+}
+
+/// Length of the target of the symbolic link `inr`, which is stored in blocks.
+pub(super) fn long_target_len(inr: InodeNum, ino: &Inode) -> IoResult<usize> {
+	match usize::try_from(ino.size) {
+		Ok(len) if len <= MAX_READ => Ok(len),
+		_ => {
+			Err(corrupt!(
+				"symlink_read({inr}): the target is too long: {}",
+				ino.size
+			))
+		}
+	}
 }
@@ -0,0 +1,95 @@
+use std::ops::Range;
+
+use super::{fsck::isset, *};
+use crate::err;
+
+impl<B: Backend> Ufs<B> {
+	/// Get the byte ranges of all free fragments, according to the maps of the cylinder groups.
+	///
+	/// Adjacent fragments are merged into a single range, and the ranges are sorted.
+	/// Cylinder groups with damaged maps are skipped.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// # let ufs = example_image();
+	/// let free: u64 = ufs.free_extents()?.iter().map(|r| r.end - r.start).sum();
+	/// let info = ufs.info();
+	/// assert_eq!(free, info.bfree * info.fsize as u64);
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	pub fn free_extents(&self) -> IoResult<Vec<Range<u64>>> {
+		let sb = &self.superblock;
+		let fs = sb.fsize as u64;
+		let mut extents: Vec<Range<u64>> = Vec::new();
+		for cgx in 0..(sb.ncg as u64) {
+			let Some(maps) = self.cg_maps(cgx)? else {
+				log::warn!("free_extents(): skipping the damaged cylinder group {cgx}");
+				continue;
+			};
+
+			for f in (0..maps.ndblk).filter(|&f| isset(&maps.free, f)) {
+				let pos = cg_addr(&self.geo, cgx, f)?;
+				match extents.last_mut() {
+					Some(last) if last.end == pos => last.end += fs,
+					_ => extents.push(pos..(pos + fs)),
+				}
+			}
+		}
+		Ok(extents)
+	}
+
+	/// Discard all free fragments, see [`Backend::discard()`], and return the number of bytes.
+	///
+	/// This releases the storage of free fragments, like by punching holes into an image file,
+	/// without changing the contents of the filesystem.
+	/// The filesystem must not be mounted elsewhere, while it is trimmed.
+	///
+	/// The maps of the cylinder groups can't be trusted, if the filesystem wasn't unmounted
+	/// cleanly, so this fails with `EINVAL` then, unless [`Options::force`] is set.
+	#[doc(alias = "fstrim")]
+	pub fn trim(&self) -> IoResult<u64> {
+		self.trim_free_space(0..u64::MAX, 0)
+	}
+
+	/// Discard the free fragments in the byte range `range` of the filesystem, like [`Ufs::trim()`],
+	/// but skip runs of free fragments, which are shorter than `minlen` bytes, like `FITRIM`.
+	/// Fragments, which are only partially inside of `range`, aren't discarded.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// # let ufs = example_image();
+	/// // Empty ranges are never passed to the backend.
+	/// assert_eq!(ufs.trim_free_space(0..0, 0)?, 0);
+	/// assert_eq!(ufs.trim_free_space(0..u64::MAX, u64::MAX)?, 0);
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	#[doc(alias = "FITRIM")]
+	pub fn trim_free_space(&self, range: Range<u64>, minlen: u64) -> IoResult<u64> {
+		let sb = &self.superblock;
+		if (sb.clean == 0 || sb.flags & (FS_UNCLEAN | FS_NEEDSFSCK) != 0) && !self.options.force {
+			log::error!("trim(): the filesystem wasn't unmounted cleanly, run fsck_ffs(8) first");
+			return Err(err!(EINVAL));
+		}
+
+		// Fragments, which are only partially inside of the range, are kept.
+		let fs = sb.fsize as u64;
+		let range = range.start.div_ceil(fs).saturating_mul(fs)..(range.end / fs * fs);
+
+		let mut total = 0;
+		for r in self.free_extents()? {
+			// Fragments beyond the end of a truncated image take no space anyway.
+			let end = self.dev_size.map_or(r.end, |size| r.end.min(size));
+			let (start, end) = (r.start.max(range.start), end.min(range.end));
+			if end > start && end - start >= minlen.max(1) {
+				self.backend.discard(start, end - start)?;
+				total += end - start;
+			}
+		}
+		log::info!("trim(): discarded {total} bytes");
+		Ok(total)
+	}
+}
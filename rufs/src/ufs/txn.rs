@@ -0,0 +1,198 @@
+use std::collections::{btree_map, BTreeMap};
+
+use super::{raw::StructureMap, *};
+use crate::err;
+
+/// A batch of writes, which are applied together by [`Ufs::with_txn()`].
+///
+/// Writes are collected per fragment, so that many small updates of the same fragment,
+/// like of neighbouring inodes, or of the bitmaps of a cylinder group,
+/// only read it once, and write it once, when the batch is committed.
+/// Reads through the batch see its own writes.
+pub struct Txn<'a, B: Backend> {
+	ufs:       &'a Ufs<B>,
+	fsize:     u64,
+	/// Modified fragments, by fragment number.
+	frags:     BTreeMap<u64, Box<[u8]>>,
+	/// Indirect blocks and directories, which are found once per batch.
+	structure: Option<StructureMap>,
+}
+
+impl<'a, B: Backend> Txn<'a, B> {
+	fn new(ufs: &'a Ufs<B>) -> Self {
+		Self {
+			ufs,
+			fsize: ufs.superblock.fsize as u64,
+			frags: BTreeMap::new(),
+			structure: None,
+		}
+	}
+
+	/// Number of bytes, that will be written, when the batch is committed.
+	pub fn dirty(&self) -> u64 {
+		self.frags.len() as u64 * self.fsize
+	}
+
+	/// Like [`Ufs::read_raw_block()`], but with the writes of this batch applied.
+	pub fn read_raw_block(&self, daddr: u64, buf: &mut [u8]) -> IoResult<()> {
+		let pos = self.ufs.raw_range("read_raw_block", daddr, buf.len())?;
+		self.read_at(pos.start, buf)
+	}
+
+	/// Like [`Ufs::write_raw_block()`], but the fragments are only written,
+	/// when the batch is committed, and the inodes are only read by the first call.
+	pub fn write_raw_block(&mut self, daddr: u64, data: &[u8]) -> IoResult<()> {
+		let pos = self.ufs.raw_write_range(daddr, data.len())?;
+		let map = match &mut self.structure {
+			Some(map) => map,
+			None => self.structure.insert(self.ufs.structure_map()?),
+		};
+		self.ufs.check_structure(map, &pos)?;
+		self.write_at(pos.start, data)
+	}
+
+	pub(crate) fn read_at(&self, pos: u64, buf: &mut [u8]) -> IoResult<()> {
+		self.ufs.read_at(pos, buf)?;
+
+		let end = pos + buf.len() as u64;
+		let first = pos / self.fsize;
+		let last = end.div_ceil(self.fsize);
+		for (&frag, data) in self.frags.range(first..last) {
+			let start = (frag * self.fsize).max(pos);
+			let stop = ((frag + 1) * self.fsize).min(end);
+			let off = (start - frag * self.fsize) as usize;
+			let len = (stop - start) as usize;
+			let boff = (start - pos) as usize;
+			buf[boff..(boff + len)].copy_from_slice(&data[off..(off + len)]);
+		}
+		Ok(())
+	}
+
+	pub(crate) fn write_at(&mut self, pos: u64, buf: &[u8]) -> IoResult<()> {
+		let fs = self.fsize as usize;
+		let mut done = 0;
+		while done < buf.len() {
+			let p = pos + done as u64;
+			let frag = p / self.fsize;
+			let off = (p % self.fsize) as usize;
+			let num = (fs - off).min(buf.len() - done);
+
+			let data = match self.frags.entry(frag) {
+				btree_map::Entry::Occupied(e) => e.into_mut(),
+				btree_map::Entry::Vacant(e) => {
+					// Fragments, which are overwritten completely, don't have to be read.
+					let mut data = vec![0u8; fs].into_boxed_slice();
+					if num != fs {
+						self.ufs.read_at(frag * self.fsize, &mut data)?;
+					}
+					e.insert(data)
+				}
+			};
+			data[off..(off + num)].copy_from_slice(&buf[done..(done + num)]);
+			done += num;
+		}
+		Ok(())
+	}
+
+	/// Write the modified fragments, each run of consecutive ones at once, and sync.
+	fn commit(self) -> IoResult<()> {
+		let mut runs = Vec::new();
+		let mut iter = self.frags.into_iter().peekable();
+		while let Some((start, data)) = iter.next() {
+			let mut run = data.into_vec();
+			let mut next = start + 1;
+			while let Some((_, data)) = iter.next_if(|(frag, _)| *frag == next) {
+				run.extend_from_slice(&data);
+				next += 1;
+			}
+			runs.push((start * self.fsize, run));
+		}
+		if runs.is_empty() {
+			return Ok(());
+		}
+		if !self.ufs.options.write.raw {
+			log::error!("with_txn(): raw writes are not enabled");
+			return Err(err!(EROFS));
+		}
+
+		let log = self.ufs.intent_log();
+		if let Some(log) = &log {
+			log.record(&runs)?;
+		}
+		for (pos, run) in &runs {
+			self.ufs.touch(*pos..(*pos + run.len() as u64));
+			self.ufs.backend.write_at(*pos, run)?;
+		}
+		log::debug!("with_txn(): {} writes", runs.len());
+		self.ufs.sync()?;
+		match &log {
+			Some(log) => log.clear(),
+			None => Ok(()),
+		}
+	}
+}
+
+impl<B: Backend> Ufs<B> {
+	/// Run `f` with a batch of writes, and apply them together, once it succeeded.
+	///
+	/// Modified fragments are written in ascending order, consecutive ones at once,
+	/// followed by a single [`Ufs::sync()`]. If `f` fails, nothing is written.
+	/// Batches, which modify anything, fail with `EROFS`, unless [`WriteCaps::raw`] is set.
+	/// The batch is not atomic on the device: if the system crashes while it is
+	/// being committed, only a part of it may have been written,
+	/// unless [`Options::intent_log`] is set.
+	/// Concurrent batches aren't isolated from each other.
+	///
+	/// # Example
+	/// ```no_run
+	/// use std::path::Path;
+	///
+	/// use rufs::Ufs;
+	///
+	/// let ufs = Ufs::open(Path::new("disk.img"))?;
+	/// let fsize = ufs.info().fsize as usize;
+	/// ufs.with_txn(|txn| {
+	///     // Fragments 100 and 101 are written at once.
+	///     txn.write_raw_block(100, &vec![0xaa; fsize])?;
+	///     txn.write_raw_block(101, &vec![0x55; fsize])?;
+	///     assert_eq!(txn.dirty(), 2 * fsize as u64);
+	///     Ok(())
+	/// })?;
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	#[doc(alias("transaction", "batch"))]
+	pub fn with_txn<T>(&self, f: impl FnOnce(&mut Txn<'_, B>) -> IoResult<T>) -> IoResult<T> {
+		let mut txn = Txn::new(self);
+		let res = f(&mut txn)?;
+		txn.commit()?;
+		Ok(res)
+	}
+}
+
+#[cfg(test)]
+mod t {
+	use super::*;
+	use crate::{mkfs, MkfsOptions};
+
+	/// Nothing is committed without `WriteCaps::raw`, even if the batch was filled internally.
+	#[test]
+	fn read_only() {
+		let opts = MkfsOptions::new(32 << 20);
+		let file = tempfile::tempfile().unwrap();
+		file.set_len(opts.size).unwrap();
+		let backend = BlockFile::new(file, 4096);
+		mkfs(&backend, &opts).unwrap();
+		let ufs = Ufs::new(backend).unwrap();
+		let fsize = ufs.superblock.fsize as u64;
+		let pos = (ufs.superblock.size as u64 - 1) * fsize;
+
+		let mut txn = Txn::new(&ufs);
+		txn.write_at(pos, &[0xff; 16]).unwrap();
+		let e = txn.commit().unwrap_err();
+		assert_eq!(e.raw_os_error(), Some(libc::EROFS));
+
+		let mut buf = [0xaa; 16];
+		ufs.read_at(pos, &mut buf).unwrap();
+		assert_eq!(buf, [0; 16]);
+	}
+}
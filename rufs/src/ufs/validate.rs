@@ -0,0 +1,225 @@
+use super::{dir::check_name, *};
+use crate::{err, InodeAttr, InodeNum};
+
+/// Mode bit of directories, in which only the owners of entries may remove them.
+const S_ISVTX: u16 = 0o1000;
+
+impl<B: Backend> Ufs<B> {
+	/// Check whether a process with the credentials `cred` could create an entry named `name`
+	/// in the directory `dinr`, like `mknod(2)`, `mkdir(2)` or `symlink(2)`, without creating it.
+	///
+	/// All checks of creating the entry are done: the directory must be writable and searchable,
+	/// `name` must be valid and unused, there must be a free inode and a free fragment,
+	/// in case the directory has to grow, and [`WriteCaps::create`] must be set.
+	///
+	/// Fails with `ENOTDIR`, if `dinr` isn't a directory, with `EACCES` or `EPERM` like
+	/// [`Ufs::access()`], with `EINVAL` or `ENAMETOOLONG`, if `name` is not a valid name,
+	/// with `EEXIST`, if it already exists, with `ENOSPC`, if the filesystem is full,
+	/// and with `EROFS`, if the filesystem is read-only.
+	/// A successful check doesn't guarantee, that the operation succeeds,
+	/// if the filesystem is modified in between.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::{Credentials, InodeNum};
+	///
+	/// # let ufs = example_image();
+	/// let root = Credentials::new(0, 0);
+	/// let err = ufs.can_mknod(InodeNum::ROOT, "file1".as_ref(), &root).unwrap_err();
+	/// assert_eq!(err.raw_os_error(), Some(libc::EEXIST));
+	///
+	/// // Everything else would be fine, but there is no write support yet.
+	/// let err = ufs.can_mknod(InodeNum::ROOT, "new".as_ref(), &root).unwrap_err();
+	/// assert_eq!(err.raw_os_error(), Some(libc::EROFS));
+	/// ```
+	#[doc(alias("can_create", "can_mkdir", "can_symlink", "dry_run"))]
+	pub fn can_mknod(&self, dinr: InodeNum, name: &OsStr, cred: &Credentials) -> IoResult<()> {
+		self.check_dir(dinr, cred)?;
+		self.check_new(dinr, name)?;
+		let info = self.info();
+		if info.ffree == 0 || info.bfree == 0 {
+			return Err(err!(ENOSPC));
+		}
+		if !self.options.write.create {
+			return Err(err!(EROFS));
+		}
+		Ok(())
+	}
+
+	/// Check whether a process with the credentials `cred` could remove the entry `name`
+	/// of the directory `dinr`, like `unlink(2)` or `rmdir(2)`, without removing it.
+	///
+	/// The directory must be writable and searchable, and neither the entry nor the directory
+	/// may be protected by flags (see [`InodeAttr::is_undeletable()`]).
+	/// In sticky directories, only the owners of the entry and of the directory may remove it.
+	/// Directories must be empty, and [`WriteCaps::delete`] must be set.
+	///
+	/// Fails with `ENOTDIR`, if `dinr` isn't a directory, with `EACCES` or `EPERM` like
+	/// [`Ufs::access()`], with `EINVAL` for "." and "..", with `ENOENT`, if the entry doesn't exist,
+	/// with `EPERM`, if it is protected, with `ENOTEMPTY`, if it is a directory,
+	/// which isn't empty, and with `EROFS`, if the filesystem is read-only.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::{Credentials, InodeNum};
+	///
+	/// # let ufs = example_image();
+	/// let root = Credentials::new(0, 0);
+	/// let err = ufs.can_unlink(InodeNum::ROOT, "dir1".as_ref(), &root).unwrap_err();
+	/// assert_eq!(err.raw_os_error(), Some(libc::ENOTEMPTY));
+	/// ```
+	#[doc(alias("can_remove", "can_rmdir"))]
+	pub fn can_unlink(&self, dinr: InodeNum, name: &OsStr, cred: &Credentials) -> IoResult<()> {
+		let (_, attr) = self.check_remove(dinr, name, cred)?;
+		if attr.kind == InodeType::Directory && !self.dir_is_empty(attr.inr)? {
+			return Err(err!(ENOTEMPTY));
+		}
+		if !self.options.write.delete {
+			return Err(err!(EROFS));
+		}
+		Ok(())
+	}
+
+	/// Check whether a process with the credentials `cred` could rename the entry `sname`
+	/// of the directory `sdinr` to `dname` in the directory `ddinr`, like `rename(2)`,
+	/// without renaming it.
+	///
+	/// The source must be removable, like [`Ufs::can_unlink()`] checks, except that directories
+	/// don't have to be empty, but have to be writable, if they are moved to another directory.
+	/// An existing destination must be removable as well, of the same kind as the source,
+	/// and empty, if it is a directory. Directories can't be moved into themselves.
+	/// This requires [`WriteCaps::create`] and [`WriteCaps::delete`].
+	///
+	/// Fails with the errors of [`Ufs::can_unlink()`] and [`Ufs::can_mknod()`],
+	/// except `EEXIST`, with `EISDIR` or `ENOTDIR`, if only one of the source and the destination
+	/// is a directory, and with `EINVAL`, if a directory would be moved into itself.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::{Credentials, InodeNum};
+	///
+	/// # let ufs = example_image();
+	/// let root = Credentials::new(0, 0);
+	/// let dir1 = ufs.dir_lookup(InodeNum::ROOT, "dir1".as_ref())?;
+	/// let res = ufs.can_rename(InodeNum::ROOT, "dir1".as_ref(), dir1, "x".as_ref(), &root);
+	/// assert_eq!(res.unwrap_err().raw_os_error(), Some(libc::EINVAL));
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	#[doc(alias("can_move", "can_mv"))]
+	pub fn can_rename(
+		&self,
+		sdinr: InodeNum,
+		sname: &OsStr,
+		ddinr: InodeNum,
+		dname: &OsStr,
+		cred: &Credentials,
+	) -> IoResult<()> {
+		let (_, src) = self.check_remove(sdinr, sname, cred)?;
+		self.check_dir(ddinr, cred)?;
+		check_name(dname)?;
+
+		let isdir = src.kind == InodeType::Directory;
+		if isdir && sdinr != ddinr {
+			// ".." of the directory is changed.
+			self.access(src.inr, cred, libc::W_OK)?;
+			self.check_ancestor(src.inr, ddinr)?;
+		}
+
+		match self.dir_lookup(ddinr, dname) {
+			Ok(dinr) if dinr == src.inr => {}
+			Ok(_) => {
+				let (_, dst) = self.check_remove(ddinr, dname, cred)?;
+				match (isdir, dst.kind == InodeType::Directory) {
+					(true, false) => return Err(err!(ENOTDIR)),
+					(false, true) => return Err(err!(EISDIR)),
+					(true, true) if !self.dir_is_empty(dst.inr)? => return Err(err!(ENOTEMPTY)),
+					_ => {}
+				}
+			}
+			Err(e) if e.raw_os_error() == Some(libc::ENOENT) => {
+				if self.info().bfree == 0 {
+					return Err(err!(ENOSPC));
+				}
+			}
+			Err(e) => return Err(e),
+		}
+
+		if !self.options.write.create || !self.options.write.delete {
+			return Err(err!(EROFS));
+		}
+		Ok(())
+	}
+
+	/// Check whether `dinr` is a directory, whose entries `cred` may modify.
+	fn check_dir(&self, dinr: InodeNum, cred: &Credentials) -> IoResult<InodeAttr> {
+		let attr = self.inode_attr(dinr)?;
+		if attr.kind != InodeType::Directory {
+			return Err(err!(ENOTDIR));
+		}
+		self.access(dinr, cred, libc::W_OK | libc::X_OK)?;
+		Ok(attr)
+	}
+
+	/// Check whether `name` is valid, and doesn't exist in `dinr` yet.
+	fn check_new(&self, dinr: InodeNum, name: &OsStr) -> IoResult<()> {
+		check_name(name)?;
+		match self.dir_lookup(dinr, name) {
+			Ok(_) => Err(err!(EEXIST)),
+			Err(e) if e.raw_os_error() == Some(libc::ENOENT) => Ok(()),
+			Err(e) => Err(e),
+		}
+	}
+
+	/// Check whether `cred` may remove the entry `name` from `dinr`, like `ufs_remove()`
+	/// in FreeBSD, and return the attributes of the directory and the entry.
+	fn check_remove(
+		&self,
+		dinr: InodeNum,
+		name: &OsStr,
+		cred: &Credentials,
+	) -> IoResult<(InodeAttr, InodeAttr)> {
+		let dir = self.check_dir(dinr, cred)?;
+		if name == "." || name == ".." {
+			return Err(err!(EINVAL));
+		}
+		let attr = self.inode_attr(self.dir_lookup(dinr, name)?)?;
+		if attr.is_undeletable() || dir.is_append_only() {
+			return Err(err!(EPERM));
+		}
+		let owner = cred.is_root() || cred.uid == dir.uid || cred.uid == attr.uid;
+		if dir.perm & S_ISVTX != 0 && !owner {
+			return Err(err!(EPERM));
+		}
+		Ok((dir, attr))
+	}
+
+	/// Fail with `EINVAL`, if `inr` is `dinr`, or one of its parent directories.
+	fn check_ancestor(&self, inr: InodeNum, dinr: InodeNum) -> IoResult<()> {
+		let mut cur = dinr;
+		// A corrupted filesystem may contain loops.
+		for _ in 0..self.info().files {
+			if cur == inr {
+				return Err(err!(EINVAL));
+			}
+			if cur == InodeNum::ROOT {
+				return Ok(());
+			}
+			cur = self.dir_lookup(cur, "..".as_ref())?;
+		}
+		Err(corrupt!("the parents of directory {dinr} form a loop"))
+	}
+
+	/// Check whether the directory `inr` only contains "." and "..".
+	fn dir_is_empty(&self, inr: InodeNum) -> IoResult<bool> {
+		let other = self.dir_iter(inr, |name, _, _| {
+			(name != "." && name != "..").then_some(())
+		})?;
+		Ok(other.is_none())
+	}
+}
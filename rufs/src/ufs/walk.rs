@@ -0,0 +1,403 @@
+use std::collections::{HashSet, VecDeque};
+
+use super::*;
+use crate::{err, InodeNum};
+
+/// Cycle and depth protection for recursive directory walkers.
+///
+/// Corrupted images may contain directories, which (indirectly) contain themselves.
+/// A `TreeGuard` remembers every directory it entered and refuses to enter
+/// a directory twice, or to descend deeper than the maximum depth.
+/// In both cases `ELOOP` is returned.
+///
+/// Note: "." and ".." must be skipped by the walker itself.
+///
+/// # Example
+/// ```
+/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+/// # use support::*;
+/// use std::io::Result;
+///
+/// use rufs::{Backend, InodeNum, InodeType, TreeGuard, Ufs};
+///
+/// fn count<B: Backend>(ufs: &Ufs<B>, g: &mut TreeGuard, inr: InodeNum) -> Result<usize> {
+///     g.enter(inr)?;
+///     let mut dirs = Vec::new();
+///     let mut n = 0;
+///     ufs.dir_iter(inr, |name, inr, kind| {
+///         if name != "." && name != ".." {
+///             n += 1;
+///             if kind == InodeType::Directory {
+///                 dirs.push(inr);
+///             }
+///         }
+///         None::<()>
+///     })?;
+///     for dir in dirs {
+///         n += count(ufs, g, dir)?;
+///     }
+///     g.leave();
+///     Ok(n)
+/// }
+///
+/// # let ufs = example_image();
+/// let n = count(&ufs, &mut TreeGuard::new(), InodeNum::ROOT)?;
+/// assert!(n > 10);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct TreeGuard {
+	visited:   HashSet<InodeNum>,
+	depth:     usize,
+	max_depth: usize,
+}
+
+impl TreeGuard {
+	/// Default maximum depth of a directory tree.
+	pub const MAX_DEPTH: usize = 1024;
+
+	/// Create a new guard, with a maximum depth of [`TreeGuard::MAX_DEPTH`].
+	pub fn new() -> Self {
+		Self::with_max_depth(Self::MAX_DEPTH)
+	}
+
+	/// Create a new guard, with a custom maximum depth.
+	pub fn with_max_depth(max_depth: usize) -> Self {
+		Self {
+			visited: HashSet::new(),
+			depth: 0,
+			max_depth,
+		}
+	}
+
+	/// Enter the directory `inr`.
+	///
+	/// Fails with `ELOOP`, if `inr` was already entered, or if the maximum depth would be exceeded.
+	pub fn enter(&mut self, inr: InodeNum) -> IoResult<()> {
+		if self.depth >= self.max_depth {
+			log::warn!(
+				"TreeGuard: maximum depth of {} exceeded at {inr}",
+				self.max_depth
+			);
+			return Err(err!(ELOOP));
+		}
+
+		if !self.visited.insert(inr) {
+			log::warn!("TreeGuard: directory cycle detected at {inr}");
+			return Err(err!(ELOOP));
+		}
+
+		self.depth += 1;
+		Ok(())
+	}
+
+	/// Leave the directory that was most recently entered.
+	///
+	/// Does nothing, if no directory was entered.
+	pub fn leave(&mut self) {
+		if self.depth == 0 {
+			log::warn!("TreeGuard::leave() without enter()");
+			return;
+		}
+		self.depth -= 1;
+	}
+
+	/// Current depth.
+	pub fn depth(&self) -> usize {
+		self.depth
+	}
+}
+
+impl Default for TreeGuard {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// Maximum number of symbolic links, which are followed, while resolving a path.
+const MAXSYMLINKS: usize = 32;
+
+/// Order, in which [`Ufs::walk()`] returns the files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WalkOrder {
+	/// Return the contents of a directory right after it (default), like find(1).
+	#[default]
+	DepthFirst,
+
+	/// Return all files of a level, before the files of the next one.
+	BreadthFirst,
+}
+
+/// What [`Ufs::walk()`] does with symbolic links.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+	/// Return the links themselves (default).
+	#[default]
+	NoFollow,
+
+	/// Return the files, which the links point to, and descend into directories.
+	/// Absolute targets are resolved from the root of the filesystem.
+	/// Links, which can't be resolved, are returned as errors.
+	Follow,
+}
+
+/// Options of [`Ufs::walk_with()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalkOptions {
+	/// Order of the files.
+	pub order: WalkOrder,
+
+	/// What to do with symbolic links.
+	pub symlinks: SymlinkPolicy,
+
+	/// Directories deeper than this aren't descended into, where the start is at depth 0.
+	pub max_depth: usize,
+}
+
+impl Default for WalkOptions {
+	fn default() -> Self {
+		Self {
+			order:     WalkOrder::default(),
+			symlinks:  SymlinkPolicy::default(),
+			max_depth: usize::MAX,
+		}
+	}
+}
+
+/// A file, which [`Walk`] will return.
+struct Pending {
+	path:   PathBuf,
+	/// The directory containing the file, which symbolic links are relative to.
+	parent: InodeNum,
+	inr:    InodeNum,
+	depth:  usize,
+}
+
+/// Iterator over a directory tree, returned by [`Ufs::walk()`].
+///
+/// Every directory is descended into only once, so that cycles, like the ones caused by
+/// corrupted directories, or by following symbolic links, end the walk.
+/// Directories, which are reachable through several paths, are still returned for each of them.
+pub struct Walk<'a, B: Backend> {
+	ufs:     &'a Ufs<B>,
+	opts:    WalkOptions,
+	pending: VecDeque<Pending>,
+	visited: HashSet<InodeNum>,
+	/// Error, which occurred while reading the directory, that was returned last.
+	error:   Option<IoError>,
+}
+
+impl<B: Backend> Walk<'_, B> {
+	/// Queue the entries of the directory `inr`, and return the first error.
+	fn descend(&mut self, path: &Path, inr: InodeNum, depth: usize) -> IoResult<()> {
+		if !self.visited.insert(inr) {
+			log::warn!("walk: {path:?} -> {inr} was already visited, not descending into it");
+			return Ok(());
+		}
+
+		let mut children = Vec::new();
+		let mut res = Ok(());
+		for entry in self.ufs.dir_entries(inr) {
+			match entry {
+				Ok(e) if e.name == "." || e.name == ".." => {}
+				Ok(e) => {
+					children.push(Pending {
+						path:   path.join(&e.name),
+						parent: inr,
+						inr:    e.inr,
+						depth:  depth + 1,
+					})
+				}
+				Err(e) => res = Err(e),
+			}
+		}
+
+		match self.opts.order {
+			WalkOrder::DepthFirst => {
+				for child in children.into_iter().rev() {
+					self.pending.push_front(child);
+				}
+			}
+			WalkOrder::BreadthFirst => self.pending.extend(children),
+		}
+		res
+	}
+}
+
+impl<B: Backend> Iterator for Walk<'_, B> {
+	type Item = IoResult<(PathBuf, InodeNum, InodeAttr)>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if let Some(e) = self.error.take() {
+			return Some(Err(e));
+		}
+		let Pending {
+			path,
+			parent,
+			mut inr,
+			depth,
+		} = self.pending.pop_front()?;
+
+		let mut attr = match self.ufs.inode_attr(inr) {
+			Ok(attr) => attr,
+			Err(e) => {
+				log::error!("walk: {path:?}: {e}");
+				return Some(Err(e));
+			}
+		};
+		if attr.kind == InodeType::Symlink && self.opts.symlinks == SymlinkPolicy::Follow {
+			let res = self
+				.ufs
+				.resolve_symlink(parent, inr)
+				.and_then(|target| Ok((target, self.ufs.inode_attr(target)?)));
+			match res {
+				Ok((target, tattr)) => (inr, attr) = (target, tattr),
+				Err(e) => {
+					log::error!("walk: {path:?}: can't follow the symbolic link: {e}");
+					return Some(Err(e));
+				}
+			}
+		}
+
+		if attr.kind == InodeType::Directory && depth < self.opts.max_depth {
+			if let Err(e) = self.descend(&path, inr, depth) {
+				log::error!("walk: {path:?}: {e}");
+				self.error = Some(e);
+			}
+		}
+		Some(Ok((path, inr, attr)))
+	}
+}
+
+impl<B: Backend> Ufs<B> {
+	/// Walk the directory tree below `start`, like [`Ufs::walk_with()`], with the default options.
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use std::path::Path;
+	///
+	/// use rufs::{InodeNum, InodeType};
+	///
+	/// # let ufs = example_image();
+	/// let mut files = Vec::new();
+	/// for entry in ufs.walk(InodeNum::ROOT) {
+	///     let (path, _inr, attr) = entry?;
+	///     if attr.kind == InodeType::RegularFile && path.starts_with("dir1") {
+	///         files.push(path);
+	///     }
+	/// }
+	/// assert!(files.contains(&Path::new("dir1/dir2/dir3/file2").to_owned()));
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	pub fn walk(&self, start: InodeNum) -> Walk<'_, B> {
+		self.walk_with(start, WalkOptions::default())
+	}
+
+	/// Walk the directory tree below `start`, and return the path, inode number and attributes
+	/// of every file, including `start` itself, except for "." and "..".
+	///
+	/// The paths are relative to `start`, which has an empty path.
+	/// Errors are returned in place of the files or entries, that couldn't be read,
+	/// and the walk continues with the next file.
+	pub fn walk_with(&self, start: InodeNum, opts: WalkOptions) -> Walk<'_, B> {
+		let mut pending = VecDeque::new();
+		pending.push_back(Pending {
+			path:   PathBuf::new(),
+			parent: start,
+			inr:    start,
+			depth:  0,
+		});
+		Walk {
+			ufs: self,
+			opts,
+			pending,
+			visited: HashSet::new(),
+			error: None,
+		}
+	}
+
+	/// Resolve the symbolic link `inr`, which is in the directory `dir`, including nested links.
+	///
+	/// Fails with `ELOOP`, if more than 32 links are followed.
+	fn resolve_symlink(&self, dir: InodeNum, inr: InodeNum) -> IoResult<InodeNum> {
+		let mut cur = dir;
+		let mut todo = Vec::new();
+		let mut link = Some(inr);
+		let mut links = 0;
+		loop {
+			if let Some(inr) = link.take() {
+				links += 1;
+				if links > MAXSYMLINKS {
+					return Err(err!(ELOOP));
+				}
+				let target = self.symlink_read(inr)?;
+				if target.is_empty() {
+					return Err(err!(ENOENT));
+				}
+				if target.starts_with(b"/") {
+					cur = InodeNum::ROOT;
+				}
+				let names = target
+					.split(|&b| b == b'/')
+					.filter(|c| !c.is_empty() && *c != b".");
+				let names = names
+					.map(|c| OsStr::from_bytes(c).to_owned())
+					.collect::<Vec<_>>();
+				todo.extend(names.into_iter().rev());
+			}
+
+			let Some(name) = todo.pop() else {
+				return Ok(cur);
+			};
+			let next = self.dir_lookup(cur, &name)?;
+			if self.inode_attr(next)?.kind == InodeType::Symlink {
+				link = Some(next);
+			} else {
+				cur = next;
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod t {
+	use super::*;
+
+	fn inr(n: u32) -> InodeNum {
+		unsafe { InodeNum::new(n) }
+	}
+
+	#[test]
+	fn cycle() {
+		let mut g = TreeGuard::new();
+		g.enter(inr(2)).unwrap();
+		g.enter(inr(3)).unwrap();
+		let e = g.enter(inr(2)).unwrap_err();
+		assert_eq!(e.raw_os_error(), Some(libc::ELOOP));
+	}
+
+	#[test]
+	fn depth() {
+		let mut g = TreeGuard::with_max_depth(2);
+		g.enter(inr(2)).unwrap();
+		g.enter(inr(3)).unwrap();
+		assert_eq!(g.depth(), 2);
+		let e = g.enter(inr(4)).unwrap_err();
+		assert_eq!(e.raw_os_error(), Some(libc::ELOOP));
+		g.leave();
+		g.enter(inr(4)).unwrap();
+	}
+
+	#[test]
+	fn unbalanced() {
+		let mut g = TreeGuard::new();
+		g.leave();
+		assert_eq!(g.depth(), 0);
+		g.enter(inr(2)).unwrap();
+		g.leave();
+		g.leave();
+		assert_eq!(g.depth(), 0);
+	}
+}
@@ -1,9 +1,28 @@
 use super::*;
 use crate::InodeNum;
 
-impl<R: Read + Seek> Ufs<R> {
+/// Error of reading a missing extended attribute.
+#[cfg(any(target_os = "freebsd", target_os = "openbsd", target_os = "macos"))]
+pub(super) const ENOATTR: i32 = libc::ENOATTR;
+#[cfg(target_os = "linux")]
+pub(super) const ENOATTR: i32 = libc::ENODATA;
+
+impl<B: Backend> Ufs<B> {
+	/// Size of the extended attribute area of `ino`.
+	///
+	/// Corrupt inodes may claim more than fits into their `UFS_NXADDR` blocks,
+	/// so the size is clamped, and the rest is ignored.
+	fn extsize(&self, ino: &Inode) -> usize {
+		let max = UFS_NXADDR * self.superblock.bsize as usize;
+		let size = ino.extsize as usize;
+		if size > max {
+			log::warn!("extattr area of {size} bytes is larger than its blocks, using {max} bytes");
+		}
+		size.min(max)
+	}
+
 	fn iter_xattr<T>(
-		&mut self,
+		&self,
 		ino: &Inode,
 		mut f: impl FnMut(&ExtattrHeader, &OsStr, &[u8]) -> Option<T>,
 	) -> IoResult<Option<T>> {
@@ -11,25 +30,22 @@ impl<R: Read + Seek> Ufs<R> {
 			return Ok(None);
 		}
 
-		let fs = self.superblock.fsize as u64;
 		let bs = self.superblock.bsize as usize;
-		let sz = ino.extsize as usize;
-		assert!(sz < UFS_NXADDR * bs);
-
-		let mut blocks = vec![0u8; ino.extsize as usize];
+		let mut blocks = vec![0u8; self.extsize(ino)];
 		let mut nr = 0;
 		let mut blkidx = 0;
 
 		while nr < blocks.len() {
-			let pos = ino.extb[blkidx] as u64 * fs;
+			let pos = frag_addr(&self.geo, ino.extb[blkidx] as u64)?;
 			let num = bs.min(blocks.len() - nr);
-			self.file.read_at(pos, &mut blocks[nr..(nr + num)])?;
+			self.read_at(pos, &mut blocks[nr..(nr + num)])?;
 			blkidx += 1;
 			nr += num;
 		}
 
+		let size = blocks.len() as u64;
 		let file = Cursor::new(blocks);
-		let mut file = Decoder::new(file, self.file.config());
+		let mut file = Decoder::new(file, self.config);
 		let mut name = [0u8; 64];
 		let mut data = Vec::new();
 
@@ -49,10 +65,19 @@ impl<R: Read + Seek> Ufs<R> {
 
 			file.read(&mut name[0..namelen])?;
 			file.align_to(8)?;
-			let len = hdr.len as u64 - (file.pos()? - begin);
+			let pos = file.pos()?;
+			let len = (hdr.len as u64).checked_sub(pos - begin);
+			let Some(len) = len.filter(|&len| len <= size.saturating_sub(pos)) else {
+				log::error!("invalid extattr length: {}", hdr.len);
+				break;
+			};
 			data.resize(len as usize, 0u8);
 			file.read(&mut data)?;
-			data.resize(data.len() - hdr.contentpadlen as usize, 0u8);
+			let Some(len) = data.len().checked_sub(hdr.contentpadlen as usize) else {
+				log::error!("invalid extattr padding: {}", hdr.contentpadlen);
+				break;
+			};
+			data.truncate(len);
 
 			let name = OsStr::from_bytes(&name[0..namelen]);
 			if let Some(x) = f(&hdr, name, &data) {
@@ -64,16 +89,11 @@ impl<R: Read + Seek> Ufs<R> {
 	}
 
 	fn read_xattr<T>(
-		&mut self,
+		&self,
 		ino: &Inode,
 		xname: &OsStr,
 		mut f: impl FnMut(&ExtattrHeader, &[u8]) -> T,
 	) -> IoResult<T> {
-		#[cfg(any(target_os = "freebsd", target_os = "openbsd", target_os = "macos"))]
-		const ERR: i32 = libc::ENOATTR;
-		#[cfg(target_os = "linux")]
-		const ERR: i32 = libc::ENODATA;
-
 		self.iter_xattr(ino, |hdr, n, data| {
 			let ns = hdr.namespace()?;
 			if xname == ns.with_name(n) {
@@ -82,19 +102,46 @@ impl<R: Read + Seek> Ufs<R> {
 				None
 			}
 		})
-		.and_then(|r| r.ok_or(IoError::from_raw_os_error(ERR)))
+		.and_then(|r| r.ok_or(IoError::from_raw_os_error(ENOATTR)))
 	}
 
 	/// Get the size of the extended attribute area of inode `inr`.
-	pub fn xattr_list_len(&mut self, inr: InodeNum) -> IoResult<u32> {
+	///
+	/// This is an upper bound for the length of [`Ufs::xattr_list()`].
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::InodeNum;
+	///
+	/// # let ufs = example_image();
+	/// let inr = ufs.dir_lookup(InodeNum::ROOT, "xattrs".as_ref())?;
+	/// let len = ufs.xattr_list_len(inr)?;
+	/// assert!(len as usize >= ufs.xattr_list(inr)?.len());
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	pub fn xattr_list_len(&self, inr: InodeNum) -> IoResult<u32> {
 		let ino = self.read_inode(inr)?;
-		Ok(ino.extsize)
+		Ok(self.extsize(&ino) as u32)
 	}
 
 	/// Get the list of extended attribyte names.
 	/// Each entry follows the following format:
 	/// `"namespace.name\0"`
-	pub fn xattr_list(&mut self, inr: InodeNum) -> IoResult<Vec<u8>> {
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::InodeNum;
+	///
+	/// # let ufs = example_image();
+	/// let inr = ufs.dir_lookup(InodeNum::ROOT, "xattrs".as_ref())?;
+	/// assert_eq!(ufs.xattr_list(inr)?, b"user.test\0");
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	pub fn xattr_list(&self, inr: InodeNum) -> IoResult<Vec<u8>> {
 		let ino = self.read_inode(inr)?;
 		let mut data = OsString::new();
 		self.iter_xattr(&ino, |hdr, name, _data| {
@@ -108,14 +155,39 @@ impl<R: Read + Seek> Ufs<R> {
 	}
 
 	/// Get the size of an extended attribute.
-	pub fn xattr_len(&mut self, inr: InodeNum, name: &OsStr) -> IoResult<u32> {
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::InodeNum;
+	///
+	/// # let ufs = example_image();
+	/// let inr = ufs.dir_lookup(InodeNum::ROOT, "xattrs".as_ref())?;
+	/// assert_eq!(ufs.xattr_len(inr, "user.test".as_ref())?, 9);
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	pub fn xattr_len(&self, inr: InodeNum, name: &OsStr) -> IoResult<u32> {
 		let ino = self.read_inode(inr)?;
 		let len = self.read_xattr(&ino, name, |_hdr, data| data.len())?;
 		Ok(len as u32)
 	}
 
 	/// Read the value of an extended attribute.
-	pub fn xattr_read(&mut self, inr: InodeNum, name: &OsStr) -> IoResult<Vec<u8>> {
+	///
+	/// # Example
+	/// ```
+	/// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+	/// # use support::*;
+	/// use rufs::InodeNum;
+	///
+	/// # let ufs = example_image();
+	/// let inr = ufs.dir_lookup(InodeNum::ROOT, "xattrs".as_ref())?;
+	/// assert_eq!(ufs.xattr_read(inr, "user.test".as_ref())?, b"testvalue");
+	/// assert!(ufs.xattr_read(inr, "user.missing".as_ref()).is_err());
+	/// # Ok::<(), std::io::Error>(())
+	/// ```
+	pub fn xattr_read(&self, inr: InodeNum, name: &OsStr) -> IoResult<Vec<u8>> {
 		let ino = self.read_inode(inr)?;
 		let data = self.read_xattr(&ino, name, |_hdr, data| data.into())?;
 		Ok(data)
@@ -0,0 +1,339 @@
+//! Asynchronous I/O on Linux, using io_uring.
+use std::{
+	collections::{HashMap, VecDeque},
+	fs::File,
+	io::{Error as IoError, ErrorKind, Result as IoResult, Seek, SeekFrom},
+	mem,
+	os::fd::AsRawFd,
+	path::Path,
+	sync::{Mutex, MutexGuard, PoisonError},
+};
+
+use io_uring::{opcode, squeue, types, IoUring};
+
+use crate::{backend::lock_file, Backend};
+
+/// Number of entries of the submission queue, which also limits the number of queued prefetches.
+const DEPTH: u32 = 32;
+
+/// Largest prefetch, longer hints are cut short.
+const MAX_PREFETCH: usize = 128 * 1024;
+
+/// `user_data` of the request, for which a thread is waiting.
+const WAIT: u64 = u64::MAX;
+
+/// A prefetch, which was submitted, but didn't complete yet.
+struct Prefetch {
+	pos:   u64,
+	/// The kernel writes into this, so it must live until the prefetch completed.
+	buf:   Vec<u8>,
+	/// The range was written to in the meantime, so the data must be discarded.
+	stale: bool,
+}
+
+struct Ring {
+	uring:  IoUring,
+	fd:     types::Fd,
+	next:   u64,
+	/// Prefetches in flight, by their `user_data`.
+	queued: HashMap<u64, Prefetch>,
+	/// Completed prefetches, the oldest first.
+	ready:  VecDeque<(u64, Vec<u8>)>,
+	/// The buffer of the request, for which a thread is waiting, and its result, once it completed.
+	/// It is kept, if waiting fails, until the kernel is done with it.
+	wait:   Option<(Vec<u8>, Option<i32>)>,
+}
+
+impl Ring {
+	/// Move completed requests out of the completion queue.
+	fn reap(&mut self) {
+		let cqes: Vec<_> = self.uring.completion().collect();
+		for cqe in cqes {
+			let res = cqe.result();
+			if cqe.user_data() == WAIT {
+				if let Some((_, r)) = &mut self.wait {
+					*r = Some(res);
+				}
+				continue;
+			}
+			let Some(mut p) = self.queued.remove(&cqe.user_data()) else {
+				continue;
+			};
+			if res <= 0 || p.stale {
+				// Prefetches are only hints, so errors are ignored.
+				continue;
+			}
+			p.buf.truncate(res as usize);
+			if self.ready.len() >= DEPTH as usize {
+				self.ready.pop_front();
+			}
+			self.ready.push_back((p.pos, p.buf));
+		}
+	}
+
+	/// Submit all queued entries, and wait for at least one completion.
+	fn wait_any(&mut self) -> IoResult<()> {
+		loop {
+			match self.uring.submit_and_wait(1) {
+				Ok(_) => break,
+				Err(e) if e.kind() == ErrorKind::Interrupted => {}
+				Err(e) => return Err(e),
+			}
+		}
+		self.reap();
+		Ok(())
+	}
+
+	/// Add `entry` to the submission queue, which fails only, if it wasn't added.
+	fn push(&mut self, entry: &squeue::Entry) -> IoResult<()> {
+		// SAFETY: the buffers of all entries are owned by the ring, until they completed.
+		while unsafe { self.uring.submission().push(entry) }.is_err() {
+			self.uring.submit()?;
+		}
+		Ok(())
+	}
+
+	/// Submit the request built by `f` from a pointer to `buf`, and wait for it.
+	///
+	/// Returns the buffer, and the number of bytes, that were transferred.
+	fn run(
+		&mut self,
+		buf: Vec<u8>,
+		f: impl FnOnce(types::Fd, *mut u8, u32) -> squeue::Entry,
+	) -> IoResult<(Vec<u8>, usize)> {
+		// A previous request was abandoned, because waiting for it failed.
+		while matches!(self.wait, Some((_, None))) {
+			self.wait_any()?;
+		}
+
+		let len = u32::try_from(buf.len()).unwrap_or(u32::MAX);
+		let (buf, _) = self.wait.insert((buf, None));
+		let entry = f(self.fd, buf.as_mut_ptr(), len).user_data(WAIT);
+		if let Err(e) = self.push(&entry) {
+			self.wait = None;
+			return Err(e);
+		}
+		loop {
+			if let Some((_, Some(res))) = self.wait {
+				let (buf, _) = self.wait.take().unwrap();
+				if res < 0 {
+					return Err(IoError::from_raw_os_error(-res));
+				}
+				return Ok((buf, res as usize));
+			}
+			self.wait_any()?;
+		}
+	}
+
+	/// Copy the data of a completed prefetch, which covers `pos..(pos + buf.len())`, into `buf`.
+	fn take(&self, pos: u64, buf: &mut [u8]) -> bool {
+		let end = pos + buf.len() as u64;
+		let Some((p, data)) = self
+			.ready
+			.iter()
+			.find(|(p, data)| *p <= pos && end <= p + data.len() as u64)
+		else {
+			return false;
+		};
+		let off = (pos - p) as usize;
+		buf.copy_from_slice(&data[off..(off + buf.len())]);
+		true
+	}
+
+	/// Check whether a queued prefetch covers `pos..end`.
+	fn is_queued(&self, pos: u64, end: u64) -> bool {
+		self.queued
+			.values()
+			.any(|p| !p.stale && p.pos <= pos && end <= p.pos + p.buf.len() as u64)
+	}
+
+	/// Discard prefetched data, which overlaps with `pos..end`.
+	fn invalidate(&mut self, pos: u64, end: u64) {
+		self.ready
+			.retain(|(p, data)| end <= *p || *p + data.len() as u64 <= pos);
+		for p in self.queued.values_mut() {
+			if pos < p.pos + p.buf.len() as u64 && p.pos < end {
+				p.stale = true;
+			}
+		}
+	}
+}
+
+impl Drop for Ring {
+	fn drop(&mut self) {
+		// The kernel may still write into the buffers of requests in flight.
+		while !self.queued.is_empty() || matches!(self.wait, Some((_, None))) {
+			if let Err(e) = self.wait_any() {
+				log::error!("UringBackend: failed to wait for requests in flight: {e}");
+				mem::forget(mem::take(&mut self.queued));
+				mem::forget(self.wait.take());
+				break;
+			}
+		}
+	}
+}
+
+/// A file or device, which is accessed using io_uring, on Linux.
+///
+/// [`Backend::prefetch()`] queues reads, without waiting for them,
+/// and later reads of the same range are served from the completed prefetches,
+/// so readahead overlaps with the processing of the data, which was read before.
+/// Up to 32 prefetches of at most 128K are queued, and as many completed ones are kept,
+/// until they are replaced by newer ones, or written to.
+///
+/// The ring is shared by all threads, so only one thread at a time waits for a request.
+/// Unlike [`BlockFile`](crate::BlockFile), accesses aren't aligned,
+/// so `O_DIRECT` files don't work.
+#[doc(alias("IoUringBackend", "io_uring"))]
+pub struct UringBackend {
+	// The ring must be dropped first, because it waits for requests in flight.
+	ring: Mutex<Ring>,
+	file: File,
+}
+
+impl UringBackend {
+	/// Open the file or device at `path`, read-only.
+	pub fn open(path: &Path) -> IoResult<Self> {
+		Self::new(File::open(path)?)
+	}
+
+	/// Use `file`, which may be a device.
+	///
+	/// Writes fail with `EBADF`, unless `file` was opened for writing.
+	/// Fails with `ENOSYS` or `EPERM`, if io_uring isn't available, like in some containers.
+	pub fn new(file: File) -> IoResult<Self> {
+		let uring = IoUring::new(DEPTH)?;
+		let ring = Ring {
+			uring,
+			fd: types::Fd(file.as_raw_fd()),
+			next: 0,
+			queued: HashMap::new(),
+			ready: VecDeque::new(),
+			wait: None,
+		};
+		Ok(Self {
+			ring: Mutex::new(ring),
+			file,
+		})
+	}
+
+	/// Take an advisory lock on the file, like [`BlockFile::lock()`](crate::BlockFile::lock).
+	#[doc(alias = "flock")]
+	pub fn lock(&self, exclusive: bool) -> IoResult<()> {
+		lock_file(&self.file, exclusive)
+	}
+
+	/// Get the underlying file.
+	pub fn get_ref(&self) -> &File {
+		&self.file
+	}
+
+	fn ring(&self) -> MutexGuard<'_, Ring> {
+		self.ring.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+}
+
+impl Backend for UringBackend {
+	fn read_at(&self, pos: u64, buf: &mut [u8]) -> IoResult<()> {
+		let mut ring = self.ring();
+		let end = pos + buf.len() as u64;
+		loop {
+			ring.reap();
+			if ring.take(pos, buf) {
+				return Ok(());
+			}
+			if !ring.is_queued(pos, end) {
+				break;
+			}
+			ring.wait_any()?;
+		}
+
+		let mut done = 0;
+		while done < buf.len() {
+			let p = pos + done as u64;
+			let (data, n) = ring.run(vec![0u8; buf.len() - done], |fd, ptr, len| {
+				opcode::Read::new(fd, ptr, len).offset(p).build()
+			})?;
+			if n == 0 {
+				return Err(IoError::from(ErrorKind::UnexpectedEof));
+			}
+			buf[done..(done + n)].copy_from_slice(&data[..n]);
+			done += n;
+		}
+		Ok(())
+	}
+
+	fn write_at(&self, pos: u64, buf: &[u8]) -> IoResult<()> {
+		let mut ring = self.ring();
+		ring.invalidate(pos, pos + buf.len() as u64);
+
+		let mut done = 0;
+		while done < buf.len() {
+			let p = pos + done as u64;
+			let (_, n) = ring.run(buf[done..].to_vec(), |fd, ptr, len| {
+				opcode::Write::new(fd, ptr, len).offset(p).build()
+			})?;
+			if n == 0 {
+				return Err(IoError::from(ErrorKind::WriteZero));
+			}
+			done += n;
+		}
+		Ok(())
+	}
+
+	/// Queue a read of up to 128K, unless the range is already prefetched,
+	/// or too many prefetches are queued.
+	fn prefetch(&self, pos: u64, len: usize) -> IoResult<()> {
+		let len = len.min(MAX_PREFETCH);
+		let end = pos + len as u64;
+		let mut ring = self.ring();
+		ring.reap();
+		let prefetched = ring
+			.ready
+			.iter()
+			.any(|(p, data)| *p <= pos && end <= p + data.len() as u64);
+		if len == 0 || prefetched || ring.is_queued(pos, end) || ring.queued.len() >= DEPTH as usize
+		{
+			return Ok(());
+		}
+
+		let id = ring.next;
+		ring.next += 1;
+		let mut buf = vec![0u8; len];
+		let entry = opcode::Read::new(ring.fd, buf.as_mut_ptr(), len as u32)
+			.offset(pos)
+			.build()
+			.user_data(id);
+		// The buffer doesn't move, when the Vec is moved.
+		ring.queued.insert(
+			id,
+			Prefetch {
+				pos,
+				buf,
+				stale: false,
+			},
+		);
+		if let Err(e) = ring.push(&entry) {
+			ring.queued.remove(&id);
+			return Err(e);
+		}
+		ring.uring.submit()?;
+		Ok(())
+	}
+
+	fn sync(&self) -> IoResult<()> {
+		self.ring()
+			.run(Vec::new(), |fd, _, _| {
+				opcode::Fsync::new(fd)
+					.flags(types::FsyncFlags::DATASYNC)
+					.build()
+			})
+			.map(drop)
+	}
+
+	fn size(&self) -> IoResult<Option<u64>> {
+		// Like `BlockFile`, seeking works for devices as well.
+		let size = (&self.file).seek(SeekFrom::End(0))?;
+		Ok((size > 0).then_some(size))
+	}
+}
@@ -0,0 +1,235 @@
+//! Reading zstd-compressed images, preferably in the seekable format.
+use std::{
+	io::{Error as IoError, ErrorKind, Read, Result as IoResult},
+	sync::{Mutex, PoisonError},
+};
+
+use ruzstd::StreamingDecoder;
+
+use crate::{err, Backend};
+
+/// Magic number at the start of every zstd frame.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Magic number of the skippable frame, which holds the seek table.
+const SKIPPABLE_MAGIC: u32 = 0x184d_2a5e;
+
+/// Magic number at the end of the seek table.
+const SEEKABLE_MAGIC: u32 = 0x8f92_eab1;
+
+/// Size of the seek table footer: number of frames, descriptor and magic number.
+const FOOTER_SIZE: u64 = 9;
+
+/// Flag in the seek table descriptor, which marks that entries contain a checksum.
+const CHECKSUM_FLAG: u8 = 0x80;
+
+/// Upper limit for the size of the seek table.
+const MAX_SEEK_TABLE: u64 = 64 << 20;
+
+/// Upper limit for the decompressed size of a single frame.
+const MAX_FRAME: u64 = 1 << 30;
+
+/// A frame of compressed data.
+#[derive(Debug)]
+struct Frame {
+	/// Offset of the compressed frame.
+	cpos: u64,
+	/// Size of the compressed frame.
+	clen: u64,
+	/// Offset of the decompressed data.
+	dpos: u64,
+	/// Size of the decompressed data.
+	dlen: u64,
+}
+
+/// A read-only backend for a zstd-compressed image.
+///
+/// Images in the [seekable format] are decompressed one frame at a time, when they are read,
+/// so only a single decompressed frame is held in memory.
+/// Other images are decompressed into memory entirely, when they are opened.
+///
+/// [seekable format]: https://github.com/facebook/zstd/blob/dev/contrib/seekable_format/zstd_seekable_compression_format.md
+pub struct ZstdBackend<B: Backend> {
+	inner:  B,
+	frames: Vec<Frame>,
+	/// The most recently decompressed frame.
+	last:   Mutex<Option<(usize, Vec<u8>)>>,
+}
+
+fn le32(buf: &[u8], off: usize) -> u32 {
+	u32::from_le_bytes(buf[off..(off + 4)].try_into().unwrap())
+}
+
+fn invalid(e: impl std::fmt::Display) -> IoError {
+	log::error!("zstd: {e}");
+	IoError::new(ErrorKind::InvalidData, e.to_string())
+}
+
+/// Decompress all frames in `data`, but not more than `limit` bytes.
+fn decompress(mut data: &[u8], limit: u64) -> IoResult<Vec<u8>> {
+	let mut out = Vec::new();
+	while !data.is_empty() {
+		// Skippable frames, like a seek table, end the data.
+		if data.len() >= 8 && le32(data, 0) & 0xffff_fff0 == SKIPPABLE_MAGIC & 0xffff_fff0 {
+			break;
+		}
+		let dec = StreamingDecoder::new(&mut data).map_err(invalid)?;
+		let left = limit.saturating_add(1).saturating_sub(out.len() as u64);
+		dec.take(left).read_to_end(&mut out)?;
+		if out.len() as u64 > limit {
+			return Err(invalid("decompressed data is too large"));
+		}
+	}
+	Ok(out)
+}
+
+impl<B: Backend> ZstdBackend<B> {
+	/// Check whether `inner` starts with a zstd frame.
+	pub fn detect(inner: &B) -> IoResult<bool> {
+		let mut magic = [0u8; 4];
+		match inner.read_at(0, &mut magic) {
+			Ok(()) => Ok(magic == ZSTD_MAGIC),
+			Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(false),
+			Err(e) => Err(e),
+		}
+	}
+
+	/// Open the compressed image in `inner`.
+	///
+	/// Fails with `EINVAL`, if the size of `inner` is unknown.
+	pub fn new(inner: B) -> IoResult<Self> {
+		let Some(size) = inner.size()? else {
+			log::error!("zstd: the size of the compressed image is unknown");
+			return Err(err!(EINVAL));
+		};
+
+		let frames = match Self::seek_table(&inner, size)? {
+			Some(frames) => frames,
+			None => {
+				log::warn!("zstd: image isn't seekable, decompressing it into memory");
+				let mut data = vec![0u8; size as usize];
+				inner.read_at(0, &mut data)?;
+				let data = decompress(&data, u64::MAX)?;
+				let frame = Frame {
+					cpos: 0,
+					clen: size,
+					dpos: 0,
+					dlen: data.len() as u64,
+				};
+				return Ok(Self {
+					inner,
+					frames: vec![frame],
+					last: Mutex::new(Some((0, data))),
+				});
+			}
+		};
+
+		log::debug!("zstd: {} seekable frames", frames.len());
+		Ok(Self {
+			inner,
+			frames,
+			last: Mutex::new(None),
+		})
+	}
+
+	/// Parse the seek table at the end of the image, if there is one.
+	fn seek_table(inner: &B, size: u64) -> IoResult<Option<Vec<Frame>>> {
+		if size < FOOTER_SIZE + 8 {
+			return Ok(None);
+		}
+		let mut footer = [0u8; FOOTER_SIZE as usize];
+		inner.read_at(size - FOOTER_SIZE, &mut footer)?;
+		if le32(&footer, 5) != SEEKABLE_MAGIC {
+			return Ok(None);
+		}
+
+		let num = le32(&footer, 0) as u64;
+		let desc = footer[4];
+		if desc & !CHECKSUM_FLAG != 0 {
+			return Err(invalid(format!("invalid seek table descriptor: {desc:#x}")));
+		}
+		let esize: u64 = if desc & CHECKSUM_FLAG != 0 { 12 } else { 8 };
+		let tsize = num * esize + FOOTER_SIZE;
+		if tsize > MAX_SEEK_TABLE || tsize + 8 > size {
+			return Err(invalid(format!("invalid number of frames: {num}")));
+		}
+
+		let mut table = vec![0u8; (tsize + 8) as usize];
+		inner.read_at(size - tsize - 8, &mut table)?;
+		if le32(&table, 0) != SKIPPABLE_MAGIC || le32(&table, 4) as u64 != tsize {
+			return Err(invalid("invalid seek table frame"));
+		}
+
+		let (mut cpos, mut dpos) = (0, 0);
+		let mut frames = Vec::with_capacity(num as usize);
+		for e in table[8..].chunks_exact(esize as usize).take(num as usize) {
+			let (clen, dlen) = (le32(e, 0) as u64, le32(e, 4) as u64);
+			frames.push(Frame {
+				cpos,
+				clen,
+				dpos,
+				dlen,
+			});
+			cpos += clen;
+			dpos += dlen;
+		}
+		if cpos > size - tsize - 8 {
+			return Err(invalid("seek table refers beyond the end of the image"));
+		}
+		Ok(Some(frames))
+	}
+
+	/// Decompress frame `idx`, if it wasn't the last one, and call `f` with its data.
+	fn with_frame<T>(&self, idx: usize, f: impl FnOnce(&[u8]) -> T) -> IoResult<T> {
+		// The frame is replaced before it is used, so a poisoned lock is harmless.
+		let mut last = self.last.lock().unwrap_or_else(PoisonError::into_inner);
+		if let Some((i, data)) = &*last {
+			if *i == idx {
+				return Ok(f(data));
+			}
+		}
+
+		let frame = &self.frames[idx];
+		log::trace!("zstd: decompressing frame {idx}: {frame:?}");
+		let mut buf = vec![0u8; frame.clen as usize];
+		self.inner.read_at(frame.cpos, &mut buf)?;
+		let data = decompress(&buf, frame.dlen.min(MAX_FRAME))?;
+		if data.len() as u64 != frame.dlen {
+			return Err(invalid(format!(
+				"frame {idx} has a size of {}, instead of {}",
+				data.len(),
+				frame.dlen
+			)));
+		}
+		Ok(f(&last.insert((idx, data)).1))
+	}
+}
+
+impl<B: Backend> Backend for ZstdBackend<B> {
+	fn read_at(&self, pos: u64, buf: &mut [u8]) -> IoResult<()> {
+		let end = pos + buf.len() as u64;
+		let size = self.frames.last().map_or(0, |f| f.dpos + f.dlen);
+		if end > size {
+			return Err(IoError::from(ErrorKind::UnexpectedEof));
+		}
+
+		let mut idx = self.frames.partition_point(|f| f.dpos + f.dlen <= pos);
+		let mut pos = pos;
+		while pos < end {
+			let frame = &self.frames[idx];
+			let off = (pos - frame.dpos) as usize;
+			let num = (frame.dlen - off as u64).min(end - pos) as usize;
+			let boff = buf.len() - (end - pos) as usize;
+			self.with_frame(idx, |data| {
+				buf[boff..(boff + num)].copy_from_slice(&data[off..(off + num)])
+			})?;
+			pos += num as u64;
+			idx += 1;
+		}
+		Ok(())
+	}
+
+	fn size(&self) -> IoResult<Option<u64>> {
+		Ok(Some(self.frames.last().map_or(0, |f| f.dpos + f.dlen)))
+	}
+}
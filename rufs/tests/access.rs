@@ -0,0 +1,86 @@
+//! Permission checks, like access(2).
+mod support;
+
+use std::io::Cursor;
+
+use libc::{F_OK, R_OK, W_OK, X_OK};
+use rufs::{Credentials, InodeNum, SeekBackend, Ufs, UF_IMMUTABLE};
+use support::*;
+
+/// The little-endian golden image, in which "file1" has the mode `mode`,
+/// is owned by 1001:1002, and has the flags `flags`.
+fn open(mode: u16, flags: u32) -> (MemUfs, InodeNum) {
+	let mut img = golden_image("ufs-little");
+	// "file1" is inode 4.
+	let file1 = inr(4);
+	let off = inode_offset(file1);
+	let old = u16::from_le_bytes(img[off..(off + 2)].try_into().unwrap());
+	let mode = (old & !0o7777) | mode;
+	patch_inode(&mut img, file1, DI_MODE, &mode.to_le_bytes());
+	patch_inode(&mut img, file1, DI_UID, &1001u32.to_le_bytes());
+	patch_inode(&mut img, file1, DI_GID, &1002u32.to_le_bytes());
+	patch_inode(&mut img, file1, DI_FLAGS, &flags.to_le_bytes());
+	let ufs = Ufs::new(SeekBackend::new(Cursor::new(img))).unwrap();
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+	(ufs, inr)
+}
+
+fn errno<T>(res: std::io::Result<T>) -> Option<i32> {
+	res.err().and_then(|e| e.raw_os_error())
+}
+
+#[test]
+fn mode() {
+	let (ufs, inr) = open(0o640, 0);
+	let owner = Credentials::new(1001, 100);
+	let group = Credentials::new(2000, 1002);
+	let other = Credentials::new(2000, 2000);
+
+	ufs.access(inr, &owner, R_OK | W_OK).unwrap();
+	assert_eq!(errno(ufs.access(inr, &owner, X_OK)), Some(libc::EACCES));
+	ufs.access(inr, &group, R_OK).unwrap();
+	assert_eq!(errno(ufs.access(inr, &group, W_OK)), Some(libc::EACCES));
+	assert_eq!(errno(ufs.access(inr, &other, R_OK)), Some(libc::EACCES));
+	ufs.access(inr, &other, F_OK).unwrap();
+	assert_eq!(errno(ufs.access(inr, &other, 0o10)), Some(libc::EINVAL));
+}
+
+/// The owner's bits apply to the owner, even if the group's bits grant more.
+#[test]
+fn owner_precedence() {
+	let (ufs, inr) = open(0o070, 0);
+	let owner = Credentials::new(1001, 1002);
+	assert_eq!(errno(ufs.access(inr, &owner, R_OK)), Some(libc::EACCES));
+}
+
+#[test]
+fn supplementary_groups() {
+	let (ufs, inr) = open(0o640, 0);
+	let mut cred = Credentials::new(2000, 2000);
+	assert_eq!(errno(ufs.access(inr, &cred, R_OK)), Some(libc::EACCES));
+	cred.groups = vec![5, 1002];
+	ufs.access(inr, &cred, R_OK).unwrap();
+}
+
+/// The superuser may only execute files, which have an execute bit set.
+#[test]
+fn root() {
+	let root = Credentials::new(0, 0);
+	let (ufs, inr) = open(0o000, 0);
+	ufs.access(inr, &root, R_OK | W_OK).unwrap();
+	assert_eq!(errno(ufs.access(inr, &root, X_OK)), Some(libc::EACCES));
+	ufs.access(InodeNum::ROOT, &root, X_OK).unwrap();
+
+	let (ufs, inr) = open(0o001, 0);
+	ufs.access(inr, &root, X_OK).unwrap();
+}
+
+#[test]
+fn immutable() {
+	let (ufs, inr) = open(0o666, UF_IMMUTABLE);
+	let cred = Credentials::new(1001, 1002);
+	ufs.access(inr, &cred, R_OK).unwrap();
+	assert_eq!(errno(ufs.access(inr, &cred, W_OK)), Some(libc::EPERM));
+	let root = Credentials::new(0, 0);
+	assert_eq!(errno(ufs.access(inr, &root, W_OK)), Some(libc::EPERM));
+}
@@ -0,0 +1,153 @@
+//! POSIX.1e ACLs, which are stored in extended attributes.
+mod support;
+
+use std::io::Cursor;
+
+use rufs::{AclEntry, AclTag, Credentials, InodeNum, SeekBackend, Ufs};
+use support::*;
+
+/// Offset of `fs_flags` in the primary superblock.
+const FLAGS: usize = 65536 + 1312;
+
+/// POSIX.1e ACLs are enabled.
+const FS_ACLS: i32 = 0x0010;
+
+/// Encode a `struct oldacl` of little-endian `(tag, id, perm)` entries.
+fn encode(entries: &[(u32, u32, u16)]) -> Vec<u8> {
+	let mut data = vec![0u8; 388];
+	data[0..4].copy_from_slice(&(entries.len() as i32).to_le_bytes());
+	for (i, &(tag, id, perm)) in entries.iter().enumerate() {
+		let pos = 4 + i * 12;
+		data[pos..(pos + 4)].copy_from_slice(&tag.to_le_bytes());
+		data[(pos + 4)..(pos + 8)].copy_from_slice(&id.to_le_bytes());
+		data[(pos + 8)..(pos + 10)].copy_from_slice(&perm.to_le_bytes());
+	}
+	data
+}
+
+/// The little-endian golden image, in which the extended attribute of "xattrs" was replaced
+/// by the access ACL `acl`, and the mode by `mode`.
+fn with_acl(acls: bool, mode: u16, acl: &[u8]) -> (Vec<u8>, InodeNum) {
+	let mut img = golden_image("ufs-little");
+	let inr = open_golden("ufs-little")
+		.dir_lookup(InodeNum::ROOT, "xattrs".as_ref())
+		.unwrap();
+
+	if acls {
+		let flags = i32::from_le_bytes(img[FLAGS..(FLAGS + 4)].try_into().unwrap()) | FS_ACLS;
+		img[FLAGS..(FLAGS + 4)].copy_from_slice(&flags.to_le_bytes());
+	}
+
+	let ino = inode_offset(inr);
+	patch_inode(&mut img, inr, DI_MODE, &mode.to_le_bytes());
+	let extb = &img[(ino + DI_EXTB)..(ino + DI_EXTB + 8)];
+	let extb = i64::from_le_bytes(extb.try_into().unwrap()) as usize;
+
+	// struct extattr: length, namespace (system), padding of the content, length of the name
+	let name = b"posix1e.acl_access";
+	let pad = acl.len().next_multiple_of(8) - acl.len();
+	let len = 32 + acl.len() + pad;
+	let mut rec = vec![0u8; len];
+	rec[0..4].copy_from_slice(&(len as u32).to_le_bytes());
+	rec[4] = 2;
+	rec[5] = pad as u8;
+	rec[6] = name.len() as u8;
+	rec[7..(7 + name.len())].copy_from_slice(name);
+	rec[32..(32 + acl.len())].copy_from_slice(acl);
+
+	let pos = extb * FSIZE;
+	img[pos..(pos + len)].copy_from_slice(&rec);
+	patch_inode(&mut img, inr, DI_EXTSIZE, &(len as u32).to_le_bytes());
+	(img, inr)
+}
+
+fn open(img: Vec<u8>) -> MemUfs {
+	Ufs::new(SeekBackend::new(Cursor::new(img))).unwrap()
+}
+
+#[test]
+fn access() {
+	let data = encode(&[
+		(0x01, u32::MAX, 7),
+		(0x02, 1001, 6),
+		(0x04, u32::MAX, 4),
+		(0x10, u32::MAX, 7),
+		(0x20, u32::MAX, 4),
+	]);
+	let (img, inr) = with_acl(true, 0o100640, &data);
+	let ufs = open(img);
+	let acl = ufs.acl(inr).unwrap().unwrap();
+
+	// The mode takes precedence, and the group bits are the mask.
+	let entry = |tag, perm| AclEntry { tag, perm };
+	assert_eq!(
+		acl.entries,
+		[
+			entry(AclTag::UserObj, 6),
+			entry(AclTag::User(1001), 6),
+			entry(AclTag::GroupObj, 4),
+			entry(AclTag::Mask, 4),
+			entry(AclTag::Other, 0),
+		]
+	);
+	assert_eq!(ufs.default_acl(inr).unwrap(), None);
+	assert_eq!(ufs.xattr_list(inr).unwrap(), b"system.posix1e.acl_access\0");
+}
+
+/// The extended attributes are ignored, unless the filesystem has ACLs enabled.
+#[test]
+fn disabled() {
+	let data = encode(&[
+		(0x01, u32::MAX, 7),
+		(0x04, u32::MAX, 5),
+		(0x20, u32::MAX, 5),
+	]);
+	let (img, inr) = with_acl(false, 0o100644, &data);
+	let ufs = open(img);
+	assert_eq!(ufs.acl(inr).unwrap(), None);
+}
+
+#[test]
+fn missing() {
+	let (img, _) = with_acl(true, 0o100644, &encode(&[]));
+	let ufs = open(img);
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+	assert_eq!(ufs.acl(inr).unwrap(), None);
+	assert_eq!(ufs.default_acl(InodeNum::ROOT).unwrap(), None);
+}
+
+#[test]
+fn corrupt() {
+	let (img, inr) = with_acl(true, 0o100644, &[0u8; 100]);
+	let ufs = open(img);
+	let e = ufs.acl(inr).unwrap_err();
+	assert_eq!(rufs::errno(&e), libc::EIO);
+}
+
+/// Named users and groups are limited by the mask, which are the group bits of the mode.
+#[test]
+fn check_access() {
+	let data = encode(&[
+		(0x01, u32::MAX, 7),
+		(0x02, 1001, 7),
+		(0x04, u32::MAX, 0),
+		(0x08, 2000, 4),
+		(0x10, u32::MAX, 7),
+		(0x20, u32::MAX, 0),
+	]);
+	let (img, inr) = with_acl(true, 0o100650, &data);
+	let ufs = open(img);
+
+	let user = Credentials::new(1001, 1001);
+	ufs.access(inr, &user, libc::R_OK | libc::X_OK).unwrap();
+	let e = ufs.access(inr, &user, libc::W_OK).unwrap_err();
+	assert_eq!(e.raw_os_error(), Some(libc::EACCES));
+
+	let mut member = Credentials::new(3000, 3000);
+	let e = ufs.access(inr, &member, libc::R_OK).unwrap_err();
+	assert_eq!(e.raw_os_error(), Some(libc::EACCES));
+	member.groups.push(2000);
+	ufs.access(inr, &member, libc::R_OK).unwrap();
+	let e = ufs.access(inr, &member, libc::X_OK).unwrap_err();
+	assert_eq!(e.raw_os_error(), Some(libc::EACCES));
+}
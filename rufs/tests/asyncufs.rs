@@ -0,0 +1,136 @@
+//! Check that [`AsyncUfs`] sees exactly the same filesystem as [`Ufs`].
+#![cfg(feature = "tokio")]
+
+mod support;
+
+use std::{ffi::OsString, io::Cursor};
+
+use rufs::{AsyncUfs, InodeNum, InodeType, TreeGuard};
+use support::*;
+
+type MemAsyncUfs = AsyncUfs<Cursor<Vec<u8>>>;
+
+fn entries(ufs: &MemUfs, inr: InodeNum) -> Vec<(OsString, InodeNum, InodeType)> {
+	let mut v = Vec::new();
+	ufs.dir_iter(inr, |name, inr, kind| {
+		v.push((name.to_owned(), inr, kind));
+		None::<()>
+	})
+	.unwrap();
+	v
+}
+
+async fn async_entries(ufs: &MemAsyncUfs, inr: InodeNum) -> Vec<(OsString, InodeNum, InodeType)> {
+	let mut v = Vec::new();
+	ufs.dir_iter(inr, |name, inr, kind| {
+		v.push((name.to_owned(), inr, kind));
+		None::<()>
+	})
+	.await
+	.unwrap();
+	v
+}
+
+/// Walk the whole filesystem, and compare everything along the way.
+async fn compare(sync: &MemUfs, aufs: &MemAsyncUfs) {
+	let mut g = TreeGuard::new();
+	let mut dirs = vec![InodeNum::ROOT];
+
+	while let Some(dir) = dirs.pop() {
+		g.enter(dir).unwrap();
+		let ents = entries(sync, dir);
+		assert_eq!(ents, async_entries(aufs, dir).await);
+
+		for (name, inr, kind) in ents {
+			if name == "." || name == ".." {
+				continue;
+			}
+
+			let attr = sync.inode_attr(inr).unwrap();
+			let aattr = aufs.inode_attr(inr).await.unwrap();
+			assert_eq!(format!("{attr:?}"), format!("{aattr:?}"), "{name:?}");
+
+			match kind {
+				InodeType::Directory => dirs.push(inr),
+				InodeType::Symlink => {
+					assert_eq!(
+						sync.symlink_read(inr).unwrap(),
+						aufs.symlink_read(inr).await.unwrap()
+					)
+				}
+				InodeType::RegularFile => {
+					// Compare the end of the file, so that the indirect blocks of the sparse files are used.
+					let len = attr.size.min(1 << 16);
+					let off = attr.size - len;
+					let mut buf = vec![0u8; len as usize];
+					let mut abuf = vec![0u8; len as usize];
+					sync.inode_read(inr, off, &mut buf).unwrap();
+					aufs.inode_read(inr, off, &mut abuf).await.unwrap();
+					assert!(buf == abuf, "{name:?}: contents differ");
+				}
+				_ => {}
+			}
+		}
+	}
+}
+
+async fn same_as_sync(image: &str) {
+	let sync = open_golden(image);
+	let aufs = AsyncUfs::new(Cursor::new(golden_image(image)))
+		.await
+		.unwrap();
+	assert_eq!(format!("{:?}", sync.info()), format!("{:?}", aufs.info()));
+	compare(&sync, &aufs).await;
+}
+
+#[tokio::test]
+async fn same_as_sync_little() {
+	same_as_sync("ufs-little").await;
+}
+
+#[tokio::test]
+async fn same_as_sync_big() {
+	same_as_sync("ufs-big").await;
+}
+
+#[tokio::test]
+async fn lookup() {
+	let ufs = AsyncUfs::new(Cursor::new(golden_image("ufs-big")))
+		.await
+		.unwrap();
+	let inr = ufs
+		.dir_lookup(InodeNum::ROOT, "link1".as_ref())
+		.await
+		.unwrap();
+	assert_eq!(
+		ufs.symlink_read(inr).await.unwrap(),
+		b"dir1/dir2/dir3/file2"
+	);
+
+	let err = ufs
+		.dir_lookup(InodeNum::ROOT, "nonexistent".as_ref())
+		.await
+		.unwrap_err();
+	assert_eq!(err.raw_os_error(), Some(libc::ENOENT));
+}
+
+#[tokio::test]
+async fn eof() {
+	let ufs = AsyncUfs::new(Cursor::new(golden_image("ufs-little")))
+		.await
+		.unwrap();
+	let inr = ufs
+		.dir_lookup(InodeNum::ROOT, "file1".as_ref())
+		.await
+		.unwrap();
+	let mut buf = vec![0u8; 1 << 20];
+	assert_eq!(ufs.inode_read(inr, 0, &mut buf).await.unwrap(), 23);
+	assert_eq!(ufs.inode_read(inr, 1 << 40, &mut buf).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn garbage() {
+	assert!(AsyncUfs::new(Cursor::new(vec![0u8; 1 << 20]))
+		.await
+		.is_err());
+}
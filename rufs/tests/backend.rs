@@ -0,0 +1,154 @@
+//! Check that all backends see the same filesystem.
+mod support;
+
+use std::{
+	fs,
+	io::{ErrorKind, Result as IoResult, Write},
+	path::PathBuf,
+	sync::atomic::{AtomicU64, Ordering},
+};
+
+use rufs::{Backend, BlockFile, BlockReader, InodeNum, Options, Ufs, WindowedBackend};
+use support::*;
+
+fn open_file(name: &str, bs: u64) -> Ufs<BlockFile> {
+	let mut f = tempfile::tempfile().unwrap();
+	f.write_all(&golden_image(name)).unwrap();
+	Ufs::new(BlockFile::new(f, bs)).unwrap()
+}
+
+#[test]
+fn block_file() {
+	for bs in [1, 512, 4096, 65536] {
+		let ufs = open_file("ufs-big", bs);
+		let mem = open_golden("ufs-big");
+
+		let inr = ufs.dir_lookup(InodeNum::ROOT, "file3".as_ref()).unwrap();
+		let size = ufs.inode_attr(inr).unwrap().size as usize;
+		let mut buf = vec![0u8; size];
+		let mut expected = vec![0u8; size];
+		// start at an odd offset, so that none of the reads are aligned
+		ufs.inode_read(inr, 7, &mut buf[7..]).unwrap();
+		mem.inode_read(inr, 7, &mut expected[7..]).unwrap();
+		assert!(buf == expected, "bs={bs}");
+
+		let inr = ufs.dir_lookup(InodeNum::ROOT, "link1".as_ref()).unwrap();
+		assert_eq!(ufs.symlink_read(inr).unwrap(), b"dir1/dir2/dir3/file2");
+	}
+}
+
+/// A filesystem embedded at an unaligned offset within a larger image.
+#[test]
+fn windowed() {
+	let img = golden_image("ufs-little");
+	let offset = 12345;
+	let mut f = tempfile::tempfile().unwrap();
+	f.write_all(&vec![0xffu8; offset]).unwrap();
+	f.write_all(&img).unwrap();
+	f.write_all(&[0xffu8; 4096]).unwrap();
+
+	let backend = WindowedBackend::new(
+		BlockFile::new(f, 512),
+		offset as u64,
+		Some(img.len() as u64),
+	);
+	let ufs = Ufs::new(backend).unwrap();
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "link1".as_ref()).unwrap();
+	assert_eq!(ufs.symlink_read(inr).unwrap(), b"dir1/dir2/dir3/file2");
+	assert_eq!(ufs.stats().missing_bytes, 0);
+}
+
+/// Bypassing the buffer cache, which requires aligned buffers.
+#[test]
+fn direct() {
+	let mut path = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+	path.push("direct.img");
+	fs::write(&path, golden_image("ufs-big")).unwrap();
+
+	let bf = match BlockFile::open_direct(&path) {
+		Ok(bf) => bf,
+		Err(e) if e.raw_os_error() == Some(libc::EINVAL) => {
+			eprintln!("O_DIRECT isn't supported by the filesystem of {path:?}");
+			return;
+		}
+		Err(e) => panic!("{e}"),
+	};
+	assert!(bf.is_direct());
+	let ufs = Ufs::new(bf).unwrap();
+	let mem = open_golden("ufs-big");
+
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "file3".as_ref()).unwrap();
+	let size = ufs.inode_attr(inr).unwrap().size as usize;
+	let mut buf = vec![0u8; size];
+	let mut expected = vec![0u8; size];
+	ufs.inode_read(inr, 7, &mut buf[7..]).unwrap();
+	mem.inode_read(inr, 7, &mut expected[7..]).unwrap();
+	assert!(buf == expected);
+}
+
+/// Exclusive locks conflict with any other lock, shared ones only with exclusive ones.
+#[test]
+fn lock() {
+	let f = tempfile::NamedTempFile::new().unwrap();
+	let open = || BlockFile::open(f.path()).unwrap();
+	let (a, b) = (open(), open());
+
+	a.lock(false).unwrap();
+	b.lock(false).unwrap();
+	let e = open().lock(true).unwrap_err();
+	assert_eq!(e.kind(), ErrorKind::WouldBlock);
+	drop((a, b));
+
+	let a = open();
+	a.lock(true).unwrap();
+	let e = open().lock(false).unwrap_err();
+	assert_eq!(e.kind(), ErrorKind::WouldBlock);
+	let e = BlockReader::open(f.path()).err().unwrap();
+	assert_eq!(e.kind(), ErrorKind::WouldBlock);
+	drop(a);
+	open().lock(true).unwrap();
+}
+
+/// Records the hint of [`Backend::set_io_size()`].
+struct IoSize<'a> {
+	inner: &'a BlockFile,
+	size:  &'a AtomicU64,
+}
+
+impl Backend for IoSize<'_> {
+	fn read_at(&self, pos: u64, buf: &mut [u8]) -> IoResult<()> {
+		self.inner.read_at(pos, buf)
+	}
+
+	fn set_io_size(&self, size: u64) {
+		self.size.store(size, Ordering::Relaxed);
+		self.inner.set_io_size(size);
+	}
+}
+
+/// Regular files are read in blocks of the fragment size, instead of their `st_blksize`,
+/// unless another size is chosen.
+#[test]
+fn io_size() {
+	for (io_size, expected) in [(None, 4096), (Some(32768), 32768)] {
+		let mut f = tempfile::tempfile().unwrap();
+		f.write_all(&golden_image("ufs-little")).unwrap();
+		let bf = BlockFile::from_file(f).unwrap();
+		assert_eq!(bf.blksize(), 1);
+
+		let size = AtomicU64::new(0);
+		let backend = IoSize {
+			inner: &bf,
+			size:  &size,
+		};
+		let opts = Options {
+			io_size,
+			..Options::default()
+		};
+		let ufs = Ufs::with_options(backend, opts).unwrap();
+		let inr = ufs.dir_lookup(InodeNum::ROOT, "link1".as_ref()).unwrap();
+		assert_eq!(ufs.symlink_read(inr).unwrap(), b"dir1/dir2/dir3/file2");
+		assert_eq!(size.load(Ordering::Relaxed), expected);
+		assert_eq!(bf.blksize(), expected);
+	}
+}
@@ -0,0 +1,169 @@
+//! Directories, which span many blocks.
+mod support;
+
+use std::{
+	io::{Cursor, Result as IoResult},
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
+};
+
+use rufs::{Backend, InodeNum, InodeType, Options, SeekBackend, Ufs};
+use support::*;
+
+const DIRBLKSIZ: usize = 512;
+
+/// Counts the reads of data, which reach the backend.
+/// Reads of indirect block pointers aren't counted.
+struct Counting<B: Backend> {
+	inner: B,
+	reads: Arc<AtomicU64>,
+}
+
+impl<B: Backend> Backend for Counting<B> {
+	fn read_at(&self, pos: u64, buf: &mut [u8]) -> IoResult<()> {
+		if buf.len() >= FSIZE {
+			self.reads.fetch_add(1, Ordering::Relaxed);
+		}
+		self.inner.read_at(pos, buf)
+	}
+}
+
+/// The little-endian golden image, where "file3" (1 MiB) was turned into a directory,
+/// with one entry per directory block.
+/// The number of reads of data from the backend is counted.
+type CountingUfs = Ufs<Counting<SeekBackend<Cursor<Vec<u8>>>>>;
+
+fn bigdir() -> (CountingUfs, InodeNum, usize, Arc<AtomicU64>) {
+	bigdir_with(Options::default())
+}
+
+fn bigdir_with(opts: Options) -> (CountingUfs, InodeNum, usize, Arc<AtomicU64>) {
+	let mut img = golden_image("ufs-little");
+	let golden = open_golden("ufs-little");
+	let inr = golden.dir_lookup(InodeNum::ROOT, "file3".as_ref()).unwrap();
+	let file1 = golden.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+	let size = golden.inode_attr(inr).unwrap().size as usize;
+
+	// Every line of "file3" is 16 bytes long, so every block starts with a known line.
+	for blk in 0..(size / BSIZE) {
+		let line = format!("{:015x}\n", blk * BSIZE / 16);
+		let pos = img
+			.chunks(FSIZE)
+			.position(|f| f.starts_with(line.as_bytes()))
+			.unwrap() * FSIZE;
+
+		for (i, d) in img[pos..(pos + BSIZE)].chunks_mut(DIRBLKSIZ).enumerate() {
+			let name = format!("f{}", blk * (BSIZE / DIRBLKSIZ) + i);
+			d.fill(0);
+			d[0..4].copy_from_slice(&file1.get().to_le_bytes());
+			d[4..6].copy_from_slice(&(DIRBLKSIZ as u16).to_le_bytes());
+			d[6] = 8; // DT_REG
+			d[7] = name.len() as u8;
+			d[8..(8 + name.len())].copy_from_slice(name.as_bytes());
+		}
+	}
+
+	patch_inode(&mut img, inr, DI_MODE, &0o40755u16.to_le_bytes());
+
+	let reads = Arc::new(AtomicU64::new(0));
+	let backend = Counting {
+		inner: SeekBackend::new(Cursor::new(img)),
+		reads: Arc::clone(&reads),
+	};
+	let ufs = Ufs::with_options(backend, opts).unwrap();
+	(ufs, inr, size / DIRBLKSIZ, reads)
+}
+
+#[test]
+fn iter() {
+	let (ufs, inr, num, _) = bigdir();
+	assert_eq!(ufs.inode_attr(inr).unwrap().kind, InodeType::Directory);
+
+	let mut names = Vec::new();
+	ufs.dir_iter(inr, |name, _, kind| {
+		assert_eq!(kind, InodeType::RegularFile);
+		names.push(name.to_str().unwrap().to_owned());
+		None::<()>
+	})
+	.unwrap();
+	let expected = (0..num).map(|i| format!("f{i}")).collect::<Vec<_>>();
+	assert_eq!(names, expected);
+
+	let last = format!("f{}", num - 1);
+	assert!(ufs.dir_lookup(inr, last.as_ref()).is_ok());
+}
+
+/// Contiguous blocks are read at once.
+#[test]
+fn reads() {
+	let (ufs, inr, _, counter) = bigdir();
+	let nblocks = ufs.inode_attr(inr).unwrap().size / BSIZE as u64;
+	let before = counter.load(Ordering::Relaxed);
+	ufs.dir_iter(inr, |_, _, _| None::<()>).unwrap();
+	let reads = counter.load(Ordering::Relaxed) - before;
+	assert!(reads <= nblocks / 2, "{reads} reads for {nblocks} blocks");
+}
+
+/// After the first lookup, the entries are found using the hash index,
+/// without reading the directory again.
+#[test]
+fn dirhash() {
+	let opts = Options {
+		dirhash: 2 << 20,
+		..Options::default()
+	};
+	let (ufs, inr, num, counter) = bigdir_with(opts);
+	assert!(ufs.dir_lookup(inr, "f0".as_ref()).is_ok());
+
+	let before = counter.load(Ordering::Relaxed);
+	for i in (0..num).rev() {
+		let name = format!("f{i}");
+		assert!(ufs.dir_lookup(inr, name.as_ref()).is_ok(), "{name}");
+	}
+	let err = ufs.dir_lookup(inr, "nonexistent".as_ref()).unwrap_err();
+	assert_eq!(err.raw_os_error(), Some(libc::ENOENT));
+	assert_eq!(counter.load(Ordering::Relaxed), before);
+
+	let stats = ufs.dirhash_stats();
+	assert_eq!((stats.hits, stats.misses), (num as u64 + 1, 1));
+	assert_eq!((stats.dirs, stats.evictions), (1, 0));
+	assert!(stats.used > 0 && stats.used <= stats.capacity);
+}
+
+/// Without a budget, large directories are searched linearly.
+#[test]
+fn no_dirhash() {
+	let (ufs, inr, num, counter) = bigdir();
+	let last = format!("f{}", num - 1);
+	for _ in 0..2 {
+		let before = counter.load(Ordering::Relaxed);
+		assert!(ufs.dir_lookup(inr, last.as_ref()).is_ok());
+		assert!(counter.load(Ordering::Relaxed) > before);
+	}
+	assert_eq!(ufs.dirhash_stats().misses, 0);
+}
+
+/// The entries are read as they are needed, and errors stop the iteration.
+#[test]
+fn entries() {
+	let (ufs, inr, num, counter) = bigdir();
+	let before = counter.load(Ordering::Relaxed);
+	let mut entries = ufs.dir_entries(inr);
+	assert_eq!(entries.next().unwrap().unwrap().name, "f0");
+	assert_eq!(counter.load(Ordering::Relaxed) - before, 1);
+
+	let names = entries
+		.map(|e| Ok(e?.name.into_string().unwrap()))
+		.collect::<IoResult<Vec<_>>>()
+		.unwrap();
+	let expected = (1..num).map(|i| format!("f{i}")).collect::<Vec<_>>();
+	assert_eq!(names, expected);
+
+	let file1 = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+	let mut entries = ufs.dir_entries(file1);
+	let err = entries.next().unwrap().unwrap_err();
+	assert_eq!(err.raw_os_error(), Some(libc::ENOTDIR));
+	assert!(entries.next().is_none());
+}
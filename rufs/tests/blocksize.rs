@@ -0,0 +1,242 @@
+//! Filesystems with other block and fragment sizes than 32K/4K.
+//!
+//! The golden images all use the defaults of newfs(8), so these images are created by
+//! [`mkfs()`], and the files are written into them by hand, because rufs can't write yet.
+//!
+//! The same sizes are also tested with images created by newfs(8), see [`newfs()`].
+#![cfg(feature = "mkfs")]
+mod support;
+
+use std::io::{Cursor, Read, Seek};
+
+use rstest::rstest;
+use rstest_reuse::{apply, template};
+use rufs::{mkfs, BlockFile, Creator, InodeNum, InodeType, MkfsOptions, SeekBackend, Ufs};
+use support::*;
+
+/// Number of direct block pointers of an inode.
+const NDADDR: usize = 12;
+
+/// Inode number of the file, which is written into the image.
+const FILE: u32 = 5;
+
+#[template]
+#[rstest]
+#[case::b64k_f8k(65536, 8192)]
+#[case::b32k_f4k(32768, 4096)]
+#[case::b16k_f2k(16384, 2048)]
+#[case::b4k_f4k(4096, 4096)]
+#[case::b32k_frag1(32768, 32768)]
+#[case::b32k_frag2(32768, 16384)]
+#[case::b32k_frag4(32768, 8192)]
+#[case::b4k_frag8(4096, 512)]
+fn sizes(#[case] bsize: u32, #[case] fsize: u32) {}
+
+/// An image created by [`mkfs()`], into which inodes and blocks can be written.
+struct Image {
+	img:   Vec<u8>,
+	bsize: usize,
+	fsize: usize,
+	/// The next fragment, which is handed out by [`Image::block()`].
+	next:  usize,
+}
+
+impl Image {
+	fn new(bsize: u32, fsize: u32) -> Self {
+		let opts = MkfsOptions {
+			bsize,
+			fsize,
+			time: 1722785995,
+			seed: 42,
+			..MkfsOptions::new(32 << 20)
+		};
+		let mut file = tempfile::tempfile().unwrap();
+		file.set_len(opts.size).unwrap();
+		mkfs(
+			&BlockFile::new(file.try_clone().unwrap(), fsize as u64),
+			&opts,
+		)
+		.unwrap();
+		let mut img = Vec::new();
+		file.rewind().unwrap();
+		file.read_to_end(&mut img).unwrap();
+
+		// The blocks at the end of the last cylinder group are still free.
+		let (bsize, fsize) = (bsize as usize, fsize as usize);
+		let next = (img.len() / bsize - 32) * bsize / fsize;
+		Self {
+			img,
+			bsize,
+			fsize,
+			next,
+		}
+	}
+
+	fn open(&self) -> MemUfs {
+		Ufs::new(SeekBackend::new(Cursor::new(self.img.clone()))).unwrap()
+	}
+
+	/// Write `data` into a new block, and return its block pointer.
+	fn block(&mut self, data: &[u8]) -> i64 {
+		assert!(data.len() <= self.bsize);
+		let pos = self.next * self.fsize;
+		self.img[pos..(pos + data.len())].copy_from_slice(data);
+		let ptr = self.next as i64;
+		self.next += self.bsize / self.fsize;
+		ptr
+	}
+
+	/// Write `data` into blocks, and return the block pointers.
+	fn blocks(&mut self, data: &[u8]) -> Vec<i64> {
+		data.chunks(self.bsize).map(|c| self.block(c)).collect()
+	}
+
+	/// Write the inode `inr`, whose contents are stored in the blocks `ptrs`.
+	fn inode(&mut self, inr: InodeNum, mode: u16, size: usize, ptrs: &[i64]) {
+		let geo = Geometry::of(&self.img);
+		let pos = geo.inode_offset(inr);
+		self.img[pos..(pos + INOSZ)].fill(0);
+		geo.patch_inode(&mut self.img, inr, DI_MODE, &mode.to_le_bytes());
+		geo.patch_inode(&mut self.img, inr, DI_NLINK, &1u16.to_le_bytes());
+		geo.patch_inode(&mut self.img, inr, DI_SIZE, &(size as u64).to_le_bytes());
+		// Symbolic links without blocks store their target in the inode.
+		let blocks = (size.next_multiple_of(self.fsize) / 512) as u64;
+		geo.patch_inode(&mut self.img, inr, DI_BLOCKS, &blocks.to_le_bytes());
+
+		let (direct, indirect) = ptrs.split_at(ptrs.len().min(NDADDR));
+		let db: Vec<u8> = direct.iter().flat_map(|p| p.to_le_bytes()).collect();
+		geo.patch_inode(&mut self.img, inr, DI_DB, &db);
+		if !indirect.is_empty() {
+			let ib: Vec<u8> = indirect.iter().flat_map(|p| p.to_le_bytes()).collect();
+			let ptr = self.block(&ib);
+			geo.patch_inode(&mut self.img, inr, DI_IB, &ptr.to_le_bytes());
+		}
+	}
+}
+
+/// Deterministic, but not repetitive contents of a file.
+fn contents(len: usize) -> Vec<u8> {
+	(0..len).map(|i| (i ^ (i >> 9) ^ (i >> 17)) as u8).collect()
+}
+
+#[apply(sizes)]
+fn empty(#[case] bsize: u32, #[case] fsize: u32) {
+	let ufs = Image::new(bsize, fsize).open();
+
+	let info = ufs.info();
+	assert_eq!((info.bsize, info.fsize), (bsize, fsize));
+	assert_eq!((info.iosize, info.frsize), (bsize, fsize));
+	assert_eq!(ufs.check_deep().unwrap(), []);
+	assert_eq!(ufs.lint().creator, Creator::Newfs);
+
+	let mut names: Vec<_> = ufs
+		.dir_entries(InodeNum::ROOT)
+		.map(|e| e.unwrap().name)
+		.collect();
+	names.sort();
+	assert_eq!(names, [".", "..", ".snap"]);
+	let snap = ufs.dir_lookup(InodeNum::ROOT, ".snap".as_ref()).unwrap();
+	assert_eq!(ufs.inode_attr(snap).unwrap().kind, InodeType::Directory);
+}
+
+#[apply(sizes)]
+fn fragment(#[case] bsize: u32, #[case] fsize: u32) {
+	let mut img = Image::new(bsize, fsize);
+	// Three blocks, and half of a fragment, or of a block, if there are no fragments.
+	let tail = if bsize == fsize { bsize / 2 } else { fsize / 2 };
+	let data = contents(3 * bsize as usize + tail as usize);
+	let ptrs = img.blocks(&data);
+	img.inode(inr(FILE), 0o100644, data.len(), &ptrs);
+	let ufs = img.open();
+
+	let mut buf = vec![0u8; data.len() + 1];
+	assert_eq!(ufs.inode_read(inr(FILE), 0, &mut buf).unwrap(), data.len());
+	assert_eq!(buf[..data.len()], data);
+	// Only the fragments of the last block are allocated.
+	let extents = ufs.inode_extents(inr(FILE)).unwrap();
+	let len = data.len().next_multiple_of(fsize as usize);
+	assert_eq!(extents.iter().map(|e| e.length).sum::<u64>(), len as u64);
+}
+
+#[apply(sizes)]
+fn indirect(#[case] bsize: u32, #[case] fsize: u32) {
+	let mut img = Image::new(bsize, fsize);
+	let data = contents((NDADDR + 3) * bsize as usize);
+	let ptrs = img.blocks(&data);
+	img.inode(inr(FILE), 0o100644, data.len(), &ptrs);
+	let ufs = img.open();
+
+	let mut buf = vec![0u8; data.len()];
+	assert_eq!(ufs.inode_read(inr(FILE), 0, &mut buf).unwrap(), data.len());
+	assert_eq!(buf, data);
+
+	// Across the boundary between the direct and the indirect blocks.
+	let pos = NDADDR * bsize as usize - 100;
+	let mut buf = [0u8; 200];
+	assert_eq!(
+		ufs.inode_read(inr(FILE), pos as u64, &mut buf).unwrap(),
+		200
+	);
+	assert_eq!(buf, data[pos..(pos + 200)]);
+}
+
+#[apply(sizes)]
+fn long_symlink(#[case] bsize: u32, #[case] fsize: u32) {
+	let mut img = Image::new(bsize, fsize);
+	let target: Vec<u8> = (0..400).map(|i| b'a' + (i % 26) as u8).collect();
+	let ptrs = img.blocks(&target);
+	img.inode(inr(FILE), 0o120755, target.len(), &ptrs);
+	let ufs = img.open();
+
+	assert_eq!(ufs.inode_attr(inr(FILE)).unwrap().kind, InodeType::Symlink);
+	assert_eq!(ufs.symlink_read(inr(FILE)).unwrap(), target);
+}
+
+/// The geometry of the golden images, which the other tests rely on.
+#[test]
+fn golden() {
+	assert_eq!(Geometry::of(&golden_image("ufs-little")), Geometry::GOLDEN);
+}
+
+/// Images created by newfs(8), with the same files as the golden images.
+///
+/// They can only be created on FreeBSD, using eg. `./scripts/mkimg.sh -b 65536 -f 8192`, which
+/// creates "resources/ufs-little-b65536-f8192.img.zst". The CI does that, and runs these tests
+/// with `--include-ignored`.
+#[apply(sizes)]
+#[ignore = "needs images created by ./scripts/mkimg.sh -b <bsize> -f <fsize>"]
+fn newfs(#[case] bsize: u32, #[case] fsize: u32) {
+	let ufs = open_golden(&format!("ufs-little-b{bsize}-f{fsize}"));
+	let lookup = |path: &str| {
+		path.split('/').fold(InodeNum::ROOT, |inr, name| {
+			ufs.dir_lookup(inr, name.as_ref()).unwrap()
+		})
+	};
+
+	let info = ufs.info();
+	assert_eq!((info.bsize, info.fsize), (bsize, fsize));
+	assert_eq!(ufs.check_deep().unwrap(), []);
+	assert_eq!(ufs.lint().creator, Creator::Newfs);
+
+	let mut buf = [0u8; 64];
+	let n = ufs.inode_read(lookup("file1"), 0, &mut buf).unwrap();
+	assert_eq!(&buf[..n], b"This is a simple file.\n");
+	let n = ufs
+		.inode_read(lookup("dir1/dir2/dir3/file2"), 0, &mut buf)
+		.unwrap();
+	assert_eq!(&buf[..n], b"Hello World\n");
+
+	// Every line of "file3" is 16 bytes long, and the lines cross all block boundaries.
+	let file3 = lookup("file3");
+	let mut data = vec![0u8; 1 << 20];
+	assert_eq!(ufs.inode_read(file3, 0, &mut data).unwrap(), data.len());
+	for (i, line) in data.chunks(16).enumerate() {
+		assert_eq!(line, format!("{i:015x}\n").as_bytes());
+	}
+
+	let link = ufs.symlink_read(lookup("link1")).unwrap();
+	assert_eq!(link, b"dir1/dir2/dir3/file2");
+	let link = ufs.symlink_read(lookup("long-link")).unwrap();
+	assert_eq!(link.len(), 2 * 508 + "//file1".len());
+	assert!(link.ends_with(b"//file1"));
+}
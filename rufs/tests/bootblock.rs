@@ -0,0 +1,58 @@
+//! Reading and replacing the boot area before the superblock.
+mod support;
+
+use std::{fs::File, io::Write, os::unix::fs::FileExt};
+
+use rufs::{BlockFile, Ufs, BOOTBLOCK_SIZE};
+use support::*;
+
+fn image_file(img: &[u8]) -> File {
+	let mut f = tempfile::tempfile().unwrap();
+	f.write_all(img).unwrap();
+	f
+}
+
+#[test]
+fn read() {
+	let mut img = golden_image("ufs-little");
+	img[..512].fill(0xeb);
+	let file = image_file(&img);
+	let ufs = Ufs::new(BlockFile::new(file, 4096)).unwrap();
+	let boot = ufs.read_bootblock().unwrap();
+	assert!(boot == img[..BOOTBLOCK_SIZE]);
+}
+
+/// Only the given part of the boot area is replaced.
+#[test]
+fn write() {
+	let img = golden_image("ufs-little");
+	let file = image_file(&img);
+	let ufs = Ufs::new(BlockFile::new(file.try_clone().unwrap(), 4096)).unwrap();
+	let code = vec![0x90u8; 1000];
+	ufs.write_bootblock(&code).unwrap();
+	ufs.sync().unwrap();
+	assert_eq!(ufs.read_bootblock().unwrap()[..1000], code);
+
+	let mut data = vec![0u8; img.len()];
+	file.read_exact_at(&mut data, 0).unwrap();
+	let mut expected = img.clone();
+	expected[..1000].copy_from_slice(&code);
+	assert!(data == expected);
+	Ufs::new(BlockFile::new(file, 4096)).unwrap();
+}
+
+/// The superblock can't be overwritten.
+#[test]
+fn too_large() {
+	let img = golden_image("ufs-little");
+	let file = image_file(&img);
+	let ufs = Ufs::new(BlockFile::new(file.try_clone().unwrap(), 4096)).unwrap();
+	let e = ufs
+		.write_bootblock(&vec![0u8; BOOTBLOCK_SIZE + 1])
+		.unwrap_err();
+	assert_eq!(e.raw_os_error(), Some(libc::EINVAL));
+
+	let mut data = vec![0u8; img.len()];
+	file.read_exact_at(&mut data, 0).unwrap();
+	assert!(data == img);
+}
@@ -0,0 +1,101 @@
+//! Directory entries, which point to unallocated inodes.
+mod support;
+
+use std::{ffi::OsString, io::Cursor};
+
+use rufs::{DanglingEntries, InodeNum, Options, SeekBackend, Ufs};
+use support::*;
+
+/// An unallocated inode in the first cylinder group.
+const FREE_INODE: u32 = 100;
+
+/// The little-endian golden image, with "file1" pointing to an unallocated inode.
+fn corrupted() -> Vec<u8> {
+	let mut img = golden_image("ufs-little");
+	// struct direct: ino (4), reclen (2), type (1), namlen (1), name
+	let pat = b"\x08\x05file1\0";
+	let pos = img
+		.windows(pat.len())
+		.position(|w| w == pat)
+		.expect("no directory entry for file1") -
+		6;
+	img[pos..(pos + 4)].copy_from_slice(&FREE_INODE.to_le_bytes());
+	img
+}
+
+/// The little-endian golden image, where only the first `n` inodes of the first
+/// cylinder group were initialized, as if newfs(8) initialized them lazily.
+fn uninitialized(n: u32) -> Vec<u8> {
+	// Offset of `cg_initediblk` in the first cylinder group.
+	const INITEDIBLK: usize = 32 * 4096 + 120;
+	let mut img = golden_image("ufs-little");
+	img[INITEDIBLK..(INITEDIBLK + 4)].copy_from_slice(&n.to_le_bytes());
+	img
+}
+
+fn open_image(
+	img: Vec<u8>,
+	dangling_entries: DanglingEntries,
+) -> Ufs<SeekBackend<Cursor<Vec<u8>>>> {
+	let opts = Options {
+		dangling_entries,
+		..Options::default()
+	};
+	Ufs::with_options(SeekBackend::new(Cursor::new(img)), opts).unwrap()
+}
+
+fn open(dangling_entries: DanglingEntries) -> Ufs<SeekBackend<Cursor<Vec<u8>>>> {
+	open_image(corrupted(), dangling_entries)
+}
+
+fn names(ufs: &Ufs<SeekBackend<Cursor<Vec<u8>>>>) -> Vec<OsString> {
+	let mut names = Vec::new();
+	ufs.dir_iter(InodeNum::ROOT, |name, _, _| {
+		names.push(name.to_owned());
+		None::<()>
+	})
+	.unwrap();
+	names
+}
+
+#[test]
+fn show() {
+	let ufs = open(DanglingEntries::Show);
+	assert!(names(&ufs).contains(&"file1".into()));
+
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+	assert_eq!(inr.get(), FREE_INODE);
+	assert!(ufs.inode_attr(inr).is_err());
+	assert_eq!(ufs.stats().dangling_entries, 0);
+}
+
+#[test]
+fn hide() {
+	let ufs = open(DanglingEntries::Hide);
+	let names = names(&ufs);
+	assert!(!names.contains(&"file1".into()));
+	assert!(names.contains(&"file3".into()));
+	assert_eq!(ufs.stats().dangling_entries, 1);
+
+	let e = ufs
+		.dir_lookup(InodeNum::ROOT, "file1".as_ref())
+		.unwrap_err();
+	assert_eq!(e.raw_os_error(), Some(libc::ENOENT));
+	assert_eq!(ufs.stats().dangling_entries, 2);
+}
+
+/// Inodes, which weren't initialized yet, are unallocated, whatever they contain.
+#[test]
+fn uninitialized_inode() {
+	let ufs = open_image(uninitialized(4), DanglingEntries::Show);
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+	assert_eq!(inr.get(), 4);
+	let e = ufs.inode_attr(inr).unwrap_err();
+	assert_eq!(e.raw_os_error(), Some(libc::EINVAL));
+	assert!(ufs.inode_attr(InodeNum::ROOT).is_ok());
+
+	let ufs = open_image(uninitialized(4), DanglingEntries::Hide);
+	let names = names(&ufs);
+	assert!(!names.contains(&"file1".into()));
+	assert!(names.contains(&"dir1".into()));
+}
@@ -0,0 +1,63 @@
+//! Caching of directory lookups.
+mod support;
+
+use std::io::Cursor;
+
+use rufs::{BlockCache, InodeNum, Options, SeekBackend, Ufs};
+use support::*;
+
+fn open(dcache: usize) -> Ufs<BlockCache<SeekBackend<Cursor<Vec<u8>>>>> {
+	let backend = SeekBackend::new(Cursor::new(golden_image("ufs-big")));
+	let opts = Options {
+		dcache,
+		..Options::default()
+	};
+	Ufs::with_options(BlockCache::new(backend, 1 << 20), opts).unwrap()
+}
+
+/// Number of block lookups, that were done so far.
+fn accesses<B: rufs::Backend>(ufs: &Ufs<BlockCache<B>>) -> u64 {
+	let st = ufs.cache_stats();
+	st.hits + st.misses
+}
+
+#[test]
+fn negative() {
+	let ufs = open(16);
+	for _ in 0..2 {
+		let e = ufs
+			.dir_lookup(InodeNum::ROOT, "nonexistent".as_ref())
+			.unwrap_err();
+		assert_eq!(e.raw_os_error(), Some(libc::ENOENT));
+	}
+	let n = accesses(&ufs);
+	assert!(ufs
+		.dir_lookup(InodeNum::ROOT, "nonexistent".as_ref())
+		.is_err());
+	assert_eq!(accesses(&ufs), n);
+}
+
+#[test]
+fn positive() {
+	let ufs = open(16);
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+	let n = accesses(&ufs);
+	assert_eq!(
+		ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap(),
+		inr
+	);
+	assert_eq!(accesses(&ufs), n);
+}
+
+#[test]
+fn disabled() {
+	let ufs = open(0);
+	assert!(ufs
+		.dir_lookup(InodeNum::ROOT, "nonexistent".as_ref())
+		.is_err());
+	let n = accesses(&ufs);
+	assert!(ufs
+		.dir_lookup(InodeNum::ROOT, "nonexistent".as_ref())
+		.is_err());
+	assert!(accesses(&ufs) > n);
+}
@@ -0,0 +1,103 @@
+//! Synthesizing "." and "..", if they are missing.
+mod support;
+
+use std::{ffi::OsString, io::Cursor};
+
+use rufs::{InodeNum, Options, SeekBackend, Ufs};
+use support::*;
+
+/// The little-endian golden image, with "." and ".." of "dir1/dir2" deleted.
+fn corrupted() -> Vec<u8> {
+	let mut img = golden_image("ufs-little");
+
+	// struct direct: ino (4), reclen (2), type (1), namlen (1), name
+	let find = |img: &[u8], pat: &[u8]| {
+		img.windows(pat.len())
+			.position(|w| w == pat)
+			.expect("pattern not found")
+	};
+	let pos = find(&img, b"\x04\x04dir2\0") - 6;
+	let dir2 = img[pos..(pos + 4)].to_vec();
+
+	let dot = find(&img, &[&dir2[..], b"\x0c\x00\x04\x01.\0\0\0"].concat());
+	img[dot..(dot + 4)].fill(0);
+	img[(dot + 12)..(dot + 16)].fill(0);
+	img
+}
+
+fn open(synthesize_dots: bool) -> Ufs<SeekBackend<Cursor<Vec<u8>>>> {
+	let opts = Options {
+		synthesize_dots,
+		..Options::default()
+	};
+	Ufs::with_options(SeekBackend::new(Cursor::new(corrupted())), opts).unwrap()
+}
+
+fn entries(ufs: &Ufs<SeekBackend<Cursor<Vec<u8>>>>, inr: InodeNum) -> Vec<(OsString, InodeNum)> {
+	let mut v = Vec::new();
+	ufs.dir_iter(inr, |name, inr, _| {
+		v.push((name.to_owned(), inr));
+		None::<()>
+	})
+	.unwrap();
+	v
+}
+
+#[test]
+fn missing() {
+	let ufs = open(false);
+	let dir1 = ufs.dir_lookup(InodeNum::ROOT, "dir1".as_ref()).unwrap();
+	let dir2 = ufs.dir_lookup(dir1, "dir2".as_ref()).unwrap();
+	let names = entries(&ufs, dir2)
+		.into_iter()
+		.map(|(name, _)| name)
+		.collect::<Vec<_>>();
+	assert_eq!(names, ["dir3"]);
+	assert!(ufs.dir_lookup(dir2, "..".as_ref()).is_err());
+}
+
+#[test]
+fn synthesized() {
+	let ufs = open(true);
+	let dir1 = ufs.dir_lookup(InodeNum::ROOT, "dir1".as_ref()).unwrap();
+	let dir2 = ufs.dir_lookup(dir1, "dir2".as_ref()).unwrap();
+	let dir3 = ufs.dir_lookup(dir2, "dir3".as_ref()).unwrap();
+	assert_eq!(
+		entries(&ufs, dir2),
+		[
+			(".".into(), dir2),
+			("..".into(), dir1),
+			("dir3".into(), dir3)
+		]
+	);
+	assert_eq!(ufs.dir_lookup(dir2, "..".as_ref()).unwrap(), dir1);
+
+	// Intact directories are left alone.
+	assert_eq!(entries(&ufs, dir1).len(), 3);
+}
+
+/// ".." of the root directory is the root directory itself, even if it says otherwise.
+#[test]
+fn root_parent() {
+	let mut img = golden_image("ufs-little");
+	let ufs = Ufs::new(SeekBackend::new(Cursor::new(img.clone()))).unwrap();
+	let dir1 = ufs.dir_lookup(InodeNum::ROOT, "dir1".as_ref()).unwrap();
+	assert_eq!(
+		ufs.dir_lookup(InodeNum::ROOT, "..".as_ref()).unwrap(),
+		InodeNum::ROOT
+	);
+
+	// Let ".." of the root directory point to "dir1".
+	let pat = b"\x02\x00\x00\x00\x0c\x00\x04\x02..\0\0";
+	let pos = img.windows(pat.len()).position(|w| w == pat).unwrap();
+	img[pos..(pos + 4)].copy_from_slice(&dir1.get().to_le_bytes());
+
+	let ufs = Ufs::new(SeekBackend::new(Cursor::new(img))).unwrap();
+	assert_eq!(
+		ufs.dir_lookup(InodeNum::ROOT, "..".as_ref()).unwrap(),
+		InodeNum::ROOT
+	);
+	let entries = entries(&ufs, InodeNum::ROOT);
+	assert!(entries.contains(&("..".into(), InodeNum::ROOT)));
+	assert_eq!(ufs.dir_lookup(dir1, "..".as_ref()).unwrap(), InodeNum::ROOT);
+}
@@ -0,0 +1,76 @@
+//! Directory entries, which don't store the file type (`DT_UNKNOWN`), like on older filesystems.
+mod support;
+
+use std::{ffi::OsString, io::Cursor};
+
+use rufs::{DanglingEntries, InodeNum, InodeType, Options, SeekBackend, Ufs};
+use support::*;
+
+/// The little-endian golden image, in which the entries of the root directory named `names`
+/// have the type `DT_UNKNOWN`.
+fn unknown(names: &[&str]) -> Vec<u8> {
+	let mut img = golden_image("ufs-little");
+	for name in names {
+		// struct direct: ino (4), reclen (2), type (1), namlen (1), name
+		let pat = [&[name.len() as u8], name.as_bytes(), b"\0"].concat();
+		let pos = img
+			.windows(pat.len())
+			.position(|w| w == pat)
+			.expect("pattern not found");
+		img[pos - 1] = 0;
+	}
+	img
+}
+
+fn entries(ufs: &MemUfs) -> std::io::Result<Vec<(OsString, InodeNum, InodeType)>> {
+	let mut v = Vec::new();
+	ufs.dir_iter(InodeNum::ROOT, |name, inr, kind| {
+		v.push((name.to_owned(), inr, kind));
+		None::<()>
+	})?;
+	Ok(v)
+}
+
+/// The type is taken from the inode instead.
+#[test]
+fn from_inode() {
+	let img = unknown(&["file1", "dir1", "link1"]);
+	let ufs = Ufs::new(SeekBackend::new(Cursor::new(img))).unwrap();
+	assert_eq!(
+		entries(&ufs).unwrap(),
+		entries(&open_golden("ufs-little")).unwrap()
+	);
+	assert!(ufs.check_deep().unwrap().is_empty());
+}
+
+/// Found by fuzzing: an entry of unknown type, which refers to an inode of an invalid type,
+/// used to abort the whole process.
+#[test]
+fn invalid_inode() {
+	let mut img = unknown(&["file1"]);
+	// The mode of "file1" (inode 4).
+	img[inode_offset(inr(4)) + DI_MODE + 1] |= 0o170000u16.to_le_bytes()[1];
+	let ufs = Ufs::new(SeekBackend::new(Cursor::new(img))).unwrap();
+	let e = entries(&ufs).unwrap_err();
+	assert_eq!(rufs::errno(&e), libc::EIO);
+}
+
+/// Entries of unknown type, which refer to unallocated inodes, can be hidden.
+#[test]
+fn dangling() {
+	let mut img = unknown(&["file1"]);
+	// "file1" is inode 4.
+	patch_inode(&mut img, inr(4), DI_MODE, &[0, 0]);
+	let ufs = Ufs::new(SeekBackend::new(Cursor::new(img.clone()))).unwrap();
+	assert_eq!(rufs::errno(&entries(&ufs).unwrap_err()), libc::EIO);
+
+	let opts = Options {
+		dangling_entries: DanglingEntries::Hide,
+		..Options::default()
+	};
+	let ufs = Ufs::with_options(SeekBackend::new(Cursor::new(img)), opts).unwrap();
+	assert!(entries(&ufs)
+		.unwrap()
+		.iter()
+		.all(|(name, ..)| name != "file1"));
+}
@@ -0,0 +1,123 @@
+//! The physical layout of files.
+mod support;
+
+use std::io::Cursor;
+
+use rufs::{Extent, InodeNum, SeekBackend, Ufs, Whence};
+use support::*;
+
+fn lookup(ufs: &MemUfs, name: &str) -> InodeNum {
+	ufs.dir_lookup(InodeNum::ROOT, name.as_ref()).unwrap()
+}
+
+/// The data of the file is found at the physical offsets.
+#[test]
+fn contents() {
+	let img = golden_image("ufs-little");
+	let ufs = open_golden("ufs-little");
+	for name in [
+		"file1",
+		"file3",
+		"long-link",
+		"sparse",
+		"sparse2",
+		"sparse3",
+	] {
+		let inr = lookup(&ufs, name);
+		let size = ufs.inode_attr(inr).unwrap().size;
+		let extents = ufs.inode_extents(inr).unwrap();
+		for e in &extents {
+			let len = e.length.min(size - e.logical) as usize;
+			let mut data = vec![0u8; len];
+			let mut pos = 0;
+			while pos < len {
+				let off = e.logical + pos as u64;
+				pos += ufs.inode_read(inr, off, &mut data[pos..]).unwrap();
+			}
+			let physical = e.physical as usize;
+			assert!(data == img[physical..(physical + len)], "{name}: {e:?}");
+		}
+
+		let last = extents.iter().filter(|e| e.flags & Extent::LAST != 0);
+		assert_eq!(last.count(), 1, "{name}");
+		assert!(extents
+			.windows(2)
+			.all(|w| w[0].logical + w[0].length <= w[1].logical));
+	}
+}
+
+/// Short symbolic links are stored in the inode.
+#[test]
+fn inline() {
+	let img = golden_image("ufs-little");
+	let ufs = open_golden("ufs-little");
+	let inr = lookup(&ufs, "link1");
+	let target = ufs.symlink_read(inr).unwrap();
+	let extents = ufs.inode_extents(inr).unwrap();
+	assert_eq!(extents.len(), 1);
+	let e = extents[0];
+	assert_eq!(e.physical as usize, inode_offset(inr) + DI_DB);
+	assert_eq!(e.length as usize, target.len());
+	let physical = e.physical as usize;
+	assert_eq!(img[physical..(physical + target.len())], target);
+}
+
+/// Contiguous blocks are merged into a single extent.
+#[test]
+fn merged() {
+	let ufs = open_golden("ufs-little");
+	let inr = lookup(&ufs, "file3");
+	let extents = ufs.inode_extents(inr).unwrap();
+	let len: u64 = extents.iter().map(|e| e.length).sum();
+	assert_eq!(len, 1 << 20);
+	assert!(extents.len() < len as usize / BSIZE);
+}
+
+/// Holes aren't reported.
+#[test]
+fn sparse() {
+	let ufs = open_golden("ufs-little");
+	for name in ["sparse", "sparse2", "sparse3"] {
+		let inr = lookup(&ufs, name);
+		let size = ufs.inode_attr(inr).unwrap().size;
+		let extents = ufs.inode_extents(inr).unwrap();
+		let data = ufs.inode_seek(inr, 0, Whence::Data).unwrap();
+		assert_eq!(extents[0].logical, data, "{name}");
+		assert!(
+			extents.iter().map(|e| e.length).sum::<u64>() < size,
+			"{name}"
+		);
+	}
+}
+
+/// Blocks of snapshots, which weren't copied, are shared with the filesystem.
+#[test]
+fn snapshot() {
+	const SF_SNAPSHOT: u32 = 0x0020_0000;
+	let mut img = golden_image("ufs-little");
+	let inr = lookup(&open_golden("ufs-little"), "file3");
+	let off = inode_offset(inr) + DI_FLAGS;
+	let flags = u32::from_le_bytes(img[off..(off + 4)].try_into().unwrap());
+	patch_inode(
+		&mut img,
+		inr,
+		DI_FLAGS,
+		&(flags | SF_SNAPSHOT).to_le_bytes(),
+	);
+	// The 2nd block was free (BLK_NOCOPY), the 3rd wasn't copied yet.
+	for (i, ptr) in [(1, 1u64), (2, 0)] {
+		patch_inode(&mut img, inr, DI_DB + i * 8, &ptr.to_le_bytes());
+	}
+	let ufs = Ufs::new(SeekBackend::new(Cursor::new(img))).unwrap();
+
+	let bsize = BSIZE as u64;
+	let extents = ufs.inode_extents(inr).unwrap();
+	assert_eq!(extents[0].logical, 0);
+	assert_eq!(extents[0].length, bsize);
+	assert_eq!(extents[1].logical, 2 * bsize);
+	assert_eq!(extents[1].physical, 2 * bsize);
+	assert_eq!(extents[1].length, bsize);
+	assert_eq!(extents[1].flags, Extent::SHARED);
+	assert_eq!(extents[2].logical, 3 * bsize);
+	assert_eq!(extents[2].flags & Extent::SHARED, 0);
+}
@@ -0,0 +1,34 @@
+//! File flags, which are set using chflags(2).
+mod support;
+
+use std::io::Cursor;
+
+use rufs::{InodeNum, SeekBackend, Ufs, SF_APPEND, SF_IMMUTABLE, UF_NOUNLINK};
+use support::*;
+
+/// The little-endian golden image, with "file1" having the flags `flags`.
+fn open(flags: u32) -> MemUfs {
+	let mut img = golden_image("ufs-little");
+	// "file1" is inode 4.
+	patch_inode(&mut img, inr(4), DI_FLAGS, &flags.to_le_bytes());
+	Ufs::new(SeekBackend::new(Cursor::new(img))).unwrap()
+}
+
+#[test]
+fn flags() {
+	let cases = [
+		(0, false, false, false),
+		(SF_IMMUTABLE, true, false, true),
+		(SF_APPEND, false, true, true),
+		(UF_NOUNLINK, false, false, true),
+	];
+	for (flags, immutable, append, undeletable) in cases {
+		let ufs = open(flags);
+		let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+		let attr = ufs.inode_attr(inr).unwrap();
+		assert_eq!(attr.flags, flags);
+		assert_eq!(attr.is_immutable(), immutable, "{flags:#x}");
+		assert_eq!(attr.is_append_only(), append, "{flags:#x}");
+		assert_eq!(attr.is_undeletable(), undeletable, "{flags:#x}");
+	}
+}
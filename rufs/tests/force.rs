@@ -0,0 +1,148 @@
+//! Opening filesystems with a corrupted superblock in degraded mode.
+mod support;
+
+use std::io::Cursor;
+
+use rufs::{Error, InodeNum, Options, SeekBackend, Ufs};
+use support::*;
+
+/// Offset of the primary superblock.
+const SBLOCK: usize = 65536;
+
+/// Offset of `fs_ncg` in the superblock.
+const FS_NCG: usize = 44;
+
+/// Offset of `fs_sbsize` in the superblock.
+const FS_SBSIZE: usize = 104;
+
+/// Offset of `fs_fpg` in the superblock.
+const FS_FPG: usize = 188;
+
+/// The little-endian golden image, with the 32-bit superblock field at `off` set to `value`.
+fn corrupted(off: usize, value: i32) -> Vec<u8> {
+	let mut img = golden_image("ufs-little");
+	let pos = SBLOCK + off;
+	img[pos..(pos + 4)].copy_from_slice(&value.to_le_bytes());
+	img
+}
+
+/// Remove the recovery information in front of the superblock,
+/// so that the backup superblocks can't be found.
+fn without_backups(mut img: Vec<u8>) -> Vec<u8> {
+	img[(SBLOCK - 20)..SBLOCK].fill(0);
+	img
+}
+
+fn open(img: Vec<u8>, force: bool) -> std::io::Result<Ufs<SeekBackend<Cursor<Vec<u8>>>>> {
+	let opts = Options {
+		force,
+		..Options::default()
+	};
+	Ufs::with_options(SeekBackend::new(Cursor::new(img)), opts)
+}
+
+#[test]
+fn strict() {
+	let ufs = open(golden_image("ufs-little"), false).unwrap();
+	assert_eq!(ufs.stats().ignored_checks, 0);
+
+	let e = open(without_backups(corrupted(FS_SBSIZE, 8192)), false)
+		.err()
+		.unwrap();
+	assert_eq!(rufs::errno(&e), libc::EIO);
+
+	// A backup superblock is used instead.
+	let ufs = open(corrupted(FS_SBSIZE, 8192), false).unwrap();
+	assert_eq!(ufs.stats().ignored_checks, 0);
+	assert!(ufs.stats().alternate_superblock.is_some());
+}
+
+#[test]
+fn degraded() {
+	let ufs = open(corrupted(FS_SBSIZE, 8192), true).unwrap();
+	assert_eq!(ufs.stats().ignored_checks, 1);
+
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+	let mut buf = [0u8; 23];
+	ufs.inode_read(inr, 0, &mut buf).unwrap();
+	assert_eq!(&buf, b"This is a simple file.\n");
+}
+
+/// Checks, which protect against crashes, can't be ignored.
+#[test]
+fn fatal() {
+	let e = open(without_backups(corrupted(FS_NCG, 0)), true)
+		.err()
+		.unwrap();
+	assert_eq!(rufs::errno(&e), libc::EIO);
+}
+
+/// Cylinder groups, which don't cover the filesystem, or whose addresses overflow, are fatal.
+#[test]
+fn cg_geometry() {
+	for (off, value) in [
+		(FS_FPG, 1),
+		(FS_FPG, i32::MAX),
+		(FS_NCG, 1),
+		(FS_NCG, i32::MAX),
+	] {
+		let e = open(without_backups(corrupted(off, value)), true)
+			.err()
+			.unwrap();
+		assert_eq!(rufs::errno(&e), libc::EIO, "{off}: {value}");
+	}
+
+	let mut img = without_backups(corrupted(FS_FPG, i32::MAX));
+	img[(SBLOCK + FS_NCG)..(SBLOCK + FS_NCG + 4)].copy_from_slice(&i32::MAX.to_le_bytes());
+	let e = open(img, true).err().unwrap();
+	assert_eq!(rufs::errno(&e), libc::EIO);
+}
+
+/// The little-endian golden image, without its last `missing` bytes,
+/// and with the data of "file1" moved to the last fragment of the filesystem.
+fn truncated(missing: usize) -> Vec<u8> {
+	let ufs = open(golden_image("ufs-little"), false).unwrap();
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+	assert_eq!(inr.get(), 4);
+
+	let mut img = golden_image("ufs-little");
+	let last = (img.len() / FSIZE - 1) as u64;
+	patch_inode(&mut img, inr, DI_DB, &last.to_le_bytes());
+	img.truncate(img.len() - missing);
+	img
+}
+
+#[test]
+fn truncated_device() {
+	let e = open(truncated(16384), false).err().unwrap();
+	assert_eq!(rufs::errno(&e), libc::EIO);
+
+	let ufs = open(truncated(16384), true).unwrap();
+	let stats = ufs.stats();
+	assert_eq!((stats.ignored_checks, stats.missing_bytes), (1, 16384));
+
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+	let e = ufs.inode_read(inr, 0, &mut [0u8; 23]).unwrap_err();
+	assert_eq!(rufs::errno(&e), libc::EIO);
+	let last = (golden_image("ufs-little").len() - FSIZE) as u64;
+	match Error::from(e) {
+		Error::Corrupt { offset, .. } => assert_eq!(offset, Some(last)),
+		e => panic!("{e:?}"),
+	}
+}
+
+/// Block pointers outside of the filesystem fail, instead of overflowing.
+#[test]
+fn bad_pointer() {
+	for ptr in [u64::MAX / FSIZE as u64 + 1, u64::MAX] {
+		let mut img = golden_image("ufs-little");
+		patch_inode(&mut img, inr(4), DI_DB, &ptr.to_le_bytes());
+		let ufs = open(img, false).unwrap();
+		let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+		let e = ufs.inode_read(inr, 0, &mut [0u8; 23]).unwrap_err();
+		assert!(
+			matches!(Error::of(&e), Some(Error::Corrupt { .. })),
+			"{ptr}: {e}"
+		);
+	}
+}
@@ -0,0 +1,228 @@
+//! Deep consistency checks of whole filesystems.
+mod support;
+
+use std::{
+	io::{Cursor, Write},
+	num::NonZeroUsize,
+};
+
+use rufs::{BlockFile, FsckFinding, InodeNum, SeekBackend, Ufs};
+use support::*;
+
+/// Offsets of the inode map and the free map in a cylinder group.
+const IUSEDOFF: usize = 168;
+const FREEOFF: usize = 200;
+
+fn check(img: Vec<u8>) -> Vec<FsckFinding> {
+	let ufs = Ufs::new(SeekBackend::new(Cursor::new(img))).unwrap();
+	ufs.check_deep().unwrap()
+}
+
+/// The little-endian golden image, with `f` applied to it and to the inode of "file1",
+/// and valid check-hashes.
+fn corrupted(f: impl FnOnce(&mut [u8], InodeNum)) -> (Vec<FsckFinding>, InodeNum) {
+	let mut img = golden_image("ufs-little");
+	let inr = open_golden("ufs-little")
+		.dir_lookup(InodeNum::ROOT, "file1".as_ref())
+		.unwrap();
+	assert_eq!(
+		inr.get() as usize / IPG,
+		0,
+		"file1 must be in the first group"
+	);
+	f(&mut img, inr);
+	rehash(&mut img);
+	(check(img), inr)
+}
+
+#[test]
+fn clean() {
+	assert_eq!(check(golden_image("ufs-little")), []);
+	assert_eq!(check(golden_image("ufs-big")), []);
+}
+
+#[test]
+#[cfg(feature = "mkfs")]
+fn clean_mkfs() {
+	use rufs::{mkfs, MkfsOptions};
+
+	let opts = MkfsOptions::new(16 << 20);
+	let file = tempfile::tempfile().unwrap();
+	file.set_len(opts.size).unwrap();
+	mkfs(
+		&rufs::BlockFile::new(file.try_clone().unwrap(), 4096),
+		&opts,
+	)
+	.unwrap();
+	let ufs = Ufs::new(rufs::BlockFile::new(file, 4096)).unwrap();
+	assert_eq!(ufs.check_deep().unwrap(), []);
+}
+
+/// The check-hashes, which newfs(8) enabled, are verified.
+#[test]
+fn check_hashes() {
+	let mut img = golden_image("ufs-little");
+	img[65536 + 1304] ^= 1;
+	img[(FPG + CBLKNO) * FSIZE + 132] ^= 1;
+	img[inode_offset(inr(4)) + 244] ^= 1;
+	assert_eq!(
+		check(img),
+		[
+			FsckFinding::SuperblockHash,
+			FsckFinding::CgHash { cg: 1 },
+			FsckFinding::InodeHash { inr: inr(4) },
+		]
+	);
+}
+
+#[test]
+fn link_count() {
+	let (findings, inr) = corrupted(|img, inr| {
+		patch_inode(img, inr, DI_NLINK, &5u16.to_le_bytes());
+	});
+	assert_eq!(
+		findings,
+		[FsckFinding::LinkCount {
+			inr,
+			nlink: 5,
+			refs: 1
+		}]
+	);
+}
+
+#[test]
+fn unallocated() {
+	let (findings, inr) = corrupted(|img, inr| {
+		patch_inode(img, inr, DI_MODE, &[0, 0]);
+	});
+	assert!(findings.contains(&FsckFinding::InodeMap {
+		inr,
+		allocated: false
+	}));
+	assert!(findings.contains(&FsckFinding::DanglingEntry {
+		dir: InodeNum::ROOT,
+		name: "file1".into(),
+		inr
+	}));
+	assert!(findings
+		.iter()
+		.any(|f| matches!(f, FsckFinding::LostFragments { cg: 0, .. })));
+}
+
+#[test]
+fn free_block_in_use() {
+	let (findings, inr) = corrupted(|img, inr| {
+		// The first direct block pointer of "file1".
+		let off = inode_offset(inr) + DI_DB;
+		let addr = u64::from_le_bytes(img[off..(off + 8)].try_into().unwrap()) as usize;
+		assert!(addr < FPG);
+
+		let byte = CBLKNO * FSIZE + FREEOFF + addr / 8;
+		img[byte] |= 1 << (addr % 8);
+	});
+	assert!(findings
+		.iter()
+		.any(|f| matches!(f, FsckFinding::FreeBlockInUse { inr: i, .. } if *i == inr)));
+	assert!(findings
+		.iter()
+		.any(|f| matches!(f, FsckFinding::CgSummary { cg: 0, .. })));
+}
+
+#[test]
+fn inode_map() {
+	let (findings, inr) = corrupted(|img, inr| {
+		let idx = inr.get() as usize;
+		img[CBLKNO * FSIZE + IUSEDOFF + idx / 8] &= !(1 << (idx % 8));
+	});
+	assert!(findings.contains(&FsckFinding::InodeMap {
+		inr,
+		allocated: true
+	}));
+	assert!(findings
+		.iter()
+		.any(|f| matches!(f, FsckFinding::TotalSummary { .. })));
+}
+
+/// The extended attribute area of "xattrs" claims more than its two blocks.
+#[test]
+fn extattr_size() {
+	let mut img = golden_image("ufs-little");
+	let inr = open_golden("ufs-little")
+		.dir_lookup(InodeNum::ROOT, "xattrs".as_ref())
+		.unwrap();
+	// di_extsize
+	patch_inode(&mut img, inr, DI_EXTSIZE, &u32::MAX.to_le_bytes());
+
+	let ufs = Ufs::new(SeekBackend::new(Cursor::new(img))).unwrap();
+	assert_eq!(ufs.xattr_list(inr).unwrap(), b"user.test\0");
+	assert_eq!(ufs.xattr_list_len(inr).unwrap(), 2 * 32768);
+	let findings = ufs.check_deep().unwrap();
+	assert!(
+		findings.contains(&FsckFinding::ExtattrSize {
+			inr,
+			extsize: u32::MAX,
+		}),
+		"{findings:?}"
+	);
+}
+
+/// Checking the cylinder groups in parallel finds the same, in the same order.
+#[test]
+fn parallel() {
+	let mut img = golden_image("ufs-little");
+	let inr = open_golden("ufs-little")
+		.dir_lookup(InodeNum::ROOT, "file1".as_ref())
+		.unwrap();
+	// Unallocate "file1", and mark an inode of the second group used.
+	patch_inode(&mut img, inr, DI_MODE, &[0, 0]);
+	img[(FPG + CBLKNO) * FSIZE + IUSEDOFF] |= 1 << 5;
+
+	let ufs = Ufs::new(SeekBackend::new(Cursor::new(img))).unwrap();
+	let serial = ufs.check_deep().unwrap();
+	assert!(serial.len() > 3, "{serial:?}");
+	for threads in [1, 2, 64] {
+		let threads = NonZeroUsize::new(threads).unwrap();
+		assert_eq!(ufs.check_deep_parallel(threads).unwrap(), serial);
+	}
+}
+
+/// Only the cylinder groups, which were written to, are checked again.
+#[test]
+fn touched() {
+	let mut img = golden_image("ufs-little");
+	// Mark inode 5 of the second group used, although it isn't.
+	img[(FPG + CBLKNO) * FSIZE + IUSEDOFF] |= 1 << 5;
+	rehash(&mut img);
+	let mut file = tempfile::tempfile().unwrap();
+	file.write_all(&img).unwrap();
+	let ufs = Ufs::with_options(BlockFile::new(file, 4096), raw_writes()).unwrap();
+	assert_eq!(ufs.check_touched().unwrap(), []);
+
+	// A data fragment of the first group.
+	ufs.write_raw_block(100, &[0; FSIZE]).unwrap();
+	assert_eq!(ufs.check_touched().unwrap(), []);
+
+	// A data fragment of the second group.
+	ufs.write_raw_block((FPG + 100) as u64, &[0; FSIZE])
+		.unwrap();
+	let findings = ufs.check_touched().unwrap();
+	let inr = inr(IPG as u32 + 5);
+	assert_eq!(
+		findings[0],
+		FsckFinding::InodeMap {
+			inr,
+			allocated: false
+		}
+	);
+	assert!(
+		matches!(findings[1..], [FsckFinding::CgSummary { cg: 1, .. }]),
+		"{findings:?}"
+	);
+	assert_eq!(ufs.check_touched().unwrap(), []);
+
+	// A full check clears the touched groups as well.
+	ufs.write_raw_block((FPG + 100) as u64, &[0; FSIZE])
+		.unwrap();
+	assert!(!ufs.check_deep().unwrap().is_empty());
+	assert_eq!(ufs.check_touched().unwrap(), []);
+}
@@ -0,0 +1,50 @@
+//! Writing back the modifications of a single inode using `Ufs::sync_inode()`.
+mod support;
+
+use std::io::Write;
+
+use rufs::{BlockCache, BlockFile, InodeNum, Ufs};
+use support::*;
+/// A fragment of the golden images, which doesn't belong to "file1".
+const DATA: u64 = 100;
+
+fn open() -> Ufs<BlockCache<BlockFile>> {
+	let mut file = tempfile::tempfile().unwrap();
+	file.write_all(&golden_image("ufs-little")).unwrap();
+	let backend = BlockCache::new(BlockFile::new(file, 4096), 4 << 20);
+	Ufs::with_options(backend, raw_writes()).unwrap()
+}
+
+/// Only the blocks of the inode are written back.
+#[test]
+fn sync_inode() {
+	let ufs = open();
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+	let ext = ufs.inode_extents(inr).unwrap()[0];
+	ufs.write_raw_block(ext.physical / FSIZE as u64, &[b'x'; FSIZE])
+		.unwrap();
+	ufs.write_raw_block(DATA, &[0xff; FSIZE]).unwrap();
+	assert_eq!(ufs.cache_stats().dirty, 2 * FSIZE as u64);
+
+	ufs.sync_inode(inr).unwrap();
+	let st = ufs.cache_stats();
+	assert_eq!(st.dirty, FSIZE as u64);
+	assert_eq!(st.writebacks, 1);
+	let mut buf = [0u8; 4];
+	ufs.inode_read(inr, 0, &mut buf).unwrap();
+	assert_eq!(&buf, b"xxxx");
+
+	ufs.sync().unwrap();
+	assert_eq!(ufs.cache_stats().dirty, 0);
+}
+
+/// Files with indirect blocks, directories and symbolic links can be synced as well.
+#[test]
+fn sync_all_kinds() {
+	let ufs = open();
+	for name in ["sparse", "sparse2", "sparse3", "dir1", "link1", "."] {
+		let inr = ufs.dir_lookup(InodeNum::ROOT, name.as_ref()).unwrap();
+		ufs.sync_inode(inr).unwrap();
+	}
+	assert_eq!(ufs.cache_stats().writebacks, 0);
+}
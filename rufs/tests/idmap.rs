@@ -0,0 +1,89 @@
+//! Presenting the owners and groups of files as different ids.
+mod support;
+
+use std::io::Cursor;
+
+use rufs::{Credentials, IdMap, InodeNum, Options, SeekBackend, Ufs};
+use support::*;
+
+/// The little-endian golden image, in which "file1" is owned by 1001:1002 with mode 0600.
+fn open(idmap: IdMap) -> (MemUfs, InodeNum) {
+	let mut img = golden_image("ufs-little");
+	// "file1" is inode 4.
+	let file1 = inr(4);
+	let off = inode_offset(file1);
+	let mode = u16::from_le_bytes(img[off..(off + 2)].try_into().unwrap());
+	patch_inode(
+		&mut img,
+		file1,
+		DI_MODE,
+		&((mode & !0o7777) | 0o600).to_le_bytes(),
+	);
+	patch_inode(&mut img, file1, DI_UID, &1001u32.to_le_bytes());
+	patch_inode(&mut img, file1, DI_GID, &1002u32.to_le_bytes());
+	let opts = Options {
+		idmap,
+		..Options::default()
+	};
+	let ufs = Ufs::with_options(SeekBackend::new(Cursor::new(img)), opts).unwrap();
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+	(ufs, inr)
+}
+
+#[test]
+fn identity() {
+	let (ufs, inr) = open(IdMap::default());
+	let attr = ufs.inode_attr(inr).unwrap();
+	assert_eq!((attr.uid, attr.gid), (1001, 1002));
+}
+
+/// Permissions are checked against the presented owner.
+#[test]
+fn table() {
+	let mut idmap = IdMap::default();
+	idmap.uids.insert(1001, 1000);
+	idmap.gids.insert(1002, 100);
+	idmap.gids.insert(0, 5);
+	let (ufs, inr) = open(idmap);
+
+	let attr = ufs.inode_attr(inr).unwrap();
+	assert_eq!((attr.uid, attr.gid), (1000, 100));
+	let attr = ufs.inode_attr(InodeNum::ROOT).unwrap();
+	assert_eq!((attr.uid, attr.gid), (0, 5));
+
+	ufs.access(inr, &Credentials::new(1000, 1000), libc::R_OK)
+		.unwrap();
+	let e = ufs
+		.access(inr, &Credentials::new(1001, 1001), libc::R_OK)
+		.unwrap_err();
+	assert_eq!(e.raw_os_error(), Some(libc::EACCES));
+}
+
+#[test]
+fn force() {
+	let mut idmap = IdMap::default();
+	idmap.uids.insert(1001, 1000);
+	idmap.force_uid = Some(500);
+	idmap.force_gid = Some(600);
+	let (ufs, inr) = open(idmap);
+
+	for inr in [inr, InodeNum::ROOT] {
+		let attr = ufs.inode_attr(inr).unwrap();
+		assert_eq!((attr.uid, attr.gid), (500, 600));
+	}
+	ufs.access(inr, &Credentials::new(500, 500), libc::R_OK | libc::W_OK)
+		.unwrap();
+}
+
+#[test]
+fn reverse() {
+	let mut idmap = IdMap::default();
+	idmap.uids.insert(1001, 1000);
+	idmap.uids.insert(1002, 1000);
+	idmap.gids.insert(7, 8);
+	assert_eq!(idmap.fs_uid(1000), 1001);
+	assert_eq!(idmap.fs_uid(42), 42);
+	assert_eq!(idmap.fs_gid(8), 7);
+	assert!(!idmap.is_identity());
+	assert!(IdMap::default().is_identity());
+}
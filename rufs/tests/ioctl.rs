@@ -0,0 +1,183 @@
+//! Passing `ioctl(2)` commands of Linux filesystems through to rufs.
+#![cfg(target_os = "linux")]
+mod support;
+
+use std::{
+	io::{Cursor, Write},
+	sync::{
+		atomic::{AtomicU32, Ordering},
+		Arc,
+	},
+};
+
+use rufs::{
+	Backend,
+	BlockFile,
+	Credentials,
+	Extent,
+	InodeNum,
+	IoctlRequest,
+	Ioctls,
+	SeekBackend,
+	Ufs,
+	FITRIM,
+	FS_IOC32_GETFLAGS,
+	FS_IOC_FIEMAP,
+	FS_IOC_GETFLAGS,
+	FS_IOC_SETFLAGS,
+	SF_IMMUTABLE,
+	UF_APPEND,
+	UF_NODUMP,
+	UF_OPAQUE,
+};
+use support::*;
+
+const FS_IMMUTABLE_FL: u32 = 0x10;
+const FS_APPEND_FL: u32 = 0x20;
+const FS_NODUMP_FL: u32 = 0x40;
+
+fn user() -> Credentials {
+	Credentials::new(1000, 1000)
+}
+
+fn file1(ufs: &Ufs<impl Backend>) -> InodeNum {
+	ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap()
+}
+
+/// The little-endian golden image, with "file1" having the flags `flags`.
+fn with_flags(flags: u32) -> MemUfs {
+	let mut img = golden_image("ufs-little");
+	// "file1" is inode 4.
+	patch_inode(&mut img, inr(4), DI_FLAGS, &flags.to_le_bytes());
+	Ufs::new(SeekBackend::new(Cursor::new(img))).unwrap()
+}
+
+fn call<B: Backend + 'static>(
+	ufs: &Ufs<B>,
+	cmd: u32,
+	cred: &Credentials,
+	input: &[u8],
+	out_size: u32,
+) -> std::io::Result<Vec<u8>> {
+	let req = IoctlRequest {
+		inr: file1(ufs),
+		cmd,
+		cred,
+		input,
+		out_size,
+	};
+	Ioctls::linux_writable().call(ufs, &req)
+}
+
+fn errno<T>(res: std::io::Result<T>) -> Option<i32> {
+	res.err().and_then(|e| e.raw_os_error())
+}
+
+#[test]
+fn fiemap() {
+	let ufs = example_image();
+	let mut input = [0u8; 32];
+	input[8..16].copy_from_slice(&u64::MAX.to_ne_bytes());
+	input[24..28].copy_from_slice(&4u32.to_ne_bytes());
+	let out = call(&ufs, FS_IOC_FIEMAP, &user(), &input, 4096).unwrap();
+	assert_eq!(out.len(), 32 + 56);
+	assert_eq!(out[20..24], 1u32.to_ne_bytes());
+
+	let ext = ufs.inode_extents(file1(&ufs)).unwrap()[0];
+	assert_eq!(out[40..48], ext.physical.to_ne_bytes());
+	assert_eq!(out[48..56], ext.length.to_ne_bytes());
+	assert_eq!(out[72..76], Extent::LAST.to_ne_bytes());
+
+	// Only FIEMAP_FLAG_SYNC is supported.
+	input[16..20].copy_from_slice(&2u32.to_ne_bytes());
+	let res = call(&ufs, FS_IOC_FIEMAP, &user(), &input, 4096);
+	assert_eq!(errno(res), Some(libc::EBADR));
+}
+
+/// The flags of chflags(1) are translated into the ones of chattr(1).
+#[test]
+fn getflags() {
+	let cases = [
+		(0, 0),
+		(SF_IMMUTABLE, FS_IMMUTABLE_FL),
+		(UF_APPEND | UF_NODUMP, FS_APPEND_FL | FS_NODUMP_FL),
+		(UF_OPAQUE, 0),
+	];
+	for (flags, expected) in cases {
+		let ufs = with_flags(flags);
+		for cmd in [FS_IOC_GETFLAGS, FS_IOC32_GETFLAGS] {
+			let out = call(&ufs, cmd, &user(), &[], 8).unwrap();
+			assert_eq!(out, expected.to_ne_bytes(), "{flags:#x}");
+		}
+	}
+}
+
+#[test]
+fn setflags() {
+	let ufs = with_flags(SF_IMMUTABLE);
+	let set = |fl: u32| call(&ufs, FS_IOC_SETFLAGS, &user(), &fl.to_ne_bytes(), 0);
+	// Setting the current flags changes nothing.
+	assert_eq!(set(FS_IMMUTABLE_FL).unwrap(), []);
+	assert_eq!(errno(set(0)), Some(libc::EROFS));
+	// FS_COMPR_FL has no equivalent.
+	assert_eq!(errno(set(FS_IMMUTABLE_FL | 0x4)), Some(libc::EOPNOTSUPP));
+}
+
+#[test]
+fn fitrim() {
+	let img = golden_image("ufs-little");
+	let mut file = tempfile::tempfile().unwrap();
+	file.write_all(&img).unwrap();
+	let ufs = Ufs::new(BlockFile::new(file, 4096)).unwrap();
+	let mut input = [0u8; 24];
+	input[8..16].copy_from_slice(&u64::MAX.to_ne_bytes());
+
+	assert_eq!(
+		errno(call(&ufs, FITRIM, &user(), &input, 24)),
+		Some(libc::EPERM)
+	);
+	let root = Credentials::new(0, 0);
+	// Read-only backends can't discard anything.
+	assert!(!Ioctls::<BlockFile>::linux().contains(FITRIM));
+	let out = match call(&ufs, FITRIM, &root, &input, 24) {
+		Err(e) if e.raw_os_error() == Some(libc::EOPNOTSUPP) => return,
+		res => res.unwrap(),
+	};
+	let free: u64 = ufs
+		.free_extents()
+		.unwrap()
+		.iter()
+		.map(|r| r.end - r.start)
+		.sum();
+	assert_eq!(out[8..16], free.to_ne_bytes());
+}
+
+/// Commands can be added, or replaced.
+#[test]
+fn register() {
+	let ufs = example_image();
+	let calls = Arc::new(AtomicU32::new(0));
+	let mut ioctls = Ioctls::linux();
+	let c = calls.clone();
+	ioctls.register(
+		FS_IOC_GETFLAGS,
+		move |_: &MemUfs, req: &IoctlRequest<'_>| {
+			c.fetch_add(1, Ordering::Relaxed);
+			Ok(req.input.to_vec())
+		},
+	);
+	assert!(ioctls.contains(FS_IOC_FIEMAP));
+	assert!(!ioctls.contains(0x1234));
+
+	let req = IoctlRequest {
+		inr:      file1(&ufs),
+		cmd:      FS_IOC_GETFLAGS,
+		cred:     &user(),
+		input:    b"abc",
+		out_size: 8,
+	};
+	assert_eq!(ioctls.call(&ufs, &req).unwrap(), b"abc");
+	assert_eq!(calls.load(Ordering::Relaxed), 1);
+	let req = IoctlRequest { cmd: 0x1234, ..req };
+	assert_eq!(errno(ioctls.call(&ufs, &req)), Some(libc::ENOTTY));
+}
@@ -0,0 +1,77 @@
+//! Detection of the soft updates journal (SU+J).
+mod support;
+
+use std::io::Cursor;
+
+use rufs::{InodeNum, Journal, Options, SeekBackend, Ufs, WriteCaps};
+use support::*;
+
+/// Offset of the primary superblock.
+const SBLOCK: usize = 65536;
+
+/// Offset of `fs_clean` in the superblock.
+const CLEAN: usize = 209;
+
+/// Offset of `fs_sujfree` in the superblock.
+const SUJFREE: usize = 1216;
+
+/// Offset of `fs_flags` in the superblock.
+const FLAGS: usize = 1312;
+
+/// Soft updates journaling is enabled.
+const FS_SUJ: i32 = 0x0008;
+
+/// The little-endian golden image, with journaling enabled.
+fn journaled(clean: bool, sujfree: u32) -> Vec<u8> {
+	let mut img = golden_image("ufs-little");
+	let pos = SBLOCK + FLAGS;
+	let flags = i32::from_le_bytes(img[pos..(pos + 4)].try_into().unwrap()) | FS_SUJ;
+	img[pos..(pos + 4)].copy_from_slice(&flags.to_le_bytes());
+	img[SBLOCK + CLEAN] = clean as u8;
+	let pos = SBLOCK + SUJFREE;
+	img[pos..(pos + 4)].copy_from_slice(&sujfree.to_le_bytes());
+	img
+}
+
+fn open(img: Vec<u8>) -> MemUfs {
+	Ufs::new(SeekBackend::new(Cursor::new(img))).unwrap()
+}
+
+#[test]
+fn none() {
+	let info = open(golden_image("ufs-little")).info();
+	assert!(info.softdep);
+	assert_eq!(info.journal, None);
+}
+
+#[test]
+fn detected() {
+	let info = open(journaled(true, 0)).info();
+	assert_eq!(
+		info.journal,
+		Some(Journal {
+			inr:          None,
+			size:         0,
+			needs_replay: false,
+			unlinked:     None,
+		})
+	);
+
+	let journal = open(journaled(false, 5)).info().journal.unwrap();
+	assert!(journal.needs_replay);
+	assert_eq!(journal.unlinked, Some(unsafe { InodeNum::new(5) }));
+}
+
+#[test]
+fn read_write() {
+	let opts = Options {
+		write: WriteCaps {
+			create: true,
+			..WriteCaps::default()
+		},
+		..Options::default()
+	};
+	let backend = SeekBackend::new(Cursor::new(journaled(true, 0)));
+	let e = Ufs::with_options(backend, opts).err().unwrap();
+	assert_eq!(e.raw_os_error(), Some(libc::EROFS));
+}
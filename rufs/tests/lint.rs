@@ -0,0 +1,160 @@
+//! Recognizing filesystems created by other tools, than newfs(8).
+mod support;
+
+use std::io::Cursor;
+
+use rufs::{
+	Creator,
+	InodeNum,
+	LintFinding,
+	Options,
+	SeekBackend,
+	SummaryCheck,
+	Ufs,
+	CK_CYLGRP,
+	CK_INODE,
+	CK_SUPERBLOCK,
+};
+use support::*;
+
+/// Offset of the primary superblock.
+const SBLOCK: usize = 65536;
+
+/// Offset of `fs_providersize` in the superblock.
+const PROVIDERSIZE: usize = 872;
+
+/// Offset of `fs_ckhash` in the superblock.
+const CKHASH: usize = 1304;
+
+/// Offset of `fs_metackhash` in the superblock.
+const METACKHASH: usize = 1308;
+
+/// Offset of `fs_flags` in the superblock.
+const FLAGS: usize = 1312;
+
+/// `FS_METACKHASH` of FreeBSD, `FS_DOQUOTA2` of NetBSD.
+const FS_METACKHASH: u32 = 0x0200;
+
+/// Set the superblock field at `off` of a little-endian image to `value`.
+fn patch(img: &mut [u8], off: usize, value: &[u8]) {
+	let pos = SBLOCK + off;
+	img[pos..(pos + value.len())].copy_from_slice(value);
+}
+
+/// `fs_flags` of a little-endian image.
+fn flags(img: &[u8]) -> u32 {
+	let pos = SBLOCK + FLAGS;
+	u32::from_le_bytes(img[pos..(pos + 4)].try_into().unwrap())
+}
+
+/// The golden image, as makefs(8) would have created it:
+/// without check-hashes and without the size of the device.
+fn makefs_image() -> Vec<u8> {
+	let mut img = golden_image("ufs-little");
+	let flags = flags(&img) & !FS_METACKHASH;
+	patch(&mut img, FLAGS, &flags.to_le_bytes());
+	patch(&mut img, METACKHASH, &0u32.to_le_bytes());
+	patch(&mut img, PROVIDERSIZE, &0i64.to_le_bytes());
+	// The check-hashes, which newfs(8) computed, are stale now.
+	patch(&mut img, CKHASH, &0u32.to_le_bytes());
+	patch_inode(&mut img, InodeNum::ROOT, DI_CKHASH, &0u32.to_le_bytes());
+	img
+}
+
+fn open(img: Vec<u8>) -> MemUfs {
+	Ufs::new(SeekBackend::new(Cursor::new(img))).unwrap()
+}
+
+#[test]
+fn newfs() {
+	for name in ["ufs-little", "ufs-big"] {
+		let lint = open_golden(name).lint();
+		assert_eq!(lint.creator, Creator::Newfs, "{name}");
+		assert_eq!(lint.findings, [], "{name}");
+		assert_eq!(lint.check_hashes, CK_SUPERBLOCK | CK_CYLGRP | CK_INODE);
+	}
+}
+
+#[test]
+fn makefs() {
+	let ufs = open(makefs_image());
+	let lint = ufs.lint();
+	assert_eq!(lint.creator, Creator::Makefs);
+	assert_eq!(
+		lint.findings,
+		[LintFinding::NoCheckHashes, LintFinding::NoProviderSize]
+	);
+	assert_eq!(lint.check_hashes, 0);
+
+	// The quirks aren't corruption, and the stale check-hashes aren't verified.
+	assert_eq!(ufs.check_deep().unwrap(), []);
+	assert!(ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).is_ok());
+}
+
+/// An image created by makefs(8) of FreeBSD, with the same files as the golden images.
+///
+/// It can only be created on FreeBSD, using `./scripts/mkimg.sh -m`. The CI does that,
+/// and runs this test with `--include-ignored`.
+#[test]
+#[ignore = "needs resources/ufs-little-makefs.img.zst, created by ./scripts/mkimg.sh -m"]
+fn makefs_golden() {
+	let backend = SeekBackend::new(Cursor::new(golden_image("ufs-little-makefs")));
+	let opts = Options {
+		summary: SummaryCheck::Check,
+		..Options::default()
+	};
+	let ufs = Ufs::with_options(backend, opts).unwrap();
+	let lint = ufs.lint();
+	assert_eq!(lint.creator, Creator::Makefs);
+	assert_eq!(lint.check_hashes, 0);
+	assert!(lint.findings.contains(&LintFinding::NoCheckHashes));
+
+	assert_eq!(ufs.check_deep().unwrap(), []);
+	assert!(!ufs.stats().stale_summary);
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+	let mut buf = [0u8; 64];
+	let n = ufs.inode_read(inr, 0, &mut buf).unwrap();
+	assert_eq!(&buf[..n], b"This is a simple file.\n");
+}
+
+#[test]
+fn netbsd() {
+	// NetBSD sets FS_DOQUOTA2, which is FS_METACKHASH on FreeBSD, but has no check-hashes.
+	let mut img = makefs_image();
+	let flags = flags(&img) | FS_METACKHASH;
+	patch(&mut img, FLAGS, &flags.to_le_bytes());
+	let lint = open(img).lint();
+	assert_eq!(lint.creator, Creator::NetBsd);
+	assert_eq!(lint.check_hashes, 0);
+	assert!(lint.findings.contains(&LintFinding::NoCheckHashes));
+	assert!(lint.findings.contains(&LintFinding::NetBsdFlags {
+		flags: FS_METACKHASH,
+	}));
+}
+
+#[test]
+fn unknown_flags() {
+	let mut img = golden_image("ufs-little");
+	let flags = flags(&img) | 0x0001_0000;
+	patch(&mut img, FLAGS, &flags.to_le_bytes());
+	let lint = open(img).lint();
+	assert_eq!(lint.creator, Creator::Newfs);
+	assert_eq!(
+		lint.findings,
+		[LintFinding::UnknownFlags { flags: 0x0001_0000 }]
+	);
+}
+
+#[test]
+#[cfg(feature = "mkfs")]
+fn mkfs() {
+	use rufs::{mkfs, BlockFile, MkfsOptions};
+
+	let opts = MkfsOptions::new(16 << 20);
+	let file = tempfile::tempfile().unwrap();
+	file.set_len(opts.size).unwrap();
+	mkfs(&BlockFile::new(file.try_clone().unwrap(), 4096), &opts).unwrap();
+	let lint = Ufs::new(BlockFile::new(file, 4096)).unwrap().lint();
+	assert_eq!(lint.creator, Creator::Newfs);
+	assert_eq!(lint.findings, [LintFinding::NoCheckHashes]);
+}
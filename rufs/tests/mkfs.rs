@@ -0,0 +1,133 @@
+//! Creating new filesystems.
+#![cfg(feature = "mkfs")]
+mod support;
+
+use std::{
+	io::{Cursor, ErrorKind, Read, Seek},
+	os::unix::fs::MetadataExt,
+	time::{Duration, UNIX_EPOCH},
+};
+
+use rufs::{mkfs, BlockFile, InodeNum, InodeType, MkfsOptions, SeekBackend, Ufs};
+use support::*;
+
+/// Offset of the primary superblock.
+const SBLOCK: usize = 65536;
+
+/// Create a filesystem with `opts`, and return the image.
+fn create(opts: &MkfsOptions) -> std::io::Result<Vec<u8>> {
+	let mut file = tempfile::tempfile()?;
+	file.set_len(opts.size)?;
+	mkfs(&BlockFile::new(file.try_clone()?, opts.fsize as u64), opts)?;
+
+	let mut img = Vec::new();
+	file.rewind()?;
+	file.read_to_end(&mut img)?;
+	assert_eq!(img.len() as u64, file.metadata()?.size());
+	Ok(img)
+}
+
+fn options(size: u64) -> MkfsOptions {
+	MkfsOptions {
+		time: 1722785995,
+		seed: 42,
+		..MkfsOptions::new(size)
+	}
+}
+
+fn open(img: Vec<u8>) -> MemUfs {
+	Ufs::new(SeekBackend::new(Cursor::new(img))).unwrap()
+}
+
+/// The layout matches the golden image, which was created by newfs(8).
+#[test]
+fn geometry() {
+	let golden = golden_image("ufs-little");
+	let img = create(&options(golden.len() as u64)).unwrap();
+
+	// sblkno, cblkno, iblkno, dblkno
+	assert_eq!(
+		img[(SBLOCK + 8)..(SBLOCK + 24)],
+		golden[(SBLOCK + 8)..(SBLOCK + 24)]
+	);
+	// ncg, bsize, fsize, frag
+	assert_eq!(
+		img[(SBLOCK + 44)..(SBLOCK + 60)],
+		golden[(SBLOCK + 44)..(SBLOCK + 60)]
+	);
+	// struct fsrecovery, in front of the superblock
+	assert_eq!(img[(SBLOCK - 20)..SBLOCK], golden[(SBLOCK - 20)..SBLOCK]);
+
+	let (new, old) = (open(img).info(), open(golden).info());
+	assert_eq!((new.blocks, new.files), (old.blocks, old.files));
+	assert_eq!((new.bsize, new.fsize), (old.bsize, old.fsize));
+}
+
+#[test]
+fn empty() {
+	for big_endian in [false, true] {
+		let opts = MkfsOptions {
+			big_endian,
+			..options(16 << 20)
+		};
+		let ufs = open(create(&opts).unwrap());
+
+		let mut names = Vec::new();
+		ufs.dir_iter(InodeNum::ROOT, |name, _, kind| {
+			assert_eq!(kind, InodeType::Directory);
+			names.push(name.to_owned());
+			None::<()>
+		})
+		.unwrap();
+		assert_eq!(names, [".", "..", ".snap"]);
+
+		let root = ufs.inode_attr(InodeNum::ROOT).unwrap();
+		assert_eq!((root.perm, root.nlink), (0o755, 3));
+
+		let inr = ufs.dir_lookup(InodeNum::ROOT, ".snap".as_ref()).unwrap();
+		let snap = ufs.inode_attr(inr).unwrap();
+		assert_eq!((snap.perm, snap.nlink, snap.gid), (0o775, 2, 5));
+		assert_eq!(ufs.dir_lookup(inr, "..".as_ref()).unwrap(), InodeNum::ROOT);
+
+		// Inodes 0 and 1 aren't counted.
+		let info = ufs.info();
+		assert_eq!(info.ffree, info.files - 2);
+	}
+}
+
+#[test]
+fn volume() {
+	let opts = MkfsOptions {
+		volname: "data".into(),
+		..options(16 << 20)
+	};
+	let vol = open(create(&opts).unwrap()).volume_info();
+	assert_eq!(vol.volname, "data");
+	assert_eq!(vol.last_mountpoint, "");
+	assert_eq!(vol.written, UNIX_EPOCH + Duration::from_secs(1722785995));
+	assert!(vol.clean);
+}
+
+#[test]
+fn deterministic() {
+	let opts = options(8 << 20);
+	assert!(create(&opts).unwrap() == create(&opts).unwrap());
+
+	let other = MkfsOptions {
+		seed: 43,
+		..opts.clone()
+	};
+	assert!(create(&other).unwrap() != create(&opts).unwrap());
+}
+
+#[test]
+fn invalid() {
+	let e = create(&options(64 << 10)).unwrap_err();
+	assert_eq!(e.kind(), ErrorKind::InvalidInput);
+
+	let opts = MkfsOptions {
+		fsize: 3000,
+		..options(16 << 20)
+	};
+	assert_eq!(create(&opts).unwrap_err().kind(), ErrorKind::InvalidInput);
+}
@@ -0,0 +1,63 @@
+//! Images, which are mapped into memory.
+#![cfg(feature = "mmap")]
+mod support;
+
+use std::{fs::OpenOptions, io::Write};
+
+use rufs::{Backend, InodeNum, MmapBackend, Ufs};
+use support::*;
+/// A fragment of the golden images, which isn't followed by metadata.
+const DATA: u64 = 100;
+
+fn image() -> tempfile::NamedTempFile {
+	let mut file = tempfile::NamedTempFile::new().unwrap();
+	file.write_all(&golden_image("ufs-little")).unwrap();
+	file
+}
+
+#[test]
+fn read() {
+	let img = image();
+	let ufs = Ufs::new(MmapBackend::open(img.path()).unwrap()).unwrap();
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+	let mut buf = [0u8; 32];
+	let n = ufs.inode_read(inr, 0, &mut buf).unwrap();
+	assert_eq!(&buf[..n], b"This is a simple file.\n");
+	assert_eq!(ufs.check_deep().unwrap(), []);
+}
+
+#[test]
+fn bounds() {
+	let img = image();
+	let len = img.as_file().metadata().unwrap().len();
+	let backend = MmapBackend::open(img.path()).unwrap();
+	assert_eq!(backend.size().unwrap(), Some(len));
+	assert_eq!(backend.mapped(len - 4, 4).map(<[u8]>::len), Some(4));
+	assert!(backend.mapped(len - 4, 5).is_none());
+	assert!(backend.mapped(u64::MAX, 1).is_none());
+	let err = backend.read_at(len, &mut [0u8; 1]).unwrap_err();
+	assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+	// Read-only files can't be written.
+	backend.write_at(0, &[0u8; FSIZE]).unwrap_err();
+}
+
+/// Writes go through the file, and are visible through the mapping.
+#[test]
+fn write() {
+	let img = image();
+	let file = OpenOptions::new()
+		.read(true)
+		.write(true)
+		.open(img.path())
+		.unwrap();
+	let ufs = Ufs::with_options(MmapBackend::new(file).unwrap(), raw_writes()).unwrap();
+	ufs.write_raw_block(DATA, &[0xaa; FSIZE]).unwrap();
+	ufs.sync().unwrap();
+
+	let mut buf = [0u8; FSIZE];
+	ufs.read_raw_block(DATA, &mut buf).unwrap();
+	assert_eq!(buf, [0xaa; FSIZE]);
+	let data = std::fs::read(img.path()).unwrap();
+	let pos = DATA as usize * FSIZE;
+	assert_eq!(data[pos..(pos + FSIZE)], [0xaa; FSIZE]);
+}
@@ -0,0 +1,92 @@
+//! File types of inodes: special files, and types, that can't be represented.
+mod support;
+
+use std::io::Cursor;
+
+use rufs::{InodeNum, InodeType, SeekBackend, Ufs, Whence};
+use support::*;
+
+/// The little-endian golden image, with "file1" having the file type `kind`.
+fn open(kind: u16) -> MemUfs {
+	open_dev(kind, None)
+}
+
+/// Like `open()`, but "file1" has the device number `rdev`, and no data.
+fn open_dev(kind: u16, rdev: Option<u64>) -> MemUfs {
+	let mut img = golden_image("ufs-little");
+	// "file1" is inode 4.
+	let file1 = inr(4);
+	let off = inode_offset(file1);
+	let mode = u16::from_le_bytes(img[off..(off + 2)].try_into().unwrap());
+	let mode = (mode & 0o7777) | kind;
+	patch_inode(&mut img, file1, DI_MODE, &mode.to_le_bytes());
+	// The first block pointer holds the device number of devices.
+	if let Some(rdev) = rdev {
+		patch_inode(&mut img, file1, DI_DB, &rdev.to_le_bytes());
+	}
+	Ufs::new(SeekBackend::new(Cursor::new(img))).unwrap()
+}
+
+/// FreeBSD's `makedev(3)`.
+fn makedev(major: u32, minor: u32) -> u64 {
+	let (major, minor) = (u64::from(major), u64::from(minor));
+	((major & 0xffff_ff00) << 32) |
+		((major & 0xff) << 8) |
+		((minor & 0xff00) << 24) |
+		(minor & 0xffff_00ff)
+}
+
+/// Device nodes, FIFOs and sockets keep their type, permissions and device number,
+/// and don't have any data, even if the size of the inode says otherwise.
+#[test]
+fn special_files() {
+	let cases = [
+		(0o020000, InodeType::CharDevice, makedev(0, 0x2a)),
+		(
+			0o060000,
+			InodeType::BlockDevice,
+			makedev(0x1_2345, 0xabc_def0),
+		),
+		(0o010000, InodeType::NamedPipe, 0),
+		(0o140000, InodeType::Socket, 0),
+	];
+	for (mode, kind, rdev) in cases {
+		let ufs = open_dev(mode, Some(rdev));
+		let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+		let attr = ufs.inode_attr(inr).unwrap();
+		assert_eq!(attr.kind, kind);
+		assert_eq!(attr.perm, 0o644);
+		assert_eq!(attr.rdev, rdev);
+		assert!(ufs.inode_extents(inr).unwrap().is_empty());
+		assert!(ufs.inode_seek(inr, 0, Whence::Data).is_err());
+		assert_eq!(ufs.inode_read(inr, 0, &mut [0u8; 16]).unwrap(), 0);
+	}
+
+	let ufs = open_dev(0o060000, Some(makedev(0x1_2345, 0xabc_def0)));
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+	let attr = ufs.inode_attr(inr).unwrap();
+	assert_eq!((attr.major(), attr.minor()), (0x1_2345, 0xabc_def0));
+
+	// Only devices have a device number.
+	let ufs = open(0o100000);
+	let attr = ufs.inode_attr(InodeNum::ROOT).unwrap();
+	assert_eq!(attr.rdev, 0);
+}
+
+/// Whiteouts, and mode bytes found by fuzzing, which don't denote any file type.
+#[test]
+fn invalid_mode() {
+	for kind in [0o030000, 0o050000, 0o070000, 0o110000, 0o160000, 0o170000] {
+		let ufs = open(kind);
+		let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+		assert_eq!(inr.get(), 4);
+
+		let e = ufs.inode_attr(inr).unwrap_err();
+		assert_eq!(e.raw_os_error(), Some(libc::EINVAL), "mode {kind:o}");
+		let e = ufs.dir_iter(inr, |_, _, _| None::<()>).unwrap_err();
+		assert_eq!(e.raw_os_error(), Some(libc::EINVAL), "mode {kind:o}");
+
+		ufs.dir_iter(InodeNum::ROOT, |_, _, _| None::<()>).unwrap();
+		assert!(ufs.inode_attr(InodeNum::ROOT).is_ok());
+	}
+}
@@ -0,0 +1,73 @@
+//! Names, which are arbitrary bytes, instead of valid UTF-8.
+mod support;
+
+use std::{
+	ffi::{OsStr, OsString},
+	io::Cursor,
+	os::unix::ffi::OsStrExt,
+};
+
+use rufs::{Error, InodeNum, SeekBackend, Ufs};
+use support::*;
+
+/// A name of the same length as "file1", with high-bit bytes and a space.
+const NAME: &[u8] = b"f\xe4 l\xff";
+
+/// Rename the entry "file1" of the root directory in `img` to `name`, which must have the same length.
+fn rename_file1(img: &mut [u8], name: &[u8]) {
+	// type (DT_REG) and length of the name, followed by the name
+	let pattern = b"\x08\x05file1";
+	let pos = img
+		.windows(pattern.len())
+		.position(|w| w == pattern)
+		.expect("no entry for file1");
+	img[(pos + 2)..(pos + 7)].copy_from_slice(name);
+}
+
+#[test]
+fn non_utf8() {
+	for golden in ["ufs-little", "ufs-big"] {
+		let mut img = golden_image(golden);
+		rename_file1(&mut img, NAME);
+		let ufs = Ufs::new(SeekBackend::new(Cursor::new(img))).unwrap();
+
+		let mut names = Vec::new();
+		ufs.dir_iter(InodeNum::ROOT, |name, _, _| {
+			names.push(name.to_owned());
+			None::<()>
+		})
+		.unwrap();
+		assert!(
+			names.contains(&OsString::from(OsStr::from_bytes(NAME))),
+			"{golden}"
+		);
+
+		let inr = ufs
+			.dir_lookup(InodeNum::ROOT, OsStr::from_bytes(NAME))
+			.unwrap();
+		let mut buf = vec![0u8; ufs.inode_attr(inr).unwrap().size as usize];
+		ufs.inode_read(inr, 0, &mut buf).unwrap();
+		assert_eq!(buf, b"This is a simple file.\n");
+
+		let e = ufs
+			.dir_lookup(InodeNum::ROOT, "file1".as_ref())
+			.unwrap_err();
+		assert_eq!(e.raw_os_error(), Some(libc::ENOENT));
+	}
+}
+
+/// A NUL byte in a name makes the directory unreadable, instead of truncating the name.
+#[test]
+fn nul() {
+	let mut img = golden_image("ufs-little");
+	rename_file1(&mut img, b"fi\0e1");
+	let ufs = Ufs::new(SeekBackend::new(Cursor::new(img))).unwrap();
+	let e = ufs
+		.dir_iter(InodeNum::ROOT, |_, _, _| None::<()>)
+		.unwrap_err();
+	assert_eq!(rufs::errno(&e), libc::EIO);
+	let Some(Error::Corrupt { what, .. }) = Error::of(&e) else {
+		panic!("{e:?}");
+	};
+	assert!(what.contains("invalid name"), "{what}");
+}
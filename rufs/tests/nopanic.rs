@@ -0,0 +1,90 @@
+//! Corrupted images must make rufs fail, but never panic.
+//!
+//! This is a tiny, deterministic version of the `nopanic` fuzz target,
+//! which overwrites random words of the metadata of the golden image.
+mod support;
+
+use std::{io::Cursor, panic};
+
+use rufs::{InodeNum, Options, SeekBackend, Ufs, Whence};
+use support::*;
+
+/// Number of corrupted images, that are tried.
+const ROUNDS: usize = 200;
+
+/// Offset of the primary superblock.
+const SBLOCK: usize = 65536;
+
+/// Offset of the first cylinder group.
+const CG0: usize = CBLKNO * FSIZE;
+
+/// Offset of the inodes of the first cylinder group.
+const INODES: usize = IBLKNO * FSIZE;
+
+/// A xorshift PRNG, so that failures can be reproduced.
+struct Rng(u64);
+
+impl Rng {
+	fn next(&mut self) -> u64 {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 7;
+		self.0 ^= self.0 << 17;
+		self.0
+	}
+}
+
+/// Call most of the public functions, and ignore their results.
+fn exercise(img: Vec<u8>) {
+	let opts = Options {
+		force: true,
+		..Options::default()
+	};
+	let Ok(ufs) = Ufs::with_options(SeekBackend::new(Cursor::new(img)), opts) else {
+		return;
+	};
+	let _ = ufs.lint();
+	let _ = ufs.check_deep();
+	let _ = ufs.free_extents();
+	ufs.walk(InodeNum::ROOT).take(64).for_each(drop);
+
+	for attr in ufs.inodes().take(64).flatten() {
+		let inr = attr.inr;
+		let _ = ufs.inode_extents(inr);
+		let _ = ufs.inode_seek(inr, attr.size / 2, Whence::Hole);
+		let _ = ufs.xattr_list(inr);
+		let _ = ufs.acl(inr);
+		let _ = ufs.symlink_read(inr);
+		let _ = ufs.dir_slack(inr);
+		ufs.dir_entries(inr).for_each(drop);
+		let mut buf = [0u8; 4096];
+		let _ = ufs.inode_read(inr, 0, &mut buf);
+		let _ = ufs.inode_read(inr, u64::MAX - 1, &mut buf);
+	}
+}
+
+#[test]
+fn corrupted_metadata() {
+	let golden = golden_image("ufs-little");
+	let mut rng = Rng(0x1234_5678);
+	for round in 0..ROUNDS {
+		let mut img = golden.clone();
+		let mut words = Vec::new();
+		for _ in 0..(1 + rng.next() % 8) {
+			let off = match rng.next() % 3 {
+				0 => SBLOCK + (rng.next() % 1400) as usize,
+				1 => CG0 + (rng.next() % FSIZE as u64) as usize,
+				_ => INODES + (rng.next() % (8 * FSIZE) as u64) as usize,
+			} & !7;
+			let value = match rng.next() % 4 {
+				0 => u64::MAX,
+				1 => 0,
+				2 => 1 << (rng.next() % 64),
+				_ => rng.next(),
+			};
+			img[off..(off + 8)].copy_from_slice(&value.to_le_bytes());
+			words.push((off, value));
+		}
+		let res = panic::catch_unwind(|| exercise(img));
+		assert!(res.is_ok(), "round {round}: panicked with {words:x?}");
+	}
+}
@@ -0,0 +1,88 @@
+//! Filesystems within partitions of a disk.
+mod support;
+
+use std::io::Cursor;
+
+use rufs::{find_ufs_partition, partitions, InodeNum, SeekBackend, Ufs, WindowedBackend};
+use support::*;
+
+/// Offset of the filesystem partition in sectors.
+const START: u64 = 2048;
+
+/// Type GUID of a `freebsd-boot` partition.
+const FREEBSD_BOOT: [u8; 16] = [
+	0x9d, 0x6b, 0xbd, 0x83, 0x41, 0x7f, 0xdc, 0x11, 0xbe, 0x0b, 0x00, 0x15, 0x60, 0xb8, 0x4f, 0x0f,
+];
+
+/// Type GUID of a `freebsd-ufs` partition.
+const FREEBSD_UFS: [u8; 16] = [
+	0xb6, 0x7c, 0x6e, 0x51, 0xcf, 0x6e, 0xd6, 0x11, 0x8f, 0xf8, 0x00, 0x02, 0x2d, 0x09, 0x71, 0x2b,
+];
+
+/// A GPT-partitioned disk with 512-byte sectors, like `gpart` would create it,
+/// with a boot partition, and the little-endian golden image in the 2nd partition.
+fn disk() -> Vec<u8> {
+	let img = golden_image("ufs-little");
+	let mut disk = vec![0u8; START as usize * 512 + img.len()];
+	disk[(START as usize * 512)..].copy_from_slice(&img);
+
+	// Protective MBR
+	disk[446 + 4] = 0xee;
+	disk[510..512].copy_from_slice(&[0x55, 0xaa]);
+
+	let hdr = &mut disk[512..1024];
+	hdr[0..8].copy_from_slice(b"EFI PART");
+	hdr[72..80].copy_from_slice(&2u64.to_le_bytes());
+	hdr[80..84].copy_from_slice(&128u32.to_le_bytes());
+	hdr[84..88].copy_from_slice(&128u32.to_le_bytes());
+
+	let parts = [
+		(FREEBSD_BOOT, 40, 1063),
+		(FREEBSD_UFS, START, START + img.len() as u64 / 512 - 1),
+	];
+	for (i, (kind, first, last)) in parts.into_iter().enumerate() {
+		let e = &mut disk[(1024 + i * 128)..(1024 + (i + 1) * 128)];
+		e[0..16].copy_from_slice(&kind);
+		e[32..40].copy_from_slice(&first.to_le_bytes());
+		e[40..48].copy_from_slice(&last.to_le_bytes());
+	}
+	disk
+}
+
+#[test]
+fn gpt() {
+	let backend = SeekBackend::new(Cursor::new(disk()));
+	let parts = partitions(&backend).unwrap();
+	let names = parts
+		.iter()
+		.map(|p| (p.name.as_str(), p.ufs))
+		.collect::<Vec<_>>();
+	assert_eq!(names, [("p1", false), ("p2", true)]);
+	assert_eq!(parts[1].start, START * 512);
+	assert_eq!(parts[1].size, 4 << 20);
+	assert!(parts[1].matches("2"));
+	assert!(parts[1].matches("p2"));
+}
+
+#[test]
+fn mount() {
+	let backend = SeekBackend::new(Cursor::new(disk()));
+	let part = find_ufs_partition(&backend).unwrap().unwrap();
+	assert_eq!(part.name, "p2");
+
+	let ufs = Ufs::new(WindowedBackend::new(backend, part.start, Some(part.size))).unwrap();
+	let golden = open_golden("ufs-little");
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+	let mut buf = [0u8; 64];
+	let mut expected = [0u8; 64];
+	let n = ufs.inode_read(inr, 0, &mut buf).unwrap();
+	golden.inode_read(inr, 0, &mut expected).unwrap();
+	assert_eq!(buf[..n], expected[..n]);
+}
+
+/// A filesystem on the whole disk isn't mistaken for a partition table.
+#[test]
+fn whole() {
+	let backend = SeekBackend::new(Cursor::new(golden_image("ufs-little")));
+	assert_eq!(find_ufs_partition(&backend).unwrap(), None);
+}
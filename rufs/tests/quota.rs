@@ -0,0 +1,126 @@
+//! Disk quotas, which are read from "quota.user" and "quota.group".
+mod support;
+
+use std::io::Cursor;
+
+use rufs::{InodeNum, Quota, SeekBackend, Ufs};
+use support::*;
+
+fn find(img: &[u8], pattern: &[u8]) -> usize {
+	img.windows(pattern.len())
+		.position(|w| w == pattern)
+		.expect("pattern not found")
+}
+
+/// The golden image `name`, in which the symlink "long-link" was turned into a quota file
+/// of 1023 bytes, which starts with `data`.
+fn with_quota_file(name: &str, file: &str, data: &[u8]) -> MemUfs {
+	let mut img = golden_image(name);
+	let big = name == "ufs-big";
+	let inr = open_golden(name)
+		.dir_lookup(InodeNum::ROOT, "long-link".as_ref())
+		.unwrap();
+
+	// The entry has room for a longer name: type (DT_LNK), length of the name, name
+	let pos = find(&img, b"\x0a\x09long-link");
+	img[pos] = 8;
+	img[pos + 1] = file.len() as u8;
+	img[(pos + 2)..(pos + 2 + file.len())].copy_from_slice(file.as_bytes());
+	img[pos + 2 + file.len()] = 0;
+
+	// S_IFREG | 0644
+	let mode = 0o100644u16;
+	let mode = if big {
+		mode.to_be_bytes()
+	} else {
+		mode.to_le_bytes()
+	};
+	patch_inode(&mut img, inr, DI_MODE, &mode);
+
+	let target = [b"./".repeat(508), b"//file1".to_vec()].concat();
+	let pos = find(&img, &target);
+	img[pos..(pos + data.len())].copy_from_slice(data);
+	Ufs::new(SeekBackend::new(Cursor::new(img))).unwrap()
+}
+
+fn quota() -> Quota {
+	Quota {
+		block_hard_limit: 2000,
+		block_soft_limit: 1000,
+		blocks:           1500,
+		inode_hard_limit: 20,
+		inode_soft_limit: 10,
+		inodes:           5,
+		block_time:       1_700_000_000,
+		inode_time:       0,
+	}
+}
+
+fn fields(q: &Quota) -> [u64; 8] {
+	[
+		q.block_hard_limit,
+		q.block_soft_limit,
+		q.blocks,
+		q.inode_hard_limit,
+		q.inode_soft_limit,
+		q.inodes,
+		q.block_time as u64,
+		q.inode_time as u64,
+	]
+}
+
+#[test]
+fn quota64() {
+	// struct dqhdr64, followed by the records of id 0 and 1
+	let mut data = b"QUOTA64\0".to_vec();
+	for x in [1u32, 64, 64] {
+		data.extend_from_slice(&x.to_be_bytes());
+	}
+	data.resize(128, 0);
+	for x in fields(&quota()) {
+		data.extend_from_slice(&x.to_be_bytes());
+	}
+
+	for name in ["ufs-little", "ufs-big"] {
+		let ufs = with_quota_file(name, "quota.user", &data);
+		assert_eq!(ufs.quota_for_uid(0).unwrap(), Some(Quota::default()));
+		assert_eq!(ufs.quota_for_uid(1).unwrap(), Some(quota()));
+		// 64 + 15 * 64 = 1024 is beyond the end of the file.
+		assert_eq!(ufs.quota_for_uid(15).unwrap(), None);
+		assert_eq!(ufs.quota_for_gid(1).unwrap(), None);
+	}
+}
+
+/// Old quota files have no header, and use the byte order of the filesystem.
+#[test]
+fn quota32() {
+	for name in ["ufs-little", "ufs-big"] {
+		let mut data = vec![0u8; 32];
+		for x in fields(&quota()) {
+			let x = x as u32;
+			let x = if name == "ufs-big" {
+				x.to_be_bytes()
+			} else {
+				x.to_le_bytes()
+			};
+			data.extend_from_slice(&x);
+		}
+
+		let ufs = with_quota_file(name, "quota.group", &data);
+		assert_eq!(ufs.quota_for_gid(1).unwrap(), Some(quota()));
+		assert!(ufs.quota_for_gid(30).unwrap().is_some());
+		assert_eq!(ufs.quota_for_gid(31).unwrap(), None);
+		assert_eq!(ufs.quota_for_uid(1).unwrap(), None);
+	}
+}
+
+#[test]
+fn bad_header() {
+	let mut data = b"QUOTA64\0".to_vec();
+	for x in [2u32, 64, 64] {
+		data.extend_from_slice(&x.to_be_bytes());
+	}
+	let ufs = with_quota_file("ufs-little", "quota.user", &data);
+	let e = ufs.quota_for_uid(1).unwrap_err();
+	assert_eq!(e.raw_os_error(), Some(libc::EINVAL));
+}
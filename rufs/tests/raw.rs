@@ -0,0 +1,185 @@
+//! Reading and writing fragments of the filesystem directly.
+mod support;
+
+use std::{
+	fs::File,
+	io::{Cursor, Write},
+	os::unix::fs::FileExt,
+};
+
+use rufs::{BlockFile, InodeNum, SeekBackend, Ufs};
+use support::*;
+
+/// Size of the golden images, in fragments.
+const SIZE: u64 = 1024;
+/// The superblock of each cylinder group.
+const SBLKNO: usize = 24;
+/// The summary area follows the inodes of the first cylinder group.
+const CSADDR: usize = 56;
+
+fn image_file(img: &[u8]) -> File {
+	let mut f = tempfile::tempfile().unwrap();
+	f.write_all(img).unwrap();
+	f
+}
+
+fn errno<T>(res: std::io::Result<T>) -> Option<i32> {
+	res.err().and_then(|e| e.raw_os_error())
+}
+
+#[test]
+fn read() {
+	let img = golden_image("ufs-little");
+	let ufs = open_golden("ufs-little");
+	let mut buf = vec![0u8; 3 * FSIZE];
+	ufs.read_raw_block(100, &mut buf).unwrap();
+	assert!(buf == img[(100 * FSIZE)..(103 * FSIZE)]);
+
+	// Partial fragments can be read as well.
+	let mut buf = [0u8; 10];
+	ufs.read_raw_block(100, &mut buf).unwrap();
+	assert_eq!(buf, img[(100 * FSIZE)..(100 * FSIZE + 10)]);
+}
+
+#[test]
+fn bounds() {
+	let ufs = open_golden("ufs-little");
+	let mut buf = vec![0u8; FSIZE];
+	ufs.read_raw_block(SIZE - 1, &mut buf).unwrap();
+	assert_eq!(
+		errno(ufs.read_raw_block(SIZE, &mut buf)),
+		Some(libc::EINVAL)
+	);
+	assert_eq!(
+		errno(ufs.read_raw_block(SIZE - 1, &mut [0u8; FSIZE + 1])),
+		Some(libc::EINVAL)
+	);
+	assert_eq!(
+		errno(ufs.read_raw_block(u64::MAX, &mut buf)),
+		Some(libc::EINVAL)
+	);
+
+	let backend = SeekBackend::new(Cursor::new(golden_image("ufs-little")));
+	let ufs = Ufs::with_options(backend, raw_writes()).unwrap();
+	assert_eq!(errno(ufs.write_raw_block(SIZE, &buf)), Some(libc::EINVAL));
+}
+
+/// Patching the data of a file changes its contents, and nothing else.
+#[test]
+fn write() {
+	let img = golden_image("ufs-little");
+	let file = image_file(&img);
+	let ufs = Ufs::with_options(
+		BlockFile::new(file.try_clone().unwrap(), 4096),
+		raw_writes(),
+	)
+	.unwrap();
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+	let ext = ufs.inode_extents(inr).unwrap()[0];
+	let daddr = ext.physical / FSIZE as u64;
+
+	let mut data = vec![0u8; FSIZE];
+	data[..6].copy_from_slice(b"Hello\n");
+	ufs.write_raw_block(daddr, &data).unwrap();
+	ufs.sync().unwrap();
+
+	let mut buf = [0u8; 6];
+	ufs.inode_read(inr, 0, &mut buf).unwrap();
+	assert_eq!(&buf, b"Hello\n");
+
+	let mut expected = img.clone();
+	let pos = ext.physical as usize;
+	expected[pos..(pos + FSIZE)].copy_from_slice(&data);
+	let mut actual = vec![0u8; img.len()];
+	file.read_exact_at(&mut actual, 0).unwrap();
+	assert!(actual == expected);
+	assert_eq!(ufs.check_deep().unwrap(), []);
+}
+
+/// Metadata can't be overwritten.
+#[test]
+fn metadata() {
+	let img = golden_image("ufs-little");
+	let file = image_file(&img);
+	let ufs = Ufs::with_options(
+		BlockFile::new(file.try_clone().unwrap(), 4096),
+		raw_writes(),
+	)
+	.unwrap();
+	let data = vec![0u8; FSIZE];
+
+	// The boot area, superblocks, cylinder groups, inodes and the summary area.
+	for daddr in [
+		0,
+		SBLKNO,
+		DBLKNO - 1,
+		FPG + SBLKNO,
+		FPG + DBLKNO - 1,
+		CSADDR,
+	] {
+		let res = ufs.write_raw_block(daddr as u64, &data);
+		assert_eq!(errno(res), Some(libc::EPERM), "{daddr}");
+	}
+	// Writes, which only partially overlap, are refused as well.
+	let res = ufs.write_raw_block(DBLKNO as u64 - 1, &vec![0u8; 2 * FSIZE]);
+	assert_eq!(errno(res), Some(libc::EPERM));
+	assert_eq!(
+		errno(ufs.write_raw_block((FPG + DBLKNO) as u64, &data[..100])),
+		Some(libc::EINVAL)
+	);
+
+	let mut actual = vec![0u8; img.len()];
+	file.read_exact_at(&mut actual, 0).unwrap();
+	assert!(actual == img);
+}
+
+/// Nothing can be overwritten, unless the filesystem was opened with `WriteCaps::raw`.
+#[test]
+fn read_only() {
+	let img = golden_image("ufs-little");
+	let file = image_file(&img);
+	let ufs = Ufs::new(BlockFile::new(file.try_clone().unwrap(), 4096)).unwrap();
+	let data = vec![0u8; FSIZE];
+	assert_eq!(errno(ufs.write_raw_block(100, &data)), Some(libc::EROFS));
+	let res = ufs.with_txn(|txn| txn.write_raw_block(100, &data));
+	assert_eq!(errno(res), Some(libc::EROFS));
+
+	let mut actual = vec![0u8; img.len()];
+	file.read_exact_at(&mut actual, 0).unwrap();
+	assert!(actual == img);
+}
+
+/// Indirect blocks and the blocks of directories can't be overwritten, but data blocks can.
+#[test]
+fn structure() {
+	let img = golden_image("ufs-little");
+	let file = image_file(&img);
+	let ufs = Ufs::with_options(
+		BlockFile::new(file.try_clone().unwrap(), 4096),
+		raw_writes(),
+	)
+	.unwrap();
+	let data = vec![0u8; FSIZE];
+
+	let dir = ufs.inode_extents(InodeNum::ROOT).unwrap()[0];
+	let res = ufs.write_raw_block(dir.physical / FSIZE as u64, &data);
+	assert_eq!(errno(res), Some(libc::EPERM));
+
+	// "file3" has 1M, so it needs an indirect block (di_ib[0]).
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "file3".as_ref()).unwrap();
+	let pos = inode_offset(inr);
+	let mut ino = vec![0u8; FSIZE];
+	ufs.read_raw_block((pos / FSIZE) as u64, &mut ino).unwrap();
+	let off = pos % FSIZE + DI_IB;
+	let ib = u64::from_le_bytes(ino[off..(off + 8)].try_into().unwrap());
+	assert_ne!(ib, 0);
+	assert_eq!(errno(ufs.write_raw_block(ib, &data)), Some(libc::EPERM));
+
+	let mut actual = vec![0u8; img.len()];
+	file.read_exact_at(&mut actual, 0).unwrap();
+	assert!(actual == img);
+
+	let ext = ufs.inode_extents(inr).unwrap()[0];
+	ufs.write_raw_block(ext.physical / FSIZE as u64, &data)
+		.unwrap();
+}
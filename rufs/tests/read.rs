@@ -0,0 +1,68 @@
+//! Reading the data of files, with requests of any size and position.
+mod support;
+
+use rufs::InodeNum;
+use support::*;
+
+/// Requests, which extend beyond the end of a file, are cut short.
+#[test]
+fn eof() {
+	for image in ["ufs-little", "ufs-big"] {
+		let ufs = open_golden(image);
+		let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+		let size = ufs.inode_attr(inr).unwrap().size;
+		assert_eq!(size, 23);
+
+		// Much larger than a block, like with a big `max_read`.
+		let mut buf = vec![0xffu8; 1 << 20];
+		assert_eq!(ufs.inode_read(inr, 0, &mut buf).unwrap(), 23);
+		assert!(buf[23..].iter().all(|&b| b == 0xff));
+		assert_eq!(ufs.inode_read(inr, 20, &mut buf).unwrap(), 3);
+
+		for pos in [size, 4096, 1 << 40, u64::MAX] {
+			assert_eq!(ufs.inode_read(inr, pos, &mut buf).unwrap(), 0, "{pos}");
+		}
+	}
+}
+
+/// Large requests return the same data as many small ones.
+#[test]
+fn chunks() {
+	let ufs = open_golden("ufs-little");
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "sparse".as_ref()).unwrap();
+	let size = ufs.inode_attr(inr).unwrap().size as usize;
+
+	let mut whole = vec![0u8; size + 12345];
+	assert_eq!(ufs.inode_read(inr, 0, &mut whole).unwrap(), size);
+	whole.truncate(size);
+
+	let mut data = Vec::new();
+	let mut buf = [0u8; 3000];
+	loop {
+		let n = ufs.inode_read(inr, data.len() as u64, &mut buf).unwrap();
+		if n == 0 {
+			break;
+		}
+		data.extend_from_slice(&buf[0..n]);
+	}
+	assert!(data == whole, "contents differ");
+}
+
+/// Reads, which don't start at the beginning of a block, return the data at their offset.
+#[test]
+fn offset() {
+	let ufs = open_golden("ufs-little");
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+	let mut buf = [0u8; 6];
+	assert_eq!(ufs.inode_read(inr, 10, &mut buf).unwrap(), 6);
+	assert_eq!(&buf, b"simple");
+
+	// Within the first fragment, within the first block, and across two blocks.
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "file3".as_ref()).unwrap();
+	let data: String = (0..(1 << 16)).map(|i| format!("{i:015x}\n")).collect();
+	for pos in [4, 4096 + 7, 32768 - 20] {
+		let mut buf = [0u8; 40];
+		assert_eq!(ufs.inode_read(inr, pos as u64, &mut buf).unwrap(), 40);
+		assert_eq!(buf, data.as_bytes()[pos..(pos + 40)], "{pos}");
+	}
+}
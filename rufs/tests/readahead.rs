@@ -0,0 +1,41 @@
+//! Prefetching of data into the block cache, when files are read sequentially.
+mod support;
+
+use std::io::Cursor;
+
+use rufs::{BlockCache, CacheStats, InodeNum, Options, SeekBackend, Ufs, Whence};
+use support::*;
+
+/// Read the data at the end of "sparse" in small chunks, and return it along with the cache statistics.
+fn read_sparse(readahead: u64) -> (Vec<u8>, CacheStats) {
+	let backend = SeekBackend::new(Cursor::new(golden_image("ufs-little")));
+	let opts = Options {
+		readahead,
+		..Options::default()
+	};
+	let ufs = Ufs::with_options(BlockCache::new(backend, 4 << 20), opts).unwrap();
+
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "sparse".as_ref()).unwrap();
+	let size = ufs.inode_attr(inr).unwrap().size;
+	let mut pos = ufs.inode_seek(inr, 0, Whence::Data).unwrap();
+
+	let mut data = Vec::new();
+	let mut buf = [0u8; 512];
+	while pos < size {
+		let len = buf.len().min((size - pos) as usize);
+		let n = ufs.inode_read(inr, pos, &mut buf[0..len]).unwrap();
+		data.extend_from_slice(&buf[0..n]);
+		pos += n as u64;
+	}
+	(data, ufs.cache_stats())
+}
+
+#[test]
+fn sequential() {
+	let (expected, plain) = read_sparse(0);
+	let (data, st) = read_sparse(64 << 10);
+	assert!(data == expected, "contents differ");
+	assert_eq!(plain.prefetched, 0);
+	assert!(st.prefetched > 0);
+	assert!(st.misses < plain.misses, "{st:?} vs. {plain:?}");
+}
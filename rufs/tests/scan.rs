@@ -0,0 +1,47 @@
+//! Scanning the inode tables.
+mod support;
+
+use std::{
+	collections::{HashMap, HashSet},
+	time::Duration,
+};
+
+use rufs::{InodeAttr, InodeNum};
+use support::*;
+
+#[test]
+fn inodes() {
+	for name in ["ufs-little", "ufs-big"] {
+		let ufs = open_golden(name);
+		let inodes = ufs
+			.inodes()
+			.map(|attr| attr.map(|a| (a.inr, a)))
+			.collect::<std::io::Result<HashMap<_, _>>>()
+			.unwrap();
+
+		let mut linked = HashSet::new();
+		for entry in ufs.walk(InodeNum::ROOT) {
+			let (_, inr, attr) = entry.unwrap();
+			assert_eq!(inodes.get(&inr), Some(&attr), "{name}: {inr}");
+			linked.insert(inr);
+		}
+		// All allocated inodes of the golden images are linked.
+		assert_eq!(linked.len(), inodes.len(), "{name}");
+	}
+}
+
+#[test]
+fn changed_since() {
+	let ufs = open_golden("ufs-little");
+	let times = |a: &InodeAttr| [a.mtime, a.ctime, a.btime].into_iter().max().unwrap();
+	let newest = ufs.inodes().map(Result::unwrap).max_by_key(times).unwrap();
+	let since = times(&newest);
+
+	assert_eq!(ufs.changed_since(since).count(), 0);
+	let changed = ufs
+		.changed_since(since - Duration::from_nanos(1))
+		.map(|attr| attr.unwrap().inr)
+		.collect::<Vec<_>>();
+	assert!(changed.contains(&newest.inr), "{changed:?}");
+	assert!(changed.len() < ufs.inodes().count());
+}
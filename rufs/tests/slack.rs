@@ -0,0 +1,99 @@
+//! Finding the remnants of deleted directory entries.
+mod support;
+
+use std::{ffi::OsStr, io::Cursor};
+
+use rufs::{InodeNum, InodeType, SeekBackend, Ufs};
+use support::*;
+
+/// Size of a chunk of directory entries.
+const DIRBLKSIZ: usize = 512;
+
+/// Find the offset of the directory entry `name`, referring to `inr`, in `img`.
+fn find_entry(img: &[u8], inr: u32, name: &str) -> usize {
+	let pos = img
+		.windows(name.len() + 8)
+		.position(|w| {
+			w[0..4] == inr.to_le_bytes() &&
+				w[7] as usize == name.len() &&
+				&w[8..] == name.as_bytes()
+		})
+		.expect("no such directory entry");
+	assert_eq!(pos % 4, 0);
+	pos
+}
+
+fn reclen(img: &[u8], pos: usize) -> u16 {
+	u16::from_le_bytes(img[(pos + 4)..(pos + 6)].try_into().unwrap())
+}
+
+/// Delete the entry at `pos`, like FreeBSD does, by extending the entry in front of it.
+fn delete(img: &mut [u8], pos: usize) {
+	let mut prev = pos - pos % DIRBLKSIZ;
+	assert_ne!(prev, pos, "the entry is the first one in its chunk");
+	while prev + (reclen(img, prev) as usize) < pos {
+		prev += reclen(img, prev) as usize;
+	}
+	assert_eq!(prev + reclen(img, prev) as usize, pos);
+	let len = reclen(img, prev) + reclen(img, pos);
+	img[(prev + 4)..(prev + 6)].copy_from_slice(&len.to_le_bytes());
+}
+
+fn open(img: Vec<u8>) -> MemUfs {
+	Ufs::new(SeekBackend::new(Cursor::new(img))).unwrap()
+}
+
+#[test]
+fn deleted() {
+	let mut img = golden_image("ufs-little");
+	let pos = find_entry(&img, 4, "file1");
+	delete(&mut img, pos);
+	let ufs = open(img);
+
+	let e = ufs
+		.dir_lookup(InodeNum::ROOT, "file1".as_ref())
+		.unwrap_err();
+	assert_eq!(e.raw_os_error(), Some(libc::ENOENT));
+
+	let slack = ufs.dir_slack(InodeNum::ROOT).unwrap();
+	let r = slack
+		.iter()
+		.flat_map(|s| &s.remnants)
+		.find(|r| r.name == "file1")
+		.expect("no remnant of file1");
+	assert_eq!(r.inr.map(|inr| inr.get()), Some(4));
+	assert_eq!(r.kind, Some(InodeType::RegularFile));
+	assert_eq!(r.offset as usize % DIRBLKSIZ, pos % DIRBLKSIZ);
+
+	// The remnant is within the unused space.
+	let s = slack.iter().find(|s| s.remnants.contains(r)).unwrap();
+	let off = (r.offset - s.offset) as usize;
+	assert_eq!(&s.data[(off + 8)..(off + 13)], b"file1");
+}
+
+/// The inode number of the first entry in a chunk is cleared.
+#[test]
+fn cleared() {
+	let ufs = open(golden_image("ufs-little"));
+	let dir1 = ufs.dir_lookup(InodeNum::ROOT, "dir1".as_ref()).unwrap();
+
+	let mut img = golden_image("ufs-little");
+	let pos = find_entry(&img, dir1.get(), ".");
+	assert_eq!(pos % DIRBLKSIZ, 0);
+	img[pos..(pos + 4)].fill(0);
+	let ufs = open(img);
+
+	let slack = ufs.dir_slack(dir1).unwrap();
+	let r = &slack[0].remnants[0];
+	assert_eq!((r.offset, r.inr), (0, None));
+	assert_eq!(r.name, OsStr::new("."));
+	assert_eq!(r.kind, Some(InodeType::Directory));
+}
+
+#[test]
+fn not_a_directory() {
+	let ufs = open(golden_image("ufs-little"));
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+	let e = ufs.dir_slack(inr).unwrap_err();
+	assert_eq!(e.raw_os_error(), Some(libc::ENOTDIR));
+}
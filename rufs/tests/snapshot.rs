@@ -0,0 +1,124 @@
+//! Snapshot inodes, which have special block mappings.
+mod support;
+
+use std::io::Cursor;
+
+use rufs::{FsckFinding, InodeNum, SeekBackend, Ufs, Whence};
+use support::*;
+
+const SF_SNAPSHOT: u32 = 0x0020_0000;
+const BLK_NOCOPY: u64 = 1;
+const BLK_SNAP: u64 = 2;
+
+/// The little-endian golden image, where "file3" was turned into a snapshot.
+/// Its 2nd block was free, the 3rd block wasn't copied yet, and the 4th is held by another snapshot.
+fn snapshot() -> (MemUfs, InodeNum, Vec<u8>) {
+	let mut img = golden_image("ufs-little");
+	let inr = open_golden("ufs-little")
+		.dir_lookup(InodeNum::ROOT, "file3".as_ref())
+		.unwrap();
+
+	let off = inode_offset(inr) + DI_FLAGS;
+	let flags = u32::from_le_bytes(img[off..(off + 4)].try_into().unwrap());
+	patch_inode(
+		&mut img,
+		inr,
+		DI_FLAGS,
+		&(flags | SF_SNAPSHOT).to_le_bytes(),
+	);
+	for (i, ptr) in [(1, BLK_NOCOPY), (2, 0), (3, BLK_SNAP)] {
+		patch_inode(&mut img, inr, DI_DB + i * 8, &ptr.to_le_bytes());
+	}
+
+	let ufs = Ufs::new(SeekBackend::new(Cursor::new(img.clone()))).unwrap();
+	(ufs, inr, img)
+}
+
+/// A snapshot created by mksnap_ffs(8), right after the golden image was populated.
+///
+/// It can only be created on FreeBSD, using `./scripts/mkimg.sh -S`. The CI does that,
+/// and runs this test with `--include-ignored`.
+#[test]
+#[ignore = "needs resources/ufs-little-snap.img.zst, created by ./scripts/mkimg.sh -S"]
+fn golden() {
+	let img = golden_image("ufs-little-snap");
+	let ufs = Ufs::new(SeekBackend::new(Cursor::new(img.clone()))).unwrap();
+	assert_eq!(ufs.check_deep().unwrap(), []);
+
+	let snap = ufs.dir_lookup(InodeNum::ROOT, ".snap".as_ref()).unwrap();
+	let inr = ufs.dir_lookup(snap, "snap1".as_ref()).unwrap();
+	let attr = ufs.inode_attr(inr).unwrap();
+	assert_ne!(attr.flags & SF_SNAPSHOT, 0);
+	assert_eq!(attr.perm & 0o222, 0, "snapshots are read-only");
+	assert_eq!(attr.size, img.len() as u64);
+
+	// Nothing changed since the snapshot was taken, so it holds the same files.
+	let file1 = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+	let ext = ufs.inode_extents(file1).unwrap()[0];
+	let mut buf = [0u8; 23];
+	assert_eq!(ufs.inode_read(inr, ext.physical, &mut buf).unwrap(), 23);
+	assert_eq!(&buf, b"This is a simple file.\n");
+
+	// Free blocks aren't copied, so they are holes.
+	let hole = ufs.inode_seek(inr, 0, Whence::Hole).unwrap();
+	assert!(hole < attr.size, "{hole}");
+}
+
+#[test]
+fn attr() {
+	let (ufs, inr, _) = snapshot();
+	let attr = ufs.inode_attr(inr).unwrap();
+	assert_ne!(attr.flags & SF_SNAPSHOT, 0);
+	assert_eq!(attr.perm & 0o222, 0, "snapshots are read-only");
+}
+
+#[test]
+fn read() {
+	let (ufs, inr, img) = snapshot();
+	let golden = open_golden("ufs-little");
+	let mut buf = vec![0u8; 4 * BSIZE];
+	let mut expected = vec![0u8; BSIZE];
+	ufs.inode_read(inr, 0, &mut buf).unwrap();
+
+	golden.inode_read(inr, 0, &mut expected).unwrap();
+	assert!(buf[0..BSIZE] == expected, "allocated block differs");
+	assert!(
+		buf[BSIZE..(2 * BSIZE)].iter().all(|&b| b == 0),
+		"BLK_NOCOPY isn't zero"
+	);
+	assert!(
+		buf[(2 * BSIZE)..(3 * BSIZE)] == img[(2 * BSIZE)..(3 * BSIZE)],
+		"unallocated block doesn't refer to the filesystem"
+	);
+	assert!(
+		buf[(3 * BSIZE)..(4 * BSIZE)].iter().all(|&b| b == 0),
+		"BLK_SNAP isn't zero"
+	);
+}
+
+#[test]
+fn seek() {
+	let (ufs, inr, _) = snapshot();
+	let bs = BSIZE as u64;
+	assert_eq!(ufs.inode_seek(inr, 0, Whence::Hole).unwrap(), bs);
+	assert_eq!(ufs.inode_seek(inr, bs, Whence::Data).unwrap(), 2 * bs);
+	assert_eq!(ufs.inode_seek(inr, 2 * bs, Whence::Hole).unwrap(), 3 * bs);
+	assert_eq!(ufs.inode_seek(inr, 3 * bs, Whence::Data).unwrap(), 4 * bs);
+}
+
+#[test]
+fn fsck() {
+	let (ufs, _, _) = snapshot();
+	let findings = ufs.check_deep().unwrap();
+	assert!(
+		!findings.iter().any(|f| {
+			matches!(
+				f,
+				FsckFinding::BadBlock { .. } |
+					FsckFinding::DuplicateBlock { .. } |
+					FsckFinding::FreeBlockInUse { .. }
+			)
+		}),
+		"{findings:?}"
+	);
+}
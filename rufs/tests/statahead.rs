@@ -0,0 +1,48 @@
+//! Prefetching of inodes into the block cache, when many entries of a directory are looked up.
+mod support;
+
+use std::io::Cursor;
+
+use rufs::{BlockCache, CacheStats, InodeNum, Options, SeekBackend, Ufs};
+use support::*;
+
+/// Look up and stat every entry of the root directory, like `ls -l` does.
+fn ls_l(statahead: usize) -> (Vec<(InodeNum, u64)>, CacheStats) {
+	let backend = SeekBackend::new(Cursor::new(golden_image("ufs-little")));
+	let opts = Options {
+		statahead,
+		..Options::default()
+	};
+	// Small cache blocks, so that the inodes of the entries don't share a single block.
+	let cache = BlockCache::with_block_size(backend, 4 << 20, 512);
+	let ufs = Ufs::with_options(cache, opts).unwrap();
+	let dir = InodeNum::ROOT;
+
+	let mut names = Vec::new();
+	ufs.dir_iter(dir, |name, _, _| {
+		names.push(name.to_owned());
+		None::<()>
+	})
+	.unwrap();
+
+	let attrs = names
+		.iter()
+		.map(|name| {
+			let inr = ufs.dir_lookup(dir, name).unwrap();
+			let attr = ufs.inode_attr(inr).unwrap();
+			(attr.inr, attr.size)
+		})
+		.collect();
+	(attrs, ufs.cache_stats())
+}
+
+#[test]
+fn storm() {
+	let (expected, plain) = ls_l(0);
+	let (attrs, st) = ls_l(1024);
+	assert!(expected.len() > 8, "not enough entries: {}", expected.len());
+	assert_eq!(attrs, expected);
+	assert_eq!(plain.prefetched, 0);
+	assert!(st.prefetched > 0);
+	assert!(st.misses < plain.misses, "{st:?} vs. {plain:?}");
+}
@@ -0,0 +1,108 @@
+//! Checking the summary in the superblock, when opening a filesystem.
+mod support;
+
+use std::io::Cursor;
+
+use rufs::{FsckFinding, Options, SeekBackend, SummaryCheck, Ufs};
+use support::*;
+
+/// Offset of `fs_cstotal.cs_nbfree` in the primary superblock.
+const NBFREE: usize = 65536 + 1008 + 8;
+
+/// Offset of `fs_metackhash` in the primary superblock, followed by `fs_flags`.
+const METACKHASH: usize = 65536 + 1308;
+
+/// Offset of the summary area, which starts with `cs_ndir` of the first group.
+const CSUM: usize = 56 * FSIZE;
+
+/// Offset of `fs_pendingblocks` in the primary superblock, followed by `fs_pendinginodes`.
+const PENDING: usize = 65536 + 1104;
+
+/// The little-endian golden image, with a stale number of free blocks in the superblock.
+fn stale() -> Vec<u8> {
+	let mut img = golden_image("ufs-little");
+	img[NBFREE..(NBFREE + 8)].copy_from_slice(&0i64.to_le_bytes());
+	rehash(&mut img);
+	img
+}
+
+fn open(img: Vec<u8>, summary: SummaryCheck) -> MemUfs {
+	let opts = Options {
+		summary,
+		..Options::default()
+	};
+	Ufs::with_options(SeekBackend::new(Cursor::new(img)), opts).unwrap()
+}
+
+#[test]
+fn clean() {
+	for summary in [SummaryCheck::Off, SummaryCheck::Check, SummaryCheck::Fix] {
+		let ufs = open(golden_image("ufs-little"), summary);
+		assert!(!ufs.stats().stale_summary);
+	}
+}
+
+#[test]
+fn off() {
+	let ufs = open(stale(), SummaryCheck::Off);
+	assert!(!ufs.stats().stale_summary);
+	assert_eq!(ufs.info().bfree, 38);
+}
+
+#[test]
+fn check() {
+	let ufs = open(stale(), SummaryCheck::Check);
+	assert!(ufs.stats().stale_summary);
+	assert_eq!(ufs.info().bfree, 38);
+}
+
+/// The free space is calculated from the cylinder groups instead.
+#[test]
+fn fix() {
+	let good = open(golden_image("ufs-little"), SummaryCheck::Off).info();
+	let ufs = open(stale(), SummaryCheck::Fix);
+	assert!(ufs.stats().stale_summary);
+	let info = ufs.info();
+	assert_eq!((info.bfree, info.ffree), (good.bfree, good.ffree));
+	assert_eq!(ufs.check_deep().unwrap(), []);
+}
+
+/// With check-hashes, the summaries are read from the cylinder groups, which they protect,
+/// instead of the summary area.
+#[test]
+fn summary_area() {
+	let mut img = golden_image("ufs-little");
+	img[CSUM..(CSUM + 4)].copy_from_slice(&100i32.to_le_bytes());
+	let ufs = open(img.clone(), SummaryCheck::Check);
+	assert!(!ufs.stats().stale_summary);
+
+	// Without check-hashes, like makefs(8) creates them, the summary area is all there is.
+	img[METACKHASH..(METACKHASH + 4)].copy_from_slice(&0u32.to_le_bytes());
+	let ufs = open(img, SummaryCheck::Check);
+	assert!(ufs.stats().stale_summary);
+}
+
+/// Blocks and inodes, which were being freed, when the system crashed, aren't free.
+#[test]
+fn pending() {
+	let good = open(golden_image("ufs-little"), SummaryCheck::Off).info();
+	assert_eq!((good.pending_blocks, good.pending_inodes), (0, 0));
+
+	let mut img = golden_image("ufs-little");
+	// In units of 512 bytes.
+	img[PENDING..(PENDING + 8)].copy_from_slice(&80i64.to_le_bytes());
+	img[(PENDING + 8)..(PENDING + 12)].copy_from_slice(&2u32.to_le_bytes());
+	rehash(&mut img);
+	let ufs = open(img, SummaryCheck::Check);
+	assert!(!ufs.stats().stale_summary);
+	let info = ufs.info();
+	assert_eq!((info.bfree, info.ffree), (good.bfree, good.ffree));
+	assert_eq!((info.pending_blocks, info.pending_inodes), (10, 2));
+	assert_eq!(
+		ufs.check_deep().unwrap(),
+		[FsckFinding::PendingFrees {
+			blocks: 10,
+			inodes: 2,
+		}]
+	);
+}
@@ -0,0 +1,132 @@
+//! Falling back to backup superblocks, if the primary one is damaged.
+mod support;
+
+use std::io::{Cursor, ErrorKind};
+
+use rufs::{Error, InodeNum, Options, SeekBackend, Ufs, WriteCaps};
+use support::*;
+
+/// Offset of the primary superblock.
+const SBLOCK: usize = 65536;
+
+/// Sector of the backup superblock of the first cylinder group in the golden images.
+const CG0_BACKUP: u64 = 24 * 4096 / 512;
+
+/// Sector of the backup superblock of the second cylinder group in the golden images.
+const CG1_BACKUP: u64 = (264 + 24) * 4096 / 512;
+
+/// The little-endian golden image, with a damaged primary superblock.
+fn damaged() -> Vec<u8> {
+	let mut img = golden_image("ufs-little");
+	img[SBLOCK..(SBLOCK + 8192)].fill(0xa5);
+	img
+}
+
+fn open(img: Vec<u8>, opts: Options) -> std::io::Result<MemUfs> {
+	Ufs::with_options(SeekBackend::new(Cursor::new(img)), opts)
+}
+
+fn assert_readable(ufs: &MemUfs) {
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+	assert!(ufs.inode_attr(inr).is_ok());
+	assert_eq!(ufs.info().bsize, 32768);
+}
+
+#[test]
+fn primary() {
+	let ufs = open(golden_image("ufs-little"), Options::default()).unwrap();
+	assert_eq!(ufs.stats().alternate_superblock, None);
+}
+
+/// The recovery information in front of the primary superblock points to the backups.
+#[test]
+fn fallback() {
+	for name in ["ufs-little", "ufs-big"] {
+		let mut img = golden_image(name);
+		img[SBLOCK..(SBLOCK + 8192)].fill(0);
+		let ufs = open(img, Options::default()).unwrap();
+		assert_eq!(ufs.stats().alternate_superblock, Some(CG0_BACKUP));
+		assert_readable(&ufs);
+	}
+}
+
+/// Damaged backups are skipped, but they are still inconsistencies of the filesystem.
+#[test]
+fn damaged_backup() {
+	let mut img = damaged();
+	let pos = CG0_BACKUP as usize * 512;
+	img[pos..(pos + 8192)].fill(0);
+	let e = open(img.clone(), Options::default()).err().unwrap();
+	assert_eq!(rufs::errno(&e), libc::EIO);
+	assert!(matches!(Error::of(&e), Some(Error::Corrupt { .. })), "{e}");
+	assert_ne!(e.kind(), ErrorKind::InvalidData);
+
+	let opts = Options {
+		force: true,
+		..Options::default()
+	};
+	let ufs = open(img, opts).unwrap();
+	assert_eq!(ufs.stats().alternate_superblock, Some(CG1_BACKUP));
+	assert_eq!(ufs.stats().ignored_checks, 1);
+	assert_readable(&ufs);
+}
+
+#[test]
+fn no_recovery_information() {
+	let mut img = damaged();
+	img[(SBLOCK - 20)..SBLOCK].fill(0);
+	assert!(open(img, Options::default()).is_err());
+}
+
+/// Like `fsck_ffs -b`.
+#[test]
+fn alternate() {
+	let opts = Options {
+		alternate_superblock: Some(CG1_BACKUP),
+		..Options::default()
+	};
+	let ufs = open(golden_image("ufs-little"), opts).unwrap();
+	assert_eq!(ufs.stats().alternate_superblock, Some(CG1_BACKUP));
+	assert_readable(&ufs);
+
+	let opts = Options {
+		alternate_superblock: Some(CG1_BACKUP + 1),
+		..Options::default()
+	};
+	assert!(open(damaged(), opts).is_err());
+}
+
+/// Filesystems are never modified using a backup superblock.
+#[test]
+fn read_only() {
+	let opts = Options {
+		write: WriteCaps {
+			create: true,
+			..WriteCaps::NONE
+		},
+		..Options::default()
+	};
+	let e = open(damaged(), opts).err().unwrap();
+	assert_eq!(e.raw_os_error(), Some(libc::EROFS));
+}
+
+/// Images without a filesystem are distinguishable from damaged filesystems.
+#[test]
+fn bad_magic() {
+	let e = open(vec![0u8; 1 << 20], Options::default()).err().unwrap();
+	assert_eq!(e.kind(), ErrorKind::InvalidData);
+	assert!(Error::of(&e).is_none());
+}
+
+#[test]
+fn ufs1() {
+	let mut img = vec![0u8; 1 << 20];
+	// Superblock offset (8192) + magic offset (1372).
+	img[9564..9568].copy_from_slice(&0x011954i32.to_le_bytes());
+	let e = open(img, Options::default()).err().unwrap();
+	assert_eq!(e.kind(), ErrorKind::Unsupported);
+	assert!(matches!(
+		Error::of(&e),
+		Some(Error::Unsupported { feature: "UFS1" })
+	));
+}
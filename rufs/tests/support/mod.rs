@@ -0,0 +1,246 @@
+// Test support code, shared between the doctests and the integration tests of rufs.
+//
+// Doctests can pull this in using:
+// ```
+// # mod support { include!(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/support/mod.rs")); }
+// # use support::*;
+// ```
+#[allow(unused_imports)]
+use std::{
+	fs::File,
+	io::{Cursor, Read},
+	path::PathBuf,
+};
+
+#[allow(unused_imports)]
+use rufs::{InodeNum, Options, SeekBackend, Ufs, WriteCaps};
+
+// Geometry of the golden images, in fragments, unless noted otherwise.
+/// Size of a fragment, in bytes.
+#[allow(dead_code)]
+pub const FSIZE: usize = 4096;
+/// Size of a block, in bytes.
+#[allow(dead_code)]
+pub const BSIZE: usize = 32768;
+/// Fragments per block.
+#[allow(dead_code)]
+pub const FRAG: usize = 8;
+/// Fragments per cylinder group.
+#[allow(dead_code)]
+pub const FPG: usize = 264;
+/// Inodes per cylinder group.
+#[allow(dead_code)]
+pub const IPG: usize = 256;
+/// Inodes per block.
+#[allow(dead_code)]
+pub const INOPB: usize = 128;
+/// Size of an inode, in bytes.
+#[allow(dead_code)]
+pub const INOSZ: usize = 256;
+/// Offset of the cylinder group header within a cylinder group.
+#[allow(dead_code)]
+pub const CBLKNO: usize = 32;
+/// Offset of the inodes within a cylinder group.
+#[allow(dead_code)]
+pub const IBLKNO: usize = 40;
+/// Offset of the first data block within a cylinder group.
+#[allow(dead_code)]
+pub const DBLKNO: usize = 56;
+
+// Byte offsets of the fields of `struct ufs2_dinode`, for `patch_inode()`.
+#[allow(dead_code)]
+pub const DI_MODE: usize = 0;
+#[allow(dead_code)]
+pub const DI_NLINK: usize = 2;
+#[allow(dead_code)]
+pub const DI_UID: usize = 4;
+#[allow(dead_code)]
+pub const DI_GID: usize = 8;
+#[allow(dead_code)]
+pub const DI_SIZE: usize = 16;
+#[allow(dead_code)]
+pub const DI_BLOCKS: usize = 24;
+#[allow(dead_code)]
+pub const DI_FLAGS: usize = 88;
+#[allow(dead_code)]
+pub const DI_EXTSIZE: usize = 92;
+#[allow(dead_code)]
+pub const DI_EXTB: usize = 96;
+#[allow(dead_code)]
+pub const DI_DB: usize = 112;
+#[allow(dead_code)]
+pub const DI_IB: usize = 208;
+#[allow(dead_code)]
+pub const DI_CKHASH: usize = 244;
+
+/// A filesystem, that lives entirely in memory.
+#[allow(dead_code)]
+pub type MemUfs = Ufs<SeekBackend<Cursor<Vec<u8>>>>;
+
+/// Decompress the golden image `name` (eg. "ufs-little") into memory.
+#[allow(dead_code)]
+pub fn golden_image(name: &str) -> Vec<u8> {
+	let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+	path.push("../resources");
+	path.push(name);
+	path.set_extension("img.zst");
+
+	let file = File::open(&path).unwrap_or_else(|e| panic!("failed to open {path:?}: {e}"));
+	let mut dec = ruzstd::StreamingDecoder::new(file)
+		.unwrap_or_else(|e| panic!("failed to uncompress {path:?}: {e}"));
+	let mut img = Vec::new();
+	dec.read_to_end(&mut img)
+		.unwrap_or_else(|e| panic!("failed to uncompress {path:?}: {e}"));
+	img
+}
+
+/// Open the golden image `name` (eg. "ufs-big") as an in-memory filesystem.
+#[allow(dead_code)]
+pub fn open_golden(name: &str) -> MemUfs {
+	let backend = SeekBackend::new(Cursor::new(golden_image(name)));
+	Ufs::new(backend).expect("failed to open golden image")
+}
+
+/// The little-endian golden image, used by the examples.
+#[allow(dead_code)]
+pub fn example_image() -> MemUfs {
+	open_golden("ufs-little")
+}
+
+/// Options, which allow overwriting fragments using `Ufs::write_raw_block()` and `Ufs::with_txn()`.
+#[allow(dead_code)]
+pub fn raw_writes() -> Options {
+	Options {
+		write: WriteCaps {
+			raw: true,
+			..WriteCaps::NONE
+		},
+		..Options::default()
+	}
+}
+
+/// The part of the geometry of an image, which is needed to find its inodes.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Geometry {
+	pub fsize:  usize,
+	pub frag:   usize,
+	pub fpg:    usize,
+	pub ipg:    usize,
+	pub inopb:  usize,
+	pub iblkno: usize,
+}
+
+#[allow(dead_code)]
+impl Geometry {
+	/// The geometry of the golden images.
+	pub const GOLDEN: Self = Self {
+		fsize:  FSIZE,
+		frag:   FRAG,
+		fpg:    FPG,
+		ipg:    IPG,
+		inopb:  INOPB,
+		iblkno: IBLKNO,
+	};
+
+	/// Read the geometry from the superblock of the little-endian image `img`.
+	pub fn of(img: &[u8]) -> Self {
+		let field = |off: usize| {
+			let pos = 65536 + off;
+			u32::from_le_bytes(img[pos..(pos + 4)].try_into().unwrap()) as usize
+		};
+		Self {
+			fsize:  field(52),
+			frag:   field(56),
+			fpg:    field(188),
+			ipg:    field(184),
+			inopb:  field(120),
+			iblkno: field(16),
+		}
+	}
+
+	/// Byte offset of the inode `inr`, in any cylinder group.
+	pub fn inode_offset(&self, inr: InodeNum) -> usize {
+		let (cg, idx) = (inr.get() as usize / self.ipg, inr.get() as usize % self.ipg);
+		(cg * self.fpg + self.iblkno + idx / self.inopb * self.frag) * self.fsize +
+			idx % self.inopb * INOSZ
+	}
+
+	/// Overwrite `bytes` at the byte offset `field` (eg. [`DI_MODE`]) of the inode `inr` in `img`.
+	///
+	/// The bytes must already be in the byte order of the image.
+	pub fn patch_inode(&self, img: &mut [u8], inr: InodeNum, field: usize, bytes: &[u8]) {
+		let pos = self.inode_offset(inr) + field;
+		img[pos..(pos + bytes.len())].copy_from_slice(bytes);
+	}
+}
+
+/// Byte offset of the inode `inr` in a golden image, in any cylinder group.
+#[allow(dead_code)]
+pub fn inode_offset(inr: InodeNum) -> usize {
+	Geometry::GOLDEN.inode_offset(inr)
+}
+
+/// Overwrite `bytes` at the byte offset `field` (eg. [`DI_MODE`]) of the inode `inr` in the
+/// golden image `img`.
+///
+/// The bytes must already be in the byte order of the image.
+#[allow(dead_code)]
+pub fn patch_inode(img: &mut [u8], inr: InodeNum, field: usize, bytes: &[u8]) {
+	Geometry::GOLDEN.patch_inode(img, inr, field, bytes)
+}
+
+/// Get the inode number `n`, which tests know to be valid in the golden images.
+#[allow(dead_code)]
+pub fn inr(n: u32) -> InodeNum {
+	unsafe { InodeNum::new(n) }
+}
+
+/// The check-hash of `buf`, with the check-hash itself at `field` treated as 0,
+/// like `calculate_crc32c(~0L, buf, len)` of FreeBSD.
+#[allow(dead_code)]
+pub fn ckhash(buf: &[u8], field: usize) -> u32 {
+	buf.iter().enumerate().fold(!0u32, |mut crc, (i, &b)| {
+		crc ^= if (field..(field + 4)).contains(&i) {
+			0
+		} else {
+			b as u32
+		};
+		for _ in 0..8 {
+			crc = if crc & 1 != 0 {
+				(crc >> 1) ^ 0x82f6_3b78
+			} else {
+				crc >> 1
+			};
+		}
+		crc
+	})
+}
+
+/// Recompute the check-hashes of the superblock, the cylinder groups and the allocated inodes
+/// of the little-endian golden image `img`, like the kernel would, when it writes them.
+///
+/// Tests, which corrupt the image, use this to model inconsistencies, that the kernel wrote.
+#[allow(dead_code)]
+pub fn rehash(img: &mut [u8]) {
+	const SBLOCK: usize = 65536;
+	let field = |img: &[u8], off: usize| {
+		u32::from_le_bytes(img[(SBLOCK + off)..(SBLOCK + off + 4)].try_into().unwrap()) as usize
+	};
+	let (ncg, sbsize, cgsize) = (field(img, 44), field(img, 104), field(img, 160));
+
+	fn update(img: &mut [u8], pos: usize, len: usize, field: usize) {
+		let hash = ckhash(&img[pos..(pos + len)], field);
+		img[(pos + field)..(pos + field + 4)].copy_from_slice(&hash.to_le_bytes());
+	}
+	for cg in 0..ncg {
+		for idx in 0..IPG {
+			let pos = inode_offset(inr((cg * IPG + idx) as u32));
+			if img[pos..(pos + 2)] != [0, 0] {
+				update(img, pos, INOSZ, DI_CKHASH);
+			}
+		}
+		update(img, (cg * FPG + CBLKNO) * FSIZE, cgsize, 132);
+	}
+	update(img, SBLOCK, sbsize, 1304);
+}
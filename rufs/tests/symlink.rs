@@ -0,0 +1,75 @@
+//! Reading and creating symbolic links.
+mod support;
+
+use std::io::Cursor;
+
+use rufs::{InodeNum, SeekBackend, Ufs, SYMLINK_MAX};
+use support::*;
+
+/// The little-endian golden image, in which the inode `inr` has the mode `mode` and size `size`.
+fn patched(inr: InodeNum, mode: Option<u16>, size: u64) -> MemUfs {
+	let mut img = golden_image("ufs-little");
+	if let Some(mode) = mode {
+		patch_inode(&mut img, inr, DI_MODE, &mode.to_le_bytes());
+	}
+	patch_inode(&mut img, inr, DI_SIZE, &size.to_le_bytes());
+	Ufs::new(SeekBackend::new(Cursor::new(img))).unwrap()
+}
+
+fn errno<T>(res: std::io::Result<T>) -> Option<i32> {
+	res.err().map(|e| rufs::errno(&e))
+}
+
+#[test]
+fn longest() {
+	let ufs = open_golden("ufs-little");
+	let inr = ufs
+		.dir_lookup(InodeNum::ROOT, "long-link".as_ref())
+		.unwrap();
+	let target = ufs.symlink_read(inr).unwrap();
+	assert_eq!(target.len(), SYMLINK_MAX);
+}
+
+/// Targets, which are longer than one block, are read completely.
+#[test]
+fn multiple_blocks() {
+	let len = 40000;
+	let file3 = open_golden("ufs-little");
+	let inr = file3.dir_lookup(InodeNum::ROOT, "file3".as_ref()).unwrap();
+	let mut expected = vec![0u8; len];
+	file3.inode_read(inr, 0, &mut expected).unwrap();
+
+	// Turn "file3" into a symbolic link.
+	let ufs = patched(inr, Some(0o120755), len as u64);
+	assert!(len > ufs.info().bsize as usize);
+	assert_eq!(ufs.symlink_read(inr).unwrap(), expected);
+}
+
+#[test]
+fn corrupted_length() {
+	let ufs = open_golden("ufs-little");
+	let link1 = ufs.dir_lookup(InodeNum::ROOT, "link1".as_ref()).unwrap();
+	let long = ufs
+		.dir_lookup(InodeNum::ROOT, "long-link".as_ref())
+		.unwrap();
+
+	let ufs = patched(link1, None, 200);
+	assert_eq!(errno(ufs.symlink_read(link1)), Some(libc::EIO));
+	let ufs = patched(long, None, 1 << 40);
+	assert_eq!(errno(ufs.symlink_read(long)), Some(libc::EIO));
+}
+
+#[test]
+fn create() {
+	let ufs = open_golden("ufs-little");
+	let errno =
+		|name: &str, target: &[u8]| errno(ufs.symlink(InodeNum::ROOT, name.as_ref(), target));
+	assert_eq!(errno("link", b""), Some(libc::ENOENT));
+	assert_eq!(errno("link", b"a\0b"), Some(libc::EINVAL));
+	assert_eq!(
+		errno("link", &[b'x'; SYMLINK_MAX + 1]),
+		Some(libc::ENAMETOOLONG)
+	);
+	assert_eq!(errno("a/b", b"file1"), Some(libc::EINVAL));
+	assert_eq!(errno("link", &[b'\xff'; SYMLINK_MAX]), Some(libc::EROFS));
+}
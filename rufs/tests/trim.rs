@@ -0,0 +1,114 @@
+//! Discarding the free fragments of a filesystem.
+mod support;
+
+use std::{
+	fs::File,
+	io::{Cursor, Write},
+	os::unix::fs::FileExt,
+};
+
+use rufs::{BlockFile, Options, SeekBackend, Ufs};
+use support::*;
+
+/// Offset of `fs_clean` in the primary superblock.
+const CLEAN: usize = 65536 + 209;
+
+fn image_file(img: &[u8]) -> File {
+	let mut f = tempfile::tempfile().unwrap();
+	f.write_all(img).unwrap();
+	f
+}
+
+#[test]
+fn free_extents() {
+	let ufs = example_image();
+	let extents = ufs.free_extents().unwrap();
+	let fsize = ufs.info().fsize as u64;
+	assert!(!extents.is_empty());
+	for w in extents.windows(2) {
+		assert!(w[0].end < w[1].start, "{w:?}");
+	}
+	for r in &extents {
+		assert_eq!(r.start % fsize, 0);
+		assert_eq!(r.end % fsize, 0);
+	}
+
+	// The superblock and the inodes of the first cylinder group are in use.
+	let (sb, inodes) = (65536, 40 * fsize);
+	for pos in [sb, inodes] {
+		assert!(!extents.iter().any(|r| r.contains(&pos)), "{pos}");
+	}
+}
+
+/// Only free fragments are discarded, everything else stays the same.
+#[test]
+fn preserves_contents() {
+	let img = golden_image("ufs-little");
+	let file = image_file(&img);
+	let ufs = Ufs::new(BlockFile::new(file.try_clone().unwrap(), 4096)).unwrap();
+	let extents = ufs.free_extents().unwrap();
+	let total = match ufs.trim() {
+		Err(e) if e.raw_os_error() == Some(libc::EOPNOTSUPP) => return,
+		res => res.unwrap(),
+	};
+	assert_eq!(total, extents.iter().map(|r| r.end - r.start).sum::<u64>());
+	assert!(ufs.check_deep().unwrap().is_empty());
+
+	let mut data = vec![0u8; img.len()];
+	file.read_exact_at(&mut data, 0).unwrap();
+	let mut expected = img.clone();
+	for r in &extents {
+		expected[(r.start as usize)..(r.end as usize)].fill(0);
+	}
+	assert!(data == expected);
+}
+
+/// The maps of a filesystem, which wasn't unmounted cleanly, can't be trusted.
+#[test]
+fn unclean() {
+	let mut img = golden_image("ufs-little");
+	img[CLEAN] = 0;
+
+	let ufs = Ufs::new(SeekBackend::new(Cursor::new(img.clone()))).unwrap();
+	let e = ufs.trim().unwrap_err();
+	assert_eq!(e.raw_os_error(), Some(libc::EINVAL));
+
+	let opts = Options {
+		force: true,
+		..Options::default()
+	};
+	let ufs = Ufs::with_options(SeekBackend::new(Cursor::new(img)), opts).unwrap();
+	let e = ufs.trim().unwrap_err();
+	assert_eq!(e.raw_os_error(), Some(libc::EOPNOTSUPP));
+}
+
+/// Only whole free fragments inside of the range are discarded, and only runs of at least `minlen`.
+#[test]
+fn range() {
+	let img = golden_image("ufs-little");
+	let file = image_file(&img);
+	let ufs = Ufs::new(BlockFile::new(file.try_clone().unwrap(), 4096)).unwrap();
+	let fsize = ufs.info().fsize as u64;
+	let long = ufs
+		.free_extents()
+		.unwrap()
+		.into_iter()
+		.find(|r| r.end - r.start >= 4 * fsize)
+		.unwrap();
+	let len = long.end - long.start;
+
+	assert_eq!(ufs.trim_free_space(long.clone(), len + 1).unwrap(), 0);
+
+	// The first and the last fragment are only partially inside of the range.
+	let total = match ufs.trim_free_space((long.start + 1)..(long.end - 1), 0) {
+		Err(e) if e.raw_os_error() == Some(libc::EOPNOTSUPP) => return,
+		res => res.unwrap(),
+	};
+	assert_eq!(total, len - 2 * fsize);
+
+	let mut data = vec![0u8; img.len()];
+	file.read_exact_at(&mut data, 0).unwrap();
+	let mut expected = img.clone();
+	expected[((long.start + fsize) as usize)..((long.end - fsize) as usize)].fill(0);
+	assert!(data == expected);
+}
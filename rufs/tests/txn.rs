@@ -0,0 +1,269 @@
+//! Batching writes using `Ufs::with_txn()`.
+mod support;
+
+use std::{
+	fs,
+	io::{Cursor, Result as IoResult},
+	sync::{
+		atomic::{AtomicBool, AtomicU64, Ordering},
+		Mutex,
+	},
+};
+
+use rufs::{Backend, InodeNum, Options, SeekBackend, Ufs};
+use support::*;
+/// A fragment of the golden images, which isn't followed by metadata.
+const DATA: u64 = 100;
+
+/// A writable in-memory image, which records all accesses.
+struct Mem {
+	data:   Mutex<Vec<u8>>,
+	reads:  Mutex<Vec<(u64, usize)>>,
+	writes: Mutex<Vec<(u64, usize)>>,
+	syncs:  AtomicU64,
+	/// Fail all writes with `EIO`, like a system, which crashed.
+	broken: AtomicBool,
+}
+
+impl Mem {
+	fn new(img: Vec<u8>) -> Self {
+		Self {
+			data:   Mutex::new(img),
+			reads:  Mutex::new(Vec::new()),
+			writes: Mutex::new(Vec::new()),
+			syncs:  AtomicU64::new(0),
+			broken: AtomicBool::new(false),
+		}
+	}
+
+	fn writes(&self) -> Vec<(u64, usize)> {
+		self.writes.lock().unwrap().clone()
+	}
+}
+
+impl Backend for &Mem {
+	fn read_at(&self, pos: u64, buf: &mut [u8]) -> IoResult<()> {
+		let pos = pos as usize;
+		buf.copy_from_slice(&self.data.lock().unwrap()[pos..(pos + buf.len())]);
+		self.reads.lock().unwrap().push((pos as u64, buf.len()));
+		Ok(())
+	}
+
+	fn write_at(&self, pos: u64, buf: &[u8]) -> IoResult<()> {
+		if self.broken.load(Ordering::Relaxed) {
+			return Err(std::io::Error::from_raw_os_error(libc::EIO));
+		}
+		let p = pos as usize;
+		self.data.lock().unwrap()[p..(p + buf.len())].copy_from_slice(buf);
+		self.writes.lock().unwrap().push((pos, buf.len()));
+		Ok(())
+	}
+
+	fn sync(&self) -> IoResult<()> {
+		self.syncs.fetch_add(1, Ordering::Relaxed);
+		Ok(())
+	}
+
+	fn size(&self) -> IoResult<Option<u64>> {
+		Ok(Some(self.data.lock().unwrap().len() as u64))
+	}
+}
+
+fn errno<T>(res: IoResult<T>) -> Option<i32> {
+	res.err().and_then(|e| e.raw_os_error())
+}
+
+/// Writes to the same fragments are coalesced, and flushed once.
+#[test]
+fn coalesce() {
+	let mem = Mem::new(golden_image("ufs-little"));
+	let ufs = Ufs::with_options(&mem, raw_writes()).unwrap();
+	ufs.with_txn(|txn| {
+		for i in 0..64u8 {
+			let frag = DATA + (i % 2) as u64;
+			txn.write_raw_block(frag, &[i; FSIZE])?;
+		}
+		assert_eq!(txn.dirty(), 2 * FSIZE as u64);
+		assert!(mem.writes().is_empty());
+
+		// The batch sees its own writes.
+		let mut buf = [0u8; 2];
+		txn.read_raw_block(DATA, &mut buf)?;
+		assert_eq!(buf, [62, 62]);
+		Ok(())
+	})
+	.unwrap();
+
+	assert_eq!(mem.writes(), [(DATA * FSIZE as u64, 2 * FSIZE)]);
+	assert_eq!(mem.syncs.load(Ordering::Relaxed), 1);
+	let data = mem.data.lock().unwrap();
+	let pos = DATA as usize * FSIZE;
+	assert!(data[pos..(pos + FSIZE)].iter().all(|&b| b == 62));
+	assert!(data[(pos + FSIZE)..(pos + 2 * FSIZE)]
+		.iter()
+		.all(|&b| b == 63));
+}
+
+/// The fragment is never read, and nothing is written, if the batch fails.
+#[test]
+fn rollback() {
+	let mem = Mem::new(golden_image("ufs-little"));
+	let ufs = Ufs::with_options(&mem, raw_writes()).unwrap();
+	let res = ufs.with_txn(|txn| {
+		txn.write_raw_block(DATA, &[0xff; FSIZE])?;
+		// Metadata can't be overwritten.
+		txn.write_raw_block(0, &[0xff; FSIZE])
+	});
+	assert_eq!(errno(res), Some(libc::EPERM));
+	let pos = DATA * FSIZE as u64;
+	let reads = mem.reads.lock().unwrap();
+	assert!(reads
+		.iter()
+		.all(|&(p, n)| p + n as u64 <= pos || p >= pos + FSIZE as u64));
+	assert!(mem.writes().is_empty());
+	assert_eq!(mem.syncs.load(Ordering::Relaxed), 0);
+}
+
+/// Fragments, which aren't consecutive, are written separately, in ascending order.
+#[test]
+fn runs() {
+	let mem = Mem::new(golden_image("ufs-little"));
+	let ufs = Ufs::with_options(&mem, raw_writes()).unwrap();
+	ufs.with_txn(|txn| {
+		txn.write_raw_block(DATA + 5, &[1; FSIZE])?;
+		txn.write_raw_block(DATA, &[2; 2 * FSIZE])
+	})
+	.unwrap();
+	let pos = DATA * FSIZE as u64;
+	assert_eq!(
+		mem.writes(),
+		[(pos, 2 * FSIZE), (pos + 5 * FSIZE as u64, FSIZE)]
+	);
+}
+
+/// The inodes are only scanned for indirect blocks and directories once per batch.
+#[test]
+fn structure() {
+	let mem = Mem::new(golden_image("ufs-little"));
+	let ufs = Ufs::with_options(&mem, raw_writes()).unwrap();
+	let reads = |n: u64| {
+		let before = mem.reads.lock().unwrap().len();
+		ufs.with_txn(|txn| (0..n).try_for_each(|i| txn.write_raw_block(DATA + i, &[3; FSIZE])))
+			.unwrap();
+		mem.reads.lock().unwrap().len() - before
+	};
+	// The first batch also reads the cylinder groups.
+	reads(1);
+	assert_eq!(reads(1), reads(10));
+
+	let dir = ufs.inode_extents(InodeNum::ROOT).unwrap()[0];
+	let res = ufs.with_txn(|txn| {
+		txn.write_raw_block(DATA, &[4; FSIZE])?;
+		txn.write_raw_block(dir.physical / FSIZE as u64, &[4; FSIZE])
+	});
+	assert_eq!(errno(res), Some(libc::EPERM));
+	assert_eq!(fragment(&mem, DATA), [3; FSIZE]);
+}
+
+fn with_log(log: &std::path::Path) -> Options {
+	Options {
+		intent_log: Some(log.to_owned()),
+		..raw_writes()
+	}
+}
+
+fn fragment(mem: &Mem, frag: u64) -> Vec<u8> {
+	let pos = frag as usize * FSIZE;
+	mem.data.lock().unwrap()[pos..(pos + FSIZE)].to_vec()
+}
+
+/// The log is empty, once a batch was applied.
+#[test]
+fn intent_log() {
+	let tmp = tempfile::tempdir().unwrap();
+	let log = tmp.path().join("intent.log");
+	let mem = Mem::new(golden_image("ufs-little"));
+	let ufs = Ufs::with_options(&mem, with_log(&log)).unwrap();
+	ufs.with_txn(|txn| txn.write_raw_block(DATA, &[7; FSIZE]))
+		.unwrap();
+	assert_eq!(fragment(&mem, DATA), [7; FSIZE]);
+	assert_eq!(fs::metadata(&log).unwrap().len(), 0);
+	assert_eq!(ufs.stats().replayed_bytes, 0);
+}
+
+/// A batch, which wasn't applied, because the system crashed, is applied when reopening.
+#[test]
+fn intent_log_replay() {
+	let tmp = tempfile::tempdir().unwrap();
+	let log = tmp.path().join("intent.log");
+	let mem = Mem::new(golden_image("ufs-little"));
+	let ufs = Ufs::with_options(&mem, with_log(&log)).unwrap();
+	mem.broken.store(true, Ordering::Relaxed);
+	ufs.with_txn(|txn| {
+		txn.write_raw_block(DATA, &[1; FSIZE])?;
+		txn.write_raw_block(DATA + 3, &[2; FSIZE])
+	})
+	.unwrap_err();
+	drop(ufs);
+	assert_ne!(fragment(&mem, DATA), [1; FSIZE]);
+
+	// Read-only backends can't replay the log.
+	let ro = SeekBackend::new(Cursor::new(golden_image("ufs-little")));
+	let e = Ufs::with_options(ro, with_log(&log)).err().unwrap();
+	assert_eq!(e.raw_os_error(), Some(libc::EROFS));
+
+	// Neither can filesystems, which weren't opened with `WriteCaps::raw`.
+	let opts = Options {
+		intent_log: Some(log.clone()),
+		..Options::default()
+	};
+	let e = Ufs::with_options(&mem, opts).err().unwrap();
+	assert_eq!(e.raw_os_error(), Some(libc::EROFS));
+
+	mem.broken.store(false, Ordering::Relaxed);
+	let ufs = Ufs::with_options(&mem, with_log(&log)).unwrap();
+	assert_eq!(ufs.stats().replayed_bytes, 2 * FSIZE as u64);
+	assert_eq!(fragment(&mem, DATA), [1; FSIZE]);
+	assert_eq!(fragment(&mem, DATA + 3), [2; FSIZE]);
+	assert_eq!(fs::metadata(&log).unwrap().len(), 0);
+}
+
+/// A log, which wasn't written completely, is discarded, because nothing was applied yet.
+#[test]
+fn intent_log_torn() {
+	let tmp = tempfile::tempdir().unwrap();
+	let log = tmp.path().join("intent.log");
+	let mem = Mem::new(golden_image("ufs-little"));
+	let ufs = Ufs::with_options(&mem, with_log(&log)).unwrap();
+	mem.broken.store(true, Ordering::Relaxed);
+	ufs.with_txn(|txn| txn.write_raw_block(DATA, &[1; FSIZE]))
+		.unwrap_err();
+	drop(ufs);
+	let data = fs::read(&log).unwrap();
+	fs::write(&log, &data[..(data.len() - 100)]).unwrap();
+
+	mem.broken.store(false, Ordering::Relaxed);
+	let ufs = Ufs::with_options(&mem, with_log(&log)).unwrap();
+	assert_eq!(ufs.stats().replayed_bytes, 0);
+	assert_ne!(fragment(&mem, DATA), [1; FSIZE]);
+}
+
+/// A log is never applied to another filesystem, than the one, which wrote it.
+#[test]
+fn intent_log_other_fs() {
+	let tmp = tempfile::tempdir().unwrap();
+	let log = tmp.path().join("intent.log");
+	let mem = Mem::new(golden_image("ufs-little"));
+	let ufs = Ufs::with_options(&mem, with_log(&log)).unwrap();
+	mem.broken.store(true, Ordering::Relaxed);
+	ufs.with_txn(|txn| txn.write_raw_block(DATA, &[1; FSIZE]))
+		.unwrap_err();
+	drop(ufs);
+	let data = fs::read(&log).unwrap();
+
+	let other = Mem::new(golden_image("ufs-big"));
+	let e = Ufs::with_options(&other, with_log(&log)).err().unwrap();
+	assert_eq!(e.raw_os_error(), Some(libc::EINVAL));
+	assert!(other.writes().is_empty());
+	assert_eq!(fs::read(&log).unwrap(), data);
+}
@@ -0,0 +1,119 @@
+//! Accessing images using io_uring.
+#![cfg(all(feature = "io-uring", target_os = "linux"))]
+mod support;
+
+use std::{
+	fs::{File, OpenOptions},
+	io::{ErrorKind, Write},
+};
+
+use rufs::{Backend, InodeNum, Ufs, UringBackend};
+use support::*;
+/// A fragment of the golden images, which isn't followed by metadata.
+const DATA: u64 = 100;
+
+fn image() -> tempfile::NamedTempFile {
+	let mut file = tempfile::NamedTempFile::new().unwrap();
+	file.write_all(&golden_image("ufs-little")).unwrap();
+	file
+}
+
+/// io_uring may be disabled, like in some containers, so the tests are skipped then.
+fn backend(file: File) -> Option<UringBackend> {
+	match UringBackend::new(file) {
+		Ok(b) => Some(b),
+		Err(e) => {
+			eprintln!("io_uring is not available, skipping: {e}");
+			None
+		}
+	}
+}
+
+fn writable(img: &tempfile::NamedTempFile) -> File {
+	OpenOptions::new()
+		.read(true)
+		.write(true)
+		.open(img.path())
+		.unwrap()
+}
+
+#[test]
+fn read() {
+	let img = image();
+	let Some(backend) = backend(File::open(img.path()).unwrap()) else {
+		return;
+	};
+	let ufs = Ufs::new(backend).unwrap();
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+	let mut buf = [0u8; 32];
+	let n = ufs.inode_read(inr, 0, &mut buf).unwrap();
+	assert_eq!(&buf[..n], b"This is a simple file.\n");
+	assert_eq!(ufs.check_deep().unwrap(), []);
+}
+
+#[test]
+fn bounds() {
+	let img = image();
+	let len = img.as_file().metadata().unwrap().len();
+	let Some(backend) = backend(File::open(img.path()).unwrap()) else {
+		return;
+	};
+	assert_eq!(backend.size().unwrap(), Some(len));
+	let mut buf = [0u8; 8];
+	backend.read_at(len - 4, &mut buf[..4]).unwrap();
+	let e = backend.read_at(len - 4, &mut buf).unwrap_err();
+	assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+	// Read-only files can't be written.
+	let e = backend.write_at(0, &[0u8; FSIZE]).unwrap_err();
+	assert_eq!(e.raw_os_error(), Some(libc::EBADF));
+	// Prefetching beyond the end is harmless.
+	backend.prefetch(len - 4, 1 << 20).unwrap();
+	backend.prefetch(len + 4096, 4096).unwrap();
+	backend.read_at(len - 4, &mut buf[..4]).unwrap();
+}
+
+/// Reads are served from completed prefetches, which are discarded, once they are written to.
+#[test]
+fn prefetch() {
+	let img = image();
+	let Some(backend) = backend(writable(&img)) else {
+		return;
+	};
+	let data = std::fs::read(img.path()).unwrap();
+	let pos = DATA * FSIZE as u64;
+	let off = pos as usize;
+	backend.prefetch(pos, 4 * FSIZE).unwrap();
+	let mut buf = [0u8; FSIZE];
+	backend.read_at(pos + 100, &mut buf).unwrap();
+	assert_eq!(buf, data[(off + 100)..(off + 100 + FSIZE)]);
+
+	backend.write_at(pos + 8, &[0xaa; 4]).unwrap();
+	let mut buf = [0u8; 16];
+	backend.read_at(pos, &mut buf).unwrap();
+	assert_eq!(buf[8..12], [0xaa; 4]);
+	assert_eq!(buf[..8], data[off..(off + 8)]);
+
+	// More prefetches, than can be queued, are ignored.
+	for i in 0..100 {
+		backend.prefetch(i * FSIZE as u64, FSIZE).unwrap();
+	}
+	backend.sync().unwrap();
+}
+
+#[test]
+fn write() {
+	let img = image();
+	let Some(backend) = backend(writable(&img)) else {
+		return;
+	};
+	let ufs = Ufs::with_options(backend, raw_writes()).unwrap();
+	ufs.write_raw_block(DATA, &[0xaa; FSIZE]).unwrap();
+	ufs.sync().unwrap();
+
+	let mut buf = [0u8; FSIZE];
+	ufs.read_raw_block(DATA, &mut buf).unwrap();
+	assert_eq!(buf, [0xaa; FSIZE]);
+	let data = std::fs::read(img.path()).unwrap();
+	let pos = DATA as usize * FSIZE;
+	assert_eq!(data[pos..(pos + FSIZE)], [0xaa; FSIZE]);
+}
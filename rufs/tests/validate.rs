@@ -0,0 +1,145 @@
+//! Checking mutating operations without applying them, using `Ufs::can_*()`.
+mod support;
+
+use std::io::Cursor;
+
+use rufs::{Credentials, InodeNum, SeekBackend, Ufs, UF_NOUNLINK};
+use support::*;
+
+/// Change the mode (keeping the type), owner and flags of inode `inr` of `img`.
+fn patch(img: &mut [u8], inr: InodeNum, mode: u16, uid: u32, flags: u32) {
+	let pos = inode_offset(inr);
+	let old = u16::from_le_bytes(img[pos..(pos + 2)].try_into().unwrap());
+	let mode = (old & !0o7777) | mode;
+	patch_inode(img, inr, DI_MODE, &mode.to_le_bytes());
+	patch_inode(img, inr, DI_UID, &uid.to_le_bytes());
+	patch_inode(img, inr, DI_FLAGS, &flags.to_le_bytes());
+}
+
+/// The little-endian golden image, in which the root directory has the mode `root`,
+/// and "file1" (inode 4) is owned by 1001, and has the flags `flags`.
+fn open(root: u16, flags: u32) -> MemUfs {
+	let mut img = golden_image("ufs-little");
+	patch(&mut img, InodeNum::ROOT, root, 0, 0);
+	patch(&mut img, inr(4), 0o644, 1001, flags);
+	Ufs::new(SeekBackend::new(Cursor::new(img))).unwrap()
+}
+
+fn errno<T>(res: std::io::Result<T>) -> Option<i32> {
+	res.err().and_then(|e| e.raw_os_error())
+}
+
+const ROOT: InodeNum = InodeNum::ROOT;
+
+/// Everything, but the missing write support, is fine.
+fn ok<T>(res: std::io::Result<T>) -> bool {
+	errno(res) == Some(libc::EROFS)
+}
+
+#[test]
+fn mknod() {
+	let ufs = open(0o755, 0);
+	let root = Credentials::new(0, 0);
+	let other = Credentials::new(1001, 1001);
+	let file1 = ufs.dir_lookup(ROOT, "file1".as_ref()).unwrap();
+
+	assert!(ok(ufs.can_mknod(ROOT, "new".as_ref(), &root)));
+	assert_eq!(
+		errno(ufs.can_mknod(ROOT, "new".as_ref(), &other)),
+		Some(libc::EACCES)
+	);
+	assert_eq!(
+		errno(ufs.can_mknod(file1, "new".as_ref(), &root)),
+		Some(libc::ENOTDIR)
+	);
+	assert_eq!(
+		errno(ufs.can_mknod(ROOT, "file1".as_ref(), &root)),
+		Some(libc::EEXIST)
+	);
+	for name in ["", ".", "..", "a/b"] {
+		assert_eq!(
+			errno(ufs.can_mknod(ROOT, name.as_ref(), &root)),
+			Some(libc::EINVAL),
+			"{name:?}"
+		);
+	}
+	let long = "x".repeat(256);
+	assert_eq!(
+		errno(ufs.can_mknod(ROOT, long.as_ref(), &root)),
+		Some(libc::ENAMETOOLONG)
+	);
+}
+
+#[test]
+fn unlink() {
+	let ufs = open(0o777, 0);
+	let other = Credentials::new(2000, 2000);
+	assert!(ok(ufs.can_unlink(ROOT, "file1".as_ref(), &other)));
+	assert_eq!(
+		errno(ufs.can_unlink(ROOT, "missing".as_ref(), &other)),
+		Some(libc::ENOENT)
+	);
+	assert_eq!(
+		errno(ufs.can_unlink(ROOT, "..".as_ref(), &other)),
+		Some(libc::EINVAL)
+	);
+	assert_eq!(
+		errno(ufs.can_unlink(ROOT, "dir1".as_ref(), &other)),
+		Some(libc::ENOTEMPTY)
+	);
+}
+
+/// In sticky directories, only the owners may remove entries.
+#[test]
+fn sticky() {
+	let ufs = open(0o1777, 0);
+	let owner = Credentials::new(1001, 1001);
+	let other = Credentials::new(2000, 2000);
+	let root = Credentials::new(0, 0);
+	assert!(ok(ufs.can_unlink(ROOT, "file1".as_ref(), &owner)));
+	assert!(ok(ufs.can_unlink(ROOT, "file1".as_ref(), &root)));
+	assert_eq!(
+		errno(ufs.can_unlink(ROOT, "file1".as_ref(), &other)),
+		Some(libc::EPERM)
+	);
+	assert_eq!(
+		errno(ufs.can_rename(ROOT, "file1".as_ref(), ROOT, "x".as_ref(), &other)),
+		Some(libc::EPERM)
+	);
+}
+
+#[test]
+fn nounlink() {
+	let ufs = open(0o777, UF_NOUNLINK);
+	let root = Credentials::new(0, 0);
+	assert_eq!(
+		errno(ufs.can_unlink(ROOT, "file1".as_ref(), &root)),
+		Some(libc::EPERM)
+	);
+	assert_eq!(
+		errno(ufs.can_rename(ROOT, "file1".as_ref(), ROOT, "x".as_ref(), &root)),
+		Some(libc::EPERM)
+	);
+}
+
+#[test]
+fn rename() {
+	let ufs = open(0o755, 0);
+	let root = Credentials::new(0, 0);
+	let dir1 = ufs.dir_lookup(ROOT, "dir1".as_ref()).unwrap();
+	let dir2 = ufs.dir_lookup(dir1, "dir2".as_ref()).unwrap();
+	let rename = |sname: &str, dinr, dname: &str| {
+		errno(ufs.can_rename(ROOT, sname.as_ref(), dinr, dname.as_ref(), &root))
+	};
+
+	assert_eq!(rename("file1", ROOT, "new"), Some(libc::EROFS));
+	assert_eq!(rename("file1", dir1, "file1"), Some(libc::EROFS));
+	// Replacing an entry by itself.
+	assert_eq!(rename("file1", ROOT, "file1"), Some(libc::EROFS));
+	assert_eq!(rename("file1", ROOT, "dir1"), Some(libc::EISDIR));
+	assert_eq!(rename("dir1", ROOT, "file1"), Some(libc::ENOTDIR));
+	assert_eq!(rename("dir1", dir2, "x"), Some(libc::EINVAL));
+	assert_eq!(rename("dir1", dir1, "x"), Some(libc::EINVAL));
+	assert_eq!(rename("file1", ROOT, "a/b"), Some(libc::EINVAL));
+	assert_eq!(rename("missing", ROOT, "x"), Some(libc::ENOENT));
+}
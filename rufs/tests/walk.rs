@@ -0,0 +1,156 @@
+//! Walking directory trees.
+mod support;
+
+use std::{io::Cursor, path::PathBuf};
+
+use rufs::{InodeNum, InodeType, SeekBackend, SymlinkPolicy, Ufs, WalkOptions, WalkOrder};
+use support::*;
+
+/// Paths of all files of the little-endian golden image, in the order of `opts`.
+fn paths(ufs: &MemUfs, opts: WalkOptions) -> Vec<String> {
+	ufs.walk_with(InodeNum::ROOT, opts)
+		.map(|e| e.unwrap().0.into_os_string().into_string().unwrap())
+		.collect()
+}
+
+/// The little-endian golden image, where "link1" points to `target`,
+/// which must be as long as its original target.
+fn relinked(target: &[u8]) -> Ufs<SeekBackend<Cursor<Vec<u8>>>> {
+	let orig = b"dir1/dir2/dir3/file2";
+	assert_eq!(target.len(), orig.len());
+	let mut img = golden_image("ufs-little");
+	let pos = img
+		.windows(orig.len())
+		.position(|w| w == orig)
+		.expect("link1 not found");
+	img[pos..(pos + orig.len())].copy_from_slice(target);
+	Ufs::new(SeekBackend::new(Cursor::new(img))).unwrap()
+}
+
+fn follow() -> WalkOptions {
+	WalkOptions {
+		symlinks: SymlinkPolicy::Follow,
+		..WalkOptions::default()
+	}
+}
+
+#[test]
+fn depth_first() {
+	let ufs = open_golden("ufs-little");
+	let paths = paths(&ufs, WalkOptions::default());
+	assert_eq!(
+		paths[..8],
+		[
+			"",
+			".snap",
+			"file1",
+			"dir1",
+			"dir1/dir2",
+			"dir1/dir2/dir3",
+			"dir1/dir2/dir3/file2",
+			"file3",
+		]
+	);
+	assert_eq!(paths.len(), 16);
+
+	let (path, inr, attr) = ufs.walk(InodeNum::ROOT).nth(8).unwrap().unwrap();
+	assert_eq!(path, PathBuf::from("link1"));
+	assert_eq!(attr.kind, InodeType::Symlink);
+	assert_eq!(ufs.inode_attr(inr).unwrap(), attr);
+}
+
+#[test]
+fn breadth_first() {
+	let ufs = open_golden("ufs-little");
+	let opts = WalkOptions {
+		order: WalkOrder::BreadthFirst,
+		..WalkOptions::default()
+	};
+	let paths = paths(&ufs, opts);
+	let depths = paths
+		.iter()
+		.map(|p| p.split('/').count())
+		.collect::<Vec<_>>();
+	assert!(depths.windows(2).all(|w| w[0] <= w[1]), "{paths:?}");
+	assert_eq!(
+		paths[13..],
+		["dir1/dir2", "dir1/dir2/dir3", "dir1/dir2/dir3/file2"]
+	);
+}
+
+#[test]
+fn max_depth() {
+	let ufs = open_golden("ufs-little");
+	let opts = WalkOptions {
+		max_depth: 1,
+		..WalkOptions::default()
+	};
+	let paths = paths(&ufs, opts);
+	assert_eq!(paths.len(), 13);
+	assert!(paths.iter().all(|p| !p.contains('/')), "{paths:?}");
+
+	let dir1 = ufs.dir_lookup(InodeNum::ROOT, "dir1".as_ref()).unwrap();
+	let paths = ufs.walk(dir1).map(|e| e.unwrap().0).collect::<Vec<_>>();
+	assert_eq!(
+		paths,
+		["", "dir2", "dir2/dir3", "dir2/dir3/file2"].map(PathBuf::from)
+	);
+}
+
+#[test]
+fn symlinks() {
+	let ufs = open_golden("ufs-little");
+	let file1 = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+	let file2 = ufs.walk(InodeNum::ROOT).nth(6).unwrap().unwrap().1;
+	let files = ufs
+		.walk_with(InodeNum::ROOT, follow())
+		.map(Result::unwrap)
+		.filter(|(path, ..)| path.ends_with("link1") || path.ends_with("long-link"))
+		.collect::<Vec<_>>();
+	assert_eq!(files.len(), 2);
+	assert_eq!(
+		(files[0].1, files[0].2.kind),
+		(file2, InodeType::RegularFile)
+	);
+	assert_eq!(
+		(files[1].1, files[1].2.kind),
+		(file1, InodeType::RegularFile)
+	);
+}
+
+/// Directories are only descended into once, even if a link points to an ancestor.
+#[test]
+fn cycle() {
+	let ufs = relinked(b"////////////////dir1");
+	let paths = paths(&ufs, follow());
+	assert_eq!(paths.len(), 16);
+	assert!(paths.contains(&"link1".to_owned()));
+	assert!(!paths.iter().any(|p| p.starts_with("link1/")), "{paths:?}");
+
+	let ufs = relinked(b"/////////////////../");
+	let (_, inr, attr) = ufs
+		.walk_with(InodeNum::ROOT, follow())
+		.find(|e| e.as_ref().unwrap().0.ends_with("link1"))
+		.unwrap()
+		.unwrap();
+	assert_eq!((inr, attr.kind), (InodeNum::ROOT, InodeType::Directory));
+}
+
+/// Links, which can't be resolved, are returned as errors, and the walk continues.
+#[test]
+fn errors() {
+	let ufs = relinked(b"./././././././/link1");
+	let res = ufs.walk_with(InodeNum::ROOT, follow()).collect::<Vec<_>>();
+	assert_eq!(res.len(), 16);
+	let err = res[8].as_ref().unwrap_err();
+	assert_eq!(err.raw_os_error(), Some(libc::ELOOP));
+	assert_eq!(res.iter().filter(|r| r.is_err()).count(), 1);
+
+	let ufs = relinked(b"dir1/dir2/dir3/file9");
+	let res = ufs.walk_with(InodeNum::ROOT, follow()).collect::<Vec<_>>();
+	let err = res[8].as_ref().unwrap_err();
+	assert_eq!(err.raw_os_error(), Some(libc::ENOENT));
+
+	// Without following them, the links themselves are returned.
+	assert!(ufs.walk(InodeNum::ROOT).all(|e| e.is_ok()));
+}
@@ -0,0 +1,85 @@
+//! Whiteout entries, which hide files of lower layers of union filesystems.
+mod support;
+
+use std::{ffi::OsString, io::Cursor};
+
+use rufs::{FsckFinding, InodeNum, InodeType, Options, SeekBackend, Ufs};
+use support::*;
+
+/// The little-endian golden image, in which "file1" is a whiteout.
+fn open(whiteouts: bool) -> MemUfs {
+	let mut img = golden_image("ufs-little");
+
+	// struct direct: ino (4), reclen (2), type (1), namlen (1), name
+	let pos = img
+		.windows(7)
+		.position(|w| w == b"\x08\x05file1")
+		.expect("pattern not found") -
+		6;
+	img[pos..(pos + 4)].copy_from_slice(&1u32.to_le_bytes());
+	img[pos + 6] = 14;
+
+	let opts = Options {
+		whiteouts,
+		..Options::default()
+	};
+	Ufs::with_options(SeekBackend::new(Cursor::new(img)), opts).unwrap()
+}
+
+fn entries(ufs: &MemUfs) -> Vec<(OsString, InodeNum, InodeType)> {
+	let mut v = Vec::new();
+	ufs.dir_iter(InodeNum::ROOT, |name, inr, kind| {
+		v.push((name.to_owned(), inr, kind));
+		None::<()>
+	})
+	.unwrap();
+	v
+}
+
+#[test]
+fn skipped() {
+	let ufs = open(false);
+	assert!(entries(&ufs).iter().all(|(name, ..)| name != "file1"));
+	let err = ufs
+		.dir_lookup(InodeNum::ROOT, "file1".as_ref())
+		.unwrap_err();
+	assert_eq!(err.raw_os_error(), Some(libc::ENOENT));
+}
+
+/// Whiteouts don't refer to an inode, so only the lost link of "file1" is found.
+#[test]
+fn fsck() {
+	let findings = open(false).check_deep().unwrap();
+	assert!(
+		matches!(findings[..], [FsckFinding::LinkCount { refs: 0, .. }]),
+		"{findings:?}"
+	);
+}
+
+#[test]
+fn reported() {
+	let ufs = open(true);
+	let entries = entries(&ufs);
+	let wh = entries.iter().find(|(name, ..)| name == "file1").unwrap();
+	assert_eq!((wh.1, wh.2), (InodeNum::WHITEOUT, InodeType::Whiteout));
+
+	let inr = ufs.dir_lookup(InodeNum::ROOT, "file1".as_ref()).unwrap();
+	assert_eq!(inr, InodeNum::WHITEOUT);
+	let attr = ufs.inode_attr(inr).unwrap();
+	assert_eq!(attr.kind, InodeType::Whiteout);
+	assert_eq!((attr.size, attr.perm, attr.uid), (0, 0, 0));
+}
+
+#[test]
+fn create() {
+	let ufs = open(true);
+	let errno = |name: &str| {
+		ufs.dir_whiteout(InodeNum::ROOT, name.as_ref())
+			.unwrap_err()
+			.raw_os_error()
+	};
+	assert_eq!(errno(".."), Some(libc::EINVAL));
+	assert_eq!(errno("a/b"), Some(libc::EINVAL));
+	assert_eq!(errno(&"x".repeat(256)), Some(libc::ENAMETOOLONG));
+	assert_eq!(errno("new"), Some(libc::EROFS));
+}
@@ -0,0 +1,93 @@
+//! Compressed images.
+#![cfg(feature = "zstd")]
+mod support;
+
+use std::{fs::File, io::Cursor, path::PathBuf};
+
+use rufs::{Backend, BlockFile, InodeNum, SeekBackend, Ufs, ZstdBackend};
+use support::*;
+
+/// Wrap `data` in a zstd frame, without compressing it.
+fn raw_frame(data: &[u8]) -> Vec<u8> {
+	// Magic number, and a frame header with a single segment and a 4-byte content size
+	let mut out = vec![0x28, 0xb5, 0x2f, 0xfd, 0xa0];
+	out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+	let mut blocks = data.chunks(128 << 10).peekable();
+	while let Some(block) = blocks.next() {
+		// Raw block: Last_Block (1 bit), Block_Type (2 bits), Block_Size (21 bits)
+		let hdr = (block.len() as u32) << 3 | blocks.peek().is_none() as u32;
+		out.extend_from_slice(&hdr.to_le_bytes()[0..3]);
+		out.extend_from_slice(block);
+	}
+	out
+}
+
+/// Store `data` in the seekable format, with frames of `frame` bytes.
+fn seekable(data: &[u8], frame: usize, checksum: bool) -> Vec<u8> {
+	let mut out = Vec::new();
+	let mut table = Vec::new();
+	for chunk in data.chunks(frame) {
+		let c = raw_frame(chunk);
+		table.extend_from_slice(&(c.len() as u32).to_le_bytes());
+		table.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+		if checksum {
+			table.extend_from_slice(&0u32.to_le_bytes());
+		}
+		out.extend_from_slice(&c);
+	}
+	let num = data.len().div_ceil(frame) as u32;
+	table.extend_from_slice(&num.to_le_bytes());
+	table.push(if checksum { 0x80 } else { 0 });
+	table.extend_from_slice(&0x8f92_eab1u32.to_le_bytes());
+
+	out.extend_from_slice(&0x184d_2a5eu32.to_le_bytes());
+	out.extend_from_slice(&(table.len() as u32).to_le_bytes());
+	out.extend_from_slice(&table);
+	out
+}
+
+/// Check that `ufs` contains the same data as the golden image.
+fn compare<B: Backend>(ufs: &Ufs<B>) {
+	let golden = open_golden("ufs-little");
+	let inr = golden.dir_lookup(InodeNum::ROOT, "file3".as_ref()).unwrap();
+	let size = golden.inode_attr(inr).unwrap().size as usize;
+	let mut buf = vec![0u8; size];
+	let mut expected = vec![0u8; size];
+	// start at an odd offset, so that reads cross frame boundaries
+	ufs.inode_read(inr, 7, &mut buf[7..]).unwrap();
+	golden.inode_read(inr, 7, &mut expected[7..]).unwrap();
+	assert!(buf == expected);
+}
+
+#[test]
+fn seekable_frames() {
+	let img = golden_image("ufs-little");
+	for checksum in [false, true] {
+		let data = seekable(&img, 100_000, checksum);
+		let backend = SeekBackend::new(Cursor::new(data));
+		assert!(ZstdBackend::detect(&backend).unwrap());
+		let zstd = ZstdBackend::new(backend).unwrap();
+		assert_eq!(zstd.size().unwrap(), Some(img.len() as u64));
+
+		let mut buf = vec![0u8; 300_000];
+		zstd.read_at(99_999, &mut buf).unwrap();
+		assert!(buf == img[99_999..399_999]);
+		compare(&Ufs::new(zstd).unwrap());
+	}
+}
+
+/// Images, which aren't seekable, are decompressed into memory.
+#[test]
+fn plain() {
+	let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+	path.push("../resources/ufs-little.img.zst");
+	let file = BlockFile::new(File::open(path).unwrap(), 512);
+	assert!(ZstdBackend::detect(&file).unwrap());
+	compare(&Ufs::new(ZstdBackend::new(file).unwrap()).unwrap());
+}
+
+#[test]
+fn uncompressed() {
+	let backend = SeekBackend::new(Cursor::new(golden_image("ufs-little")));
+	assert!(!ZstdBackend::detect(&backend).unwrap());
+}
@@ -56,6 +56,9 @@ create() {
     mount -t ufs "/dev/$dev" "$mnt" || die "$path: failed to mount '/dev/$dev' onto '$mnt'"
 
     populate "$mnt"
+    if [ -n "${SNAPSHOT}" ]; then
+        mksnap_ffs "$mnt/.snap/snap1" || die "$path: failed to create a snapshot"
+    fi
 
     # These may fail with only a warning:
     umount "$mnt"
@@ -65,6 +68,24 @@ create() {
     zstd -f "$path" || die "$path: failed to compress with zstd"
 }
 
+# $1: name
+# $2: size
+# Like create(), but using makefs(8), which creates images from a directory tree, without
+# check-hashes.  It copies holes as zeroes, so the sparse files are left out.
+create_makefs() {
+    name=$1
+    path=resources/${name}.img
+    size=$2
+
+    dir=$(mktemp -d) || die "$path: failed to create tempdir"
+    populate "$dir"
+    rm -f "$dir"/sparse*
+    makefs -t ffs -o version=2 -s "$size" "$path" "$dir" || die "$path: failed to makefs $dir"
+    rm -rf "$dir"
+
+    zstd -f "$path" || die "$path: failed to compress with zstd"
+}
+
 # I don't know why it works, but it does. Tested on FreeBSD/powerpc64, OpenBSD/amd64, Arch Linux (amd64)
 case "$(echo I | tr -d '[:space:]' | od -to2 | awk 'NR==1 {print substr($2, 6, 1)}')" in
     0)
@@ -78,10 +99,14 @@ case "$(echo I | tr -d '[:space:]' | od -to2 | awk 'NR==1 {print substr($2, 6, 1
 	;;
 esac
 
-args=$(getopt 'p:s:' $*) || die "usage: ./scripts/mkimg.sh [-p dir|-s size]"
+args=$(getopt 'b:f:mp:s:S' $*) || die "usage: ./scripts/mkimg.sh [-p dir|-s size|-b bsize -f fsize|-m|-S]"
 set -- $args
 
 SIZE=4m
+NAME="ufs-${ENDIAN}"
+NEWFS_ARGS=
+CREATE=create
+SNAPSHOT=
 
 while true; do
     case "$1" in
@@ -93,6 +118,29 @@ while true; do
 	    SIZE=$2
 	    shift 2
 	    ;;
+	# Other block and fragment sizes than the defaults (32K/4K) get their own image
+	-b)
+	    NAME="${NAME}-b$2"
+	    NEWFS_ARGS="${NEWFS_ARGS} -b $2"
+	    shift 2
+	    ;;
+	-f)
+	    NAME="${NAME}-f$2"
+	    NEWFS_ARGS="${NEWFS_ARGS} -f $2"
+	    shift 2
+	    ;;
+	# An image with a snapshot of the populated filesystem, in .snap/snap1
+	-S)
+	    NAME="${NAME}-snap"
+	    SNAPSHOT=1
+	    shift
+	    ;;
+	# An image created by makefs(8) instead of newfs(8)
+	-m)
+	    NAME="${NAME}-makefs"
+	    CREATE=create_makefs
+	    shift
+	    ;;
 	--)
 	    shift
 	    break
@@ -100,4 +148,4 @@ while true; do
     esac
 done
 
-create "ufs-${ENDIAN}" "${SIZE}"
+${CREATE} "${NAME}" "${SIZE}" ${NEWFS_ARGS}
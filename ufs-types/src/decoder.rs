@@ -3,8 +3,10 @@ use std::io::{BufReader, Error, ErrorKind, Read, Result, Seek, SeekFrom};
 use bincode::{
 	config::{BigEndian, Configuration, Fixint, LittleEndian, NoLimit},
 	Decode,
+	Encode,
 };
 
+/// Byte order of a filesystem.
 #[derive(Clone, Copy)]
 pub enum Config {
 	Little(Configuration<LittleEndian, Fixint, NoLimit>),
@@ -26,6 +28,25 @@ impl Config {
 		Self::Big(cfg)
 	}
 
+	/// Parse a structure from the beginning of `buf`.
+	pub fn decode_slice<X: Decode>(&self, buf: &[u8]) -> Result<X> {
+		match self {
+			Self::Little(cfg) => bincode::decode_from_slice(buf, *cfg),
+			Self::Big(cfg) => bincode::decode_from_slice(buf, *cfg),
+		}
+		.map(|(x, _)| x)
+		.map_err(|_| Error::new(ErrorKind::InvalidInput, "failed to decode"))
+	}
+
+	/// Serialize a structure into its on-disk representation.
+	pub fn encode_to_vec<X: Encode>(&self, x: &X) -> Result<Vec<u8>> {
+		match self {
+			Self::Little(cfg) => bincode::encode_to_vec(x, *cfg),
+			Self::Big(cfg) => bincode::encode_to_vec(x, *cfg),
+		}
+		.map_err(|_| Error::new(ErrorKind::InvalidInput, "failed to encode"))
+	}
+
 	fn decode<T: Read, X: Decode>(&self, rdr: &mut BufReader<T>) -> Result<X> {
 		match self {
 			Self::Little(cfg) => bincode::decode_from_reader(rdr, *cfg),
@@ -35,6 +56,7 @@ impl Config {
 	}
 }
 
+/// Decodes on-disk structures from a reader.
 pub struct Decoder<T> {
 	inner:  BufReader<T>,
 	config: Config,
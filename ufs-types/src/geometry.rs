@@ -0,0 +1,246 @@
+use crate::*;
+
+/// The geometry of a filesystem, taken from a [`Superblock`], whose invariants were checked.
+///
+/// Unlike the fields of the superblock, which come straight from the disk, the values are
+/// positive and consistent, so calculations with them can't divide by zero or overflow.
+/// Only addresses, which are calculated from untrusted values, like block pointers
+/// and inode numbers, are checked, and `None` if they are invalid.
+///
+/// # Example
+/// ```
+/// use ufs_types::{Config, FsGeometry, Superblock, SBLOCKSIZE};
+///
+/// let sb: Superblock = Config::little().decode_slice(&[0u8; SBLOCKSIZE])?;
+/// assert_eq!(FsGeometry::new(&sb).unwrap_err(), "sb.ncg > 0");
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FsGeometry {
+	bsize:  u64,
+	fsize:  u64,
+	frag:   u64,
+	ncg:    u64,
+	fpg:    u64,
+	ipg:    u64,
+	inopb:  u64,
+	size:   u64,
+	sblkno: u64,
+	cblkno: u64,
+	iblkno: u64,
+}
+
+impl FsGeometry {
+	/// Check the geometry of `sb`.
+	///
+	/// Fails with the first violated invariant, as a string.
+	pub fn new(sb: &Superblock) -> Result<Self, &'static str> {
+		macro_rules! check {
+			($e:expr) => {
+				if !($e) {
+					return Err(stringify!($e));
+				}
+			};
+		}
+
+		check!(sb.ncg > 0);
+		check!(sb.ipg > 0);
+		check!(sb.fpg > 0);
+		check!(sb.inopb > 0);
+		check!(sb.frag > 0 && sb.frag <= MAXFRAG as i32);
+		check!(sb.bsize >= MINBSIZE as i32 && sb.bsize <= MAXBSIZE as i32);
+		check!(sb.fsize >= DEV_BSIZE as i32);
+		check!(sb.fsize == (sb.bsize / sb.frag));
+		check!(sb.inopb as i32 == sb.bsize / UFS_INOSZ as i32);
+		check!(Some(sb.bsize) == 1i32.checked_shl(sb.bshift as u32));
+		check!(Some(sb.fsize) == 1i32.checked_shl(sb.fshift as u32));
+		check!(Some(sb.frag) == 1i32.checked_shl(sb.fragshift as u32));
+		// The cylinder groups must cover the filesystem, like in validate_sblock(),
+		// and their addresses must fit into a u64.
+		let (ncg, fpg, size) = (sb.ncg as u64, sb.fpg as u64, sb.size as u64);
+		check!(sb.size > 0);
+		check!(sb.cg_addr(ncg, 0).is_some());
+		check!((ncg - 1) * fpg < size && size <= ncg * fpg);
+		check!(sb.sblkno >= 0 && sb.cblkno >= 0 && sb.iblkno >= 0);
+
+		Ok(Self {
+			bsize: sb.bsize as u64,
+			fsize: sb.fsize as u64,
+			frag: sb.frag as u64,
+			ncg,
+			fpg,
+			ipg: sb.ipg as u64,
+			inopb: sb.inopb as u64,
+			size,
+			sblkno: sb.sblkno as u64,
+			cblkno: sb.cblkno as u64,
+			iblkno: sb.iblkno as u64,
+		})
+	}
+
+	/// Size of a block in bytes.
+	pub fn bsize(&self) -> u64 {
+		self.bsize
+	}
+
+	/// Size of a fragment in bytes.
+	pub fn fsize(&self) -> u64 {
+		self.fsize
+	}
+
+	/// Number of fragments per block.
+	pub fn frag(&self) -> u64 {
+		self.frag
+	}
+
+	/// Number of cylinder groups.
+	pub fn ncg(&self) -> u64 {
+		self.ncg
+	}
+
+	/// Number of fragments per cylinder group.
+	pub fn fpg(&self) -> u64 {
+		self.fpg
+	}
+
+	/// Number of inodes per cylinder group.
+	pub fn ipg(&self) -> u64 {
+		self.ipg
+	}
+
+	/// Number of inodes per block.
+	pub fn inopb(&self) -> u64 {
+		self.inopb
+	}
+
+	/// Size of the filesystem in fragments.
+	pub fn size(&self) -> u64 {
+		self.size
+	}
+
+	/// Fragment of the superblock within a cylinder group.
+	pub fn sblkno(&self) -> u64 {
+		self.sblkno
+	}
+
+	/// Fragment of the cylinder group structure within a cylinder group.
+	pub fn cblkno(&self) -> u64 {
+		self.cblkno
+	}
+
+	/// Fragment of the inodes within a cylinder group.
+	pub fn iblkno(&self) -> u64 {
+		self.iblkno
+	}
+
+	/// Number of inodes of the filesystem, including the unused ones.
+	pub fn ninodes(&self) -> u64 {
+		self.ncg * self.ipg
+	}
+
+	/// Size of a cylinder group in bytes.
+	pub fn cgsize(&self) -> u64 {
+		self.fpg * self.fsize
+	}
+
+	/// Byte offset of fragment `frag` of cylinder group `cgx`, see [`Superblock::cg_addr()`].
+	///
+	/// Returns `None`, if the offset doesn't fit into a `u64`.
+	pub fn cg_addr(&self, cgx: u64, frag: u64) -> Option<u64> {
+		cgx.checked_mul(self.fpg)?
+			.checked_add(frag)?
+			.checked_mul(self.fsize)
+	}
+
+	/// Byte offset of fragment `frag` of the filesystem, like a block pointer.
+	///
+	/// Returns `None`, if the fragment is outside of the filesystem.
+	/// Offsets within the block of a valid fragment can't overflow.
+	pub fn frag_addr(&self, frag: u64) -> Option<u64> {
+		(frag < self.size).then(|| frag * self.fsize)
+	}
+
+	/// Number of fragments of `blocks` blocks.
+	///
+	/// Returns `None`, if the result doesn't fit into a `u64`.
+	pub fn blocks_to_frags(&self, blocks: u64) -> Option<u64> {
+		blocks.checked_mul(self.frag)
+	}
+
+	/// Cylinder group of inode `inr`.
+	pub fn ino_to_cg(&self, inr: InodeNum) -> u64 {
+		inr.get64() / self.ipg
+	}
+
+	/// Byte offset of inode `inr`, see [`Superblock::ino_to_fso()`].
+	///
+	/// Returns `None`, if the offset doesn't fit into a `u64`.
+	pub fn ino_to_fso(&self, inr: InodeNum) -> Option<u64> {
+		let block = inr.get64() % self.ipg / self.inopb;
+		let frag = self.iblkno.checked_add(self.blocks_to_frags(block)?)?;
+		let off = inr.get64() % self.inopb * UFS_INOSZ as u64;
+		self.cg_addr(self.ino_to_cg(inr), frag)?.checked_add(off)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::*;
+
+	fn superblock() -> Superblock {
+		let mut sb: Superblock = Config::little().decode_slice(&[0u8; SBLOCKSIZE]).unwrap();
+		sb.ncg = 4;
+		sb.size = 1000;
+		sb.bsize = 32768;
+		sb.bshift = 15;
+		sb.fsize = 4096;
+		sb.fshift = 12;
+		sb.frag = 8;
+		sb.fragshift = 3;
+		sb.fpg = 264;
+		sb.ipg = 256;
+		sb.inopb = 128;
+		sb.iblkno = 40;
+		sb
+	}
+
+	#[test]
+	fn invariants() {
+		let sb = superblock();
+		let geo = FsGeometry::new(&sb).unwrap();
+		assert_eq!((geo.bsize(), geo.fsize(), geo.frag()), (32768, 4096, 8));
+		assert_eq!(geo.cgsize(), sb.cgsize().unwrap());
+
+		let mut bad = superblock();
+		bad.fsize = 2048;
+		assert_eq!(
+			FsGeometry::new(&bad),
+			Err("sb.fsize == (sb.bsize / sb.frag)")
+		);
+		let mut bad = superblock();
+		bad.inopb = 256;
+		assert!(FsGeometry::new(&bad).is_err());
+		let mut bad = superblock();
+		bad.size = 2000;
+		assert!(FsGeometry::new(&bad).is_err());
+		let mut bad = superblock();
+		bad.fpg = i32::MAX;
+		bad.ncg = u32::MAX;
+		assert_eq!(FsGeometry::new(&bad), Err("sb.cg_addr(ncg, 0).is_some()"));
+	}
+
+	#[test]
+	fn addresses() {
+		let sb = superblock();
+		let geo = FsGeometry::new(&sb).unwrap();
+		assert_eq!(geo.frag_addr(999), Some(999 * 4096));
+		assert_eq!(geo.frag_addr(1000), None);
+		assert_eq!(geo.frag_addr(u64::MAX), None);
+		assert_eq!(geo.blocks_to_frags(u64::MAX), None);
+
+		for inr in [2, 127, 128, 256 + 17, u32::MAX] {
+			let inr = unsafe { InodeNum::new(inr) };
+			assert_eq!(geo.ino_to_fso(inr), sb.ino_to_fso(inr), "{inr}");
+		}
+	}
+}
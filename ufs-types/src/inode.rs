@@ -0,0 +1,310 @@
+use std::time::{Duration, SystemTime};
+
+use bincode::{
+	de::Decoder,
+	enc::Encoder,
+	error::{DecodeError, EncodeError},
+	Decode,
+	Encode,
+};
+
+use crate::data::*;
+
+/// Convert a timestamp of UFS into a [`SystemTime`].
+///
+/// Like a `struct timespec`, the nanoseconds count forward, even before the epoch,
+/// so -2s + 500000000ns is 1.5s before the epoch.
+/// Times, which the platform can't represent, are clamped to the nearest whole second,
+/// or to the epoch, as a last resort.
+pub(crate) fn timetosys(s: UfsTime, ns: u32) -> SystemTime {
+	let secs = Duration::from_secs(s.unsigned_abs());
+	let time = if s < 0 {
+		SystemTime::UNIX_EPOCH.checked_sub(secs)
+	} else {
+		SystemTime::UNIX_EPOCH.checked_add(secs)
+	};
+	time.and_then(|t| t.checked_add(Duration::from_nanos(ns.into())))
+		.or(time)
+		.unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// Convert a [`SystemTime`] into a timestamp of UFS, the inverse of [`timetosys()`].
+///
+/// The nanoseconds are always less than one second,
+/// and times beyond the range of [`UfsTime`] saturate.
+pub(crate) fn systotime(t: SystemTime) -> (UfsTime, u32) {
+	match t.duration_since(SystemTime::UNIX_EPOCH) {
+		Ok(d) => {
+			match UfsTime::try_from(d.as_secs()) {
+				Ok(s) => (s, d.subsec_nanos()),
+				Err(_) => (UfsTime::MAX, 999_999_999),
+			}
+		}
+		Err(e) => {
+			let d = e.duration();
+			let (secs, ns) = match d.subsec_nanos() {
+				0 => (d.as_secs(), 0),
+				ns => (d.as_secs() + 1, 1_000_000_000 - ns),
+			};
+			match UfsTime::try_from(secs) {
+				Ok(s) => (-s, ns),
+				Err(_) if secs == UfsTime::MIN.unsigned_abs() => (UfsTime::MIN, ns),
+				Err(_) => (UfsTime::MIN, 0),
+			}
+		}
+	}
+}
+
+impl Inode {
+	pub fn atime(&self) -> SystemTime {
+		timetosys(self.atime, self.atimensec)
+	}
+
+	pub fn mtime(&self) -> SystemTime {
+		timetosys(self.mtime, self.mtimensec)
+	}
+
+	pub fn ctime(&self) -> SystemTime {
+		timetosys(self.ctime, self.ctimensec)
+	}
+
+	pub fn btime(&self) -> SystemTime {
+		timetosys(self.birthtime, self.birthnsec)
+	}
+
+	/// The device number of a character or block device, which is stored in place of the
+	/// first block pointer, like `di_rdev`, or 0 for other files.
+	pub fn rdev(&self) -> u64 {
+		match (self.mode & S_IFMT, &self.data) {
+			(S_IFCHR | S_IFBLK, InodeData::Blocks(blocks)) => blocks.direct[0] as u64,
+			_ => 0,
+		}
+	}
+
+	pub fn set_atime(&mut self, t: SystemTime) {
+		(self.atime, self.atimensec) = systotime(t);
+	}
+
+	pub fn set_mtime(&mut self, t: SystemTime) {
+		(self.mtime, self.mtimensec) = systotime(t);
+	}
+
+	pub fn set_ctime(&mut self, t: SystemTime) {
+		(self.ctime, self.ctimensec) = systotime(t);
+	}
+
+	pub fn set_btime(&mut self, t: SystemTime) {
+		(self.birthtime, self.birthnsec) = systotime(t);
+	}
+
+	/// The number of blocks and fragments this inode occupies.
+	///
+	/// Device nodes, FIFOs and sockets never have any data.
+	pub fn size(&self, bs: u64, fs: u64) -> (u64, u64) {
+		let size = match self.mode & S_IFMT {
+			S_IFDIR | S_IFREG | S_IFLNK => self.size,
+			_ => 0,
+		};
+		Self::inode_size(bs, fs, size)
+	}
+
+	/// The number of blocks and fragments this inode needs.
+	fn inode_size(bs: u64, fs: u64, size: u64) -> (u64, u64) {
+		let blocks = size / bs;
+		let frags = (size % bs).div_ceil(fs);
+
+		(blocks, frags)
+	}
+}
+
+impl Decode for Inode {
+	fn decode<D: Decoder>(d: &mut D) -> Result<Self, DecodeError> {
+		let mode = u16::decode(d)?;
+		let nlink = u16::decode(d)?;
+		let uid = u32::decode(d)?;
+		let gid = u32::decode(d)?;
+		let blksize = u32::decode(d)?;
+		let size = u64::decode(d)?;
+		let blocks = u64::decode(d)?;
+		let atime = UfsTime::decode(d)?;
+		let mtime = UfsTime::decode(d)?;
+		let ctime = UfsTime::decode(d)?;
+		let birthtime = UfsTime::decode(d)?;
+		let mtimensec = u32::decode(d)?;
+		let atimensec = u32::decode(d)?;
+		let ctimensec = u32::decode(d)?;
+		let birthnsec = u32::decode(d)?;
+		let gen = u32::decode(d)?;
+		let kernflags = u32::decode(d)?;
+		let flags = u32::decode(d)?;
+		let extsize = u32::decode(d)?;
+		let extb = <[UfsDaddr; UFS_NXADDR]>::decode(d)?;
+		let data = if (mode & S_IFMT) == S_IFLNK && blocks == 0 {
+			InodeData::Shortlink(Decode::decode(d)?)
+		} else {
+			InodeData::Blocks(InodeBlocks::decode(d)?)
+		};
+
+		let ino = Self {
+			mode,
+			nlink,
+			uid,
+			gid,
+			blksize,
+			size,
+			blocks,
+			atime,
+			mtime,
+			ctime,
+			birthtime,
+			mtimensec,
+			atimensec,
+			ctimensec,
+			birthnsec,
+			gen,
+			kernflags,
+			flags,
+			extsize,
+			extb,
+			data,
+			modrev: u64::decode(d)?,
+			ignored: u32::decode(d)?,
+			ckhash: u32::decode(d)?,
+			spare: <[u32; 2]>::decode(d)?,
+		};
+
+		Ok(ino)
+	}
+}
+
+impl Encode for Inode {
+	fn encode<E: Encoder>(&self, e: &mut E) -> Result<(), EncodeError> {
+		self.mode.encode(e)?;
+		self.nlink.encode(e)?;
+		self.uid.encode(e)?;
+		self.gid.encode(e)?;
+		self.blksize.encode(e)?;
+		self.size.encode(e)?;
+		self.blocks.encode(e)?;
+		self.atime.encode(e)?;
+		self.mtime.encode(e)?;
+		self.ctime.encode(e)?;
+		self.birthtime.encode(e)?;
+		self.mtimensec.encode(e)?;
+		self.atimensec.encode(e)?;
+		self.ctimensec.encode(e)?;
+		self.birthnsec.encode(e)?;
+		self.gen.encode(e)?;
+		self.kernflags.encode(e)?;
+		self.flags.encode(e)?;
+		self.extsize.encode(e)?;
+		self.extb.encode(e)?;
+		match &self.data {
+			InodeData::Blocks(blocks) => blocks.encode(e)?,
+			InodeData::Shortlink(link) => link.encode(e)?,
+		}
+		self.modrev.encode(e)?;
+		self.ignored.encode(e)?;
+		self.ckhash.encode(e)?;
+		self.spare.encode(e)?;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::time::{Duration, SystemTime};
+
+	use super::{systotime, timetosys};
+	use crate::*;
+
+	#[test]
+	fn inode_size() {
+		let bs = 32768;
+		let fs = 4096;
+
+		let isz = |sz| Inode::inode_size(bs, fs, sz);
+
+		assert_eq!(isz(0), (0, 0));
+		assert_eq!(isz(1), (0, 1));
+		assert_eq!(isz(fs), (0, 1));
+		assert_eq!(isz(bs), (1, 0));
+		assert_eq!(isz(bs + 2 * fs), (1, 2));
+		assert_eq!(isz(100 * bs + 7 * fs), (100, 7));
+	}
+
+	#[test]
+	fn roundtrip() {
+		let mut raw = [0u8; UFS_INOSZ];
+		for (i, b) in raw.iter_mut().enumerate() {
+			*b = i as u8;
+		}
+		raw[0..2].copy_from_slice(&(S_IFREG | 0o644).to_le_bytes());
+
+		let cfg = Config::little();
+		let ino: Inode = cfg.decode_slice(&raw).unwrap();
+		assert_eq!(ino.mode, S_IFREG | 0o644);
+		assert_eq!(cfg.encode_to_vec(&ino).unwrap(), raw);
+
+		let cfg = Config::big();
+		let ino: Inode = cfg.decode_slice(&raw).unwrap();
+		assert_eq!(cfg.encode_to_vec(&ino).unwrap(), raw);
+	}
+
+	/// 2100-01-01 00:00:00 UTC, beyond the range of a 32-bit `time_t`.
+	const Y2100: UfsTime = 4_102_444_800;
+
+	#[test]
+	fn times() {
+		let epoch = SystemTime::UNIX_EPOCH;
+		let cases = [
+			(0, 0, epoch),
+			(
+				Y2100,
+				123_456_789,
+				epoch + Duration::new(Y2100 as u64, 123_456_789),
+			),
+			(-1, 0, epoch - Duration::from_secs(1)),
+			// 1969-12-31 23:59:58.5
+			(-2, 500_000_000, epoch - Duration::from_millis(1500)),
+			// 1901-12-13 20:45:52, the lower end of a 32-bit `time_t`
+			(
+				i32::MIN.into(),
+				1,
+				epoch - Duration::new(1 << 31, 0) + Duration::new(0, 1),
+			),
+		];
+		for (s, ns, t) in cases {
+			assert_eq!(timetosys(s, ns), t, "{s}.{ns:09}");
+			assert_eq!(systotime(t), (s, ns), "{t:?}");
+		}
+	}
+
+	/// Extreme times of corrupted inodes don't panic.
+	#[test]
+	fn times_extreme() {
+		for s in [UfsTime::MIN, UfsTime::MIN + 1, UfsTime::MAX] {
+			for ns in [0, 999_999_999, u32::MAX] {
+				let (s2, ns2) = systotime(timetosys(s, ns));
+				assert!(ns2 < 1_000_000_000);
+				assert_eq!(s2.signum(), s.signum());
+			}
+		}
+		// Nanoseconds beyond one second carry over.
+		assert_eq!(systotime(timetosys(1, 1_500_000_000)), (2, 500_000_000));
+	}
+
+	#[test]
+	fn set_times() {
+		let mut ino: Inode = Config::little().decode_slice(&[0u8; UFS_INOSZ]).unwrap();
+		let t = SystemTime::UNIX_EPOCH - Duration::new(86400, 250);
+		ino.set_btime(t);
+		assert_eq!((ino.birthtime, ino.birthnsec), (-86401, 999_999_750));
+		assert_eq!(ino.btime(), t);
+
+		let t = SystemTime::UNIX_EPOCH + Duration::new(Y2100 as u64, 7);
+		ino.set_mtime(t);
+		assert_eq!((ino.mtime, ino.mtimensec), (Y2100, 7));
+		assert_eq!(ino.mtime(), t);
+	}
+}
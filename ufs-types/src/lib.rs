@@ -0,0 +1,25 @@
+//! On-disk data structures of FreeBSD's UFSv2.
+//!
+//! This crate only contains the layout of the on-disk structures,
+//! and functions to parse and serialize them.
+//! It doesn't perform any I/O on its own, see the `rufs` crate for a filesystem driver.
+//!
+//! # Example
+//! ```
+//! use ufs_types::{Config, Superblock, FS_UFS2_MAGIC, SBLOCKSIZE};
+//!
+//! // Normally this would be read from offset `SBLOCK_UFS2` of a disk.
+//! let mut buf = vec![0u8; SBLOCKSIZE];
+//! buf[1372..1376].copy_from_slice(&FS_UFS2_MAGIC.to_le_bytes());
+//!
+//! let sb: Superblock = Config::little().decode_slice(&buf)?;
+//! assert_eq!(sb.magic, FS_UFS2_MAGIC);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+mod data;
+mod decoder;
+mod geometry;
+mod inode;
+
+pub use crate::{data::*, decoder::*, geometry::FsGeometry};